@@ -0,0 +1,387 @@
+//! End-to-end encrypted file attachments.
+//!
+//! A file is split into fixed-size chunks and each chunk is sealed with
+//! AES-256-GCM under a per-file key exported from the MLS group
+//! ([`FILE_KEY_LABEL`], with the file id as exporter context). The nonce is
+//! the chunk index plus a flag marking the last chunk, so chunks can't be
+//! reordered, and a blob cut short after a chunk boundary fails to decrypt.
+//! The file id is the associated data of every chunk.
+//!
+//! The encrypted blob is the sealed chunks back to back. Its [`FileManifest`]
+//! lists each chunk's offset, size and SHA-256, and the SHA-256 of the whole
+//! blob, which is what the blob store addresses uploads by.
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+/// MLS exporter label for file keys; the exporter context is the file id.
+pub const FILE_KEY_LABEL: &str = "vox file key";
+/// Length of a file key (AES-256).
+pub const FILE_KEY_LEN: usize = 32;
+/// Length of a file id.
+pub const FILE_ID_LEN: usize = 16;
+/// Plaintext bytes per chunk unless the caller picks otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+/// Largest plaintext chunk accepted, to bound decrypt buffering.
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+/// AES-GCM tag appended to every chunk.
+pub const TAG_LEN: usize = 16;
+/// Manifest format version.
+pub const MANIFEST_VERSION: u64 = 1;
+const ALGORITHM: &str = "aes-256-gcm";
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(std::io::Error),
+    /// Bad key, manifest or arguments.
+    Invalid(String),
+    /// The ciphertext does not match the manifest or fails authentication.
+    Integrity(String),
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::Io(e) => write!(f, "I/O error: {e}"),
+            FileError::Invalid(msg) | FileError::Integrity(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<std::io::Error> for FileError {
+    fn from(e: std::io::Error) -> Self {
+        FileError::Io(e)
+    }
+}
+
+/// One sealed chunk in the blob.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub offset: u64,
+    /// Sealed size, tag included.
+    pub size: u32,
+    pub sha256: [u8; 32],
+}
+
+/// Describes an encrypted blob. Holds no key material; the key comes from
+/// the MLS group at `key_epoch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileManifest {
+    pub file_id: [u8; FILE_ID_LEN],
+    pub key_epoch: u64,
+    /// Plaintext bytes per chunk; the last chunk may be shorter.
+    pub chunk_size: u32,
+    /// Plaintext size.
+    pub size: u64,
+    /// Blob size.
+    pub encrypted_size: u64,
+    /// SHA-256 of the whole blob.
+    pub sha256: [u8; 32],
+    pub chunks: Vec<ChunkInfo>,
+}
+
+impl FileManifest {
+    pub fn to_json(&self) -> String {
+        let chunks: Vec<Value> = self
+            .chunks
+            .iter()
+            .map(|c| json!({ "offset": c.offset, "size": c.size, "sha256": hex(&c.sha256) }))
+            .collect();
+        json!({
+            "version": MANIFEST_VERSION,
+            "algorithm": ALGORITHM,
+            "file_id": hex(&self.file_id),
+            "key_epoch": self.key_epoch,
+            "chunk_size": self.chunk_size,
+            "size": self.size,
+            "encrypted_size": self.encrypted_size,
+            "sha256": hex(&self.sha256),
+            "chunks": chunks,
+        })
+        .to_string()
+    }
+
+    /// Parse and sanity-check a manifest: chunks must tile the blob and
+    /// their sizes must add up to `size`.
+    pub fn from_json(text: &str) -> Result<Self, FileError> {
+        let v: Value = serde_json::from_str(text).map_err(|e| invalid(format!("invalid manifest JSON: {e}")))?;
+        let version = u64_field(&v, "version")?;
+        if version != MANIFEST_VERSION {
+            return Err(invalid(format!("unsupported manifest version {version}")));
+        }
+        if v.get("algorithm").and_then(Value::as_str) != Some(ALGORITHM) {
+            return Err(invalid(format!("manifest algorithm is not {ALGORITHM}")));
+        }
+        let chunk_size = u32::try_from(u64_field(&v, "chunk_size")?).map_err(|_| invalid("chunk_size out of range"))?;
+        check_chunk_size(chunk_size as usize)?;
+        let chunks = v
+            .get("chunks")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("manifest has no 'chunks' list"))?
+            .iter()
+            .map(|c| {
+                Ok(ChunkInfo {
+                    offset: u64_field(c, "offset")?,
+                    size: u32::try_from(u64_field(c, "size")?).map_err(|_| invalid("chunk size out of range"))?,
+                    sha256: hex_field(c, "sha256")?,
+                })
+            })
+            .collect::<Result<Vec<_>, FileError>>()?;
+        let manifest = FileManifest {
+            file_id: hex_field(&v, "file_id")?,
+            key_epoch: u64_field(&v, "key_epoch")?,
+            chunk_size,
+            size: u64_field(&v, "size")?,
+            encrypted_size: u64_field(&v, "encrypted_size")?,
+            sha256: hex_field(&v, "sha256")?,
+            chunks,
+        };
+        manifest.check_layout()?;
+        Ok(manifest)
+    }
+
+    fn check_layout(&self) -> Result<(), FileError> {
+        if self.chunks.is_empty() {
+            return Err(invalid("manifest lists no chunks"));
+        }
+        let mut offset = 0u64;
+        let mut plaintext = 0u64;
+        let last = self.chunks.len() - 1;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let body = (chunk.size as u64)
+                .checked_sub(TAG_LEN as u64)
+                .ok_or_else(|| invalid(format!("chunk {i} is shorter than its tag")))?;
+            let full = body == self.chunk_size as u64;
+            if chunk.offset != offset || !(full || (i == last && body < self.chunk_size as u64)) {
+                return Err(invalid(format!("chunk {i} does not fit the manifest layout")));
+            }
+            offset += chunk.size as u64;
+            plaintext += body;
+        }
+        if offset != self.encrypted_size || plaintext != self.size {
+            return Err(invalid("manifest sizes do not add up"));
+        }
+        Ok(())
+    }
+}
+
+fn check_chunk_size(chunk_size: usize) -> Result<(), FileError> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(invalid(format!("chunk size must be between 1 and {MAX_CHUNK_SIZE} bytes")));
+    }
+    Ok(())
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm, FileError> {
+    if key.len() != FILE_KEY_LEN {
+        return Err(invalid(format!("file key must be {FILE_KEY_LEN} bytes, got {}", key.len())));
+    }
+    Aes256Gcm::new_from_slice(key).map_err(|e| invalid(format!("file key: {e}")))
+}
+
+/// Chunk index in the low 8 bytes; byte 3 is 1 for the last chunk.
+fn chunk_nonce(index: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[3] = last as u8;
+    nonce[4..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+/// Read until `buf` is full or the reader is exhausted.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypt everything `reader` yields into `writer`, one chunk in memory
+/// at a time. An empty input still produces one (empty) chunk.
+pub fn encrypt_stream(
+    key: &[u8],
+    file_id: [u8; FILE_ID_LEN],
+    key_epoch: u64,
+    chunk_size: usize,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<FileManifest, FileError> {
+    check_chunk_size(chunk_size)?;
+    let cipher = cipher(key)?;
+    let mut blob_hash = Sha256::new();
+    let mut chunks = Vec::new();
+    let mut offset = 0u64;
+    let mut size = 0u64;
+
+    // One chunk of lookahead tells whether the current chunk is the last
+    let mut current = vec![0u8; chunk_size];
+    let mut next = vec![0u8; chunk_size];
+    let mut current_len = read_full(&mut reader, &mut current)?;
+    loop {
+        let next_len = if current_len == chunk_size { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        let index = chunks.len() as u64;
+        let sealed = cipher
+            .encrypt(
+                Nonce::from_slice(&chunk_nonce(index, last)),
+                Payload { msg: &current[..current_len], aad: &file_id },
+            )
+            .map_err(|_| invalid("chunk encryption failed"))?;
+        writer.write_all(&sealed)?;
+        blob_hash.update(&sealed);
+        chunks.push(ChunkInfo { offset, size: sealed.len() as u32, sha256: Sha256::digest(&sealed).into() });
+        offset += sealed.len() as u64;
+        size += current_len as u64;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+    writer.flush()?;
+
+    Ok(FileManifest {
+        file_id,
+        key_epoch,
+        chunk_size: chunk_size as u32,
+        size,
+        encrypted_size: offset,
+        sha256: blob_hash.finalize().into(),
+        chunks,
+    })
+}
+
+/// Incremental decryption of a blob as it arrives, e.g. from a download.
+/// Feed ciphertext in pieces of any size with [`update`](Self::update);
+/// plaintext comes out a chunk at a time, only after that chunk verified.
+pub struct FileDecryptor {
+    cipher: Aes256Gcm,
+    manifest: FileManifest,
+    buf: Vec<u8>,
+    next_chunk: usize,
+    blob_hash: Sha256,
+}
+
+impl FileDecryptor {
+    pub fn new(key: &[u8], manifest: FileManifest) -> Result<Self, FileError> {
+        manifest.check_layout()?;
+        Ok(FileDecryptor { cipher: cipher(key)?, manifest, buf: Vec::new(), next_chunk: 0, blob_hash: Sha256::new() })
+    }
+
+    pub fn manifest(&self) -> &FileManifest {
+        &self.manifest
+    }
+
+    /// Take more ciphertext and append any plaintext it completes to `out`.
+    pub fn update(&mut self, ciphertext: &[u8], out: &mut Vec<u8>) -> Result<(), FileError> {
+        self.buf.extend_from_slice(ciphertext);
+        while let Some(chunk) = self.manifest.chunks.get(self.next_chunk) {
+            let len = chunk.size as usize;
+            if self.buf.len() < len {
+                return Ok(());
+            }
+            let sealed: Vec<u8> = self.buf.drain(..len).collect();
+            let index = self.next_chunk;
+            if <[u8; 32]>::from(Sha256::digest(&sealed)) != chunk.sha256 {
+                return Err(FileError::Integrity(format!("chunk {index} hash mismatch")));
+            }
+            self.blob_hash.update(&sealed);
+            let last = index + 1 == self.manifest.chunks.len();
+            let plaintext = self
+                .cipher
+                .decrypt(
+                    Nonce::from_slice(&chunk_nonce(index as u64, last)),
+                    Payload { msg: &sealed, aad: &self.manifest.file_id },
+                )
+                .map_err(|_| FileError::Integrity(format!("chunk {index} failed authentication")))?;
+            out.extend_from_slice(&plaintext);
+            self.next_chunk += 1;
+        }
+        if !self.buf.is_empty() {
+            return Err(FileError::Integrity("data past the end of the blob".into()));
+        }
+        Ok(())
+    }
+
+    /// Check that the whole blob arrived and matches the manifest hash.
+    pub fn finish(self) -> Result<(), FileError> {
+        if self.next_chunk < self.manifest.chunks.len() {
+            return Err(FileError::Integrity(format!(
+                "blob truncated: {} of {} chunks received",
+                self.next_chunk,
+                self.manifest.chunks.len()
+            )));
+        }
+        if <[u8; 32]>::from(self.blob_hash.finalize()) != self.manifest.sha256 {
+            return Err(FileError::Integrity("blob hash mismatch".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Decrypt a whole blob from `reader` into `writer`. Returns the plaintext size.
+///
+/// Plaintext is written as chunks verify, so on error `writer` may hold a
+/// prefix of the file; callers writing to disk should discard it.
+pub fn decrypt_stream(
+    key: &[u8],
+    manifest: FileManifest,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<u64, FileError> {
+    let mut decryptor = FileDecryptor::new(key, manifest)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut out = Vec::new();
+    let mut written = 0u64;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        decryptor.update(&buf[..n], &mut out)?;
+        writer.write_all(&out)?;
+        written += out.len() as u64;
+        out.clear();
+    }
+    decryptor.finish()?;
+    writer.flush()?;
+    Ok(written)
+}
+
+fn invalid(msg: impl Into<String>) -> FileError {
+    FileError::Invalid(msg.into())
+}
+
+fn u64_field(v: &Value, name: &str) -> Result<u64, FileError> {
+    v.get(name).and_then(Value::as_u64).ok_or_else(|| invalid(format!("manifest field '{name}' missing or invalid")))
+}
+
+fn hex_field<const N: usize>(v: &Value, name: &str) -> Result<[u8; N], FileError> {
+    let text = v.get(name).and_then(Value::as_str).unwrap_or_default();
+    unhex(text).ok_or_else(|| invalid(format!("manifest field '{name}' is not {N} hex-encoded bytes")))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != 2 * N {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
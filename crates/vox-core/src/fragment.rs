@@ -0,0 +1,251 @@
+//! Video fragmentation and reassembly.
+//!
+//! An encoded video frame larger than one datagram is split into fragments
+//! that share its timestamp and carry consecutive sequence numbers. The
+//! first fragment has FLAG_START_OF_FRAME (and FLAG_KEYFRAME for a keyframe)
+//! and the last fragment has FLAG_END_OF_FRAME.
+//!
+//! Older senders do not set FLAG_START_OF_FRAME. Until a sender is seen
+//! setting it, the start of its delta frames is taken to follow the previous
+//! frame's END_OF_FRAME fragment.
+
+use crate::frame::OutFrame;
+use crate::header::{MediaHeader, FLAG_START_OF_FRAME, HEADER_SIZE};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Fallback payload per fragment (1200 MTU - 22 header), used when the
+/// connection does not report a maximum datagram size.
+pub const MAX_FRAGMENT_PAYLOAD: usize = 1200 - HEADER_SIZE;
+
+/// Split an encoded video frame into datagrams of at most `max_payload`
+/// payload bytes, numbered from `first_seq`. An empty frame still produces
+/// one (empty) fragment.
+pub fn fragment_video(
+    room_id: u32,
+    user_id: u32,
+    first_seq: u32,
+    timestamp: u32,
+    is_keyframe: bool,
+    data: &[u8],
+    max_payload: usize,
+) -> Vec<OutFrame> {
+    let max_payload = max_payload.max(1);
+    let count = data.len().div_ceil(max_payload).max(1);
+    (0..count)
+        .map(|i| {
+            let start = i * max_payload;
+            let end = (start + max_payload).min(data.len());
+            let mut out = OutFrame::video(
+                room_id,
+                user_id,
+                first_seq.wrapping_add(i as u32),
+                timestamp,
+                is_keyframe && i == 0,
+                i + 1 == count,
+                Bytes::copy_from_slice(&data[start..end]),
+            );
+            if i == 0 {
+                out.header.flags |= FLAG_START_OF_FRAME;
+            }
+            out
+        })
+        .collect()
+}
+
+/// Key for video fragment reassembly: (user_id, timestamp).
+#[derive(Hash, Eq, PartialEq, Clone)]
+struct ReassemblyKey {
+    user_id: u32,
+    timestamp: u32,
+}
+
+/// Partial frame being assembled from fragments.
+struct PartialFrame {
+    fragments: Vec<(u32, Vec<u8>)>, // (sequence, payload)
+    is_keyframe: bool,
+    /// Sequence numbers of the START_OF_FRAME and END_OF_FRAME fragments,
+    /// once seen.
+    start_sequence: Option<u32>,
+    end_sequence: Option<u32>,
+    /// The sender's latest END_OF_FRAME sequence when this frame began.
+    previous_end: Option<u32>,
+    last_activity: Instant,
+}
+
+impl PartialFrame {
+    /// Whether every sequence number from the first fragment to the
+    /// END_OF_FRAME fragment has arrived. Sequence numbers may wrap.
+    ///
+    /// The first fragment is the START_OF_FRAME one, or for a sender that
+    /// does not set that flag, the one after `previous_end`.
+    fn is_complete(&self, marks_start: bool) -> bool {
+        let start = match self.start_sequence {
+            Some(start) => Some(start),
+            None if !marks_start => self.previous_end.map(|end| end.wrapping_add(1)),
+            None => None,
+        };
+        let (Some(start), Some(end)) = (start, self.end_sequence) else {
+            return false;
+        };
+        let span = end.wrapping_sub(start);
+        // Duplicates are rejected on insert, so span + 1 distinct fragments
+        // inside the run cover all of it.
+        self.fragments.len() as u64 == u64::from(span) + 1
+            && self.fragments.iter().all(|(seq, _)| end.wrapping_sub(*seq) <= span)
+    }
+}
+
+/// Reassembles fragmented video datagrams into complete AV1 frames.
+pub struct VideoReassembler {
+    pending: HashMap<ReassemblyKey, PartialFrame>,
+    /// Partial frames held per sender and in total before the oldest is
+    /// discarded to make room.
+    max_per_user: usize,
+    max_total: usize,
+    /// Partial frames discarded to stay within the limits.
+    evicted: u64,
+    /// Latest END_OF_FRAME sequence per sender.
+    last_end: HashMap<u32, u32>,
+    /// Senders seen setting FLAG_START_OF_FRAME.
+    marks_start: HashSet<u32>,
+}
+
+/// A fully reassembled video frame ready for decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReassembledFrame {
+    pub user_id: u32,
+    pub timestamp: u32,
+    pub is_keyframe: bool,
+    pub data: Vec<u8>,
+}
+
+impl Default for VideoReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VideoReassembler {
+    pub fn new() -> Self {
+        VideoReassembler {
+            pending: HashMap::new(),
+            max_per_user: usize::MAX,
+            max_total: usize::MAX,
+            evicted: 0,
+            last_end: HashMap::new(),
+            marks_start: HashSet::new(),
+        }
+    }
+
+    /// Bound the partial frames held at once, per sender and in total.
+    pub fn set_limits(&mut self, max_per_user: usize, max_total: usize) {
+        self.max_per_user = max_per_user.max(1);
+        self.max_total = max_total.max(1);
+    }
+
+    /// Partial frames discarded over the limits since the last call.
+    pub fn take_evicted(&mut self) -> u64 {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Discard the least recently active partial frame, of `user_id` only
+    /// if given.
+    fn evict_oldest(&mut self, user_id: Option<u32>) {
+        let oldest = self
+            .pending
+            .iter()
+            .filter(|(k, _)| user_id.is_none_or(|id| k.user_id == id))
+            .min_by_key(|(_, v)| v.last_activity)
+            .map(|(k, _)| k.clone());
+        if let Some(key) = oldest {
+            self.pending.remove(&key);
+            self.evicted += 1;
+        }
+    }
+
+    /// Add a video datagram fragment. Returns a complete frame when all
+    /// fragments have arrived (its first fragment and END_OF_FRAME seen and
+    /// every sequence number between them).
+    pub fn add_fragment(&mut self, header: &MediaHeader, payload: &[u8]) -> Option<ReassembledFrame> {
+        let key = ReassemblyKey {
+            user_id: header.user_id,
+            timestamp: header.timestamp,
+        };
+
+        if !self.pending.contains_key(&key) {
+            if self.pending.keys().filter(|k| k.user_id == key.user_id).count() >= self.max_per_user {
+                self.evict_oldest(Some(key.user_id));
+            }
+            if self.pending.len() >= self.max_total {
+                self.evict_oldest(None);
+            }
+        }
+
+        let previous_end = self.last_end.get(&key.user_id).copied();
+        let partial = self.pending.entry(key.clone()).or_insert_with(|| PartialFrame {
+            fragments: Vec::new(),
+            is_keyframe: false,
+            start_sequence: None,
+            end_sequence: None,
+            previous_end,
+            last_activity: Instant::now(),
+        });
+
+        partial.last_activity = Instant::now();
+        if partial.fragments.iter().any(|(seq, _)| *seq == header.sequence) {
+            return None; // Duplicate
+        }
+        if header.is_keyframe() {
+            partial.is_keyframe = true;
+        }
+        if header.is_start_of_frame() {
+            self.marks_start.insert(key.user_id);
+        }
+        if header.is_start_of_frame() || header.is_keyframe() {
+            partial.start_sequence = Some(header.sequence);
+        }
+        if header.is_end_of_frame() {
+            partial.end_sequence = Some(header.sequence);
+            let newer = previous_end.is_none_or(|end| header.sequence.wrapping_sub(end) < u32::MAX / 2);
+            if newer {
+                self.last_end.insert(key.user_id, header.sequence);
+            }
+        }
+        partial.fragments.push((header.sequence, payload.to_vec()));
+
+        if !partial.is_complete(self.marks_start.contains(&key.user_id)) {
+            return None;
+        }
+        let mut partial = self.pending.remove(&key)?;
+        let end = partial.end_sequence?;
+        // Order by distance from the last fragment, so wraparound sorts correctly
+        partial.fragments.sort_by_key(|(seq, _)| std::cmp::Reverse(end.wrapping_sub(*seq)));
+        let data: Vec<u8> = partial.fragments.into_iter().flat_map(|(_, d)| d).collect();
+        Some(ReassembledFrame {
+            user_id: key.user_id,
+            timestamp: key.timestamp,
+            is_keyframe: partial.is_keyframe,
+            data,
+        })
+    }
+
+    /// Number of frames still waiting for fragments.
+    pub fn pending_frames(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Evict stale partial frames older than the given duration.
+    pub fn evict_stale(&mut self, max_age: Duration) {
+        let now = Instant::now();
+        self.pending.retain(|_, v| now.duration_since(v.last_activity) < max_age);
+    }
+
+    /// Discard all partial frames from one sender.
+    pub fn drop_user(&mut self, user_id: u32) {
+        self.pending.retain(|k, _| k.user_id != user_id);
+        self.last_end.remove(&user_id);
+        self.marks_start.remove(&user_id);
+    }
+}
@@ -0,0 +1,219 @@
+//! Outbound and inbound frames, and the RTCP feedback payloads carried in
+//! them.
+
+use crate::header::*;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// ALPN protocol identifier for media connections.
+pub const ALPN_PROTOCOL: &[u8] = b"vox-media/1";
+
+/// Outbound media frame.
+pub struct OutFrame {
+    pub header: MediaHeader,
+    pub payload: Bytes,
+}
+
+impl OutFrame {
+    /// Build an audio frame with sensible defaults.
+    pub fn audio(room_id: u32, user_id: u32, codec_id: u8, seq: u32, timestamp: u32, payload: Bytes) -> Self {
+        OutFrame {
+            header: MediaHeader {
+                version: PROTOCOL_VERSION,
+                media_type: MEDIA_TYPE_AUDIO,
+                codec_id,
+                flags: FLAG_END_OF_FRAME,
+                room_id,
+                user_id,
+                sequence: seq,
+                timestamp,
+                spatial_id: 0,
+                temporal_id: 0,
+                dtx: false,
+            },
+            payload,
+        }
+    }
+
+    /// Build a video frame datagram.
+    pub fn video(
+        room_id: u32,
+        user_id: u32,
+        seq: u32,
+        timestamp: u32,
+        is_keyframe: bool,
+        is_end_of_frame: bool,
+        payload: Bytes,
+    ) -> Self {
+        let mut flags: u8 = 0;
+        if is_keyframe {
+            flags |= FLAG_KEYFRAME;
+        }
+        if is_end_of_frame {
+            flags |= FLAG_END_OF_FRAME;
+        }
+        OutFrame {
+            header: MediaHeader {
+                version: PROTOCOL_VERSION,
+                media_type: MEDIA_TYPE_VIDEO,
+                codec_id: CODEC_AV1,
+                flags,
+                room_id,
+                user_id,
+                sequence: seq,
+                timestamp,
+                spatial_id: 0,
+                temporal_id: 0,
+                dtx: false,
+            },
+            payload,
+        }
+    }
+
+    /// Build an application data frame. The payload is prefixed with the
+    /// big-endian channel id.
+    pub fn data(room_id: u32, user_id: u32, seq: u32, channel_id: u16, data: &[u8]) -> Self {
+        let mut payload = BytesMut::with_capacity(DATA_CHANNEL_PREFIX + data.len());
+        payload.put_u16(channel_id);
+        payload.put_slice(data);
+        OutFrame {
+            header: MediaHeader {
+                version: PROTOCOL_VERSION,
+                media_type: MEDIA_TYPE_DATA,
+                codec_id: CODEC_NONE,
+                flags: FLAG_END_OF_FRAME,
+                room_id,
+                user_id,
+                sequence: seq,
+                timestamp: 0,
+                spatial_id: 0,
+                temporal_id: 0,
+                dtx: false,
+            },
+            payload: payload.freeze(),
+        }
+    }
+
+    /// Build an RTCP feedback frame addressed to the SFU.
+    pub fn feedback(room_id: u32, user_id: u32, seq: u32, payload: Bytes) -> Self {
+        OutFrame {
+            header: MediaHeader {
+                version: PROTOCOL_VERSION,
+                media_type: MEDIA_TYPE_RTCP_FB,
+                codec_id: CODEC_NONE,
+                flags: FLAG_END_OF_FRAME,
+                room_id,
+                user_id,
+                sequence: seq,
+                timestamp: 0,
+                spatial_id: 0,
+                temporal_id: 0,
+                dtx: false,
+            },
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> Bytes {
+        let header_bytes = self.header.encode();
+        let mut buf = BytesMut::with_capacity(HEADER_SIZE + self.payload.len());
+        buf.put_slice(&header_bytes);
+        buf.extend_from_slice(&self.payload);
+        buf.freeze()
+    }
+}
+
+/// Inbound media frame.
+pub struct InFrame {
+    pub header: MediaHeader,
+    pub payload: Bytes,
+}
+
+impl InFrame {
+    pub fn decode(data: Bytes) -> Option<Self> {
+        let header = MediaHeader::parse(&data)?;
+        let payload = data.slice(HEADER_SIZE..);
+        Some(InFrame { header, payload })
+    }
+}
+
+/// Length of the channel id prefix on data channel payloads.
+pub const DATA_CHANNEL_PREFIX: usize = 2;
+
+// RTCP feedback kinds (first payload byte of MEDIA_TYPE_RTCP_FB frames)
+pub const FB_RECEIVE_PREFERENCES: u8 = 1;
+/// The client is leaving the room (payload is this byte alone).
+pub const FB_LEAVE: u8 = 2;
+
+// QUIC application close codes
+/// Connection closed without a more specific reason.
+pub const CLOSE_NORMAL: u32 = 0;
+/// The user left the room (disconnect or client shutdown).
+pub const CLOSE_LEAVE: u32 = 1;
+
+/// What the client wants the SFU to forward to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReceivePreferences {
+    /// Maximum number of remote video streams, `None` for no limit.
+    pub max_video_streams: Option<u16>,
+    /// Maximum video resolution as (width, height), `None` for no limit.
+    pub max_resolution: Option<(u16, u16)>,
+    /// Forward audio only.
+    pub audio_only: bool,
+}
+
+impl ReceivePreferences {
+    /// Encode as an RTCP feedback payload.
+    ///
+    /// ```text
+    /// Byte 0:    FB_RECEIVE_PREFERENCES
+    /// Byte 1:    flags (bit 0 = audio only)
+    /// Bytes 2-3: max video streams (0xFFFF = unlimited)
+    /// Bytes 4-5: max width (0 = unlimited)
+    /// Bytes 6-7: max height (0 = unlimited)
+    /// ```
+    pub fn encode(&self) -> Bytes {
+        let (width, height) = self.max_resolution.unwrap_or((0, 0));
+        let mut buf = BytesMut::with_capacity(8);
+        buf.put_u8(FB_RECEIVE_PREFERENCES);
+        buf.put_u8(u8::from(self.audio_only));
+        buf.put_u16(self.max_video_streams.unwrap_or(u16::MAX));
+        buf.put_u16(width);
+        buf.put_u16(height);
+        buf.freeze()
+    }
+
+    /// Decode an RTCP feedback payload produced by [`encode`](Self::encode).
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 || data[0] != FB_RECEIVE_PREFERENCES {
+            return None;
+        }
+        let field = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        let (width, height) = (field(4), field(6));
+        Some(ReceivePreferences {
+            max_video_streams: Some(field(2)).filter(|&n| n != u16::MAX),
+            max_resolution: Some((width, height)).filter(|&(w, h)| w != 0 || h != 0),
+            audio_only: data[1] & 1 != 0,
+        })
+    }
+
+    /// The size a `width`x`height` video frame scales down to so it fits
+    /// `max_resolution`, keeping its aspect ratio. `None` if it already
+    /// fits. A zero limit leaves that dimension unbounded.
+    pub fn fit_resolution(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (max_w, max_h) = self.max_resolution?;
+        let (max_w, max_h) = (u64::from(max_w), u64::from(max_h));
+        let (w, h) = (u64::from(width), u64::from(height));
+        let too_wide = max_w != 0 && w > max_w;
+        let too_tall = max_h != 0 && h > max_h;
+        if !too_wide && !too_tall {
+            return None;
+        }
+        // Scale to whichever limit is tighter for this aspect ratio.
+        let (fit_w, fit_h) = if max_h == 0 || (max_w != 0 && w * max_h >= h * max_w) {
+            (max_w, h * max_w / w)
+        } else {
+            (w * max_h / h, max_h)
+        };
+        Some((fit_w.max(1) as u32, fit_h.max(1) as u32))
+    }
+}
@@ -0,0 +1,115 @@
+//! The fixed media frame header.
+
+/// Current protocol version.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Size of the fixed media frame header in bytes.
+pub const HEADER_SIZE: usize = 22;
+
+// Media type values
+pub const MEDIA_TYPE_AUDIO: u8 = 0;
+pub const MEDIA_TYPE_VIDEO: u8 = 1;
+pub const MEDIA_TYPE_SCREEN: u8 = 2;
+pub const MEDIA_TYPE_FEC: u8 = 3;
+pub const MEDIA_TYPE_RTCP_FB: u8 = 4;
+pub const MEDIA_TYPE_DATA: u8 = 5;
+
+// Codec ID values
+pub const CODEC_NONE: u8 = 0;
+pub const CODEC_OPUS: u8 = 1;
+pub const CODEC_AV1: u8 = 2;
+pub const CODEC_AV1_SCREEN: u8 = 3;
+
+// Flag bits (byte 3)
+pub const FLAG_KEYFRAME: u8 = 0b1000_0000;
+pub const FLAG_END_OF_FRAME: u8 = 0b0100_0000;
+pub const FLAG_FEC: u8 = 0b0010_0000;
+pub const FLAG_MARKER: u8 = 0b0001_0000;
+pub const FLAG_HAS_DEP_DESC: u8 = 0b0000_1000;
+pub const FLAG_START_OF_FRAME: u8 = 0b0000_0100;
+
+/// Media frame header (22 bytes fixed).
+///
+/// Wire layout (big-endian):
+/// ```text
+/// Byte 0:      version (u8)
+/// Byte 1:      media_type (u8)
+/// Byte 2:      codec_id (u8)
+/// Byte 3:      flags (u8)
+/// Bytes 4-7:   room_id (u32)
+/// Bytes 8-11:  user_id (u32)
+/// Bytes 12-15: sequence (u32)
+/// Bytes 16-19: timestamp (u32)
+/// Byte 20:     spatial_id (upper 4 bits) | temporal_id (lower 4 bits)
+/// Byte 21:     dtx flag (bit 7, MSB)
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaHeader {
+    pub version: u8,
+    pub media_type: u8,
+    pub codec_id: u8,
+    pub flags: u8,
+    pub room_id: u32,
+    pub user_id: u32,
+    pub sequence: u32,
+    pub timestamp: u32,
+    /// 4 bits; higher bits are dropped on encode.
+    pub spatial_id: u8,
+    /// 4 bits; higher bits are dropped on encode.
+    pub temporal_id: u8,
+    pub dtx: bool,
+}
+
+impl MediaHeader {
+    /// Parse a media header from the first 22 bytes of a datagram.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE {
+            return None;
+        }
+        Some(MediaHeader {
+            version: data[0],
+            media_type: data[1],
+            codec_id: data[2],
+            flags: data[3],
+            room_id: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            user_id: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            sequence: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
+            timestamp: u32::from_be_bytes([data[16], data[17], data[18], data[19]]),
+            spatial_id: data[20] >> 4,
+            temporal_id: data[20] & 0x0F,
+            dtx: (data[21] & 0x80) != 0,
+        })
+    }
+
+    /// Serialize the header into 22 bytes (big-endian).
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0] = self.version;
+        buf[1] = self.media_type;
+        buf[2] = self.codec_id;
+        buf[3] = self.flags;
+        buf[4..8].copy_from_slice(&self.room_id.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.user_id.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.sequence.to_be_bytes());
+        buf[16..20].copy_from_slice(&self.timestamp.to_be_bytes());
+        buf[20] = (self.spatial_id << 4) | (self.temporal_id & 0x0F);
+        buf[21] = if self.dtx { 0x80 } else { 0 };
+        buf
+    }
+
+    pub fn is_keyframe(&self) -> bool {
+        self.flags & FLAG_KEYFRAME != 0
+    }
+
+    pub fn is_end_of_frame(&self) -> bool {
+        self.flags & FLAG_END_OF_FRAME != 0
+    }
+
+    pub fn is_start_of_frame(&self) -> bool {
+        self.flags & FLAG_START_OF_FRAME != 0
+    }
+
+    pub fn has_dep_desc(&self) -> bool {
+        self.flags & FLAG_HAS_DEP_DESC != 0
+    }
+}
@@ -0,0 +1,107 @@
+//! Media key handoff between extension modules.
+//!
+//! vox-mls and vox-media are separate shared libraries, so neither can see
+//! the other's Rust types. A [`KeySink`] is a reference-counted callback with
+//! a C ABI: vox-media wraps one around its key ring and passes it to an
+//! `MlsEngine` inside a Python capsule named [`CAPSULE_NAME`]; the engine then
+//! pushes each epoch's media key straight into the ring, so the key bytes
+//! never become Python objects.
+
+use std::ffi::{c_void, CStr};
+use std::sync::Arc;
+
+/// Name of the Python capsule carrying a [`RawKeySink`]. The version suffix
+/// changes whenever the struct layout does.
+pub const CAPSULE_NAME: &CStr = c"vox.media_key_sink.v1";
+
+/// C-ABI view of a [`KeySink`], as stored in the capsule.
+///
+/// `set_key` returns false once the receiver is gone, after which the sink
+/// can be dropped. `retain` and `release` adjust the reference count on
+/// `context`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawKeySink {
+    pub context: *const c_void,
+    pub set_key: unsafe extern "C" fn(context: *const c_void, key_id: u64, key: *const u8, key_len: usize) -> bool,
+    pub retain: unsafe extern "C" fn(context: *const c_void),
+    pub release: unsafe extern "C" fn(context: *const c_void),
+}
+
+type Callback = Box<dyn Fn(u64, &[u8]) -> bool + Send + Sync>;
+
+/// Owned handle to a key receiver, possibly living in another library.
+///
+/// Transparent over [`RawKeySink`], so a pointer to one is a pointer to the
+/// other.
+#[repr(transparent)]
+pub struct KeySink {
+    raw: RawKeySink,
+}
+
+// The context is an `Arc` of a `Send + Sync` callback.
+unsafe impl Send for KeySink {}
+unsafe impl Sync for KeySink {}
+
+impl KeySink {
+    /// Wrap a callback taking `(key_id, key)`. It should return false once
+    /// it no longer wants keys.
+    pub fn new(callback: impl Fn(u64, &[u8]) -> bool + Send + Sync + 'static) -> Self {
+        let callback: Arc<Callback> = Arc::new(Box::new(callback));
+        KeySink {
+            raw: RawKeySink {
+                context: Arc::into_raw(callback).cast(),
+                set_key: set_key_trampoline,
+                retain: retain_trampoline,
+                release: release_trampoline,
+            },
+        }
+    }
+
+    /// Take a new reference to the sink described by `raw`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must come from [`KeySink::as_raw`] on a sink that is still alive.
+    pub unsafe fn from_raw(raw: &RawKeySink) -> Self {
+        (raw.retain)(raw.context);
+        KeySink { raw: *raw }
+    }
+
+    /// The C-ABI view, valid for as long as this handle is alive.
+    pub fn as_raw(&self) -> &RawKeySink {
+        &self.raw
+    }
+
+    /// Deliver a key. Returns false if the receiver is gone.
+    pub fn set_key(&self, key_id: u64, key: &[u8]) -> bool {
+        unsafe { (self.raw.set_key)(self.raw.context, key_id, key.as_ptr(), key.len()) }
+    }
+}
+
+impl Clone for KeySink {
+    fn clone(&self) -> Self {
+        unsafe { KeySink::from_raw(&self.raw) }
+    }
+}
+
+impl Drop for KeySink {
+    fn drop(&mut self) {
+        unsafe { (self.raw.release)(self.raw.context) }
+    }
+}
+
+unsafe extern "C" fn set_key_trampoline(context: *const c_void, key_id: u64, key: *const u8, key_len: usize) -> bool {
+    let callback = &*context.cast::<Callback>();
+    let key = std::slice::from_raw_parts(key, key_len);
+    // Unwinding across the C ABI aborts; treat a panicking receiver as gone
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(key_id, key))).unwrap_or(false)
+}
+
+unsafe extern "C" fn retain_trampoline(context: *const c_void) {
+    Arc::increment_strong_count(context.cast::<Callback>());
+}
+
+unsafe extern "C" fn release_trampoline(context: *const c_void) {
+    drop(Arc::from_raw(context.cast::<Callback>()));
+}
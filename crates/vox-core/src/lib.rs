@@ -0,0 +1,45 @@
+//! Vox media wire protocol.
+//!
+//! The 22-byte media header, its flag and codec values, frame builders and
+//! video fragmentation/reassembly, shared by the SFU and its clients so the
+//! format is defined in exactly one place. [`key_sink`] is the C-ABI media
+//! key handoff between this SDK's own extension modules,
+//! [`telemetry`] (feature `otel`) their shared OTLP span exporter,
+//! [`signal`] (feature `signal`) the gateway signaling messages,
+//! [`files`] (feature `files`) encrypted file attachments, [`settings`]
+//! (feature `settings`) the persistent settings store, [`session`] and
+//! [`sframe`] (feature `session`) the sans-IO client media session and its
+//! end-to-end media keys, [`validate`] the strict checks on received frames,
+//! [`ratelimit`] the per-sender and total limits on them,
+//! [`mobile`] (feature `uniffi`) the session's bindings for iOS and Android,
+//! and [`profile`] the opt-in timing histograms behind each module's
+//! `profile_report`.
+
+#[cfg(feature = "files")]
+pub mod files;
+pub mod fragment;
+pub mod frame;
+pub mod header;
+pub mod key_sink;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod profile;
+pub mod ratelimit;
+#[cfg(feature = "session")]
+pub mod session;
+#[cfg(feature = "settings")]
+pub mod settings;
+#[cfg(feature = "session")]
+pub mod sframe;
+#[cfg(feature = "signal")]
+pub mod signal;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod validate;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+pub use fragment::*;
+pub use frame::*;
+pub use header::*;
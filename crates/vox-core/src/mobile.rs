@@ -0,0 +1,260 @@
+//! UniFFI bindings for the sans-IO media session.
+//!
+//! iOS and Android apps capture, encode and send media with the platform's
+//! own APIs; [`MobileMediaSession`] gives them the same framing, receive
+//! tracking and SFrame sealing as vox-media. Every method takes datagrams
+//! in and hands datagrams back, reading the clock itself. vox-mls links
+//! this module into its mobile library so one `.so`/`.a` carries both.
+
+use crate::fragment::{fragment_video, MAX_FRAGMENT_PAYLOAD};
+use crate::frame::FB_LEAVE;
+use crate::key_sink::KeySink;
+use crate::session::{MediaSession, ReceiveStatsSnapshot, SessionEvent};
+use crate::sframe::KeyRing;
+use bytes::Bytes;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Error from [`MobileMediaSession`].
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum SessionError {
+    /// A payload could not be sealed with the installed media key.
+    Crypto(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Crypto(msg) => write!(f, "media encryption failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Receive statistics for one remote user. Packet counters refer to the
+/// audio stream.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct UserReceiveStats {
+    pub bitrate_kbps: f64,
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+    /// Milliseconds since the last packet from this user.
+    pub idle_ms: u64,
+    pub concealed_frames: u64,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    pub packets_reordered: u64,
+    pub packets_duplicated: u64,
+}
+
+impl UserReceiveStats {
+    fn new(snapshot: ReceiveStatsSnapshot, now: Instant) -> Self {
+        UserReceiveStats {
+            bitrate_kbps: snapshot.bitrate_kbps,
+            loss_percent: snapshot.loss_percent,
+            jitter_ms: snapshot.jitter_ms,
+            idle_ms: now.duration_since(snapshot.last_packet).as_millis() as u64,
+            concealed_frames: snapshot.concealed_frames,
+            packets_received: snapshot.packets_received,
+            packets_lost: snapshot.packets_lost,
+            packets_reordered: snapshot.packets_reordered,
+            packets_duplicated: snapshot.packets_duplicated,
+        }
+    }
+}
+
+/// Output of [`MobileMediaSession::poll_event`]. Payloads are already
+/// opened; frames that fail to decrypt are dropped.
+#[derive(Debug, Clone, uniffi::Enum)]
+pub enum MediaSessionEvent {
+    /// First media from a user since they were last seen.
+    UserActive { user_id: u32 },
+    /// A user's media stopped for longer than the idle timeout.
+    UserInactive { user_id: u32, stats: UserReceiveStats },
+    /// An encoded Opus frame, in order. `gap` frames before it were lost.
+    Audio { user_id: u32, sequence: u32, timestamp: u32, gap: u32, payload: Vec<u8> },
+    /// An encoded video frame.
+    Video { user_id: u32, timestamp: u32, is_keyframe: bool, data: Vec<u8> },
+    /// A data channel message.
+    Data { user_id: u32, channel_id: u16, timestamp: u32, payload: Vec<u8> },
+}
+
+/// Protocol state of one client in one room, plus its media keys.
+#[derive(uniffi::Object)]
+pub struct MobileMediaSession {
+    session: Mutex<MediaSession>,
+    keys: Arc<Mutex<KeyRing>>,
+}
+
+impl MobileMediaSession {
+    /// A sink that installs keys in this session's key ring, for
+    /// `MlsEngine::attach_media_key_sink`. It stops accepting keys once
+    /// the session is dropped.
+    pub fn media_key_sink(&self) -> KeySink {
+        let keys = Arc::downgrade(&self.keys);
+        KeySink::new(move |key_id, key| match keys.upgrade() {
+            Some(keys) => {
+                lock(&keys).set_key(key_id, key.to_vec());
+                true
+            }
+            None => false,
+        })
+    }
+
+    fn seal(&self, user_id: u32, payload: &[u8]) -> Result<Vec<u8>, SessionError> {
+        let mut keys = lock(&self.keys);
+        if !keys.is_enabled() {
+            return Ok(payload.to_vec());
+        }
+        keys.encrypt(user_id, payload).map_err(SessionError::Crypto)
+    }
+
+    fn open(&self, user_id: u32, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut keys = lock(&self.keys);
+        if !keys.is_enabled() {
+            return Some(payload.to_vec());
+        }
+        keys.decrypt(user_id, payload).ok()
+    }
+}
+
+#[uniffi::export]
+impl MobileMediaSession {
+    #[uniffi::constructor]
+    pub fn new(room_id: u32, user_id: u32) -> Arc<Self> {
+        Arc::new(MobileMediaSession {
+            session: Mutex::new(MediaSession::new(room_id, user_id)),
+            keys: Arc::new(Mutex::new(KeyRing::new())),
+        })
+    }
+
+    /// Install an SFrame base key and send with it from now on. Media is
+    /// sent in the clear until the first key is installed.
+    pub fn set_media_key(&self, key_id: u64, key: Vec<u8>) {
+        lock(&self.keys).set_key(key_id, key);
+    }
+
+    /// Remove all media keys, disabling media E2EE.
+    pub fn clear_media_keys(&self) {
+        lock(&self.keys).clear();
+    }
+
+    /// The key id currently used for sending, if any.
+    pub fn active_key_id(&self) -> Option<u64> {
+        lock(&self.keys).active_key_id()
+    }
+
+    /// Seal and frame one encoded 20 ms Opus frame. Returns the datagram.
+    pub fn audio_datagram(&self, payload: Vec<u8>, dtx: bool) -> Result<Vec<u8>, SessionError> {
+        let mut session = lock(&self.session);
+        let sealed = self.seal(session.user_id(), &payload)?;
+        Ok(session.audio_frame(Bytes::from(sealed), dtx).encode().to_vec())
+    }
+
+    /// Seal and fragment one encoded video frame into datagrams of at most
+    /// `max_payload` payload bytes (default: 1200-byte datagrams).
+    pub fn video_datagrams(
+        &self,
+        frame: Vec<u8>,
+        is_keyframe: bool,
+        max_payload: Option<u32>,
+    ) -> Result<Vec<Vec<u8>>, SessionError> {
+        let mut session = lock(&self.session);
+        let (room_id, user_id) = (session.room_id(), session.user_id());
+        let sealed = self.seal(user_id, &frame)?;
+        let timestamp = session.next_video_timestamp();
+        let sequence = session.video_sequence_mut();
+        let max_payload = max_payload.map_or(MAX_FRAGMENT_PAYLOAD, |m| m as usize);
+        let fragments = fragment_video(room_id, user_id, *sequence, timestamp, is_keyframe, &sealed, max_payload);
+        *sequence = sequence.wrapping_add(fragments.len() as u32);
+        Ok(fragments.iter().map(|f| f.encode().to_vec()).collect())
+    }
+
+    /// Start the video stream over, as after re-enabling the camera.
+    pub fn reset_video(&self) {
+        lock(&self.session).reset_video();
+    }
+
+    /// Seal and frame a data channel message.
+    pub fn data_datagram(&self, channel_id: u16, data: Vec<u8>) -> Result<Vec<u8>, SessionError> {
+        let mut session = lock(&self.session);
+        let sealed = self.seal(session.user_id(), &data)?;
+        Ok(session.data_frame(channel_id, &sealed).encode().to_vec())
+    }
+
+    /// The datagram telling the SFU this client is leaving the room.
+    pub fn leave_datagram(&self) -> Vec<u8> {
+        lock(&self.session).feedback_frame(Bytes::from_static(&[FB_LEAVE])).encode().to_vec()
+    }
+
+    /// Handle one received datagram. Video is only reassembled when
+    /// `accept_video` is true. Unparseable input is ignored.
+    pub fn handle_datagram(&self, datagram: Vec<u8>, accept_video: bool) {
+        lock(&self.session).handle_datagram(Instant::now(), Bytes::from(datagram), accept_video);
+    }
+
+    /// Expire idle senders and stale partial video frames. Call
+    /// periodically, e.g. every 100 ms.
+    pub fn handle_timeout(&self, idle_timeout_ms: u64, reassembly_timeout_ms: u64) {
+        lock(&self.session).handle_timeout(
+            Instant::now(),
+            Duration::from_millis(idle_timeout_ms),
+            Duration::from_millis(reassembly_timeout_ms),
+        );
+    }
+
+    /// The next event, or None when there are none.
+    pub fn poll_event(&self) -> Option<MediaSessionEvent> {
+        loop {
+            let event = lock(&self.session).poll_event()?;
+            return Some(match event {
+                SessionEvent::UserActive(user_id) => MediaSessionEvent::UserActive { user_id },
+                SessionEvent::UserInactive { user_id, stats } => MediaSessionEvent::UserInactive {
+                    user_id,
+                    stats: UserReceiveStats::new(stats.snapshot(), Instant::now()),
+                },
+                SessionEvent::Audio { user_id, sequence, timestamp, gap, payload } => {
+                    let Some(payload) = self.open(user_id, &payload) else {
+                        continue;
+                    };
+                    MediaSessionEvent::Audio { user_id, sequence, timestamp, gap, payload }
+                }
+                SessionEvent::Video(frame) => {
+                    let Some(data) = self.open(frame.user_id, &frame.data) else {
+                        continue;
+                    };
+                    MediaSessionEvent::Video {
+                        user_id: frame.user_id,
+                        timestamp: frame.timestamp,
+                        is_keyframe: frame.is_keyframe,
+                        data,
+                    }
+                }
+                SessionEvent::Data { user_id, channel_id, timestamp, payload } => {
+                    let Some(payload) = self.open(user_id, &payload) else {
+                        continue;
+                    };
+                    MediaSessionEvent::Data { user_id, channel_id, timestamp, payload }
+                }
+            });
+        }
+    }
+
+    /// Statistics for a user with recent media.
+    pub fn user_stats(&self, user_id: u32) -> Option<UserReceiveStats> {
+        let session = lock(&self.session);
+        session.stats(user_id).map(|stats| UserReceiveStats::new(stats.snapshot(), Instant::now()))
+    }
+
+    /// Forget a user who left the room.
+    pub fn drop_user(&self, user_id: u32) {
+        lock(&self.session).drop_user(user_id);
+    }
+}
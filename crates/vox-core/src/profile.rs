@@ -0,0 +1,144 @@
+//! Opt-in timing histograms for hot operations.
+//!
+//! Each extension module declares a [`Histogram`] static per operation and
+//! wraps the operation in [`Histogram::start`]. Recording is off until
+//! [`set_enabled`] turns it on, and costs one relaxed atomic load while off.
+//! Durations go into power-of-two nanosecond buckets, so recording never
+//! locks or allocates; percentiles are interpolated within a bucket.
+//!
+//! The switch and the statics live in each shared library that links this
+//! crate, so vox-media and vox-mls are profiled independently.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of buckets. Bucket `i > 0` holds durations in
+/// `[2^(i-1), 2^i)` ns; the last also holds anything longer.
+pub const BUCKETS: usize = 48;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn recording on or off for this library.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Lock-free duration histogram for one named operation.
+pub struct Histogram {
+    name: &'static str,
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    max_ns: AtomicU64,
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Histogram {
+    pub const fn new(name: &'static str) -> Self {
+        Histogram {
+            name,
+            count: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+            max_ns: AtomicU64::new(0),
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Start timing one call, or None while profiling is off. The duration
+    /// is recorded when the returned guard drops.
+    #[inline]
+    pub fn start(&self) -> Option<Timer<'_>> {
+        is_enabled().then(|| Timer { histogram: self, started: Instant::now() })
+    }
+
+    /// Record one call that took `elapsed`, whether or not profiling is on.
+    pub fn record(&self, elapsed: Duration) {
+        let ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.max_ns.fetch_max(ns, Ordering::Relaxed);
+        self.buckets[bucket_index(ns)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Copy the current counts. With `reset`, the counts are cleared as they
+    /// are read; calls recorded concurrently land in one snapshot or the next.
+    pub fn snapshot(&self, reset: bool) -> Snapshot {
+        let take = |v: &AtomicU64| if reset { v.swap(0, Ordering::Relaxed) } else { v.load(Ordering::Relaxed) };
+        Snapshot {
+            name: self.name,
+            count: take(&self.count),
+            total_ns: take(&self.total_ns),
+            max_ns: take(&self.max_ns),
+            buckets: std::array::from_fn(|i| take(&self.buckets[i])),
+        }
+    }
+}
+
+fn bucket_index(ns: u64) -> usize {
+    ((u64::BITS - ns.leading_zeros()) as usize).min(BUCKETS - 1)
+}
+
+/// Guard returned by [`Histogram::start`].
+pub struct Timer<'a> {
+    histogram: &'a Histogram,
+    started: Instant,
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        self.histogram.record(self.started.elapsed());
+    }
+}
+
+/// Point-in-time copy of a [`Histogram`].
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub name: &'static str,
+    pub count: u64,
+    pub total_ns: u64,
+    pub max_ns: u64,
+    pub buckets: [u64; BUCKETS],
+}
+
+impl Snapshot {
+    pub fn mean_ns(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total_ns as f64 / self.count as f64
+        }
+    }
+
+    /// Estimated `q`-quantile (0.0 to 1.0) in nanoseconds, never above the
+    /// recorded maximum.
+    pub fn percentile_ns(&self, q: f64) -> f64 {
+        let total: u64 = self.buckets.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let rank = q.clamp(0.0, 1.0) * total as f64;
+        let mut seen = 0u64;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            if n == 0 {
+                continue;
+            }
+            if (seen + n) as f64 >= rank {
+                let (lower, upper) = match i {
+                    0 => (0.0, 1.0),
+                    _ => ((1u64 << (i - 1)) as f64, (1u64 << i) as f64),
+                };
+                let within = (rank - seen as f64) / n as f64;
+                return (lower + within * (upper - lower)).min(self.max_ns as f64);
+            }
+            seen += n;
+        }
+        self.max_ns as f64
+    }
+}
@@ -0,0 +1,236 @@
+//! Rate limits on received frames.
+//!
+//! [`RateLimiter`] keeps a token bucket per sender and one for all senders
+//! together, each limiting packets and bytes per second, so one participant
+//! spraying datagrams cannot starve the others or the decoders. Frames over
+//! a limit are dropped before they are parsed further. Like
+//! [`crate::validate::MalformedTracker`], drops are counted and summarized
+//! in at most one [`RateLimitReport`] per interval.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A packet and byte rate; `None` leaves that dimension unlimited. Bursts
+/// of up to one second's worth are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub packets_per_sec: Option<u32>,
+    pub bytes_per_sec: Option<u64>,
+}
+
+impl Rate {
+    pub const UNLIMITED: Rate = Rate { packets_per_sec: None, bytes_per_sec: None };
+}
+
+/// Limits on what remote senders may cost us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Applied to each sender separately.
+    pub per_user: Rate,
+    /// Applied to all senders together.
+    pub total: Rate,
+    /// Partial video frames held for one sender; beyond it their oldest
+    /// partial frame is discarded.
+    pub max_reassembly_per_user: usize,
+    /// Partial video frames held for everyone.
+    pub max_reassembly_total: usize,
+    /// At most one report per interval.
+    pub report_interval: Duration,
+}
+
+impl Default for RateLimits {
+    /// Far above what a sender of 1080p video and Opus audio needs, so only
+    /// misbehaving senders are limited.
+    fn default() -> Self {
+        RateLimits {
+            per_user: Rate { packets_per_sec: Some(2_000), bytes_per_sec: Some(2_000_000) },
+            total: Rate { packets_per_sec: Some(20_000), bytes_per_sec: Some(16_000_000) },
+            max_reassembly_per_user: 32,
+            max_reassembly_total: 256,
+            report_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Which limit a frame exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limited {
+    UserPackets(u32),
+    UserBytes(u32),
+    TotalPackets,
+    TotalBytes,
+}
+
+impl Limited {
+    /// Short name used as the statistics key.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Limited::UserPackets(_) => "user_packets",
+            Limited::UserBytes(_) => "user_bytes",
+            Limited::TotalPackets => "total_packets",
+            Limited::TotalBytes => "total_bytes",
+        }
+    }
+}
+
+impl fmt::Display for Limited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limited::UserPackets(user_id) => write!(f, "user {user_id} exceeded the packet rate"),
+            Limited::UserBytes(user_id) => write!(f, "user {user_id} exceeded the byte rate"),
+            Limited::TotalPackets => f.write_str("total packet rate exceeded"),
+            Limited::TotalBytes => f.write_str("total byte rate exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for Limited {}
+
+/// Rate-limited frames and discarded partial frames during one interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitReport {
+    /// Frames dropped in this interval.
+    pub dropped: u64,
+    /// Dropped frames in this interval by [`Limited::reason`].
+    pub by_reason: BTreeMap<&'static str, u64>,
+    /// Senders that exceeded their own limit.
+    pub users: BTreeSet<u32>,
+    /// Partial video frames discarded over the reassembly limits.
+    pub evicted: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    packets: f64,
+    bytes: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn full(rate: &Rate, now: Instant) -> Self {
+        Bucket {
+            packets: rate.packets_per_sec.unwrap_or(0) as f64,
+            bytes: rate.bytes_per_sec.unwrap_or(0) as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        if let Some(pps) = rate.packets_per_sec {
+            self.packets = (self.packets + elapsed * pps as f64).min(pps as f64);
+        }
+        if let Some(bps) = rate.bytes_per_sec {
+            self.bytes = (self.bytes + elapsed * bps as f64).min(bps as f64);
+        }
+    }
+
+    /// Whether a packet of `len` bytes fits: `Err(true)` if the packet
+    /// rate is exhausted, `Err(false)` if the byte rate is.
+    fn check(&self, rate: &Rate, len: usize) -> Result<(), bool> {
+        if rate.packets_per_sec.is_some() && self.packets < 1.0 {
+            return Err(true);
+        }
+        if rate.bytes_per_sec.is_some() && self.bytes < len as f64 {
+            return Err(false);
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) {
+        self.packets -= 1.0;
+        self.bytes -= len as f64;
+    }
+}
+
+/// Applies [`RateLimits`] to received frames and rate-limits their reports.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    users: HashMap<u32, Bucket>,
+    total: Bucket,
+    interval_start: Instant,
+    dropped: u64,
+    by_reason: BTreeMap<&'static str, u64>,
+    users_limited: BTreeSet<u32>,
+    evicted: u64,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, now: Instant) -> Self {
+        RateLimiter {
+            total: Bucket::full(&limits.total, now),
+            limits,
+            users: HashMap::new(),
+            interval_start: now,
+            dropped: 0,
+            by_reason: BTreeMap::new(),
+            users_limited: BTreeSet::new(),
+            evicted: 0,
+        }
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Admit or refuse a `len`-byte frame from `user_id`. Refused frames
+    /// are counted and cost nothing against the limits.
+    pub fn check(&mut self, now: Instant, user_id: u32, len: usize) -> Result<(), Limited> {
+        let per_user = self.limits.per_user;
+        let user = self.users.entry(user_id).or_insert_with(|| Bucket::full(&per_user, now));
+        user.refill(&per_user, now);
+        self.total.refill(&self.limits.total, now);
+
+        let limited = match (user.check(&per_user, len), self.total.check(&self.limits.total, len)) {
+            (Err(true), _) => Limited::UserPackets(user_id),
+            (Err(false), _) => Limited::UserBytes(user_id),
+            (Ok(()), Err(true)) => Limited::TotalPackets,
+            (Ok(()), Err(false)) => Limited::TotalBytes,
+            (Ok(()), Ok(())) => {
+                user.take(len);
+                self.total.take(len);
+                return Ok(());
+            }
+        };
+        self.dropped += 1;
+        *self.by_reason.entry(limited.reason()).or_default() += 1;
+        if let Limited::UserPackets(user_id) | Limited::UserBytes(user_id) = limited {
+            self.users_limited.insert(user_id);
+        }
+        Err(limited)
+    }
+
+    /// Count partial video frames discarded over the reassembly limits.
+    pub fn record_evictions(&mut self, count: u64) {
+        self.evicted += count;
+    }
+
+    /// Forget a sender who left.
+    pub fn drop_user(&mut self, user_id: u32) {
+        self.users.remove(&user_id);
+    }
+
+    /// Close the current interval if it has run its course, returning a
+    /// report if anything was dropped or evicted. Senders silent for the
+    /// whole interval are forgotten, so spoofed user ids cannot grow the
+    /// bucket map without bound.
+    pub fn poll(&mut self, now: Instant) -> Option<RateLimitReport> {
+        if now.saturating_duration_since(self.interval_start) < self.limits.report_interval {
+            return None;
+        }
+        let interval_start = std::mem::replace(&mut self.interval_start, now);
+        self.users.retain(|_, bucket| bucket.last >= interval_start);
+        if self.dropped == 0 && self.evicted == 0 {
+            return None;
+        }
+        Some(RateLimitReport {
+            dropped: std::mem::take(&mut self.dropped),
+            by_reason: std::mem::take(&mut self.by_reason),
+            users: std::mem::take(&mut self.users_limited),
+            evicted: std::mem::take(&mut self.evicted),
+        })
+    }
+}
@@ -0,0 +1,438 @@
+//! Sans-IO media session.
+//!
+//! [`MediaSession`] holds the protocol state of one client in a room, with
+//! no sockets, threads or clocks of its own: the caller passes in received
+//! frames and the current time, sends the frames it builds, and drains
+//! [`SessionEvent`]s. It numbers outgoing frames, tracks each sender's
+//! sequence numbers and jitter, and reassembles video. vox-media drives one
+//! from its tokio runtime; tests and other runtimes can drive it directly.
+//!
+//! Payload sealing is left to the caller, with [`crate::sframe::KeyRing`],
+//! so that insertable transforms can run between codec and cipher.
+
+use crate::fragment::{ReassembledFrame, VideoReassembler};
+use crate::frame::{InFrame, OutFrame, DATA_CHANNEL_PREFIX};
+use crate::header::*;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Media clock ticks per 20 ms audio frame at 48 kHz.
+pub const AUDIO_FRAME_TICKS: u32 = 960;
+/// Number of recent sequence numbers remembered for duplicate/reorder checks.
+const HISTORY: u32 = 64;
+/// Forward jumps larger than this are treated as a sender restart rather
+/// than a burst of loss.
+const MAX_SEQUENCE_GAP: u32 = 3000;
+/// Audio RTP-style clock rate used by media timestamps (48 kHz).
+const AUDIO_CLOCK_HZ: f64 = 48_000.0;
+/// Bitrate is averaged over windows of this length.
+const BITRATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Classification of an incoming packet by its sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceOutcome {
+    /// Newest packet so far; `gap` packets between it and the previous newest
+    /// are missing.
+    InOrder { gap: u32 },
+    /// Arrived after a newer packet; its slot was already counted as lost.
+    Reordered,
+    /// Already received.
+    Duplicate,
+    /// Too old to classify (outside the history window). Not counted.
+    Late,
+}
+
+/// Tracks a sender's sequence numbers to count loss, reordering and
+/// duplicates. Handles u32 wrap-around.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    /// Highest sequence number received.
+    highest: Option<u32>,
+    /// Bit `i` set means `highest - i` was received.
+    history: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub reordered: u64,
+    pub duplicates: u64,
+}
+
+impl SequenceTracker {
+    /// Record a packet and classify it.
+    pub fn record(&mut self, seq: u32) -> SequenceOutcome {
+        let Some(highest) = self.highest else {
+            self.restart(seq);
+            return SequenceOutcome::InOrder { gap: 0 };
+        };
+
+        let ahead = seq.wrapping_sub(highest);
+        if ahead == 0 {
+            self.duplicates += 1;
+            return SequenceOutcome::Duplicate;
+        }
+        if ahead <= MAX_SEQUENCE_GAP {
+            let gap = ahead - 1;
+            self.history = if ahead >= HISTORY { 0 } else { self.history << ahead };
+            self.history |= 1;
+            self.highest = Some(seq);
+            self.received += 1;
+            self.lost += u64::from(gap);
+            return SequenceOutcome::InOrder { gap };
+        }
+
+        let behind = highest.wrapping_sub(seq);
+        if behind < HISTORY {
+            let bit = 1u64 << behind;
+            if self.history & bit != 0 {
+                self.duplicates += 1;
+                return SequenceOutcome::Duplicate;
+            }
+            self.history |= bit;
+            self.received += 1;
+            self.reordered += 1;
+            self.lost = self.lost.saturating_sub(1);
+            return SequenceOutcome::Reordered;
+        }
+        if behind <= MAX_SEQUENCE_GAP {
+            return SequenceOutcome::Late;
+        }
+
+        // Far outside the window in both directions: the sender restarted
+        self.restart(seq);
+        SequenceOutcome::InOrder { gap: 0 }
+    }
+
+    fn restart(&mut self, seq: u32) {
+        self.highest = Some(seq);
+        self.history = 1;
+        self.received += 1;
+    }
+
+    /// Fraction of expected packets that were lost, 0.0–1.0.
+    pub fn loss_fraction(&self) -> f64 {
+        let expected = self.received + self.lost;
+        if expected == 0 {
+            0.0
+        } else {
+            self.lost as f64 / expected as f64
+        }
+    }
+}
+
+/// Interarrival jitter estimate (RFC 3550 §6.4.1).
+#[derive(Debug, Default)]
+pub struct JitterEstimator {
+    /// Previous (arrival, media timestamp) pair.
+    last: Option<(Instant, u32)>,
+    /// Smoothed jitter in clock units.
+    jitter: f64,
+}
+
+impl JitterEstimator {
+    pub fn record(&mut self, arrival: Instant, timestamp: u32) {
+        if let Some((prev_arrival, prev_ts)) = self.last {
+            let arrival_delta = arrival.duration_since(prev_arrival).as_secs_f64() * AUDIO_CLOCK_HZ;
+            let ts_delta = timestamp.wrapping_sub(prev_ts) as i32 as f64;
+            let d = (arrival_delta - ts_delta).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last = Some((arrival, timestamp));
+    }
+
+    pub fn millis(&self) -> f64 {
+        self.jitter / AUDIO_CLOCK_HZ * 1000.0
+    }
+}
+
+/// Receive statistics for one remote user.
+#[derive(Debug)]
+pub struct ReceiveStats {
+    /// Audio sequence tracking (loss, reordering, duplicates).
+    pub audio_seq: SequenceTracker,
+    jitter: JitterEstimator,
+    /// Frames synthesized by packet loss concealment.
+    pub concealed_frames: u64,
+    pub last_packet: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+    bitrate_kbps: f64,
+}
+
+impl ReceiveStats {
+    pub fn new(now: Instant) -> Self {
+        ReceiveStats {
+            audio_seq: SequenceTracker::default(),
+            jitter: JitterEstimator::default(),
+            concealed_frames: 0,
+            last_packet: now,
+            window_start: now,
+            window_bytes: 0,
+            bitrate_kbps: 0.0,
+        }
+    }
+
+    /// Record any media packet of `len` bytes from this user.
+    pub fn record_packet(&mut self, now: Instant, len: usize) {
+        self.last_packet = now;
+        self.window_bytes += len as u64;
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= BITRATE_WINDOW {
+            self.bitrate_kbps = self.window_bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1000.0;
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+    }
+
+    /// Record the media timestamp of an in-order audio packet.
+    pub fn record_audio_timing(&mut self, now: Instant, timestamp: u32) {
+        self.jitter.record(now, timestamp);
+    }
+
+    pub fn snapshot(&self) -> ReceiveStatsSnapshot {
+        ReceiveStatsSnapshot {
+            bitrate_kbps: self.bitrate_kbps,
+            loss_percent: self.audio_seq.loss_fraction() * 100.0,
+            jitter_ms: self.jitter.millis(),
+            last_packet: self.last_packet,
+            concealed_frames: self.concealed_frames,
+            packets_received: self.audio_seq.received,
+            packets_lost: self.audio_seq.lost,
+            packets_reordered: self.audio_seq.reordered,
+            packets_duplicated: self.audio_seq.duplicates,
+        }
+    }
+}
+
+/// Point-in-time copy of a user's receive statistics. Packet counters refer
+/// to the audio stream.
+#[derive(Debug, Clone)]
+pub struct ReceiveStatsSnapshot {
+    pub bitrate_kbps: f64,
+    pub loss_percent: f64,
+    pub jitter_ms: f64,
+    pub last_packet: Instant,
+    pub concealed_frames: u64,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    pub packets_reordered: u64,
+    pub packets_duplicated: u64,
+}
+
+/// Output of a [`MediaSession`], drained with [`MediaSession::poll_event`].
+#[derive(Debug)]
+pub enum SessionEvent {
+    /// First media from a user since they were last seen.
+    UserActive(u32),
+    /// A user's media stopped for longer than the idle timeout. Carries
+    /// their final statistics.
+    UserInactive { user_id: u32, stats: ReceiveStats },
+    /// An in-order audio payload, still sealed. `gap` frames before it
+    /// were lost.
+    Audio { user_id: u32, sequence: u32, timestamp: u32, gap: u32, payload: Bytes },
+    /// A reassembled video frame, still sealed.
+    Video(ReassembledFrame),
+    /// A data channel message, still sealed.
+    Data { user_id: u32, channel_id: u16, timestamp: u32, payload: Bytes },
+}
+
+/// Protocol state of one client in one room.
+pub struct MediaSession {
+    room_id: u32,
+    user_id: u32,
+    audio_sequence: u32,
+    audio_timestamp: u32,
+    video_sequence: u32,
+    video_timestamp: u32,
+    data_sequence: u32,
+    feedback_sequence: u32,
+    receivers: HashMap<u32, ReceiveStats>,
+    reassembler: VideoReassembler,
+    events: VecDeque<SessionEvent>,
+}
+
+impl MediaSession {
+    pub fn new(room_id: u32, user_id: u32) -> Self {
+        MediaSession {
+            room_id,
+            user_id,
+            audio_sequence: 0,
+            audio_timestamp: 0,
+            video_sequence: 0,
+            video_timestamp: 0,
+            data_sequence: 0,
+            feedback_sequence: 0,
+            receivers: HashMap::new(),
+            reassembler: VideoReassembler::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn room_id(&self) -> u32 {
+        self.room_id
+    }
+
+    pub fn user_id(&self) -> u32 {
+        self.user_id
+    }
+
+    /// Timestamp the next audio frame will carry.
+    pub fn audio_timestamp(&self) -> u32 {
+        self.audio_timestamp
+    }
+
+    /// Frame one encoded (and sealed) 20 ms audio payload and advance the
+    /// audio sequence and clock.
+    pub fn audio_frame(&mut self, payload: Bytes, dtx: bool) -> OutFrame {
+        let mut frame =
+            OutFrame::audio(self.room_id, self.user_id, CODEC_OPUS, self.audio_sequence, self.audio_timestamp, payload);
+        frame.header.dtx = dtx;
+        self.audio_sequence = self.audio_sequence.wrapping_add(1);
+        self.audio_timestamp = self.audio_timestamp.wrapping_add(AUDIO_FRAME_TICKS);
+        frame
+    }
+
+    /// Claim the timestamp for the next encoded video frame.
+    pub fn next_video_timestamp(&mut self) -> u32 {
+        let ts = self.video_timestamp;
+        self.video_timestamp = self.video_timestamp.wrapping_add(1);
+        ts
+    }
+
+    /// Sequence counter for video fragments, advanced by whoever fragments
+    /// frames (see [`crate::fragment_video`]).
+    pub fn video_sequence_mut(&mut self) -> &mut u32 {
+        &mut self.video_sequence
+    }
+
+    /// Start the video stream over, as after re-enabling the camera.
+    pub fn reset_video(&mut self) {
+        self.video_sequence = 0;
+        self.video_timestamp = 0;
+    }
+
+    /// Frame a data channel message.
+    pub fn data_frame(&mut self, channel_id: u16, data: &[u8]) -> OutFrame {
+        let frame = OutFrame::data(self.room_id, self.user_id, self.data_sequence, channel_id, data);
+        self.data_sequence = self.data_sequence.wrapping_add(1);
+        frame
+    }
+
+    /// Frame an RTCP feedback payload for the SFU.
+    pub fn feedback_frame(&mut self, payload: Bytes) -> OutFrame {
+        let frame = OutFrame::feedback(self.room_id, self.user_id, self.feedback_sequence, payload);
+        self.feedback_sequence = self.feedback_sequence.wrapping_add(1);
+        frame
+    }
+
+    /// Parse and handle one received datagram. Unparseable input is ignored.
+    pub fn handle_datagram(&mut self, now: Instant, data: Bytes, accept_video: bool) {
+        if let Some(frame) = InFrame::decode(data) {
+            self.handle_frame(now, frame, accept_video);
+        }
+    }
+
+    /// Handle one received frame. Video from senders the caller does not
+    /// want (`accept_video` false) still counts towards their statistics but
+    /// is not reassembled.
+    pub fn handle_frame(&mut self, now: Instant, frame: InFrame, accept_video: bool) {
+        let header = frame.header;
+        let user_id = header.user_id;
+        let is_media = matches!(header.media_type, MEDIA_TYPE_AUDIO | MEDIA_TYPE_VIDEO);
+        if is_media {
+            let stats = self.receivers.entry(user_id).or_insert_with(|| {
+                self.events.push_back(SessionEvent::UserActive(user_id));
+                ReceiveStats::new(now)
+            });
+            stats.record_packet(now, HEADER_SIZE + frame.payload.len());
+        }
+
+        match header.media_type {
+            MEDIA_TYPE_AUDIO => {
+                let Some(stats) = self.receivers.get_mut(&user_id) else {
+                    return;
+                };
+                // Late and repeated packets would play out of order; their
+                // slots were already filled by concealment
+                let SequenceOutcome::InOrder { gap } = stats.audio_seq.record(header.sequence) else {
+                    return;
+                };
+                stats.record_audio_timing(now, header.timestamp);
+                self.events.push_back(SessionEvent::Audio {
+                    user_id,
+                    sequence: header.sequence,
+                    timestamp: header.timestamp,
+                    gap,
+                    payload: frame.payload,
+                });
+            }
+            MEDIA_TYPE_VIDEO if accept_video => {
+                if let Some(reassembled) = self.reassembler.add_fragment(&header, &frame.payload) {
+                    self.events.push_back(SessionEvent::Video(reassembled));
+                }
+            }
+            MEDIA_TYPE_DATA if frame.payload.len() >= DATA_CHANNEL_PREFIX => {
+                let channel_id = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+                self.events.push_back(SessionEvent::Data {
+                    user_id,
+                    channel_id,
+                    timestamp: header.timestamp,
+                    payload: frame.payload.slice(DATA_CHANNEL_PREFIX..),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Bound the partial video frames held at once, per sender and in
+    /// total; beyond them the oldest is discarded.
+    pub fn set_reassembly_limits(&mut self, max_per_user: usize, max_total: usize) {
+        self.reassembler.set_limits(max_per_user, max_total);
+    }
+
+    /// Partial video frames discarded over the limits since the last call.
+    pub fn take_reassembly_evictions(&mut self) -> u64 {
+        self.reassembler.take_evicted()
+    }
+
+    /// Expire idle senders and stale partial video frames. Call periodically.
+    pub fn handle_timeout(&mut self, now: Instant, idle_timeout: Duration, reassembly_timeout: Duration) {
+        self.reassembler.evict_stale(reassembly_timeout);
+        let idle: Vec<u32> = self
+            .receivers
+            .iter()
+            .filter(|(_, stats)| now.duration_since(stats.last_packet) >= idle_timeout)
+            .map(|(&user_id, _)| user_id)
+            .collect();
+        for user_id in idle {
+            if let Some(stats) = self.receivers.remove(&user_id) {
+                self.reassembler.drop_user(user_id);
+                self.events.push_back(SessionEvent::UserInactive { user_id, stats });
+            }
+        }
+    }
+
+    /// Forget a user who left the room, returning their statistics if they
+    /// were active.
+    pub fn drop_user(&mut self, user_id: u32) -> Option<ReceiveStats> {
+        self.reassembler.drop_user(user_id);
+        self.receivers.remove(&user_id)
+    }
+
+    pub fn poll_event(&mut self) -> Option<SessionEvent> {
+        self.events.pop_front()
+    }
+
+    /// Statistics for a user with recent media.
+    pub fn stats(&self, user_id: u32) -> Option<&ReceiveStats> {
+        self.receivers.get(&user_id)
+    }
+
+    pub fn stats_mut(&mut self, user_id: u32) -> Option<&mut ReceiveStats> {
+        self.receivers.get_mut(&user_id)
+    }
+
+    /// Whether any remote user sent media recently.
+    pub fn has_active_users(&self) -> bool {
+        !self.receivers.is_empty()
+    }
+}
@@ -0,0 +1,107 @@
+//! Persistent client settings shared by the SDK.
+//!
+//! A single SQLite table of text key/value pairs, so the Python layer can
+//! edit the same file with the standard `sqlite3` module (see
+//! `vox_sdk.settings`). vox-media reads [`MediaSettings`] from it when its
+//! runtime starts. Keys are dotted names; the ones the SDK understands are
+//! the constants below, and anything else is left for the application.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::time::Duration;
+
+/// Name of the preferred microphone, as listed by the audio backend.
+pub const INPUT_DEVICE: &str = "audio.input_device";
+/// Name of the preferred playback device.
+pub const OUTPUT_DEVICE: &str = "audio.output_device";
+/// Microphone gain, 1.0 being unity.
+pub const INPUT_VOLUME: &str = "audio.input_volume";
+/// Playback gain, 1.0 being unity.
+pub const OUTPUT_VOLUME: &str = "audio.output_volume";
+/// Index of the preferred camera.
+pub const CAMERA: &str = "video.camera";
+/// Automatic reconnection attempts after a dropped media connection.
+pub const RECONNECT_MAX_ATTEMPTS: &str = "reconnect.max_attempts";
+/// Longest wait between reconnection attempts, in seconds.
+pub const RECONNECT_MAX_BACKOFF_SECS: &str = "reconnect.max_backoff_secs";
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)";
+
+/// How long to wait for another process holding the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Handle to a settings database.
+pub struct SettingsStore {
+    conn: Connection,
+}
+
+impl SettingsStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SettingsStore { conn })
+    }
+
+    pub fn get(&self, key: &str) -> rusqlite::Result<Option<String>> {
+        self.conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> rusqlite::Result<()> {
+        self.conn
+            .execute("INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)", params![key, value])?;
+        Ok(())
+    }
+
+    pub fn remove(&self, key: &str) -> rusqlite::Result<()> {
+        self.conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// Every stored pair, sorted by key.
+    pub fn all(&self) -> rusqlite::Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare("SELECT key, value FROM settings ORDER BY key")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Read `key` and parse it, treating an unparsable value as unset.
+    fn parsed<T: std::str::FromStr>(&self, key: &str) -> rusqlite::Result<Option<T>> {
+        Ok(self.get(key)?.and_then(|v| v.trim().parse().ok()))
+    }
+}
+
+/// The settings vox-media applies to its sessions. Unset fields keep the
+/// runtime's own defaults.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaSettings {
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub input_volume: Option<f32>,
+    pub output_volume: Option<f32>,
+    pub camera: Option<u32>,
+    pub reconnect_max_attempts: Option<u32>,
+    pub reconnect_max_backoff_secs: Option<u64>,
+}
+
+impl MediaSettings {
+    /// Read the media keys from `store`. Values that do not parse, and
+    /// negative or non-finite volumes, are ignored.
+    pub fn load(store: &SettingsStore) -> rusqlite::Result<Self> {
+        let volume = |key| -> rusqlite::Result<Option<f32>> {
+            Ok(store.parsed::<f32>(key)?.filter(|v| v.is_finite() && *v >= 0.0))
+        };
+        Ok(MediaSettings {
+            input_device: store.get(INPUT_DEVICE)?.filter(|d| !d.is_empty()),
+            output_device: store.get(OUTPUT_DEVICE)?.filter(|d| !d.is_empty()),
+            input_volume: volume(INPUT_VOLUME)?,
+            output_volume: volume(OUTPUT_VOLUME)?,
+            camera: store.parsed(CAMERA)?,
+            reconnect_max_attempts: store.parsed(RECONNECT_MAX_ATTEMPTS)?,
+            reconnect_max_backoff_secs: store.parsed(RECONNECT_MAX_BACKOFF_SECS)?,
+        })
+    }
+}
@@ -0,0 +1,241 @@
+//! SFrame end-to-end encryption of encoded media payloads.
+//!
+//! Opus/AV1 frames are sealed before they are packetized into QUIC datagrams,
+//! so the SFU only ever forwards ciphertext. Keys are supplied from outside
+//! (typically an MLS exporter secret per epoch) and identified by a `key_id`;
+//! a few previous keys are retained so frames in flight across a rotation
+//! still decrypt.
+//!
+//! Wire format follows RFC 9605: a variable-length header carrying the key id
+//! (KID) and counter (CTR), followed by the AES-128-GCM ciphertext and tag.
+//! Each sender's key and salt are derived from the base key with HKDF-SHA256,
+//! mixing in the sender's user id so that senders sharing an epoch key never
+//! reuse a nonce. The send counter starts at a random value, so two key rings
+//! sending as the same user (a rebuilt client, or a second device) do not
+//! either.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, OsRng, Payload};
+use aes_gcm::{Aes128Gcm, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+
+/// RFC 9605 cipher suite: AES_128_GCM_SHA256_128.
+const CIPHER_SUITE: u16 = 0x0004;
+/// AEAD key length (Nk) in bytes.
+const KEY_LEN: usize = 16;
+/// AEAD nonce length (Nn) in bytes.
+const NONCE_LEN: usize = 12;
+/// Number of base keys kept for decrypting frames sent before a rotation.
+const MAX_RETAINED_KEYS: usize = 4;
+
+/// Per-sender AEAD state derived from a base key.
+#[derive(Clone)]
+struct SenderKey {
+    cipher: Aes128Gcm,
+    salt: [u8; NONCE_LEN],
+}
+
+/// Set of media keys plus the local send counter.
+pub struct KeyRing {
+    /// Base keys by key id, oldest first. The last entry is used for sending.
+    keys: VecDeque<(u64, Vec<u8>)>,
+    /// Derived per-sender keys, keyed by (key_id, user_id).
+    derived: HashMap<(u64, u32), SenderKey>,
+    /// Frame counter for outgoing frames, starting at a random value.
+    send_counter: u64,
+}
+
+impl Default for KeyRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyRing {
+    pub fn new() -> Self {
+        KeyRing {
+            keys: VecDeque::new(),
+            derived: HashMap::new(),
+            send_counter: OsRng.next_u64(),
+        }
+    }
+
+    /// Whether media E2EE is active (at least one key installed).
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// The key id currently used for sending, if any.
+    pub fn active_key_id(&self) -> Option<u64> {
+        self.keys.back().map(|(kid, _)| *kid)
+    }
+
+    /// Install a base key and make it the active send key.
+    ///
+    /// Older keys stay available for decryption until more than
+    /// `MAX_RETAINED_KEYS` have been installed.
+    pub fn set_key(&mut self, key_id: u64, key: Vec<u8>) {
+        self.keys.retain(|(kid, _)| *kid != key_id);
+        self.derived.retain(|(kid, _), _| *kid != key_id);
+        self.keys.push_back((key_id, key));
+        while self.keys.len() > MAX_RETAINED_KEYS {
+            if let Some((old, _)) = self.keys.pop_front() {
+                self.derived.retain(|(kid, _), _| *kid != old);
+            }
+        }
+    }
+
+    /// Remove all keys, disabling media E2EE.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.derived.clear();
+    }
+
+    /// Seal an encoded frame sent by `user_id` with the active key.
+    pub fn encrypt(&mut self, user_id: u32, payload: &[u8]) -> Result<Vec<u8>, String> {
+        let key_id = self.active_key_id().ok_or("no media key installed")?;
+        let ctr = self.send_counter;
+        self.send_counter = self.send_counter.wrapping_add(1);
+
+        let sender = self.sender_key(key_id, user_id)?;
+        let header = encode_header(key_id, ctr);
+        let nonce = make_nonce(&sender.salt, ctr);
+        let ciphertext = sender
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: payload, aad: &header })
+            .map_err(|e| format!("sframe encrypt: {e}"))?;
+
+        let mut out = header;
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Open a frame sent by `user_id`, selecting the key by the KID in its header.
+    pub fn decrypt(&mut self, user_id: u32, data: &[u8]) -> Result<Vec<u8>, String> {
+        let (key_id, ctr, header_len) = parse_header(data).ok_or("malformed sframe header")?;
+        let sender = self.sender_key(key_id, user_id)?;
+        let (header, ciphertext) = data.split_at(header_len);
+        let nonce = make_nonce(&sender.salt, ctr);
+        sender
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|e| format!("sframe decrypt (kid={key_id}): {e}"))
+    }
+
+    /// Look up or derive the AEAD state for a sender under a given key id.
+    fn sender_key(&mut self, key_id: u64, user_id: u32) -> Result<SenderKey, String> {
+        if let Some(sk) = self.derived.get(&(key_id, user_id)) {
+            return Ok(sk.clone());
+        }
+        let base = self
+            .keys
+            .iter()
+            .find(|(kid, _)| *kid == key_id)
+            .map(|(_, k)| k)
+            .ok_or_else(|| format!("unknown media key id {key_id}"))?;
+        let sk = derive_sender_key(base, key_id, user_id)?;
+        self.derived.insert((key_id, user_id), sk.clone());
+        Ok(sk)
+    }
+}
+
+/// Derive the per-sender key and salt (RFC 9605 §4.4.2, with the sender's
+/// user id folded into the base key first).
+fn derive_sender_key(base_key: &[u8], key_id: u64, user_id: u32) -> Result<SenderKey, String> {
+    let mut sender_secret = [0u8; 32];
+    let mut label = b"Vox SFrame sender ".to_vec();
+    label.extend_from_slice(&user_id.to_be_bytes());
+    Hkdf::<Sha256>::new(None, base_key)
+        .expand(&label, &mut sender_secret)
+        .map_err(|e| format!("sframe sender derivation: {e}"))?;
+
+    let hk = Hkdf::<Sha256>::new(None, &sender_secret);
+    let mut suffix = key_id.to_be_bytes().to_vec();
+    suffix.extend_from_slice(&CIPHER_SUITE.to_be_bytes());
+
+    let mut key = [0u8; KEY_LEN];
+    let mut key_label = b"SFrame 1.0 Secret key ".to_vec();
+    key_label.extend_from_slice(&suffix);
+    hk.expand(&key_label, &mut key)
+        .map_err(|e| format!("sframe key derivation: {e}"))?;
+
+    let mut salt = [0u8; NONCE_LEN];
+    let mut salt_label = b"SFrame 1.0 Secret salt ".to_vec();
+    salt_label.extend_from_slice(&suffix);
+    hk.expand(&salt_label, &mut salt)
+        .map_err(|e| format!("sframe salt derivation: {e}"))?;
+
+    let cipher = Aes128Gcm::new_from_slice(&key).map_err(|e| format!("sframe key: {e}"))?;
+    Ok(SenderKey { cipher, salt })
+}
+
+/// Nonce = salt XOR counter (left-padded to the nonce length).
+fn make_nonce(salt: &[u8; NONCE_LEN], ctr: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = *salt;
+    for (n, c) in nonce[NONCE_LEN - 8..].iter_mut().zip(ctr.to_be_bytes()) {
+        *n ^= c;
+    }
+    nonce
+}
+
+/// Minimal big-endian encoding of `v` (at least one byte).
+fn trimmed_be(v: u64) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+    let skip = (v.leading_zeros() / 8).min(7) as usize;
+    bytes[skip..].to_vec()
+}
+
+/// Encode an SFrame header.
+///
+/// ```text
+///  0 1 2 3 4 5 6 7
+/// +-+-+-+-+-+-+-+-+---------------------------------+
+/// |X|  K  |Y|  C  |   KID...  (if X)  |  CTR... (if Y)
+/// +-+-+-+-+-+-+-+-+---------------------------------+
+/// ```
+/// Values below 8 are stored inline in K/C; larger values set X/Y and store
+/// `len - 1` in K/C followed by the value bytes.
+fn encode_header(kid: u64, ctr: u64) -> Vec<u8> {
+    let mut config = 0u8;
+    let mut tail = Vec::new();
+    if kid < 8 {
+        config |= (kid as u8) << 4;
+    } else {
+        let b = trimmed_be(kid);
+        config |= 0x80 | (((b.len() - 1) as u8) << 4);
+        tail.extend_from_slice(&b);
+    }
+    if ctr < 8 {
+        config |= ctr as u8;
+    } else {
+        let b = trimmed_be(ctr);
+        config |= 0x08 | ((b.len() - 1) as u8);
+        tail.extend_from_slice(&b);
+    }
+    let mut out = Vec::with_capacity(1 + tail.len());
+    out.push(config);
+    out.extend_from_slice(&tail);
+    out
+}
+
+/// Parse an SFrame header, returning `(kid, ctr, header_len)`.
+fn parse_header(data: &[u8]) -> Option<(u64, u64, usize)> {
+    let config = *data.first()?;
+    let mut pos = 1;
+
+    let mut read_field = |extended: bool, bits: u8| -> Option<u64> {
+        if !extended {
+            return Some(bits as u64);
+        }
+        let len = bits as usize + 1;
+        let bytes = data.get(pos..pos + len)?;
+        pos += len;
+        Some(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    };
+
+    let kid = read_field(config & 0x80 != 0, (config >> 4) & 0x07)?;
+    let ctr = read_field(config & 0x08 != 0, config & 0x07)?;
+    Some((kid, ctr, pos))
+}
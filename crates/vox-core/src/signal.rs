@@ -0,0 +1,196 @@
+//! Gateway signaling messages.
+//!
+//! Every gateway message is a JSON object `{"type": ..., "seq": ..., "d": {...}}`.
+//! The server opens with `hello` carrying the heartbeat interval, the client
+//! answers with `identify` (or `resume` after a drop), and from then on the
+//! server numbers its dispatch events with `seq`. Over WebSocket each message
+//! is one text frame; over a QUIC stream each is a UTF-8 payload behind a
+//! big-endian `u32` length ([`encode_frame`], [`FrameDecoder`]).
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Map, Value};
+
+/// Gateway protocol version sent in `identify`.
+pub const GATEWAY_PROTOCOL_VERSION: u32 = 1;
+
+/// ALPN identifier for signaling over QUIC.
+pub const SIGNAL_ALPN: &[u8] = b"vox-signal/1";
+
+/// Largest message accepted on a QUIC signaling stream.
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// Close codes after which the session can be resumed.
+const RESUMABLE_CLOSE_CODES: [u16; 2] = [4007, 4008];
+
+/// Close codes after which the client may reconnect at all.
+const RECONNECTABLE_CLOSE_CODES: [u16; 9] = [4000, 4001, 4002, 4006, 4007, 4008, 4009, 4010, 4011];
+
+/// Whether a session closed with `code` can be resumed with `resume`.
+pub fn can_resume(code: u16) -> bool {
+    RESUMABLE_CLOSE_CODES.contains(&code)
+}
+
+/// Whether the client should reconnect after the gateway closed with `code`.
+/// Anything else (bad token, unsupported version, ...) is fatal.
+pub fn can_reconnect(code: u16) -> bool {
+    RECONNECTABLE_CLOSE_CODES.contains(&code)
+}
+
+/// Kind of MLS handshake message relayed through the gateway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MlsMessageType {
+    Welcome,
+    Commit,
+    Proposal,
+}
+
+impl MlsMessageType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MlsMessageType::Welcome => "welcome",
+            MlsMessageType::Commit => "commit",
+            MlsMessageType::Proposal => "proposal",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "welcome" => Ok(MlsMessageType::Welcome),
+            "commit" => Ok(MlsMessageType::Commit),
+            "proposal" => Ok(MlsMessageType::Proposal),
+            other => Err(format!("unknown MLS message type '{other}' (expected welcome, commit or proposal)")),
+        }
+    }
+}
+
+/// Message sent by the client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Identify { token: String, protocol_version: u32 },
+    Resume { token: String, session_id: String, last_seq: u64 },
+    Heartbeat,
+    /// MLS handshake message fanned out by the server to the group.
+    MlsRelay { mls_type: MlsMessageType, data: Vec<u8> },
+    /// Any other message; `data` must be a JSON object.
+    Raw { kind: String, data: Value },
+}
+
+impl ClientMessage {
+    pub fn kind(&self) -> &str {
+        match self {
+            ClientMessage::Identify { .. } => "identify",
+            ClientMessage::Resume { .. } => "resume",
+            ClientMessage::Heartbeat => "heartbeat",
+            ClientMessage::MlsRelay { .. } => "mls_relay",
+            ClientMessage::Raw { kind, .. } => kind,
+        }
+    }
+
+    /// Serialize to the gateway's JSON envelope.
+    pub fn to_json(&self) -> String {
+        let data = match self {
+            ClientMessage::Identify { token, protocol_version } => {
+                json!({ "token": token, "protocol_version": protocol_version })
+            }
+            ClientMessage::Resume { token, session_id, last_seq } => {
+                json!({ "token": token, "session_id": session_id, "last_seq": last_seq })
+            }
+            ClientMessage::Heartbeat => Value::Object(Map::new()),
+            ClientMessage::MlsRelay { mls_type, data } => {
+                json!({ "mls_type": mls_type.as_str(), "data": STANDARD.encode(data) })
+            }
+            ClientMessage::Raw { data, .. } => data.clone(),
+        };
+        json!({ "type": self.kind(), "d": data }).to_string()
+    }
+}
+
+/// Message received from the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMessage {
+    pub kind: String,
+    /// Sequence number of dispatch events; control messages carry none.
+    pub seq: Option<u64>,
+    /// The `d` payload, an empty object when absent.
+    pub data: Value,
+}
+
+impl ServerMessage {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| format!("invalid gateway message: {e}"))?;
+        let Value::Object(mut obj) = value else {
+            return Err("gateway message is not a JSON object".into());
+        };
+        let kind = match obj.remove("type") {
+            Some(Value::String(kind)) => kind,
+            _ => return Err("gateway message has no 'type'".into()),
+        };
+        let seq = obj.get("seq").and_then(Value::as_u64);
+        let data = match obj.remove("d") {
+            Some(Value::Null) | None => Value::Object(Map::new()),
+            Some(d) => d,
+        };
+        Ok(ServerMessage { kind, seq, data })
+    }
+
+    /// Heartbeat interval in milliseconds, from `hello`.
+    pub fn heartbeat_interval(&self) -> Option<u64> {
+        self.data.get("heartbeat_interval").and_then(Value::as_u64)
+    }
+
+    /// Session id, from `ready`.
+    pub fn session_id(&self) -> Option<&str> {
+        self.data.get("session_id").and_then(Value::as_str)
+    }
+
+    /// Decoded `data` field of an incoming MLS message (`mls_welcome`,
+    /// `mls_commit`, `mls_proposal`).
+    pub fn mls_payload(&self) -> Result<Vec<u8>, String> {
+        let data = self.data.get("data").and_then(Value::as_str).ok_or("MLS message has no 'data'")?;
+        STANDARD.decode(data).map_err(|e| format!("invalid base64 in MLS message: {e}"))
+    }
+}
+
+/// Length-prefix a message for a QUIC signaling stream.
+pub fn encode_frame(message: &str) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message.as_bytes());
+    frame
+}
+
+/// Splits a QUIC signaling stream back into messages.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Next complete message, or `None` until more bytes arrive. An oversized
+    /// length or invalid UTF-8 is an error; the stream can't be resynced after it.
+    pub fn next_message(&mut self) -> Result<Option<String>, String> {
+        let Some(prefix) = self.buf.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(*prefix) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(format!("signaling frame of {len} bytes exceeds {MAX_FRAME_LEN}"));
+        }
+        if self.buf.len() < 4 + len {
+            return Ok(None);
+        }
+        let payload: Vec<u8> = self.buf.drain(..4 + len).skip(4).collect();
+        String::from_utf8(payload)
+            .map(Some)
+            .map_err(|_| "signaling frame is not valid UTF-8".to_string())
+    }
+}
@@ -0,0 +1,72 @@
+//! OTLP span export for the extension modules.
+//!
+//! vox-media and vox-mls each own a tracing subscriber, since they are
+//! separate shared libraries. Both build their exporter here so spans from
+//! either land in the same collector under the same resource conventions and
+//! can be joined into one trace by the backend.
+
+use std::collections::HashMap;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Where and how to export spans.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// OTLP/HTTP traces URL, e.g. `http://localhost:4318/v1/traces`.
+    pub endpoint: String,
+    /// Reported as the `service.name` resource attribute.
+    pub service_name: String,
+    /// Fraction of root spans kept, in `0.0..=1.0`. Child spans follow
+    /// their parent's decision.
+    pub sample_ratio: f64,
+    /// Extra HTTP headers, typically collector authentication.
+    pub headers: HashMap<String, String>,
+}
+
+/// A running exporter. Spans are batched on a background thread, so no
+/// async runtime is required; call [`OtlpExporter::shutdown`] to flush.
+pub struct OtlpExporter {
+    provider: SdkTracerProvider,
+}
+
+impl OtlpExporter {
+    pub fn start(config: &OtlpConfig) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&config.sample_ratio) {
+            return Err(format!("sample_ratio must be between 0 and 1, got {}", config.sample_ratio));
+        }
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(config.endpoint.as_str())
+            .with_headers(config.headers.clone())
+            .build()
+            .map_err(|e| format!("Cannot create OTLP exporter for {}: {e}", config.endpoint))?;
+        let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(sampler)
+            .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+            .build();
+        Ok(OtlpExporter { provider })
+    }
+
+    /// A tracing layer feeding this exporter. `scope` names the
+    /// instrumentation library, normally the crate name.
+    pub fn layer<S>(&self, scope: &'static str) -> OpenTelemetryLayer<S, SdkTracer>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.provider.tracer(scope))
+    }
+
+    /// Flush pending spans and stop the background exporter. Blocks until
+    /// the collector answers or the export times out.
+    pub fn shutdown(self) -> Result<(), String> {
+        self.provider.shutdown().map_err(|e| format!("OTLP exporter shutdown failed: {e}"))
+    }
+}
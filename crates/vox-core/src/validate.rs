@@ -0,0 +1,213 @@
+//! Strict validation of received frames.
+//!
+//! A lenient receiver only rejects datagrams too short to hold a header.
+//! [`check_frame`] also rejects unknown versions and media types, codecs
+//! that do not belong to the media type, payloads outside the type's
+//! bounds, and frames for another room or claiming to come from ourselves.
+//! [`MalformedTracker`] counts what it rejects and turns the counts into at
+//! most one [`MalformedReport`] per interval, so a flood of garbage costs a
+//! counter increment per datagram rather than a log line or event each.
+
+use crate::frame::DATA_CHANNEL_PREFIX;
+use crate::header::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Largest audio payload accepted: a maximal 1275-byte Opus packet plus
+/// room for SFrame and transform overhead.
+pub const MAX_AUDIO_PAYLOAD: usize = 1500;
+/// Largest payload of any other frame, the default datagram buffer size.
+pub const MAX_FRAME_PAYLOAD: usize = 65_535;
+
+/// Why a frame was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Malformed {
+    /// Shorter than the fixed header.
+    Truncated(usize),
+    Version(u8),
+    MediaType(u8),
+    /// Codec not valid for the media type.
+    Codec { media_type: u8, codec_id: u8 },
+    /// Payload length outside the media type's bounds.
+    Length { media_type: u8, len: usize },
+    /// Addressed to a different room.
+    Room(u32),
+    /// Claims to come from the receiving user.
+    OwnUser(u32),
+}
+
+impl Malformed {
+    /// Short name used as the statistics key.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Malformed::Truncated(_) => "truncated",
+            Malformed::Version(_) => "version",
+            Malformed::MediaType(_) => "media_type",
+            Malformed::Codec { .. } => "codec",
+            Malformed::Length { .. } => "length",
+            Malformed::Room(_) => "room",
+            Malformed::OwnUser(_) => "own_user",
+        }
+    }
+}
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Malformed::Truncated(len) => write!(f, "{len}-byte datagram is shorter than the header"),
+            Malformed::Version(v) => write!(f, "unsupported protocol version {v}"),
+            Malformed::MediaType(t) => write!(f, "unknown media type {t}"),
+            Malformed::Codec { media_type, codec_id } => {
+                write!(f, "codec {codec_id} is not valid for media type {media_type}")
+            }
+            Malformed::Length { media_type, len } => {
+                write!(f, "{len}-byte payload is out of bounds for media type {media_type}")
+            }
+            Malformed::Room(room_id) => write!(f, "frame for room {room_id}"),
+            Malformed::OwnUser(user_id) => write!(f, "frame claims to come from ourselves ({user_id})"),
+        }
+    }
+}
+
+impl std::error::Error for Malformed {}
+
+/// Check a received datagram against what a client in `room_id` as
+/// `user_id` should ever be sent.
+pub fn check_datagram(data: &[u8], room_id: u32, user_id: u32) -> Result<(), Malformed> {
+    let header = MediaHeader::parse(data).ok_or(Malformed::Truncated(data.len()))?;
+    check_frame(&header, data.len() - HEADER_SIZE, room_id, user_id)
+}
+
+/// Check a parsed frame header and its payload length.
+pub fn check_frame(header: &MediaHeader, payload_len: usize, room_id: u32, user_id: u32) -> Result<(), Malformed> {
+    if header.version != PROTOCOL_VERSION {
+        return Err(Malformed::Version(header.version));
+    }
+    let (codecs, min_len, max_len): (&[u8], usize, usize) = match header.media_type {
+        MEDIA_TYPE_AUDIO => (&[CODEC_OPUS], 1, MAX_AUDIO_PAYLOAD),
+        // An empty encoded frame still travels as one empty fragment
+        MEDIA_TYPE_VIDEO => (&[CODEC_AV1], 0, MAX_FRAME_PAYLOAD),
+        MEDIA_TYPE_SCREEN => (&[CODEC_AV1, CODEC_AV1_SCREEN], 0, MAX_FRAME_PAYLOAD),
+        MEDIA_TYPE_FEC => (&[CODEC_NONE, CODEC_OPUS, CODEC_AV1, CODEC_AV1_SCREEN], 1, MAX_FRAME_PAYLOAD),
+        MEDIA_TYPE_RTCP_FB => (&[CODEC_NONE], 1, MAX_FRAME_PAYLOAD),
+        MEDIA_TYPE_DATA => (&[CODEC_NONE], DATA_CHANNEL_PREFIX, MAX_FRAME_PAYLOAD),
+        other => return Err(Malformed::MediaType(other)),
+    };
+    if !codecs.contains(&header.codec_id) {
+        return Err(Malformed::Codec { media_type: header.media_type, codec_id: header.codec_id });
+    }
+    if !(min_len..=max_len).contains(&payload_len) {
+        return Err(Malformed::Length { media_type: header.media_type, len: payload_len });
+    }
+    if header.room_id != room_id {
+        return Err(Malformed::Room(header.room_id));
+    }
+    // Feedback from the SFU may be addressed to us by our own id
+    if header.user_id == user_id && header.media_type != MEDIA_TYPE_RTCP_FB {
+        return Err(Malformed::OwnUser(header.user_id));
+    }
+    Ok(())
+}
+
+/// How malformed frames are reported and when the sender is given up on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// At most one report per interval.
+    pub report_interval: Duration,
+    /// Give up on the connection once an interval holds this many
+    /// malformed frames. `None` never does.
+    pub disconnect_threshold: Option<u64>,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            report_interval: Duration::from_secs(5),
+            disconnect_threshold: None,
+        }
+    }
+}
+
+/// Malformed frames seen during one report interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedReport {
+    /// Malformed frames in this interval.
+    pub count: u64,
+    /// Malformed frames since the tracker was created.
+    pub total: u64,
+    /// Running totals by [`Malformed::reason`].
+    pub by_reason: BTreeMap<&'static str, u64>,
+    /// The most recent rejection.
+    pub last: String,
+    /// Whether `count` reached the disconnect threshold.
+    pub disconnect: bool,
+}
+
+/// Counts malformed frames and rate-limits their reports.
+#[derive(Debug)]
+pub struct MalformedTracker {
+    config: ValidationConfig,
+    interval_start: Instant,
+    interval_count: u64,
+    total: u64,
+    by_reason: BTreeMap<&'static str, u64>,
+    last: Option<Malformed>,
+}
+
+impl MalformedTracker {
+    pub fn new(config: ValidationConfig, now: Instant) -> Self {
+        MalformedTracker {
+            config,
+            interval_start: now,
+            interval_count: 0,
+            total: 0,
+            by_reason: BTreeMap::new(),
+            last: None,
+        }
+    }
+
+    pub fn config(&self) -> &ValidationConfig {
+        &self.config
+    }
+
+    /// Count one rejected frame.
+    pub fn record(&mut self, malformed: Malformed) {
+        self.interval_count += 1;
+        self.total += 1;
+        *self.by_reason.entry(malformed.reason()).or_default() += 1;
+        self.last = Some(malformed);
+    }
+
+    /// Running totals by reason.
+    pub fn by_reason(&self) -> &BTreeMap<&'static str, u64> {
+        &self.by_reason
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Close the current interval if it has run its course, returning a
+    /// report if it saw any malformed frames. The threshold is checked
+    /// immediately, so a flood is cut off without waiting for the
+    /// interval to end.
+    pub fn poll(&mut self, now: Instant) -> Option<MalformedReport> {
+        let disconnect = self.config.disconnect_threshold.is_some_and(|t| self.interval_count >= t);
+        if !disconnect && now.duration_since(self.interval_start) < self.config.report_interval {
+            return None;
+        }
+        let count = std::mem::take(&mut self.interval_count);
+        self.interval_start = now;
+        if count == 0 {
+            return None;
+        }
+        Some(MalformedReport {
+            count,
+            total: self.total,
+            by_reason: self.by_reason.clone(),
+            last: self.last.as_ref().map(Malformed::to_string).unwrap_or_default(),
+            disconnect,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::Cursor;
+use vox_core::files::*;
+
+const KEY: [u8; FILE_KEY_LEN] = [7; FILE_KEY_LEN];
+const FILE_ID: [u8; FILE_ID_LEN] = [9; FILE_ID_LEN];
+
+fn encrypt(plaintext: &[u8], chunk_size: usize) -> (Vec<u8>, FileManifest) {
+    let mut blob = Vec::new();
+    let manifest = encrypt_stream(&KEY, FILE_ID, 3, chunk_size, Cursor::new(plaintext), &mut blob).unwrap();
+    (blob, manifest)
+}
+
+fn decrypt(blob: &[u8], manifest: FileManifest) -> Result<Vec<u8>, FileError> {
+    let mut out = Vec::new();
+    decrypt_stream(&KEY, manifest, Cursor::new(blob), &mut out)?;
+    Ok(out)
+}
+
+#[test]
+fn round_trip_across_chunk_boundaries() {
+    for len in [0, 1, 99, 100, 101, 1000] {
+        let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let (blob, manifest) = encrypt(&plaintext, 100);
+        assert_eq!(manifest.size, len as u64);
+        assert_eq!(manifest.encrypted_size, blob.len() as u64);
+        assert_eq!(manifest.chunks.len(), usize::div_ceil(len, 100).max(1));
+        assert_eq!(decrypt(&blob, manifest).unwrap(), plaintext, "len {len}");
+    }
+}
+
+#[test]
+fn manifest_json_round_trip() {
+    let (_, manifest) = encrypt(&[1; 250], 100);
+    let json = manifest.to_json();
+    assert_eq!(FileManifest::from_json(&json).unwrap(), manifest);
+    assert!(json.contains("\"algorithm\":\"aes-256-gcm\""));
+}
+
+#[test]
+fn rejects_inconsistent_manifests() {
+    let (_, manifest) = encrypt(&[1; 250], 100);
+    let mut bad = manifest.clone();
+    bad.size += 1;
+    assert!(matches!(FileManifest::from_json(&bad.to_json()), Err(FileError::Invalid(_))));
+    let mut bad = manifest.clone();
+    bad.chunks[1].offset += 1;
+    assert!(FileManifest::from_json(&bad.to_json()).is_err());
+    assert!(FileManifest::from_json("{}").is_err());
+}
+
+#[test]
+fn incremental_decrypt_in_small_pieces() {
+    let plaintext: Vec<u8> = (0..777u32).map(|i| (i * 31) as u8).collect();
+    let (blob, manifest) = encrypt(&plaintext, 64);
+    let mut decryptor = FileDecryptor::new(&KEY, manifest).unwrap();
+    let mut out = Vec::new();
+    for piece in blob.chunks(7) {
+        decryptor.update(piece, &mut out).unwrap();
+    }
+    decryptor.finish().unwrap();
+    assert_eq!(out, plaintext);
+}
+
+#[test]
+fn detects_tampering() {
+    let (mut blob, manifest) = encrypt(&[5; 300], 100);
+    blob[150] ^= 1;
+    assert!(matches!(decrypt(&blob, manifest), Err(FileError::Integrity(_))));
+}
+
+#[test]
+fn detects_truncation_and_extra_data() {
+    let (blob, manifest) = encrypt(&[5; 300], 100);
+    let cut = manifest.chunks[2].offset as usize;
+    assert!(matches!(decrypt(&blob[..cut], manifest.clone()), Err(FileError::Integrity(_))));
+    let mut longer = blob.clone();
+    longer.push(0);
+    assert!(matches!(decrypt(&longer, manifest), Err(FileError::Integrity(_))));
+}
+
+#[test]
+fn truncated_manifest_cannot_pass_for_shorter_file() {
+    // Dropping the trailing chunks from both blob and manifest leaves a chunk
+    // that was not sealed as the last one
+    let (blob, mut manifest) = encrypt(&[5; 300], 100);
+    manifest.chunks.truncate(2);
+    manifest.size = 200;
+    manifest.encrypted_size = manifest.chunks.iter().map(|c| c.size as u64).sum();
+    let blob = &blob[..manifest.encrypted_size as usize];
+    manifest.sha256 = {
+        use sha2::Digest;
+        sha2::Sha256::digest(blob).into()
+    };
+    assert!(matches!(decrypt(blob, manifest), Err(FileError::Integrity(_))));
+}
+
+#[test]
+fn wrong_key_fails() {
+    let (blob, manifest) = encrypt(&[5; 10], 100);
+    let mut out = Vec::new();
+    let err = decrypt_stream(&[8; FILE_KEY_LEN], manifest, Cursor::new(&blob), &mut out).unwrap_err();
+    assert!(matches!(err, FileError::Integrity(_)));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn rejects_bad_parameters() {
+    let mut sink = Vec::new();
+    assert!(encrypt_stream(&KEY[..16], FILE_ID, 0, 100, Cursor::new(b"x"), &mut sink).is_err());
+    assert!(encrypt_stream(&KEY, FILE_ID, 0, 0, Cursor::new(b"x"), &mut sink).is_err());
+    assert!(encrypt_stream(&KEY, FILE_ID, 0, MAX_CHUNK_SIZE + 1, Cursor::new(b"x"), &mut sink).is_err());
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+use vox_core::key_sink::KeySink;
+
+type Recorded = Arc<Mutex<Vec<(u64, Vec<u8>)>>>;
+
+fn recording_sink() -> (KeySink, Recorded) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let store = received.clone();
+    let sink = KeySink::new(move |key_id, key| {
+        store.lock().unwrap().push((key_id, key.to_vec()));
+        true
+    });
+    (sink, received)
+}
+
+#[test]
+fn delivers_keys() {
+    let (sink, received) = recording_sink();
+    assert!(sink.set_key(3, b"epoch three"));
+    assert!(sink.set_key(4, b""));
+    assert_eq!(*received.lock().unwrap(), [(3, b"epoch three".to_vec()), (4, Vec::new())]);
+}
+
+#[test]
+fn raw_handle_reaches_same_receiver() {
+    let (sink, received) = recording_sink();
+    let other = unsafe { KeySink::from_raw(sink.as_raw()) };
+    drop(sink);
+    assert!(other.set_key(1, b"k"));
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn callback_freed_with_last_handle() {
+    let (sink, received) = recording_sink();
+    let clone = sink.clone();
+    assert_eq!(Arc::strong_count(&received), 2);
+    drop(sink);
+    assert_eq!(Arc::strong_count(&received), 2);
+    drop(clone);
+    assert_eq!(Arc::strong_count(&received), 1);
+}
+
+#[test]
+fn receiver_can_decline() {
+    let sink = KeySink::new(|_, _| false);
+    assert!(!sink.set_key(1, b"k"));
+}
+
+#[test]
+fn panicking_receiver_reports_gone() {
+    let sink = KeySink::new(|_, _| panic!("receiver failed"));
+    assert!(!sink.set_key(1, b"k"));
+}
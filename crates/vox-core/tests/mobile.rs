@@ -0,0 +1,56 @@
+use vox_core::mobile::*;
+
+const ROOM: u32 = 7;
+
+fn drain(session: &MobileMediaSession) -> Vec<MediaSessionEvent> {
+    std::iter::from_fn(|| session.poll_event()).collect()
+}
+
+#[test]
+fn sealed_media_round_trips_between_sessions() {
+    let tx = MobileMediaSession::new(ROOM, 1);
+    let rx = MobileMediaSession::new(ROOM, 2);
+    tx.set_media_key(1, vec![9; 32]);
+    rx.media_key_sink().set_key(1, &[9; 32]);
+    assert_eq!(rx.active_key_id(), Some(1));
+
+    rx.handle_datagram(tx.audio_datagram(b"opus".to_vec(), false).unwrap(), true);
+    for datagram in tx.video_datagrams(vec![5; 3000], true, Some(1000)).unwrap() {
+        rx.handle_datagram(datagram, true);
+    }
+    rx.handle_datagram(tx.data_datagram(3, b"hi".to_vec()).unwrap(), true);
+
+    let events = drain(&rx);
+    assert!(matches!(events[0], MediaSessionEvent::UserActive { user_id: 1 }));
+    let MediaSessionEvent::Audio { user_id, gap, ref payload, .. } = events[1] else {
+        panic!("expected audio, got {:?}", events[1]);
+    };
+    assert_eq!((user_id, gap, payload.as_slice()), (1, 0, &b"opus"[..]));
+    let MediaSessionEvent::Video { is_keyframe, ref data, .. } = events[2] else {
+        panic!("expected video, got {:?}", events[2]);
+    };
+    assert!(is_keyframe);
+    assert_eq!(data, &vec![5; 3000]);
+    let MediaSessionEvent::Data { channel_id, ref payload, .. } = events[3] else {
+        panic!("expected data, got {:?}", events[3]);
+    };
+    assert_eq!((channel_id, payload.as_slice()), (3, &b"hi"[..]));
+    assert_eq!(events.len(), 4);
+    assert_eq!(rx.user_stats(1).unwrap().packets_received, 1);
+}
+
+#[test]
+fn undecryptable_frames_are_dropped_and_sinks_stop_with_the_session() {
+    let tx = MobileMediaSession::new(ROOM, 1);
+    let rx = MobileMediaSession::new(ROOM, 2);
+    tx.set_media_key(1, vec![1; 32]);
+    rx.set_media_key(2, vec![2; 32]);
+
+    rx.handle_datagram(tx.audio_datagram(b"opus".to_vec(), false).unwrap(), true);
+    let events = drain(&rx);
+    assert!(matches!(events[..], [MediaSessionEvent::UserActive { user_id: 1 }]));
+
+    let sink = rx.media_key_sink();
+    drop(rx);
+    assert!(!sink.set_key(3, &[3; 32]));
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+use vox_core::profile::{self, Histogram};
+
+#[test]
+fn records_counts_and_extremes() {
+    static H: Histogram = Histogram::new("test.op");
+    for us in [10, 20, 30, 1000] {
+        H.record(Duration::from_micros(us));
+    }
+    let snap = H.snapshot(false);
+    assert_eq!(snap.name, "test.op");
+    assert_eq!(snap.count, 4);
+    assert_eq!(snap.total_ns, 1_060_000);
+    assert_eq!(snap.max_ns, 1_000_000);
+    assert_eq!(snap.mean_ns(), 265_000.0);
+    assert_eq!(snap.buckets.iter().sum::<u64>(), 4);
+}
+
+#[test]
+fn percentiles_stay_within_bucket_and_max() {
+    static H: Histogram = Histogram::new("test.percentile");
+    for _ in 0..99 {
+        H.record(Duration::from_nanos(1500));
+    }
+    H.record(Duration::from_millis(5));
+    let snap = H.snapshot(false);
+    let p50 = snap.percentile_ns(0.5);
+    assert!((1024.0..=2048.0).contains(&p50), "p50 {p50}");
+    assert_eq!(snap.percentile_ns(1.0), 5_000_000.0);
+    assert!(snap.percentile_ns(0.0) <= p50);
+}
+
+#[test]
+fn snapshot_reset_clears() {
+    static H: Histogram = Histogram::new("test.reset");
+    H.record(Duration::from_micros(1));
+    assert_eq!(H.snapshot(true).count, 1);
+    let snap = H.snapshot(false);
+    assert_eq!((snap.count, snap.max_ns), (0, 0));
+    assert_eq!(snap.percentile_ns(0.99), 0.0);
+}
+
+#[test]
+fn start_records_only_when_enabled() {
+    static H: Histogram = Histogram::new("test.timer");
+    assert!(!profile::is_enabled());
+    drop(H.start());
+    assert_eq!(H.snapshot(false).count, 0);
+
+    profile::set_enabled(true);
+    drop(H.start());
+    profile::set_enabled(false);
+    assert_eq!(H.snapshot(false).count, 1);
+}
@@ -0,0 +1,99 @@
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+use vox_core::ratelimit::*;
+use vox_core::*;
+
+fn limits(per_user: Rate, total: Rate) -> RateLimits {
+    RateLimits { per_user, total, ..RateLimits::default() }
+}
+
+#[test]
+fn senders_are_limited_separately() {
+    let now = Instant::now();
+    let per_user = Rate { packets_per_sec: Some(10), bytes_per_sec: None };
+    let mut limiter = RateLimiter::new(limits(per_user, Rate::UNLIMITED), now);
+
+    for _ in 0..10 {
+        assert_eq!(limiter.check(now, 1, 100), Ok(()));
+    }
+    assert_eq!(limiter.check(now, 1, 100), Err(Limited::UserPackets(1)));
+    // Another sender is unaffected
+    assert_eq!(limiter.check(now, 2, 100), Ok(()));
+    // Tokens come back at the configured rate
+    let later = now + Duration::from_millis(200);
+    assert_eq!(limiter.check(later, 1, 100), Ok(()));
+    assert_eq!(limiter.check(later, 1, 100), Ok(()));
+    assert_eq!(limiter.check(later, 1, 100), Err(Limited::UserPackets(1)));
+}
+
+#[test]
+fn byte_and_total_limits() {
+    let now = Instant::now();
+    let per_user = Rate { packets_per_sec: None, bytes_per_sec: Some(1000) };
+    let total = Rate { packets_per_sec: Some(3), bytes_per_sec: None };
+    let mut limiter = RateLimiter::new(limits(per_user, total), now);
+
+    assert_eq!(limiter.check(now, 1, 900), Ok(()));
+    assert_eq!(limiter.check(now, 1, 200), Err(Limited::UserBytes(1)));
+    assert_eq!(limiter.check(now, 2, 10), Ok(()));
+    assert_eq!(limiter.check(now, 3, 10), Ok(()));
+    assert_eq!(limiter.check(now, 4, 10), Err(Limited::TotalPackets));
+}
+
+#[test]
+fn reports_are_rate_limited() {
+    let now = Instant::now();
+    let per_user = Rate { packets_per_sec: Some(1), bytes_per_sec: None };
+    let mut limiter = RateLimiter::new(limits(per_user, Rate::UNLIMITED), now);
+    let interval = limiter.limits().report_interval;
+
+    assert!(limiter.check(now, 5, 10).is_ok());
+    for _ in 0..3 {
+        assert!(limiter.check(now, 5, 10).is_err());
+    }
+    limiter.record_evictions(2);
+    assert_eq!(limiter.poll(now + interval / 2), None);
+
+    let report = limiter.poll(now + interval).unwrap();
+    assert_eq!(report.dropped, 3);
+    assert_eq!(report.by_reason.get("user_packets"), Some(&3));
+    assert_eq!(report.users.iter().copied().collect::<Vec<_>>(), [5]);
+    assert_eq!(report.evicted, 2);
+    // Nothing new, nothing to report
+    assert_eq!(limiter.poll(now + interval * 2), None);
+}
+
+#[test]
+fn unlimited_admits_everything() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new(limits(Rate::UNLIMITED, Rate::UNLIMITED), now);
+    for _ in 0..10_000 {
+        assert_eq!(limiter.check(now, 1, 1200), Ok(()));
+    }
+}
+
+#[test]
+fn reassembly_buffers_are_bounded() {
+    let mut reassembler = VideoReassembler::new();
+    reassembler.set_limits(2, 3);
+    // First fragments only, so nothing completes
+    let partial = |user_id, timestamp| {
+        let frame = OutFrame::video(7, user_id, timestamp, timestamp, true, false, Bytes::from_static(b"x"));
+        InFrame::decode(frame.encode()).unwrap()
+    };
+
+    for ts in 0..5 {
+        let frame = partial(1, ts);
+        assert!(reassembler.add_fragment(&frame.header, &frame.payload).is_none());
+    }
+    assert_eq!(reassembler.pending_frames(), 2);
+    assert_eq!(reassembler.take_evicted(), 3);
+
+    for user_id in [2, 3] {
+        let frame = partial(user_id, 0);
+        reassembler.add_fragment(&frame.header, &frame.payload);
+    }
+    assert_eq!(reassembler.pending_frames(), 3);
+    assert_eq!(reassembler.take_evicted(), 1);
+    assert_eq!(reassembler.take_evicted(), 0);
+}
@@ -0,0 +1,186 @@
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+use vox_core::session::*;
+use vox_core::sframe::KeyRing;
+use vox_core::*;
+
+const ROOM: u32 = 7;
+
+/// Deliver every frame in `frames` to `rx`, encoded and decoded as on the wire.
+fn deliver(rx: &mut MediaSession, now: Instant, frames: &[OutFrame]) {
+    for frame in frames {
+        rx.handle_datagram(now, frame.encode(), true);
+    }
+}
+
+fn drain(session: &mut MediaSession) -> Vec<SessionEvent> {
+    std::iter::from_fn(|| session.poll_event()).collect()
+}
+
+#[test]
+fn audio_frames_advance_sequence_and_clock() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let a = tx.audio_frame(Bytes::from_static(b"a"), false);
+    let b = tx.audio_frame(Bytes::from_static(b"b"), true);
+    assert_eq!((a.header.sequence, a.header.timestamp), (0, 0));
+    assert_eq!((b.header.sequence, b.header.timestamp), (1, AUDIO_FRAME_TICKS));
+    assert!(b.header.dtx);
+    assert_eq!(b.header.room_id, ROOM);
+    assert_eq!(b.header.user_id, 1);
+    assert_eq!(tx.audio_timestamp(), 2 * AUDIO_FRAME_TICKS);
+}
+
+#[test]
+fn sealed_audio_round_trip() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let mut rx = MediaSession::new(ROOM, 2);
+    let mut tx_keys = KeyRing::new();
+    let mut rx_keys = KeyRing::new();
+    tx_keys.set_key(1, vec![9; 32]);
+    rx_keys.set_key(1, vec![9; 32]);
+
+    let sealed = tx_keys.encrypt(1, b"opus").unwrap();
+    let frame = tx.audio_frame(Bytes::from(sealed), false);
+    let now = Instant::now();
+    deliver(&mut rx, now, &[frame]);
+
+    let events = drain(&mut rx);
+    assert!(matches!(events[0], SessionEvent::UserActive(1)));
+    let SessionEvent::Audio { user_id, gap, ref payload, .. } = events[1] else {
+        panic!("expected audio, got {:?}", events[1]);
+    };
+    assert_eq!((user_id, gap), (1, 0));
+    assert_eq!(rx_keys.decrypt(1, payload).unwrap(), b"opus");
+    assert_eq!(events.len(), 2);
+    assert_eq!(rx.stats(1).unwrap().audio_seq.received, 1);
+}
+
+#[test]
+fn audio_loss_reports_gap_and_drops_late_packets() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let mut rx = MediaSession::new(ROOM, 2);
+    let frames: Vec<OutFrame> = (0..4).map(|_| tx.audio_frame(Bytes::from_static(b"x"), false)).collect();
+    let now = Instant::now();
+
+    for i in [0, 3, 1] {
+        rx.handle_datagram(now, frames[i].encode(), true);
+    }
+    let gaps: Vec<u32> = drain(&mut rx)
+        .into_iter()
+        .filter_map(|e| match e {
+            SessionEvent::Audio { gap, .. } => Some(gap),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(gaps, [0, 2]);
+
+    let snap = rx.stats(1).unwrap().snapshot();
+    assert_eq!(snap.packets_received, 3);
+    assert_eq!(snap.packets_lost, 1);
+    assert_eq!(snap.packets_reordered, 1);
+}
+
+#[test]
+fn video_is_reassembled_only_when_accepted() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let mut rx = MediaSession::new(ROOM, 2);
+    let data = vec![0xAB; 3000];
+    let ts = tx.next_video_timestamp();
+    let seq = *tx.video_sequence_mut();
+    let fragments = fragment_video(ROOM, 1, seq, ts, true, &data, MAX_FRAGMENT_PAYLOAD);
+    *tx.video_sequence_mut() = seq.wrapping_add(fragments.len() as u32);
+    assert_eq!(tx.next_video_timestamp(), 1);
+
+    let now = Instant::now();
+    for f in &fragments {
+        rx.handle_datagram(now, f.encode(), false);
+    }
+    let events = drain(&mut rx);
+    assert!(matches!(events[..], [SessionEvent::UserActive(1)]));
+
+    deliver(&mut rx, now, &fragments);
+    let events = drain(&mut rx);
+    let [SessionEvent::Video(frame)] = &events[..] else {
+        panic!("expected one video frame, got {events:?}");
+    };
+    assert_eq!((frame.user_id, frame.timestamp, frame.is_keyframe), (1, 0, true));
+    assert_eq!(frame.data, data);
+
+    tx.reset_video();
+    assert_eq!(*tx.video_sequence_mut(), 0);
+    assert_eq!(tx.next_video_timestamp(), 0);
+}
+
+#[test]
+fn data_and_feedback_frames() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let mut rx = MediaSession::new(ROOM, 2);
+    let first = tx.data_frame(3, b"hello");
+    let second = tx.data_frame(3, b"again");
+    assert_eq!((first.header.sequence, second.header.sequence), (0, 1));
+    deliver(&mut rx, Instant::now(), &[first]);
+
+    let events = drain(&mut rx);
+    let [SessionEvent::Data { user_id, channel_id, payload, .. }] = &events[..] else {
+        panic!("expected one data message, got {events:?}");
+    };
+    assert_eq!((*user_id, *channel_id), (1, 3));
+    assert_eq!(&payload[..], b"hello");
+    // Data alone does not make a user active
+    assert!(!rx.has_active_users());
+
+    let fb = tx.feedback_frame(Bytes::from_static(&[FB_LEAVE]));
+    assert_eq!(fb.header.media_type, MEDIA_TYPE_RTCP_FB);
+    assert_eq!(tx.feedback_frame(Bytes::new()).header.sequence, 1);
+}
+
+#[test]
+fn idle_users_are_reported_inactive() {
+    let mut tx = MediaSession::new(ROOM, 1);
+    let mut rx = MediaSession::new(ROOM, 2);
+    let start = Instant::now();
+    deliver(&mut rx, start, &[tx.audio_frame(Bytes::from_static(b"x"), false)]);
+    drain(&mut rx);
+
+    let timeout = Duration::from_secs(5);
+    rx.handle_timeout(start + Duration::from_secs(1), timeout, timeout);
+    assert!(rx.poll_event().is_none());
+
+    rx.handle_timeout(start + timeout, timeout, timeout);
+    let events = drain(&mut rx);
+    let [SessionEvent::UserInactive { user_id: 1, stats }] = &events[..] else {
+        panic!("expected user 1 inactive, got {events:?}");
+    };
+    assert_eq!(stats.audio_seq.received, 1);
+    assert!(!rx.has_active_users());
+
+    // Seen again after going idle
+    deliver(&mut rx, start + timeout, &[tx.audio_frame(Bytes::from_static(b"x"), false)]);
+    assert!(matches!(rx.poll_event(), Some(SessionEvent::UserActive(1))));
+    assert!(rx.drop_user(1).is_some());
+    assert!(rx.stats(1).is_none());
+}
+
+#[test]
+fn sequence_tracker_handles_wraparound() {
+    let mut seq = SequenceTracker::default();
+    assert_eq!(seq.record(u32::MAX - 1), SequenceOutcome::InOrder { gap: 0 });
+    assert_eq!(seq.record(1), SequenceOutcome::InOrder { gap: 2 });
+    assert_eq!(seq.record(u32::MAX), SequenceOutcome::Reordered);
+    assert_eq!(seq.record(u32::MAX), SequenceOutcome::Duplicate);
+    assert_eq!((seq.received, seq.lost, seq.reordered, seq.duplicates), (3, 1, 1, 1));
+}
+
+#[test]
+fn key_rings_for_one_sender_never_share_a_counter() {
+    let mut first = KeyRing::new();
+    let mut second = KeyRing::new();
+    first.set_key(1, vec![9; 32]);
+    second.set_key(1, vec![9; 32]);
+
+    let sealed: std::collections::HashSet<Vec<u8>> = (0..1000).map(|_| first.encrypt(1, b"opus").unwrap()).collect();
+    assert_eq!(sealed.len(), 1000);
+    for _ in 0..1000 {
+        assert!(!sealed.contains(&second.encrypt(1, b"opus").unwrap()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+use vox_core::settings::*;
+
+/// A fresh database file under the system temp dir, removed on drop.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vox-settings-{}-{name}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        TempDb(path)
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn set_get_remove() {
+    let store = SettingsStore::open(":memory:").unwrap();
+    assert_eq!(store.get("app.theme").unwrap(), None);
+    store.set("app.theme", "dark").unwrap();
+    store.set("app.theme", "light").unwrap();
+    store.set(INPUT_DEVICE, "USB Mic").unwrap();
+    assert_eq!(store.get("app.theme").unwrap().as_deref(), Some("light"));
+    assert_eq!(
+        store.all().unwrap(),
+        [("app.theme".to_string(), "light".to_string()), (INPUT_DEVICE.to_string(), "USB Mic".to_string())]
+    );
+    store.remove("app.theme").unwrap();
+    store.remove("app.theme").unwrap();
+    assert_eq!(store.get("app.theme").unwrap(), None);
+}
+
+#[test]
+fn survives_reopen() {
+    let db = TempDb::new("reopen");
+    SettingsStore::open(&db.0).unwrap().set(OUTPUT_DEVICE, "Speakers").unwrap();
+    let store = SettingsStore::open(&db.0).unwrap();
+    assert_eq!(store.get(OUTPUT_DEVICE).unwrap().as_deref(), Some("Speakers"));
+}
+
+#[test]
+fn media_settings_default_when_empty() {
+    let store = SettingsStore::open(":memory:").unwrap();
+    assert_eq!(MediaSettings::load(&store).unwrap(), MediaSettings::default());
+}
+
+#[test]
+fn media_settings_parse() {
+    let store = SettingsStore::open(":memory:").unwrap();
+    for (key, value) in [
+        (INPUT_DEVICE, "USB Mic"),
+        (OUTPUT_DEVICE, ""),
+        (INPUT_VOLUME, "0.5"),
+        (OUTPUT_VOLUME, " 1.5 "),
+        (CAMERA, "2"),
+        (RECONNECT_MAX_ATTEMPTS, "8"),
+        (RECONNECT_MAX_BACKOFF_SECS, "60"),
+    ] {
+        store.set(key, value).unwrap();
+    }
+    let settings = MediaSettings::load(&store).unwrap();
+    assert_eq!(
+        settings,
+        MediaSettings {
+            input_device: Some("USB Mic".into()),
+            output_device: None,
+            input_volume: Some(0.5),
+            output_volume: Some(1.5),
+            camera: Some(2),
+            reconnect_max_attempts: Some(8),
+            reconnect_max_backoff_secs: Some(60),
+        }
+    );
+}
+
+#[test]
+fn media_settings_ignore_bad_values() {
+    let store = SettingsStore::open(":memory:").unwrap();
+    store.set(INPUT_VOLUME, "-1").unwrap();
+    store.set(OUTPUT_VOLUME, "NaN").unwrap();
+    store.set(CAMERA, "front").unwrap();
+    store.set(RECONNECT_MAX_ATTEMPTS, "-3").unwrap();
+    assert_eq!(MediaSettings::load(&store).unwrap(), MediaSettings::default());
+}
@@ -0,0 +1,94 @@
+use serde_json::{json, Value};
+use vox_core::signal::*;
+
+fn parsed(message: &ClientMessage) -> Value {
+    serde_json::from_str(&message.to_json()).unwrap()
+}
+
+#[test]
+fn identify_envelope() {
+    let msg = ClientMessage::Identify { token: "tok".into(), protocol_version: GATEWAY_PROTOCOL_VERSION };
+    assert_eq!(parsed(&msg), json!({"type": "identify", "d": {"token": "tok", "protocol_version": 1}}));
+}
+
+#[test]
+fn resume_and_heartbeat_envelopes() {
+    let msg = ClientMessage::Resume { token: "tok".into(), session_id: "s1".into(), last_seq: 42 };
+    assert_eq!(parsed(&msg)["d"], json!({"token": "tok", "session_id": "s1", "last_seq": 42}));
+    assert_eq!(parsed(&ClientMessage::Heartbeat), json!({"type": "heartbeat", "d": {}}));
+}
+
+#[test]
+fn mls_relay_is_base64() {
+    let msg = ClientMessage::MlsRelay { mls_type: MlsMessageType::Commit, data: vec![0, 1, 2, 0xff] };
+    assert_eq!(parsed(&msg), json!({"type": "mls_relay", "d": {"mls_type": "commit", "data": "AAEC/w=="}}));
+}
+
+#[test]
+fn mls_message_type_round_trip() {
+    for t in [MlsMessageType::Welcome, MlsMessageType::Commit, MlsMessageType::Proposal] {
+        assert_eq!(MlsMessageType::parse(t.as_str()), Ok(t));
+    }
+    assert!(MlsMessageType::parse("application").is_err());
+}
+
+#[test]
+fn parses_server_messages() {
+    let hello = ServerMessage::parse(r#"{"type":"hello","d":{"heartbeat_interval":41250}}"#).unwrap();
+    assert_eq!(hello.kind, "hello");
+    assert_eq!(hello.seq, None);
+    assert_eq!(hello.heartbeat_interval(), Some(41250));
+
+    let ready = ServerMessage::parse(r#"{"type":"ready","seq":1,"d":{"session_id":"abc","user_id":7}}"#).unwrap();
+    assert_eq!(ready.seq, Some(1));
+    assert_eq!(ready.session_id(), Some("abc"));
+
+    let ack = ServerMessage::parse(r#"{"type":"heartbeat_ack","d":null}"#).unwrap();
+    assert_eq!(ack.data, json!({}));
+}
+
+#[test]
+fn rejects_malformed_server_messages() {
+    assert!(ServerMessage::parse("not json").is_err());
+    assert!(ServerMessage::parse("[1,2]").is_err());
+    assert!(ServerMessage::parse(r#"{"d":{}}"#).is_err());
+}
+
+#[test]
+fn decodes_mls_payload() {
+    let msg = ServerMessage::parse(r#"{"type":"mls_commit","seq":3,"d":{"data":"AAEC/w==","group_id":"g"}}"#).unwrap();
+    assert_eq!(msg.mls_payload().unwrap(), vec![0, 1, 2, 0xff]);
+    let bad = ServerMessage::parse(r#"{"type":"mls_commit","d":{"data":"%%"}}"#).unwrap();
+    assert!(bad.mls_payload().is_err());
+}
+
+#[test]
+fn close_codes() {
+    assert!(can_resume(4007) && can_resume(4008));
+    assert!(!can_resume(4000));
+    assert!(can_reconnect(4000) && can_reconnect(4011));
+    assert!(!can_reconnect(4004));
+    assert!(!can_reconnect(1000));
+}
+
+#[test]
+fn frames_split_across_reads() {
+    let mut wire = encode_frame(r#"{"type":"hello"}"#);
+    wire.extend(encode_frame("second"));
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&wire[..3]);
+    assert_eq!(decoder.next_message(), Ok(None));
+    decoder.push(&wire[3..10]);
+    assert_eq!(decoder.next_message(), Ok(None));
+    decoder.push(&wire[10..]);
+    assert_eq!(decoder.next_message().unwrap().as_deref(), Some(r#"{"type":"hello"}"#));
+    assert_eq!(decoder.next_message().unwrap().as_deref(), Some("second"));
+    assert_eq!(decoder.next_message(), Ok(None));
+}
+
+#[test]
+fn rejects_oversized_frames() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&((MAX_FRAME_LEN as u32) + 1).to_be_bytes());
+    assert!(decoder.next_message().is_err());
+}
@@ -0,0 +1,61 @@
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+use vox_core::validate::*;
+use vox_core::*;
+
+const ROOM: u32 = 7;
+const ME: u32 = 2;
+
+#[test]
+fn frames_we_build_pass() {
+    let frames = [
+        OutFrame::audio(ROOM, 1, CODEC_OPUS, 0, 0, Bytes::from_static(b"opus")),
+        OutFrame::video(ROOM, 1, 0, 0, true, true, Bytes::new()),
+        OutFrame::data(ROOM, 1, 0, 3, b""),
+        OutFrame::feedback(ROOM, ME, 0, Bytes::from_static(&[FB_LEAVE])),
+    ];
+    for frame in frames {
+        assert_eq!(check_datagram(&frame.encode(), ROOM, ME), Ok(()));
+    }
+}
+
+#[test]
+fn each_rule_rejects() {
+    let check = |edit: fn(&mut OutFrame)| {
+        let mut frame = OutFrame::audio(ROOM, 1, CODEC_OPUS, 0, 0, Bytes::from_static(b"opus"));
+        edit(&mut frame);
+        check_datagram(&frame.encode(), ROOM, ME).unwrap_err()
+    };
+
+    assert_eq!(check_datagram(&[0; 10], ROOM, ME), Err(Malformed::Truncated(10)));
+    assert_eq!(check(|f| f.header.version = 9), Malformed::Version(9));
+    assert_eq!(check(|f| f.header.media_type = 42), Malformed::MediaType(42));
+    assert_eq!(check(|f| f.header.codec_id = CODEC_AV1).reason(), "codec");
+    assert_eq!(check(|f| f.payload = Bytes::new()).reason(), "length");
+    assert_eq!(check(|f| f.payload = Bytes::from(vec![0; MAX_AUDIO_PAYLOAD + 1])).reason(), "length");
+    assert_eq!(check(|f| f.header.room_id = 8), Malformed::Room(8));
+    assert_eq!(check(|f| f.header.user_id = ME), Malformed::OwnUser(ME));
+}
+
+#[test]
+fn tracker_rate_limits_reports_and_flags_floods() {
+    let start = Instant::now();
+    let config = ValidationConfig { report_interval: Duration::from_secs(5), disconnect_threshold: Some(3) };
+    let mut tracker = MalformedTracker::new(config, start);
+
+    tracker.record(Malformed::Version(9));
+    tracker.record(Malformed::Room(8));
+    assert_eq!(tracker.poll(start + Duration::from_secs(1)), None);
+
+    let report = tracker.poll(start + Duration::from_secs(5)).unwrap();
+    assert_eq!((report.count, report.total, report.disconnect), (2, 2, false));
+    assert_eq!(report.by_reason.get("room"), Some(&1));
+    assert_eq!(report.last, "frame for room 8");
+    assert_eq!(tracker.poll(start + Duration::from_secs(11)), None);
+
+    for _ in 0..3 {
+        tracker.record(Malformed::MediaType(42));
+    }
+    let report = tracker.poll(start + Duration::from_secs(12)).unwrap();
+    assert_eq!((report.count, report.total, report.disconnect), (3, 5, true));
+}
@@ -0,0 +1,478 @@
+use bytes::Bytes;
+use std::time::Duration;
+use vox_core::*;
+
+fn sample_header() -> MediaHeader {
+    MediaHeader {
+        version: PROTOCOL_VERSION,
+        media_type: MEDIA_TYPE_VIDEO,
+        codec_id: CODEC_AV1,
+        flags: FLAG_KEYFRAME | FLAG_END_OF_FRAME,
+        room_id: 0x0102_0304,
+        user_id: 0x0506_0708,
+        sequence: 0x090A_0B0C,
+        timestamp: 0x0D0E_0F10,
+        spatial_id: 0x3,
+        temporal_id: 0x5,
+        dtx: true,
+    }
+}
+
+fn video_header(user_id: u32, seq: u32, timestamp: u32, flags: u8) -> MediaHeader {
+    MediaHeader {
+        version: PROTOCOL_VERSION,
+        media_type: MEDIA_TYPE_VIDEO,
+        codec_id: CODEC_AV1,
+        flags,
+        room_id: 1,
+        user_id,
+        sequence: seq,
+        timestamp,
+        spatial_id: 0,
+        temporal_id: 0,
+        dtx: false,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Header layout
+// ---------------------------------------------------------------------------
+
+#[test]
+fn header_size_is_22_bytes() {
+    assert_eq!(HEADER_SIZE, 22);
+    assert_eq!(sample_header().encode().len(), HEADER_SIZE);
+}
+
+#[test]
+fn header_byte_layout() {
+    let bytes = sample_header().encode();
+    assert_eq!(
+        bytes,
+        [
+            1, // version
+            MEDIA_TYPE_VIDEO,
+            CODEC_AV1,
+            0b1100_0000, // keyframe | end of frame
+            0x01, 0x02, 0x03, 0x04, // room_id
+            0x05, 0x06, 0x07, 0x08, // user_id
+            0x09, 0x0A, 0x0B, 0x0C, // sequence
+            0x0D, 0x0E, 0x0F, 0x10, // timestamp
+            0x35, // spatial_id << 4 | temporal_id
+            0x80, // dtx
+        ]
+    );
+}
+
+#[test]
+fn header_round_trip() {
+    let header = sample_header();
+    assert_eq!(MediaHeader::parse(&header.encode()), Some(header));
+}
+
+#[test]
+fn header_round_trip_extremes() {
+    for value in [0u32, 1, u32::MAX] {
+        for layer in [0u8, 0xF] {
+            for dtx in [false, true] {
+                let header = MediaHeader {
+                    version: u8::MAX,
+                    media_type: MEDIA_TYPE_DATA,
+                    codec_id: CODEC_NONE,
+                    flags: u8::MAX,
+                    room_id: value,
+                    user_id: value,
+                    sequence: value,
+                    timestamp: value,
+                    spatial_id: layer,
+                    temporal_id: layer,
+                    dtx,
+                };
+                assert_eq!(MediaHeader::parse(&header.encode()), Some(header));
+            }
+        }
+    }
+}
+
+#[test]
+fn header_layers_are_masked_to_four_bits() {
+    let mut header = sample_header();
+    header.spatial_id = 0x1F;
+    header.temporal_id = 0xF7;
+    let parsed = MediaHeader::parse(&header.encode()).unwrap();
+    assert_eq!(parsed.spatial_id, 0xF);
+    assert_eq!(parsed.temporal_id, 0x7);
+}
+
+#[test]
+fn header_parse_ignores_reserved_dtx_bits() {
+    let mut bytes = sample_header().encode();
+    bytes[21] = 0x7F;
+    assert!(!MediaHeader::parse(&bytes).unwrap().dtx);
+}
+
+#[test]
+fn header_parse_rejects_short_input() {
+    let bytes = sample_header().encode();
+    for len in 0..HEADER_SIZE {
+        assert_eq!(MediaHeader::parse(&bytes[..len]), None, "len {len}");
+    }
+}
+
+#[test]
+fn header_parse_ignores_trailing_payload() {
+    let mut bytes = sample_header().encode().to_vec();
+    bytes.extend_from_slice(b"payload");
+    assert_eq!(MediaHeader::parse(&bytes), Some(sample_header()));
+}
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+#[test]
+fn wire_constants() {
+    assert_eq!(ALPN_PROTOCOL, b"vox-media/1");
+    assert_eq!(PROTOCOL_VERSION, 1);
+    assert_eq!(
+        [MEDIA_TYPE_AUDIO, MEDIA_TYPE_VIDEO, MEDIA_TYPE_SCREEN, MEDIA_TYPE_FEC, MEDIA_TYPE_RTCP_FB, MEDIA_TYPE_DATA],
+        [0, 1, 2, 3, 4, 5]
+    );
+    assert_eq!([CODEC_NONE, CODEC_OPUS, CODEC_AV1, CODEC_AV1_SCREEN], [0, 1, 2, 3]);
+    assert_eq!([FB_RECEIVE_PREFERENCES, FB_LEAVE], [1, 2]);
+    assert_eq!([CLOSE_NORMAL, CLOSE_LEAVE], [0, 1]);
+    assert_eq!(DATA_CHANNEL_PREFIX, 2);
+    assert_eq!(MAX_FRAGMENT_PAYLOAD, 1178);
+}
+
+#[test]
+fn flags_are_distinct_bits() {
+    let flags = [FLAG_KEYFRAME, FLAG_END_OF_FRAME, FLAG_FEC, FLAG_MARKER, FLAG_HAS_DEP_DESC, FLAG_START_OF_FRAME];
+    assert_eq!(flags, [0x80, 0x40, 0x20, 0x10, 0x08, 0x04]);
+    let mut seen = 0u8;
+    for flag in flags {
+        assert_eq!(flag.count_ones(), 1);
+        assert_eq!(seen & flag, 0);
+        seen |= flag;
+    }
+}
+
+#[test]
+fn flag_accessors() {
+    let mut header = sample_header();
+    header.flags = 0;
+    assert!(!header.is_keyframe() && !header.is_end_of_frame() && !header.has_dep_desc());
+    header.flags = FLAG_KEYFRAME;
+    assert!(header.is_keyframe() && !header.is_end_of_frame());
+    header.flags = FLAG_END_OF_FRAME;
+    assert!(header.is_end_of_frame() && !header.is_keyframe());
+    header.flags = FLAG_HAS_DEP_DESC;
+    assert!(header.has_dep_desc() && !header.is_keyframe());
+}
+
+// ---------------------------------------------------------------------------
+// Frames
+// ---------------------------------------------------------------------------
+
+#[test]
+fn audio_frame() {
+    let frame = OutFrame::audio(7, 9, CODEC_OPUS, 11, 960, Bytes::from_static(b"opus"));
+    let decoded = InFrame::decode(frame.encode()).unwrap();
+    assert_eq!(decoded.header.media_type, MEDIA_TYPE_AUDIO);
+    assert_eq!(decoded.header.codec_id, CODEC_OPUS);
+    assert_eq!(decoded.header.flags, FLAG_END_OF_FRAME);
+    assert_eq!((decoded.header.room_id, decoded.header.user_id), (7, 9));
+    assert_eq!((decoded.header.sequence, decoded.header.timestamp), (11, 960));
+    assert_eq!(&decoded.payload[..], b"opus");
+}
+
+#[test]
+fn video_frame_flags() {
+    for (keyframe, end) in [(false, false), (true, false), (false, true), (true, true)] {
+        let frame = OutFrame::video(1, 2, 3, 4, keyframe, end, Bytes::new());
+        assert_eq!(frame.header.media_type, MEDIA_TYPE_VIDEO);
+        assert_eq!(frame.header.codec_id, CODEC_AV1);
+        assert_eq!(frame.header.is_keyframe(), keyframe);
+        assert_eq!(frame.header.is_end_of_frame(), end);
+    }
+}
+
+#[test]
+fn data_frame_prefixes_channel_id() {
+    let frame = OutFrame::data(1, 2, 3, 0xABCD, b"hello");
+    assert_eq!(frame.header.media_type, MEDIA_TYPE_DATA);
+    assert_eq!(frame.header.codec_id, CODEC_NONE);
+    assert_eq!(&frame.payload[..DATA_CHANNEL_PREFIX], &[0xAB, 0xCD]);
+    assert_eq!(&frame.payload[DATA_CHANNEL_PREFIX..], b"hello");
+}
+
+#[test]
+fn feedback_frame() {
+    let frame = OutFrame::feedback(1, 2, 3, Bytes::from_static(&[FB_LEAVE]));
+    let decoded = InFrame::decode(frame.encode()).unwrap();
+    assert_eq!(decoded.header.media_type, MEDIA_TYPE_RTCP_FB);
+    assert_eq!(&decoded.payload[..], &[FB_LEAVE]);
+}
+
+#[test]
+fn frame_encoding_is_header_then_payload() {
+    let frame = OutFrame::audio(1, 2, CODEC_OPUS, 3, 4, Bytes::from_static(b"xyz"));
+    let bytes = frame.encode();
+    assert_eq!(bytes.len(), HEADER_SIZE + 3);
+    assert_eq!(&bytes[..HEADER_SIZE], &frame.header.encode());
+    assert_eq!(&bytes[HEADER_SIZE..], b"xyz");
+}
+
+#[test]
+fn in_frame_rejects_short_datagram() {
+    assert!(InFrame::decode(Bytes::from_static(&[0; HEADER_SIZE - 1])).is_none());
+    let empty = InFrame::decode(Bytes::from_static(&[0; HEADER_SIZE])).unwrap();
+    assert!(empty.payload.is_empty());
+}
+
+#[test]
+fn receive_preferences_layout() {
+    let prefs = ReceivePreferences {
+        max_video_streams: Some(4),
+        max_resolution: Some((1280, 720)),
+        audio_only: true,
+    };
+    assert_eq!(&prefs.encode()[..], &[FB_RECEIVE_PREFERENCES, 1, 0, 4, 0x05, 0x00, 0x02, 0xD0]);
+    assert_eq!(
+        &ReceivePreferences::default().encode()[..],
+        &[FB_RECEIVE_PREFERENCES, 0, 0xFF, 0xFF, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn receive_preferences_round_trip() {
+    for prefs in [
+        ReceivePreferences::default(),
+        ReceivePreferences {
+            max_video_streams: Some(0),
+            max_resolution: Some((640, 360)),
+            audio_only: false,
+        },
+        ReceivePreferences {
+            max_video_streams: None,
+            max_resolution: None,
+            audio_only: true,
+        },
+    ] {
+        assert_eq!(ReceivePreferences::decode(&prefs.encode()), Some(prefs));
+    }
+    assert_eq!(ReceivePreferences::decode(&[FB_RECEIVE_PREFERENCES, 0, 0]), None);
+    assert_eq!(ReceivePreferences::decode(&[FB_LEAVE, 0, 0, 0, 0, 0, 0, 0]), None);
+}
+
+#[test]
+fn receive_preferences_fit_resolution() {
+    let limit = |width, height| ReceivePreferences {
+        max_resolution: Some((width, height)),
+        ..Default::default()
+    };
+    assert_eq!(ReceivePreferences::default().fit_resolution(3840, 2160), None);
+    assert_eq!(limit(640, 360).fit_resolution(640, 360), None);
+    assert_eq!(limit(640, 360).fit_resolution(320, 180), None);
+    assert_eq!(limit(640, 360).fit_resolution(1920, 1080), Some((640, 360)));
+    // Portrait frames are bounded by height, keeping the aspect ratio.
+    assert_eq!(limit(640, 360).fit_resolution(720, 1280), Some((202, 360)));
+    assert_eq!(limit(640, 360).fit_resolution(1280, 400), Some((640, 200)));
+    // A zero limit leaves that dimension unbounded.
+    assert_eq!(limit(640, 0).fit_resolution(1280, 4000), Some((640, 2000)));
+    assert_eq!(limit(0, 360).fit_resolution(4000, 720), Some((2000, 360)));
+    assert_eq!(limit(0, 360).fit_resolution(4000, 360), None);
+}
+
+// ---------------------------------------------------------------------------
+// Fragmentation and reassembly
+// ---------------------------------------------------------------------------
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn reassemble(reassembler: &mut VideoReassembler, fragments: &[OutFrame]) -> Option<ReassembledFrame> {
+    let mut result = None;
+    for fragment in fragments {
+        let frame = InFrame::decode(fragment.encode()).unwrap();
+        if let Some(done) = reassembler.add_fragment(&frame.header, &frame.payload) {
+            assert!(result.is_none(), "frame emitted twice");
+            result = Some(done);
+        }
+    }
+    result
+}
+
+#[test]
+fn fragment_sizes_and_flags() {
+    let data = payload(2500);
+    let fragments = fragment_video(1, 2, 100, 7, true, &data, 1000);
+    assert_eq!(fragments.len(), 3);
+    let sizes: Vec<usize> = fragments.iter().map(|f| f.payload.len()).collect();
+    assert_eq!(sizes, [1000, 1000, 500]);
+    for (i, fragment) in fragments.iter().enumerate() {
+        assert_eq!(fragment.header.sequence, 100 + i as u32);
+        assert_eq!(fragment.header.timestamp, 7);
+        assert_eq!(fragment.header.is_keyframe(), i == 0);
+        assert_eq!(fragment.header.is_start_of_frame(), i == 0);
+        assert_eq!(fragment.header.is_end_of_frame(), i == 2);
+    }
+}
+
+#[test]
+fn fragment_exact_multiple_and_small_frames() {
+    assert_eq!(fragment_video(1, 2, 0, 0, false, &payload(2000), 1000).len(), 2);
+    assert_eq!(fragment_video(1, 2, 0, 0, false, &payload(1), 1000).len(), 1);
+    let empty = fragment_video(1, 2, 0, 0, false, &[], 1000);
+    assert_eq!(empty.len(), 1);
+    assert!(empty[0].header.is_end_of_frame());
+}
+
+#[test]
+fn fragment_fits_max_payload() {
+    let fragments = fragment_video(1, 2, 0, 0, false, &payload(10_000), MAX_FRAGMENT_PAYLOAD);
+    for fragment in &fragments {
+        assert!(fragment.encode().len() <= MAX_FRAGMENT_PAYLOAD + HEADER_SIZE);
+    }
+}
+
+#[test]
+fn reassemble_in_order() {
+    let data = payload(5000);
+    let fragments = fragment_video(1, 2, 0, 42, true, &data, 1178);
+    let frame = reassemble(&mut VideoReassembler::new(), &fragments).unwrap();
+    assert_eq!(frame.user_id, 2);
+    assert_eq!(frame.timestamp, 42);
+    assert!(frame.is_keyframe);
+    assert_eq!(frame.data, data);
+}
+
+#[test]
+fn reassemble_single_fragment() {
+    let data = payload(100);
+    let fragments = fragment_video(1, 2, 0, 0, false, &data, 1178);
+    let frame = reassemble(&mut VideoReassembler::new(), &fragments).unwrap();
+    assert!(!frame.is_keyframe);
+    assert_eq!(frame.data, data);
+}
+
+#[test]
+fn reassemble_out_of_order() {
+    let data = payload(4000);
+    let mut fragments = fragment_video(1, 2, 10, 0, false, &data, 1000);
+    fragments.reverse();
+    let mut reassembler = VideoReassembler::new();
+    let frame = reassemble(&mut reassembler, &fragments).unwrap();
+    assert_eq!(frame.data, data);
+    assert_eq!(reassembler.pending_frames(), 0);
+}
+
+#[test]
+fn reassemble_across_sequence_wraparound() {
+    let data = payload(3500);
+    let mut fragments = fragment_video(1, 2, u32::MAX - 1, 0, true, &data, 1000);
+    assert_eq!(fragments[2].header.sequence, 0);
+    fragments.swap(0, 3);
+    let frame = reassemble(&mut VideoReassembler::new(), &fragments).unwrap();
+    assert_eq!(frame.data, data);
+    assert!(frame.is_keyframe);
+}
+
+#[test]
+fn reassemble_waits_for_gap() {
+    let data = payload(3000);
+    let fragments = fragment_video(1, 2, 0, 0, false, &data, 1000);
+    let mut reassembler = VideoReassembler::new();
+    let mut receive = |f: &OutFrame| reassembler.add_fragment(&f.header, &f.payload);
+    assert!(receive(&fragments[0]).is_none());
+    assert!(receive(&fragments[2]).is_none(), "emitted with a missing fragment");
+    assert_eq!(receive(&fragments[1]).unwrap().data, data);
+}
+
+#[test]
+fn reassemble_waits_for_start_of_frame() {
+    let data = payload(3000);
+    let fragments = fragment_video(1, 2, 0, 0, false, &data, 1000);
+    let mut reassembler = VideoReassembler::new();
+    let mut receive = |f: &OutFrame| reassembler.add_fragment(&f.header, &f.payload);
+    assert!(receive(&fragments[2]).is_none(), "emitted from the last fragment alone");
+    assert!(receive(&fragments[1]).is_none(), "emitted without the first fragment");
+    assert_eq!(receive(&fragments[0]).unwrap().data, data);
+}
+
+#[test]
+fn reassemble_infers_start_for_senders_without_the_flag() {
+    let legacy = |first_seq, timestamp, is_keyframe, data: &[u8]| {
+        let mut fragments = fragment_video(1, 2, first_seq, timestamp, is_keyframe, data, 1000);
+        for fragment in &mut fragments {
+            fragment.header.flags &= !FLAG_START_OF_FRAME;
+        }
+        fragments
+    };
+    let (key, delta) = (payload(3000), payload(2500));
+    let keyframe = legacy(0, 0, true, &key);
+    let next = legacy(3, 1, false, &delta);
+
+    let mut fresh = VideoReassembler::new();
+    assert!(reassemble(&mut fresh, &next[1..]).is_none(), "emitted a delta frame with no known start");
+
+    let mut reassembler = VideoReassembler::new();
+    assert_eq!(reassemble(&mut reassembler, &keyframe).unwrap().data, key);
+    let mut receive = |f: &OutFrame| reassembler.add_fragment(&f.header, &f.payload);
+    assert!(receive(&next[2]).is_none());
+    assert!(receive(&next[1]).is_none(), "emitted without the first fragment");
+    assert_eq!(receive(&next[0]).unwrap().data, delta);
+}
+
+#[test]
+fn reassemble_ignores_duplicates() {
+    let data = payload(2000);
+    let fragments = fragment_video(1, 2, 0, 0, false, &data, 1000);
+    let mut reassembler = VideoReassembler::new();
+    assert!(reassembler.add_fragment(&fragments[0].header, &fragments[0].payload).is_none());
+    assert!(reassembler.add_fragment(&fragments[0].header, &fragments[0].payload).is_none());
+    let frame = reassembler.add_fragment(&fragments[1].header, &fragments[1].payload).unwrap();
+    assert_eq!(frame.data, data);
+}
+
+#[test]
+fn reassemble_keeps_senders_and_timestamps_apart() {
+    let a = payload(1500);
+    let b: Vec<u8> = payload(1500).iter().map(|v| v ^ 0xFF).collect();
+    let from_a = fragment_video(1, 2, 0, 5, false, &a, 1000);
+    let from_b = fragment_video(1, 3, 0, 5, false, &b, 1000);
+    let next_a = fragment_video(1, 2, 2, 6, false, &b, 1000);
+    let mut reassembler = VideoReassembler::new();
+    let mut done = Vec::new();
+    for fragment in [&from_a[0], &from_b[0], &next_a[0], &from_b[1], &next_a[1], &from_a[1]] {
+        done.extend(reassembler.add_fragment(&fragment.header, &fragment.payload));
+    }
+    let summary: Vec<(u32, u32, bool)> = done.iter().map(|f| (f.user_id, f.timestamp, f.data == a)).collect();
+    assert_eq!(summary, [(3, 5, false), (2, 6, false), (2, 5, true)]);
+}
+
+#[test]
+fn evict_stale_partial_frames() {
+    let fragments = fragment_video(1, 2, 0, 0, false, &payload(2000), 1000);
+    let mut reassembler = VideoReassembler::new();
+    reassembler.add_fragment(&fragments[0].header, &fragments[0].payload);
+    reassembler.evict_stale(Duration::from_secs(60));
+    assert_eq!(reassembler.pending_frames(), 1);
+    std::thread::sleep(Duration::from_millis(5));
+    reassembler.evict_stale(Duration::from_millis(1));
+    assert_eq!(reassembler.pending_frames(), 0);
+}
+
+#[test]
+fn drop_user_discards_only_that_sender() {
+    let mut reassembler = VideoReassembler::new();
+    for user_id in [2, 3] {
+        let header = video_header(user_id, 0, 0, FLAG_START_OF_FRAME);
+        reassembler.add_fragment(&header, b"partial");
+    }
+    reassembler.drop_user(2);
+    assert_eq!(reassembler.pending_frames(), 1);
+    let end = video_header(3, 1, 0, FLAG_END_OF_FRAME);
+    assert_eq!(reassembler.add_fragment(&end, b"!").unwrap().data, b"partial!");
+}
@@ -0,0 +1,169 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use vox_core::files::{self, FileError, FileManifest, DEFAULT_CHUNK_SIZE, FILE_ID_LEN};
+
+/// I/O errors become `OSError`, bad arguments and manifests `ValueError`,
+/// and ciphertext that fails verification `RuntimeError`.
+fn to_py_err(e: FileError) -> PyErr {
+    match e {
+        FileError::Io(e) => e.into(),
+        FileError::Invalid(msg) => PyErr::new::<pyo3::exceptions::PyValueError, _>(msg),
+        FileError::Integrity(msg) => {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("integrity check failed: {msg}"))
+        }
+    }
+}
+
+fn parse_manifest(manifest: &str) -> PyResult<FileManifest> {
+    FileManifest::from_json(manifest).map_err(to_py_err)
+}
+
+fn file_id_array(file_id: &[u8]) -> PyResult<[u8; FILE_ID_LEN]> {
+    file_id.try_into().map_err(|_| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("file_id must be {FILE_ID_LEN} bytes"))
+    })
+}
+
+/// A fresh random file id, to export the file's key with
+/// `MlsEngine.export_file_key` before encrypting.
+#[pyfunction]
+fn new_file_id<'py>(py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+    let mut id = [0u8; FILE_ID_LEN];
+    getrandom::fill(&mut id)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("no randomness: {e}")))?;
+    Ok(PyBytes::new(py, &id))
+}
+
+/// Encrypt the file at `src` into the blob at `dst`, streaming a chunk at
+/// a time, and return the manifest as JSON.
+///
+/// `key` is the file key for `file_id` from the MLS group at `key_epoch`.
+/// The manifest records the id and epoch, the chunk layout and SHA-256
+/// hashes of each chunk and of the whole blob, for the blob store. It holds
+/// no secrets. The GIL is released while working.
+#[pyfunction]
+#[pyo3(signature = (src, dst, key, file_id, key_epoch, chunk_size=DEFAULT_CHUNK_SIZE))]
+fn encrypt_file(
+    py: Python<'_>,
+    src: PathBuf,
+    dst: PathBuf,
+    key: Vec<u8>,
+    file_id: Vec<u8>,
+    key_epoch: u64,
+    chunk_size: usize,
+) -> PyResult<String> {
+    let file_id = file_id_array(&file_id)?;
+    py.detach(|| {
+        let reader = BufReader::new(File::open(&src)?);
+        let writer = BufWriter::new(File::create(&dst)?);
+        let result = files::encrypt_stream(&key, file_id, key_epoch, chunk_size, reader, writer);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&dst);
+        }
+        result.map(|m| m.to_json())
+    })
+    .map_err(to_py_err)
+}
+
+/// Decrypt the blob at `src` into `dst`, verifying every chunk and the
+/// whole-blob hash against `manifest`. Returns the plaintext size.
+///
+/// If verification fails, `dst` is removed rather than left holding part
+/// of the file. The GIL is released while working.
+#[pyfunction]
+fn decrypt_file(py: Python<'_>, src: PathBuf, dst: PathBuf, key: Vec<u8>, manifest: &str) -> PyResult<u64> {
+    let manifest = parse_manifest(manifest)?;
+    py.detach(|| {
+        let reader = BufReader::new(File::open(&src)?);
+        let writer = BufWriter::new(File::create(&dst)?);
+        let result = files::decrypt_stream(&key, manifest, reader, writer);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&dst);
+        }
+        result
+    })
+    .map_err(to_py_err)
+}
+
+/// The fields of a manifest, to find which key decrypts it: `file_id`
+/// (bytes), `key_epoch`, `size`, `encrypted_size`, `chunk_size`, `chunks`
+/// and `sha256` (hex).
+#[pyfunction]
+fn read_manifest<'py>(py: Python<'py>, manifest: &str) -> PyResult<Bound<'py, PyDict>> {
+    let m = parse_manifest(manifest)?;
+    let d = PyDict::new(py);
+    d.set_item("file_id", PyBytes::new(py, &m.file_id))?;
+    d.set_item("key_epoch", m.key_epoch)?;
+    d.set_item("size", m.size)?;
+    d.set_item("encrypted_size", m.encrypted_size)?;
+    d.set_item("chunk_size", m.chunk_size)?;
+    d.set_item("chunks", m.chunks.len())?;
+    d.set_item("sha256", m.sha256.iter().map(|b| format!("{b:02x}")).collect::<String>())?;
+    Ok(d)
+}
+
+/// Decrypts a blob piece by piece as it downloads.
+///
+/// Pass ciphertext of any length to `update`; it returns the plaintext of
+/// the chunks it completed, each released only after it verified. Call
+/// `finish` after the last piece to check that the blob was complete.
+#[pyclass]
+struct FileDecryptor {
+    inner: Option<files::FileDecryptor>,
+}
+
+#[pymethods]
+impl FileDecryptor {
+    #[new]
+    fn new(key: Vec<u8>, manifest: &str) -> PyResult<Self> {
+        let decryptor = files::FileDecryptor::new(&key, parse_manifest(manifest)?).map_err(to_py_err)?;
+        Ok(FileDecryptor { inner: Some(decryptor) })
+    }
+
+    /// Plaintext size of the whole file.
+    #[getter]
+    fn size(&self) -> PyResult<u64> {
+        Ok(self.decryptor()?.manifest().size)
+    }
+
+    fn update<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        let decryptor = self.inner.as_mut().ok_or_else(finished)?;
+        let mut out = Vec::new();
+        if let Err(e) = decryptor.update(data, &mut out) {
+            // The stream can't recover from a bad chunk
+            self.inner = None;
+            return Err(to_py_err(e));
+        }
+        Ok(PyBytes::new(py, &out))
+    }
+
+    fn finish(&mut self) -> PyResult<()> {
+        self.inner.take().ok_or_else(finished)?.finish().map_err(to_py_err)
+    }
+}
+
+impl FileDecryptor {
+    fn decryptor(&self) -> PyResult<&files::FileDecryptor> {
+        self.inner.as_ref().ok_or_else(finished)
+    }
+}
+
+fn finished() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("FileDecryptor is finished")
+}
+
+/// Python module definition.
+#[pymodule]
+fn vox_files(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<FileDecryptor>()?;
+    m.add_function(wrap_pyfunction!(new_file_id, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(read_manifest, m)?)?;
+    m.add("DEFAULT_CHUNK_SIZE", DEFAULT_CHUNK_SIZE)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
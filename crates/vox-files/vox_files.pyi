@@ -0,0 +1,38 @@
+# Type stubs for the vox_files extension module.
+#
+# Packaged by maturin alongside the compiled module. Keep in sync with
+# src/lib.rs; tests/test_stubs.py checks them against the built module.
+
+from os import PathLike
+from typing import TypedDict, final
+
+__version__: str
+DEFAULT_CHUNK_SIZE: int
+
+class _ManifestInfo(TypedDict):
+    file_id: bytes
+    key_epoch: int
+    size: int
+    encrypted_size: int
+    chunk_size: int
+    chunks: int
+    sha256: str
+
+def new_file_id() -> bytes: ...
+def encrypt_file(
+    src: str | PathLike[str],
+    dst: str | PathLike[str],
+    key: bytes,
+    file_id: bytes,
+    key_epoch: int,
+    chunk_size: int = 262144,
+) -> str: ...
+def decrypt_file(src: str | PathLike[str], dst: str | PathLike[str], key: bytes, manifest: str) -> int: ...
+def read_manifest(manifest: str) -> _ManifestInfo: ...
+@final
+class FileDecryptor:
+    def __new__(cls, key: bytes, manifest: str) -> FileDecryptor: ...
+    @property
+    def size(self) -> int: ...
+    def update(self, data: bytes) -> bytes: ...
+    def finish(self) -> None: ...
@@ -1,13 +1,14 @@
-//! cpal audio capture and playback.
+//! Audio capture and playback.
 //!
-//! Negotiates the best supported device config (targeting 48 kHz mono)
-//! and resamples on-the-fly when the hardware rate differs.
+//! Device I/O lives in `device`: cpal with the `audio-devices` feature, or a
+//! headless stand-in that captures nothing and discards playback, so servers
+//! can still connect, decode and report stats. Stats, ducking and the
+//! process-wide mix are shared by both.
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::SupportedStreamConfigRange;
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
 
 /// Captured audio samples from the microphone.
 pub type AudioSamples = Vec<i16>;
@@ -18,448 +19,367 @@ const TARGET_RATE: u32 = 48_000;
 const TARGET_CHANNELS: u16 = 1;
 
 // ---------------------------------------------------------------------------
-// Config negotiation
+// Local audio scheduling statistics
 // ---------------------------------------------------------------------------
 
-/// Chosen device configuration after negotiation.
-struct NegotiatedConfig {
-    /// The stream config to pass to cpal.
-    stream: cpal::StreamConfig,
-    /// Whether we need to resample (device rate != 48 kHz).
-    needs_resample: bool,
-    /// The device's native sample rate.
-    device_rate: u32,
-    /// The device's native channel count.
-    device_channels: u16,
+/// Counters for glitches caused by local audio scheduling rather than the
+/// network, updated from the cpal callbacks.
+#[derive(Debug, Default)]
+pub struct AudioStats {
+    /// Playback ran dry between two frames of a continuous stream and
+    /// silence was inserted.
+    underruns: AtomicU64,
+    /// Samples dropped because the playback buffer overflowed.
+    overflow_samples: AtomicU64,
+    /// Captured frames dropped because the media loop fell behind.
+    capture_overruns: AtomicU64,
 }
 
-/// Pick the best supported config for a device, targeting 48 kHz mono.
-///
-/// Priority:
-/// 1. Exact match: 48 kHz, 1 channel
-/// 2. 48 kHz with any channel count (we down-mix)
-/// 3. Closest rate that is a multiple/factor of 48 kHz, mono preferred
-/// 4. Any supported config (we resample + channel convert)
-fn negotiate_config(
-    configs: impl Iterator<Item = SupportedStreamConfigRange>,
-) -> Result<NegotiatedConfig, Box<dyn std::error::Error>> {
-    let ranges: Vec<SupportedStreamConfigRange> = configs.collect();
-    if ranges.is_empty() {
-        return Err("Device reports no supported configurations".into());
+impl AudioStats {
+    pub fn snapshot(&self) -> AudioStatsSnapshot {
+        AudioStatsSnapshot {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            overflow_samples: self.overflow_samples.load(Ordering::Relaxed),
+            capture_overruns: self.capture_overruns.load(Ordering::Relaxed),
+        }
     }
 
-    // Check if a range includes our target rate
-    let supports_48k = |r: &SupportedStreamConfigRange| -> bool {
-        r.min_sample_rate() <= TARGET_RATE
-            && r.max_sample_rate() >= TARGET_RATE
-    };
+    pub fn reset(&self) {
+        self.underruns.store(0, Ordering::Relaxed);
+        self.overflow_samples.store(0, Ordering::Relaxed);
+        self.capture_overruns.store(0, Ordering::Relaxed);
+    }
+}
 
-    // 1. Exact match: 48 kHz and mono
-    if let Some(r) = ranges
-        .iter()
-        .find(|r| supports_48k(r) && r.channels() == TARGET_CHANNELS)
-    {
-        let cfg = r.with_sample_rate(TARGET_RATE);
-        return Ok(NegotiatedConfig {
-            stream: cfg.into(),
-            needs_resample: false,
-            device_rate: TARGET_RATE,
-            device_channels: TARGET_CHANNELS,
-        });
+/// Point-in-time copy of [`AudioStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AudioStatsSnapshot {
+    pub underruns: u64,
+    pub overflow_samples: u64,
+    pub capture_overruns: u64,
+}
+
+// ---------------------------------------------------------------------------
+// Voice ducking
+// ---------------------------------------------------------------------------
+
+/// Playback attenuation applied while the local user is speaking.
+#[derive(Debug, Clone)]
+pub struct DuckingConfig {
+    /// Gain applied to ducked sources while speaking (0.0–1.0).
+    pub level: f32,
+    /// Time to ramp back to full volume after the local user stops.
+    pub release: Duration,
+    /// Remote users to duck; `None` ducks all playback.
+    pub sources: Option<HashSet<u32>>,
+}
+
+/// Mixer-side ducking gain, driven per captured frame by local voice activity.
+///
+/// Attack is immediate: the first voiced capture frame ducks every playback
+/// frame mixed after it. Release ramps linearly back to unity.
+#[derive(Debug, Default)]
+pub struct Ducker {
+    config: Option<DuckingConfig>,
+    voiced: bool,
+    /// When the local user last stopped speaking (start of the release ramp).
+    released_at: Option<Instant>,
+}
+
+impl Ducker {
+    /// Enable ducking with `config`, or disable it with `None`.
+    pub fn set_config(&mut self, config: Option<DuckingConfig>) {
+        self.config = config;
     }
 
-    // 2. 48 kHz with any channel count (we'll down-mix in the callback)
-    if let Some(r) = ranges.iter().find(|r| supports_48k(r)) {
-        let ch = r.channels();
-        let cfg = r.with_sample_rate(TARGET_RATE);
-        return Ok(NegotiatedConfig {
-            stream: cfg.into(),
-            needs_resample: false,
-            device_rate: TARGET_RATE,
-            device_channels: ch,
-        });
+    /// Record whether the latest captured frame contained local voice.
+    pub fn set_local_voice(&mut self, voiced: bool) {
+        self.set_local_voice_at(voiced, Instant::now());
     }
 
-    // 3. Preferred alternative rates (multiples / factors of 48 kHz)
-    let preferred_rates: &[u32] = &[96_000, 44_100, 24_000, 16_000, 8_000];
-    for &rate in preferred_rates {
-        // Prefer mono first, then any channel count
-        for want_mono in [true, false] {
-            if let Some(r) = ranges.iter().find(|r| {
-                r.min_sample_rate() <= rate
-                    && r.max_sample_rate() >= rate
-                    && (!want_mono || r.channels() == TARGET_CHANNELS)
-            }) {
-                let ch = r.channels();
-                let cfg = r.with_sample_rate(rate);
-                return Ok(NegotiatedConfig {
-                    stream: cfg.into(),
-                    needs_resample: true,
-                    device_rate: rate,
-                    device_channels: ch,
-                });
-            }
+    /// [`set_local_voice`](Self::set_local_voice) for a frame captured at `now`.
+    pub fn set_local_voice_at(&mut self, voiced: bool, now: Instant) {
+        if voiced {
+            self.voiced = true;
+            self.released_at = None;
+        } else if self.voiced {
+            self.voiced = false;
+            self.released_at = Some(now);
         }
     }
 
-    // 4. Fallback: use the device's default/max config from the first range
-    let r = &ranges[0];
-    let rate = r.max_sample_rate().min(96_000).max(r.min_sample_rate());
-    let ch = r.channels();
-    let cfg = r.with_sample_rate(rate);
-    Ok(NegotiatedConfig {
-        stream: cfg.into(),
-        needs_resample: rate != TARGET_RATE,
-        device_rate: rate,
-        device_channels: ch,
-    })
+    /// Current ducking gain for a remote user's audio (1.0 = not ducked).
+    pub fn gain(&self, user_id: u32) -> f32 {
+        self.gain_at(user_id, Instant::now())
+    }
+
+    /// [`gain`](Self::gain) as of `now`.
+    pub fn gain_at(&self, user_id: u32, now: Instant) -> f32 {
+        let Some(config) = &self.config else {
+            return 1.0;
+        };
+        if config.sources.as_ref().is_some_and(|s| !s.contains(&user_id)) {
+            return 1.0;
+        }
+        if self.voiced {
+            return config.level;
+        }
+        let Some(released_at) = self.released_at else {
+            return 1.0;
+        };
+        if config.release.is_zero() {
+            return 1.0;
+        }
+        let released_for = now.saturating_duration_since(released_at);
+        let progress = (released_for.as_secs_f32() / config.release.as_secs_f32()).min(1.0);
+        config.level + (1.0 - config.level) * progress
+    }
 }
 
 // ---------------------------------------------------------------------------
-// Linear resampler (capture: device rate → 48 kHz)
+// Mix priorities
 // ---------------------------------------------------------------------------
 
-/// Simple linear-interpolation resampler from `from_rate` to `to_rate`.
-struct LinearResampler {
-    from_rate: u32,
-    to_rate: u32,
-    /// Fractional position in the source stream.
-    phase: f64,
-    /// Last source sample (for interpolation).
-    prev: f64,
+/// A source counts as active for this long after it last played speech.
+const MIX_ACTIVE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Priority and last speech time of each registered playback source.
+static MIX_SOURCES: LazyLock<Mutex<HashMap<u64, (i32, Option<Instant>)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_MIX_SOURCE: AtomicU64 = AtomicU64::new(0);
+
+/// One playback source (a client's session) in the process-wide mix.
+///
+/// While a higher-priority source is playing speech, this source is
+/// attenuated, so a primary room stays intelligible over secondary rooms or
+/// soundboard audio. Unregisters itself on drop.
+#[derive(Debug)]
+pub struct MixSource {
+    id: u64,
+    /// Gain applied while outranked (1.0 = no attenuation).
+    attenuation: f32,
 }
 
-impl LinearResampler {
-    fn new(from_rate: u32, to_rate: u32) -> Self {
-        Self {
-            from_rate,
-            to_rate,
-            phase: 0.0,
-            prev: 0.0,
+impl MixSource {
+    pub fn new() -> Self {
+        let id = NEXT_MIX_SOURCE.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut sources) = MIX_SOURCES.lock() {
+            sources.insert(id, (0, None));
         }
+        MixSource { id, attenuation: 1.0 }
     }
 
-    /// Resample a mono i16 buffer. Returns the resampled output.
-    fn process(&mut self, input: &[i16]) -> Vec<i16> {
-        if input.is_empty() {
-            return Vec::new();
-        }
-        let ratio = self.from_rate as f64 / self.to_rate as f64;
-        let est_len = ((input.len() as f64) / ratio).ceil() as usize + 1;
-        let mut out = Vec::with_capacity(est_len);
-
-        for &s in input {
-            let cur = s as f64;
-            // Emit output samples while our phase is behind the current input sample
-            while self.phase < 1.0 {
-                let interp = self.prev + (cur - self.prev) * self.phase;
-                out.push(interp.clamp(-32767.0, 32767.0) as i16);
-                self.phase += ratio;
+    /// Set this source's priority and its gain while a higher one is active.
+    pub fn configure(&mut self, priority: i32, attenuation: f32) {
+        self.attenuation = attenuation;
+        if let Ok(mut sources) = MIX_SOURCES.lock() {
+            if let Some(entry) = sources.get_mut(&self.id) {
+                entry.0 = priority;
             }
-            self.phase -= 1.0;
-            self.prev = cur;
         }
-        out
     }
-}
 
-/// Resample from 48 kHz to device rate for playback.
-struct PlaybackResampler {
-    from_rate: u32,
-    to_rate: u32,
-    phase: f64,
-    prev: f64,
-}
+    /// Record that this source is playing speech now.
+    pub fn mark_active(&self) {
+        self.mark_active_at(Instant::now());
+    }
 
-impl PlaybackResampler {
-    fn new(to_rate: u32) -> Self {
-        Self {
-            from_rate: TARGET_RATE,
-            to_rate,
-            phase: 0.0,
-            prev: 0.0,
+    /// Record that this source played speech at `now`.
+    pub fn mark_active_at(&self, now: Instant) {
+        if let Ok(mut sources) = MIX_SOURCES.lock() {
+            if let Some(entry) = sources.get_mut(&self.id) {
+                entry.1 = Some(now);
+            }
         }
     }
 
-    /// Resample mono i16 from 48 kHz → device rate.
-    fn process(&mut self, input: &[i16]) -> Vec<i16> {
-        if input.is_empty() {
-            return Vec::new();
+    /// Gain for this source given the other sources' current activity.
+    pub fn gain(&self) -> f32 {
+        self.gain_at(Instant::now())
+    }
+
+    /// [`gain`](Self::gain) as of `now`.
+    pub fn gain_at(&self, now: Instant) -> f32 {
+        if (self.attenuation - 1.0).abs() < f32::EPSILON {
+            return 1.0;
         }
-        let ratio = self.from_rate as f64 / self.to_rate as f64;
-        let est_len = ((input.len() as f64) / ratio).ceil() as usize + 1;
-        let mut out = Vec::with_capacity(est_len);
-
-        for &s in input {
-            let cur = s as f64;
-            while self.phase < 1.0 {
-                let interp = self.prev + (cur - self.prev) * self.phase;
-                out.push(interp.clamp(-32767.0, 32767.0) as i16);
-                self.phase += ratio;
-            }
-            self.phase -= 1.0;
-            self.prev = cur;
+        let Ok(sources) = MIX_SOURCES.lock() else {
+            return 1.0;
+        };
+        let Some(&(priority, _)) = sources.get(&self.id) else {
+            return 1.0;
+        };
+        let outranked = sources.iter().any(|(&id, &(p, active))| {
+            let recent = active.is_some_and(|t| now.saturating_duration_since(t) < MIX_ACTIVE_WINDOW);
+            id != self.id && p > priority && recent
+        });
+        if outranked {
+            self.attenuation
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Default for MixSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MixSource {
+    fn drop(&mut self) {
+        if let Ok(mut sources) = MIX_SOURCES.lock() {
+            sources.remove(&self.id);
         }
-        out
     }
 }
 
 // ---------------------------------------------------------------------------
-// Channel conversion helpers
+// Comfort noise
 // ---------------------------------------------------------------------------
 
-/// Down-mix interleaved multi-channel f32 samples to mono i16.
-fn downmix_to_mono_i16(data: &[f32], channels: u16) -> Vec<i16> {
-    let ch = channels as usize;
-    data.chunks_exact(ch)
-        .map(|frame| {
-            let sum: f32 = frame.iter().sum();
-            let avg = sum / ch as f32;
-            (avg * 32767.0).clamp(-32767.0, 32767.0) as i16
-        })
-        .collect()
+/// Amplitude of generated comfort noise (about -60 dBFS).
+pub const COMFORT_NOISE_LEVEL: f32 = 0.001;
+
+/// Low-level white noise used to fill playback gaps (xorshift32 PRNG).
+#[derive(Debug)]
+pub struct ComfortNoise {
+    state: u32,
 }
 
-/// Up-mix mono i16 to interleaved multi-channel f32.
-fn upmix_from_mono_f32(mono: &[i16], channels: u16) -> Vec<f32> {
-    let ch = channels as usize;
-    let mut out = Vec::with_capacity(mono.len() * ch);
-    for &s in mono {
-        let f = s as f32 / 32767.0;
-        for _ in 0..ch {
-            out.push(f);
-        }
+impl Default for ComfortNoise {
+    fn default() -> Self {
+        Self { state: 0x9E37_79B9 }
+    }
+}
+
+impl ComfortNoise {
+    /// Next sample, uniform within ±[`COMFORT_NOISE_LEVEL`].
+    pub fn next_sample(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state as f32 / u32::MAX as f32 * 2.0 - 1.0) * COMFORT_NOISE_LEVEL
+    }
+}
+
+/// Fill a playback callback's `out` from the front of `buffered`. Samples
+/// the buffer runs short of are comfort noise from `noise`, or silence
+/// without it.
+pub fn fill_playback(out: &mut [f32], buffered: &mut VecDeque<f32>, mut noise: Option<&mut ComfortNoise>) {
+    for sample in out.iter_mut() {
+        *sample = match (buffered.pop_front(), noise.as_deref_mut()) {
+            (Some(s), _) => s,
+            (None, Some(noise)) => noise.next_sample(),
+            (None, None) => 0.0,
+        };
     }
-    out
 }
 
 // ---------------------------------------------------------------------------
 // Public API
 // ---------------------------------------------------------------------------
 
-/// Get the human-readable name from a cpal device via its description.
-fn device_display_name(device: &cpal::Device) -> String {
-    device
-        .description()
-        .map_or_else(|_| "<unknown>".into(), |d| d.name().to_string())
+/// Negotiated configuration of an opened audio device.
+#[derive(Debug, Clone)]
+pub struct AudioDeviceConfig {
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Whether audio is resampled to/from 48 kHz.
+    pub needs_resample: bool,
 }
 
-/// Find an input device by name, falling back to the default if not found.
-fn find_input_device(
-    host: &cpal::Host,
-    device_name: Option<&str>,
-) -> Result<cpal::Device, Box<dyn std::error::Error>> {
-    if let Some(name) = device_name {
-        if let Ok(devices) = host.input_devices() {
-            for dev in devices {
-                if device_display_name(&dev) == name {
-                    tracing::info!("Found requested input device: {}", name);
-                    return Ok(dev);
-                }
-            }
-        }
-        tracing::warn!(
-            "Requested input device {:?} not found, falling back to default",
-            name
-        );
-    }
-    host.default_input_device()
-        .ok_or_else(|| "No input device available".into())
+/// Levels measured by [`measure_input`].
+#[cfg_attr(not(feature = "audio-devices"), allow(dead_code))]
+pub struct InputLevelReport {
+    pub config: AudioDeviceConfig,
+    /// Peak absolute sample value (0.0–1.0).
+    pub peak: f32,
+    /// RMS level over the whole measurement (0.0–1.0).
+    pub rms: f32,
 }
 
-/// Find an output device by name, falling back to the default if not found.
-fn find_output_device(
-    host: &cpal::Host,
-    device_name: Option<&str>,
-) -> Result<cpal::Device, Box<dyn std::error::Error>> {
-    if let Some(name) = device_name {
-        if let Ok(devices) = host.output_devices() {
-            for dev in devices {
-                if device_display_name(&dev) == name {
-                    tracing::info!("Found requested output device: {}", name);
-                    return Ok(dev);
-                }
-            }
+/// Which side of the audio pipeline a stream belongs to.
+#[cfg_attr(not(feature = "audio-devices"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioDirection {
+    Capture,
+    Playback,
+}
+
+impl AudioDirection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AudioDirection::Capture => "capture",
+            AudioDirection::Playback => "playback",
         }
-        tracing::warn!(
-            "Requested output device {:?} not found, falling back to default",
-            name
-        );
     }
-    host.default_output_device()
-        .ok_or_else(|| "No output device available".into())
 }
 
-/// Start capturing audio from an input device.
-/// If `device_name` is provided, attempts to find a matching device by name,
-/// falling back to the default input device if not found.
-/// Returns a receiver that yields PCM frames at 48 kHz mono.
-pub fn start_capture(
-    device_name: Option<&str>,
-    frame_size: usize,
-) -> Result<(cpal::Stream, mpsc::UnboundedReceiver<AudioSamples>), Box<dyn std::error::Error>> {
-    let host = cpal::default_host();
-    let device = find_input_device(&host, device_name)?;
-
-    let dev_name = device_display_name(&device);
-    tracing::info!("Audio capture device: {}", dev_name);
-
-    let neg = negotiate_config(device.supported_input_configs()?)?;
-    tracing::info!(
-        "Capture config: {}Hz {}ch (resample={})",
-        neg.device_rate,
-        neg.device_channels,
-        neg.needs_resample
-    );
-
-    let (tx, rx) = mpsc::unbounded_channel();
-
-    let needs_resample = neg.needs_resample;
-    let dev_channels = neg.device_channels;
-    let dev_rate = neg.device_rate;
-
-    // Shared state for the capture callback
-    let resampler: Arc<Mutex<Option<LinearResampler>>> = if needs_resample {
-        Arc::new(Mutex::new(Some(LinearResampler::new(dev_rate, TARGET_RATE))))
-    } else {
-        Arc::new(Mutex::new(None))
-    };
-    let buffer: Arc<Mutex<Vec<i16>>> = Arc::new(Mutex::new(Vec::with_capacity(frame_size)));
-    let buffer_clone = buffer.clone();
-    let resampler_clone = resampler.clone();
-
-    let stream = device.build_input_stream(
-        &neg.stream,
-        move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            // Convert to mono i16
-            let mono = if dev_channels == 1 {
-                data.iter()
-                    .map(|&s| (s * 32767.0).clamp(-32767.0, 32767.0) as i16)
-                    .collect::<Vec<i16>>()
-            } else {
-                downmix_to_mono_i16(data, dev_channels)
-            };
-
-            // Resample if needed
-            let samples = if let Ok(mut guard) = resampler_clone.lock() {
-                if let Some(ref mut rs) = *guard {
-                    rs.process(&mono)
-                } else {
-                    mono
-                }
-            } else {
-                mono
-            };
-
-            // Buffer into frame_size chunks
-            let mut buf = buffer_clone.lock().unwrap_or_else(|p| p.into_inner());
-            buf.extend_from_slice(&samples);
-            while buf.len() >= frame_size {
-                let frame = buf.drain(..frame_size).collect();
-                let _ = tx.send(frame);
-            }
-        },
-        |err| {
-            tracing::error!("Audio capture error: {}", err);
-        },
-        None,
-    )?;
-
-    stream.play()?;
-    Ok((stream, rx))
-}
+// ---------------------------------------------------------------------------
+// Devices
+// ---------------------------------------------------------------------------
 
-/// Start playback on an output device.
-/// If `device_name` is provided, attempts to find a matching device by name,
-/// falling back to the default output device if not found.
-/// Accepts PCM frames at 48 kHz mono and handles resampling/up-mixing.
-pub fn start_playback(
-    device_name: Option<&str>,
-) -> Result<(cpal::Stream, mpsc::UnboundedSender<AudioSamples>), Box<dyn std::error::Error>> {
-    let host = cpal::default_host();
-    let device = find_output_device(&host, device_name)?;
-
-    let dev_name = device_display_name(&device);
-    tracing::info!("Audio playback device: {}", dev_name);
-
-    let neg = negotiate_config(device.supported_output_configs()?)?;
-    tracing::info!(
-        "Playback config: {}Hz {}ch (resample={})",
-        neg.device_rate,
-        neg.device_channels,
-        neg.needs_resample
-    );
-
-    let needs_resample = neg.needs_resample;
-    let dev_channels = neg.device_channels;
-    let dev_rate = neg.device_rate;
-
-    let (tx, rx) = mpsc::unbounded_channel::<AudioSamples>();
-    let rx = Arc::new(Mutex::new(rx));
-
-    // Playback buffer stores f32 samples ready for the device
-    let playback_buffer: Arc<Mutex<VecDeque<f32>>> = Arc::new(Mutex::new(VecDeque::new()));
-
-    let pb_clone = playback_buffer.clone();
-    let rx_clone = rx.clone();
-    let resampler: Arc<Mutex<Option<PlaybackResampler>>> = if needs_resample {
-        Arc::new(Mutex::new(Some(PlaybackResampler::new(dev_rate))))
-    } else {
-        Arc::new(Mutex::new(None))
+#[cfg(feature = "audio-devices")]
+mod device;
+
+#[cfg(not(feature = "audio-devices"))]
+mod device {
+    use super::{
+        AudioDeviceConfig, AudioDirection, AudioSamples, AudioStats, InputLevelReport, TARGET_CHANNELS, TARGET_RATE,
     };
-    let resampler_clone = resampler.clone();
-
-    // Max buffer in device samples (2 seconds)
-    let max_buf = (dev_rate as usize) * (dev_channels as usize) * 2;
-
-    let stream = device.build_output_stream(
-        &neg.stream,
-        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            let mut buf = pb_clone.lock().unwrap_or_else(|p| p.into_inner());
-            // Drain any waiting frames into the buffer
-            if let Ok(mut rx) = rx_clone.try_lock() {
-                while let Ok(frame) = rx.try_recv() {
-                    // frame is 48 kHz mono i16 — resample then up-mix
-                    let resampled = if let Ok(mut guard) = resampler_clone.lock() {
-                        if let Some(ref mut rs) = *guard {
-                            rs.process(&frame)
-                        } else {
-                            frame
-                        }
-                    } else {
-                        frame
-                    };
-
-                    if dev_channels == 1 {
-                        for &s in &resampled {
-                            buf.push_back(s as f32 / 32767.0);
-                        }
-                    } else {
-                        let floats = upmix_from_mono_f32(&resampled, dev_channels);
-                        buf.extend(floats.into_iter());
-                    }
-                }
-            }
-            // Cap the buffer to prevent unbounded growth
-            if buf.len() > max_buf {
-                let excess = buf.len() - max_buf;
-                buf.drain(..excess);
-                tracing::warn!("Playback buffer overflow, dropped {} samples", excess);
-            }
-            for sample in data.iter_mut() {
-                if let Some(s) = buf.pop_front() {
-                    *sample = s;
-                } else {
-                    *sample = 0.0;
-                }
-            }
-        },
-        |err| {
-            tracing::error!("Audio playback error: {}", err);
-        },
-        None,
-    )?;
-
-    stream.play()?;
-    Ok((stream, tx))
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// Stand-in for a device stream. Holds the capture sender so the media
+    /// loop sees a silent microphone rather than a closed channel.
+    pub struct Stream {
+        _capture: Option<mpsc::Sender<AudioSamples>>,
+    }
+
+    fn headless_config(device_name: Option<&str>) -> AudioDeviceConfig {
+        if let Some(name) = device_name {
+            tracing::warn!("Ignoring audio device {name:?}: vox-media was built without the audio-devices feature");
+        }
+        AudioDeviceConfig {
+            device: "<none>".into(),
+            sample_rate: TARGET_RATE,
+            channels: TARGET_CHANNELS,
+            needs_resample: false,
+        }
+    }
+
+    pub fn measure_input(
+        _device_name: Option<&str>,
+        _duration: Duration,
+    ) -> Result<InputLevelReport, Box<dyn std::error::Error>> {
+        Err("vox-media was built without the audio-devices feature".into())
+    }
+
+    pub fn start_capture(
+        device_name: Option<&str>,
+        _frame_size: usize,
+        _errors: mpsc::UnboundedSender<AudioDirection>,
+        _stats: Arc<AudioStats>,
+    ) -> Result<(Stream, mpsc::Receiver<AudioSamples>, AudioDeviceConfig), Box<dyn std::error::Error>> {
+        let (tx, rx) = mpsc::channel(1);
+        Ok((Stream { _capture: Some(tx) }, rx, headless_config(device_name)))
+    }
+
+    pub fn start_playback(
+        device_name: Option<&str>,
+        _comfort_noise: Arc<AtomicBool>,
+        _errors: mpsc::UnboundedSender<AudioDirection>,
+        _stats: Arc<AudioStats>,
+    ) -> Result<(Stream, mpsc::UnboundedSender<AudioSamples>, AudioDeviceConfig), Box<dyn std::error::Error>> {
+        // Sends to a closed channel fail, and the session ignores the error
+        let (tx, _) = mpsc::unbounded_channel();
+        Ok((Stream { _capture: None }, tx, headless_config(device_name)))
+    }
 }
+
+pub use device::{measure_input, start_capture, start_playback, Stream};
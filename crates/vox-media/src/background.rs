@@ -0,0 +1,199 @@
+//! Background blur and replacement for the outgoing camera stream.
+//!
+//! A segmentation model estimates, per pixel, how likely it is to belong to
+//! a person. Everything else is blurred or replaced with an image before the
+//! frame is encoded. Segmentation runs an ONNX model and needs the
+//! `background-segmentation` feature; without it, enabling an effect fails.
+
+use crate::video;
+use std::sync::{Arc, Mutex};
+
+/// What replaces the background.
+pub enum Backdrop {
+    /// Box blur of the frame itself; `radius` in pixels.
+    Blur { radius: usize },
+    /// A still RGB888 image, cropped and scaled to the frame.
+    Image { rgb: Vec<u8>, width: usize, height: usize },
+}
+
+/// A segmentation model plus the backdrop it composites onto.
+pub struct BackgroundStage {
+    segmenter: Segmenter,
+    backdrop: Backdrop,
+    /// `Backdrop::Image` scaled to the last frame size.
+    scaled_image: Option<(usize, usize, Vec<u8>)>,
+    failed: bool,
+}
+
+/// Stage shared between the session (which swaps it at runtime) and the
+/// camera thread (which applies it).
+pub(crate) type SharedBackground = Arc<Mutex<Option<BackgroundStage>>>;
+
+impl BackgroundStage {
+    pub fn new(model_path: &std::path::Path, backdrop: Backdrop) -> Result<Self, String> {
+        Ok(BackgroundStage {
+            segmenter: Segmenter::load(model_path)?,
+            backdrop,
+            scaled_image: None,
+            failed: false,
+        })
+    }
+
+    /// Composite the person in `rgb` onto the backdrop, in place. If the
+    /// model fails the frame is left untouched.
+    pub fn apply(&mut self, rgb: &mut [u8], width: usize, height: usize) {
+        let mask = match self.segmenter.mask(rgb, width, height) {
+            Ok(m) => m,
+            Err(e) => {
+                if !std::mem::replace(&mut self.failed, true) {
+                    tracing::warn!("Background segmentation failed, sending frames unmodified: {e}");
+                }
+                return;
+            }
+        };
+        let backdrop: std::borrow::Cow<'_, [u8]> = match &self.backdrop {
+            Backdrop::Blur { radius } => box_blur(rgb, width, height, *radius).into(),
+            Backdrop::Image { rgb: image, width: iw, height: ih } => {
+                if !matches!(&self.scaled_image, Some((w, h, _)) if (*w, *h) == (width, height)) {
+                    let scaled = video::scale_rgb(image, *iw, *ih, width, height, video::ScaleMode::Crop);
+                    self.scaled_image = Some((width, height, scaled.into_owned()));
+                }
+                match &self.scaled_image {
+                    Some((_, _, scaled)) => scaled.as_slice().into(),
+                    None => return,
+                }
+            }
+        };
+        for row in 0..height {
+            for col in 0..width {
+                let person = mask.sample((col as f32 + 0.5) / width as f32, (row as f32 + 0.5) / height as f32);
+                let idx = (row * width + col) * 3;
+                for (fg, bg) in rgb[idx..idx + 3].iter_mut().zip(&backdrop[idx..idx + 3]) {
+                    *fg = (f32::from(*fg) * person + f32::from(*bg) * (1.0 - person)).round() as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Person probability in 0..=1 at the model's resolution.
+#[cfg_attr(not(feature = "background-segmentation"), allow(dead_code))]
+struct Mask {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+impl Mask {
+    /// Bilinear lookup at normalised coordinates.
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let fx = (x * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let fy = (y * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (left, top) = (fx as usize, fy as usize);
+        let (right, bottom) = ((left + 1).min(self.width - 1), (top + 1).min(self.height - 1));
+        let (tx, ty) = (fx.fract(), fy.fract());
+        let at = |r: usize, c: usize| self.values[r * self.width + c];
+        let upper = at(top, left) * (1.0 - tx) + at(top, right) * tx;
+        let lower = at(bottom, left) * (1.0 - tx) + at(bottom, right) * tx;
+        (upper * (1.0 - ty) + lower * ty).clamp(0.0, 1.0)
+    }
+}
+
+/// Two passes of a separable box blur, close enough to a Gaussian for a
+/// background that is only there to be out of focus.
+fn box_blur(rgb: &[u8], width: usize, height: usize, radius: usize) -> Vec<u8> {
+    let mut out = rgb.to_vec();
+    if radius == 0 {
+        return out;
+    }
+    let mut tmp = vec![0u8; rgb.len()];
+    for _ in 0..2 {
+        blur_pass(&out, &mut tmp, width, height, radius, true);
+        blur_pass(&tmp, &mut out, width, height, radius, false);
+    }
+    out
+}
+
+/// One sliding-window box blur along rows (`horizontal`) or columns.
+fn blur_pass(src: &[u8], dst: &mut [u8], width: usize, height: usize, radius: usize, horizontal: bool) {
+    let (lines, len) = if horizontal { (height, width) } else { (width, height) };
+    let index = |line: usize, i: usize| if horizontal { (line * width + i) * 3 } else { (i * width + line) * 3 };
+    let window = (2 * radius + 1) as u32;
+    for line in 0..lines {
+        for c in 0..3 {
+            // Edge pixels are repeated to fill the window
+            let px = |i: isize| u32::from(src[index(line, i.clamp(0, len as isize - 1) as usize) + c]);
+            let mut sum: u32 = (-(radius as isize)..=radius as isize).map(px).sum();
+            for i in 0..len {
+                dst[index(line, i) + c] = (sum / window) as u8;
+                sum += px(i as isize + radius as isize + 1);
+                sum -= px(i as isize - radius as isize);
+            }
+        }
+    }
+}
+
+/// ONNX person segmentation. The model takes a `1×H×W×3` float RGB tensor
+/// in 0..=1 and returns a `1×H×W×1` person probability, like MediaPipe's
+/// selfie segmentation model.
+#[cfg(feature = "background-segmentation")]
+struct Segmenter {
+    session: ort::session::Session,
+    width: usize,
+    height: usize,
+}
+
+#[cfg(feature = "background-segmentation")]
+impl Segmenter {
+    fn load(path: &std::path::Path) -> Result<Self, String> {
+        let session = ort::session::Session::builder()
+            .and_then(|b| b.commit_from_file(path))
+            .map_err(|e| format!("Cannot load segmentation model {path:?}: {e}"))?;
+        let (height, width) = match &session.inputs.first().map(|i| &i.input_type) {
+            Some(ort::value::ValueType::Tensor { shape, .. }) if shape.len() == 4 && shape[3] == 3 => {
+                (shape[1], shape[2])
+            }
+            _ => return Err("Segmentation model must take a 1xHxWx3 tensor".into()),
+        };
+        if height <= 0 || width <= 0 {
+            return Err("Segmentation model must have a fixed input size".into());
+        }
+        Ok(Segmenter {
+            session,
+            width: width as usize,
+            height: height as usize,
+        })
+    }
+
+    fn mask(&mut self, rgb: &[u8], width: usize, height: usize) -> Result<Mask, String> {
+        let scaled = video::scale_rgb(rgb, width, height, self.width, self.height, video::ScaleMode::Stretch);
+        let input: Vec<f32> = scaled.iter().map(|&v| f32::from(v) / 255.0).collect();
+        let tensor = ort::value::Tensor::from_array(([1, self.height, self.width, 3], input.into_boxed_slice()))
+            .map_err(|e| e.to_string())?;
+        let outputs = self.session.run(ort::inputs![tensor]).map_err(|e| e.to_string())?;
+        let (_, values) = outputs[0].try_extract_tensor::<f32>().map_err(|e| e.to_string())?;
+        if values.len() != self.width * self.height {
+            return Err(format!("unexpected mask size {}", values.len()));
+        }
+        Ok(Mask {
+            width: self.width,
+            height: self.height,
+            values: values.to_vec(),
+        })
+    }
+}
+
+/// Built without segmentation support; cannot be constructed.
+#[cfg(not(feature = "background-segmentation"))]
+enum Segmenter {}
+
+#[cfg(not(feature = "background-segmentation"))]
+impl Segmenter {
+    fn load(_path: &std::path::Path) -> Result<Self, String> {
+        Err("vox-media was built without the background-segmentation feature".into())
+    }
+
+    fn mask(&mut self, _rgb: &[u8], _width: usize, _height: usize) -> Result<Mask, String> {
+        match *self {}
+    }
+}
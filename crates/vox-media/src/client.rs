@@ -0,0 +1,645 @@
+//! The media client as a plain Rust API.
+//!
+//! [`MediaClient`] owns the background runtime that the Python
+//! `VoxMediaClient` wraps: commands go to the media loop over a bounded
+//! queue, and events, decoded video and data messages come back through
+//! queues that the caller polls.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use vox_core::key_sink::KeySink;
+use vox_core::session::ReceiveStatsSnapshot;
+use vox_core::settings::MediaSettings;
+use vox_core::sframe::KeyRing;
+use vox_core::ratelimit::RateLimits;
+use vox_core::validate::ValidationConfig;
+
+use crate::{
+    audio, background, gpu, proxy, push_event, quic, state, transform, video, DataMessage, DataQueue, EventQueue,
+    MediaCommand, MediaEvent, MediaKeyRing, UserStatsMap, VideoFrameOutput, VideoFrameQueue, VideoFrames,
+};
+
+/// Commands queued for the runtime before `send_cmd` fails.
+const COMMAND_QUEUE_CAPACITY: usize = 256;
+
+/// Name of the media runtime thread; panics there are reported as `fatal_error`.
+const RUNTIME_THREAD_NAME: &str = "vox-media-runtime";
+
+thread_local! {
+    /// Location and backtrace of the last panic on this thread.
+    static PANIC_DETAILS: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Chain a panic hook that records where the runtime thread panicked, since
+/// the payload caught by `catch_unwind` only carries the message.
+fn install_panic_hook() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if std::thread::current().name() == Some(RUNTIME_THREAD_NAME) {
+                let location = info.location().map_or_else(|| "<unknown>".into(), |l| l.to_string());
+                let backtrace = std::backtrace::Backtrace::force_capture();
+                PANIC_DETAILS.with(|d| *d.borrow_mut() = Some(format!("at {location}\n{backtrace}")));
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Build the `fatal_error` message for a panic caught on the runtime thread.
+fn panic_report(payload: Box<dyn std::any::Any + Send>) -> String {
+    let msg = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".into());
+    match PANIC_DETAILS.with(|d| d.borrow_mut().take()) {
+        Some(details) => format!("{msg} {details}"),
+        None => msg,
+    }
+}
+
+/// Error from a [`MediaClient`] call. The Python layer raises `ValueError`
+/// for `InvalidArgument` and `RuntimeError` for the rest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientError {
+    /// `start` has not been called, or `stop` has.
+    NotStarted,
+    /// `start` was called while the runtime is running.
+    AlreadyRunning,
+    /// The command queue is full; the runtime is not keeping up.
+    QueueFull,
+    /// The runtime thread has exited; a `FatalError` event says why.
+    RuntimeDead,
+    /// An argument was out of range.
+    InvalidArgument(String),
+    /// Anything else, e.g. the runtime thread could not be spawned.
+    Failed(String),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::NotStarted => f.write_str("Media client not started"),
+            ClientError::AlreadyRunning => f.write_str("Media client is already running"),
+            ClientError::QueueFull => f.write_str("Media command queue is full; the runtime is not keeping up"),
+            ClientError::RuntimeDead => {
+                f.write_str("Media runtime is dead (see the fatal_error event); call start() again")
+            }
+            ClientError::InvalidArgument(msg) | ClientError::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Everything needed to join a room; see `VoxMediaClient.connect` for what
+/// each option does.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub url: String,
+    pub token: String,
+    pub room_id: u32,
+    pub user_id: u32,
+    /// Server certificate pins; empty verifies against Mozilla's root CAs.
+    pub pins: quic::CertPins,
+    /// Client certificate for mutual TLS.
+    pub client_identity: Option<quic::ClientIdentity>,
+    pub idle_timeout_secs: u64,
+    pub datagram_buffer_size: usize,
+    /// Audio devices; `None` uses the settings database, then the default.
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub transport: quic::TransportPreference,
+    pub bind: quic::BindOptions,
+    pub proxy: Option<proxy::ProxyConfig>,
+    pub tuning: quic::TransportTuning,
+    /// Drop datagrams that fail strict validation, reporting them as
+    /// `MalformedPackets`; `None` accepts anything with a header.
+    pub validation: Option<ValidationConfig>,
+    /// Limits on what each sender and all of them together may send us,
+    /// with drops reported as `RateLimited`.
+    pub rate_limits: RateLimits,
+}
+
+impl ConnectOptions {
+    /// Options with the same defaults as `VoxMediaClient.connect`.
+    pub fn new(url: impl Into<String>, token: impl Into<String>, room_id: u32, user_id: u32) -> Self {
+        ConnectOptions {
+            url: url.into(),
+            token: token.into(),
+            room_id,
+            user_id,
+            pins: quic::CertPins::default(),
+            client_identity: None,
+            idle_timeout_secs: 30,
+            datagram_buffer_size: 65535,
+            input_device: None,
+            output_device: None,
+            transport: quic::TransportPreference::Auto,
+            bind: quic::BindOptions::default(),
+            proxy: None,
+            tuning: quic::TransportTuning::default(),
+            validation: None,
+            rate_limits: RateLimits::default(),
+        }
+    }
+}
+
+/// Video capture and encoding parameters for [`MediaClient::set_video_config`].
+#[derive(Debug, Clone)]
+pub struct VideoConfig {
+    /// Camera size before rotation; frames are encoded at exactly this size.
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub bitrate_kbps: u32,
+    pub rotation: video::Rotation,
+    /// Flip only the local preview.
+    pub mirror_preview: bool,
+    pub scale_mode: video::ScaleMode,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            width: 640,
+            height: 480,
+            fps: 30,
+            bitrate_kbps: 500,
+            rotation: video::Rotation::None,
+            mirror_preview: false,
+            scale_mode: video::ScaleMode::Crop,
+        }
+    }
+}
+
+/// Client-side media transport for Vox voice/video rooms.
+///
+/// Runs a background tokio runtime that manages QUIC transport to the SFU,
+/// Opus encoding/decoding, and cpal audio capture/playback.
+pub struct MediaClient {
+    cmd_tx: Option<mpsc::Sender<MediaCommand>>,
+    cancel: Option<CancellationToken>,
+    rt_handle: Option<std::thread::JoinHandle<()>>,
+    events: EventQueue,
+    video_frames: VideoFrameQueue,
+    data_messages: DataQueue,
+    media_keys: MediaKeyRing,
+    /// Bumped whenever the key source changes; MLS key sinks handed out
+    /// under an older value stop delivering.
+    key_source: Arc<AtomicU64>,
+    frame_transform: transform::SharedTransform,
+    user_stats: UserStatsMap,
+    audio_stats: Arc<audio::AudioStats>,
+    /// Source of correlation ids for acknowledged commands.
+    command_ids: AtomicU64,
+    muted: bool,
+    deafened: bool,
+    video: bool,
+}
+
+impl Default for MediaClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MediaClient {
+    pub fn new() -> Self {
+        MediaClient {
+            cmd_tx: None,
+            command_ids: AtomicU64::new(1),
+            cancel: None,
+            rt_handle: None,
+            events: Arc::new(Mutex::new(VecDeque::new())),
+            video_frames: Arc::new(VideoFrames::default()),
+            data_messages: Arc::new(Mutex::new(VecDeque::new())),
+            media_keys: Arc::new(Mutex::new(KeyRing::new())),
+            key_source: Arc::new(AtomicU64::new(0)),
+            frame_transform: Arc::new(Mutex::new(None)),
+            user_stats: Arc::new(Mutex::new(HashMap::new())),
+            audio_stats: Arc::new(audio::AudioStats::default()),
+            muted: false,
+            deafened: false,
+            video: false,
+        }
+    }
+
+    /// Whether `start` has been called without a matching `stop`. Stays
+    /// true after the runtime died until the next `start` or `stop`.
+    pub fn is_started(&self) -> bool {
+        self.cancel.is_some()
+    }
+
+    /// Whether the runtime thread is alive.
+    pub fn is_running(&self) -> bool {
+        self.cancel.is_some() && !self.rt_handle.as_ref().is_some_and(|h| h.is_finished())
+    }
+
+    /// Start the background media runtime. `settings` apply to every
+    /// session it runs. May be called again after the runtime died.
+    pub fn start(&mut self, settings: MediaSettings) -> Result<(), ClientError> {
+        if self.is_running() {
+            return Err(ClientError::AlreadyRunning);
+        }
+        if self.cancel.is_some() {
+            // The previous runtime died; clear it out before restarting
+            self.cancel = None;
+            self.cmd_tx = None;
+            if let Some(handle) = self.rt_handle.take() {
+                let _ = handle.join();
+            }
+        }
+
+        install_panic_hook();
+        let cancel = CancellationToken::new();
+        let cancel_thread = cancel.clone();
+        let (cmd_tx, cmd_rx) = mpsc::channel(COMMAND_QUEUE_CAPACITY);
+
+        let events = self.events.clone();
+        let events_thread = self.events.clone();
+        let video_frames = self.video_frames.clone();
+        let data_messages = self.data_messages.clone();
+        let media_keys = self.media_keys.clone();
+        let frame_transform = self.frame_transform.clone();
+        let user_stats = self.user_stats.clone();
+        let audio_stats = self.audio_stats.clone();
+        let cancel_loop = cancel.clone();
+        let spawned = std::thread::Builder::new().name(RUNTIME_THREAD_NAME.into()).spawn(move || {
+            let rt = match tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    push_event(&events_thread, MediaEvent::FatalError(format!("Failed to create runtime: {e}")));
+                    return;
+                }
+            };
+            // A panic anywhere in the media loop must not leave a silently
+            // dead thread behind: report it so the caller can tear down.
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                rt.block_on(async move {
+                    state::run_media_loop(cmd_rx, cancel_loop, events, video_frames, data_messages, media_keys, frame_transform, user_stats, audio_stats, settings).await;
+                });
+            }));
+            match result {
+                Err(payload) => {
+                    let msg = panic_report(payload);
+                    tracing::error!("Media runtime panicked: {msg}");
+                    push_event(&events_thread, MediaEvent::FatalError(msg));
+                }
+                Ok(()) if !cancel_thread.is_cancelled() => {
+                    tracing::error!("Media loop exited without stop()");
+                    push_event(&events_thread, MediaEvent::FatalError("media loop exited unexpectedly".into()));
+                }
+                Ok(()) => {}
+            }
+        });
+        let handle =
+            spawned.map_err(|e| ClientError::Failed(format!("Failed to spawn media runtime thread: {e}")))?;
+
+        self.cancel = Some(cancel);
+        self.cmd_tx = Some(cmd_tx);
+        self.rt_handle = Some(handle);
+        Ok(())
+    }
+
+    /// Stop the runtime, leaving an active session gracefully first. Blocks
+    /// for at most about two seconds. Unread events are kept.
+    pub fn stop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            cancel.cancel();
+        }
+        self.cmd_tx = None;
+        if let Some(handle) = self.rt_handle.take() {
+            let _ = handle.join();
+        }
+        // Session state died with the runtime; a restart begins from scratch
+        self.muted = false;
+        self.deafened = false;
+        self.video = false;
+        if let Ok(mut stats) = self.user_stats.lock() {
+            stats.clear();
+        }
+    }
+
+    /// Connect to a voice room. Returns the command id echoed in the
+    /// `CommandResult` event once the attempt finishes.
+    pub fn connect(&self, options: ConnectOptions) -> Result<u64, ClientError> {
+        self.send_connect(options, None)
+    }
+
+    /// `connect`, additionally sending `None` once connected or the failure
+    /// reason on `reply`.
+    pub(crate) fn send_connect(
+        &self,
+        options: ConnectOptions,
+        reply: Option<std::sync::mpsc::SyncSender<Option<String>>>,
+    ) -> Result<u64, ClientError> {
+        let command_id = self.next_command_id();
+        self.send_cmd(MediaCommand::Connect {
+            url: options.url,
+            token: options.token,
+            room_id: options.room_id,
+            user_id: options.user_id,
+            pins: options.pins,
+            client_identity: options.client_identity,
+            idle_timeout_secs: options.idle_timeout_secs,
+            datagram_buffer_size: options.datagram_buffer_size,
+            input_device: options.input_device,
+            output_device: options.output_device,
+            transport: options.transport,
+            bind: options.bind,
+            proxy: options.proxy,
+            tuning: options.tuning,
+            validation: options.validation,
+            rate_limits: options.rate_limits,
+            command_id,
+            reply,
+        })?;
+        Ok(command_id)
+    }
+
+    /// Leave the current room, flushing pending media first.
+    pub fn disconnect(&self) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::Disconnect)
+    }
+
+    pub fn set_mute(&mut self, muted: bool) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetMute(muted))?;
+        self.muted = muted;
+        Ok(())
+    }
+
+    pub fn set_deaf(&mut self, deafened: bool) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetDeaf(deafened))?;
+        self.deafened = deafened;
+        Ok(())
+    }
+
+    /// Enable or disable video. Returns a command id; the `CommandResult`
+    /// event reports whether the camera and encoder started.
+    pub fn set_video(&mut self, enabled: bool) -> Result<u64, ClientError> {
+        let command_id = self.next_command_id();
+        self.send_cmd(MediaCommand::SetVideo { enabled, command_id })?;
+        self.video = enabled;
+        Ok(command_id)
+    }
+
+    /// Configure video capture. Must be called before `set_video(true)`.
+    pub fn set_video_config(&self, config: VideoConfig) -> Result<(), ClientError> {
+        if config.width == 0 || config.height == 0 {
+            return Err(ClientError::InvalidArgument("width and height must be positive".into()));
+        }
+        self.send_cmd(MediaCommand::SetVideoConfig {
+            width: config.width,
+            height: config.height,
+            fps: config.fps,
+            bitrate_kbps: config.bitrate_kbps,
+            rotation: config.rotation,
+            mirror_preview: config.mirror_preview,
+            scale_mode: config.scale_mode,
+        })
+    }
+
+    /// Blur or replace the background of outgoing video, or `None` to stop.
+    pub fn set_background(&self, stage: Option<background::BackgroundStage>) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetBackground(stage))
+    }
+
+    /// Composite an image onto outgoing video, or `None` to remove it.
+    pub fn set_video_overlay(&self, overlay: Option<video::Overlay>) -> Result<(), ClientError> {
+        if let Some(o) = &overlay {
+            if o.width == 0 || o.height == 0 || o.rgba.len() != o.width * o.height * 4 {
+                return Err(ClientError::InvalidArgument(
+                    "image must be non-empty RGBA with width * height * 4 bytes".into(),
+                ));
+            }
+            if !(0.0..=1.0).contains(&o.opacity) {
+                return Err(ClientError::InvalidArgument("opacity must be between 0.0 and 1.0".into()));
+            }
+        }
+        self.send_cmd(MediaCommand::SetOverlay(overlay))
+    }
+
+    /// Ask the SFU to forward fewer or smaller streams.
+    pub fn set_receive_preferences(&self, preferences: quic::ReceivePreferences) -> Result<(), ClientError> {
+        if preferences.max_resolution.is_some_and(|(w, h)| w == 0 || h == 0) {
+            return Err(ClientError::InvalidArgument(
+                "max_resolution must be a positive (width, height)".into(),
+            ));
+        }
+        self.send_cmd(MediaCommand::SetReceivePreferences(preferences))
+    }
+
+    /// Cap total outgoing media bitrate in kbit/s; `None` or 0 removes the cap.
+    pub fn set_max_uplink_kbps(&self, kbps: Option<u32>) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetMaxUplink(kbps.filter(|&k| k > 0)))
+    }
+
+    pub fn set_input_volume(&self, volume: f32) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetInputVolume(volume))
+    }
+
+    pub fn set_output_volume(&self, volume: f32) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetOutputVolume(volume))
+    }
+
+    pub fn set_noise_gate(&self, threshold: f32) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetNoiseGate(threshold))
+    }
+
+    pub fn set_decoder_idle_timeout(&self, timeout: Duration) -> Result<(), ClientError> {
+        if timeout.is_zero() {
+            return Err(ClientError::InvalidArgument(
+                "Decoder idle timeout must be a positive number of seconds".into(),
+            ));
+        }
+        self.send_cmd(MediaCommand::SetDecoderIdleTimeout(timeout))
+    }
+
+    /// Free a remote user's decoders, partial frames and volume state.
+    pub fn drop_user(&self, user_id: u32) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::DropUser(user_id))
+    }
+
+    pub fn set_user_volume(&self, user_id: u32, volume: f32) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetUserVolume { user_id, volume })
+    }
+
+    /// Attenuate remote playback while the local user speaks; `None` turns
+    /// ducking off.
+    pub fn set_voice_ducking(&self, config: Option<audio::DuckingConfig>) -> Result<(), ClientError> {
+        if config.as_ref().is_some_and(|c| !(0.0..=1.0).contains(&c.level)) {
+            return Err(ClientError::InvalidArgument("Ducking level must be between 0.0 and 1.0".into()));
+        }
+        self.send_cmd(MediaCommand::SetDucking(config))
+    }
+
+    pub fn set_comfort_noise(&self, enabled: bool) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SetComfortNoise(enabled))
+    }
+
+    /// Set this client's playback priority among all clients in the process.
+    pub fn set_mix_priority(&self, priority: i32, attenuation: f32) -> Result<(), ClientError> {
+        if !(0.0..=1.0).contains(&attenuation) {
+            return Err(ClientError::InvalidArgument("Attenuation must be between 0.0 and 1.0".into()));
+        }
+        self.send_cmd(MediaCommand::SetMixPriority { priority, attenuation })
+    }
+
+    /// Send application data on a data channel; failures are reported as
+    /// `DataError` events.
+    pub fn send_data(&self, channel_id: u16, data: Vec<u8>, reliable: bool) -> Result<(), ClientError> {
+        self.send_cmd(MediaCommand::SendData {
+            channel_id,
+            data,
+            reliable,
+        })
+    }
+
+    /// Install an end-to-end media key and make it the active send key.
+    pub fn set_media_key(&self, key_id: u64, key: Vec<u8>) -> Result<(), ClientError> {
+        if key.is_empty() {
+            return Err(ClientError::InvalidArgument("Media key must not be empty".into()));
+        }
+        self.media_keys
+            .lock()
+            .map_err(|_| ClientError::Failed("Media key ring poisoned".into()))?
+            .set_key(key_id, key);
+        Ok(())
+    }
+
+    /// A sink for an MLS group's media keys, e.g. for
+    /// `vox_mls::MlsEngine::attach_media_key_sink`. Replaces keys from any
+    /// earlier source; sinks handed out before stop delivering.
+    pub fn media_key_sink(&self) -> Result<KeySink, ClientError> {
+        let generation = self.key_source.fetch_add(1, Ordering::SeqCst) + 1;
+        self.clear_keys()?;
+        let source = self.key_source.clone();
+        let ring = Arc::downgrade(&self.media_keys);
+        Ok(KeySink::new(move |key_id, key| {
+            if source.load(Ordering::SeqCst) != generation {
+                return false;
+            }
+            let Some(ring) = ring.upgrade() else {
+                return false;
+            };
+            if let Ok(mut ring) = ring.lock() {
+                ring.set_key(key_id, key.to_vec());
+            }
+            true
+        }))
+    }
+
+    /// Remove all media keys and detach any key sink.
+    pub fn clear_media_keys(&self) -> Result<(), ClientError> {
+        self.key_source.fetch_add(1, Ordering::SeqCst);
+        self.clear_keys()
+    }
+
+    /// Register a transform invoked on every encoded frame, or `None` to
+    /// remove it. Returns the previous transform.
+    pub fn set_frame_transform(
+        &self,
+        transform: Option<Box<dyn transform::FrameTransform>>,
+    ) -> Result<Option<Box<dyn transform::FrameTransform>>, ClientError> {
+        self.frame_transform
+            .lock()
+            .map(|mut guard| std::mem::replace(&mut *guard, transform))
+            .map_err(|_| ClientError::Failed("Frame transform slot poisoned".into()))
+    }
+
+    /// The next event from the runtime, if any.
+    pub fn poll_event(&self) -> Option<MediaEvent> {
+        self.events.lock().ok()?.pop_front()
+    }
+
+    /// The next decoded video frame; `user_id` 0 is the local preview.
+    pub fn poll_video_frame(&self) -> Option<Arc<VideoFrameOutput>> {
+        self.video_frames.pop()
+    }
+
+    /// The latest frame of `user_id` no older than `max_age`, waiting up to
+    /// `wait` for one to arrive.
+    pub fn latest_video_frame(&self, user_id: u32, max_age: Duration, wait: Duration) -> Option<Arc<VideoFrameOutput>> {
+        self.video_frames.latest(user_id, max_age, wait)
+    }
+
+    /// The next received data channel message, if any.
+    pub fn poll_data(&self) -> Option<DataMessage> {
+        self.data_messages.lock().ok()?.pop_front()
+    }
+
+    /// Receive statistics for one remote user, if media arrived recently.
+    pub fn user_stats(&self, user_id: u32) -> Option<ReceiveStatsSnapshot> {
+        self.user_stats.lock().ok()?.get(&user_id).cloned()
+    }
+
+    /// Receive statistics for all recently active remote users.
+    pub fn all_user_stats(&self) -> Vec<(u32, ReceiveStatsSnapshot)> {
+        match self.user_stats.lock() {
+            Ok(map) => map.iter().map(|(&uid, s)| (uid, s.clone())).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Local audio glitch counters for the current session.
+    pub fn audio_stats(&self) -> audio::AudioStatsSnapshot {
+        self.audio_stats.snapshot()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn is_deafened(&self) -> bool {
+        self.deafened
+    }
+
+    pub fn is_video_enabled(&self) -> bool {
+        self.video
+    }
+
+    /// Whether end-to-end media encryption is active.
+    pub fn is_e2ee_enabled(&self) -> bool {
+        self.media_keys.lock().is_ok_and(|k| k.is_enabled())
+    }
+
+    /// Allow or forbid GPU video color conversion and scaling (process-wide,
+    /// on by default). No effect without the `gpu` feature.
+    pub fn set_gpu_acceleration(enabled: bool) {
+        gpu::set_enabled(enabled);
+    }
+
+    /// Whether video conversion currently runs on the GPU.
+    pub fn gpu_acceleration_available() -> bool {
+        gpu::available()
+    }
+
+    fn clear_keys(&self) -> Result<(), ClientError> {
+        self.media_keys
+            .lock()
+            .map_err(|_| ClientError::Failed("Media key ring poisoned".into()))?
+            .clear();
+        Ok(())
+    }
+
+    fn next_command_id(&self) -> u64 {
+        self.command_ids.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn send_cmd(&self, cmd: MediaCommand) -> Result<(), ClientError> {
+        match &self.cmd_tx {
+            Some(tx) => tx.try_send(cmd).map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => ClientError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => ClientError::RuntimeDead,
+            }),
+            None => Err(ClientError::NotStarted),
+        }
+    }
+}
@@ -1,8 +1,11 @@
 //! Opus and AV1 codec encode/decode wrappers.
 
 use bytes::Bytes;
+#[cfg(feature = "av1-encoder")]
 use rav1e::prelude::*;
 
+use crate::profile;
+
 /// Opus encoder wrapper.
 pub struct OpusEncoder {
     inner: opus::Encoder,
@@ -24,6 +27,7 @@ impl OpusEncoder {
     /// Returns `(opus_bytes, is_dtx)` where `is_dtx` is true when the encoder
     /// produced a DTX comfort-noise frame (payload <= 2 bytes).
     pub fn encode(&mut self, pcm: &[i16]) -> Result<(Bytes, bool), opus::Error> {
+        let _timer = profile::OPUS_ENCODE.start();
         let mut output = vec![0u8; 4000]; // max opus frame
         let len = self.inner.encode(pcm, &mut output)?;
         output.truncate(len);
@@ -31,6 +35,15 @@ impl OpusEncoder {
         Ok((Bytes::from(output), is_dtx))
     }
 
+    /// Set the target bitrate in kbit/s, or `None` to let libopus choose.
+    pub fn set_bitrate(&mut self, kbps: Option<u32>) -> Result<(), opus::Error> {
+        let bitrate = match kbps {
+            Some(k) => opus::Bitrate::Bits((k * 1000) as i32),
+            None => opus::Bitrate::Auto,
+        };
+        self.inner.set_bitrate(bitrate)
+    }
+
     pub fn frame_size(&self) -> usize {
         self.frame_size
     }
@@ -54,12 +67,18 @@ impl OpusDecoder {
 
     /// Decode an Opus frame to PCM i16 samples.
     pub fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>, opus::Error> {
+        let _timer = profile::OPUS_DECODE.start();
         let mut output = vec![0i16; self.frame_size];
         let len = self.inner.decode(data, &mut output, false)?;
         output.truncate(len);
         Ok(output)
     }
 
+    /// Synthesize one frame for a lost packet (Opus packet loss concealment).
+    pub fn conceal(&mut self) -> Result<Vec<i16>, opus::Error> {
+        self.decode(&[])
+    }
+
     pub fn frame_size(&self) -> usize {
         self.frame_size
     }
@@ -70,6 +89,7 @@ impl OpusDecoder {
 // ---------------------------------------------------------------------------
 
 /// An encoded AV1 packet from the encoder.
+#[cfg_attr(not(feature = "av1-encoder"), allow(dead_code))]
 pub struct EncodedPacket {
     pub data: Vec<u8>,
     pub is_keyframe: bool,
@@ -77,6 +97,7 @@ pub struct EncodedPacket {
 }
 
 /// AV1 encoder using rav1e with low-latency settings.
+#[cfg(feature = "av1-encoder")]
 pub struct Av1Encoder {
     ctx: Context<u8>,
     width: usize,
@@ -84,6 +105,7 @@ pub struct Av1Encoder {
     frame_count: u64,
 }
 
+#[cfg(feature = "av1-encoder")]
 impl Av1Encoder {
     /// Create a new AV1 encoder.
     ///
@@ -124,6 +146,7 @@ impl Av1Encoder {
     /// - Y: width * height
     /// - U, V: (width/2) * (height/2)
     pub fn encode(&mut self, y: &[u8], u: &[u8], v: &[u8]) -> Result<Vec<EncodedPacket>, String> {
+        let _timer = profile::AV1_ENCODE.start();
         let mut frame = self.ctx.new_frame();
 
         frame.planes[0].copy_from_raw_u8(y, self.width, 1);
@@ -163,11 +186,31 @@ impl Av1Encoder {
     }
 }
 
+/// Stand-in for builds without the `av1-encoder` feature; never constructed.
+#[cfg(not(feature = "av1-encoder"))]
+pub enum Av1Encoder {}
+
+#[cfg(not(feature = "av1-encoder"))]
+impl Av1Encoder {
+    pub fn new(_width: usize, _height: usize, _fps: u32, _bitrate_kbps: u32) -> Result<Self, String> {
+        Err("vox-media was built without the av1-encoder feature".into())
+    }
+
+    pub fn encode(&mut self, _y: &[u8], _u: &[u8], _v: &[u8]) -> Result<Vec<EncodedPacket>, String> {
+        match *self {}
+    }
+
+    pub fn flush(&mut self) -> Result<Vec<EncodedPacket>, String> {
+        match *self {}
+    }
+}
+
 // ---------------------------------------------------------------------------
 // AV1 decoder (dav1d)
 // ---------------------------------------------------------------------------
 
 /// A decoded video frame (RGBA).
+#[cfg_attr(not(feature = "av1-decoder"), allow(dead_code))]
 pub struct DecodedFrame {
     pub width: u32,
     pub height: u32,
@@ -175,10 +218,12 @@ pub struct DecodedFrame {
 }
 
 /// AV1 decoder using dav1d.
+#[cfg(feature = "av1-decoder")]
 pub struct Av1Decoder {
     decoder: dav1d::Decoder,
 }
 
+#[cfg(feature = "av1-decoder")]
 impl Av1Decoder {
     /// Create a new AV1 decoder with 2 threads and minimal frame delay.
     pub fn new() -> Result<Self, String> {
@@ -193,6 +238,7 @@ impl Av1Decoder {
 
     /// Feed encoded AV1 data and try to get a decoded frame.
     pub fn decode(&mut self, data: &[u8]) -> Result<Option<DecodedFrame>, String> {
+        let _timer = profile::AV1_DECODE.start();
         self.decoder
             .send_data(data.to_vec(), None, None, None)
             .map_err(|e| format!("dav1d send_data: {e}"))?;
@@ -214,7 +260,23 @@ impl Av1Decoder {
     }
 }
 
+/// Stand-in for builds without the `av1-decoder` feature; never constructed.
+#[cfg(not(feature = "av1-decoder"))]
+pub enum Av1Decoder {}
+
+#[cfg(not(feature = "av1-decoder"))]
+impl Av1Decoder {
+    pub fn new() -> Result<Self, String> {
+        Err("vox-media was built without the av1-decoder feature".into())
+    }
+
+    pub fn decode(&mut self, _data: &[u8]) -> Result<Option<DecodedFrame>, String> {
+        match *self {}
+    }
+}
+
 /// Convert a dav1d I420 picture to RGBA.
+#[cfg(feature = "av1-decoder")]
 fn yuv_picture_to_rgba(pic: &dav1d::Picture, w: u32, h: u32) -> Vec<u8> {
     use dav1d::PlanarImageComponent;
 
@@ -228,6 +290,13 @@ fn yuv_picture_to_rgba(pic: &dav1d::Picture, w: u32, h: u32) -> Vec<u8> {
 
     let w = w as usize;
     let h = h as usize;
+    if let Some(rgba) = crate::gpu::i420_to_rgba(
+        [(&y_plane[..], y_stride), (&u_plane[..], u_stride), (&v_plane[..], v_stride)],
+        w,
+        h,
+    ) {
+        return rgba;
+    }
     let mut rgba = vec![255u8; w * h * 4];
 
     for row in 0..h {
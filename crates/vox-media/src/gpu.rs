@@ -0,0 +1,440 @@
+//! Optional GPU path for video color conversion and scaling.
+//!
+//! With the `gpu` feature, RGB scaling, RGB→I420 for outgoing frames and
+//! I420→RGBA for decoded frames run as wgpu compute shaders. Every function
+//! returns `None` when the GPU path is disabled, unavailable or fails, and
+//! the caller falls back to its CPU implementation.
+
+use crate::video::ScaleMode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Switched off by `set_enabled(false)` or after a GPU error.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Allow or forbid the GPU path for all clients in the process.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether conversions currently run on the GPU. Initializes the device
+/// on first call.
+pub fn available() -> bool {
+    imp::device().is_some()
+}
+
+/// GPU counterpart of `video::scale_rgb`.
+pub fn scale_rgb(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    out_w: usize,
+    out_h: usize,
+    mode: ScaleMode,
+) -> Option<Vec<u8>> {
+    let gpu = imp::device()?;
+    imp::checked(gpu.scale_rgb(rgb, width, height, out_w, out_h, mode))
+}
+
+/// GPU counterpart of `video::rgb_to_i420`.
+pub fn rgb_to_i420(rgb: &[u8], width: usize, height: usize) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let gpu = imp::device()?;
+    imp::checked(gpu.rgb_to_i420(rgb, width, height))
+}
+
+/// Convert I420 planes, each given with its row stride, to RGBA.
+#[cfg_attr(not(feature = "av1-decoder"), allow(dead_code))]
+pub fn i420_to_rgba(planes: [(&[u8], usize); 3], width: usize, height: usize) -> Option<Vec<u8>> {
+    let gpu = imp::device()?;
+    imp::checked(gpu.i420_to_rgba(planes, width, height))
+}
+
+#[cfg(feature = "gpu")]
+mod imp {
+    use super::{ScaleMode, ENABLED};
+    use crate::video;
+    use std::sync::atomic::Ordering;
+    use std::sync::OnceLock;
+    use wgpu::util::DeviceExt;
+
+    const WORKGROUP_SIZE: usize = 256;
+
+    const SHADER: &str = r#"
+struct Params {
+    src_w: u32, src_h: u32, dst_w: u32, dst_h: u32,
+    crop_x: f32, crop_y: f32, crop_w: f32, crop_h: f32,
+    out_words: u32, _p0: u32, _p1: u32, _p2: u32,
+}
+
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> src: array<u32>;
+@group(0) @binding(2) var<storage, read_write> dst: array<u32>;
+
+fn src_byte(i: u32) -> f32 {
+    return f32((src[i >> 2u] >> ((i & 3u) * 8u)) & 0xffu);
+}
+
+fn sample_rgb(x: u32, y: u32, c: u32) -> f32 {
+    let fx = clamp(p.crop_x + (f32(x) + 0.5) * p.crop_w / f32(p.dst_w) - 0.5, 0.0, f32(p.src_w - 1u));
+    let fy = clamp(p.crop_y + (f32(y) + 0.5) * p.crop_h / f32(p.dst_h) - 0.5, 0.0, f32(p.src_h - 1u));
+    let x0 = u32(fx);
+    let y0 = u32(fy);
+    let x1 = min(x0 + 1u, p.src_w - 1u);
+    let y1 = min(y0 + 1u, p.src_h - 1u);
+    let upper = mix(src_byte((y0 * p.src_w + x0) * 3u + c), src_byte((y0 * p.src_w + x1) * 3u + c), fract(fx));
+    let lower = mix(src_byte((y1 * p.src_w + x0) * 3u + c), src_byte((y1 * p.src_w + x1) * 3u + c), fract(fx));
+    return mix(upper, lower, fract(fy));
+}
+
+@compute @workgroup_size(256)
+fn scale_rgb(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.out_words) { return; }
+    let total = p.dst_w * p.dst_h * 3u;
+    var word = 0u;
+    for (var k = 0u; k < 4u; k++) {
+        let i = id.x * 4u + k;
+        if (i < total) {
+            let px = i / 3u;
+            let v = u32(round(sample_rgb(px % p.dst_w, px / p.dst_w, i % 3u)));
+            word |= min(v, 255u) << (k * 8u);
+        }
+    }
+    dst[id.x] = word;
+}
+
+fn rgb_at(x: u32, y: u32) -> vec3<f32> {
+    let i = (y * p.dst_w + x) * 3u;
+    return vec3<f32>(src_byte(i), src_byte(i + 1u), src_byte(i + 2u));
+}
+
+fn i420_byte(i: u32) -> u32 {
+    let y_size = p.dst_w * p.dst_h;
+    if (i < y_size) {
+        let c = rgb_at(i % p.dst_w, i / p.dst_w);
+        return u32(clamp(0.299 * c.r + 0.587 * c.g + 0.114 * c.b, 0.0, 255.0));
+    }
+    let cw = p.dst_w / 2u;
+    let c_size = cw * (p.dst_h / 2u);
+    let j = i - y_size;
+    let k = j % c_size;
+    let c = rgb_at((k % cw) * 2u, (k / cw) * 2u);
+    if (j < c_size) {
+        return u32(clamp(-0.169 * c.r - 0.331 * c.g + 0.5 * c.b + 128.0, 0.0, 255.0));
+    }
+    return u32(clamp(0.5 * c.r - 0.419 * c.g - 0.081 * c.b + 128.0, 0.0, 255.0));
+}
+
+@compute @workgroup_size(256)
+fn rgb_to_i420(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.out_words) { return; }
+    let total = p.dst_w * p.dst_h + 2u * (p.dst_w / 2u) * (p.dst_h / 2u);
+    var word = 0u;
+    for (var k = 0u; k < 4u; k++) {
+        let i = id.x * 4u + k;
+        if (i < total) {
+            word |= i420_byte(i) << (k * 8u);
+        }
+    }
+    dst[id.x] = word;
+}
+
+@compute @workgroup_size(256)
+fn i420_to_rgba(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= p.out_words) { return; }
+    let x = i % p.src_w;
+    let y = i / p.src_w;
+    let cw = (p.src_w + 1u) / 2u;
+    let c_size = cw * ((p.src_h + 1u) / 2u);
+    let y_size = p.src_w * p.src_h;
+    let ci = (y / 2u) * cw + x / 2u;
+    let luma = src_byte(i);
+    let u = src_byte(y_size + ci) - 128.0;
+    let v = src_byte(y_size + c_size + ci) - 128.0;
+    let r = u32(clamp(luma + 1.402 * v, 0.0, 255.0));
+    let g = u32(clamp(luma - 0.344136 * u - 0.714136 * v, 0.0, 255.0));
+    let b = u32(clamp(luma + 1.772 * u, 0.0, 255.0));
+    dst[i] = r | (g << 8u) | (b << 16u) | (255u << 24u);
+}
+"#;
+
+    /// Mirrors `Params` in the shader.
+    struct Params {
+        src: (usize, usize),
+        dst: (usize, usize),
+        crop: (f32, f32, f32, f32),
+        out_words: usize,
+    }
+
+    impl Params {
+        fn to_bytes(&self) -> Vec<u8> {
+            let words = [
+                self.src.0 as u32,
+                self.src.1 as u32,
+                self.dst.0 as u32,
+                self.dst.1 as u32,
+                self.crop.0.to_bits(),
+                self.crop.1.to_bits(),
+                self.crop.2.to_bits(),
+                self.crop.3.to_bits(),
+                self.out_words as u32,
+                0,
+                0,
+                0,
+            ];
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        }
+    }
+
+    pub struct Gpu {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        layout: wgpu::BindGroupLayout,
+        scale: wgpu::ComputePipeline,
+        to_i420: wgpu::ComputePipeline,
+        to_rgba: wgpu::ComputePipeline,
+    }
+
+    static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+
+    pub fn device() -> Option<&'static Gpu> {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        GPU.get_or_init(|| match Gpu::new() {
+            Ok(gpu) => Some(gpu),
+            Err(e) => {
+                tracing::info!("GPU video conversion unavailable, using CPU: {e}");
+                None
+            }
+        })
+        .as_ref()
+    }
+
+    /// Disable the GPU path after an error so every frame does not retry.
+    pub fn checked<T>(result: Result<T, String>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(e) => {
+                tracing::warn!("GPU video conversion failed, falling back to CPU: {e}");
+                ENABLED.store(false, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    impl Gpu {
+        fn new() -> Result<Self, String> {
+            let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+            let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            }))
+            .map_err(|e| format!("no adapter: {e}"))?;
+            let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+                .map_err(|e| format!("request_device: {e}"))?;
+            tracing::info!("GPU video conversion on {}", adapter.get_info().name);
+
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("vox-media video"),
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+            let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("vox-media video"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage(1, true),
+                    storage(2, false),
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("vox-media video"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = |entry: &str| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point: Some(entry),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            };
+            Ok(Gpu {
+                scale: pipeline("scale_rgb"),
+                to_i420: pipeline("rgb_to_i420"),
+                to_rgba: pipeline("i420_to_rgba"),
+                device,
+                queue,
+                layout,
+            })
+        }
+
+        pub fn scale_rgb(
+            &self,
+            rgb: &[u8],
+            width: usize,
+            height: usize,
+            out_w: usize,
+            out_h: usize,
+            mode: ScaleMode,
+        ) -> Result<Vec<u8>, String> {
+            let len = out_w * out_h * 3;
+            let params = Params {
+                src: (width, height),
+                dst: (out_w, out_h),
+                crop: video::source_rect(width, height, out_w, out_h, mode),
+                out_words: len.div_ceil(4),
+            };
+            let mut out = self.run(&self.scale, &params, rgb)?;
+            out.truncate(len);
+            Ok(out)
+        }
+
+        pub fn rgb_to_i420(&self, rgb: &[u8], width: usize, height: usize) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), String> {
+            let (y_len, c_len) = (width * height, (width / 2) * (height / 2));
+            let params = Params {
+                src: (width, height),
+                dst: (width, height),
+                crop: (0.0, 0.0, width as f32, height as f32),
+                out_words: (y_len + 2 * c_len).div_ceil(4),
+            };
+            let mut y = self.run(&self.to_i420, &params, rgb)?;
+            let v = y.split_off(y_len + c_len);
+            let u = y.split_off(y_len);
+            Ok((y, u, v[..c_len].to_vec()))
+        }
+
+        pub fn i420_to_rgba(&self, planes: [(&[u8], usize); 3], width: usize, height: usize) -> Result<Vec<u8>, String> {
+            // Pack the planes without row padding, as the shader expects
+            let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+            let mut packed = Vec::with_capacity(width * height + 2 * cw * ch);
+            for (i, (plane, stride)) in planes.into_iter().enumerate() {
+                let (w, h) = if i == 0 { (width, height) } else { (cw, ch) };
+                for row in 0..h {
+                    packed.extend_from_slice(&plane[row * stride..row * stride + w]);
+                }
+            }
+            let params = Params {
+                src: (width, height),
+                dst: (width, height),
+                crop: (0.0, 0.0, width as f32, height as f32),
+                out_words: width * height,
+            };
+            self.run(&self.to_rgba, &params, &packed)
+        }
+
+        /// Dispatch one pass over `params.out_words` output words and read
+        /// the result back.
+        fn run(&self, pipeline: &wgpu::ComputePipeline, params: &Params, input: &[u8]) -> Result<Vec<u8>, String> {
+            let mut padded = input.to_vec();
+            padded.resize(input.len().div_ceil(4).max(1) * 4, 0);
+            let out_size = (params.out_words * 4) as u64;
+
+            let uniform = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &params.to_bytes(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let src = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &padded,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let dst = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: out_size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: out_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: uniform.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 1, resource: src.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 2, resource: dst.as_entire_binding() },
+                ],
+            });
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(params.out_words.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+            }
+            encoder.copy_buffer_to_buffer(&dst, 0, &readback, 0, out_size);
+            self.queue.submit([encoder.finish()]);
+
+            let slice = readback.slice(..);
+            let (tx, rx) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = tx.send(result);
+            });
+            self.device.poll(wgpu::PollType::Wait).map_err(|e| format!("poll: {e}"))?;
+            rx.recv()
+                .map_err(|e| format!("map: {e}"))?
+                .map_err(|e| format!("map: {e}"))?;
+            let data = slice.get_mapped_range().to_vec();
+            readback.unmap();
+            Ok(data)
+        }
+    }
+}
+
+/// Built without the `gpu` feature: everything stays on the CPU.
+#[cfg(not(feature = "gpu"))]
+mod imp {
+    use super::ScaleMode;
+
+    pub enum Gpu {}
+
+    pub fn device() -> Option<&'static Gpu> {
+        None
+    }
+
+    pub fn checked<T>(result: Result<T, String>) -> Option<T> {
+        result.ok()
+    }
+
+    impl Gpu {
+        pub fn scale_rgb(&self, _: &[u8], _: usize, _: usize, _: usize, _: usize, _: ScaleMode) -> Result<Vec<u8>, String> {
+            match *self {}
+        }
+
+        pub fn rgb_to_i420(&self, _: &[u8], _: usize, _: usize) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), String> {
+            match *self {}
+        }
+
+        pub fn i420_to_rgba(&self, _: [(&[u8], usize); 3], _: usize, _: usize) -> Result<Vec<u8>, String> {
+            match *self {}
+        }
+    }
+}
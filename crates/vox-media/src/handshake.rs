@@ -0,0 +1,169 @@
+//! Versioned authentication handshake with the SFU.
+//!
+//! Right after the QUIC connection is established the client opens a
+//! bidirectional stream, writes a single `Hello` message and finishes its
+//! side. The SFU answers with a single response on the same stream, either
+//! accepting the session (with the negotiated protocol version and its own
+//! capabilities) or rejecting it with a reason. Because the exchange is
+//! explicit, an auth rejection is distinguishable from a network failure.
+//!
+//! ```text
+//! Hello (client → SFU), big-endian:
+//! +---------+---------+---------+--------------+-----------+-------+
+//! | version | room_id | user_id | capabilities | token_len | token |
+//! |   u16   |   u32   |   u32   |     u32      |    u16    |  ...  |
+//! +---------+---------+---------+--------------+-----------+-------+
+//!
+//! Response (SFU → client), big-endian:
+//! +---------+--------+--------------+------------+--------+
+//! | version | status | capabilities | reason_len | reason |
+//! |   u16   |   u8   |     u32      |    u16     |  ...   |
+//! +---------+--------+--------------+------------+--------+
+//! ```
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::time::Duration;
+
+/// Highest protocol version this client speaks.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+// Capability bits advertised in the handshake
+pub const CAP_DATAGRAMS: u32 = 1 << 0;
+pub const CAP_STREAM_FALLBACK: u32 = 1 << 1;
+pub const CAP_DATA_CHANNEL: u32 = 1 << 2;
+pub const CAP_SFRAME: u32 = 1 << 3;
+
+const STATUS_ACCEPTED: u8 = 0;
+const STATUS_REJECTED: u8 = 1;
+
+/// How long to wait for the SFU's response before giving up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Upper bound on the response size (header plus reason text).
+const MAX_RESPONSE_LEN: usize = 4096;
+/// Fixed part of the response before the reason text.
+const RESPONSE_HEADER_LEN: usize = 9;
+
+/// The client's opening handshake message.
+pub struct Hello {
+    pub room_id: u32,
+    pub user_id: u32,
+    pub capabilities: u32,
+    pub token: String,
+}
+
+impl Hello {
+    pub fn encode(&self) -> Result<BytesMut, String> {
+        let token = self.token.as_bytes();
+        let token_len = u16::try_from(token.len()).map_err(|_| "auth token too long")?;
+        let mut buf = BytesMut::with_capacity(16 + token.len());
+        buf.put_u16(PROTOCOL_VERSION);
+        buf.put_u32(self.room_id);
+        buf.put_u32(self.user_id);
+        buf.put_u32(self.capabilities);
+        buf.put_u16(token_len);
+        buf.put_slice(token);
+        Ok(buf)
+    }
+}
+
+/// The SFU side of the handshake, for the in-process test SFU.
+#[cfg(feature = "test-sfu")]
+impl Hello {
+    /// Parse a `Hello`, returning the client's protocol version with it.
+    pub fn decode(mut data: &[u8]) -> Option<(u16, Hello)> {
+        if data.len() < 16 {
+            return None;
+        }
+        let version = data.get_u16();
+        let room_id = data.get_u32();
+        let user_id = data.get_u32();
+        let capabilities = data.get_u32();
+        let token_len = data.get_u16() as usize;
+        let token = String::from_utf8(data.get(..token_len)?.to_vec()).ok()?;
+        Some((version, Hello { room_id, user_id, capabilities, token }))
+    }
+}
+
+/// Encode the SFU's response: accepted with its capabilities, or rejected
+/// with a reason.
+#[cfg(feature = "test-sfu")]
+pub fn encode_response(version: u16, result: Result<u32, &str>) -> BytesMut {
+    let (status, capabilities, reason) = match result {
+        Ok(capabilities) => (STATUS_ACCEPTED, capabilities, ""),
+        Err(reason) => (STATUS_REJECTED, 0, reason),
+    };
+    let reason = &reason.as_bytes()[..reason.len().min(MAX_RESPONSE_LEN - RESPONSE_HEADER_LEN)];
+    let mut buf = BytesMut::with_capacity(RESPONSE_HEADER_LEN + reason.len());
+    buf.put_u16(version);
+    buf.put_u8(status);
+    buf.put_u32(capabilities);
+    buf.put_u16(reason.len() as u16);
+    buf.put_slice(reason);
+    buf
+}
+
+/// The SFU accepted the session.
+#[derive(Debug, Clone, Copy)]
+pub struct Accepted {
+    /// Protocol version selected by the SFU.
+    pub version: u16,
+    /// Capabilities supported by the SFU.
+    pub capabilities: u32,
+}
+
+/// The SFU explicitly rejected the session (bad token, wrong room, ...).
+#[derive(Debug)]
+pub struct AuthRejected(pub String);
+
+impl std::fmt::Display for AuthRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "auth rejected: {}", self.0)
+    }
+}
+
+impl std::error::Error for AuthRejected {}
+
+/// Parse the SFU's handshake response.
+fn parse_response(data: &[u8]) -> Result<Accepted, Box<dyn std::error::Error>> {
+    if data.len() < RESPONSE_HEADER_LEN {
+        return Err("handshake response too short".into());
+    }
+    let mut buf = data;
+    let version = buf.get_u16();
+    let status = buf.get_u8();
+    let capabilities = buf.get_u32();
+    let reason_len = buf.get_u16() as usize;
+    let reason = String::from_utf8_lossy(buf.get(..reason_len).ok_or("handshake reason truncated")?);
+
+    match status {
+        STATUS_ACCEPTED => {
+            if version == 0 || version > PROTOCOL_VERSION {
+                return Err(format!("SFU selected unsupported protocol version {version}").into());
+            }
+            Ok(Accepted { version, capabilities })
+        }
+        STATUS_REJECTED => Err(Box::new(AuthRejected(reason.into_owned()))),
+        s => Err(format!("unknown handshake status {s}").into()),
+    }
+}
+
+/// Run the handshake on a fresh bidirectional stream.
+pub async fn perform(
+    connection: &quinn::Connection,
+    hello: &Hello,
+) -> Result<Accepted, Box<dyn std::error::Error>> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, exchange(connection, hello))
+        .await
+        .map_err(|_| "timed out waiting for handshake response")?
+}
+
+async fn exchange(
+    connection: &quinn::Connection,
+    hello: &Hello,
+) -> Result<Accepted, Box<dyn std::error::Error>> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    send.write_all(&hello.encode()?).await?;
+    send.finish()?;
+    let response = recv.read_to_end(MAX_RESPONSE_LEN).await?;
+    parse_response(&response)
+}
@@ -1,59 +1,141 @@
+//! Media transport for Vox voice and video rooms: QUIC to the SFU, Opus
+//! and AV1 codecs, audio devices and camera capture.
+//!
+//! With the default `python` feature this crate is the `vox_media`
+//! extension module. Rust applications can depend on it with
+//! `default-features = false` and drive a [`MediaClient`] directly.
+
 mod audio;
+mod background;
+pub mod client;
 mod codec;
+mod gpu;
+mod handshake;
+#[cfg(feature = "python")]
+mod logging;
+mod profile;
+mod proxy;
+#[cfg(feature = "python")]
+mod python;
 mod quic;
 mod state;
+#[cfg(feature = "test-sfu")]
+pub mod test_sfu;
+mod transform;
 mod video;
 
-use pyo3::prelude::*;
-use pyo3::types::PyBytes;
-use std::collections::VecDeque;
+pub use audio::{
+    fill_playback, measure_input, AudioDeviceConfig, AudioStatsSnapshot, ComfortNoise, Ducker, DuckingConfig,
+    InputLevelReport, MixSource, COMFORT_NOISE_LEVEL,
+};
+pub use background::{Backdrop, BackgroundStage};
+pub use client::{ClientError, ConnectOptions, MediaClient, VideoConfig};
+pub use proxy::ProxyConfig;
+pub use quic::{
+    BindOptions, CertPins, ClientIdentity, CongestionController, LossDetector, LossSample, ReceivePreferences,
+    StreamQueue, TransportPreference, TransportTuning, DSCP_AF41, DSCP_EF, MIN_UDP_PAYLOAD,
+};
+pub use state::{evict_idle, UplinkPlan};
+pub use transform::{FrameInfo, FrameTransform};
+pub use video::{encode_snapshot, Overlay, OverlayAnchor, Rotation, ScaleMode, SnapshotFormat};
+pub use vox_core::session::ReceiveStatsSnapshot;
+pub use vox_core::settings::{MediaSettings, SettingsStore};
+pub use vox_core::ratelimit::{Rate, RateLimits};
+pub use vox_core::validate::ValidationConfig;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
-use tokio_util::sync::CancellationToken;
+use vox_core::sframe::KeyRing;
 
-/// Commands from Python to the media runtime.
+/// Commands to the media runtime.
 enum MediaCommand {
     Connect {
         url: String,
         token: String,
         room_id: u32,
         user_id: u32,
-        cert_der: Option<Vec<u8>>,
+        pins: quic::CertPins,
+        client_identity: Option<quic::ClientIdentity>,
         idle_timeout_secs: u64,
         datagram_buffer_size: usize,
         input_device: Option<String>,
         output_device: Option<String>,
+        transport: quic::TransportPreference,
+        bind: quic::BindOptions,
+        proxy: Option<proxy::ProxyConfig>,
+        tuning: quic::TransportTuning,
+        validation: Option<ValidationConfig>,
+        rate_limits: RateLimits,
+        /// Echoed in the `command_result` event.
+        command_id: u64,
+        /// Receives `None` once connected or the failure reason, for
+        /// `connect_and_wait`.
+        reply: Option<std::sync::mpsc::SyncSender<Option<String>>>,
     },
     Disconnect,
     SetMute(bool),
     SetDeaf(bool),
-    SetVideo(bool),
+    SetVideo { enabled: bool, command_id: u64 },
     SetVideoConfig {
         width: u32,
         height: u32,
         fps: u32,
         bitrate_kbps: u32,
+        rotation: video::Rotation,
+        mirror_preview: bool,
+        scale_mode: video::ScaleMode,
     },
+    SetMaxUplink(Option<u32>),
     SetInputVolume(f32),
     SetOutputVolume(f32),
     SetNoiseGate(f32),
     SetUserVolume { user_id: u32, volume: f32 },
+    SendData { channel_id: u16, data: Vec<u8>, reliable: bool },
+    SetReceivePreferences(quic::ReceivePreferences),
+    SetDecoderIdleTimeout(std::time::Duration),
+    DropUser(u32),
+    SetDucking(Option<audio::DuckingConfig>),
+    SetComfortNoise(bool),
+    SetMixPriority { priority: i32, attenuation: f32 },
+    SetBackground(Option<background::BackgroundStage>),
+    SetOverlay(Option<video::Overlay>),
 }
 
-/// Events emitted by the media runtime for Python consumption.
-enum MediaEvent {
+/// Events emitted by the media runtime, returned by [`MediaClient::poll_event`].
+#[derive(Debug, Clone)]
+pub enum MediaEvent {
     Connected,
     Disconnected(String),
     ConnectFailed(String),
     Reconnecting { attempt: u32, delay_secs: u64 },
     AudioError(String),
     VideoError(String),
+    TransportChanged(String),
+    AuthAccepted { version: u16, capabilities: u32 },
+    AuthRejected(String),
+    DataError(String),
     SpeakingStart(u32),
     SpeakingStop(u32),
+    FatalError(String),
+    UserActive(u32),
+    UserInactive(u32),
+    AudioConfig { direction: &'static str, config: audio::AudioDeviceConfig },
+    DeviceFallback { direction: &'static str, device: String },
+    AudioWarning { underruns: u64, overflow_samples: u64, capture_overruns: u64 },
+    /// Outcome of an acknowledged command; `error` is `None` on success.
+    CommandResult { id: u64, error: Option<String> },
+    /// Datagrams dropped by strict validation during one report interval,
+    /// with running totals by reason and the latest rejection.
+    MalformedPackets { count: u64, total: u64, by_reason: BTreeMap<&'static str, u64>, last: String },
+    /// Frames dropped over the rate limits during one report interval, by
+    /// limit, with the senders that exceeded their own and the partial
+    /// video frames discarded over the reassembly limits.
+    RateLimited { dropped: u64, by_reason: BTreeMap<&'static str, u64>, users: Vec<u32>, evicted: u64 },
 }
 
 impl MediaEvent {
-    fn to_tuple(&self) -> (String, String) {
+    /// The `(event_type, detail)` pair Python's `poll_event` returns.
+    pub fn to_tuple(&self) -> (String, String) {
         match self {
             MediaEvent::Connected => ("connected".into(), String::new()),
             MediaEvent::Disconnected(reason) => ("disconnected".into(), reason.clone()),
@@ -63,36 +145,119 @@ impl MediaEvent {
             }
             MediaEvent::AudioError(msg) => ("audio_error".into(), msg.clone()),
             MediaEvent::VideoError(msg) => ("video_error".into(), msg.clone()),
+            MediaEvent::TransportChanged(mode) => ("transport_changed".into(), mode.clone()),
+            MediaEvent::AuthAccepted { version, capabilities } => {
+                ("auth_accepted".into(), format!("version={version},capabilities={capabilities}"))
+            }
+            MediaEvent::AuthRejected(reason) => ("auth_rejected".into(), reason.clone()),
+            MediaEvent::DataError(msg) => ("data_error".into(), msg.clone()),
             MediaEvent::SpeakingStart(uid) => ("speaking_start".into(), uid.to_string()),
             MediaEvent::SpeakingStop(uid) => ("speaking_stop".into(), uid.to_string()),
+            MediaEvent::FatalError(msg) => ("fatal_error".into(), msg.clone()),
+            MediaEvent::UserActive(uid) => ("user_active".into(), uid.to_string()),
+            MediaEvent::UserInactive(uid) => ("user_inactive".into(), uid.to_string()),
+            MediaEvent::CommandResult { id, error: None } => ("command_result".into(), format!("id={id},ok=true")),
+            // Error text last: it may itself contain commas
+            MediaEvent::CommandResult { id, error: Some(e) } => {
+                ("command_result".into(), format!("id={id},ok=false,error={e}"))
+            }
+            MediaEvent::AudioWarning { underruns, overflow_samples, capture_overruns } => (
+                "audio_warning".into(),
+                format!("underruns={underruns},overflow_samples={overflow_samples},capture_overruns={capture_overruns}"),
+            ),
+            MediaEvent::DeviceFallback { direction, device } => {
+                ("device_fallback".into(), format!("direction={direction},device={device}"))
+            }
+            MediaEvent::RateLimited { dropped, by_reason, users, evicted } => {
+                let reasons: String = by_reason.iter().map(|(reason, n)| format!(",{reason}={n}")).collect();
+                let users: Vec<String> = users.iter().map(u32::to_string).collect();
+                (
+                    "rate_limited".into(),
+                    format!("dropped={dropped}{reasons},evicted={evicted},users={}", users.join(";")),
+                )
+            }
+            // Rejection text last: it may itself contain commas
+            MediaEvent::MalformedPackets { count, total, by_reason, last } => {
+                let reasons: String = by_reason.iter().map(|(reason, n)| format!(",{reason}={n}")).collect();
+                ("malformed_packets".into(), format!("count={count},total={total}{reasons},last={last}"))
+            }
+            // Device name last: it may itself contain commas
+            MediaEvent::AudioConfig { direction, config } => (
+                "audio_config".into(),
+                format!(
+                    "direction={direction},sample_rate={},channels={},resample={},device={}",
+                    config.sample_rate, config.channels, config.needs_resample, config.device
+                ),
+            ),
         }
     }
 }
 
-/// Thread-safe event queue for pushing events from the media runtime to Python.
-pub(crate) type EventQueue = Arc<Mutex<VecDeque<(String, String)>>>;
+/// Thread-safe event queue for pushing events from the media runtime to the client.
+pub(crate) type EventQueue = Arc<Mutex<VecDeque<MediaEvent>>>;
 
 /// Push an event onto the queue.
 pub(crate) fn push_event(queue: &EventQueue, event: MediaEvent) {
     if let Ok(mut q) = queue.lock() {
-        q.push_back(event.to_tuple());
+        q.push_back(event);
     }
 }
 
-/// A decoded video frame ready for Python consumption.
-pub(crate) struct VideoFrameOutput {
+/// A decoded video frame, RGBA8888.
+pub struct VideoFrameOutput {
     pub user_id: u32,   // 0 = local preview
     pub width: u32,
     pub height: u32,
     pub rgba: Vec<u8>,
 }
 
+/// Decoded video frames waiting to be polled, plus the most recent frame of
+/// each user for `capture_snapshot`.
+#[derive(Default)]
+pub(crate) struct VideoFrames {
+    queue: Mutex<VecDeque<Arc<VideoFrameOutput>>>,
+    latest: Mutex<HashMap<u32, (std::time::Instant, Arc<VideoFrameOutput>)>>,
+    arrived: std::sync::Condvar,
+}
+
+impl VideoFrames {
+    fn pop(&self) -> Option<Arc<VideoFrameOutput>> {
+        self.queue.lock().ok()?.pop_front()
+    }
+
+    /// The latest frame of `user_id` no older than `max_age`, waiting up to
+    /// `wait` for one to arrive.
+    fn latest(
+        &self,
+        user_id: u32,
+        max_age: std::time::Duration,
+        wait: std::time::Duration,
+    ) -> Option<Arc<VideoFrameOutput>> {
+        let deadline = std::time::Instant::now() + wait;
+        let mut latest = self.latest.lock().ok()?;
+        loop {
+            if let Some((at, frame)) = latest.get(&user_id) {
+                if at.elapsed() <= max_age {
+                    return Some(frame.clone());
+                }
+            }
+            let remaining = deadline.checked_duration_since(std::time::Instant::now())?;
+            latest = self.arrived.wait_timeout(latest, remaining).ok()?.0;
+        }
+    }
+}
+
 /// Thread-safe queue of decoded video frames.
-pub(crate) type VideoFrameQueue = Arc<Mutex<VecDeque<VideoFrameOutput>>>;
+pub(crate) type VideoFrameQueue = Arc<VideoFrames>;
 
 /// Push a video frame onto the queue (bounded to 8 frames, drops oldest).
 pub(crate) fn push_video_frame(queue: &VideoFrameQueue, frame: VideoFrameOutput) {
-    if let Ok(mut q) = queue.lock() {
+    let frame = Arc::new(frame);
+    if let Ok(mut latest) = queue.latest.lock() {
+        latest.insert(frame.user_id, (std::time::Instant::now(), frame.clone()));
+        queue.arrived.notify_all();
+    }
+    if let Ok(mut q) = queue.queue.lock() {
         if q.len() >= 8 {
             q.pop_front();
         }
@@ -100,205 +265,29 @@ pub(crate) fn push_video_frame(queue: &VideoFrameQueue, frame: VideoFrameOutput)
     }
 }
 
-/// Client-side media transport for Vox voice/video rooms.
-///
-/// Runs a background tokio runtime that manages QUIC transport to the SFU,
-/// Opus encoding/decoding, and cpal audio capture/playback.
-#[pyclass]
-struct VoxMediaClient {
-    cmd_tx: Option<mpsc::UnboundedSender<MediaCommand>>,
-    cancel: Option<CancellationToken>,
-    rt_handle: Option<std::thread::JoinHandle<()>>,
-    events: EventQueue,
-    video_frames: VideoFrameQueue,
-    muted: bool,
-    deafened: bool,
-    video: bool,
+/// An application data message received on a data channel.
+pub struct DataMessage {
+    pub user_id: u32,
+    pub channel_id: u16,
+    pub data: Vec<u8>,
 }
 
-#[pymethods]
-impl VoxMediaClient {
-    #[new]
-    fn new() -> Self {
-        let _ = tracing_subscriber::fmt::try_init();
-        VoxMediaClient {
-            cmd_tx: None,
-            cancel: None,
-            rt_handle: None,
-            events: Arc::new(Mutex::new(VecDeque::new())),
-            video_frames: Arc::new(Mutex::new(VecDeque::new())),
-            muted: false,
-            deafened: false,
-            video: false,
-        }
-    }
+/// Thread-safe queue of received data channel messages.
+pub(crate) type DataQueue = Arc<Mutex<VecDeque<DataMessage>>>;
 
-    /// Start the background media runtime.
-    fn start(&mut self) -> PyResult<()> {
-        if self.cancel.is_some() {
-            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-                "Media client is already running",
-            ));
-        }
-
-        let cancel = CancellationToken::new();
-        self.cancel = Some(cancel.clone());
-
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
-        self.cmd_tx = Some(cmd_tx);
-
-        let events = self.events.clone();
-        let events_thread = self.events.clone();
-        let video_frames = self.video_frames.clone();
-        let handle = std::thread::spawn(move || {
-            let rt = match tokio::runtime::Runtime::new() {
-                Ok(rt) => rt,
-                Err(e) => {
-                    push_event(&events_thread, MediaEvent::ConnectFailed(format!("Failed to create runtime: {e}")));
-                    return;
-                }
-            };
-            rt.block_on(async move {
-                state::run_media_loop(cmd_rx, cancel, events, video_frames).await;
-            });
-        });
-
-        self.rt_handle = Some(handle);
-        Ok(())
-    }
-
-    /// Connect to a voice room via the SFU.
-    #[pyo3(signature = (url, token, room_id, user_id, cert_der=None, idle_timeout_secs=30, datagram_buffer_size=65535, input_device=None, output_device=None))]
-    fn connect(&self, url: &str, token: &str, room_id: u32, user_id: u32, cert_der: Option<Vec<u8>>, idle_timeout_secs: u64, datagram_buffer_size: usize, input_device: Option<String>, output_device: Option<String>) -> PyResult<()> {
-        self.send_cmd(MediaCommand::Connect {
-            url: url.to_string(),
-            token: token.to_string(),
-            room_id,
-            user_id,
-            cert_der,
-            idle_timeout_secs,
-            datagram_buffer_size,
-            input_device,
-            output_device,
-        })
-    }
-
-    /// Disconnect from the current room.
-    fn disconnect(&self) -> PyResult<()> {
-        self.send_cmd(MediaCommand::Disconnect)
-    }
-
-    /// Set microphone mute state.
-    fn set_mute(&mut self, muted: bool) -> PyResult<()> {
-        self.muted = muted;
-        self.send_cmd(MediaCommand::SetMute(muted))
-    }
-
-    /// Set deafen state (no audio playback).
-    fn set_deaf(&mut self, deafened: bool) -> PyResult<()> {
-        self.deafened = deafened;
-        self.send_cmd(MediaCommand::SetDeaf(deafened))
-    }
-
-    /// Enable or disable video.
-    fn set_video(&mut self, enabled: bool) -> PyResult<()> {
-        self.video = enabled;
-        self.send_cmd(MediaCommand::SetVideo(enabled))
-    }
-
-    /// Configure video capture parameters. Must be called before set_video(true).
-    #[pyo3(signature = (width=640, height=480, fps=30, bitrate_kbps=500))]
-    fn set_video_config(&self, width: u32, height: u32, fps: u32, bitrate_kbps: u32) -> PyResult<()> {
-        self.send_cmd(MediaCommand::SetVideoConfig {
-            width,
-            height,
-            fps,
-            bitrate_kbps,
-        })
-    }
-
-    /// Set global input (microphone) volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
-    fn set_input_volume(&self, volume: f32) -> PyResult<()> {
-        self.send_cmd(MediaCommand::SetInputVolume(volume))
-    }
-
-    /// Set global output (playback) volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
-    fn set_output_volume(&self, volume: f32) -> PyResult<()> {
-        self.send_cmd(MediaCommand::SetOutputVolume(volume))
-    }
-
-    /// Set noise gate threshold. RMS below this value silences the mic. 0.0 = disabled.
-    fn set_noise_gate(&self, threshold: f32) -> PyResult<()> {
-        self.send_cmd(MediaCommand::SetNoiseGate(threshold))
-    }
-
-    /// Set per-user output volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
-    fn set_user_volume(&self, user_id: u32, volume: f32) -> PyResult<()> {
-        self.send_cmd(MediaCommand::SetUserVolume { user_id, volume })
-    }
-
-    /// Poll for the next decoded video frame.
-    /// Returns (user_id, width, height, rgba_bytes) or None.
-    /// user_id=0 means local camera preview.
-    fn poll_video_frame<'py>(&self, py: Python<'py>) -> Option<(u32, u32, u32, Bound<'py, PyBytes>)> {
-        let frame = self.video_frames.lock().ok()?.pop_front()?;
-        let bytes = PyBytes::new(py, &frame.rgba);
-        Some((frame.user_id, frame.width, frame.height, bytes))
-    }
-
-    /// Poll for the next event from the media runtime.
-    /// Returns a (event_type, detail) tuple, or None if no events are pending.
-    fn poll_event(&self) -> Option<(String, String)> {
-        self.events.lock().ok()?.pop_front()
-    }
-
-    /// Stop the media runtime entirely.
-    fn stop(&mut self) -> PyResult<()> {
-        if let Some(cancel) = self.cancel.take() {
-            cancel.cancel();
-        }
-        self.cmd_tx = None;
-        if let Some(handle) = self.rt_handle.take() {
-            let _ = handle.join();
+/// Push a data message onto the queue (bounded to 256 messages, drops oldest).
+pub(crate) fn push_data_message(queue: &DataQueue, msg: DataMessage) {
+    if let Ok(mut q) = queue.lock() {
+        if q.len() >= 256 {
+            q.pop_front();
         }
-        Ok(())
-    }
-
-    /// Whether the microphone is muted.
-    #[getter]
-    fn is_muted(&self) -> bool {
-        self.muted
-    }
-
-    /// Whether audio playback is deafened.
-    #[getter]
-    fn is_deafened(&self) -> bool {
-        self.deafened
-    }
-
-    /// Whether video is enabled.
-    #[getter]
-    fn is_video_enabled(&self) -> bool {
-        self.video
+        q.push_back(msg);
     }
 }
 
-impl VoxMediaClient {
-    fn send_cmd(&self, cmd: MediaCommand) -> PyResult<()> {
-        match &self.cmd_tx {
-            Some(tx) => tx.send(cmd).map_err(|_| {
-                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Media runtime is not running")
-            }),
-            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-                "Media client not started",
-            )),
-        }
-    }
-}
+/// Latest receive statistics per remote user, updated by the media runtime.
+pub(crate) type UserStatsMap = Arc<Mutex<HashMap<u32, ReceiveStatsSnapshot>>>;
+
+/// Shared SFrame key ring, set by the client and used by the media runtime.
+pub(crate) type MediaKeyRing = Arc<Mutex<KeyRing>>;
 
-/// Python module definition.
-#[pymodule]
-fn vox_media(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<VoxMediaClient>()?;
-    Ok(())
-}
@@ -0,0 +1,216 @@
+//! Python-configurable tracing output for the media runtime.
+//!
+//! A single global subscriber is installed on first use. Its level filter,
+//! output layer and span exporter sit behind reload handles, so
+//! `configure_logging` and `configure_tracing` can be called any number of
+//! times to change verbosity, destination or collector.
+
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+type OutputLayer = Box<dyn Layer<Registry> + Send + Sync>;
+type OutputHandle = reload::Handle<OutputLayer, Registry>;
+type OutputSubscriber = Layered<reload::Layer<OutputLayer, Registry>, Registry>;
+type TelemetryLayer = Box<dyn Layer<OutputSubscriber> + Send + Sync>;
+type TelemetryHandle = reload::Handle<TelemetryLayer, OutputSubscriber>;
+type FilterHandle = reload::Handle<EnvFilter, Layered<reload::Layer<TelemetryLayer, OutputSubscriber>, OutputSubscriber>>;
+
+static HANDLES: OnceLock<(FilterHandle, OutputHandle, TelemetryHandle)> = OnceLock::new();
+
+/// The running OTLP exporter, kept so it can be flushed when replaced.
+#[cfg(feature = "otel")]
+static EXPORTER: std::sync::Mutex<Option<vox_core::telemetry::OtlpExporter>> = std::sync::Mutex::new(None);
+
+/// Level used until the application calls `configure_logging`.
+const DEFAULT_LEVEL: &str = "info";
+
+/// Install the default stderr logger unless logging is already configured.
+pub fn init_default() {
+    if HANDLES.get().is_none() {
+        let _ = install(EnvFilter::new(DEFAULT_LEVEL), stderr_layer(false));
+    }
+}
+
+fn stderr_layer(json: bool) -> OutputLayer {
+    if json {
+        fmt::layer().json().with_writer(std::io::stderr).boxed()
+    } else {
+        fmt::layer().with_writer(std::io::stderr).boxed()
+    }
+}
+
+/// Daily-rotated log file; the date is appended to the file name.
+fn file_layer(path: &Path, json: bool) -> Result<OutputLayer, String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Log file path {path:?} has no file name"))?;
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(tracing_appender::rolling::Rotation::DAILY)
+        .filename_prefix(file_name.to_string_lossy())
+        .build(dir)
+        .map_err(|e| format!("Cannot open log file {path:?}: {e}"))?;
+    Ok(if json {
+        fmt::layer().json().with_writer(appender).boxed()
+    } else {
+        fmt::layer().with_ansi(false).with_writer(appender).boxed()
+    })
+}
+
+/// Replace the filter and output, installing the global subscriber if needed.
+fn install(filter: EnvFilter, output: OutputLayer) -> Result<(), String> {
+    if let Some((filter_handle, output_handle, _)) = HANDLES.get() {
+        output_handle.reload(output).map_err(|e| e.to_string())?;
+        return filter_handle.reload(filter).map_err(|e| e.to_string());
+    }
+    let (output, output_handle) = reload::Layer::new(output);
+    let (telemetry, telemetry_handle) = reload::Layer::new(no_telemetry());
+    let (filter, filter_handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(output)
+        .with(telemetry)
+        .with(filter)
+        .try_init()
+        .map_err(|e| format!("Another global logger is already installed: {e}"))?;
+    let _ = HANDLES.set((filter_handle, output_handle, telemetry_handle));
+    Ok(())
+}
+
+fn no_telemetry() -> TelemetryLayer {
+    tracing_subscriber::layer::Identity::new().boxed()
+}
+
+/// Forwards events to a Python callable as `(level, target, message)`.
+struct PythonLayer {
+    callback: Py<PyAny>,
+}
+
+impl<S: Subscriber> Layer<S> for PythonLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        let meta = event.metadata();
+        Python::attach(|py| {
+            if let Err(e) = self.callback.call1(py, (meta.level().as_str(), meta.target(), message.0)) {
+                e.print(py);
+            }
+        });
+    }
+}
+
+/// Renders an event's message followed by its other fields as `key=value`.
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, "{}={value:?}", field.name());
+        }
+    }
+}
+
+/// Configure Rust-side logging.
+///
+/// `level` is a level (`"debug"`) or a filter directive such as
+/// `"vox_media=debug,quinn=warn"`. Logs go to stderr unless `file` (rotated
+/// daily) or `python_callback` is given; a callback receives
+/// `(level, target, message)` and can forward to the `logging` module.
+/// `json` switches stderr and file output to JSON lines. May be called again
+/// at any time to reconfigure.
+#[pyfunction]
+#[pyo3(signature = (level, file=None, json=false, python_callback=None))]
+pub fn configure_logging(
+    level: &str,
+    file: Option<std::path::PathBuf>,
+    json: bool,
+    python_callback: Option<Py<PyAny>>,
+) -> PyResult<()> {
+    let filter = EnvFilter::try_new(level).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid log level {level:?}: {e}"))
+    })?;
+
+    let mut outputs: Vec<OutputLayer> = Vec::new();
+    if let Some(path) = &file {
+        outputs.push(file_layer(path, json).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?);
+    }
+    if let Some(callback) = python_callback {
+        outputs.push(Box::new(PythonLayer { callback }));
+    }
+    if outputs.is_empty() {
+        outputs.push(stderr_layer(json));
+    }
+
+    install(filter, Box::new(outputs)).map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)
+}
+
+/// Export spans to an OpenTelemetry collector over OTLP/HTTP.
+///
+/// `endpoint` is the collector's traces URL, e.g.
+/// `"http://localhost:4318/v1/traces"`; None flushes and stops the current
+/// exporter. Spans cover connect and reconnect attempts, AV1 encode/decode
+/// at `debug` and Opus encode/decode at `trace`; they pass the same filter
+/// as logs, so `configure_logging("vox_media=debug")` enables the former. `sample_ratio` is the fraction of
+/// root spans kept; `headers` are sent with every export request. Raises
+/// RuntimeError if the module was built without the `otel` feature.
+#[pyfunction]
+#[pyo3(signature = (endpoint, service_name="vox-media", sample_ratio=1.0, headers=None))]
+pub fn configure_tracing(
+    py: Python<'_>,
+    endpoint: Option<String>,
+    service_name: &str,
+    sample_ratio: f64,
+    headers: Option<HashMap<String, String>>,
+) -> PyResult<()> {
+    #[cfg(feature = "otel")]
+    {
+        use vox_core::telemetry::{OtlpConfig, OtlpExporter};
+
+        init_default();
+        let (_, _, telemetry_handle) = HANDLES.get().ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Another global logger is already installed")
+        })?;
+        let exporter = match endpoint {
+            Some(endpoint) => {
+                let config = OtlpConfig {
+                    endpoint,
+                    service_name: service_name.to_string(),
+                    sample_ratio,
+                    headers: headers.unwrap_or_default(),
+                };
+                Some(OtlpExporter::start(&config).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?)
+            }
+            None => None,
+        };
+        let layer = exporter.as_ref().map_or_else(no_telemetry, |e| e.layer("vox-media").boxed());
+        telemetry_handle.reload(layer).map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))?;
+        let previous = std::mem::replace(&mut *EXPORTER.lock().unwrap(), exporter);
+        if let Some(previous) = previous {
+            // Flushing waits on the collector
+            py.detach(move || previous.shutdown()).map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = (py, service_name, sample_ratio, headers);
+        match endpoint {
+            Some(_) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "vox_media was built without the otel feature",
+            )),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Timing histograms for the media hot path, reported by `profile_report`.
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+#[cfg(feature = "python")]
+use vox_core::profile;
+use vox_core::profile::Histogram;
+
+pub static SFRAME_ENCRYPT: Histogram = Histogram::new("sframe_encrypt");
+pub static SFRAME_DECRYPT: Histogram = Histogram::new("sframe_decrypt");
+pub static OPUS_ENCODE: Histogram = Histogram::new("opus_encode");
+pub static OPUS_DECODE: Histogram = Histogram::new("opus_decode");
+pub static AV1_ENCODE: Histogram = Histogram::new("av1_encode");
+pub static AV1_DECODE: Histogram = Histogram::new("av1_decode");
+pub static DATAGRAM_SEND: Histogram = Histogram::new("datagram_send");
+
+#[cfg(feature = "python")]
+static ALL: [&Histogram; 7] = [
+    &SFRAME_ENCRYPT,
+    &SFRAME_DECRYPT,
+    &OPUS_ENCODE,
+    &OPUS_DECODE,
+    &AV1_ENCODE,
+    &AV1_DECODE,
+    &DATAGRAM_SEND,
+];
+
+/// Start or stop recording operation timings in vox_media.
+///
+/// Off by default; while off, each instrumented call costs one atomic load.
+/// Counts already recorded are kept until read with `profile_report(reset=True)`.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (enabled=true))]
+pub fn enable_profiling(enabled: bool) {
+    profile::set_enabled(enabled);
+}
+
+/// Timing statistics per operation recorded since profiling was enabled.
+///
+/// Keys are operation names (`sframe_encrypt`, `sframe_decrypt`,
+/// `opus_encode`, `opus_decode`, `av1_encode`, `av1_decode`,
+/// `datagram_send`); operations with no calls are left out. Each value is a
+/// dict with `count`, `total_ms`, `mean_us`, `p50_us`, `p90_us`, `p99_us`
+/// and `max_us`. Percentiles are estimated from power-of-two buckets. With
+/// `reset=True` the counts start over.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (reset=false))]
+pub fn profile_report(py: Python<'_>, reset: bool) -> PyResult<Bound<'_, PyDict>> {
+    let report = PyDict::new(py);
+    for histogram in ALL {
+        let snap = histogram.snapshot(reset);
+        if snap.count == 0 {
+            continue;
+        }
+        let d = PyDict::new(py);
+        d.set_item("count", snap.count)?;
+        d.set_item("total_ms", snap.total_ns as f64 / 1e6)?;
+        d.set_item("mean_us", snap.mean_ns() / 1e3)?;
+        d.set_item("p50_us", snap.percentile_ns(0.5) / 1e3)?;
+        d.set_item("p90_us", snap.percentile_ns(0.9) / 1e3)?;
+        d.set_item("p99_us", snap.percentile_ns(0.99) / 1e3)?;
+        d.set_item("max_us", snap.max_ns as f64 / 1e3)?;
+        report.set_item(snap.name, d)?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,301 @@
+//! SOCKS5 proxy support for the media transport.
+//!
+//! QUIC runs over UDP, so the only proxy type that can carry it is SOCKS5
+//! with UDP ASSOCIATE (RFC 1928), optionally with username/password auth
+//! (RFC 1929). The TCP control connection is kept open for the lifetime of
+//! the endpoint, and every datagram is wrapped in the SOCKS5 UDP request
+//! header on its way to the relay.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::quic;
+
+const SOCKS_VERSION: u8 = 5;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_USERPASS: u8 = 0x02;
+const AUTH_NO_ACCEPTABLE: u8 = 0xFF;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A SOCKS5 proxy to tunnel the media connection through.
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Parse `socks5://[user[:password]@]host:port`.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("socks5://")
+            .or_else(|| url.strip_prefix("socks5h://"))
+            .ok_or_else(|| {
+                if url.starts_with("http://") || url.starts_with("https://") {
+                    "HTTP proxies cannot carry QUIC/UDP; use a socks5:// proxy".to_string()
+                } else {
+                    format!("Unsupported proxy URL {url:?} (expected socks5://host:port)")
+                }
+            })?;
+
+        let (creds, hostport) = match rest.rsplit_once('@') {
+            Some((c, h)) => (Some(c), h),
+            None => (None, rest),
+        };
+        let (username, password) = match creds {
+            Some(c) => match c.split_once(':') {
+                Some((u, p)) => (Some(u.to_string()), Some(p.to_string())),
+                None => (Some(c.to_string()), None),
+            },
+            None => (None, None),
+        };
+
+        let colon = hostport.rfind(':').ok_or("missing port in proxy URL")?;
+        let host = hostport[..colon].trim_start_matches('[').trim_end_matches(']');
+        let port: u16 = hostport[colon + 1..]
+            .parse()
+            .map_err(|e| format!("Invalid proxy port: {e}"))?;
+        if host.is_empty() {
+            return Err("missing host in proxy URL".into());
+        }
+        if username.as_ref().is_some_and(|u| u.len() > 255)
+            || password.as_ref().is_some_and(|p| p.len() > 255)
+        {
+            return Err("proxy username and password must be at most 255 bytes".into());
+        }
+
+        Ok(ProxyConfig {
+            host: host.to_string(),
+            port,
+            username,
+            password,
+        })
+    }
+}
+
+/// Create a client endpoint whose UDP traffic is relayed through `proxy`.
+pub async fn connect_endpoint(
+    proxy: &ProxyConfig,
+    bind: &quic::BindOptions,
+) -> Result<quinn::Endpoint, Box<dyn std::error::Error>> {
+    let proxy_addr = tokio::net::lookup_host((proxy.host.as_str(), proxy.port))
+        .await?
+        .next()
+        .ok_or("proxy DNS resolution failed")?;
+
+    let udp = UdpSocket::from_std(quic::bind_socket(bind, proxy_addr)?)?;
+    let mut control = TcpStream::connect(proxy_addr).await?;
+    negotiate_auth(&mut control, proxy).await?;
+    let relay = udp_associate(&mut control, udp.local_addr()?, proxy_addr.ip()).await?;
+    tracing::info!("SOCKS5 UDP relay at {relay} via proxy {proxy_addr}");
+
+    let socket = Arc::new(Socks5UdpSocket {
+        io: udp,
+        relay,
+        _control: control,
+    });
+    let endpoint = quinn::Endpoint::new_with_abstract_socket(
+        quinn::EndpointConfig::default(),
+        None,
+        socket,
+        Arc::new(quinn::TokioRuntime),
+    )?;
+    Ok(endpoint)
+}
+
+/// Method selection and optional username/password sub-negotiation.
+async fn negotiate_auth(control: &mut TcpStream, proxy: &ProxyConfig) -> io::Result<()> {
+    let greeting: &[u8] = if proxy.username.is_some() {
+        &[SOCKS_VERSION, 2, AUTH_NONE, AUTH_USERPASS]
+    } else {
+        &[SOCKS_VERSION, 1, AUTH_NONE]
+    };
+    control.write_all(greeting).await?;
+
+    let mut reply = [0u8; 2];
+    control.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(proxy_error("proxy is not a SOCKS5 server"));
+    }
+    match reply[1] {
+        AUTH_NONE => Ok(()),
+        AUTH_USERPASS => {
+            let user = proxy.username.as_deref().unwrap_or("");
+            let pass = proxy.password.as_deref().unwrap_or("");
+            let mut req = Vec::with_capacity(3 + user.len() + pass.len());
+            req.push(1);
+            req.push(user.len() as u8);
+            req.extend_from_slice(user.as_bytes());
+            req.push(pass.len() as u8);
+            req.extend_from_slice(pass.as_bytes());
+            control.write_all(&req).await?;
+
+            let mut status = [0u8; 2];
+            control.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(proxy_error("proxy authentication failed"));
+            }
+            Ok(())
+        }
+        AUTH_NO_ACCEPTABLE => Err(proxy_error("proxy requires an unsupported auth method")),
+        m => Err(proxy_error(&format!("proxy selected unknown auth method {m:#x}"))),
+    }
+}
+
+/// Issue UDP ASSOCIATE and return the relay address.
+async fn udp_associate(
+    control: &mut TcpStream,
+    local: SocketAddr,
+    proxy_ip: IpAddr,
+) -> io::Result<SocketAddr> {
+    let mut req = vec![SOCKS_VERSION, CMD_UDP_ASSOCIATE, 0];
+    encode_addr(&mut req, local);
+    control.write_all(&req).await?;
+
+    let mut head = [0u8; 4];
+    control.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(proxy_error(&format!("UDP ASSOCIATE rejected (reply {})", head[1])));
+    }
+    let ip = match head[3] {
+        ATYP_IPV4 => {
+            let mut b = [0u8; 4];
+            control.read_exact(&mut b).await?;
+            IpAddr::from(b)
+        }
+        ATYP_IPV6 => {
+            let mut b = [0u8; 16];
+            control.read_exact(&mut b).await?;
+            IpAddr::from(b)
+        }
+        ATYP_DOMAIN => {
+            // Relay given by name: skip it and use the proxy's address.
+            let len = control.read_u8().await? as usize;
+            let mut name = vec![0u8; len];
+            control.read_exact(&mut name).await?;
+            proxy_ip
+        }
+        t => return Err(proxy_error(&format!("unknown address type {t}"))),
+    };
+    let port = control.read_u16().await?;
+
+    // Many servers answer with the unspecified address, meaning "same host".
+    let ip = if ip.is_unspecified() { proxy_ip } else { ip };
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn proxy_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, format!("SOCKS5: {msg}"))
+}
+
+/// Append ATYP + address + port.
+fn encode_addr(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(ATYP_IPV4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(ATYP_IPV6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+/// Parse the SOCKS5 UDP request header, returning the source address and
+/// header length. Fragmented datagrams are not supported and yield `None`.
+fn parse_udp_header(buf: &[u8]) -> Option<(SocketAddr, usize)> {
+    if buf.len() < 4 || buf[2] != 0 {
+        return None;
+    }
+    let (ip, pos): (IpAddr, usize) = match buf[3] {
+        ATYP_IPV4 => {
+            let b: [u8; 4] = buf.get(4..8)?.try_into().ok()?;
+            (Ipv4Addr::from(b).into(), 8)
+        }
+        ATYP_IPV6 => {
+            let b: [u8; 16] = buf.get(4..20)?.try_into().ok()?;
+            (Ipv6Addr::from(b).into(), 20)
+        }
+        _ => return None,
+    };
+    let port = u16::from_be_bytes(buf.get(pos..pos + 2)?.try_into().ok()?);
+    Some((SocketAddr::new(ip, port), pos + 2))
+}
+
+/// UDP socket that wraps every datagram for a SOCKS5 relay.
+#[derive(Debug)]
+struct Socks5UdpSocket {
+    io: UdpSocket,
+    relay: SocketAddr,
+    /// The association lasts as long as this TCP connection stays open.
+    _control: TcpStream,
+}
+
+#[derive(Debug)]
+struct Socks5Poller {
+    socket: Arc<Socks5UdpSocket>,
+}
+
+impl quinn::UdpPoller for Socks5Poller {
+    fn poll_writable(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.socket.io.poll_send_ready(cx)
+    }
+}
+
+impl quinn::AsyncUdpSocket for Socks5UdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn quinn::UdpPoller>> {
+        Box::pin(Socks5Poller { socket: self })
+    }
+
+    fn try_send(&self, transmit: &quinn::udp::Transmit) -> io::Result<()> {
+        let mut packet = vec![0, 0, 0];
+        encode_addr(&mut packet, transmit.destination);
+        packet.extend_from_slice(transmit.contents);
+        self.io.try_send_to(&packet, self.relay).map(|_| ())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [io::IoSliceMut<'_>],
+        meta: &mut [quinn::udp::RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut read_buf = ReadBuf::new(&mut bufs[0]);
+            let from = ready!(self.io.poll_recv_from(cx, &mut read_buf))?;
+            let n = read_buf.filled().len();
+            if from != self.relay {
+                continue;
+            }
+            let buf = &mut bufs[0][..n];
+            let Some((src, header_len)) = parse_udp_header(buf) else {
+                tracing::trace!("Dropping malformed SOCKS5 UDP datagram");
+                continue;
+            };
+            buf.copy_within(header_len.., 0);
+            let len = n - header_len;
+            let mut m = quinn::udp::RecvMeta::default();
+            m.addr = src;
+            m.len = len;
+            m.stride = len;
+            meta[0] = m;
+            return Poll::Ready(Ok(1));
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.local_addr()
+    }
+}
@@ -0,0 +1,1088 @@
+//! The `vox_media` Python module: PyO3 wrappers over [`crate::client`].
+//! Built with the `python` feature.
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use vox_core::session::ReceiveStatsSnapshot;
+use vox_core::settings::{MediaSettings, SettingsStore};
+use vox_core::validate::ValidationConfig;
+
+use crate::client::{ClientError, ConnectOptions, MediaClient, VideoConfig};
+use crate::{audio, background, logging, profile, proxy, quic, transform, video};
+
+impl From<ClientError> for PyErr {
+    fn from(e: ClientError) -> Self {
+        match e {
+            ClientError::InvalidArgument(_) => PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()),
+            _ => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()),
+        }
+    }
+}
+
+/// A local port, or an inclusive `(first, last)` range to try in order.
+#[derive(FromPyObject)]
+enum PortSpec {
+    Single(u16),
+    Range(u16, u16),
+}
+
+/// A DSCP class name ("ef", "af41") or a raw code point (0–63).
+#[derive(FromPyObject)]
+enum DscpSpec {
+    Value(u8),
+    Name(String),
+}
+
+/// A single DER client certificate, or a chain with the end-entity first.
+#[derive(FromPyObject)]
+enum ClientCertSpec {
+    Single(Vec<u8>),
+    Chain(Vec<Vec<u8>>),
+}
+
+/// QUIC transport tuning passed to `VoxMediaClient.connect`.
+///
+/// `congestion_controller` is "bbr", "cubic" (default) or "newreno".
+/// `keep_alive_interval_ms`, `send_window`, `receive_window` (bytes) and
+/// `max_udp_payload_size` (upper bound for path MTU discovery) keep quinn's
+/// defaults when `None`.
+#[pyclass]
+struct TransportConfig {
+    tuning: quic::TransportTuning,
+}
+
+#[pymethods]
+impl TransportConfig {
+    #[new]
+    #[pyo3(signature = (congestion_controller="cubic", keep_alive_interval_ms=None, send_window=None, receive_window=None, max_udp_payload_size=None))]
+    fn new(congestion_controller: &str, keep_alive_interval_ms: Option<u64>, send_window: Option<u64>, receive_window: Option<u64>, max_udp_payload_size: Option<u16>) -> PyResult<Self> {
+        let congestion = quic::CongestionController::parse(congestion_controller).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown congestion controller {congestion_controller:?} (expected 'bbr', 'cubic' or 'newreno')"
+            ))
+        })?;
+        if keep_alive_interval_ms == Some(0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "keep_alive_interval_ms must be positive",
+            ));
+        }
+        if send_window == Some(0) || receive_window == Some(0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "window sizes must be positive",
+            ));
+        }
+        if max_udp_payload_size.is_some_and(|m| m < quic::MIN_UDP_PAYLOAD) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "max_udp_payload_size must be at least {}",
+                quic::MIN_UDP_PAYLOAD
+            )));
+        }
+        Ok(TransportConfig {
+            tuning: quic::TransportTuning {
+                congestion,
+                keep_alive: keep_alive_interval_ms.map(std::time::Duration::from_millis),
+                send_window,
+                receive_window,
+                max_udp_payload: max_udp_payload_size,
+            },
+        })
+    }
+}
+
+/// Strict validation of incoming datagrams, passed to
+/// `VoxMediaClient.connect` as `validation`.
+///
+/// Datagrams with an unknown version or media type, a codec that does not
+/// match the media type, an out-of-bounds payload, another room's id or our
+/// own user id are dropped. At most one `malformed_packets` event is
+/// emitted per `report_interval_secs`. With `disconnect_threshold`, the
+/// session is closed (without reconnecting) once that many arrive within
+/// one interval.
+#[pyclass]
+struct DatagramValidation {
+    config: ValidationConfig,
+}
+
+#[pymethods]
+impl DatagramValidation {
+    #[new]
+    #[pyo3(signature = (report_interval_secs=5.0, disconnect_threshold=None))]
+    fn new(report_interval_secs: f64, disconnect_threshold: Option<u64>) -> PyResult<Self> {
+        let report_interval = std::time::Duration::try_from_secs_f64(report_interval_secs)
+            .ok()
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>("report_interval_secs must be positive")
+            })?;
+        if disconnect_threshold == Some(0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "disconnect_threshold must be positive",
+            ));
+        }
+        Ok(DatagramValidation {
+            config: ValidationConfig { report_interval, disconnect_threshold },
+        })
+    }
+}
+
+/// Limits on incoming media, passed to `VoxMediaClient.connect` as
+/// `rate_limits`.
+///
+/// Each sender, and all senders together, may send at most the given
+/// packets and bytes per second (with bursts of up to one second's worth);
+/// `None` leaves a rate unlimited. Frames over a limit are dropped before
+/// decoding. At most `max_reassembly_per_user` partial video frames are held
+/// per sender and `max_reassembly_total` in all, the oldest being discarded
+/// beyond that. At most one `rate_limited` event is emitted per
+/// `report_interval_secs`.
+#[pyclass]
+struct RateLimits {
+    limits: vox_core::ratelimit::RateLimits,
+}
+
+#[pymethods]
+impl RateLimits {
+    #[new]
+    #[pyo3(signature = (user_packets_per_sec=Some(2000), user_bytes_per_sec=Some(2_000_000), total_packets_per_sec=Some(20_000), total_bytes_per_sec=Some(16_000_000), max_reassembly_per_user=32, max_reassembly_total=256, report_interval_secs=5.0))]
+    fn new(user_packets_per_sec: Option<u32>, user_bytes_per_sec: Option<u64>, total_packets_per_sec: Option<u32>, total_bytes_per_sec: Option<u64>, max_reassembly_per_user: usize, max_reassembly_total: usize, report_interval_secs: f64) -> PyResult<Self> {
+        use vox_core::ratelimit::Rate;
+
+        let report_interval = std::time::Duration::try_from_secs_f64(report_interval_secs)
+            .ok()
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>("report_interval_secs must be positive")
+            })?;
+        let rates = [user_packets_per_sec.map(u64::from), user_bytes_per_sec, total_packets_per_sec.map(u64::from), total_bytes_per_sec];
+        if rates.contains(&Some(0)) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("rates must be positive or None"));
+        }
+        if max_reassembly_per_user == 0 || max_reassembly_total == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "reassembly limits must be positive",
+            ));
+        }
+        Ok(RateLimits {
+            limits: vox_core::ratelimit::RateLimits {
+                per_user: Rate { packets_per_sec: user_packets_per_sec, bytes_per_sec: user_bytes_per_sec },
+                total: Rate { packets_per_sec: total_packets_per_sec, bytes_per_sec: total_bytes_per_sec },
+                max_reassembly_per_user,
+                max_reassembly_total,
+                report_interval,
+            },
+        })
+    }
+}
+
+/// An in-process SFU for tests: accepts the handshake and forwards each
+/// frame to the other clients in the sender's room. Connect to `url`
+/// pinning `cert_der`. With `echo_user_id`, frames also come back to their
+/// sender as if from that user; `reject` refuses every handshake with a
+/// reason, and `tokens` limits which tokens are accepted. Built with the
+/// `test-sfu` feature.
+#[cfg(feature = "test-sfu")]
+#[pyclass]
+struct MockSfu {
+    inner: Option<crate::test_sfu::MockSfu>,
+}
+
+#[cfg(feature = "test-sfu")]
+impl MockSfu {
+    fn sfu(&self) -> PyResult<&crate::test_sfu::MockSfu> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Mock SFU is closed"))
+    }
+}
+
+#[cfg(feature = "test-sfu")]
+#[pymethods]
+impl MockSfu {
+    #[new]
+    #[pyo3(signature = (echo_user_id=None, reject=None, tokens=None))]
+    fn new(echo_user_id: Option<u32>, reject: Option<String>, tokens: Option<Vec<String>>) -> PyResult<Self> {
+        let config = crate::test_sfu::MockSfuConfig { reject, tokens, echo_user_id };
+        let sfu = crate::test_sfu::MockSfu::start(config).map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        Ok(MockSfu { inner: Some(sfu) })
+    }
+
+    #[getter]
+    fn url(&self) -> PyResult<String> {
+        Ok(self.sfu()?.url())
+    }
+
+    #[getter]
+    fn cert_der<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, self.sfu()?.cert_der()))
+    }
+
+    /// Connected sessions as sorted `(room_id, user_id)` pairs.
+    fn sessions(&self) -> PyResult<Vec<(u32, u32)>> {
+        Ok(self.sfu()?.sessions().into_iter().map(|s| (s.room_id, s.user_id)).collect())
+    }
+
+    /// Handshakes received, accepted or not.
+    #[getter]
+    fn handshakes(&self) -> PyResult<u64> {
+        Ok(self.sfu()?.handshakes())
+    }
+
+    /// Media and data frames received from clients.
+    #[getter]
+    fn frames_received(&self) -> PyResult<u64> {
+        Ok(self.sfu()?.frames_received())
+    }
+
+    /// Close every client connection as a network failure would; clients
+    /// reconnect to the same SFU.
+    fn drop_connections(&self) -> PyResult<()> {
+        self.sfu()?.drop_connections();
+        Ok(())
+    }
+
+    /// Stop the SFU. Also happens when the object is garbage collected.
+    fn close(&mut self) {
+        self.inner = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: Option<Bound<'_, PyAny>>, _exc: Option<Bound<'_, PyAny>>, _tb: Option<Bound<'_, PyAny>>) {
+        self.close();
+    }
+}
+
+/// Frames older than this are not used for `capture_snapshot`.
+const SNAPSHOT_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Client-side media transport for Vox voice/video rooms.
+///
+/// Runs a background tokio runtime that manages QUIC transport to the SFU,
+/// Opus encoding/decoding, and cpal audio capture/playback.
+#[pyclass]
+struct VoxMediaClient {
+    inner: MediaClient,
+}
+
+#[pymethods]
+impl VoxMediaClient {
+    #[new]
+    fn new() -> Self {
+        logging::init_default();
+        VoxMediaClient { inner: MediaClient::new() }
+    }
+
+    /// Start the background media runtime.
+    ///
+    /// `settings_path` names a settings database (see `vox_sdk.settings`),
+    /// read once here. Its preferred devices are used when `connect` is not
+    /// given any, and its volumes, camera and reconnect policy apply to every
+    /// session of this runtime. Raises RuntimeError if it cannot be read.
+    ///
+    /// May be called again after the runtime died (see `fatal_error`).
+    #[pyo3(signature = (settings_path=None))]
+    fn start(&mut self, py: Python<'_>, settings_path: Option<std::path::PathBuf>) -> PyResult<()> {
+        if self.inner.is_running() {
+            return Err(ClientError::AlreadyRunning.into());
+        }
+        let settings = match settings_path {
+            Some(path) => py
+                .detach(|| SettingsStore::open(&path).and_then(|store| MediaSettings::load(&store)))
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Failed to read settings: {e}")))?,
+            None => MediaSettings::default(),
+        };
+        Ok(self.inner.start(settings)?)
+    }
+
+    /// Connect to a voice room via the SFU.
+    ///
+    /// Without pins the server certificate is verified against Mozilla's root
+    /// CAs. `cert_der` and `cert_pins` pin exact DER certificates, and
+    /// `spki_pins` pins SHA-256 hashes of the SubjectPublicKeyInfo, which
+    /// keep working when the server rotates its certificate but not its key.
+    ///
+    /// `client_cert` (a DER certificate, or a list of DER certificates with
+    /// the end-entity first) and `client_key` (DER PKCS#8, PKCS#1 or SEC1)
+    /// enable mutual TLS. If the certificate is unusable or the SFU rejects
+    /// it, `connect_failed` is emitted with a reason starting with
+    /// `"client auth failed:"`.
+    ///
+    /// `transport` selects how media is carried: "auto" (datagrams, falling
+    /// back to QUIC streams if the peer does not support them or most of
+    /// them are being dropped), "datagram" or "stream".
+    ///
+    /// `bind_address`, `bind_port` (a port or an inclusive `(first, last)`
+    /// range) and `bind_interface` control the local UDP socket; by default
+    /// an ephemeral port on the unspecified address of the server's family.
+    ///
+    /// `dscp` marks outgoing packets for QoS: "ef" (voice), "af41" (video)
+    /// or a raw code point 0–63. The whole session shares one socket, so one
+    /// class applies to all media. If the OS refuses, packets go unmarked.
+    ///
+    /// `proxy` tunnels the connection through a SOCKS5 proxy with UDP
+    /// ASSOCIATE, given as "socks5://[user[:password]@]host:port".
+    ///
+    /// `transport_config` is a `TransportConfig` tuning congestion control,
+    /// keep-alive, flow-control windows and the maximum UDP payload.
+    ///
+    /// `validation` is a `DatagramValidation` enabling strict checks on
+    /// incoming datagrams; by default anything with a header is accepted.
+    ///
+    /// `rate_limits` is a `RateLimits` bounding what each sender and all of
+    /// them together may send us; the defaults only catch floods.
+    ///
+    /// Returns a command id. When the attempt finishes, a `command_result`
+    /// event with `"id=<id>,ok=true"` or `"id=<id>,ok=false,error=<reason>"`
+    /// is emitted.
+    #[pyo3(signature = (url, token, room_id, user_id, cert_der=None, idle_timeout_secs=30, datagram_buffer_size=65535, input_device=None, output_device=None, transport="auto", bind_address=None, bind_port=None, bind_interface=None, proxy=None, cert_pins=None, spki_pins=None, client_cert=None, client_key=None, transport_config=None, dscp=None, validation=None, rate_limits=None))]
+    fn connect(&self, url: &str, token: &str, room_id: u32, user_id: u32, cert_der: Option<Vec<u8>>, idle_timeout_secs: u64, datagram_buffer_size: usize, input_device: Option<String>, output_device: Option<String>, transport: &str, bind_address: Option<&str>, bind_port: Option<PortSpec>, bind_interface: Option<String>, proxy: Option<&str>, cert_pins: Option<Vec<Vec<u8>>>, spki_pins: Option<Vec<Vec<u8>>>, client_cert: Option<ClientCertSpec>, client_key: Option<Vec<u8>>, transport_config: Option<PyRef<'_, TransportConfig>>, dscp: Option<DscpSpec>, validation: Option<PyRef<'_, DatagramValidation>>, rate_limits: Option<PyRef<'_, RateLimits>>) -> PyResult<u64> {
+        self.send_connect(url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind_address, bind_port, bind_interface, proxy, cert_pins, spki_pins, client_cert, client_key, transport_config, dscp, validation, rate_limits, None)
+    }
+
+    /// Connect and block until the session is up.
+    ///
+    /// Takes the same arguments as `connect`, plus `timeout` in seconds. The
+    /// GIL is released while waiting. Raises `ConnectionError` with the
+    /// `connect_failed` reason if the attempt fails, or `TimeoutError` if it
+    /// has not finished in time (the attempt itself carries on). Events are
+    /// still emitted as for `connect`.
+    #[pyo3(signature = (url, token, room_id, user_id, cert_der=None, idle_timeout_secs=30, datagram_buffer_size=65535, input_device=None, output_device=None, transport="auto", bind_address=None, bind_port=None, bind_interface=None, proxy=None, cert_pins=None, spki_pins=None, client_cert=None, client_key=None, transport_config=None, dscp=None, validation=None, rate_limits=None, timeout=10.0))]
+    fn connect_and_wait(&self, py: Python<'_>, url: &str, token: &str, room_id: u32, user_id: u32, cert_der: Option<Vec<u8>>, idle_timeout_secs: u64, datagram_buffer_size: usize, input_device: Option<String>, output_device: Option<String>, transport: &str, bind_address: Option<&str>, bind_port: Option<PortSpec>, bind_interface: Option<String>, proxy: Option<&str>, cert_pins: Option<Vec<Vec<u8>>>, spki_pins: Option<Vec<Vec<u8>>>, client_cert: Option<ClientCertSpec>, client_key: Option<Vec<u8>>, transport_config: Option<PyRef<'_, TransportConfig>>, dscp: Option<DscpSpec>, validation: Option<PyRef<'_, DatagramValidation>>, rate_limits: Option<PyRef<'_, RateLimits>>, timeout: f64) -> PyResult<()> {
+        let timeout = std::time::Duration::try_from_secs_f64(timeout).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timeout {timeout}"))
+        })?;
+        let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+        self.send_connect(url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind_address, bind_port, bind_interface, proxy, cert_pins, spki_pins, client_cert, client_key, transport_config, dscp, validation, rate_limits, Some(reply_tx))?;
+        match py.detach(move || reply_rx.recv_timeout(timeout)) {
+            Ok(None) => Ok(()),
+            Ok(Some(reason)) => Err(PyErr::new::<pyo3::exceptions::PyConnectionError, _>(reason)),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(PyErr::new::<pyo3::exceptions::PyTimeoutError, _>(
+                format!("Connect did not finish within {:.1}s", timeout.as_secs_f64()),
+            )),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "Media runtime stopped before the connect finished",
+            )),
+        }
+    }
+
+    /// Awaitable `connect_and_wait`, run on the event loop's default executor
+    /// so the loop is not blocked. Takes the same arguments.
+    #[pyo3(signature = (*args, **kwargs))]
+    fn connect_async<'py>(slf: &Bound<'py, Self>, args: &Bound<'py, PyTuple>, kwargs: Option<&Bound<'py, PyDict>>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let mut partial_args = vec![slf.getattr("connect_and_wait")?];
+        partial_args.extend(args.iter());
+        let call = py.import("functools")?.getattr("partial")?.call(PyTuple::new(py, partial_args)?, kwargs)?;
+        event_loop.call_method1("run_in_executor", (py.None(), call))
+    }
+
+    /// Disconnect from the current room, flushing pending media and telling
+    /// the SFU we are leaving before `disconnected` is emitted.
+    fn disconnect(&self) -> PyResult<()> {
+        Ok(self.inner.disconnect()?)
+    }
+
+    /// Set microphone mute state.
+    fn set_mute(&mut self, muted: bool) -> PyResult<()> {
+        Ok(self.inner.set_mute(muted)?)
+    }
+
+    /// Set deafen state (no audio playback).
+    fn set_deaf(&mut self, deafened: bool) -> PyResult<()> {
+        Ok(self.inner.set_deaf(deafened)?)
+    }
+
+    /// Enable or disable video.
+    ///
+    /// Returns a command id; a `command_result` event reports whether the
+    /// camera and encoder actually started. Builds without the `camera` or
+    /// `av1-encoder` feature always report failure.
+    fn set_video(&mut self, enabled: bool) -> PyResult<u64> {
+        Ok(self.inner.set_video(enabled)?)
+    }
+
+    /// Configure video capture parameters. Must be called before set_video(true).
+    ///
+    /// `rotation` (0, 90, 180 or 270 degrees clockwise) turns sideways
+    /// cameras upright for both the sent stream and the preview; `width`
+    /// and `height` are the camera's, before rotation. `mirror_preview`
+    /// flips only the local preview, as users expect of a self-view.
+    ///
+    /// Frames are encoded at exactly `width`x`height` even if the camera
+    /// picks another resolution. `scale_mode` "crop" center-crops to the
+    /// configured aspect ratio before scaling; "stretch" scales the whole
+    /// frame.
+    #[pyo3(signature = (width=640, height=480, fps=30, bitrate_kbps=500, rotation=0, mirror_preview=false, scale_mode="crop"))]
+    fn set_video_config(&self, width: u32, height: u32, fps: u32, bitrate_kbps: u32, rotation: u32, mirror_preview: bool, scale_mode: &str) -> PyResult<()> {
+        if width == 0 || height == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "width and height must be positive",
+            ));
+        }
+        let scale_mode = video::ScaleMode::parse(scale_mode).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown scale_mode {scale_mode:?} (expected 'crop' or 'stretch')"
+            ))
+        })?;
+        let rotation = video::Rotation::from_degrees(rotation).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("rotation must be 0, 90, 180 or 270")
+        })?;
+        Ok(self.inner.set_video_config(VideoConfig {
+            width,
+            height,
+            fps,
+            bitrate_kbps,
+            rotation,
+            mirror_preview,
+            scale_mode,
+        })?)
+    }
+
+    /// Blur or replace the background of the outgoing camera video.
+    ///
+    /// `effect` is "blur", "image", or None to turn the effect off. An ONNX
+    /// person segmentation model at `model_path` decides what is background,
+    /// which needs vox-media built with the `background-segmentation`
+    /// feature. "image" takes `image` as `(width, height, rgba_bytes)`. May
+    /// be changed while video is on; the local preview shows the result.
+    #[pyo3(signature = (effect, model_path=None, blur_radius=12, image=None))]
+    fn set_background_effect(&self, py: Python<'_>, effect: Option<&str>, model_path: Option<std::path::PathBuf>, blur_radius: usize, image: Option<(u32, u32, Vec<u8>)>) -> PyResult<()> {
+        let backdrop = match effect {
+            None => return Ok(self.inner.set_background(None)?),
+            Some("blur") => background::Backdrop::Blur { radius: blur_radius },
+            Some("image") => {
+                let (width, height, rgba) = image.ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "effect 'image' needs image=(width, height, rgba_bytes)",
+                    )
+                })?;
+                if width == 0 || height == 0 || rgba.len() != width as usize * height as usize * 4 {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "image must be non-empty RGBA with width * height * 4 bytes",
+                    ));
+                }
+                background::Backdrop::Image {
+                    rgb: video::rgba_to_rgb(&rgba),
+                    width: width as usize,
+                    height: height as usize,
+                }
+            }
+            Some(other) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown background effect {other:?} (expected 'blur', 'image' or None)"
+                )));
+            }
+        };
+        let model_path = model_path.ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("model_path is required for background effects")
+        })?;
+        let stage = py
+            .detach(move || background::BackgroundStage::new(&model_path, backdrop))
+            .map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        Ok(self.inner.set_background(Some(stage))?)
+    }
+
+    /// Composite an image onto outgoing video, e.g. a logo, name banner or
+    /// recording indicator.
+    ///
+    /// `image` is `(width, height, rgba_bytes)`, or None to remove the
+    /// overlay. It is placed at `anchor` ("top-left", "top-right",
+    /// "bottom-left", "bottom-right" or "center"), moved `offset` pixels
+    /// inward, and blended using its alpha channel scaled by `opacity`. The
+    /// local preview shows it too. May be changed while video is on.
+    #[pyo3(signature = (image, anchor="top-left", offset=(0, 0), opacity=1.0))]
+    fn set_video_overlay(&self, image: Option<(u32, u32, Vec<u8>)>, anchor: &str, offset: (i32, i32), opacity: f32) -> PyResult<()> {
+        let Some((width, height, rgba)) = image else {
+            return Ok(self.inner.set_video_overlay(None)?);
+        };
+        let anchor = video::OverlayAnchor::parse(anchor).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown anchor {anchor:?} (expected 'top-left', 'top-right', 'bottom-left', 'bottom-right' or 'center')"
+            ))
+        })?;
+        Ok(self.inner.set_video_overlay(Some(video::Overlay {
+            rgba,
+            width: width as usize,
+            height: height as usize,
+            anchor,
+            offset,
+            opacity,
+        }))?)
+    }
+
+    /// Ask the SFU to forward fewer or smaller streams.
+    ///
+    /// `max_video_streams` limits how many remote videos are received,
+    /// `max_resolution` is a `(width, height)` upper bound, and `audio_only`
+    /// stops video entirely. The stream limit and `audio_only` are also
+    /// enforced locally, so excess video is never decoded.
+    #[pyo3(signature = (max_video_streams=None, max_resolution=None, audio_only=false))]
+    fn set_receive_preferences(&self, max_video_streams: Option<u16>, max_resolution: Option<(u16, u16)>, audio_only: bool) -> PyResult<()> {
+        Ok(self.inner.set_receive_preferences(quic::ReceivePreferences {
+            max_video_streams,
+            max_resolution,
+            audio_only,
+        })?)
+    }
+
+    /// Cap total outgoing media bitrate in kbit/s; `None` or 0 removes the cap.
+    ///
+    /// Video quality is lowered first, then video is paused, and finally the
+    /// audio bitrate is reduced and silent frames are no longer sent.
+    #[pyo3(signature = (kbps=None))]
+    fn set_max_uplink_kbps(&self, kbps: Option<u32>) -> PyResult<()> {
+        Ok(self.inner.set_max_uplink_kbps(kbps)?)
+    }
+
+    /// Set global input (microphone) volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
+    fn set_input_volume(&self, volume: f32) -> PyResult<()> {
+        Ok(self.inner.set_input_volume(volume)?)
+    }
+
+    /// Set global output (playback) volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
+    fn set_output_volume(&self, volume: f32) -> PyResult<()> {
+        Ok(self.inner.set_output_volume(volume)?)
+    }
+
+    /// Set noise gate threshold. RMS below this value silences the mic. 0.0 = disabled.
+    fn set_noise_gate(&self, threshold: f32) -> PyResult<()> {
+        Ok(self.inner.set_noise_gate(threshold)?)
+    }
+
+    /// Free a remote user's decoders after this many seconds without media (default 10).
+    fn set_decoder_idle_timeout(&self, seconds: f64) -> PyResult<()> {
+        let timeout = std::time::Duration::try_from_secs_f64(seconds)
+            .ok()
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "Decoder idle timeout must be a positive number of seconds",
+                )
+            })?;
+        Ok(self.inner.set_decoder_idle_timeout(timeout)?)
+    }
+
+    /// Immediately free a remote user's decoders, partial frames and volume
+    /// state, e.g. when signaling reports they left the room.
+    fn drop_user(&self, user_id: u32) -> PyResult<()> {
+        Ok(self.inner.drop_user(user_id)?)
+    }
+
+    /// Set per-user output volume. 0.0 = silence, 1.0 = unity, 2.0 = 2x gain.
+    fn set_user_volume(&self, user_id: u32, volume: f32) -> PyResult<()> {
+        Ok(self.inner.set_user_volume(user_id, volume)?)
+    }
+
+    /// Attenuate remote playback while the local user is speaking.
+    ///
+    /// `level` is the gain applied while speaking (0.0–1.0) and `release_ms`
+    /// how long playback takes to ramp back to full volume afterwards.
+    /// `sources` restricts ducking to those users (e.g. a music bot); by
+    /// default all remote audio is ducked.
+    #[pyo3(signature = (enabled, level=0.3, release_ms=300, sources=None))]
+    fn set_voice_ducking(&self, enabled: bool, level: f32, release_ms: u64, sources: Option<Vec<u32>>) -> PyResult<()> {
+        if !(0.0..=1.0).contains(&level) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Ducking level must be between 0.0 and 1.0",
+            ));
+        }
+        let config = enabled.then(|| audio::DuckingConfig {
+            level,
+            release: std::time::Duration::from_millis(release_ms),
+            sources: sources.map(|s| s.into_iter().collect()),
+        });
+        Ok(self.inner.set_voice_ducking(config)?)
+    }
+
+    /// Play low-level comfort noise instead of silence while remote users
+    /// are quiet (DTX or VAD gaps). Enabled by default.
+    fn set_comfort_noise(&self, enabled: bool) -> PyResult<()> {
+        Ok(self.inner.set_comfort_noise(enabled)?)
+    }
+
+    /// Set this client's playback priority among all clients in the process.
+    ///
+    /// While a client with a higher priority is playing speech, this one's
+    /// playback is scaled by `attenuation` (0.0–1.0), e.g. to keep a primary
+    /// room intelligible over a secondary room. All clients default to
+    /// priority 0 with no attenuation.
+    #[pyo3(signature = (priority, attenuation=0.3))]
+    fn set_mix_priority(&self, priority: i32, attenuation: f32) -> PyResult<()> {
+        Ok(self.inner.set_mix_priority(priority, attenuation)?)
+    }
+
+    /// Install an end-to-end media key and make it the active send key.
+    ///
+    /// Once any key is installed, all outgoing audio/video payloads are
+    /// SFrame-encrypted and incoming payloads that fail to decrypt are dropped.
+    /// Previous keys are retained briefly so frames sent before a rotation
+    /// still decrypt. Pair with the MLS exporter secret for the current epoch.
+    fn set_media_key(&self, key_id: u64, key: Vec<u8>) -> PyResult<()> {
+        Ok(self.inner.set_media_key(key_id, key)?)
+    }
+
+    /// Take media keys from an MLS group instead of `set_media_key`.
+    ///
+    /// `engine` is a `vox_mls.MlsEngine`. It installs the group's exporter
+    /// secret for the current epoch now, and the next one after every commit
+    /// it applies, keyed by epoch, so keys rotate without ever passing
+    /// through Python. Replaces keys from any earlier source;
+    /// `clear_media_keys` detaches the group.
+    fn set_e2ee_from(&self, engine: &Bound<'_, PyAny>, group_id: &str) -> PyResult<()> {
+        if !engine.hasattr("attach_media_key_sink")? {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "engine must be a vox_mls.MlsEngine",
+            ));
+        }
+        let sink = self.inner.media_key_sink()?;
+        let capsule = key_sink_capsule(engine.py(), sink)?;
+        engine.call_method1("attach_media_key_sink", (group_id, capsule))?;
+        Ok(())
+    }
+
+    /// Remove all media keys, disabling end-to-end media encryption, and
+    /// detach any MLS group set with `set_e2ee_from`.
+    fn clear_media_keys(&self) -> PyResult<()> {
+        Ok(self.inner.clear_media_keys()?)
+    }
+
+    /// Register a transform invoked on every encoded frame, or None to remove it.
+    ///
+    /// The object may define `on_send(media_type, user_id, timestamp, payload)`
+    /// and/or `on_receive(media_type, user_id, timestamp, payload)`, each
+    /// returning the new payload bytes or None to drop the frame. Send
+    /// transforms run before SFrame encryption; receive transforms run after
+    /// decryption. Callbacks run on the media thread and must be fast.
+    #[pyo3(signature = (transform=None))]
+    fn set_frame_transform(&self, py: Python<'_>, transform: Option<Py<PyAny>>) -> PyResult<()> {
+        let new = transform.map(|t| {
+            Box::new(transform::PyFrameTransform::new(t)) as Box<dyn transform::FrameTransform>
+        });
+        // The media thread holds this lock while calling into Python, so wait
+        // for it without holding the GIL.
+        let inner = &self.inner;
+        let old = py.detach(move || inner.set_frame_transform(new))?;
+        // Dropping a Python transform needs the GIL, which we hold again
+        drop(old);
+        Ok(())
+    }
+
+    /// Poll for the next decoded video frame.
+    /// Returns (user_id, width, height, rgba_bytes) or None.
+    /// user_id=0 means local camera preview.
+    fn poll_video_frame<'py>(&self, py: Python<'py>) -> Option<(u32, u32, u32, Bound<'py, PyBytes>)> {
+        let frame = self.inner.poll_video_frame()?;
+        let bytes = PyBytes::new(py, &frame.rgba);
+        Some((frame.user_id, frame.width, frame.height, bytes))
+    }
+
+    /// Encode the latest video frame of `user_id` (0 = local camera) as an
+    /// image, for avatars, thumbnails or moderation reports.
+    ///
+    /// Uses the most recent frame if it is under two seconds old, otherwise
+    /// waits up to `timeout` seconds for the next one. `format` is "png" or
+    /// "jpeg" (by default taken from the `path` extension, else PNG) and
+    /// `quality` (1–100) applies to JPEG. If `path` is given the image is
+    /// also written there. Returns the encoded bytes, or None if no frame
+    /// arrived in time.
+    #[pyo3(signature = (user_id, path=None, format=None, quality=85, timeout=1.0))]
+    fn capture_snapshot<'py>(&self, py: Python<'py>, user_id: u32, path: Option<std::path::PathBuf>, format: Option<&str>, quality: u8, timeout: f64) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let format = match format {
+            Some(f) => video::SnapshotFormat::parse(f).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown snapshot format {f:?} (expected 'png' or 'jpeg')"
+                ))
+            })?,
+            None => path
+                .as_deref()
+                .and_then(|p| p.extension())
+                .and_then(|e| e.to_str())
+                .and_then(video::SnapshotFormat::parse)
+                .unwrap_or(video::SnapshotFormat::Png),
+        };
+        if !(1..=100).contains(&quality) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "quality must be between 1 and 100",
+            ));
+        }
+        let wait = std::time::Duration::try_from_secs_f64(timeout).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid timeout {timeout}"))
+        })?;
+
+        let inner = &self.inner;
+        let encoded = py.detach(move || -> PyResult<Option<Vec<u8>>> {
+            let Some(frame) = inner.latest_video_frame(user_id, SNAPSHOT_MAX_AGE, wait) else {
+                return Ok(None);
+            };
+            let bytes = video::encode_snapshot(&frame.rgba, frame.width, frame.height, format, quality)
+                .map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+            if let Some(path) = &path {
+                std::fs::write(path, &bytes).map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyOSError, _>(format!("Cannot write snapshot to {path:?}: {e}"))
+                })?;
+            }
+            Ok(Some(bytes))
+        })?;
+        Ok(encoded.map(|bytes| PyBytes::new(py, &bytes)))
+    }
+
+    /// Send application data on a data channel over the media connection.
+    ///
+    /// Reliable messages are delivered in order on a QUIC stream; unreliable
+    /// ones go out as datagrams and must fit in a single packet. Failures are
+    /// reported as "data_error" events.
+    #[pyo3(signature = (channel_id, data, reliable=true))]
+    fn send_data(&self, channel_id: u16, data: Vec<u8>, reliable: bool) -> PyResult<()> {
+        Ok(self.inner.send_data(channel_id, data, reliable)?)
+    }
+
+    /// Poll for the next received data channel message.
+    /// Returns (user_id, channel_id, data_bytes) or None.
+    fn poll_data<'py>(&self, py: Python<'py>) -> Option<(u32, u16, Bound<'py, PyBytes>)> {
+        let msg = self.inner.poll_data()?;
+        let bytes = PyBytes::new(py, &msg.data);
+        Some((msg.user_id, msg.channel_id, bytes))
+    }
+
+    /// Receive statistics for one remote user, or None if no media has
+    /// arrived from them recently.
+    ///
+    /// Keys: `bitrate_kbps`, `loss_percent`, `jitter_ms`,
+    /// `ms_since_last_packet`, `concealed_frames`, `packets_received`,
+    /// `packets_lost`, `packets_reordered`, `packets_duplicated`. Loss,
+    /// jitter and packet counters refer to the user's audio stream.
+    fn get_user_stats<'py>(&self, py: Python<'py>, user_id: u32) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.inner.user_stats(user_id).map(|s| user_stats_dict(py, &s)).transpose()
+    }
+
+    /// Local audio glitch counters for the current session: `underruns`
+    /// (playback ran dry mid-stream), `overflow_samples` (dropped from an
+    /// overfull playback buffer) and `capture_overruns` (microphone frames
+    /// dropped because the media loop fell behind). These point at local
+    /// scheduling problems rather than the network; new glitches are also
+    /// reported every 10 s as an `audio_warning` event.
+    fn get_audio_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let s = self.inner.audio_stats();
+        let d = PyDict::new(py);
+        d.set_item("underruns", s.underruns)?;
+        d.set_item("overflow_samples", s.overflow_samples)?;
+        d.set_item("capture_overruns", s.capture_overruns)?;
+        Ok(d)
+    }
+
+    /// Receive statistics for all recently active remote users, keyed by user id.
+    fn get_all_user_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let out = PyDict::new(py);
+        for (uid, s) in self.inner.all_user_stats() {
+            out.set_item(uid, user_stats_dict(py, &s)?)?;
+        }
+        Ok(out)
+    }
+
+    /// Poll for the next event from the media runtime.
+    /// Returns a (event_type, detail) tuple, or None if no events are pending.
+    fn poll_event(&self) -> Option<(String, String)> {
+        self.inner.poll_event().map(|event| event.to_tuple())
+    }
+
+    /// Stop the media runtime entirely.
+    ///
+    /// An active session is left gracefully first: pending video and audio
+    /// are flushed, the SFU is told we are leaving and the connection is
+    /// closed. This blocks for at most about two seconds.
+    ///
+    /// The client can be started again afterwards. Unread events are kept.
+    fn stop(&mut self, py: Python<'_>) -> PyResult<()> {
+        // The runtime may be blocked on the GIL inside a frame transform.
+        let inner = &mut self.inner;
+        py.detach(move || inner.stop());
+        Ok(())
+    }
+
+    /// Start the runtime (if not already running) on entering a `with` block.
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyResult<PyRefMut<'_, Self>> {
+        if !slf.inner.is_started() {
+            let py = slf.py();
+            slf.start(py, None)?;
+        }
+        Ok(slf)
+    }
+
+    /// Stop the runtime on leaving a `with` block. Exceptions propagate.
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: Option<Py<PyAny>>,
+        _exc_value: Option<Py<PyAny>>,
+        _traceback: Option<Py<PyAny>>,
+    ) -> PyResult<bool> {
+        self.stop(py)?;
+        Ok(false)
+    }
+
+    /// Allow or forbid GPU video color conversion and scaling (process-wide,
+    /// on by default). Only has an effect when vox-media is built with the
+    /// `gpu` feature; otherwise conversion always runs on the CPU.
+    #[staticmethod]
+    fn set_gpu_acceleration(enabled: bool) {
+        MediaClient::set_gpu_acceleration(enabled);
+    }
+
+    /// Whether video conversion currently runs on the GPU. False without the
+    /// `gpu` feature, without a usable adapter, when disabled, or after a
+    /// GPU error caused a fallback to the CPU.
+    #[staticmethod]
+    fn gpu_acceleration_available(py: Python<'_>) -> bool {
+        py.detach(MediaClient::gpu_acceleration_available)
+    }
+
+    /// Open an input device for `duration_s` seconds and measure its signal.
+    ///
+    /// Works without start() or a session. Returns a dict with the device
+    /// name, negotiated `sample_rate`/`channels`, whether resampling would be
+    /// needed, and normalized `peak` and `rms` levels (0.0–1.0). `name=None`
+    /// tests the default input device. Raises RuntimeError in builds without
+    /// the `audio-devices` feature.
+    #[staticmethod]
+    #[pyo3(signature = (name=None, duration_s=1.0))]
+    fn test_input_device<'py>(py: Python<'py>, name: Option<String>, duration_s: f64) -> PyResult<Bound<'py, PyDict>> {
+        let duration = std::time::Duration::try_from_secs_f64(duration_s)
+            .ok()
+            .filter(|d| !d.is_zero() && *d <= std::time::Duration::from_secs(30))
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "duration_s must be between 0 and 30 seconds",
+                )
+            })?;
+        let report = py
+            .detach(|| audio::measure_input(name.as_deref(), duration).map_err(|e| e.to_string()))
+            .map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        let d = PyDict::new(py);
+        d.set_item("device", report.config.device)?;
+        d.set_item("sample_rate", report.config.sample_rate)?;
+        d.set_item("channels", report.config.channels)?;
+        d.set_item("needs_resample", report.config.needs_resample)?;
+        d.set_item("peak", report.peak)?;
+        d.set_item("rms", report.rms)?;
+        Ok(d)
+    }
+
+    /// Whether the microphone is muted.
+    #[getter]
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+
+    /// Whether audio playback is deafened.
+    #[getter]
+    fn is_deafened(&self) -> bool {
+        self.inner.is_deafened()
+    }
+
+    /// Whether video is enabled.
+    #[getter]
+    fn is_video_enabled(&self) -> bool {
+        self.inner.is_video_enabled()
+    }
+
+    /// Whether end-to-end media encryption is active.
+    #[getter]
+    fn is_e2ee_enabled(&self) -> bool {
+        self.inner.is_e2ee_enabled()
+    }
+}
+
+impl VoxMediaClient {
+    fn send_connect(&self, url: &str, token: &str, room_id: u32, user_id: u32, cert_der: Option<Vec<u8>>, idle_timeout_secs: u64, datagram_buffer_size: usize, input_device: Option<String>, output_device: Option<String>, transport: &str, bind_address: Option<&str>, bind_port: Option<PortSpec>, bind_interface: Option<String>, proxy: Option<&str>, cert_pins: Option<Vec<Vec<u8>>>, spki_pins: Option<Vec<Vec<u8>>>, client_cert: Option<ClientCertSpec>, client_key: Option<Vec<u8>>, transport_config: Option<PyRef<'_, TransportConfig>>, dscp: Option<DscpSpec>, validation: Option<PyRef<'_, DatagramValidation>>, rate_limits: Option<PyRef<'_, RateLimits>>, reply: Option<std::sync::mpsc::SyncSender<Option<String>>>) -> PyResult<u64> {
+        let transport = quic::TransportPreference::parse(transport).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown transport {transport:?} (expected 'auto', 'datagram' or 'stream')"
+            ))
+        })?;
+        let address = bind_address
+            .map(|a| {
+                a.parse::<std::net::IpAddr>().map_err(|e| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid bind_address {a:?}: {e}"
+                    ))
+                })
+            })
+            .transpose()?;
+        let ports = match bind_port {
+            None => None,
+            Some(PortSpec::Single(p)) => Some((p, p)),
+            Some(PortSpec::Range(first, last)) if first <= last => Some((first, last)),
+            Some(PortSpec::Range(..)) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "bind_port range must be (first, last) with first <= last",
+                ));
+            }
+        };
+        let dscp = match dscp {
+            None => None,
+            Some(DscpSpec::Value(v)) if v < 64 => Some(v),
+            Some(DscpSpec::Name(name)) if name.eq_ignore_ascii_case("ef") => Some(quic::DSCP_EF),
+            Some(DscpSpec::Name(name)) if name.eq_ignore_ascii_case("af41") => Some(quic::DSCP_AF41),
+            Some(_) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "dscp must be 'ef', 'af41' or a code point 0-63",
+                ));
+            }
+        };
+        let bind = quic::BindOptions {
+            address,
+            ports,
+            interface: bind_interface,
+            dscp,
+        };
+        let proxy = proxy
+            .map(proxy::ProxyConfig::parse)
+            .transpose()
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        let mut pins = quic::CertPins {
+            certs: cert_pins.unwrap_or_default(),
+            spki_sha256: spki_pins
+                .unwrap_or_default()
+                .into_iter()
+                .map(|h| {
+                    <[u8; 32]>::try_from(h).map_err(|_| {
+                        PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                            "spki_pins entries must be 32-byte SHA-256 hashes",
+                        )
+                    })
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+        };
+        pins.certs.extend(cert_der);
+        let client_identity = match (client_cert, client_key) {
+            (Some(cert), Some(key)) => Some(quic::ClientIdentity {
+                cert_chain: match cert {
+                    ClientCertSpec::Single(der) => vec![der],
+                    ClientCertSpec::Chain(chain) => chain,
+                },
+                key,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "client_cert and client_key must be given together",
+                ))
+            }
+        };
+        if client_identity.as_ref().is_some_and(|id| id.cert_chain.is_empty()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "client_cert must not be empty",
+            ));
+        }
+        let tuning = transport_config.map(|c| c.tuning.clone()).unwrap_or_default();
+        let options = ConnectOptions {
+            url: url.to_string(),
+            token: token.to_string(),
+            room_id,
+            user_id,
+            pins,
+            client_identity,
+            idle_timeout_secs,
+            datagram_buffer_size,
+            input_device,
+            output_device,
+            transport,
+            bind,
+            proxy,
+            tuning,
+            validation: validation.map(|v| v.config.clone()),
+            rate_limits: rate_limits.map(|r| r.limits.clone()).unwrap_or_default(),
+        };
+        Ok(self.inner.send_connect(options, reply)?)
+    }
+
+}
+
+/// Wrap a media key sink in the capsule `MlsEngine.attach_media_key_sink`
+/// takes. The capsule owns the sink; the engine keeps its own reference.
+fn key_sink_capsule(py: Python<'_>, sink: vox_core::key_sink::KeySink) -> PyResult<Bound<'_, PyAny>> {
+    use vox_core::key_sink::{KeySink, CAPSULE_NAME};
+
+    unsafe extern "C" fn destroy(capsule: *mut pyo3::ffi::PyObject) {
+        let ptr = pyo3::ffi::PyCapsule_GetPointer(capsule, CAPSULE_NAME.as_ptr());
+        if !ptr.is_null() {
+            drop(Box::from_raw(ptr.cast::<KeySink>()));
+        }
+    }
+
+    // KeySink is a transparent wrapper, so the capsule points at a RawKeySink
+    let ptr = Box::into_raw(Box::new(sink));
+    unsafe {
+        let capsule = pyo3::ffi::PyCapsule_New(ptr.cast(), CAPSULE_NAME.as_ptr(), Some(destroy));
+        if capsule.is_null() {
+            drop(Box::from_raw(ptr));
+        }
+        Bound::from_owned_ptr_or_err(py, capsule)
+    }
+}
+
+/// Convert a stats snapshot to the dict returned by `get_user_stats`.
+fn user_stats_dict<'py>(py: Python<'py>, s: &ReceiveStatsSnapshot) -> PyResult<Bound<'py, PyDict>> {
+    let d = PyDict::new(py);
+    d.set_item("bitrate_kbps", s.bitrate_kbps)?;
+    d.set_item("loss_percent", s.loss_percent)?;
+    d.set_item("jitter_ms", s.jitter_ms)?;
+    d.set_item("ms_since_last_packet", s.last_packet.elapsed().as_millis() as u64)?;
+    d.set_item("concealed_frames", s.concealed_frames)?;
+    d.set_item("packets_received", s.packets_received)?;
+    d.set_item("packets_lost", s.packets_lost)?;
+    d.set_item("packets_reordered", s.packets_reordered)?;
+    d.set_item("packets_duplicated", s.packets_duplicated)?;
+    Ok(d)
+}
+
+/// Python module definition.
+#[pymodule]
+fn vox_media(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<VoxMediaClient>()?;
+    m.add_class::<TransportConfig>()?;
+    m.add_class::<DatagramValidation>()?;
+    m.add_class::<RateLimits>()?;
+    #[cfg(feature = "test-sfu")]
+    m.add_class::<MockSfu>()?;
+    m.add_function(wrap_pyfunction!(logging::configure_logging, m)?)?;
+    m.add_function(wrap_pyfunction!(logging::configure_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(profile::enable_profiling, m)?)?;
+    m.add_function(wrap_pyfunction!(profile::profile_report, m)?)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("__features__", enabled_features())?;
+    Ok(())
+}
+
+/// Optional cargo features this build was compiled with.
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "audio-devices") {
+        features.push("audio-devices");
+    }
+    if cfg!(feature = "camera") {
+        features.push("camera");
+    }
+    if cfg!(feature = "av1-encoder") {
+        features.push("av1-encoder");
+    }
+    if cfg!(feature = "av1-decoder") {
+        features.push("av1-decoder");
+    }
+    if cfg!(feature = "background-segmentation") {
+        features.push("background-segmentation");
+    }
+    if cfg!(feature = "gpu") {
+        features.push("gpu");
+    }
+    if cfg!(feature = "otel") {
+        features.push("otel");
+    }
+    if cfg!(feature = "test-sfu") {
+        features.push("test-sfu");
+    }
+    features
+}
@@ -1,357 +1,1060 @@
 //! QUIC transport to the Vox SFU.
 //!
 //! Connects to the SFU using the same packet format as vox-sfu,
-//! sends/receives media frames over QUIC datagrams.
+//! sends/receives media frames over QUIC datagrams, falling back to
+//! length-prefixed frames on unidirectional streams when the path does
+//! not support datagrams.
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::Bytes;
 use quinn::ClientConfig;
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Instant;
-
-/// ALPN protocol identifier — must match the SFU server.
-const ALPN_PROTOCOL: &[u8] = b"vox-media/1";
-
-/// Size of the fixed media frame header in bytes (matches vox-sfu header.rs).
-pub const HEADER_SIZE: usize = 22;
-
-// Media type values
-pub const MEDIA_TYPE_AUDIO: u8 = 0;
-pub const MEDIA_TYPE_VIDEO: u8 = 1;
-pub const MEDIA_TYPE_SCREEN: u8 = 2;
-pub const MEDIA_TYPE_FEC: u8 = 3;
-pub const MEDIA_TYPE_RTCP_FB: u8 = 4;
-
-// Codec ID values
-pub const CODEC_NONE: u8 = 0;
-pub const CODEC_OPUS: u8 = 1;
-pub const CODEC_AV1: u8 = 2;
-pub const CODEC_AV1_SCREEN: u8 = 3;
-
-// Flag bits (byte 3) — mirrors vox-sfu header.rs
-pub const FLAG_KEYFRAME: u8 = 0b1000_0000;
-pub const FLAG_END_OF_FRAME: u8 = 0b0100_0000;
-pub const FLAG_FEC: u8 = 0b0010_0000;
-pub const FLAG_MARKER: u8 = 0b0001_0000;
-pub const FLAG_HAS_DEP_DESC: u8 = 0b0000_1000;
-
-/// Current protocol version.
-const PROTOCOL_VERSION: u8 = 1;
-
-/// Media frame header (22 bytes fixed), matching vox-sfu exactly.
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+
+use crate::profile;
+
+// Wire format shared with vox-sfu
+pub use vox_core::*;
+
+/// Largest media payload that fits in one datagram on this connection.
 ///
-/// Wire layout (big-endian):
-/// ```text
-/// Byte 0:      version (u8)
-/// Byte 1:      media_type (u8)
-/// Byte 2:      codec_id (u8)
-/// Byte 3:      flags (u8)
-/// Bytes 4-7:   room_id (u32)
-/// Bytes 8-11:  user_id (u32)
-/// Bytes 12-15: sequence (u32)
-/// Bytes 16-19: timestamp (u32)
-/// Byte 20:     spatial_id (upper 4 bits) | temporal_id (lower 4 bits)
-/// Byte 21:     dtx flag (bit 7, MSB)
-/// ```
-#[derive(Debug, Clone)]
-pub struct MediaHeader {
-    pub version: u8,
-    pub media_type: u8,
-    pub codec_id: u8,
-    pub flags: u8,
-    pub room_id: u32,
-    pub user_id: u32,
-    pub sequence: u32,
-    pub timestamp: u32,
-    pub spatial_id: u8,
-    pub temporal_id: u8,
-    pub dtx: bool,
-}
-
-impl MediaHeader {
-    /// Parse a media header from the first 22 bytes of a datagram.
-    pub fn parse(data: &[u8]) -> Option<Self> {
-        if data.len() < HEADER_SIZE {
-            return None;
+/// Re-queried per frame so fragment sizes follow path MTU changes mid-call:
+/// larger-MTU paths carry fewer fragments per frame, and a shrinking MTU
+/// does not turn into a stream of send errors.
+pub fn max_fragment_payload(connection: &quinn::Connection) -> usize {
+    connection
+        .max_datagram_size()
+        .map(|max| max.saturating_sub(HEADER_SIZE))
+        .filter(|&payload| payload > 0)
+        .unwrap_or(MAX_FRAGMENT_PAYLOAD)
+}
+
+// ---------------------------------------------------------------------------
+// Video pacing
+// ---------------------------------------------------------------------------
+
+/// Pacing rate relative to the target bitrate, leaving headroom so that
+/// keyframes (several times the average frame size) drain quickly.
+const PACING_FACTOR: f64 = 2.5;
+/// Bytes allowed out back-to-back, expressed as time at the pacing rate.
+const PACING_BURST: Duration = Duration::from_millis(5);
+/// Minimum burst so that low bitrates still send whole fragments.
+const MIN_PACING_BURST_BYTES: f64 = 2.0 * MAX_FRAGMENT_PAYLOAD as f64;
+/// Encoded frames allowed to wait in the pacer before old ones are dropped.
+const MAX_PACED_FRAMES: usize = 8;
+
+/// An encoded video frame waiting to be fragmented onto the link.
+struct PendingVideoFrame {
+    timestamp: u32,
+    is_keyframe: bool,
+    data: Vec<u8>,
+    /// Bytes already sent.
+    offset: usize,
+}
+
+/// Spreads the fragments of large video frames over time instead of
+/// writing a whole keyframe into the socket at once, which overflows the
+/// small buffers of consumer routers.
+///
+/// A token bucket refilled at `PACING_FACTOR` × the video bitrate decides
+/// how many bytes may be sent; `next_send_time` tells the media loop when to
+/// call `flush` again. Frames are fragmented lazily, so each fragment is
+/// sized to the link's maximum payload at the moment it is sent.
+pub struct VideoPacer {
+    frames: std::collections::VecDeque<PendingVideoFrame>,
+    /// Pacing rate in bytes per second.
+    rate: f64,
+    /// Bytes that may be sent now; negative while paying off a fragment.
+    budget: f64,
+    last_refill: Instant,
+}
+
+impl VideoPacer {
+    pub fn new(bitrate_kbps: u32) -> Self {
+        let mut pacer = VideoPacer {
+            frames: std::collections::VecDeque::new(),
+            rate: 0.0,
+            budget: 0.0,
+            last_refill: Instant::now(),
+        };
+        pacer.set_bitrate(bitrate_kbps);
+        pacer.budget = pacer.max_burst();
+        pacer
+    }
+
+    /// Derive the pacing rate from the video bitrate.
+    pub fn set_bitrate(&mut self, bitrate_kbps: u32) {
+        self.rate = (bitrate_kbps.max(1) as f64 * 1000.0 / 8.0) * PACING_FACTOR;
+    }
+
+    fn max_burst(&self) -> f64 {
+        (self.rate * PACING_BURST.as_secs_f64()).max(MIN_PACING_BURST_BYTES)
+    }
+
+    /// Queue an encoded frame. If the link cannot keep up, the oldest frames
+    /// that have not started sending are dropped.
+    pub fn push(&mut self, timestamp: u32, is_keyframe: bool, data: Vec<u8>) {
+        self.frames.push_back(PendingVideoFrame {
+            timestamp,
+            is_keyframe,
+            data,
+            offset: 0,
+        });
+        while self.frames.len() > MAX_PACED_FRAMES {
+            let idx = usize::from(self.frames.front().is_some_and(|f| f.offset > 0));
+            if let Some(dropped) = self.frames.remove(idx) {
+                tracing::debug!("Video pacer backlog, dropping frame ts={}", dropped.timestamp);
+            }
         }
-        Some(MediaHeader {
-            version: data[0],
-            media_type: data[1],
-            codec_id: data[2],
-            flags: data[3],
-            room_id: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
-            user_id: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
-            sequence: u32::from_be_bytes([data[12], data[13], data[14], data[15]]),
-            timestamp: u32::from_be_bytes([data[16], data[17], data[18], data[19]]),
-            spatial_id: data[20] >> 4,
-            temporal_id: data[20] & 0x0F,
-            dtx: (data[21] & 0x80) != 0,
-        })
     }
 
-    /// Serialize the header into 22 bytes (big-endian).
-    pub fn encode(&self) -> [u8; HEADER_SIZE] {
-        let mut buf = [0u8; HEADER_SIZE];
-        buf[0] = self.version;
-        buf[1] = self.media_type;
-        buf[2] = self.codec_id;
-        buf[3] = self.flags;
-        buf[4..8].copy_from_slice(&self.room_id.to_be_bytes());
-        buf[8..12].copy_from_slice(&self.user_id.to_be_bytes());
-        buf[12..16].copy_from_slice(&self.sequence.to_be_bytes());
-        buf[16..20].copy_from_slice(&self.timestamp.to_be_bytes());
-        buf[20] = (self.spatial_id << 4) | (self.temporal_id & 0x0F);
-        buf[21] = if self.dtx { 0x80 } else { 0 };
-        buf
+    /// Drop all queued frames (e.g. when video is disabled).
+    pub fn clear(&mut self) {
+        self.frames.clear();
     }
 
-    pub fn is_keyframe(&self) -> bool {
-        self.flags & FLAG_KEYFRAME != 0
+    /// When `flush` should next be called, or `None` if nothing is queued.
+    pub fn next_send_time(&self) -> Option<Instant> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        if self.budget > 0.0 {
+            return Some(Instant::now());
+        }
+        Some(self.last_refill + Duration::from_secs_f64(-self.budget / self.rate))
     }
 
-    pub fn is_end_of_frame(&self) -> bool {
-        self.flags & FLAG_END_OF_FRAME != 0
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.budget = (self.budget + elapsed * self.rate).min(self.max_burst());
+        self.last_refill = now;
     }
 
-    pub fn has_dep_desc(&self) -> bool {
-        self.flags & FLAG_HAS_DEP_DESC != 0
+    /// Send as many fragments as the budget allows.
+    ///
+    /// Each frame's fragments share its timestamp, the first one gets
+    /// FLAG_START_OF_FRAME and the last one gets FLAG_END_OF_FRAME. If the
+    /// path MTU shrinks between the size query and the send, the rest of the
+    /// frame is re-fragmented at the new size. A frame that fails to send is
+    /// dropped.
+    pub fn flush(
+        &mut self,
+        link: &mut MediaLink,
+        room_id: u32,
+        user_id: u32,
+        seq: &mut u32,
+    ) -> Result<(), String> {
+        self.refill();
+        while self.budget > 0.0 {
+            let Some(frame) = self.frames.front_mut() else {
+                break;
+            };
+            let mut chunk_size = link.max_payload();
+            let sent = loop {
+                let end = (frame.offset + chunk_size).min(frame.data.len());
+                let is_last = end == frame.data.len();
+                let mut out = OutFrame::video(
+                    room_id,
+                    user_id,
+                    *seq,
+                    frame.timestamp,
+                    frame.is_keyframe && frame.offset == 0,
+                    is_last,
+                    Bytes::copy_from_slice(&frame.data[frame.offset..end]),
+                );
+                if frame.offset == 0 {
+                    out.header.flags |= FLAG_START_OF_FRAME;
+                }
+                match link.send(out.encode()) {
+                    Ok(()) => break Ok(end - frame.offset),
+                    Err(LinkError::TooLarge) => {
+                        let new_size = link.max_payload();
+                        if new_size >= chunk_size {
+                            break Err("send video fragment: datagram too large".to_string());
+                        }
+                        tracing::debug!(
+                            "Max datagram payload shrank {chunk_size} -> {new_size}, re-fragmenting"
+                        );
+                        chunk_size = new_size;
+                    }
+                    Err(e) => break Err(format!("send video fragment: {e}")),
+                }
+            };
+            match sent {
+                Ok(n) => {
+                    *seq = seq.wrapping_add(1);
+                    frame.offset += n;
+                    self.budget -= (n + HEADER_SIZE) as f64;
+                    if frame.offset == frame.data.len() {
+                        self.frames.pop_front();
+                    }
+                }
+                Err(e) => {
+                    self.frames.pop_front();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
     }
 }
 
-/// Outbound media frame to send to the SFU.
-pub struct OutFrame {
-    pub header: MediaHeader,
-    pub payload: Bytes,
+// ---------------------------------------------------------------------------
+// Media link: datagrams with stream fallback
+// ---------------------------------------------------------------------------
+
+/// How the client would like media carried on a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportPreference {
+    /// Datagrams when the peer supports them, streams otherwise.
+    Auto,
+    /// Datagrams only; never fall back.
+    Datagram,
+    /// Always use unidirectional streams.
+    Stream,
 }
 
-impl OutFrame {
-    /// Build an audio frame with sensible defaults.
-    pub fn audio(room_id: u32, user_id: u32, codec_id: u8, seq: u32, timestamp: u32, payload: Bytes) -> Self {
-        OutFrame {
-            header: MediaHeader {
-                version: PROTOCOL_VERSION,
-                media_type: MEDIA_TYPE_AUDIO,
-                codec_id,
-                flags: FLAG_END_OF_FRAME,
-                room_id,
-                user_id,
-                sequence: seq,
-                timestamp,
-                spatial_id: 0,
-                temporal_id: 0,
-                dtx: false,
-            },
-            payload,
+impl TransportPreference {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(TransportPreference::Auto),
+            "datagram" => Some(TransportPreference::Datagram),
+            "stream" => Some(TransportPreference::Stream),
+            _ => None,
         }
     }
+}
+
+/// How media is currently carried on a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportMode {
+    Datagram,
+    Stream,
+}
 
-    pub fn encode(&self) -> Bytes {
-        let header_bytes = self.header.encode();
-        let mut buf = BytesMut::with_capacity(HEADER_SIZE + self.payload.len());
-        buf.put_slice(&header_bytes);
-        buf.extend_from_slice(&self.payload);
-        buf.freeze()
+impl TransportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransportMode::Datagram => "datagram",
+            TransportMode::Stream => "stream",
+        }
     }
 }
 
-/// Inbound media frame received from the SFU.
-pub struct InFrame {
-    pub header: MediaHeader,
-    pub payload: Bytes,
+/// Stream frames carry a u16 length prefix.
+const MAX_STREAM_PACKET: usize = u16::MAX as usize;
+
+/// Media packets queued for the stream before the oldest are dropped, about
+/// a second of video at typical bitrates.
+const MAX_QUEUED_STREAM_PACKETS: usize = 256;
+
+/// How often `Auto` links measure datagram loss.
+const LOSS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Datagrams an interval needs before its loss counts (audio alone sends 50
+/// a second).
+const MIN_LOSS_SAMPLE: u64 = 25;
+
+/// Fraction of datagrams dropped that counts as heavy loss, and how many
+/// intervals running of it make an `Auto` link fall back to streams.
+const HEAVY_LOSS: f64 = 0.3;
+const HEAVY_LOSS_INTERVALS: u32 = 3;
+
+/// Error sending on a [`MediaLink`].
+#[derive(Debug)]
+pub enum LinkError {
+    /// The packet exceeds the current maximum datagram size.
+    TooLarge,
+    Other(String),
 }
 
-impl InFrame {
-    pub fn decode(data: Bytes) -> Option<Self> {
-        let header = MediaHeader::parse(&data)?;
-        let payload = data.slice(HEADER_SIZE..);
-        Some(InFrame { header, payload })
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::TooLarge => write!(f, "datagram too large"),
+            LinkError::Other(msg) => write!(f, "{msg}"),
+        }
     }
 }
 
-// ---------------------------------------------------------------------------
-// Video helpers: fragmentation + reassembly
-// ---------------------------------------------------------------------------
+/// Outbound media path for one session.
+///
+/// Sends packets as QUIC datagrams, or as length-prefixed frames on a single
+/// unidirectional stream when datagrams are unavailable. In `Auto` mode the
+/// link starts on datagrams if the peer advertised support and, if the peer
+/// also accepts media on streams, switches to streams for the rest of the
+/// session the first time the peer rejects one, or once heavy datagram loss
+/// has lasted a few seconds.
+/// Dropping the link closes the connection; [`MediaLink::close`] first
+/// drains queued stream packets.
+pub struct MediaLink {
+    connection: quinn::Connection,
+    preference: TransportPreference,
+    /// Whether the peer accepts media on streams, so `Auto` may fall back.
+    peer_streams: bool,
+    mode: TransportMode,
+    stream_queue: Option<Arc<StreamQueue>>,
+    stream_writer: Option<tokio::task::JoinHandle<()>>,
+    /// Datagrams handed to quinn so far.
+    datagrams_queued: u64,
+    loss: LossWindow,
+    loss_detector: LossDetector,
+}
 
-/// Maximum payload per fragment (1200 MTU - 22 header).
-pub const MAX_FRAGMENT_PAYLOAD: usize = 1178;
+/// Datagram counters, either running totals or the change over one loss
+/// measurement interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LossSample {
+    /// Datagrams handed to quinn.
+    pub queued: u64,
+    /// Datagram frames quinn transmitted.
+    pub transmitted: u64,
+    /// Packets sent on the path.
+    pub sent_packets: u64,
+    /// Packets on the path declared lost.
+    pub lost_packets: u64,
+}
 
-impl OutFrame {
-    /// Build a video frame datagram.
-    pub fn video(
-        room_id: u32,
-        user_id: u32,
-        seq: u32,
-        timestamp: u32,
-        is_keyframe: bool,
-        is_end_of_frame: bool,
-        payload: Bytes,
-    ) -> Self {
-        let mut flags: u8 = 0;
-        if is_keyframe {
-            flags |= FLAG_KEYFRAME;
-        }
-        if is_end_of_frame {
-            flags |= FLAG_END_OF_FRAME;
-        }
-        OutFrame {
-            header: MediaHeader {
-                version: PROTOCOL_VERSION,
-                media_type: MEDIA_TYPE_VIDEO,
-                codec_id: CODEC_AV1,
-                flags,
-                room_id,
-                user_id,
-                sequence: seq,
-                timestamp,
-                spatial_id: 0,
-                temporal_id: 0,
-                dtx: false,
-            },
-            payload,
-        }
-    }
-}
-
-/// Split a large AV1 frame into multiple datagrams sharing the same timestamp.
-/// The last fragment gets FLAG_END_OF_FRAME set.
-pub fn send_video_fragmented(
-    connection: &quinn::Connection,
-    room_id: u32,
-    user_id: u32,
-    start_seq: &mut u32,
-    timestamp: u32,
-    is_keyframe: bool,
-    data: &[u8],
-) -> Result<(), String> {
-    let chunks: Vec<&[u8]> = if data.is_empty() {
-        vec![&[]]
-    } else {
-        data.chunks(MAX_FRAGMENT_PAYLOAD).collect()
-    };
-    let last_idx = chunks.len() - 1;
-
-    for (i, chunk) in chunks.iter().enumerate() {
-        let is_last = i == last_idx;
-        let frame = OutFrame::video(
-            room_id,
-            user_id,
-            *start_seq,
-            timestamp,
-            is_keyframe && i == 0,
-            is_last,
-            Bytes::copy_from_slice(chunk),
-        );
-        connection
-            .send_datagram(frame.encode())
-            .map_err(|e| format!("send video fragment: {e}"))?;
-        *start_seq = start_seq.wrapping_add(1);
+impl LossSample {
+    fn of(connection: &quinn::Connection, queued: u64) -> Self {
+        let stats = connection.stats();
+        LossSample {
+            queued,
+            transmitted: stats.frame_tx.datagram,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+        }
+    }
+
+    /// The change in the counters since `earlier`.
+    fn since(&self, earlier: &LossSample) -> Self {
+        LossSample {
+            queued: self.queued.saturating_sub(earlier.queued),
+            transmitted: self.transmitted.saturating_sub(earlier.transmitted),
+            sent_packets: self.sent_packets.saturating_sub(earlier.sent_packets),
+            lost_packets: self.lost_packets.saturating_sub(earlier.lost_packets),
+        }
     }
 
-    Ok(())
+    /// Fraction of the queued datagrams that were dropped: those quinn
+    /// discarded unsent to make room for newer ones, then those lost with
+    /// their packets.
+    pub fn dropped(&self) -> f64 {
+        let transmitted = self.transmitted.min(self.queued);
+        let lost = self.lost_packets.min(self.sent_packets);
+        let delivered = transmitted as f64 / self.queued.max(1) as f64
+            * (1.0 - lost as f64 / self.sent_packets.max(1) as f64);
+        1.0 - delivered
+    }
 }
 
-/// Key for video fragment reassembly: (user_id, timestamp).
-#[derive(Hash, Eq, PartialEq, Clone)]
-struct ReassemblyKey {
-    user_id: u32,
-    timestamp: u32,
+/// Counters at the start of the current loss measurement interval.
+struct LossWindow {
+    started: Instant,
+    counts: LossSample,
 }
 
-/// Partial frame being assembled from fragments.
-struct PartialFrame {
-    fragments: Vec<(u32, Vec<u8>)>, // (sequence, payload)
-    is_keyframe: bool,
-    received_end: bool,
-    last_activity: Instant,
+impl LossWindow {
+    fn new(connection: &quinn::Connection, queued: u64) -> Self {
+        LossWindow {
+            started: Instant::now(),
+            counts: LossSample::of(connection, queued),
+        }
+    }
 }
 
-/// Reassembles fragmented video datagrams into complete AV1 frames.
-pub struct VideoReassembler {
-    pending: HashMap<ReassemblyKey, PartialFrame>,
+/// Decides when an `Auto` link falls back to streams: after
+/// `HEAVY_LOSS_INTERVALS` intervals running with at least `HEAVY_LOSS` of
+/// datagrams dropped. An interval with less loss starts the count again;
+/// one with too few datagrams to measure leaves it as it is.
+#[derive(Debug, Default)]
+pub struct LossDetector {
+    /// Intervals running with heavy loss.
+    heavy: u32,
 }
 
-/// A fully reassembled video frame ready for decoding.
-pub struct ReassembledFrame {
-    pub user_id: u32,
-    pub timestamp: u32,
-    pub is_keyframe: bool,
-    pub data: Vec<u8>,
+impl LossDetector {
+    /// Record one interval's counts. Returns whether to fall back.
+    pub fn record(&mut self, interval: &LossSample) -> bool {
+        if interval.queued < MIN_LOSS_SAMPLE {
+            return false;
+        }
+        let dropped = interval.dropped();
+        if dropped < HEAVY_LOSS {
+            self.heavy = 0;
+            return false;
+        }
+        self.heavy += 1;
+        tracing::debug!("{:.0}% of datagrams dropped in the last interval", dropped * 100.0);
+        self.heavy >= HEAVY_LOSS_INTERVALS
+    }
 }
 
-impl VideoReassembler {
-    pub fn new() -> Self {
-        VideoReassembler {
-            pending: HashMap::new(),
+impl MediaLink {
+    /// `peer_streams` is whether the peer advertised `CAP_STREAM_FALLBACK`.
+    /// Without it an `Auto` link stays on datagrams whatever the loss.
+    pub fn new(connection: quinn::Connection, preference: TransportPreference, peer_streams: bool) -> Self {
+        let mode = match preference {
+            TransportPreference::Stream => TransportMode::Stream,
+            TransportPreference::Datagram => TransportMode::Datagram,
+            TransportPreference::Auto if connection.max_datagram_size().is_some() => {
+                TransportMode::Datagram
+            }
+            TransportPreference::Auto => {
+                tracing::info!("Peer does not support datagrams, using QUIC streams");
+                TransportMode::Stream
+            }
+        };
+        let loss = LossWindow::new(&connection, 0);
+        let mut link = MediaLink {
+            connection,
+            preference,
+            peer_streams,
+            mode,
+            stream_queue: None,
+            stream_writer: None,
+            datagrams_queued: 0,
+            loss,
+            loss_detector: LossDetector::default(),
+        };
+        if mode == TransportMode::Stream {
+            link.start_stream_writer();
+        }
+        link
+    }
+
+    fn start_stream_writer(&mut self) {
+        let queue = Arc::new(StreamQueue::default());
+        self.stream_writer = Some(spawn_stream_writer(self.connection.clone(), Arc::clone(&queue)));
+        self.stream_queue = Some(queue);
+    }
+
+    fn fall_back_to_streams(&mut self) {
+        self.mode = TransportMode::Stream;
+        if self.stream_queue.is_none() {
+            self.start_stream_writer();
+        }
+    }
+
+    /// Wait until packets queued on the stream have been acknowledged, then
+    /// close the connection with `code`. Callers should bound this with a
+    /// timeout; dropping the future still closes the connection on drop.
+    pub async fn close(&mut self, code: u32, reason: &[u8]) {
+        if let Some(queue) = self.stream_queue.take() {
+            queue.close();
+        }
+        if let Some(writer) = self.stream_writer.take() {
+            let _ = writer.await;
+        }
+        self.connection.close(code.into(), reason);
+    }
+
+    pub fn connection(&self) -> &quinn::Connection {
+        &self.connection
+    }
+
+    pub fn mode(&self) -> TransportMode {
+        self.mode
+    }
+
+    /// Largest media payload that fits in one packet on this link.
+    pub fn max_payload(&self) -> usize {
+        match self.mode {
+            TransportMode::Datagram => max_fragment_payload(&self.connection),
+            TransportMode::Stream => MAX_FRAGMENT_PAYLOAD,
+        }
+    }
+
+    /// Send one packet reliably and in order on the link's stream,
+    /// regardless of the current mode.
+    pub fn send_reliable(&mut self, packet: Bytes) -> Result<(), LinkError> {
+        if self.stream_queue.is_none() {
+            self.start_stream_writer();
         }
+        self.send_stream(packet, true)
     }
 
-    /// Add a video datagram fragment. Returns a complete frame when all
-    /// fragments have arrived (END_OF_FRAME seen and contiguous sequence).
-    pub fn add_fragment(&mut self, header: &MediaHeader, payload: &[u8]) -> Option<ReassembledFrame> {
-        let key = ReassemblyKey {
-            user_id: header.user_id,
-            timestamp: header.timestamp,
+    /// Send one encoded packet (header + payload).
+    pub fn send(&mut self, packet: Bytes) -> Result<(), LinkError> {
+        if self.mode == TransportMode::Stream {
+            return self.send_stream(packet, false);
+        }
+        let sent = {
+            let _timer = profile::DATAGRAM_SEND.start();
+            self.connection.send_datagram(packet.clone())
         };
+        match sent {
+            Ok(()) => {
+                self.datagrams_queued += 1;
+                if self.can_fall_back() {
+                    self.check_datagram_loss();
+                }
+                Ok(())
+            }
+            Err(quinn::SendDatagramError::TooLarge) => Err(LinkError::TooLarge),
+            Err(quinn::SendDatagramError::UnsupportedByPeer | quinn::SendDatagramError::Disabled)
+                if self.can_fall_back() =>
+            {
+                tracing::warn!("Peer rejected datagrams, falling back to QUIC streams");
+                self.fall_back_to_streams();
+                self.send_stream(packet, false)
+            }
+            Err(e) => Err(LinkError::Other(e.to_string())),
+        }
+    }
 
-        let partial = self.pending.entry(key.clone()).or_insert_with(|| PartialFrame {
-            fragments: Vec::new(),
-            is_keyframe: false,
-            received_end: false,
-            last_activity: Instant::now(),
-        });
+    /// Whether the link may switch from datagrams to streams: only in
+    /// `Auto` mode, and only if the peer accepts media on streams.
+    fn can_fall_back(&self) -> bool {
+        self.preference == TransportPreference::Auto && self.peer_streams
+    }
 
-        if header.is_keyframe() {
-            partial.is_keyframe = true;
+    /// Once per interval, measure datagram loss and fall back to streams
+    /// after a few heavy intervals.
+    fn check_datagram_loss(&mut self) {
+        if self.loss.started.elapsed() < LOSS_CHECK_INTERVAL {
+            return;
         }
-        if header.is_end_of_frame() {
-            partial.received_end = true;
+        let previous = std::mem::replace(&mut self.loss, LossWindow::new(&self.connection, self.datagrams_queued));
+        if self.loss_detector.record(&self.loss.counts.since(&previous.counts)) {
+            tracing::warn!("Datagrams are being dropped heavily, falling back to QUIC streams");
+            self.fall_back_to_streams();
         }
+    }
 
-        partial.fragments.push((header.sequence, payload.to_vec()));
-        partial.last_activity = Instant::now();
+    fn send_stream(&self, packet: Bytes, reliable: bool) -> Result<(), LinkError> {
+        if packet.len() > MAX_STREAM_PACKET {
+            return Err(LinkError::Other(format!(
+                "packet of {} bytes exceeds stream frame limit",
+                packet.len()
+            )));
+        }
+        self.stream_queue
+            .as_ref()
+            .ok_or_else(|| LinkError::Other("stream writer not running".into()))?
+            .push(packet, reliable)
+    }
+}
 
-        if partial.received_end {
-            let mut partial = self.pending.remove(&key).unwrap();
-            // Sort by sequence number and concatenate
-            partial.fragments.sort_by_key(|(seq, _)| *seq);
-            let data: Vec<u8> = partial.fragments.into_iter().flat_map(|(_, d)| d).collect();
-            Some(ReassembledFrame {
-                user_id: key.user_id,
-                timestamp: key.timestamp,
-                is_keyframe: partial.is_keyframe,
-                data,
-            })
-        } else {
-            None
+impl Drop for MediaLink {
+    fn drop(&mut self) {
+        if let Some(queue) = &self.stream_queue {
+            queue.close();
+        }
+        self.connection.close(CLOSE_NORMAL.into(), b"session closed");
+    }
+}
+
+/// Packets waiting for the stream writer. Media packets are capped at
+/// [`MAX_QUEUED_STREAM_PACKETS`], the oldest going first while the stream
+/// is stalled; reliable packets are never dropped.
+#[derive(Default)]
+pub struct StreamQueue {
+    state: Mutex<StreamQueueState>,
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct StreamQueueState {
+    /// `(packet, reliable)`, oldest first.
+    packets: VecDeque<(Bytes, bool)>,
+    /// Media (not reliable) packets in `packets`.
+    media: usize,
+    /// Media packets dropped to stay within the cap.
+    dropped: u64,
+    closed: bool,
+}
+
+impl StreamQueue {
+    fn state(&self) -> std::sync::MutexGuard<'_, StreamQueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queue a packet, dropping the oldest media packet if media is at
+    /// the cap. Fails once the queue is closed.
+    pub fn push(&self, packet: Bytes, reliable: bool) -> Result<(), LinkError> {
+        {
+            let mut state = self.state();
+            if state.closed {
+                return Err(LinkError::Other("stream writer closed".into()));
+            }
+            if !reliable && state.media >= MAX_QUEUED_STREAM_PACKETS {
+                if let Some(oldest) = state.packets.iter().position(|(_, reliable)| !reliable) {
+                    state.packets.remove(oldest);
+                    state.media -= 1;
+                    if state.dropped == 0 {
+                        tracing::warn!("Media stream is stalled, dropping the oldest packets");
+                    }
+                    state.dropped += 1;
+                }
+            }
+            state.packets.push_back((packet, reliable));
+            if !reliable {
+                state.media += 1;
+            }
         }
+        self.ready.notify_one();
+        Ok(())
     }
 
-    /// Evict stale partial frames older than the given duration.
-    pub fn evict_stale(&mut self, max_age: std::time::Duration) {
-        let now = Instant::now();
-        self.pending.retain(|_, v| now.duration_since(v.last_activity) < max_age);
+    /// The next packet, or None once the queue is closed and empty.
+    pub async fn pop(&self) -> Option<Bytes> {
+        loop {
+            {
+                let mut state = self.state();
+                if let Some((packet, reliable)) = state.packets.pop_front() {
+                    if !reliable {
+                        state.media -= 1;
+                    }
+                    return Some(packet);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    /// Accept no more packets; those queued are still written.
+    pub fn close(&self) {
+        self.state().closed = true;
+        self.ready.notify_one();
+    }
+
+    /// Media packets dropped to stay within the cap.
+    pub fn dropped(&self) -> u64 {
+        self.state().dropped
+    }
+}
+
+/// Open a unidirectional stream and write each queued packet to it with a
+/// big-endian u16 length prefix. Once the queue is closed the stream is
+/// finished and the task ends when the peer has acknowledged all of it.
+fn spawn_stream_writer(connection: quinn::Connection, queue: Arc<StreamQueue>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut stream = match connection.open_uni().await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("Failed to open media stream: {e}");
+                queue.close();
+                return;
+            }
+        };
+        while let Some(packet) = queue.pop().await {
+            let len = (packet.len() as u16).to_be_bytes();
+            if let Err(e) = stream.write_all(&len).await {
+                tracing::warn!("Media stream write error: {e}");
+                queue.close();
+                return;
+            }
+            if let Err(e) = stream.write_all(&packet).await {
+                tracing::warn!("Media stream write error: {e}");
+                queue.close();
+                return;
+            }
+        }
+        let dropped = queue.dropped();
+        if dropped > 0 {
+            tracing::debug!("Dropped {dropped} media packets while the stream was stalled");
+        }
+        if stream.finish().is_ok() {
+            let _ = stream.stopped().await;
+        }
+    })
+}
+
+/// Accept unidirectional streams from the peer and yield each
+/// length-prefixed packet on them, as if it had arrived as a datagram.
+///
+/// Always running, so the SFU can fall back to streams independently of us.
+pub fn spawn_stream_reader(connection: quinn::Connection) -> mpsc::UnboundedReceiver<Bytes> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok(mut recv) = connection.accept_uni().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut len_buf = [0u8; 2];
+                loop {
+                    if recv.read_exact(&mut len_buf).await.is_err() {
+                        break;
+                    }
+                    let mut buf = vec![0u8; u16::from_be_bytes(len_buf) as usize];
+                    if recv.read_exact(&mut buf).await.is_err() {
+                        break;
+                    }
+                    if tx.send(Bytes::from(buf)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    rx
+}
+
+/// Congestion control algorithm for the QUIC connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionController {
+    Bbr,
+    Cubic,
+    NewReno,
+}
+
+impl CongestionController {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "bbr" => Some(CongestionController::Bbr),
+            "cubic" => Some(CongestionController::Cubic),
+            "newreno" => Some(CongestionController::NewReno),
+            _ => None,
+        }
+    }
+}
+
+/// Smallest UDP payload QUIC allows (RFC 9000 §14).
+pub const MIN_UDP_PAYLOAD: u16 = 1200;
+
+/// Transport tuning for high-latency or lossy links. `None` keeps quinn's default.
+#[derive(Debug, Clone)]
+pub struct TransportTuning {
+    pub congestion: CongestionController,
+    pub keep_alive: Option<std::time::Duration>,
+    /// Bytes of unacknowledged data the client may have in flight.
+    pub send_window: Option<u64>,
+    /// Bytes the peer may send before the client grants more credit.
+    pub receive_window: Option<u64>,
+    /// Upper bound for path MTU discovery.
+    pub max_udp_payload: Option<u16>,
+}
+
+impl Default for TransportTuning {
+    fn default() -> Self {
+        TransportTuning {
+            congestion: CongestionController::Cubic,
+            keep_alive: None,
+            send_window: None,
+            receive_window: None,
+            max_udp_payload: None,
+        }
+    }
+}
+
+impl TransportTuning {
+    /// Apply the tuning on top of an existing transport config.
+    pub fn apply(&self, config: &mut quinn::TransportConfig) -> Result<(), String> {
+        match self.congestion {
+            CongestionController::Bbr => {
+                config.congestion_controller_factory(Arc::new(quinn::congestion::BbrConfig::default()))
+            }
+            CongestionController::Cubic => {
+                config.congestion_controller_factory(Arc::new(quinn::congestion::CubicConfig::default()))
+            }
+            CongestionController::NewReno => config
+                .congestion_controller_factory(Arc::new(quinn::congestion::NewRenoConfig::default())),
+        };
+        if let Some(interval) = self.keep_alive {
+            config.keep_alive_interval(Some(interval));
+        }
+        if let Some(window) = self.send_window {
+            config.send_window(window);
+        }
+        if let Some(window) = self.receive_window {
+            let window = quinn::VarInt::from_u64(window)
+                .map_err(|_| format!("receive window {window} too large"))?;
+            config.receive_window(window);
+            config.stream_receive_window(window);
+        }
+        if let Some(max) = self.max_udp_payload {
+            let mut mtu = quinn::MtuDiscoveryConfig::default();
+            mtu.upper_bound(max);
+            config.mtu_discovery_config(Some(mtu));
+        }
+        Ok(())
+    }
+}
+
+/// Local socket options for the client endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct BindOptions {
+    /// Local address to bind. Defaults to the unspecified address of the
+    /// remote's family (so IPv6-only hosts work without configuration).
+    pub address: Option<IpAddr>,
+    /// Inclusive local port range to try in order. Defaults to an ephemeral port.
+    pub ports: Option<(u16, u16)>,
+    /// Network interface to bind to (Linux `SO_BINDTODEVICE`).
+    pub interface: Option<String>,
+    /// DSCP code point (0–63) to mark outgoing packets with.
+    pub dscp: Option<u8>,
+}
+
+/// DSCP Expedited Forwarding, for interactive voice (RFC 3246).
+pub const DSCP_EF: u8 = 46;
+/// DSCP Assured Forwarding class 4, low drop, for interactive video (RFC 2597).
+pub const DSCP_AF41: u8 = 34;
+
+/// Create a client endpoint bound according to `opts`.
+pub fn bind_endpoint(
+    opts: &BindOptions,
+    remote: SocketAddr,
+) -> Result<quinn::Endpoint, Box<dyn std::error::Error>> {
+    let socket = bind_socket(opts, remote)?;
+    if opts.dscp.is_none() {
+        let endpoint = quinn::Endpoint::new(
+            quinn::EndpointConfig::default(),
+            None,
+            socket,
+            Arc::new(quinn::TokioRuntime),
+        )?;
+        return Ok(endpoint);
+    }
+
+    let runtime = Arc::new(quinn::TokioRuntime);
+    let inner = quinn::Runtime::wrap_udp_socket(&*runtime, socket)?;
+    let endpoint = quinn::Endpoint::new_with_abstract_socket(
+        quinn::EndpointConfig::default(),
+        None,
+        Arc::new(DscpUdpSocket { inner }),
+        runtime,
+    )?;
+    Ok(endpoint)
+}
+
+/// Wrapper that stops quinn from writing the ECN bits as a per-packet TOS
+/// control message, which would replace the socket's DSCP marking with zero.
+#[derive(Debug)]
+struct DscpUdpSocket {
+    inner: Arc<dyn quinn::AsyncUdpSocket>,
+}
+
+impl quinn::AsyncUdpSocket for DscpUdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> std::pin::Pin<Box<dyn quinn::UdpPoller>> {
+        self.inner.clone().create_io_poller()
+    }
+
+    fn try_send(&self, transmit: &quinn::udp::Transmit) -> std::io::Result<()> {
+        self.inner.try_send(&quinn::udp::Transmit { ecn: None, ..*transmit })
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut std::task::Context,
+        bufs: &mut [std::io::IoSliceMut<'_>],
+        meta: &mut [quinn::udp::RecvMeta],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.inner.poll_recv(cx, bufs, meta)
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn max_transmit_segments(&self) -> usize {
+        self.inner.max_transmit_segments()
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.inner.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.inner.may_fragment()
+    }
+}
+
+/// Bind a non-blocking UDP socket according to `opts`, for talking to `remote`.
+pub fn bind_socket(
+    opts: &BindOptions,
+    remote: SocketAddr,
+) -> Result<std::net::UdpSocket, Box<dyn std::error::Error>> {
+    let ip = opts.address.unwrap_or(if remote.is_ipv6() {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    });
+    let (first_port, last_port) = opts.ports.unwrap_or((0, 0));
+
+    let mut last_err: Option<std::io::Error> = None;
+    for port in first_port..=last_port {
+        let addr = SocketAddr::new(ip, port);
+        let socket = socket2::Socket::new(
+            socket2::Domain::for_address(addr),
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+        if let Some(iface) = &opts.interface {
+            bind_to_interface(&socket, iface)?;
+        }
+        if let Some(dscp) = opts.dscp {
+            // Best effort: some platforms or sandboxes refuse to set TOS
+            if let Err(e) = set_dscp(&socket, addr.is_ipv6(), dscp) {
+                tracing::warn!("Could not set DSCP {dscp} on media socket: {e}");
+            }
+        }
+        match socket.bind(&addr.into()) {
+            Ok(()) => {
+                socket.set_nonblocking(true)?;
+                tracing::debug!("Bound media socket to {addr}");
+                return Ok(socket.into());
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(format!(
+        "Failed to bind {ip} on ports {first_port}-{last_port}: {}",
+        last_err.map_or_else(|| "empty port range".to_string(), |e| e.to_string())
+    )
+    .into())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_to_interface(socket: &socket2::Socket, iface: &str) -> std::io::Result<()> {
+    socket.bind_device(Some(iface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_to_interface(_socket: &socket2::Socket, iface: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("binding to interface {iface:?} is not supported on this platform"),
+    ))
+}
+
+/// Set the DSCP bits of the IPv4 TOS / IPv6 traffic class byte.
+fn set_dscp(socket: &socket2::Socket, ipv6: bool, dscp: u8) -> std::io::Result<()> {
+    let tos = u32::from(dscp) << 2;
+    if ipv6 {
+        set_tclass_v6(socket, tos)?;
+        // Dual-stack sockets may also carry IPv4-mapped traffic
+        let _ = socket.set_tos(tos);
+        Ok(())
+    } else {
+        socket.set_tos(tos)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+fn set_tclass_v6(socket: &socket2::Socket, tclass: u32) -> std::io::Result<()> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd")))]
+fn set_tclass_v6(_socket: &socket2::Socket, _tclass: u32) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "IPv6 traffic class is not supported on this platform",
+    ))
+}
+
+/// Server certificate pins. Empty means CA-signed mode.
+#[derive(Debug, Clone, Default)]
+pub struct CertPins {
+    /// Exact end-entity certificate DER bytes.
+    pub certs: Vec<Vec<u8>>,
+    /// SHA-256 hashes of the end-entity SubjectPublicKeyInfo.
+    pub spki_sha256: Vec<[u8; 32]>,
+}
+
+impl CertPins {
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty() && self.spki_sha256.is_empty()
+    }
+}
+
+/// Client certificate presented to the SFU for mutual TLS.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    /// DER certificate chain, end-entity first.
+    pub cert_chain: Vec<Vec<u8>>,
+    /// DER private key (PKCS#8, PKCS#1 or SEC1).
+    pub key: Vec<u8>,
+}
+
+/// TLS alerts a server sends when it rejects (or requires) a client certificate.
+const CLIENT_AUTH_ALERTS: &[u8] = &[
+    42,  // bad_certificate
+    43,  // unsupported_certificate
+    44,  // certificate_revoked
+    45,  // certificate_expired
+    46,  // certificate_unknown
+    48,  // unknown_ca
+    49,  // access_denied
+    116, // certificate_required
+];
+
+/// Client certificate authentication failed, either locally (unusable
+/// certificate or key) or because the server rejected it.
+#[derive(Debug)]
+pub struct ClientAuthError(pub String);
+
+impl std::fmt::Display for ClientAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "client auth failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for ClientAuthError {}
+
+/// Classify a handshake failure, separating client certificate rejections
+/// from other connection errors.
+pub fn connect_error(err: quinn::ConnectionError) -> Box<dyn std::error::Error> {
+    let code = match &err {
+        quinn::ConnectionError::ConnectionClosed(close) => Some(close.error_code),
+        quinn::ConnectionError::TransportError(e) => Some(e.code),
+        _ => None,
+    };
+    let rejected = code.is_some_and(|code| {
+        CLIENT_AUTH_ALERTS
+            .iter()
+            .any(|&alert| code == quinn::TransportErrorCode::crypto(alert))
+    });
+    if rejected {
+        Box::new(ClientAuthError(format!("server rejected client certificate ({err})")))
+    } else {
+        Box::new(err)
     }
 }
 
 /// Build a QUIC client config.
 ///
-/// - `None` → CA-signed mode: uses Mozilla root certificates.
-/// - `Some(der)` → Self-signed mode: pins to the exact certificate DER bytes.
-pub fn make_client_config(cert_der: Option<Vec<u8>>) -> Result<ClientConfig, Box<dyn std::error::Error>> {
-    let mut crypto = match cert_der {
-        None => {
-            let mut roots = rustls::RootCertStore::empty();
-            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-            rustls::ClientConfig::builder()
-                .with_root_certificates(roots)
-                .with_no_client_auth()
-        }
-        Some(der) => {
-            rustls::ClientConfig::builder()
-                .dangerous()
-                .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier { der }))
-                .with_no_client_auth()
+/// - No pins → CA-signed mode: uses Mozilla root certificates.
+/// - Pins → Self-signed mode: the server certificate must match any pinned
+///   DER certificate exactly, or have a pinned SPKI hash. SPKI pins survive
+///   certificate rotation as long as the key is kept.
+///
+/// When `identity` is given, it is presented as the client certificate.
+pub fn make_client_config(
+    pins: CertPins,
+    identity: Option<ClientIdentity>,
+) -> Result<ClientConfig, Box<dyn std::error::Error>> {
+    let builder = if pins.is_empty() {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        rustls::ClientConfig::builder().with_root_certificates(roots)
+    } else {
+        rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier { pins }))
+    };
+    let mut crypto = match identity {
+        Some(id) => {
+            let chain = id
+                .cert_chain
+                .into_iter()
+                .map(rustls::pki_types::CertificateDer::from)
+                .collect();
+            let key = rustls::pki_types::PrivateKeyDer::try_from(id.key)
+                .map_err(|e| ClientAuthError(format!("invalid client key: {e}")))?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .map_err(|e| ClientAuthError(format!("invalid client certificate: {e}")))?
         }
+        None => builder.with_no_client_auth(),
     };
     crypto.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
     let quic_config = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
@@ -359,12 +1062,71 @@ pub fn make_client_config(cert_der: Option<Vec<u8>>) -> Result<ClientConfig, Box
     Ok(ClientConfig::new(Arc::new(quic_config)))
 }
 
-/// Verifies the server certificate by comparing its raw DER bytes against a
-/// pinned value, then delegates signature verification to the default ring
-/// provider.
+/// Verifies the server certificate against pinned DER bytes or SPKI hashes,
+/// then delegates signature verification to the default ring provider.
 #[derive(Debug)]
 struct PinnedCertVerifier {
-    der: Vec<u8>,
+    pins: CertPins,
+}
+
+impl PinnedCertVerifier {
+    fn matches(&self, der: &[u8]) -> bool {
+        if self.pins.certs.iter().any(|pin| pin.as_slice() == der) {
+            return true;
+        }
+        if self.pins.spki_sha256.is_empty() {
+            return false;
+        }
+        match extract_spki(der) {
+            Some(spki) => {
+                let hash: [u8; 32] = Sha256::digest(spki).into();
+                self.pins.spki_sha256.contains(&hash)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Read one DER TLV at the start of `data`, returning (tag, full TLV, content).
+fn der_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_len = *data.get(1)? as usize;
+    let (len, header) = if first_len < 0x80 {
+        (first_len, 2)
+    } else {
+        let n = first_len & 0x7F;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let bytes = data.get(2..2 + n)?;
+        (bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize), 2 + n)
+    };
+    let end = header.checked_add(len)?;
+    Some((tag, data.get(..end)?, data.get(header..end)?))
+}
+
+/// Extract the DER-encoded SubjectPublicKeyInfo from an X.509 certificate.
+///
+/// ```text
+/// Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+/// TBSCertificate ::= SEQUENCE { [0] version OPTIONAL, serialNumber, signature,
+///                               issuer, validity, subject, subjectPublicKeyInfo, ... }
+/// ```
+fn extract_spki(cert_der: &[u8]) -> Option<&[u8]> {
+    let (_, _, cert) = der_tlv(cert_der)?;
+    let (_, _, mut tbs) = der_tlv(cert)?;
+    // Skip the explicit [0] version tag if present
+    if tbs.first() == Some(&0xA0) {
+        let (_, whole, _) = der_tlv(tbs)?;
+        tbs = &tbs[whole.len()..];
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        let (_, whole, _) = der_tlv(tbs)?;
+        tbs = &tbs[whole.len()..];
+    }
+    let (tag, spki, _) = der_tlv(tbs)?;
+    (tag == 0x30).then_some(spki)
 }
 
 impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
@@ -376,7 +1138,7 @@ impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
         _ocsp_response: &[u8],
         _now: rustls::pki_types::UnixTime,
     ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        if end_entity.as_ref() == self.der.as_slice() {
+        if self.matches(end_entity.as_ref()) {
             Ok(rustls::client::danger::ServerCertVerified::assertion())
         } else {
             Err(rustls::Error::InvalidCertificate(
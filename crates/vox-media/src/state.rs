@@ -1,29 +1,61 @@
 //! Media state machine — processes commands from Python.
 
 use crate::{
-    audio, codec, push_event, push_video_frame, quic, video, EventQueue, MediaCommand,
-    MediaEvent, VideoFrameOutput, VideoFrameQueue,
+    audio, codec, handshake, profile, proxy, push_data_message, push_event, push_video_frame, quic, transform, video,
+    DataMessage, DataQueue, EventQueue, MediaCommand, MediaEvent, MediaKeyRing, UserStatsMap, VideoFrameOutput,
+    VideoFrameQueue,
 };
 use bytes::Bytes;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
+use vox_core::session::{MediaSession, ReceiveStats, SessionEvent};
+use vox_core::settings::MediaSettings;
+use vox_core::ratelimit::{RateLimiter, RateLimits};
+use vox_core::validate::{self, MalformedTracker, ValidationConfig};
 
-/// Maximum number of automatic reconnection attempts after a QUIC read error.
+/// Automatic reconnection attempts after a QUIC read error, unless the
+/// settings store says otherwise.
 const MAX_RECONNECT_ATTEMPTS: u32 = 5;
-/// Maximum backoff delay in seconds between reconnection attempts.
+/// Default maximum backoff delay in seconds between reconnection attempts.
 const MAX_BACKOFF_SECS: u64 = 30;
-/// Evict idle per-user video decoders after this duration.
+/// Default time after which idle per-user decoders are evicted.
 const DECODER_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most consecutive lost audio frames replaced with concealment; longer
+/// gaps are left silent.
+const MAX_CONCEALED_FRAMES: u32 = 5;
+/// Wait this long before retrying a decoder that failed to initialize.
+const DECODER_RETRY_INTERVAL: Duration = Duration::from_secs(5);
 /// Evict stale partial video frames after this duration.
 const REASSEMBLY_STALE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Samples per captured audio frame (20 ms at 48 kHz).
+const CAPTURE_FRAME_SAMPLES: usize = 960;
+/// How often local audio glitch counters are checked for `audio_warning`.
+const AUDIO_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+/// Minimum time between automatic rebuilds of the same audio stream.
+const DEVICE_FALLBACK_INTERVAL: Duration = Duration::from_secs(2);
+/// Upper bound on flushing media and closing the connection on disconnect/stop.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 /// RMS threshold (normalized 0.0–1.0) above which a user is considered speaking.
 const SPEAKING_THRESHOLD: f64 = 0.01;
+/// Opus bitrate budgeted for audio when splitting a capped uplink.
+const AUDIO_BITRATE_KBPS: u32 = 32;
+/// Lowest Opus bitrate used under an uplink cap.
+const MIN_AUDIO_BITRATE_KBPS: u32 = 6;
+/// Below this budget video is paused rather than sent at unusable quality.
+const MIN_VIDEO_BITRATE_KBPS: u32 = 100;
 /// How long after the last above-threshold frame before emitting speaking_stop.
 const SPEAKING_HOLDOFF: Duration = Duration::from_millis(200);
+/// Most recent captured audio held while reconnecting and sent once the new
+/// session is up; older audio is dropped.
+const RECONNECT_AUDIO_BUFFER: Duration = Duration::from_secs(2);
+/// Spacing of buffered audio frames replayed after a reconnect: twice real
+/// time, so the backlog drains without flooding receivers' jitter buffers.
+const REPLAY_INTERVAL: Duration = Duration::from_millis(10);
 
 /// Snapshot of connection parameters for automatic reconnection.
 #[derive(Clone)]
@@ -32,30 +64,256 @@ struct ConnectParams {
     token: String,
     room_id: u32,
     user_id: u32,
-    cert_der: Option<Vec<u8>>,
+    pins: quic::CertPins,
+    client_identity: Option<quic::ClientIdentity>,
     idle_timeout_secs: u64,
     datagram_buffer_size: usize,
     input_device: Option<String>,
     output_device: Option<String>,
+    transport: quic::TransportPreference,
+    bind: quic::BindOptions,
+    proxy: Option<proxy::ProxyConfig>,
+    tuning: quic::TransportTuning,
+    validation: Option<ValidationConfig>,
+    rate_limits: RateLimits,
+}
+
+/// What the application set through commands. Kept by the media loop
+/// rather than the session, so it survives reconnects and can be set before
+/// connecting; every new session starts from it.
+struct LocalSettings {
+    muted: bool,
+    deafened: bool,
+    input_volume: f32,
+    output_volume: f32,
+    noise_gate_threshold: f32,
+    user_volumes: HashMap<u32, f32>,
+    ducking: Option<audio::DuckingConfig>,
+    comfort_noise: bool,
+    /// Mix priority and the gain applied while outranked.
+    mix_priority: (i32, f32),
+    video_config: VideoConfig,
+    /// Shared with each session, so changes reach a running camera.
+    effects: video::LiveEffects,
+    max_uplink_kbps: Option<u32>,
+    decoder_idle_timeout: Duration,
+    receive_prefs: quic::ReceivePreferences,
+}
+
+impl LocalSettings {
+    /// Defaults, with the settings store's volumes and camera.
+    fn new(settings: &MediaSettings) -> Self {
+        let mut video_config = VideoConfig::default();
+        if let Some(camera) = settings.camera {
+            video_config.camera = camera;
+        }
+        LocalSettings {
+            muted: false,
+            deafened: false,
+            input_volume: settings.input_volume.unwrap_or(1.0),
+            output_volume: settings.output_volume.unwrap_or(1.0),
+            noise_gate_threshold: 0.0,
+            user_volumes: HashMap::new(),
+            ducking: None,
+            comfort_noise: true,
+            mix_priority: (0, 1.0),
+            video_config,
+            effects: video::LiveEffects::default(),
+            max_uplink_kbps: None,
+            decoder_idle_timeout: DECODER_IDLE_TIMEOUT,
+            receive_prefs: quic::ReceivePreferences::default(),
+        }
+    }
+}
+
+/// Set a remote user's playback volume; 1.0 removes the entry.
+fn set_user_volume(volumes: &mut HashMap<u32, f32>, user_id: u32, volume: f32) {
+    if (volume - 1.0).abs() < f32::EPSILON {
+        volumes.remove(&user_id);
+    } else {
+        volumes.insert(user_id, volume);
+    }
 }
 
 /// Video configuration (set before enabling video).
 #[derive(Clone)]
 struct VideoConfig {
+    camera: u32,
     width: u32,
     height: u32,
     fps: u32,
     bitrate_kbps: u32,
+    rotation: video::Rotation,
+    mirror_preview: bool,
+    scale_mode: video::ScaleMode,
 }
 
 impl Default for VideoConfig {
     fn default() -> Self {
         VideoConfig {
+            camera: 0,
             width: 640,
             height: 480,
             fps: 30,
             bitrate_kbps: 500,
+            rotation: video::Rotation::None,
+            mirror_preview: false,
+            scale_mode: video::ScaleMode::Crop,
+        }
+    }
+}
+
+impl VideoConfig {
+    fn camera_config(&self) -> video::CameraConfig {
+        video::CameraConfig {
+            device: self.camera,
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+            rotation: self.rotation,
+            mirror_preview: self.mirror_preview,
+            scale_mode: self.scale_mode,
+        }
+    }
+
+    /// Frame size as seen by the encoder.
+    fn encoded_size(&self) -> (usize, usize) {
+        self.camera_config().output_size()
+    }
+}
+
+/// How the uplink budget is split between video and audio.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UplinkPlan {
+    /// Video encoder bitrate, or `None` while video is paused by the cap.
+    pub video_kbps: Option<u32>,
+    /// Opus bitrate, or `None` for the libopus default.
+    pub audio_kbps: Option<u32>,
+    /// Only send audio while the local user is speaking.
+    pub vad: bool,
+}
+
+impl UplinkPlan {
+    /// Respect `max_uplink_kbps` by lowering video quality first, then
+    /// pausing video, and finally lowering the audio bitrate and gating
+    /// audio on voice activity.
+    pub fn new(max_uplink_kbps: Option<u32>, video_kbps: u32) -> Self {
+        let Some(cap) = max_uplink_kbps else {
+            return UplinkPlan { video_kbps: Some(video_kbps), audio_kbps: None, vad: false };
+        };
+        let video_budget = cap.saturating_sub(AUDIO_BITRATE_KBPS);
+        if video_budget >= MIN_VIDEO_BITRATE_KBPS {
+            return UplinkPlan {
+                video_kbps: Some(video_kbps.min(video_budget)),
+                audio_kbps: Some(AUDIO_BITRATE_KBPS),
+                vad: false,
+            };
+        }
+        UplinkPlan {
+            video_kbps: None,
+            audio_kbps: Some(cap.clamp(MIN_AUDIO_BITRATE_KBPS, AUDIO_BITRATE_KBPS)),
+            vad: cap < AUDIO_BITRATE_KBPS,
+        }
+    }
+}
+
+/// Outgoing media held across `reconnect_with_backoff`, so a brief network
+/// blip costs a hiccup rather than the speech captured while the link was
+/// down.
+struct UplinkBuffer {
+    /// Processed capture frames, oldest first.
+    audio: VecDeque<Vec<i16>>,
+    muted: bool,
+    /// Whether the lost session was sending video.
+    video: bool,
+}
+
+impl UplinkBuffer {
+    /// Take what the lost session was sending. Its camera is stopped; its
+    /// audio capture keeps running until the new session replaces it.
+    fn take_from(session: &mut ActiveSession) -> Self {
+        session.camera_rx = None;
+        session.camera_stop = None;
+        UplinkBuffer {
+            audio: VecDeque::new(),
+            muted: session.muted,
+            video: session.video,
+        }
+    }
+
+    /// Run `fut` to completion, buffering what `old` captures meanwhile.
+    async fn capture_while<F: std::future::Future>(&mut self, old: &mut Option<ActiveSession>, fut: F) -> F::Output {
+        // 48 samples per millisecond at 48 kHz
+        let max_frames = RECONNECT_AUDIO_BUFFER.as_millis() as usize * 48 / CAPTURE_FRAME_SAMPLES;
+        tokio::pin!(fut);
+        loop {
+            tokio::select! {
+                out = &mut fut => return out,
+                pcm = next_capture(old) => match (pcm, old.as_ref()) {
+                    (Some(mut pcm), Some(s)) if !self.muted => {
+                        apply_input_processing(&mut pcm, s.input_volume, s.noise_gate_threshold);
+                        if self.audio.len() == max_frames {
+                            self.audio.pop_front();
+                        }
+                        self.audio.push_back(pcm);
+                    }
+                    (Some(_), _) => {}
+                    // Capture stopped; nothing more to buffer
+                    (None, _) => *old = None,
+                },
+            }
+        }
+    }
+
+    /// Queue the buffered audio for paced replay ahead of live capture and
+    /// restart video on the new session. Its fresh encoder starts with a
+    /// keyframe of its own.
+    fn resume(self, session: &mut ActiveSession, events: &EventQueue) {
+        if !self.audio.is_empty() {
+            tracing::info!("Replaying {} buffered audio frames", self.audio.len());
+        }
+        session.audio_backlog.frames.extend(self.audio);
+        if self.video {
+            let _ = handle_set_video(session, true, events);
+        }
+    }
+}
+
+/// Audio captured while reconnecting, sent on the new session one frame per
+/// `REPLAY_INTERVAL`. Live capture queues behind it until it has drained,
+/// so frames still go out in order.
+#[derive(Default)]
+struct AudioBacklog {
+    frames: VecDeque<Vec<i16>>,
+    next_send: Option<Instant>,
+}
+
+impl AudioBacklog {
+    fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// When the next frame is due, while any are queued.
+    fn next_send_time(&self) -> Option<Instant> {
+        if self.frames.is_empty() {
+            return None;
         }
+        Some(self.next_send.unwrap_or_else(Instant::now))
+    }
+
+    /// The next frame, making the one after it due `REPLAY_INTERVAL` later.
+    fn pop(&mut self) -> Option<Vec<i16>> {
+        let pcm = self.frames.pop_front()?;
+        self.next_send = Some(Instant::now() + REPLAY_INTERVAL);
+        Some(pcm)
+    }
+}
+
+/// The next frame captured by `session`; pending forever without one.
+async fn next_capture(session: &mut Option<ActiveSession>) -> Option<Vec<i16>> {
+    match session {
+        Some(s) => s.capture_rx.recv().await,
+        None => std::future::pending().await,
     }
 }
 
@@ -81,52 +339,116 @@ struct UserVideoDecoder {
 /// Dropping this struct tears down the QUIC connection, stops audio streams,
 /// and frees the Opus encoder/decoder automatically.
 struct ActiveSession {
-    connection: quinn::Connection,
+    link: quic::MediaLink,
+    /// Kept so a graceful shutdown can wait for the close to be delivered.
+    endpoint: quinn::Endpoint,
+    /// Packets received on peer-opened unidirectional streams.
+    stream_rx: mpsc::UnboundedReceiver<Bytes>,
+    /// Transport mode last reported to Python.
+    reported_transport: quic::TransportMode,
+    /// Protocol version and SFU capabilities from the auth handshake.
+    protocol: handshake::Accepted,
     room_id: u32,
     user_id: u32,
+    /// Frame numbering, receive statistics and video reassembly.
+    media: MediaSession,
+    /// Strict datagram validation, if enabled for this connection.
+    validator: Option<MalformedTracker>,
+    /// Per-sender and total limits on received frames.
+    rate_limiter: RateLimiter,
     // Audio state
-    sequence: u32,
-    timestamp: u32,
     encoder: codec::OpusEncoder,
     audio_decoders: HashMap<u32, UserAudioDecoder>,
-    _capture_stream: cpal::Stream,
-    capture_rx: mpsc::UnboundedReceiver<Vec<i16>>,
-    _playback_stream: cpal::Stream,
+    /// Evict per-user decoders after this long without media.
+    decoder_idle_timeout: Duration,
+    /// Last failed decoder creation, keyed by (user_id, media_type).
+    decoder_failures: HashMap<(u32, u8), Instant>,
+    _capture_stream: audio::Stream,
+    capture_rx: mpsc::Receiver<Vec<i16>>,
+    _playback_stream: audio::Stream,
     playback_tx: mpsc::UnboundedSender<Vec<i16>>,
+    /// Negotiated capture and playback device configuration.
+    capture_config: audio::AudioDeviceConfig,
+    playback_config: audio::AudioDeviceConfig,
+    /// Stream errors from the cpal callbacks; the sender is handed to rebuilt streams.
+    audio_errors_tx: mpsc::UnboundedSender<audio::AudioDirection>,
+    audio_errors_rx: mpsc::UnboundedReceiver<audio::AudioDirection>,
+    /// Last automatic stream rebuild per direction.
+    device_fallbacks: HashMap<audio::AudioDirection, Instant>,
     muted: bool,
     deafened: bool,
+    /// Audio buffered across a reconnect, still being replayed.
+    audio_backlog: AudioBacklog,
     // Volume / noise gate
     input_volume: f32,
     output_volume: f32,
     noise_gate_threshold: f32,
     user_volumes: HashMap<u32, f32>,
+    /// Attenuates remote playback while the local user speaks.
+    ducker: audio::Ducker,
+    /// This session's place in the process-wide mix of playback sources.
+    mix_source: audio::MixSource,
+    /// Whether to play comfort noise while remote users are silent.
+    comfort_noise_enabled: bool,
+    /// Read by the playback callback; set while comfort noise should fill gaps.
+    comfort_noise: Arc<AtomicBool>,
     // Speaking detection
     speaking_states: HashMap<u32, SpeakingState>,
+    // Copy of the receive statistics shared with Python
+    user_stats: UserStatsMap,
+    /// Local capture/playback glitch counters, shared with Python, and the
+    /// values at the last `audio_warning` check.
+    audio_stats: Arc<audio::AudioStats>,
+    audio_stats_checked: (Instant, audio::AudioStatsSnapshot),
     // Video state
     video: bool,
     video_config: VideoConfig,
-    video_sequence: u32,
-    video_timestamp: u32,
+    /// Background effect and overlay applied by the camera thread.
+    effects: video::LiveEffects,
     video_encoder: Option<codec::Av1Encoder>,
     video_decoders: HashMap<u32, UserVideoDecoder>,
-    video_reassembler: quic::VideoReassembler,
+    video_pacer: quic::VideoPacer,
+    // Uplink bandwidth cap and the resulting bitrate split
+    max_uplink_kbps: Option<u32>,
+    uplink: UplinkPlan,
     camera_rx: Option<mpsc::Receiver<video::CapturedFrame>>,
     camera_stop: Option<video::CameraStopHandle>,
     video_frame_queue: VideoFrameQueue,
+    // Downlink preferences signaled to the SFU and enforced locally
+    receive_prefs: quic::ReceivePreferences,
+    // Data channels
+    data_queue: DataQueue,
+    // End-to-end media encryption and insertable transforms
+    media_keys: MediaKeyRing,
+    frame_transform: transform::SharedTransform,
 }
 
 /// Establish a QUIC connection and start the audio pipeline.
+#[tracing::instrument(name = "connect", skip_all, fields(url = %url, room_id = room_id, user_id = user_id))]
 async fn establish_session(
     url: String,
     token: String,
     room_id: u32,
     user_id: u32,
-    cert_der: Option<Vec<u8>>,
+    pins: quic::CertPins,
+    client_identity: Option<quic::ClientIdentity>,
     idle_timeout_secs: u64,
     datagram_buffer_size: usize,
     input_device: Option<String>,
     output_device: Option<String>,
+    transport: quic::TransportPreference,
+    bind: quic::BindOptions,
+    proxy: Option<proxy::ProxyConfig>,
+    tuning: quic::TransportTuning,
+    validation: Option<ValidationConfig>,
+    rate_limits: RateLimits,
     video_frame_queue: VideoFrameQueue,
+    data_queue: DataQueue,
+    media_keys: MediaKeyRing,
+    frame_transform: transform::SharedTransform,
+    user_stats: UserStatsMap,
+    audio_stats: Arc<audio::AudioStats>,
+    local: &LocalSettings,
 ) -> Result<ActiveSession, Box<dyn std::error::Error>> {
     // Parse URL — strip optional quic:// prefix
     let addr_str = url
@@ -146,95 +468,231 @@ async fn establish_session(
     };
 
     // Create QUIC endpoint and connect
-    let mut client_config = quic::make_client_config(cert_der)?;
+    let mut client_config = quic::make_client_config(pins, client_identity)?;
 
-    let mut transport = quinn::TransportConfig::default();
-    transport.max_idle_timeout(Some(
+    let mut transport_config = quinn::TransportConfig::default();
+    transport_config.max_idle_timeout(Some(
         quinn::IdleTimeout::try_from(Duration::from_secs(idle_timeout_secs))
             .map_err(|e| format!("Invalid idle timeout: {e}"))?,
     ));
-    transport.datagram_receive_buffer_size(Some(datagram_buffer_size));
-    client_config.transport_config(Arc::new(transport));
+    transport_config.datagram_receive_buffer_size(Some(datagram_buffer_size));
+    tuning.apply(&mut transport_config)?;
+    client_config.transport_config(Arc::new(transport_config));
 
-    let mut endpoint = quinn::Endpoint::client("0.0.0.0:0".parse()?)?;
+    let mut endpoint = match &proxy {
+        Some(p) => proxy::connect_endpoint(p, &bind).await?,
+        None => quic::bind_endpoint(&bind, addr)?,
+    };
     endpoint.set_default_client_config(client_config);
 
-    let connection = endpoint.connect(addr, &host)?.await?;
+    let connection = endpoint
+        .connect(addr, &host)?
+        .await
+        .map_err(quic::connect_error)?;
+    let stream_rx = quic::spawn_stream_reader(connection.clone());
+
+    // Authenticate before any media flows
+    let mut capabilities = handshake::CAP_STREAM_FALLBACK | handshake::CAP_DATA_CHANNEL;
+    if transport != quic::TransportPreference::Stream {
+        capabilities |= handshake::CAP_DATAGRAMS;
+    }
+    if media_keys.lock().map(|k| k.is_enabled()).unwrap_or(false) {
+        capabilities |= handshake::CAP_SFRAME;
+    }
+    let hello = handshake::Hello { room_id, user_id, capabilities, token };
+    let protocol = handshake::perform(&connection, &hello).await?;
+    tracing::info!(
+        "SFU accepted session (protocol v{}, capabilities {:#x})",
+        protocol.version,
+        protocol.capabilities
+    );
 
-    // Send auth token as first datagram (SFU protocol requirement)
-    connection.send_datagram(Bytes::from(token))?;
+    let peer_streams = protocol.capabilities & handshake::CAP_STREAM_FALLBACK != 0;
+    let link = quic::MediaLink::new(connection, transport, peer_streams);
+    if let Ok(mut shared) = user_stats.lock() {
+        shared.clear();
+    }
+    audio_stats.reset();
 
-    // Start audio capture (960 samples = 20ms at 48kHz)
-    let (capture_stream, capture_rx) = audio::start_capture(input_device.as_deref(), 960)?;
+    // Start audio capture
+    let (audio_errors_tx, audio_errors_rx) = mpsc::unbounded_channel();
+    let (capture_stream, capture_rx, capture_config) =
+        audio::start_capture(input_device.as_deref(), CAPTURE_FRAME_SAMPLES, audio_errors_tx.clone(), audio_stats.clone())?;
 
     // Start audio playback
-    let (playback_stream, playback_tx) = audio::start_playback(output_device.as_deref())?;
+    let comfort_noise = Arc::new(AtomicBool::new(false));
+    let (playback_stream, playback_tx, playback_config) = audio::start_playback(output_device.as_deref(), comfort_noise.clone(), audio_errors_tx.clone(), audio_stats.clone())?;
+
+    // Create Opus encoder, within the uplink cap
+    let mut encoder = codec::OpusEncoder::new()?;
+    let uplink = UplinkPlan::new(local.max_uplink_kbps, local.video_config.bitrate_kbps);
+    if let Err(e) = encoder.set_bitrate(uplink.audio_kbps) {
+        tracing::warn!("Failed to set Opus bitrate: {e}");
+    }
 
-    // Create Opus encoder
-    let encoder = codec::OpusEncoder::new()?;
+    let mut media = MediaSession::new(room_id, user_id);
+    media.set_reassembly_limits(rate_limits.max_reassembly_per_user, rate_limits.max_reassembly_total);
 
-    Ok(ActiveSession {
-        connection,
+    let mut ducker = audio::Ducker::default();
+    ducker.set_config(local.ducking.clone());
+    let mut mix_source = audio::MixSource::new();
+    mix_source.configure(local.mix_priority.0, local.mix_priority.1);
+
+    let mut session = ActiveSession {
+        reported_transport: link.mode(),
+        protocol,
+        link,
+        endpoint,
+        stream_rx,
         room_id,
         user_id,
-        sequence: 0,
-        timestamp: 0,
+        media,
+        validator: validation.map(|config| MalformedTracker::new(config, Instant::now())),
+        rate_limiter: RateLimiter::new(rate_limits, Instant::now()),
         encoder,
         audio_decoders: HashMap::new(),
+        decoder_idle_timeout: local.decoder_idle_timeout,
+        decoder_failures: HashMap::new(),
         _capture_stream: capture_stream,
         capture_rx,
         _playback_stream: playback_stream,
         playback_tx,
-        muted: false,
-        deafened: false,
-        input_volume: 1.0,
-        output_volume: 1.0,
-        noise_gate_threshold: 0.0,
-        user_volumes: HashMap::new(),
+        capture_config,
+        playback_config,
+        audio_errors_tx,
+        audio_errors_rx,
+        device_fallbacks: HashMap::new(),
+        muted: local.muted,
+        deafened: local.deafened,
+        audio_backlog: AudioBacklog::default(),
+        input_volume: local.input_volume,
+        output_volume: local.output_volume,
+        noise_gate_threshold: local.noise_gate_threshold,
+        user_volumes: local.user_volumes.clone(),
+        ducker,
+        mix_source,
+        comfort_noise_enabled: local.comfort_noise,
+        comfort_noise,
         speaking_states: HashMap::new(),
+        user_stats,
+        audio_stats,
+        audio_stats_checked: (Instant::now(), audio::AudioStatsSnapshot::default()),
         video: false,
-        video_config: VideoConfig::default(),
-        video_sequence: 0,
-        video_timestamp: 0,
+        video_pacer: quic::VideoPacer::new(uplink.video_kbps.unwrap_or(local.video_config.bitrate_kbps)),
+        video_config: local.video_config.clone(),
+        effects: local.effects.clone(),
+        max_uplink_kbps: local.max_uplink_kbps,
+        uplink,
         video_encoder: None,
         video_decoders: HashMap::new(),
-        video_reassembler: quic::VideoReassembler::new(),
         camera_rx: None,
         camera_stop: None,
         video_frame_queue,
-    })
+        receive_prefs: quic::ReceivePreferences::default(),
+        data_queue,
+        media_keys,
+        frame_transform,
+    };
+    // The SFU forgets preferences with the connection that set them
+    if local.receive_prefs != quic::ReceivePreferences::default() {
+        set_receive_preferences(&mut session, local.receive_prefs);
+    }
+    Ok(session)
+}
+
+/// Push the auth handshake outcome of a connect attempt, so a rejection is
+/// distinguishable from a network failure, and the negotiated audio devices
+/// of a successful one.
+fn report_connect(events: &EventQueue, result: &Result<ActiveSession, Box<dyn std::error::Error>>) {
+    match result {
+        Ok(s) => {
+            push_event(
+                events,
+                MediaEvent::AuthAccepted {
+                    version: s.protocol.version,
+                    capabilities: s.protocol.capabilities,
+                },
+            );
+            push_event(events, MediaEvent::AudioConfig { direction: "capture", config: s.capture_config.clone() });
+            push_event(events, MediaEvent::AudioConfig { direction: "playback", config: s.playback_config.clone() });
+        }
+        Err(e) => {
+            if let Some(rejected) = e.downcast_ref::<handshake::AuthRejected>() {
+                push_event(events, MediaEvent::AuthRejected(rejected.0.clone()));
+            }
+        }
+    }
 }
 
-/// Attempt to reconnect with exponential backoff.
+/// Attempt to reconnect with exponential backoff. Audio `lost` still
+/// captures meanwhile is buffered and replayed on the new session, which
+/// starts from `local` and resumes video if `lost` was sending it.
+#[tracing::instrument(name = "reconnect", skip_all, fields(room_id = params.room_id, user_id = params.user_id))]
 async fn reconnect_with_backoff(
+    mut lost: ActiveSession,
     params: &ConnectParams,
+    settings: &MediaSettings,
     events: &EventQueue,
     video_frames: &VideoFrameQueue,
+    data_messages: &DataQueue,
+    media_keys: &MediaKeyRing,
+    frame_transform: &transform::SharedTransform,
+    user_stats: &UserStatsMap,
+    audio_stats: &Arc<audio::AudioStats>,
+    local: &LocalSettings,
 ) -> Option<ActiveSession> {
-    for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
-        let delay_secs = std::cmp::min(2u64.pow(attempt - 1), MAX_BACKOFF_SECS);
+    let max_attempts = settings.reconnect_max_attempts.unwrap_or(MAX_RECONNECT_ATTEMPTS);
+    let max_backoff_secs = settings.reconnect_max_backoff_secs.unwrap_or(MAX_BACKOFF_SECS);
+    let mut buffer = UplinkBuffer::take_from(&mut lost);
+    let mut lost = Some(lost);
+    for attempt in 1..=max_attempts {
+        let delay_secs = 1u64.checked_shl(attempt - 1).unwrap_or(u64::MAX).min(max_backoff_secs);
         push_event(events, MediaEvent::Reconnecting { attempt, delay_secs });
-        tokio::time::sleep(Duration::from_secs(delay_secs)).await;
+        buffer.capture_while(&mut lost, tokio::time::sleep(Duration::from_secs(delay_secs))).await;
 
-        tracing::info!("Reconnect attempt {}/{}", attempt, MAX_RECONNECT_ATTEMPTS);
-        match establish_session(
+        tracing::info!("Reconnect attempt {}/{}", attempt, max_attempts);
+        let connect = establish_session(
             params.url.clone(),
             params.token.clone(),
             params.room_id,
             params.user_id,
-            params.cert_der.clone(),
+            params.pins.clone(),
+            params.client_identity.clone(),
             params.idle_timeout_secs,
             params.datagram_buffer_size,
             params.input_device.clone(),
             params.output_device.clone(),
+            params.transport,
+            params.bind.clone(),
+            params.proxy.clone(),
+            params.tuning.clone(),
+            params.validation.clone(),
+            params.rate_limits.clone(),
             video_frames.clone(),
-        ).await {
-            Ok(s) => {
+            data_messages.clone(),
+            media_keys.clone(),
+            frame_transform.clone(),
+            user_stats.clone(),
+            audio_stats.clone(),
+            local,
+        );
+        let result = buffer.capture_while(&mut lost, connect).await;
+        report_connect(events, &result);
+        match result {
+            Ok(mut s) => {
+                // Release the old audio devices before replaying
+                drop(lost);
                 push_event(events, MediaEvent::Connected);
+                buffer.resume(&mut s, events);
                 return Some(s);
             }
             Err(e) => {
                 tracing::warn!("Reconnect attempt {} failed: {}", attempt, e);
+                // Retrying with the same credentials cannot succeed
+                if e.is::<quic::ClientAuthError>() || e.is::<handshake::AuthRejected>() {
+                    push_event(events, MediaEvent::Disconnected(e.to_string()));
+                    return None;
+                }
             }
         }
     }
@@ -243,22 +701,43 @@ async fn reconnect_with_backoff(
         events,
         MediaEvent::Disconnected(format!(
             "Reconnection failed after {} attempts",
-            MAX_RECONNECT_ATTEMPTS
+            max_attempts
         )),
     );
     None
 }
 
+/// Report a finished connect attempt as `command_result`, and to a blocked
+/// `connect_and_wait` caller if there is one.
+fn complete_connect(
+    events: &EventQueue,
+    command_id: u64,
+    reply: Option<std::sync::mpsc::SyncSender<Option<String>>>,
+    error: Option<String>,
+) {
+    if let Some(reply) = reply {
+        let _ = reply.send(error.clone());
+    }
+    push_event(events, MediaEvent::CommandResult { id: command_id, error });
+}
+
 /// Main media event loop. Receives commands from the Python layer
 /// and manages QUIC connection + audio/video pipeline lifecycle.
 pub async fn run_media_loop(
-    mut cmd_rx: mpsc::UnboundedReceiver<MediaCommand>,
+    mut cmd_rx: mpsc::Receiver<MediaCommand>,
     cancel: CancellationToken,
     events: EventQueue,
     video_frames: VideoFrameQueue,
+    data_messages: DataQueue,
+    media_keys: MediaKeyRing,
+    frame_transform: transform::SharedTransform,
+    user_stats: UserStatsMap,
+    audio_stats: Arc<audio::AudioStats>,
+    settings: MediaSettings,
 ) {
     let mut session: Option<ActiveSession> = None;
     let mut last_connect_params: Option<ConnectParams> = None;
+    let mut local = LocalSettings::new(&settings);
 
     loop {
         match &mut session {
@@ -272,41 +751,103 @@ pub async fn run_media_loop(
                     cmd = cmd_rx.recv() => {
                         match cmd {
                             None => break,
-                            Some(MediaCommand::Connect { url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device }) => {
+                            Some(MediaCommand::Connect { url, token, room_id, user_id, pins, client_identity, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind, proxy, tuning, validation, rate_limits, command_id, reply }) => {
                                 tracing::info!("Connecting to SFU at {}", url);
+                                let input_device = input_device.or_else(|| settings.input_device.clone());
+                                let output_device = output_device.or_else(|| settings.output_device.clone());
                                 let params = ConnectParams {
                                     url: url.clone(),
                                     token: token.clone(),
                                     room_id,
                                     user_id,
-                                    cert_der: cert_der.clone(),
+                                    pins: pins.clone(),
+                                    client_identity: client_identity.clone(),
                                     idle_timeout_secs,
                                     datagram_buffer_size,
                                     input_device: input_device.clone(),
                                     output_device: output_device.clone(),
+                                    transport,
+                                    bind: bind.clone(),
+                                    proxy: proxy.clone(),
+                                    tuning: tuning.clone(),
+                                    validation: validation.clone(),
+                                    rate_limits: rate_limits.clone(),
                                 };
-                                match establish_session(url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device, video_frames.clone()).await {
+                                let result = establish_session(url, token, room_id, user_id, pins, client_identity, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind, proxy, tuning, validation, rate_limits, video_frames.clone(), data_messages.clone(), media_keys.clone(), frame_transform.clone(), user_stats.clone(), audio_stats.clone(), &local).await;
+                                report_connect(&events, &result);
+                                match result {
                                     Ok(s) => {
                                         tracing::info!("Connected to SFU");
                                         push_event(&events, MediaEvent::Connected);
+                                        complete_connect(&events, command_id, reply, None);
                                         last_connect_params = Some(params);
                                         session = Some(s);
                                     }
                                     Err(e) => {
                                         tracing::error!("Failed to connect to SFU: {}", e);
                                         push_event(&events, MediaEvent::ConnectFailed(e.to_string()));
+                                        complete_connect(&events, command_id, reply, Some(e.to_string()));
                                     }
                                 }
                             }
                             Some(MediaCommand::Disconnect) => {}
-                            Some(MediaCommand::SetMute(_)) => {}
-                            Some(MediaCommand::SetDeaf(_)) => {}
-                            Some(MediaCommand::SetVideo(_)) => {}
-                            Some(MediaCommand::SetVideoConfig { .. }) => {}
-                            Some(MediaCommand::SetInputVolume(_)) => {}
-                            Some(MediaCommand::SetOutputVolume(_)) => {}
-                            Some(MediaCommand::SetNoiseGate(_)) => {}
-                            Some(MediaCommand::SetUserVolume { .. }) => {}
+                            // Settings are kept for the next session
+                            Some(MediaCommand::SetMute(muted)) => {
+                                local.muted = muted;
+                            }
+                            Some(MediaCommand::SetDeaf(deafened)) => {
+                                local.deafened = deafened;
+                            }
+                            Some(MediaCommand::SetVideo { command_id, .. }) => {
+                                push_event(&events, MediaEvent::CommandResult { id: command_id, error: Some("not connected".into()) });
+                            }
+                            Some(MediaCommand::SetVideoConfig { width, height, fps, bitrate_kbps, rotation, mirror_preview, scale_mode }) => {
+                                local.video_config = VideoConfig { camera: local.video_config.camera, width, height, fps, bitrate_kbps, rotation, mirror_preview, scale_mode };
+                            }
+                            Some(MediaCommand::SetMaxUplink(kbps)) => {
+                                local.max_uplink_kbps = kbps;
+                            }
+                            Some(MediaCommand::SetInputVolume(v)) => {
+                                local.input_volume = v;
+                            }
+                            Some(MediaCommand::SetOutputVolume(v)) => {
+                                local.output_volume = v;
+                            }
+                            Some(MediaCommand::SetNoiseGate(t)) => {
+                                local.noise_gate_threshold = t;
+                            }
+                            Some(MediaCommand::SetUserVolume { user_id, volume }) => {
+                                set_user_volume(&mut local.user_volumes, user_id, volume);
+                            }
+                            Some(MediaCommand::SendData { .. }) => {}
+                            Some(MediaCommand::SetReceivePreferences(prefs)) => {
+                                local.receive_prefs = prefs;
+                            }
+                            Some(MediaCommand::SetDecoderIdleTimeout(timeout)) => {
+                                local.decoder_idle_timeout = timeout;
+                            }
+                            Some(MediaCommand::DropUser(user_id)) => {
+                                local.user_volumes.remove(&user_id);
+                            }
+                            Some(MediaCommand::SetDucking(config)) => {
+                                local.ducking = config;
+                            }
+                            Some(MediaCommand::SetComfortNoise(enabled)) => {
+                                local.comfort_noise = enabled;
+                            }
+                            Some(MediaCommand::SetMixPriority { priority, attenuation }) => {
+                                local.mix_priority = (priority, attenuation);
+                            }
+                            Some(MediaCommand::SetBackground(stage)) => {
+                                if let Ok(mut current) = local.effects.background.lock() {
+                                    *current = stage;
+                                }
+                            }
+                            Some(MediaCommand::SetOverlay(overlay)) => {
+                                if let Ok(mut current) = local.effects.overlay.lock() {
+                                    *current = overlay;
+                                }
+                            }
                         }
                     }
                 }
@@ -314,82 +855,164 @@ pub async fn run_media_loop(
             Some(s) => {
                 // Connected — listen for commands, capture frames, and incoming datagrams.
                 // We need to conditionally poll the camera receiver.
+                let pacer_deadline = s.video_pacer.next_send_time();
                 let camera_frame = async {
                     match &mut s.camera_rx {
                         Some(rx) => rx.recv().await,
                         None => std::future::pending().await,
                     }
                 };
+                let pacer_tick = async move {
+                    match pacer_deadline {
+                        Some(t) => tokio::time::sleep_until(t.into()).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                let replay_deadline = s.audio_backlog.next_send_time();
+                let replay_tick = async move {
+                    match replay_deadline {
+                        Some(t) => tokio::time::sleep_until(t.into()).await,
+                        None => std::future::pending().await,
+                    }
+                };
 
                 tokio::select! {
                     _ = cancel.cancelled() => {
                         tracing::info!("Media loop cancelled");
+                        if let Some(s) = session.take() {
+                            shutdown_session(s).await;
+                        }
                         break;
                     }
                     cmd = cmd_rx.recv() => {
                         match cmd {
                             None => break,
-                            Some(MediaCommand::Connect { url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device }) => {
+                            Some(MediaCommand::Connect { url, token, room_id, user_id, pins, client_identity, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind, proxy, tuning, validation, rate_limits, command_id, reply }) => {
                                 tracing::info!("Reconnecting to SFU at {}", url);
+                                let input_device = input_device.or_else(|| settings.input_device.clone());
+                                let output_device = output_device.or_else(|| settings.output_device.clone());
                                 session = None;
                                 let params = ConnectParams {
                                     url: url.clone(),
                                     token: token.clone(),
                                     room_id,
                                     user_id,
-                                    cert_der: cert_der.clone(),
+                                    pins: pins.clone(),
+                                    client_identity: client_identity.clone(),
                                     idle_timeout_secs,
                                     datagram_buffer_size,
                                     input_device: input_device.clone(),
                                     output_device: output_device.clone(),
+                                    transport,
+                                    bind: bind.clone(),
+                                    proxy: proxy.clone(),
+                                    tuning: tuning.clone(),
+                                    validation: validation.clone(),
+                                    rate_limits: rate_limits.clone(),
                                 };
-                                match establish_session(url, token, room_id, user_id, cert_der, idle_timeout_secs, datagram_buffer_size, input_device, output_device, video_frames.clone()).await {
+                                let result = establish_session(url, token, room_id, user_id, pins, client_identity, idle_timeout_secs, datagram_buffer_size, input_device, output_device, transport, bind, proxy, tuning, validation, rate_limits, video_frames.clone(), data_messages.clone(), media_keys.clone(), frame_transform.clone(), user_stats.clone(), audio_stats.clone(), &local).await;
+                                report_connect(&events, &result);
+                                match result {
                                     Ok(new_s) => {
                                         tracing::info!("Connected to SFU");
                                         push_event(&events, MediaEvent::Connected);
+                                        complete_connect(&events, command_id, reply, None);
                                         last_connect_params = Some(params);
                                         session = Some(new_s);
                                     }
                                     Err(e) => {
                                         tracing::error!("Failed to connect to SFU: {}", e);
                                         push_event(&events, MediaEvent::ConnectFailed(e.to_string()));
+                                        complete_connect(&events, command_id, reply, Some(e.to_string()));
                                     }
                                 }
                                 continue;
                             }
                             Some(MediaCommand::Disconnect) => {
                                 tracing::info!("Disconnecting from SFU");
-                                push_event(&events, MediaEvent::Disconnected("user requested".into()));
                                 last_connect_params = None;
-                                session = None;
+                                if let Some(s) = session.take() {
+                                    shutdown_session(s).await;
+                                }
+                                push_event(&events, MediaEvent::Disconnected("user requested".into()));
                                 continue;
                             }
                             Some(MediaCommand::SetMute(muted)) => {
+                                local.muted = muted;
                                 s.muted = muted;
                             }
                             Some(MediaCommand::SetDeaf(deafened)) => {
+                                local.deafened = deafened;
                                 s.deafened = deafened;
                             }
-                            Some(MediaCommand::SetVideo(enabled)) => {
-                                handle_set_video(s, enabled, &events);
+                            Some(MediaCommand::SetVideo { enabled, command_id }) => {
+                                let error = handle_set_video(s, enabled, &events).err();
+                                push_event(&events, MediaEvent::CommandResult { id: command_id, error });
+                            }
+                            Some(MediaCommand::SetVideoConfig { width, height, fps, bitrate_kbps, rotation, mirror_preview, scale_mode }) => {
+                                local.video_config = VideoConfig { camera: local.video_config.camera, width, height, fps, bitrate_kbps, rotation, mirror_preview, scale_mode };
+                                s.video_config = local.video_config.clone();
+                                // Takes effect on the next set_video(true)
+                                s.uplink = UplinkPlan::new(s.max_uplink_kbps, bitrate_kbps);
+                                s.video_pacer.set_bitrate(s.uplink.video_kbps.unwrap_or(bitrate_kbps));
                             }
-                            Some(MediaCommand::SetVideoConfig { width, height, fps, bitrate_kbps }) => {
-                                s.video_config = VideoConfig { width, height, fps, bitrate_kbps };
+                            Some(MediaCommand::SetMaxUplink(kbps)) => {
+                                local.max_uplink_kbps = kbps;
+                                s.max_uplink_kbps = kbps;
+                                apply_uplink_plan(s, &events);
                             }
                             Some(MediaCommand::SetInputVolume(v)) => {
+                                local.input_volume = v;
                                 s.input_volume = v;
                             }
                             Some(MediaCommand::SetOutputVolume(v)) => {
+                                local.output_volume = v;
                                 s.output_volume = v;
                             }
                             Some(MediaCommand::SetNoiseGate(t)) => {
+                                local.noise_gate_threshold = t;
                                 s.noise_gate_threshold = t;
                             }
                             Some(MediaCommand::SetUserVolume { user_id, volume }) => {
-                                if (volume - 1.0).abs() < f32::EPSILON {
-                                    s.user_volumes.remove(&user_id);
-                                } else {
-                                    s.user_volumes.insert(user_id, volume);
+                                set_user_volume(&mut local.user_volumes, user_id, volume);
+                                set_user_volume(&mut s.user_volumes, user_id, volume);
+                            }
+                            Some(MediaCommand::SendData { channel_id, data, reliable }) => {
+                                send_data(s, channel_id, data, reliable, &events);
+                            }
+                            Some(MediaCommand::SetReceivePreferences(prefs)) => {
+                                local.receive_prefs = prefs;
+                                set_receive_preferences(s, prefs);
+                            }
+                            Some(MediaCommand::SetDecoderIdleTimeout(timeout)) => {
+                                local.decoder_idle_timeout = timeout;
+                                s.decoder_idle_timeout = timeout;
+                            }
+                            Some(MediaCommand::DropUser(user_id)) => {
+                                local.user_volumes.remove(&user_id);
+                                drop_user(s, user_id, &events);
+                            }
+                            Some(MediaCommand::SetDucking(config)) => {
+                                local.ducking = config.clone();
+                                s.ducker.set_config(config);
+                            }
+                            Some(MediaCommand::SetComfortNoise(enabled)) => {
+                                local.comfort_noise = enabled;
+                                s.comfort_noise_enabled = enabled;
+                            }
+                            Some(MediaCommand::SetMixPriority { priority, attenuation }) => {
+                                local.mix_priority = (priority, attenuation);
+                                s.mix_source.configure(priority, attenuation);
+                            }
+                            // The session shares `local.effects`
+                            Some(MediaCommand::SetBackground(stage)) => {
+                                if let Ok(mut current) = local.effects.background.lock() {
+                                    *current = stage;
+                                }
+                            }
+                            Some(MediaCommand::SetOverlay(overlay)) => {
+                                if let Ok(mut current) = local.effects.overlay.lock() {
+                                    *current = overlay;
                                 }
                             }
                         }
@@ -398,9 +1021,18 @@ pub async fn run_media_loop(
                         if !s.muted {
                             apply_input_processing(&mut pcm, s.input_volume, s.noise_gate_threshold);
                             // Speaking detection on processed local audio
-                            update_speaking_state(s, s.user_id, &pcm, &events);
-                            send_audio_frame(s, pcm);
+                            let voiced = update_speaking_state(s, s.user_id, &pcm, &events);
+                            s.ducker.set_local_voice(voiced);
+                            let speaking = s.speaking_states.get(&s.user_id).is_some_and(|st| st.speaking);
+                            if speaking || !s.uplink.vad {
+                                if s.audio_backlog.is_empty() {
+                                    send_audio_frame(s, pcm);
+                                } else {
+                                    s.audio_backlog.frames.push_back(pcm);
+                                }
+                            }
                         } else {
+                            s.ducker.set_local_voice(false);
                             // Muted → ensure we stop speaking
                             let state = s.speaking_states.get(&s.user_id);
                             if state.is_some_and(|st| st.speaking) {
@@ -411,20 +1043,34 @@ pub async fn run_media_loop(
                             }
                         }
                     }
+                    Some(direction) = s.audio_errors_rx.recv() => {
+                        handle_audio_stream_error(s, direction, &events);
+                    }
                     Some(frame) = camera_frame => {
                         handle_camera_frame(s, frame, &events);
                     }
-                    result = s.connection.read_datagram() => {
+                    _ = pacer_tick => {
+                        flush_video(s);
+                    }
+                    _ = replay_tick => {
+                        if let Some(pcm) = s.audio_backlog.pop() {
+                            send_audio_frame(s, pcm);
+                        }
+                    }
+                    Some(data) = s.stream_rx.recv() => {
+                        receive_datagram(s, data, &events);
+                    }
+                    result = s.link.connection().read_datagram() => {
                         match result {
                             Ok(data) => {
                                 receive_datagram(s, data, &events);
                             }
                             Err(e) => {
                                 tracing::error!("QUIC read error: {}", e);
-                                session = None;
+                                let lost = session.take();
 
-                                if let Some(ref params) = last_connect_params {
-                                    if let Some(new_session) = reconnect_with_backoff(params, &events, &video_frames).await {
+                                if let (Some(params), Some(lost)) = (&last_connect_params, lost) {
+                                    if let Some(new_session) = reconnect_with_backoff(lost, params, &settings, &events, &video_frames, &data_messages, &media_keys, &frame_transform, &user_stats, &audio_stats, &local).await {
                                         session = Some(new_session);
                                     } else {
                                         last_connect_params = None;
@@ -439,44 +1085,181 @@ pub async fn run_media_loop(
                 }
 
                 // Periodic cleanup: evict stale reassembly entries and idle decoders
+                let mut flooded = None;
                 if let Some(s) = &mut session {
-                    s.video_reassembler.evict_stale(REASSEMBLY_STALE_TIMEOUT);
-                    evict_idle_decoders(s);
+                    flooded = report_malformed(s, &events);
+                    report_rate_limits(s, &events);
+                    evict_idle_decoders(s, &events);
+                    // Only fill gaps while someone is actually sending media
+                    let fill = s.comfort_noise_enabled && !s.deafened && s.media.has_active_users();
+                    s.comfort_noise.store(fill, Ordering::Relaxed);
+                    check_audio_stats(s, &events);
+                    if s.link.mode() != s.reported_transport {
+                        s.reported_transport = s.link.mode();
+                        push_event(&events, MediaEvent::TransportChanged(s.reported_transport.as_str().into()));
+                    }
+                }
+                // Reconnecting would only meet the same garbage
+                if let Some(reason) = flooded {
+                    tracing::warn!("Disconnecting: {reason}");
+                    last_connect_params = None;
+                    if let Some(s) = session.take() {
+                        shutdown_session(s).await;
+                    }
+                    push_event(&events, MediaEvent::Disconnected(reason));
                 }
             }
         }
     }
 }
 
+/// Emit a `RateLimited` event if frames were dropped or partial video
+/// frames discarded over the limits since the last one and the report
+/// interval has passed.
+fn report_rate_limits(session: &mut ActiveSession, events: &EventQueue) {
+    session.rate_limiter.record_evictions(session.media.take_reassembly_evictions());
+    let Some(report) = session.rate_limiter.poll(Instant::now()) else {
+        return;
+    };
+    tracing::debug!(
+        "Rate-limited {} frames from users {:?}, discarded {} partial video frames",
+        report.dropped,
+        report.users,
+        report.evicted
+    );
+    push_event(
+        events,
+        MediaEvent::RateLimited {
+            dropped: report.dropped,
+            by_reason: report.by_reason,
+            users: report.users.into_iter().collect(),
+            evicted: report.evicted,
+        },
+    );
+}
+
+/// Emit a `MalformedPackets` event if strict validation rejected datagrams
+/// since the last one and the report interval has passed. Returns a
+/// disconnect reason once the configured threshold is reached.
+fn report_malformed(session: &mut ActiveSession, events: &EventQueue) -> Option<String> {
+    let report = session.validator.as_mut()?.poll(Instant::now())?;
+    tracing::debug!("Dropped {} malformed datagrams, last: {}", report.count, report.last);
+    let reason = report.disconnect.then(|| {
+        format!("{} malformed datagrams within the report interval (last: {})", report.count, report.last)
+    });
+    push_event(
+        events,
+        MediaEvent::MalformedPackets {
+            count: report.count,
+            total: report.total,
+            by_reason: report.by_reason,
+            last: report.last,
+        },
+    );
+    reason
+}
+
+/// Leave a session gracefully: drain the AV1 encoder and video pacer, send
+/// captured tail audio, tell the SFU we are leaving and close the connection
+/// with `CLOSE_LEAVE`, all bounded by `SHUTDOWN_TIMEOUT`.
+async fn shutdown_session(mut session: ActiveSession) {
+    let graceful = async {
+        if let Some(mut encoder) = session.video_encoder.take() {
+            match encoder.flush() {
+                Ok(packets) => queue_video_packets(&mut session, packets),
+                Err(e) => tracing::warn!("AV1 flush error: {e}"),
+            }
+        }
+        while let Some(at) = session.video_pacer.next_send_time() {
+            tokio::time::sleep_until(at.into()).await;
+            flush_video(&mut session);
+        }
+
+        while let Ok(pcm) = session.capture_rx.try_recv() {
+            if !session.muted {
+                send_audio_frame(&mut session, pcm);
+            }
+        }
+
+        let leave = session.media.feedback_frame(Bytes::from_static(&[quic::FB_LEAVE]));
+        if let Err(e) = session.link.send_reliable(leave.encode()) {
+            tracing::warn!("Failed to send leave: {e}");
+        }
+        session.link.close(quic::CLOSE_LEAVE, b"user left").await;
+        session.endpoint.wait_idle().await;
+    };
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, graceful).await.is_err() {
+        tracing::warn!("Graceful shutdown timed out, closing immediately");
+    }
+}
+
+/// Recompute the uplink split and push it to the audio and video encoders.
+fn apply_uplink_plan(session: &mut ActiveSession, events: &EventQueue) {
+    let plan = UplinkPlan::new(session.max_uplink_kbps, session.video_config.bitrate_kbps);
+    let old = std::mem::replace(&mut session.uplink, plan);
+
+    if plan.audio_kbps != old.audio_kbps {
+        if let Err(e) = session.encoder.set_bitrate(plan.audio_kbps) {
+            push_event(events, MediaEvent::AudioError(format!("Opus bitrate: {e}")));
+        }
+    }
+
+    let video_kbps = plan.video_kbps.unwrap_or(session.video_config.bitrate_kbps);
+    session.video_pacer.set_bitrate(video_kbps);
+    if plan.video_kbps.is_none() {
+        session.video_pacer.clear();
+    }
+    // rav1e cannot change bitrate in place, so rebuild the encoder
+    if session.video && plan.video_kbps.is_some() && plan.video_kbps != old.video_kbps {
+        let (width, height) = session.video_config.encoded_size();
+        match codec::Av1Encoder::new(
+            width,
+            height,
+            session.video_config.fps,
+            video_kbps,
+        ) {
+            Ok(enc) => session.video_encoder = Some(enc),
+            Err(e) => {
+                push_event(events, MediaEvent::VideoError(format!("AV1 encoder init failed: {e}")));
+            }
+        }
+    }
+    tracing::info!(
+        "Uplink plan: cap={:?} video={:?} audio={:?} vad={}",
+        session.max_uplink_kbps,
+        plan.video_kbps,
+        plan.audio_kbps,
+        plan.vad
+    );
+}
+
 /// Handle SetVideo command: start/stop camera + encoder.
-fn handle_set_video(session: &mut ActiveSession, enabled: bool, events: &EventQueue) {
+fn handle_set_video(session: &mut ActiveSession, enabled: bool, events: &EventQueue) -> Result<(), String> {
     if enabled == session.video {
-        return;
+        return Ok(());
     }
 
     if enabled {
-        let cfg = video::CameraConfig {
-            width: session.video_config.width,
-            height: session.video_config.height,
-            fps: session.video_config.fps,
-        };
+        let cfg = session.video_config.camera_config();
 
-        match video::start_camera_capture(cfg) {
+        match video::start_camera_capture(cfg, session.effects.clone()) {
             Ok((rx, stop)) => {
                 session.camera_rx = Some(rx);
                 session.camera_stop = Some(stop);
             }
             Err(e) => {
-                push_event(events, MediaEvent::VideoError(format!("Camera start failed: {e}")));
-                return;
+                let msg = format!("Camera start failed: {e}");
+                push_event(events, MediaEvent::VideoError(msg.clone()));
+                return Err(msg);
             }
         }
 
+        let (width, height) = session.video_config.encoded_size();
         match codec::Av1Encoder::new(
-            session.video_config.width as usize,
-            session.video_config.height as usize,
+            width,
+            height,
             session.video_config.fps,
-            session.video_config.bitrate_kbps,
+            session.uplink.video_kbps.unwrap_or(session.video_config.bitrate_kbps),
         ) {
             Ok(enc) => {
                 session.video_encoder = Some(enc);
@@ -485,23 +1268,25 @@ fn handle_set_video(session: &mut ActiveSession, enabled: bool, events: &EventQu
                 // Stop camera if encoder fails
                 session.camera_rx = None;
                 session.camera_stop = None;
-                push_event(events, MediaEvent::VideoError(format!("AV1 encoder init failed: {e}")));
-                return;
+                let msg = format!("AV1 encoder init failed: {e}");
+                push_event(events, MediaEvent::VideoError(msg.clone()));
+                return Err(msg);
             }
         }
 
         session.video = true;
-        session.video_sequence = 0;
-        session.video_timestamp = 0;
+        session.media.reset_video();
         tracing::info!("Video enabled");
     } else {
         // Stop camera and drop encoder
         session.camera_rx = None;
         session.camera_stop = None;
         session.video_encoder = None;
+        session.video_pacer.clear();
         session.video = false;
         tracing::info!("Video disabled");
     }
+    Ok(())
 }
 
 /// Process a captured camera frame: push local preview + encode + send.
@@ -518,13 +1303,18 @@ fn handle_camera_frame(
         rgba: frame.rgba,
     });
 
-    // Encode and send
+    // Encode and send, unless the uplink cap has paused video
+    if session.uplink.video_kbps.is_none() {
+        return;
+    }
     let encoder = match &mut session.video_encoder {
         Some(enc) => enc,
         None => return,
     };
 
-    let packets = match encoder.encode(&frame.y, &frame.u, &frame.v) {
+    let encoded = tracing::debug_span!("av1_encode", width = frame.width, height = frame.height)
+        .in_scope(|| encoder.encode(&frame.y, &frame.u, &frame.v));
+    let packets = match encoded {
         Ok(pkts) => pkts,
         Err(e) => {
             tracing::warn!("AV1 encode error: {e}");
@@ -533,25 +1323,49 @@ fn handle_camera_frame(
         }
     };
 
+    queue_video_packets(session, packets);
+
+    // Send what the pacing budget allows now; the rest follows on pacer ticks
+    flush_video(session);
+}
+
+/// Seal encoded AV1 packets and queue them on the pacer.
+fn queue_video_packets(session: &mut ActiveSession, packets: Vec<codec::EncodedPacket>) {
     for pkt in packets {
-        let ts = session.video_timestamp;
-        if let Err(e) = quic::send_video_fragmented(
-            &session.connection,
-            session.room_id,
-            session.user_id,
-            &mut session.video_sequence,
-            ts,
-            pkt.is_keyframe,
-            &pkt.data,
-        ) {
-            tracing::warn!("Failed to send video: {e}");
-        }
-        session.video_timestamp = session.video_timestamp.wrapping_add(1);
+        let ts = session.media.next_video_timestamp();
+        let info = transform::FrameInfo {
+            media_type: quic::MEDIA_TYPE_VIDEO,
+            user_id: session.user_id,
+            timestamp: ts,
+        };
+        let Some(data) = seal_payload(session, &info, pkt.data) else {
+            continue;
+        };
+        session.video_pacer.push(ts, pkt.is_keyframe, data);
+    }
+}
+
+/// Send queued video fragments allowed by the pacer.
+fn flush_video(session: &mut ActiveSession) {
+    if let Err(e) = session.video_pacer.flush(
+        &mut session.link,
+        session.room_id,
+        session.user_id,
+        session.media.video_sequence_mut(),
+    ) {
+        tracing::warn!("Failed to send video: {e}");
     }
 }
 
-/// Dispatch an incoming datagram based on media type.
+/// Hand an incoming datagram to the media session and act on what it yields.
 fn receive_datagram(session: &mut ActiveSession, data: Bytes, events: &EventQueue) {
+    if let Some(validator) = &mut session.validator {
+        if let Err(malformed) = validate::check_datagram(&data, session.room_id, session.user_id) {
+            validator.record(malformed);
+            return;
+        }
+    }
+    let len = data.len();
     let frame = match quic::InFrame::decode(data) {
         Some(f) => f,
         None => {
@@ -559,55 +1373,257 @@ fn receive_datagram(session: &mut ActiveSession, data: Bytes, events: &EventQueu
             return;
         }
     };
+    if let Err(limited) = session.rate_limiter.check(Instant::now(), frame.header.user_id, len) {
+        tracing::trace!("Dropping frame: {limited}");
+        return;
+    }
 
-    match frame.header.media_type {
-        quic::MEDIA_TYPE_AUDIO => {
-            if !session.deafened {
-                receive_audio_frame(session, frame, events);
+    let user_id = frame.header.user_id;
+    let accept_video = accepts_video(session, user_id);
+    session.media.handle_frame(Instant::now(), frame, accept_video);
+    drain_media_events(session, events);
+    publish_user_stats(session, user_id);
+}
+
+/// Decode, play and report everything the media session has queued.
+fn drain_media_events(session: &mut ActiveSession, events: &EventQueue) {
+    while let Some(event) = session.media.poll_event() {
+        match event {
+            SessionEvent::UserActive(user_id) => push_event(events, MediaEvent::UserActive(user_id)),
+            SessionEvent::UserInactive { user_id, stats } => {
+                retire_user_stats(&session.user_stats, user_id, &stats);
+                push_event(events, MediaEvent::UserInactive(user_id));
+            }
+            SessionEvent::Audio { user_id, timestamp, gap, payload, .. } => {
+                if !session.deafened {
+                    receive_audio_frame(session, user_id, timestamp, gap, payload, events);
+                }
+            }
+            SessionEvent::Video(reassembled) => receive_video_frame(session, reassembled, events),
+            SessionEvent::Data { user_id, channel_id, timestamp, payload } => {
+                receive_data(session, user_id, channel_id, timestamp, payload)
             }
         }
-        quic::MEDIA_TYPE_VIDEO => {
-            receive_video_fragment(session, frame, events);
-        }
-        _ => {
-            tracing::trace!("Ignoring media_type={}", frame.header.media_type);
-        }
+    }
+}
+
+/// Copy a user's receive statistics to the map shared with Python.
+fn publish_user_stats(session: &ActiveSession, user_id: u32) {
+    let Some(stats) = session.media.stats(user_id) else {
+        return;
+    };
+    if let Ok(mut shared) = session.user_stats.lock() {
+        shared.insert(user_id, stats.snapshot());
     }
 }
 
 /// Encode and send an audio frame over QUIC.
 fn send_audio_frame(session: &mut ActiveSession, pcm: Vec<i16>) {
-    let (opus_data, is_dtx) = match session.encoder.encode(&pcm) {
+    let encoded = tracing::trace_span!("opus_encode").in_scope(|| session.encoder.encode(&pcm));
+    let (opus_data, is_dtx) = match encoded {
         Ok(pair) => pair,
         Err(e) => {
             tracing::warn!("Opus encode error: {}", e);
             return;
         }
     };
+    let info = transform::FrameInfo {
+        media_type: quic::MEDIA_TYPE_AUDIO,
+        user_id: session.user_id,
+        timestamp: session.media.audio_timestamp(),
+    };
+    let opus_data = match seal_payload(session, &info, opus_data.to_vec()) {
+        Some(d) => Bytes::from(d),
+        None => return,
+    };
 
-    let mut frame = quic::OutFrame::audio(
-        session.room_id,
-        session.user_id,
-        quic::CODEC_OPUS,
-        session.sequence,
-        session.timestamp,
-        opus_data,
-    );
-    frame.header.dtx = is_dtx;
+    let frame = session.media.audio_frame(opus_data, is_dtx);
+
+    // Audio frames are never fragmented; drop rather than error if the
+    // path cannot carry this one.
+    let max_payload = session.link.max_payload();
+    if frame.payload.len() > max_payload {
+        tracing::warn!(
+            "Audio frame of {} bytes exceeds max datagram payload {}, dropping",
+            frame.payload.len(),
+            max_payload
+        );
+    } else if let Err(e) = session.link.send(frame.encode()) {
+        tracing::warn!("Failed to send audio frame: {}", e);
+    }
+}
+
+/// Store new downlink preferences, signal them to the SFU and drop video
+/// decoders that no longer fit.
+fn set_receive_preferences(session: &mut ActiveSession, prefs: quic::ReceivePreferences) {
+    session.receive_prefs = prefs;
+
+    let frame = session.media.feedback_frame(prefs.encode());
+    if let Err(e) = session.link.send_reliable(frame.encode()) {
+        tracing::warn!("Failed to send receive preferences: {e}");
+    }
+
+    let keep = if prefs.audio_only {
+        0
+    } else {
+        prefs.max_video_streams.map_or(usize::MAX, usize::from)
+    };
+    if session.video_decoders.len() > keep {
+        // Keep the most recently active streams
+        let mut users: Vec<(u32, Instant)> = session
+            .video_decoders
+            .iter()
+            .map(|(&uid, d)| (uid, d.last_used))
+            .collect();
+        users.sort_by(|a, b| b.1.cmp(&a.1));
+        for (uid, _) in users.into_iter().skip(keep) {
+            session.video_decoders.remove(&uid);
+        }
+    }
+}
+
+/// Whether video from `user_id` fits the local receive preferences.
+fn accepts_video(session: &ActiveSession, user_id: u32) -> bool {
+    let prefs = &session.receive_prefs;
+    if prefs.audio_only {
+        return false;
+    }
+    match prefs.max_video_streams {
+        Some(max) => {
+            session.video_decoders.contains_key(&user_id)
+                || session.video_decoders.len() < usize::from(max)
+        }
+        None => true,
+    }
+}
+
+/// Send an application data message on a data channel.
+fn send_data(
+    session: &mut ActiveSession,
+    channel_id: u16,
+    data: Vec<u8>,
+    reliable: bool,
+    events: &EventQueue,
+) {
+    let info = transform::FrameInfo {
+        media_type: quic::MEDIA_TYPE_DATA,
+        user_id: session.user_id,
+        timestamp: 0,
+    };
+    let data = match seal_payload(session, &info, data) {
+        Some(d) => d,
+        None => return,
+    };
+
+    let frame = session.media.data_frame(channel_id, &data);
+
+    let result = if reliable {
+        session.link.send_reliable(frame.encode())
+    } else if frame.payload.len() > session.link.max_payload() {
+        Err(quic::LinkError::TooLarge)
+    } else {
+        session.link.send(frame.encode())
+    };
+    if let Err(e) = result {
+        push_event(
+            events,
+            MediaEvent::DataError(format!("channel {channel_id}: {e}")),
+        );
+    }
+}
+
+/// Queue a received data channel message for Python.
+fn receive_data(session: &mut ActiveSession, user_id: u32, channel_id: u16, timestamp: u32, payload: Bytes) {
+    let info = transform::FrameInfo {
+        media_type: quic::MEDIA_TYPE_DATA,
+        user_id,
+        timestamp,
+    };
+    let data = match open_payload(session, &info, payload.to_vec()) {
+        Some(d) => d,
+        None => return,
+    };
+    push_data_message(&session.data_queue, DataMessage {
+        user_id,
+        channel_id,
+        data,
+    });
+}
+
+/// Emit `audio_warning` if local audio glitches occurred since the last
+/// check, at most once per `AUDIO_WARNING_INTERVAL`.
+fn check_audio_stats(session: &mut ActiveSession, events: &EventQueue) {
+    let (checked_at, last) = session.audio_stats_checked;
+    if checked_at.elapsed() < AUDIO_WARNING_INTERVAL {
+        return;
+    }
+    let now = session.audio_stats.snapshot();
+    session.audio_stats_checked = (Instant::now(), now);
+    if now != last {
+        push_event(
+            events,
+            MediaEvent::AudioWarning {
+                underruns: now.underruns - last.underruns,
+                overflow_samples: now.overflow_samples - last.overflow_samples,
+                capture_overruns: now.capture_overruns - last.capture_overruns,
+            },
+        );
+    }
+}
 
-    if let Err(e) = session.connection.send_datagram(frame.encode()) {
-        tracing::warn!("Failed to send datagram: {}", e);
+/// Rebuild a failed capture or playback stream on the default device.
+///
+/// Errors from the old stream that are still queued, and repeated failures
+/// within `DEVICE_FALLBACK_INTERVAL`, are reported instead of rebuilding, so
+/// a persistently broken default device cannot cause a rebuild loop.
+fn handle_audio_stream_error(session: &mut ActiveSession, direction: audio::AudioDirection, events: &EventQueue) {
+    if session
+        .device_fallbacks
+        .get(&direction)
+        .is_some_and(|t| t.elapsed() < DEVICE_FALLBACK_INTERVAL)
+    {
+        return;
     }
+    session.device_fallbacks.insert(direction, Instant::now());
 
-    session.sequence = session.sequence.wrapping_add(1);
-    session.timestamp = session.timestamp.wrapping_add(960);
+    let result = match direction {
+        audio::AudioDirection::Capture => {
+            audio::start_capture(None, CAPTURE_FRAME_SAMPLES, session.audio_errors_tx.clone(), session.audio_stats.clone()).map(|(stream, rx, config)| {
+                session._capture_stream = stream;
+                session.capture_rx = rx;
+                session.capture_config = config.clone();
+                config
+            })
+        }
+        audio::AudioDirection::Playback => {
+            audio::start_playback(None, session.comfort_noise.clone(), session.audio_errors_tx.clone(), session.audio_stats.clone()).map(
+                |(stream, tx, config)| {
+                    session._playback_stream = stream;
+                    session.playback_tx = tx;
+                    session.playback_config = config.clone();
+                    config
+                },
+            )
+        }
+    };
+    match result {
+        Ok(config) => {
+            tracing::warn!("Audio {} stream failed, switched to default device {:?}", direction.as_str(), config.device);
+            push_event(events, MediaEvent::DeviceFallback { direction: direction.as_str(), device: config.device.clone() });
+            push_event(events, MediaEvent::AudioConfig { direction: direction.as_str(), config });
+        }
+        Err(e) => {
+            push_event(events, MediaEvent::AudioError(format!("{} device fallback failed: {e}", direction.as_str())));
+        }
+    }
 }
 
 /// Update speaking state for a user based on PCM audio levels.
 /// Emits SpeakingStart/SpeakingStop events with hysteresis.
-fn update_speaking_state(session: &mut ActiveSession, user_id: u32, pcm: &[i16], events: &EventQueue) {
+/// Returns whether this frame was above the speaking threshold.
+fn update_speaking_state(session: &mut ActiveSession, user_id: u32, pcm: &[i16], events: &EventQueue) -> bool {
     if pcm.is_empty() {
-        return;
+        return false;
     }
     let rms = (pcm.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / pcm.len() as f64).sqrt();
     let normalized_rms = rms / 32767.0;
@@ -618,7 +1634,8 @@ fn update_speaking_state(session: &mut ActiveSession, user_id: u32, pcm: &[i16],
         last_above_threshold: now - SPEAKING_HOLDOFF - Duration::from_millis(1),
     });
 
-    if normalized_rms >= SPEAKING_THRESHOLD {
+    let voiced = normalized_rms >= SPEAKING_THRESHOLD;
+    if voiced {
         state.last_above_threshold = now;
         if !state.speaking {
             state.speaking = true;
@@ -628,22 +1645,68 @@ fn update_speaking_state(session: &mut ActiveSession, user_id: u32, pcm: &[i16],
         state.speaking = false;
         push_event(events, MediaEvent::SpeakingStop(user_id));
     }
+    voiced
 }
 
-/// Decode and play back a received audio frame with per-user decoder and volume scaling.
-fn receive_audio_frame(session: &mut ActiveSession, frame: quic::InFrame, events: &EventQueue) {
-    let user_id = frame.header.user_id;
+/// Decode and play back a received audio frame with per-user decoder and
+/// volume scaling, concealing the `gap` frames lost before it.
+fn receive_audio_frame(
+    session: &mut ActiveSession,
+    user_id: u32,
+    timestamp: u32,
+    gap: u32,
+    payload: Bytes,
+    events: &EventQueue,
+) {
+    let info = transform::FrameInfo {
+        media_type: quic::MEDIA_TYPE_AUDIO,
+        user_id,
+        timestamp,
+    };
+    let payload = match open_payload(session, &info, payload.to_vec()) {
+        Some(p) => p,
+        None => return,
+    };
 
-    let user_decoder = session
-        .audio_decoders
-        .entry(user_id)
-        .or_insert_with(|| UserAudioDecoder {
-            decoder: codec::OpusDecoder::new().expect("opus decoder"),
-            last_used: Instant::now(),
-        });
+    if !session.audio_decoders.contains_key(&user_id) {
+        if !decoder_retry_due(session, user_id, quic::MEDIA_TYPE_AUDIO) {
+            return;
+        }
+        match codec::OpusDecoder::new() {
+            Ok(decoder) => {
+                session.decoder_failures.remove(&(user_id, quic::MEDIA_TYPE_AUDIO));
+                session.audio_decoders.insert(user_id, UserAudioDecoder {
+                    decoder,
+                    last_used: Instant::now(),
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to create Opus decoder for user {user_id}: {e}");
+                session.decoder_failures.insert((user_id, quic::MEDIA_TYPE_AUDIO), Instant::now());
+                push_event(events, MediaEvent::AudioError(format!("Opus decoder init failed for user {user_id}: {e}")));
+                return;
+            }
+        }
+    }
+    let Some(user_decoder) = session.audio_decoders.get_mut(&user_id) else {
+        return;
+    };
     user_decoder.last_used = Instant::now();
 
-    let mut pcm = match user_decoder.decoder.decode(&frame.payload) {
+    // Conceal lost frames before playing the one that just arrived
+    let mut concealed = Vec::new();
+    for _ in 0..gap.min(MAX_CONCEALED_FRAMES) {
+        match user_decoder.decoder.conceal() {
+            Ok(pcm) => concealed.push(pcm),
+            Err(e) => {
+                tracing::warn!("Opus concealment error for user {}: {}", user_id, e);
+                break;
+            }
+        }
+    }
+
+    let decoded = tracing::trace_span!("opus_decode", user_id).in_scope(|| user_decoder.decoder.decode(&payload));
+    let pcm = match decoded {
         Ok(samples) => samples,
         Err(e) => {
             tracing::warn!("Opus decode error for user {}: {}", user_id, e);
@@ -651,12 +1714,26 @@ fn receive_audio_frame(session: &mut ActiveSession, frame: quic::InFrame, events
         }
     };
 
+    if let Some(stats) = session.media.stats_mut(user_id) {
+        stats.concealed_frames += concealed.len() as u64;
+    }
+    for frame in concealed {
+        play_audio(session, user_id, frame);
+    }
+
     // Speaking detection on decoded PCM (before volume scaling)
     update_speaking_state(session, user_id, &pcm, events);
+    play_audio(session, user_id, pcm);
+}
 
-    // Apply per-user volume and global output volume
+/// Apply per-user volume, ducking, mix priority and global output volume,
+/// then queue PCM for playback.
+fn play_audio(session: &mut ActiveSession, user_id: u32, mut pcm: Vec<i16>) {
+    if session.speaking_states.get(&user_id).is_some_and(|st| st.speaking) {
+        session.mix_source.mark_active();
+    }
     let user_vol = session.user_volumes.get(&user_id).copied().unwrap_or(1.0);
-    let combined_vol = user_vol * session.output_volume;
+    let combined_vol = user_vol * session.ducker.gain(user_id) * session.mix_source.gain() * session.output_volume;
 
     if (combined_vol - 1.0).abs() > f32::EPSILON {
         for s in pcm.iter_mut() {
@@ -667,47 +1744,67 @@ fn receive_audio_frame(session: &mut ActiveSession, frame: quic::InFrame, events
     let _ = session.playback_tx.send(pcm);
 }
 
-/// Process a received video fragment: reassemble → decode → push to queue.
-fn receive_video_fragment(
+/// Process a reassembled video frame: decrypt → decode → push to queue.
+fn receive_video_frame(
     session: &mut ActiveSession,
-    frame: quic::InFrame,
-    _events: &EventQueue,
+    reassembled: quic::ReassembledFrame,
+    events: &EventQueue,
 ) {
-    let reassembled = match session
-        .video_reassembler
-        .add_fragment(&frame.header, &frame.payload)
-    {
-        Some(r) => r,
-        None => return, // Still collecting fragments
+    let info = transform::FrameInfo {
+        media_type: quic::MEDIA_TYPE_VIDEO,
+        user_id: reassembled.user_id,
+        timestamp: reassembled.timestamp,
+    };
+    let data = match open_payload(session, &info, reassembled.data) {
+        Some(d) => d,
+        None => return,
     };
 
+    // Re-check now that the frame is complete: other new senders may have
+    // taken the remaining stream slots while this one was reassembling.
+    if !accepts_video(session, reassembled.user_id) {
+        return;
+    }
+
     // Get or create per-user decoder
-    let user_decoder = session
-        .video_decoders
-        .entry(reassembled.user_id)
-        .or_insert_with(|| {
-            let decoder = codec::Av1Decoder::new().unwrap_or_else(|e| {
-                tracing::error!("Failed to create AV1 decoder for user {}: {e}", reassembled.user_id);
-                // Return a decoder that will likely fail — but we log the error
-                // This branch shouldn't realistically happen.
-                panic!("dav1d init failed: {e}");
-            });
-            UserVideoDecoder {
-                decoder,
-                last_used: Instant::now(),
+    let user_id = reassembled.user_id;
+    if !session.video_decoders.contains_key(&user_id) {
+        if !decoder_retry_due(session, user_id, quic::MEDIA_TYPE_VIDEO) {
+            return;
+        }
+        match codec::Av1Decoder::new() {
+            Ok(decoder) => {
+                session.decoder_failures.remove(&(user_id, quic::MEDIA_TYPE_VIDEO));
+                session.video_decoders.insert(user_id, UserVideoDecoder {
+                    decoder,
+                    last_used: Instant::now(),
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to create AV1 decoder for user {user_id}: {e}");
+                session.decoder_failures.insert((user_id, quic::MEDIA_TYPE_VIDEO), Instant::now());
+                push_event(events, MediaEvent::VideoError(format!("AV1 decoder init failed for user {user_id}: {e}")));
+                return;
             }
-        });
+        }
+    }
+    let Some(user_decoder) = session.video_decoders.get_mut(&user_id) else {
+        return;
+    };
     user_decoder.last_used = Instant::now();
 
-    match user_decoder.decoder.decode(&reassembled.data) {
+    let decoded = tracing::debug_span!("av1_decode", user_id, bytes = data.len())
+        .in_scope(|| user_decoder.decoder.decode(&data));
+    match decoded {
         Ok(Some(decoded)) => {
+            let (width, height, rgba) = fit_receive_resolution(&session.receive_prefs, decoded);
             push_video_frame(
                 &session.video_frame_queue,
                 VideoFrameOutput {
-                    user_id: reassembled.user_id,
-                    width: decoded.width,
-                    height: decoded.height,
-                    rgba: decoded.rgba,
+                    user_id,
+                    width,
+                    height,
+                    rgba,
                 },
             );
         }
@@ -715,8 +1812,84 @@ fn receive_video_fragment(
             // Decoder needs more data
         }
         Err(e) => {
-            tracing::warn!("AV1 decode error for user {}: {e}", reassembled.user_id);
+            tracing::warn!("AV1 decode error for user {}: {e}", user_id);
+        }
+    }
+}
+
+/// Downscale a decoded frame larger than the preferred maximum resolution.
+/// The SFU may still forward a larger layer, for example when it has no
+/// smaller one, so the limit is also enforced here.
+fn fit_receive_resolution(prefs: &quic::ReceivePreferences, frame: codec::DecodedFrame) -> (u32, u32, Vec<u8>) {
+    let Some((width, height)) = prefs.fit_resolution(frame.width, frame.height) else {
+        return (frame.width, frame.height, frame.rgba);
+    };
+    let rgb = video::rgba_to_rgb(&frame.rgba);
+    let scaled = video::scale_rgb(
+        &rgb,
+        frame.width as usize,
+        frame.height as usize,
+        width as usize,
+        height as usize,
+        video::ScaleMode::Stretch,
+    );
+    (width, height, video::rgb_to_rgba(&scaled))
+}
+
+/// Run an outgoing encoded payload through the frame transform, then
+/// SFrame-encrypt it if media E2EE is enabled.
+/// Returns `None` if the frame should be dropped.
+fn seal_payload(
+    session: &ActiveSession,
+    info: &transform::FrameInfo,
+    payload: Vec<u8>,
+) -> Option<Vec<u8>> {
+    let payload = match session.frame_transform.lock().ok()?.as_mut() {
+        Some(t) => t.on_send(info, payload)?,
+        None => payload,
+    };
+
+    let mut keys = session.media_keys.lock().ok()?;
+    if !keys.is_enabled() {
+        return Some(payload);
+    }
+    let _timer = profile::SFRAME_ENCRYPT.start();
+    match keys.encrypt(session.user_id, &payload) {
+        Ok(sealed) => Some(sealed),
+        Err(e) => {
+            tracing::warn!("Media encrypt error: {e}");
+            None
+        }
+    }
+}
+
+/// SFrame-decrypt a received payload if media E2EE is enabled, then run it
+/// through the frame transform.
+/// Returns `None` if the frame should be dropped.
+fn open_payload(
+    session: &ActiveSession,
+    info: &transform::FrameInfo,
+    payload: Vec<u8>,
+) -> Option<Vec<u8>> {
+    let payload = {
+        let mut keys = session.media_keys.lock().ok()?;
+        if keys.is_enabled() {
+            let _timer = profile::SFRAME_DECRYPT.start();
+            match keys.decrypt(info.user_id, &payload) {
+                Ok(plain) => plain,
+                Err(e) => {
+                    tracing::debug!("Dropping undecryptable frame from user {}: {e}", info.user_id);
+                    return None;
+                }
+            }
+        } else {
+            payload
         }
+    };
+
+    match session.frame_transform.lock().ok()?.as_mut() {
+        Some(t) => t.on_receive(info, payload),
+        None => Some(payload),
     }
 }
 
@@ -739,25 +1912,79 @@ fn apply_input_processing(pcm: &mut Vec<i16>, volume: f32, gate_threshold: f32)
     }
 }
 
-/// Evict per-user audio and video decoders that have been idle too long.
-fn evict_idle_decoders(session: &mut ActiveSession) {
-    let now = Instant::now();
+/// Whether a decoder for this user may be (re)created now, i.e. no creation
+/// failed within the last `DECODER_RETRY_INTERVAL`.
+fn decoder_retry_due(session: &ActiveSession, user_id: u32, media_type: u8) -> bool {
     session
-        .audio_decoders
-        .retain(|uid, dec| {
-            let keep = now.duration_since(dec.last_used) < DECODER_IDLE_TIMEOUT;
-            if !keep {
-                tracing::debug!("Evicting idle audio decoder for user {uid}");
-            }
-            keep
-        });
-    session
-        .video_decoders
-        .retain(|uid, dec| {
-            let keep = now.duration_since(dec.last_used) < DECODER_IDLE_TIMEOUT;
-            if !keep {
-                tracing::debug!("Evicting idle video decoder for user {uid}");
-            }
-            keep
-        });
+        .decoder_failures
+        .get(&(user_id, media_type))
+        .is_none_or(|t| t.elapsed() >= DECODER_RETRY_INTERVAL)
+}
+
+/// Log a departing user's audio sequence counters and forget their shared stats.
+fn retire_user_stats(user_stats: &UserStatsMap, user_id: u32, stats: &ReceiveStats) {
+    if let Ok(mut shared) = user_stats.lock() {
+        shared.remove(&user_id);
+    }
+    let seq = &stats.audio_seq;
+    tracing::debug!(
+        "Audio from user {user_id}: received={} lost={} ({:.1}%) reordered={} duplicates={}",
+        seq.received,
+        seq.lost,
+        seq.loss_fraction() * 100.0,
+        seq.reordered,
+        seq.duplicates
+    );
+}
+
+/// Free everything held for a remote user who left the room.
+fn drop_user(session: &mut ActiveSession, user_id: u32, events: &EventQueue) {
+    session.audio_decoders.remove(&user_id);
+    session.video_decoders.remove(&user_id);
+    session.decoder_failures.retain(|(uid, _), _| *uid != user_id);
+    session.user_volumes.remove(&user_id);
+    session.rate_limiter.drop_user(user_id);
+    if session.speaking_states.remove(&user_id).is_some_and(|st| st.speaking) {
+        push_event(events, MediaEvent::SpeakingStop(user_id));
+    }
+    if let Some(stats) = session.media.drop_user(user_id) {
+        retire_user_stats(&session.user_stats, user_id, &stats);
+        push_event(events, MediaEvent::UserInactive(user_id));
+    }
+    tracing::debug!("Dropped media state for user {user_id}");
+}
+
+/// Remove the per-user entries last used `timeout` or longer before `now`,
+/// returning the user ids removed.
+pub fn evict_idle<T>(
+    entries: &mut HashMap<u32, T>,
+    now: Instant,
+    timeout: Duration,
+    last_used: impl Fn(&T) -> Instant,
+) -> Vec<u32> {
+    let mut evicted = Vec::new();
+    entries.retain(|&user_id, entry| {
+        let keep = now.saturating_duration_since(last_used(entry)) < timeout;
+        if !keep {
+            evicted.push(user_id);
+        }
+        keep
+    });
+    evicted
+}
+
+/// Evict per-user audio and video decoders that have been idle too long and
+/// stale partial video frames, and report remote users whose media stopped
+/// as inactive.
+fn evict_idle_decoders(session: &mut ActiveSession, events: &EventQueue) {
+    let now = Instant::now();
+    let timeout = session.decoder_idle_timeout;
+    for uid in evict_idle(&mut session.audio_decoders, now, timeout, |dec| dec.last_used) {
+        tracing::debug!("Evicting idle audio decoder for user {uid}");
+    }
+    for uid in evict_idle(&mut session.video_decoders, now, timeout, |dec| dec.last_used) {
+        tracing::debug!("Evicting idle video decoder for user {uid}");
+    }
+    session.media.handle_timeout(now, timeout, REASSEMBLY_STALE_TIMEOUT);
+    drain_media_events(session, events);
 }
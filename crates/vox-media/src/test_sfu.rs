@@ -0,0 +1,271 @@
+//! An in-process SFU for tests, built with the `test-sfu` feature.
+//!
+//! [`MockSfu`] listens on localhost with a freshly generated self-signed
+//! certificate, runs the auth handshake and forwards each frame a client
+//! sends to the other clients in its room, so [`MediaClient`] connect,
+//! reconnect and media paths can be exercised in CI without a real server.
+//! It speaks only as much of the protocol as that needs: frames are
+//! forwarded unchanged, receive preferences are ignored and nothing is
+//! paced or congestion controlled.
+//!
+//! [`MediaClient`]: crate::MediaClient
+
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::client::ConnectOptions;
+use crate::handshake::{self, Hello};
+use crate::quic::{self, CertPins, MediaHeader, MediaLink, TransportPreference};
+
+/// Capabilities the mock SFU advertises.
+const CAPABILITIES: u32 =
+    handshake::CAP_DATAGRAMS | handshake::CAP_STREAM_FALLBACK | handshake::CAP_DATA_CHANNEL | handshake::CAP_SFRAME;
+/// Upper bound on a client's `Hello`.
+const MAX_HELLO_LEN: usize = 4096;
+/// Close code for [`MockSfu::drop_connections`], distinct from a leave.
+const CLOSE_DROPPED: u32 = 0x100;
+/// How long a rejected client gets to read the rejection.
+const REJECT_LINGER: Duration = Duration::from_secs(1);
+
+/// How a [`MockSfu`] treats its clients.
+#[derive(Debug, Clone, Default)]
+pub struct MockSfuConfig {
+    /// Reject every handshake with this reason.
+    pub reject: Option<String>,
+    /// Only accept these tokens; `None` accepts any.
+    pub tokens: Option<Vec<String>>,
+    /// Also send each frame back to its sender, relabelled as coming from
+    /// this user, so a lone client receives its own media.
+    pub echo_user_id: Option<u32>,
+}
+
+/// A client session the SFU has accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MockSession {
+    pub room_id: u32,
+    pub user_id: u32,
+}
+
+struct Peer {
+    session: MockSession,
+    link: MediaLink,
+}
+
+#[derive(Default)]
+struct Shared {
+    config: Mutex<MockSfuConfig>,
+    peers: Mutex<HashMap<u64, Peer>>,
+    next_peer: AtomicU64,
+    handshakes: AtomicU64,
+    frames: AtomicU64,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A minimal SFU running on its own runtime thread. Stops when dropped.
+pub struct MockSfu {
+    runtime: Option<tokio::runtime::Runtime>,
+    endpoint: quinn::Endpoint,
+    addr: SocketAddr,
+    cert_der: Vec<u8>,
+    shared: Arc<Shared>,
+}
+
+impl MockSfu {
+    /// Start listening on an ephemeral localhost port.
+    pub fn start(config: MockSfuConfig) -> Result<Self, String> {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+            .map_err(|e| format!("Failed to generate certificate: {e}"))?;
+        let cert = generated.cert.der().clone();
+        let key = rustls::pki_types::PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der());
+        let mut tls = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key.into())
+            .map_err(|e| format!("Invalid certificate: {e}"))?;
+        tls.alpn_protocols = vec![quic::ALPN_PROTOCOL.to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+            .map_err(|e| format!("QUIC TLS config error: {e}"))?;
+        let server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("vox-mock-sfu")
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create runtime: {e}"))?;
+        let endpoint = {
+            let _guard = runtime.enter();
+            quinn::Endpoint::server(server_config, SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+                .map_err(|e| format!("Failed to bind mock SFU: {e}"))?
+        };
+        let addr = endpoint.local_addr().map_err(|e| format!("Failed to read local address: {e}"))?;
+        let shared = Arc::new(Shared { config: Mutex::new(config), ..Default::default() });
+        runtime.spawn(accept_loop(endpoint.clone(), shared.clone()));
+        tracing::info!("Mock SFU listening on {addr}");
+
+        Ok(MockSfu { runtime: Some(runtime), endpoint, addr, cert_der: cert.to_vec(), shared })
+    }
+
+    /// URL to connect to.
+    pub fn url(&self) -> String {
+        format!("quic://{}", self.addr)
+    }
+
+    /// The SFU's self-signed certificate, to pin.
+    pub fn cert_der(&self) -> &[u8] {
+        &self.cert_der
+    }
+
+    pub fn pins(&self) -> CertPins {
+        CertPins { certs: vec![self.cert_der.clone()], spki_sha256: Vec::new() }
+    }
+
+    /// Options for joining `room_id` as `user_id`, pinned to this SFU.
+    pub fn connect_options(&self, token: &str, room_id: u32, user_id: u32) -> ConnectOptions {
+        let mut options = ConnectOptions::new(self.url(), token, room_id, user_id);
+        options.pins = self.pins();
+        options
+    }
+
+    /// Replace the configuration; applies to handshakes and frames from now on.
+    pub fn set_config(&self, config: MockSfuConfig) {
+        *lock(&self.shared.config) = config;
+    }
+
+    /// Sessions currently connected, sorted.
+    pub fn sessions(&self) -> Vec<MockSession> {
+        let mut sessions: Vec<_> = lock(&self.shared.peers).values().map(|p| p.session).collect();
+        sessions.sort();
+        sessions
+    }
+
+    /// Handshakes received, accepted or not.
+    pub fn handshakes(&self) -> u64 {
+        self.shared.handshakes.load(Ordering::Relaxed)
+    }
+
+    /// Media and data frames received from clients.
+    pub fn frames_received(&self) -> u64 {
+        self.shared.frames.load(Ordering::Relaxed)
+    }
+
+    /// Close every client connection as a network failure would. The SFU
+    /// keeps listening, so clients can reconnect.
+    pub fn drop_connections(&self) {
+        for (_, peer) in lock(&self.shared.peers).drain() {
+            peer.link.connection().close(CLOSE_DROPPED.into(), b"dropped by test");
+        }
+    }
+}
+
+impl Drop for MockSfu {
+    fn drop(&mut self) {
+        self.endpoint.close(quic::CLOSE_NORMAL.into(), b"mock SFU stopped");
+        lock(&self.shared.peers).clear();
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+async fn accept_loop(endpoint: quinn::Endpoint, shared: Arc<Shared>) {
+    while let Some(incoming) = endpoint.accept().await {
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(incoming, shared).await {
+                tracing::debug!("Mock SFU connection ended: {e}");
+            }
+        });
+    }
+}
+
+/// Run one client connection: handshake, then forward until it goes away.
+async fn serve(incoming: quinn::Incoming, shared: Arc<Shared>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let connection = incoming.await?;
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let hello = recv.read_to_end(MAX_HELLO_LEN).await?;
+    let (version, hello) = Hello::decode(&hello).ok_or("malformed hello")?;
+    shared.handshakes.fetch_add(1, Ordering::Relaxed);
+
+    let verdict = {
+        let config = lock(&shared.config);
+        match (&config.reject, &config.tokens) {
+            (Some(reason), _) => Err(reason.clone()),
+            (None, Some(tokens)) if !tokens.contains(&hello.token) => Err("invalid token".to_string()),
+            _ => Ok(CAPABILITIES),
+        }
+    };
+    let version = version.min(handshake::PROTOCOL_VERSION);
+    send.write_all(&handshake::encode_response(version, verdict.as_ref().copied().map_err(String::as_str)))
+        .await?;
+    send.finish()?;
+    if verdict.is_err() {
+        let _ = tokio::time::timeout(REJECT_LINGER, send.stopped()).await;
+        return Ok(());
+    }
+
+    let session = MockSession { room_id: hello.room_id, user_id: hello.user_id };
+    let id = shared.next_peer.fetch_add(1, Ordering::Relaxed);
+    let mut stream_rx = quic::spawn_stream_reader(connection.clone());
+    let peer_streams = hello.capabilities & handshake::CAP_STREAM_FALLBACK != 0;
+    let link = MediaLink::new(connection.clone(), TransportPreference::Auto, peer_streams);
+    lock(&shared.peers).insert(id, Peer { session, link });
+
+    loop {
+        let packet = tokio::select! {
+            result = connection.read_datagram() => match result {
+                Ok(packet) => packet,
+                Err(_) => break,
+            },
+            Some(packet) = stream_rx.recv() => packet,
+        };
+        if !forward(&shared, id, session, packet) {
+            break;
+        }
+    }
+    lock(&shared.peers).remove(&id);
+    Ok(())
+}
+
+/// Forward a packet from peer `from` to the rest of its room. Returns
+/// false once the peer says it is leaving.
+fn forward(shared: &Shared, from: u64, session: MockSession, packet: Bytes) -> bool {
+    let Some(header) = MediaHeader::parse(&packet) else {
+        return true;
+    };
+    if header.media_type == quic::MEDIA_TYPE_RTCP_FB {
+        // Feedback is for the SFU; only a leave needs acting on
+        return packet.get(quic::HEADER_SIZE) != Some(&quic::FB_LEAVE);
+    }
+    shared.frames.fetch_add(1, Ordering::Relaxed);
+
+    let echo_user_id = lock(&shared.config).echo_user_id;
+    for (&id, peer) in lock(&shared.peers).iter_mut() {
+        if peer.session.room_id != session.room_id {
+            continue;
+        }
+        let packet = match echo_user_id {
+            _ if id != from => packet.clone(),
+            Some(user_id) => relabel(&packet, &header, user_id),
+            None => continue,
+        };
+        if let Err(e) = peer.link.send(packet) {
+            tracing::debug!("Mock SFU failed to forward to {:?}: {e}", peer.session);
+        }
+    }
+    true
+}
+
+/// A copy of `packet` claiming to come from `user_id`.
+fn relabel(packet: &Bytes, header: &MediaHeader, user_id: u32) -> Bytes {
+    let header = MediaHeader { user_id, ..header.clone() };
+    let mut relabelled = BytesMut::from(&packet[..]);
+    relabelled[..quic::HEADER_SIZE].copy_from_slice(&header.encode());
+    relabelled.freeze()
+}
@@ -0,0 +1,102 @@
+//! Insertable transforms on encoded media frames.
+//!
+//! A transform sees every encoded Opus/AV1 frame just before it is sent
+//! (after encoding, before SFrame encryption and packetization) and just
+//! after it is received (after reassembly and SFrame decryption, before
+//! decoding). It can rewrite the payload or drop the frame entirely, which
+//! allows custom encryption, watermarking or metadata injection without
+//! forking the media pipeline.
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyBytes;
+use std::sync::{Arc, Mutex};
+
+/// Metadata describing the frame passed to a transform.
+pub struct FrameInfo {
+    /// One of the `quic::MEDIA_TYPE_*` values.
+    pub media_type: u8,
+    /// Sender of the frame (the local user on send).
+    pub user_id: u32,
+    /// Media timestamp from the frame header.
+    pub timestamp: u32,
+}
+
+/// A transform applied to encoded frames. Returning `None` drops the frame.
+pub trait FrameTransform: Send {
+    fn on_send(&mut self, _info: &FrameInfo, payload: Vec<u8>) -> Option<Vec<u8>> {
+        Some(payload)
+    }
+
+    fn on_receive(&mut self, _info: &FrameInfo, payload: Vec<u8>) -> Option<Vec<u8>> {
+        Some(payload)
+    }
+}
+
+/// Shared slot holding the currently registered transform, if any.
+pub(crate) type SharedTransform = Arc<Mutex<Option<Box<dyn FrameTransform>>>>;
+
+/// Transform backed by a Python object.
+///
+/// The object may define either or both of:
+///
+/// ```python
+/// def on_send(self, media_type: int, user_id: int, timestamp: int, payload: bytes) -> bytes | None
+/// def on_receive(self, media_type: int, user_id: int, timestamp: int, payload: bytes) -> bytes | None
+/// ```
+///
+/// Returning `None` drops the frame. A missing method passes frames through
+/// unchanged. If the method raises, the frame is dropped rather than sent or
+/// played unmodified.
+#[cfg(feature = "python")]
+pub struct PyFrameTransform {
+    obj: Py<PyAny>,
+}
+
+#[cfg(feature = "python")]
+impl PyFrameTransform {
+    pub fn new(obj: Py<PyAny>) -> Self {
+        PyFrameTransform { obj }
+    }
+
+    fn call(&self, method: &str, info: &FrameInfo, payload: Vec<u8>) -> Option<Vec<u8>> {
+        Python::attach(|py| {
+            let obj = self.obj.bind(py);
+            if !obj.hasattr(method).unwrap_or(false) {
+                return Some(payload);
+            }
+            let args = (
+                info.media_type,
+                info.user_id,
+                info.timestamp,
+                PyBytes::new(py, &payload),
+            );
+            match obj.call_method1(method, args) {
+                Ok(ret) if ret.is_none() => None,
+                Ok(ret) => match ret.extract::<Vec<u8>>() {
+                    Ok(out) => Some(out),
+                    Err(e) => {
+                        tracing::warn!("Frame transform {method} returned non-bytes: {e}");
+                        None
+                    }
+                },
+                Err(e) => {
+                    tracing::warn!("Frame transform {method} raised: {e}");
+                    None
+                }
+            }
+        })
+    }
+}
+
+#[cfg(feature = "python")]
+impl FrameTransform for PyFrameTransform {
+    fn on_send(&mut self, info: &FrameInfo, payload: Vec<u8>) -> Option<Vec<u8>> {
+        self.call("on_send", info, payload)
+    }
+
+    fn on_receive(&mut self, info: &FrameInfo, payload: Vec<u8>) -> Option<Vec<u8>> {
+        self.call("on_receive", info, payload)
+    }
+}
@@ -1,14 +1,19 @@
 //! Video capture via nokhwa — camera capture and pixel format conversion.
 
-use nokhwa::pixel_format::RgbFormat;
-use nokhwa::utils::{
-    CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution,
-};
-use nokhwa::Camera;
+use crate::background::SharedBackground;
+use crate::gpu;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// Fraction of the frame interval a frame may arrive early and still be kept,
+/// so ordinary camera jitter does not halve the frame rate.
+const FRAME_EARLY_TOLERANCE: f64 = 0.2;
+
 /// A captured frame from the camera, with both RGB→I420 and RGBA data.
 pub struct CapturedFrame {
     pub width: u32,
@@ -23,20 +28,93 @@ pub struct CapturedFrame {
     pub rgba: Vec<u8>,
 }
 
+/// Clockwise rotation applied to camera frames, for sideways cameras.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u32) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Cw90),
+            180 => Some(Rotation::Cw180),
+            270 => Some(Rotation::Cw270),
+            _ => None,
+        }
+    }
+
+    /// Whether width and height trade places.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+/// How camera frames are fitted to the encode size when the camera delivers
+/// a different resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Center-crop to the target aspect ratio, then scale.
+    #[default]
+    Crop,
+    /// Scale to the target size, distorting the aspect ratio if needed.
+    Stretch,
+}
+
+impl ScaleMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "crop" => Some(ScaleMode::Crop),
+            "stretch" => Some(ScaleMode::Stretch),
+            _ => None,
+        }
+    }
+}
+
 /// Camera configuration.
 #[derive(Debug, Clone)]
 pub struct CameraConfig {
+    /// Index of the camera to open.
+    pub device: u32,
     pub width: u32,
     pub height: u32,
     pub fps: u32,
+    /// Applied to both the encoded stream and the local preview.
+    pub rotation: Rotation,
+    /// Flip the local preview horizontally; the encoded stream is unchanged.
+    pub mirror_preview: bool,
+    /// Frames are always delivered at `width`x`height` (swapped when
+    /// rotated); this decides how other camera resolutions are fitted.
+    pub scale_mode: ScaleMode,
+}
+
+impl CameraConfig {
+    /// Size of delivered frames, after rotation.
+    pub fn output_size(&self) -> (usize, usize) {
+        let (w, h) = (self.width as usize, self.height as usize);
+        if self.rotation.swaps_dimensions() {
+            (h, w)
+        } else {
+            (w, h)
+        }
+    }
 }
 
 impl Default for CameraConfig {
     fn default() -> Self {
         CameraConfig {
+            device: 0,
             width: 640,
             height: 480,
             fps: 30,
+            rotation: Rotation::None,
+            mirror_preview: false,
+            scale_mode: ScaleMode::Crop,
         }
     }
 }
@@ -52,6 +130,14 @@ impl Drop for CameraStopHandle {
     }
 }
 
+/// Effects the session can change while the camera runs; they are
+/// consulted on every frame.
+#[derive(Clone, Default)]
+pub struct LiveEffects {
+    pub background: SharedBackground,
+    pub overlay: SharedOverlay,
+}
+
 /// Start camera capture in a background std::thread.
 ///
 /// Returns a bounded receiver of captured frames and a stop handle.
@@ -59,13 +145,16 @@ impl Drop for CameraStopHandle {
 /// if the consumer can't keep up.
 pub fn start_camera_capture(
     config: CameraConfig,
+    effects: LiveEffects,
 ) -> Result<(mpsc::Receiver<CapturedFrame>, CameraStopHandle), String> {
+    // The camera is opened on its own thread; fail here if there is none
+    webcam::Webcam::supported()?;
     let (tx, rx) = mpsc::channel(4);
     let stop = Arc::new(AtomicBool::new(false));
     let stop_clone = stop.clone();
 
     std::thread::spawn(move || {
-        if let Err(e) = camera_thread(config, tx, stop_clone) {
+        if let Err(e) = camera_thread(config, effects, tx, stop_clone) {
             tracing::error!("Camera thread exited with error: {e}");
         }
     });
@@ -73,30 +162,126 @@ pub fn start_camera_capture(
     Ok((rx, CameraStopHandle { stop }))
 }
 
+#[cfg(feature = "camera")]
+mod webcam {
+    use nokhwa::pixel_format::RgbFormat;
+    use nokhwa::utils::{CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution};
+    use nokhwa::{Buffer, Camera};
+
+    /// A camera streaming MJPEG. Stops the stream on drop.
+    pub struct Webcam(Camera);
+
+    /// One compressed frame as delivered by the camera.
+    pub struct Frame(Buffer);
+
+    impl Webcam {
+        pub fn supported() -> Result<(), String> {
+            Ok(())
+        }
+
+        /// Open the camera at the closest format to the one requested.
+        pub fn open(device: u32, width: u32, height: u32, fps: u32) -> Result<Self, String> {
+            let format = CameraFormat::new(Resolution::new(width, height), FrameFormat::MJPEG, fps);
+            let requested = RequestedFormat::new::<RgbFormat>(RequestedFormatType::Closest(format));
+            let mut camera =
+                Camera::new(CameraIndex::Index(device), requested).map_err(|e| format!("Camera open: {e}"))?;
+            camera.open_stream().map_err(|e| format!("Camera stream: {e}"))?;
+            Ok(Webcam(camera))
+        }
+
+        /// Actual `(width, height, fps)` the camera settled on.
+        pub fn format(&self) -> (u32, u32, u32) {
+            let actual = self.0.camera_format();
+            (actual.resolution().width(), actual.resolution().height(), actual.frame_rate())
+        }
+
+        pub fn frame(&mut self) -> Result<Frame, String> {
+            self.0.frame().map(Frame).map_err(|e| e.to_string())
+        }
+    }
+
+    impl Drop for Webcam {
+        fn drop(&mut self) {
+            let _ = self.0.stop_stream();
+        }
+    }
+
+    impl Frame {
+        pub fn bytes(&self) -> &[u8] {
+            self.0.buffer()
+        }
+
+        /// Decode to RGB888, returning the pixels, width and height.
+        pub fn decode_rgb(&self) -> Result<(Vec<u8>, usize, usize), String> {
+            let image = self.0.decode_image::<RgbFormat>().map_err(|e| e.to_string())?;
+            let (width, height) = (image.width() as usize, image.height() as usize);
+            Ok((image.into_raw(), width, height))
+        }
+    }
+}
+
+#[cfg(not(feature = "camera"))]
+mod webcam {
+    const UNAVAILABLE: &str = "vox-media was built without the camera feature";
+
+    pub enum Webcam {}
+
+    pub enum Frame {}
+
+    impl Webcam {
+        pub fn supported() -> Result<(), String> {
+            Err(UNAVAILABLE.into())
+        }
+
+        pub fn open(_device: u32, _width: u32, _height: u32, _fps: u32) -> Result<Self, String> {
+            Err(UNAVAILABLE.into())
+        }
+
+        pub fn format(&self) -> (u32, u32, u32) {
+            match *self {}
+        }
+
+        pub fn frame(&mut self) -> Result<Frame, String> {
+            match *self {}
+        }
+    }
+
+    impl Frame {
+        pub fn bytes(&self) -> &[u8] {
+            match *self {}
+        }
+
+        pub fn decode_rgb(&self) -> Result<(Vec<u8>, usize, usize), String> {
+            match *self {}
+        }
+    }
+}
+
 fn camera_thread(
     config: CameraConfig,
+    effects: LiveEffects,
     tx: mpsc::Sender<CapturedFrame>,
     stop: Arc<AtomicBool>,
 ) -> Result<(), String> {
-    let index = CameraIndex::Index(0);
-    let format = CameraFormat::new(
-        Resolution::new(config.width, config.height),
-        FrameFormat::MJPEG,
-        config.fps,
-    );
-    let requested =
-        RequestedFormat::new::<RgbFormat>(RequestedFormatType::Closest(format));
-
-    let mut camera =
-        Camera::new(index, requested).map_err(|e| format!("Camera open: {e}"))?;
-    camera
-        .open_stream()
-        .map_err(|e| format!("Camera stream: {e}"))?;
-
-    let actual = camera.camera_format();
-    let w = actual.resolution().width();
-    let h = actual.resolution().height();
-    tracing::info!("Camera started: {}x{} @ {}fps", w, h, actual.frame_rate());
+    let mut camera = webcam::Webcam::open(config.device, config.width, config.height, config.fps)?;
+
+    let (w, h, fps) = camera.format();
+    tracing::info!("Camera started: {}x{} @ {}fps", w, h, fps);
+    if (w, h) != (config.width, config.height) {
+        tracing::info!(
+            "Camera resolution differs from requested {}x{}, frames will be {:?} to fit",
+            config.width,
+            config.height,
+            config.scale_mode
+        );
+    }
+
+    // Cameras may deliver more frames than requested; keep at most config.fps
+    let interval = Duration::from_secs_f64(1.0 / f64::from(config.fps.max(1)));
+    let tolerance = interval.mul_f64(FRAME_EARLY_TOLERANCE);
+    let mut next_due = Instant::now();
+    let mut last_hash: Option<u64> = None;
+    let (mut rate_limited, mut duplicates) = (0u64, 0u64);
 
     while !stop.load(Ordering::Relaxed) {
         let frame = match camera.frame() {
@@ -107,7 +292,24 @@ fn camera_thread(
             }
         };
 
-        let decoded = match frame.decode_image::<RgbFormat>() {
+        let now = Instant::now();
+        if now + tolerance < next_due {
+            rate_limited += 1;
+            continue;
+        }
+
+        // Stuck webcams repeat the exact same compressed frame
+        let mut hasher = DefaultHasher::new();
+        hasher.write(frame.bytes());
+        let hash = hasher.finish();
+        if last_hash == Some(hash) {
+            duplicates += 1;
+            continue;
+        }
+        last_hash = Some(hash);
+        next_due = (next_due + interval).max(now);
+
+        let (decoded, dw, dh) = match frame.decode_rgb() {
             Ok(img) => img,
             Err(e) => {
                 tracing::warn!("Frame decode error: {e}");
@@ -115,13 +317,29 @@ fn camera_thread(
             }
         };
 
-        let rgb = decoded.as_raw();
-        let (y, u, v) = rgb_to_i420(rgb, w as usize, h as usize);
-        let rgba = rgb_to_rgba(rgb);
+        let (rgb, rw, rh) = rotate_rgb(&decoded, dw, dh, config.rotation);
+        // Cameras often ignore the requested resolution; the encoder cannot
+        let (fw, fh) = config.output_size();
+        let mut rgb = scale_rgb(&rgb, rw, rh, fw, fh, config.scale_mode);
+        if let Ok(mut stage) = effects.background.lock() {
+            if let Some(stage) = stage.as_mut() {
+                stage.apply(rgb.to_mut(), fw, fh);
+            }
+        }
+        if let Ok(overlay) = effects.overlay.lock() {
+            if let Some(overlay) = overlay.as_ref() {
+                overlay.apply(rgb.to_mut(), fw, fh);
+            }
+        }
+        let (y, u, v) = rgb_to_i420(&rgb, fw, fh);
+        let mut rgba = rgb_to_rgba(&rgb);
+        if config.mirror_preview {
+            mirror_rgba(&mut rgba, fw);
+        }
 
         let captured = CapturedFrame {
-            width: w,
-            height: h,
+            width: fw as u32,
+            height: fh as u32,
             y,
             u,
             v,
@@ -134,13 +352,189 @@ fn camera_thread(
         }
     }
 
-    let _ = camera.stop_stream();
-    tracing::info!("Camera stopped");
+    drop(camera);
+    tracing::info!("Camera stopped ({rate_limited} frames over the fps limit, {duplicates} duplicates skipped)");
     Ok(())
 }
 
+/// Rotate an RGB888 image clockwise. Returns the pixels and the new
+/// width and height; `Rotation::None` borrows the input.
+pub fn rotate_rgb(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    rotation: Rotation,
+) -> (Cow<'_, [u8]>, usize, usize) {
+    if rotation == Rotation::None {
+        return (Cow::Borrowed(rgb), width, height);
+    }
+    let (out_w, out_h) = if rotation.swaps_dimensions() { (height, width) } else { (width, height) };
+    let mut out = vec![0u8; rgb.len()];
+    for row in 0..height {
+        for col in 0..width {
+            let (r, c) = match rotation {
+                Rotation::Cw90 => (col, height - 1 - row),
+                Rotation::Cw180 => (height - 1 - row, width - 1 - col),
+                Rotation::Cw270 => (width - 1 - col, row),
+                Rotation::None => unreachable!(),
+            };
+            let src = (row * width + col) * 3;
+            let dst = (r * out_w + c) * 3;
+            out[dst..dst + 3].copy_from_slice(&rgb[src..src + 3]);
+        }
+    }
+    (Cow::Owned(out), out_w, out_h)
+}
+
+/// The `(x, y, width, height)` of the source read when scaling to
+/// `out_w`x`out_h`: the whole frame, or its centered crop to the target
+/// aspect ratio.
+pub fn source_rect(width: usize, height: usize, out_w: usize, out_h: usize, mode: ScaleMode) -> (f32, f32, f32, f32) {
+    let (w, h) = (width as f32, height as f32);
+    if mode == ScaleMode::Stretch {
+        return (0.0, 0.0, w, h);
+    }
+    let target = out_w as f32 / out_h as f32;
+    if w / h > target {
+        ((w - h * target) / 2.0, 0.0, h * target, h)
+    } else {
+        (0.0, (h - w / target) / 2.0, w, w / target)
+    }
+}
+
+/// Resize an RGB888 image to `out_w`x`out_h` with bilinear filtering,
+/// center-cropping first in `ScaleMode::Crop`. Borrows the input if the
+/// size already matches. Runs on the GPU when available.
+pub fn scale_rgb(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    out_w: usize,
+    out_h: usize,
+    mode: ScaleMode,
+) -> Cow<'_, [u8]> {
+    if (width, height) == (out_w, out_h) || width == 0 || height == 0 {
+        return Cow::Borrowed(rgb);
+    }
+    if let Some(scaled) = gpu::scale_rgb(rgb, width, height, out_w, out_h, mode) {
+        return Cow::Owned(scaled);
+    }
+    let (x0, y0, src_w, src_h) = source_rect(width, height, out_w, out_h, mode);
+    let (sx, sy) = (src_w / out_w as f32, src_h / out_h as f32);
+
+    let mut out = vec![0u8; out_w * out_h * 3];
+    for row in 0..out_h {
+        let fy = (y0 + (row as f32 + 0.5) * sy - 0.5).clamp(0.0, (height - 1) as f32);
+        let (top, ty) = (fy as usize, fy.fract());
+        let bottom = (top + 1).min(height - 1);
+        for col in 0..out_w {
+            let fx = (x0 + (col as f32 + 0.5) * sx - 0.5).clamp(0.0, (width - 1) as f32);
+            let (left, tx) = (fx as usize, fx.fract());
+            let right = (left + 1).min(width - 1);
+            for c in 0..3 {
+                let px = |r: usize, col: usize| rgb[(r * width + col) * 3 + c] as f32;
+                let upper = px(top, left) * (1.0 - tx) + px(top, right) * tx;
+                let lower = px(bottom, left) * (1.0 - tx) + px(bottom, right) * tx;
+                out[(row * out_w + col) * 3 + c] = (upper * (1.0 - ty) + lower * ty).round() as u8;
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Corner (or center) of the frame an overlay is positioned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl OverlayAnchor {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "top-left" => Some(OverlayAnchor::TopLeft),
+            "top-right" => Some(OverlayAnchor::TopRight),
+            "bottom-left" => Some(OverlayAnchor::BottomLeft),
+            "bottom-right" => Some(OverlayAnchor::BottomRight),
+            "center" => Some(OverlayAnchor::Center),
+            _ => None,
+        }
+    }
+}
+
+/// An RGBA image composited onto outgoing frames (logo, name banner,
+/// recording indicator).
+pub struct Overlay {
+    pub rgba: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub anchor: OverlayAnchor,
+    /// Pixels inward from the anchor corner (a plain shift for `Center`).
+    pub offset: (i32, i32),
+    /// Multiplies the image's own alpha, 0.0–1.0.
+    pub opacity: f32,
+}
+
+/// Overlay shared between the session and the camera thread.
+pub(crate) type SharedOverlay = Arc<Mutex<Option<Overlay>>>;
+
+impl Overlay {
+    /// Alpha-blend onto an RGB888 frame in place, clipping at the edges.
+    pub fn apply(&self, rgb: &mut [u8], width: usize, height: usize) {
+        let (fw, fh) = (width as i64, height as i64);
+        let (ow, oh) = (self.width as i64, self.height as i64);
+        let (dx, dy) = (i64::from(self.offset.0), i64::from(self.offset.1));
+        let (left, top) = match self.anchor {
+            OverlayAnchor::TopLeft => (dx, dy),
+            OverlayAnchor::TopRight => (fw - ow - dx, dy),
+            OverlayAnchor::BottomLeft => (dx, fh - oh - dy),
+            OverlayAnchor::BottomRight => (fw - ow - dx, fh - oh - dy),
+            OverlayAnchor::Center => ((fw - ow) / 2 + dx, (fh - oh) / 2 + dy),
+        };
+        for oy in 0..oh {
+            let y = top + oy;
+            if !(0..fh).contains(&y) {
+                continue;
+            }
+            for ox in 0..ow {
+                let x = left + ox;
+                if !(0..fw).contains(&x) {
+                    continue;
+                }
+                let src = ((oy * ow + ox) * 4) as usize;
+                let alpha = f32::from(self.rgba[src + 3]) / 255.0 * self.opacity;
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let dst = ((y * fw + x) * 3) as usize;
+                for (d, s) in rgb[dst..dst + 3].iter_mut().zip(&self.rgba[src..src + 3]) {
+                    *d = (f32::from(*d) * (1.0 - alpha) + f32::from(*s) * alpha).round() as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Flip an RGBA8888 image horizontally in place.
+pub fn mirror_rgba(rgba: &mut [u8], width: usize) {
+    for row in rgba.chunks_exact_mut(width * 4) {
+        for col in 0..width / 2 {
+            let (a, b) = (col * 4, (width - 1 - col) * 4);
+            for i in 0..4 {
+                row.swap(a + i, b + i);
+            }
+        }
+    }
+}
+
 /// Convert RGB888 to I420 (YUV 4:2:0) planes.
 pub fn rgb_to_i420(rgb: &[u8], width: usize, height: usize) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    if let Some(planes) = gpu::rgb_to_i420(rgb, width, height) {
+        return planes;
+    }
     let mut y = vec![0u8; width * height];
     let cw = width / 2;
     let ch = height / 2;
@@ -182,3 +576,51 @@ pub fn rgb_to_rgba(rgb: &[u8]) -> Vec<u8> {
     }
     rgba
 }
+
+/// Convert RGBA8888 to RGB888, dropping alpha.
+pub fn rgba_to_rgb(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect()
+}
+
+/// Still-image formats for `capture_snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Png,
+    Jpeg,
+}
+
+impl SnapshotFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Some(SnapshotFormat::Png),
+            "jpeg" | "jpg" => Some(SnapshotFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+/// Encode an RGBA8888 frame as PNG or JPEG (`quality` 1–100, JPEG only;
+/// alpha is dropped).
+pub fn encode_snapshot(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: SnapshotFormat,
+    quality: u8,
+) -> Result<Vec<u8>, String> {
+    use image::ImageEncoder;
+
+    let mut out = Vec::new();
+    match format {
+        SnapshotFormat::Png => image::codecs::png::PngEncoder::new(&mut out)
+            .write_image(rgba, width, height, image::ExtendedColorType::Rgba8)
+            .map_err(|e| format!("PNG encode: {e}"))?,
+        SnapshotFormat::Jpeg => {
+            let rgb = rgba_to_rgb(rgba);
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality)
+                .write_image(&rgb, width, height, image::ExtendedColorType::Rgb8)
+                .map_err(|e| format!("JPEG encode: {e}"))?
+        }
+    }
+    Ok(out)
+}
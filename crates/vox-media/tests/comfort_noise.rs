@@ -0,0 +1,50 @@
+//! Comfort noise: playback gaps are filled with noise at about -60 dBFS,
+//! while buffered audio plays untouched.
+
+use std::collections::VecDeque;
+use vox_media::{fill_playback, ComfortNoise, COMFORT_NOISE_LEVEL};
+
+#[test]
+fn noise_stays_at_the_comfort_level() {
+    let mut noise = ComfortNoise::default();
+    let samples: Vec<f32> = (0..48_000).map(|_| noise.next_sample()).collect();
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    assert!(peak <= COMFORT_NOISE_LEVEL);
+    assert!(peak > COMFORT_NOISE_LEVEL * 0.99, "peak {peak}");
+    // Uniform noise has an RMS of its peak over sqrt(3)
+    let expected_rms = COMFORT_NOISE_LEVEL / 3f32.sqrt();
+    assert!((rms - expected_rms).abs() < expected_rms * 0.05, "rms {rms}");
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    assert!(mean.abs() < COMFORT_NOISE_LEVEL * 0.05, "mean {mean}");
+}
+
+#[test]
+fn buffered_audio_is_not_mixed_with_noise() {
+    let mut noise = ComfortNoise::default();
+    let mut buffered: VecDeque<f32> = [0.5, -0.5, 0.25, 0.0, 0.125].into();
+    let mut out = [1.0f32; 3];
+    fill_playback(&mut out, &mut buffered, Some(&mut noise));
+    assert_eq!(out, [0.5, -0.5, 0.25]);
+    assert_eq!(buffered, [0.0, 0.125]);
+}
+
+#[test]
+fn only_the_gap_is_filled() {
+    let mut noise = ComfortNoise::default();
+    let mut buffered: VecDeque<f32> = [0.5, -0.5].into();
+    let mut out = [1.0f32; 480];
+    fill_playback(&mut out, &mut buffered, Some(&mut noise));
+    assert_eq!(out[..2], [0.5, -0.5]);
+    assert!(out[2..].iter().all(|s| s.abs() <= COMFORT_NOISE_LEVEL));
+    assert!(out[2..].iter().any(|&s| s != 0.0));
+    assert!(buffered.is_empty());
+}
+
+#[test]
+fn gaps_are_silent_without_noise() {
+    let mut buffered: VecDeque<f32> = [0.5].into();
+    let mut out = [1.0f32; 4];
+    fill_playback(&mut out, &mut buffered, None);
+    assert_eq!(out, [0.5, 0.0, 0.0, 0.0]);
+}
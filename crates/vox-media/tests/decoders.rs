@@ -0,0 +1,43 @@
+//! Releasing per-user decoders that have been idle for the configured
+//! timeout.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use vox_media::evict_idle;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn decoders(now: Instant, idle: &[(u32, u64)]) -> HashMap<u32, Instant> {
+    idle.iter().map(|&(user_id, secs)| (user_id, now - Duration::from_secs(secs))).collect()
+}
+
+#[test]
+fn idle_decoders_are_released() {
+    let now = Instant::now() + Duration::from_secs(60);
+    let mut entries = decoders(now, &[(1, 0), (2, 29), (3, 30), (4, 45)]);
+    let mut evicted = evict_idle(&mut entries, now, TIMEOUT, |last_used| *last_used);
+    evicted.sort_unstable();
+    assert_eq!(evicted, [3, 4]);
+    let mut kept: Vec<u32> = entries.into_keys().collect();
+    kept.sort_unstable();
+    assert_eq!(kept, [1, 2]);
+}
+
+#[test]
+fn a_shorter_timeout_releases_sooner() {
+    let now = Instant::now() + Duration::from_secs(60);
+    let mut entries = decoders(now, &[(1, 5), (2, 10)]);
+    assert!(evict_idle(&mut entries, now, TIMEOUT, |last_used| *last_used).is_empty());
+    assert_eq!(evict_idle(&mut entries, now, Duration::from_secs(10), |last_used| *last_used), [2]);
+    assert_eq!(entries.len(), 1);
+}
+
+#[test]
+fn recently_used_decoders_are_kept() {
+    let now = Instant::now();
+    let mut entries = decoders(now, &[(1, 0)]);
+    // Used after `now`, as when a frame arrives mid-sweep
+    entries.insert(2, now + Duration::from_millis(5));
+    assert!(evict_idle(&mut entries, now, TIMEOUT, |last_used| *last_used).is_empty());
+    assert_eq!(entries.len(), 2);
+}
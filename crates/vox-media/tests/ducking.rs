@@ -0,0 +1,85 @@
+//! Voice ducking: remote playback drops to the configured level while the
+//! local user speaks and ramps back over the release time.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use vox_media::{Ducker, DuckingConfig};
+
+const RELEASE: Duration = Duration::from_millis(400);
+
+fn ducker(sources: Option<HashSet<u32>>) -> Ducker {
+    let mut ducker = Ducker::default();
+    ducker.set_config(Some(DuckingConfig { level: 0.2, release: RELEASE, sources }));
+    ducker
+}
+
+fn assert_gain(ducker: &Ducker, user_id: u32, now: Instant, expected: f32) {
+    let gain = ducker.gain_at(user_id, now);
+    assert!((gain - expected).abs() < 1e-4, "gain {gain}, expected {expected}");
+}
+
+#[test]
+fn speech_ducks_playback_immediately() {
+    let now = Instant::now();
+    let mut ducker = ducker(None);
+    assert_gain(&ducker, 1, now, 1.0);
+    ducker.set_local_voice_at(true, now);
+    assert_gain(&ducker, 1, now, 0.2);
+    assert_gain(&ducker, 2, now + Duration::from_secs(10), 0.2);
+}
+
+#[test]
+fn release_ramps_back_linearly() {
+    let start = Instant::now();
+    let mut ducker = ducker(None);
+    ducker.set_local_voice_at(true, start);
+    let stopped = start + Duration::from_millis(100);
+    ducker.set_local_voice_at(false, stopped);
+    // Silent frames after the first do not restart the ramp
+    ducker.set_local_voice_at(false, stopped + Duration::from_millis(100));
+
+    assert_gain(&ducker, 1, stopped, 0.2);
+    assert_gain(&ducker, 1, stopped + RELEASE / 4, 0.4);
+    assert_gain(&ducker, 1, stopped + RELEASE / 2, 0.6);
+    assert_gain(&ducker, 1, stopped + RELEASE, 1.0);
+    assert_gain(&ducker, 1, stopped + RELEASE * 3, 1.0);
+}
+
+#[test]
+fn speaking_again_during_release_ducks_fully() {
+    let start = Instant::now();
+    let mut ducker = ducker(None);
+    ducker.set_local_voice_at(true, start);
+    ducker.set_local_voice_at(false, start);
+    ducker.set_local_voice_at(true, start + RELEASE / 2);
+    assert_gain(&ducker, 1, start + RELEASE * 2, 0.2);
+}
+
+#[test]
+fn only_listed_sources_are_ducked() {
+    let now = Instant::now();
+    let mut ducker = ducker(Some(HashSet::from([7])));
+    ducker.set_local_voice_at(true, now);
+    assert_gain(&ducker, 7, now, 0.2);
+    assert_gain(&ducker, 8, now, 1.0);
+}
+
+#[test]
+fn disabling_stops_ducking() {
+    let now = Instant::now();
+    let mut ducker = ducker(None);
+    ducker.set_local_voice_at(true, now);
+    ducker.set_config(None);
+    assert_gain(&ducker, 1, now, 1.0);
+}
+
+#[test]
+fn zero_release_restores_at_once() {
+    let now = Instant::now();
+    let mut ducker = Ducker::default();
+    ducker.set_config(Some(DuckingConfig { level: 0.0, release: Duration::ZERO, sources: None }));
+    ducker.set_local_voice_at(true, now);
+    assert_gain(&ducker, 1, now, 0.0);
+    ducker.set_local_voice_at(false, now);
+    assert_gain(&ducker, 1, now, 1.0);
+}
@@ -0,0 +1,116 @@
+//! When an `Auto` link gives up on datagrams, and how the stream queue
+//! sheds media while the stream is stalled.
+
+use bytes::Bytes;
+use vox_media::{LossDetector, LossSample, StreamQueue};
+
+/// Media packets the stream queue holds before dropping the oldest.
+const MAX_QUEUED_MEDIA: usize = 256;
+
+/// One interval in which `dropped` of 100 datagrams never arrived.
+fn interval(dropped: u64) -> LossSample {
+    LossSample {
+        queued: 100,
+        transmitted: 100 - dropped,
+        sent_packets: 100,
+        lost_packets: 0,
+    }
+}
+
+#[test]
+fn dropped_counts_unsent_and_lost_datagrams() {
+    assert_eq!(interval(0).dropped(), 0.0);
+    assert!((interval(30).dropped() - 0.3).abs() < 1e-9);
+    let half_lost = LossSample { lost_packets: 50, ..interval(0) };
+    assert!((half_lost.dropped() - 0.5).abs() < 1e-9);
+    let both = LossSample { lost_packets: 50, ..interval(50) };
+    assert!((both.dropped() - 0.75).abs() < 1e-9);
+}
+
+#[test]
+fn falls_back_after_three_heavy_intervals() {
+    let mut detector = LossDetector::default();
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&interval(50)));
+    assert!(detector.record(&interval(90)));
+}
+
+#[test]
+fn light_loss_is_tolerated() {
+    let mut detector = LossDetector::default();
+    for _ in 0..10 {
+        assert!(!detector.record(&interval(29)));
+    }
+}
+
+#[test]
+fn a_light_interval_restarts_the_count() {
+    let mut detector = LossDetector::default();
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&interval(10)));
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&interval(40)));
+    assert!(detector.record(&interval(40)));
+}
+
+#[test]
+fn small_intervals_neither_count_nor_restart() {
+    let mut detector = LossDetector::default();
+    let quiet = LossSample { queued: 24, transmitted: 0, sent_packets: 24, lost_packets: 0 };
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&quiet));
+    assert!(!detector.record(&interval(40)));
+    assert!(!detector.record(&quiet));
+    assert!(detector.record(&interval(40)));
+}
+
+fn packet(n: usize) -> Bytes {
+    Bytes::from(n.to_be_bytes().to_vec())
+}
+
+async fn drain(queue: &StreamQueue) -> Vec<Bytes> {
+    queue.close();
+    let mut packets = Vec::new();
+    while let Some(packet) = queue.pop().await {
+        packets.push(packet);
+    }
+    packets
+}
+
+#[tokio::test]
+async fn stalled_queue_drops_the_oldest_media() {
+    let queue = StreamQueue::default();
+    for n in 0..MAX_QUEUED_MEDIA + 10 {
+        queue.push(packet(n), false).unwrap();
+    }
+    assert_eq!(queue.dropped(), 10);
+    let expected: Vec<Bytes> = (10..MAX_QUEUED_MEDIA + 10).map(packet).collect();
+    assert_eq!(drain(&queue).await, expected);
+}
+
+#[tokio::test]
+async fn reliable_packets_are_never_dropped() {
+    let queue = StreamQueue::default();
+    let control = Bytes::from_static(b"control");
+    queue.push(control.clone(), true).unwrap();
+    for n in 0..MAX_QUEUED_MEDIA + 1 {
+        queue.push(packet(n), false).unwrap();
+    }
+    queue.push(control.clone(), true).unwrap();
+    assert_eq!(queue.dropped(), 1);
+
+    let packets = drain(&queue).await;
+    assert_eq!(packets.len(), MAX_QUEUED_MEDIA + 2);
+    assert_eq!(packets[0], control);
+    assert_eq!(packets[1], packet(1));
+    assert_eq!(packets[MAX_QUEUED_MEDIA + 1], control);
+}
+
+#[tokio::test]
+async fn closed_queue_rejects_packets() {
+    let queue = StreamQueue::default();
+    queue.push(packet(0), false).unwrap();
+    assert_eq!(drain(&queue).await, [packet(0)]);
+    assert!(queue.push(packet(1), false).is_err());
+}
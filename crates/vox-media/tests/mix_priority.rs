@@ -0,0 +1,80 @@
+//! Mix priorities: while a higher-priority source plays speech, lower ones
+//! are attenuated by their configured amount.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use vox_media::MixSource;
+
+/// Sources share one process-wide registry, so tests must not overlap.
+static REGISTRY: Mutex<()> = Mutex::new(());
+
+fn source(priority: i32, attenuation: f32) -> MixSource {
+    let mut source = MixSource::new();
+    source.configure(priority, attenuation);
+    source
+}
+
+#[test]
+fn active_higher_priority_attenuates_lower() {
+    let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let primary = source(10, 0.5);
+    let secondary = source(0, 0.25);
+
+    assert_eq!(secondary.gain_at(now), 1.0);
+    primary.mark_active_at(now);
+    assert_eq!(secondary.gain_at(now), 0.25);
+    assert_eq!(primary.gain_at(now), 1.0);
+    // A lower source speaking never attenuates a higher one
+    secondary.mark_active_at(now);
+    assert_eq!(primary.gain_at(now), 1.0);
+}
+
+#[test]
+fn attenuation_ends_after_the_activity_window() {
+    let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let primary = source(1, 1.0);
+    let secondary = source(0, 0.3);
+    primary.mark_active_at(now);
+    assert_eq!(secondary.gain_at(now + Duration::from_millis(499)), 0.3);
+    assert_eq!(secondary.gain_at(now + Duration::from_millis(500)), 1.0);
+}
+
+#[test]
+fn equal_priorities_do_not_attenuate() {
+    let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let first = source(3, 0.2);
+    let second = source(3, 0.2);
+    first.mark_active_at(now);
+    second.mark_active_at(now);
+    assert_eq!(first.gain_at(now), 1.0);
+    assert_eq!(second.gain_at(now), 1.0);
+}
+
+#[test]
+fn dropped_sources_stop_attenuating() {
+    let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let primary = source(5, 1.0);
+    let secondary = source(0, 0.4);
+    primary.mark_active_at(now);
+    assert_eq!(secondary.gain_at(now), 0.4);
+    drop(primary);
+    assert_eq!(secondary.gain_at(now), 1.0);
+}
+
+#[test]
+fn reconfiguring_changes_the_ranking() {
+    let _registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let mut first = source(1, 0.5);
+    let second = source(0, 0.5);
+    first.mark_active_at(now);
+    second.mark_active_at(now);
+    assert_eq!(second.gain_at(now), 0.5);
+    first.configure(-1, 0.5);
+    assert_eq!(second.gain_at(now), 1.0);
+    assert_eq!(first.gain_at(now), 0.5);
+}
@@ -0,0 +1,72 @@
+//! A `MediaClient` against the in-process SFU. Run without `audio-devices`
+//! so sessions connect on machines with no sound card.
+
+use std::time::{Duration, Instant};
+use vox_media::test_sfu::{MockSession, MockSfu, MockSfuConfig};
+use vox_media::{MediaClient, MediaEvent, MediaSettings};
+
+const ROOM: u32 = 7;
+
+fn wait_for<T>(what: &str, mut poll: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(15);
+    loop {
+        if let Some(value) = poll() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn wait_for_event(client: &MediaClient, what: &str, matches: impl Fn(&MediaEvent) -> bool) -> MediaEvent {
+    wait_for(what, || std::iter::from_fn(|| client.poll_event()).find(|e| matches(e)))
+}
+
+fn joined(sfu: &MockSfu, user_id: u32) -> MediaClient {
+    let mut client = MediaClient::new();
+    client.start(MediaSettings::default()).unwrap();
+    client.connect(sfu.connect_options("token", ROOM, user_id)).unwrap();
+    wait_for_event(&client, "connected", |e| matches!(e, MediaEvent::Connected));
+    client
+}
+
+#[test]
+fn data_is_forwarded_and_clients_reconnect() {
+    let sfu = MockSfu::start(MockSfuConfig::default()).unwrap();
+    let mut alice = joined(&sfu, 1);
+    let mut bob = joined(&sfu, 2);
+    assert_eq!(
+        sfu.sessions(),
+        [MockSession { room_id: ROOM, user_id: 1 }, MockSession { room_id: ROOM, user_id: 2 }]
+    );
+
+    alice.send_data(3, b"hello".to_vec(), true).unwrap();
+    let message = wait_for("data", || bob.poll_data());
+    assert_eq!((message.user_id, message.channel_id, message.data.as_slice()), (1, 3, &b"hello"[..]));
+
+    sfu.drop_connections();
+    wait_for_event(&alice, "reconnecting", |e| matches!(e, MediaEvent::Reconnecting { .. }));
+    wait_for_event(&alice, "reconnected", |e| matches!(e, MediaEvent::Connected));
+    wait_for_event(&bob, "reconnected", |e| matches!(e, MediaEvent::Connected));
+    assert_eq!(sfu.handshakes(), 4);
+
+    bob.send_data(3, b"back".to_vec(), true).unwrap();
+    let message = wait_for("data after reconnect", || alice.poll_data());
+    assert_eq!((message.user_id, message.data.as_slice()), (2, &b"back"[..]));
+
+    alice.stop();
+    bob.stop();
+    wait_for("leaves", || sfu.sessions().is_empty().then_some(()));
+}
+
+#[test]
+fn rejected_handshakes_are_reported() {
+    let sfu = MockSfu::start(MockSfuConfig { tokens: Some(vec!["good".into()]), ..Default::default() }).unwrap();
+    let mut client = MediaClient::new();
+    client.start(MediaSettings::default()).unwrap();
+    client.connect(sfu.connect_options("bad", ROOM, 1)).unwrap();
+    let event = wait_for_event(&client, "auth_rejected", |e| matches!(e, MediaEvent::AuthRejected(_)));
+    assert!(matches!(event, MediaEvent::AuthRejected(reason) if reason == "invalid token"));
+    assert!(sfu.sessions().is_empty());
+    client.stop();
+}
@@ -0,0 +1,34 @@
+//! How an uplink cap splits the budget between video and audio: 32 kbit/s
+//! for audio, video lowered to the rest down to 100 kbit/s, then paused,
+//! then audio lowered to 6 kbit/s and gated on voice activity.
+
+use vox_media::UplinkPlan;
+
+fn plan(video_kbps: Option<u32>, audio_kbps: Option<u32>, vad: bool) -> UplinkPlan {
+    UplinkPlan { video_kbps, audio_kbps, vad }
+}
+
+#[test]
+fn no_cap_keeps_the_configured_video_bitrate() {
+    assert_eq!(UplinkPlan::new(None, 500), plan(Some(500), None, false));
+}
+
+#[test]
+fn cap_lowers_video_before_audio() {
+    assert_eq!(UplinkPlan::new(Some(332), 500), plan(Some(300), Some(32), false));
+    assert_eq!(UplinkPlan::new(Some(2000), 500), plan(Some(500), Some(32), false));
+}
+
+#[test]
+fn cap_pauses_video_below_its_minimum() {
+    assert_eq!(UplinkPlan::new(Some(132), 500), plan(Some(100), Some(32), false));
+    assert_eq!(UplinkPlan::new(Some(131), 500), plan(None, Some(32), false));
+    assert_eq!(UplinkPlan::new(Some(32), 500), plan(None, Some(32), false));
+}
+
+#[test]
+fn cap_below_the_audio_budget_gates_audio_on_voice() {
+    assert_eq!(UplinkPlan::new(Some(31), 500), plan(None, Some(31), true));
+    assert_eq!(UplinkPlan::new(Some(6), 500), plan(None, Some(6), true));
+    assert_eq!(UplinkPlan::new(Some(1), 500), plan(None, Some(6), true));
+}
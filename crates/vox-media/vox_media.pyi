@@ -0,0 +1,295 @@
+# Type stubs for the vox_media extension module.
+#
+# Packaged by maturin alongside the compiled module. Keep in sync with
+# src/lib.rs; tests/test_stubs.py checks them against the built module.
+
+from collections.abc import Awaitable, Callable, Sequence
+from os import PathLike
+from typing import Literal, Protocol, TypedDict, final
+
+__version__: str
+__features__: list[str]
+
+_EventName = Literal[
+    "connected",
+    "disconnected",
+    "connect_failed",
+    "reconnecting",
+    "audio_error",
+    "video_error",
+    "transport_changed",
+    "auth_accepted",
+    "auth_rejected",
+    "data_error",
+    "speaking_start",
+    "speaking_stop",
+    "fatal_error",
+    "user_active",
+    "user_inactive",
+    "command_result",
+    "audio_warning",
+    "device_fallback",
+    "audio_config",
+    "malformed_packets",
+    "rate_limited",
+]
+
+_Transport = Literal["auto", "datagram", "stream"]
+_PortSpec = int | tuple[int, int]
+_DscpSpec = int | str
+_ClientCert = bytes | Sequence[bytes]
+_Rgba = tuple[int, int, bytes]
+
+class _ProfileStats(TypedDict):
+    count: int
+    total_ms: float
+    mean_us: float
+    p50_us: float
+    p90_us: float
+    p99_us: float
+    max_us: float
+
+class _UserStats(TypedDict):
+    bitrate_kbps: float
+    loss_percent: float
+    jitter_ms: float
+    ms_since_last_packet: int
+    concealed_frames: int
+    packets_received: int
+    packets_lost: int
+    packets_reordered: int
+    packets_duplicated: int
+
+class _AudioStats(TypedDict):
+    underruns: int
+    overflow_samples: int
+    capture_overruns: int
+
+class _InputDeviceReport(TypedDict):
+    device: str
+    sample_rate: int
+    channels: int
+    needs_resample: bool
+    peak: float
+    rms: float
+
+class _FrameTransform(Protocol):
+    def on_send(self, media_type: int, user_id: int, timestamp: int, payload: bytes, /) -> bytes | None: ...
+    def on_receive(self, media_type: int, user_id: int, timestamp: int, payload: bytes, /) -> bytes | None: ...
+
+class _MlsEngine(Protocol):
+    def attach_media_key_sink(self, group_id: str, sink: object, /) -> None: ...
+
+@final
+class TransportConfig:
+    def __new__(
+        cls,
+        congestion_controller: Literal["bbr", "cubic", "newreno"] = "cubic",
+        keep_alive_interval_ms: int | None = None,
+        send_window: int | None = None,
+        receive_window: int | None = None,
+        max_udp_payload_size: int | None = None,
+    ) -> TransportConfig: ...
+
+@final
+class DatagramValidation:
+    def __new__(
+        cls,
+        report_interval_secs: float = 5.0,
+        disconnect_threshold: int | None = None,
+    ) -> DatagramValidation: ...
+
+@final
+class RateLimits:
+    def __new__(
+        cls,
+        user_packets_per_sec: int | None = 2000,
+        user_bytes_per_sec: int | None = 2_000_000,
+        total_packets_per_sec: int | None = 20_000,
+        total_bytes_per_sec: int | None = 16_000_000,
+        max_reassembly_per_user: int = 32,
+        max_reassembly_total: int = 256,
+        report_interval_secs: float = 5.0,
+    ) -> RateLimits: ...
+
+@final
+class MockSfu:
+    """Only in builds with the ``test-sfu`` feature."""
+
+    def __new__(
+        cls,
+        echo_user_id: int | None = None,
+        reject: str | None = None,
+        tokens: list[str] | None = None,
+    ) -> MockSfu: ...
+    @property
+    def url(self) -> str: ...
+    @property
+    def cert_der(self) -> bytes: ...
+    @property
+    def handshakes(self) -> int: ...
+    @property
+    def frames_received(self) -> int: ...
+    def sessions(self) -> list[tuple[int, int]]: ...
+    def drop_connections(self) -> None: ...
+    def close(self) -> None: ...
+    def __enter__(self) -> MockSfu: ...
+    def __exit__(self, *args: object) -> None: ...
+
+@final
+class VoxMediaClient:
+    def __new__(cls) -> VoxMediaClient: ...
+    def start(self, settings_path: str | PathLike[str] | None = None) -> None: ...
+    def connect(
+        self,
+        url: str,
+        token: str,
+        room_id: int,
+        user_id: int,
+        cert_der: bytes | None = None,
+        idle_timeout_secs: int = 30,
+        datagram_buffer_size: int = 65535,
+        input_device: str | None = None,
+        output_device: str | None = None,
+        transport: _Transport = "auto",
+        bind_address: str | None = None,
+        bind_port: _PortSpec | None = None,
+        bind_interface: str | None = None,
+        proxy: str | None = None,
+        cert_pins: Sequence[bytes] | None = None,
+        spki_pins: Sequence[bytes] | None = None,
+        client_cert: _ClientCert | None = None,
+        client_key: bytes | None = None,
+        transport_config: TransportConfig | None = None,
+        dscp: _DscpSpec | None = None,
+        validation: DatagramValidation | None = None,
+        rate_limits: RateLimits | None = None,
+    ) -> int: ...
+    def connect_and_wait(
+        self,
+        url: str,
+        token: str,
+        room_id: int,
+        user_id: int,
+        cert_der: bytes | None = None,
+        idle_timeout_secs: int = 30,
+        datagram_buffer_size: int = 65535,
+        input_device: str | None = None,
+        output_device: str | None = None,
+        transport: _Transport = "auto",
+        bind_address: str | None = None,
+        bind_port: _PortSpec | None = None,
+        bind_interface: str | None = None,
+        proxy: str | None = None,
+        cert_pins: Sequence[bytes] | None = None,
+        spki_pins: Sequence[bytes] | None = None,
+        client_cert: _ClientCert | None = None,
+        client_key: bytes | None = None,
+        transport_config: TransportConfig | None = None,
+        dscp: _DscpSpec | None = None,
+        validation: DatagramValidation | None = None,
+        rate_limits: RateLimits | None = None,
+        timeout: float = 10.0,
+    ) -> None: ...
+    def connect_async(self, *args: object, **kwargs: object) -> Awaitable[None]: ...
+    def disconnect(self) -> None: ...
+    def set_mute(self, muted: bool) -> None: ...
+    def set_deaf(self, deafened: bool) -> None: ...
+    def set_video(self, enabled: bool) -> int: ...
+    def set_video_config(
+        self,
+        width: int = 640,
+        height: int = 480,
+        fps: int = 30,
+        bitrate_kbps: int = 500,
+        rotation: Literal[0, 90, 180, 270] = 0,
+        mirror_preview: bool = False,
+        scale_mode: Literal["crop", "stretch"] = "crop",
+    ) -> None: ...
+    def set_background_effect(
+        self,
+        effect: Literal["blur", "image"] | None,
+        model_path: str | PathLike[str] | None = None,
+        blur_radius: int = 12,
+        image: _Rgba | None = None,
+    ) -> None: ...
+    def set_video_overlay(
+        self,
+        image: _Rgba | None,
+        anchor: Literal["top-left", "top-right", "bottom-left", "bottom-right", "center"] = "top-left",
+        offset: tuple[int, int] = (0, 0),
+        opacity: float = 1.0,
+    ) -> None: ...
+    def set_receive_preferences(
+        self,
+        max_video_streams: int | None = None,
+        max_resolution: tuple[int, int] | None = None,
+        audio_only: bool = False,
+    ) -> None: ...
+    def set_max_uplink_kbps(self, kbps: int | None = None) -> None: ...
+    def set_input_volume(self, volume: float) -> None: ...
+    def set_output_volume(self, volume: float) -> None: ...
+    def set_noise_gate(self, threshold: float) -> None: ...
+    def set_decoder_idle_timeout(self, seconds: float) -> None: ...
+    def drop_user(self, user_id: int) -> None: ...
+    def set_user_volume(self, user_id: int, volume: float) -> None: ...
+    def set_voice_ducking(
+        self,
+        enabled: bool,
+        level: float = 0.3,
+        release_ms: int = 300,
+        sources: Sequence[int] | None = None,
+    ) -> None: ...
+    def set_comfort_noise(self, enabled: bool) -> None: ...
+    def set_mix_priority(self, priority: int, attenuation: float = 0.3) -> None: ...
+    def set_media_key(self, key_id: int, key: bytes) -> None: ...
+    def set_e2ee_from(self, engine: _MlsEngine, group_id: str) -> None: ...
+    def clear_media_keys(self) -> None: ...
+    def set_frame_transform(self, transform: _FrameTransform | None = None) -> None: ...
+    def poll_video_frame(self) -> tuple[int, int, int, bytes] | None: ...
+    def capture_snapshot(
+        self,
+        user_id: int,
+        path: str | PathLike[str] | None = None,
+        format: Literal["png", "jpeg", "jpg"] | None = None,
+        quality: int = 85,
+        timeout: float = 1.0,
+    ) -> bytes | None: ...
+    def send_data(self, channel_id: int, data: bytes, reliable: bool = True) -> None: ...
+    def poll_data(self) -> tuple[int, int, bytes] | None: ...
+    def get_user_stats(self, user_id: int) -> _UserStats | None: ...
+    def get_audio_stats(self) -> _AudioStats: ...
+    def get_all_user_stats(self) -> dict[int, _UserStats]: ...
+    def poll_event(self) -> tuple[_EventName, str] | None: ...
+    def stop(self) -> None: ...
+    def __enter__(self) -> VoxMediaClient: ...
+    def __exit__(self, _exc_type: object, _exc_value: object, _traceback: object) -> bool: ...
+    @staticmethod
+    def set_gpu_acceleration(enabled: bool) -> None: ...
+    @staticmethod
+    def gpu_acceleration_available() -> bool: ...
+    @staticmethod
+    def test_input_device(name: str | None = None, duration_s: float = 1.0) -> _InputDeviceReport: ...
+    @property
+    def is_muted(self) -> bool: ...
+    @property
+    def is_deafened(self) -> bool: ...
+    @property
+    def is_video_enabled(self) -> bool: ...
+    @property
+    def is_e2ee_enabled(self) -> bool: ...
+
+def configure_logging(
+    level: str,
+    file: str | PathLike[str] | None = None,
+    json: bool = False,
+    python_callback: Callable[[str, str, str], object] | None = None,
+) -> None: ...
+def configure_tracing(
+    endpoint: str | None,
+    service_name: str = "vox-media",
+    sample_ratio: float = 1.0,
+    headers: dict[str, str] | None = None,
+) -> None: ...
+def enable_profiling(enabled: bool = True) -> None: ...
+def profile_report(reset: bool = False) -> dict[str, _ProfileStats]: ...
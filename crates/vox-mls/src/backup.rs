@@ -0,0 +1,82 @@
+//! Passphrase-encrypted state backups.
+//!
+//! A backup is `MAGIC | m_cost | t_cost | p_cost | salt | nonce | ciphertext`,
+//! with the costs as little-endian u32s. The key is derived from the
+//! passphrase with Argon2id and the state sealed with AES-256-GCM, using
+//! everything before the ciphertext as associated data. Storing the costs
+//! lets later versions raise them without breaking old backups.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+const MAGIC: &[u8; 8] = b"VOXSTAT1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+/// Argon2id costs for new backups: 64 MiB, 3 passes, 1 lane.
+const M_COST: u32 = 64 * 1024;
+const T_COST: u32 = 3;
+const P_COST: u32 = 1;
+/// Most memory, in KiB, a backup may ask for when opened, so a crafted
+/// header cannot exhaust memory.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+fn derive_key(passphrase: &[u8], salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<[u8; 32], String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| format!("Invalid Argon2 parameters: {e}"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| format!("Failed to derive backup key: {e}"))?;
+    Ok(key)
+}
+
+/// Encrypt `data` with a key derived from `passphrase`.
+pub fn seal(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let key = derive_key(passphrase.as_bytes(), &salt, M_COST, T_COST, P_COST)?;
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + data.len() + 16);
+    sealed.extend_from_slice(MAGIC);
+    for cost in [M_COST, T_COST, P_COST] {
+        sealed.extend_from_slice(&cost.to_le_bytes());
+    }
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+
+    let ciphertext = Aes256Gcm::new((&key).into())
+        .encrypt(&nonce, Payload { msg: data, aad: &sealed })
+        .map_err(|e| format!("Failed to encrypt backup: {e}"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt a backup made by [`seal`]. A wrong passphrase and a corrupted
+/// backup are indistinguishable.
+pub fn open(passphrase: &str, sealed: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < HEADER_LEN || !sealed.starts_with(MAGIC) {
+        return Err("Not an encrypted state backup".into());
+    }
+    let (header, ciphertext) = sealed.split_at(HEADER_LEN);
+    let cost = |i: usize| {
+        let start = MAGIC.len() + 4 * i;
+        u32::from_le_bytes(header[start..start + 4].try_into().expect("4 bytes"))
+    };
+    let (m_cost, t_cost, p_cost) = (cost(0), cost(1), cost(2));
+    if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(format!("Backup key derivation costs too high: m={m_cost} t={t_cost} p={p_cost}"));
+    }
+    let salt = &header[MAGIC.len() + 12..MAGIC.len() + 12 + SALT_LEN];
+    let nonce = Nonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]);
+    let key = derive_key(passphrase.as_bytes(), salt, m_cost, t_cost, p_cost)?;
+
+    Aes256Gcm::new((&key).into())
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| "Decryption failed: wrong passphrase or corrupted backup".to_string())
+}
@@ -0,0 +1,6 @@
+//! `uniffi-bindgen`, pinned to the UniFFI version the library is built
+//! with. See the `mls-mobile` Makefile target.
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
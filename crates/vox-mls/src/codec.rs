@@ -1,18 +1,136 @@
+use std::fmt;
+
+use ciborium::tag::Required;
+use ciborium::Value;
 use openmls_sqlite_storage::Codec;
-use serde::Serialize;
+use openmls_traits::storage::{traits, Key, CURRENT_VERSION};
+use serde::{Serialize, Serializer};
 
-/// JSON codec for SQLite storage serialization.
+/// CBOR codec for SQLite storage serialization.
+///
+/// CBOR rather than bincode or postcard: it is just as compact for OpenMLS
+/// state, but self-describing, so rows written by the old JSON codec can be
+/// converted without knowing their types (see [`json_to_cbor`]).
 #[derive(Default)]
-pub struct JsonCodec;
+pub struct CborCodec;
+
+/// CBOR tag ("VX") of a namespaced key.
+const NAMESPACE_TAG: u64 = 0x5658;
+/// How a namespaced key starts: a two-byte tag header.
+const NAMESPACE_HEADER: [u8; 3] = [0xd9, 0x56, 0x58];
+
+/// An OpenMLS storage key of a named identity, so that identities sharing
+/// a database, even in the same group, never see each other's rows.
+///
+/// It is encoded as `VX(["handle", key])`: [`namespace_prefix`] followed
+/// by the key's own encoding. Keys of the default identity, `""`, are
+/// encoded as they are. Group IDs and the keys OpenMLS stores outside a
+/// group are namespaced; values are only ever found through them.
+pub struct Namespaced<'a, K: ?Sized> {
+    pub(crate) namespace: &'a str,
+    pub(crate) key: &'a K,
+}
+
+impl<K: Serialize + ?Sized> Serialize for Namespaced<'_, K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.namespace.is_empty() {
+            return self.key.serialize(serializer);
+        }
+        Required::<_, NAMESPACE_TAG>((self.namespace, self.key)).serialize(serializer)
+    }
+}
+
+impl<K: Serialize + ?Sized> Key<CURRENT_VERSION> for Namespaced<'_, K> {}
+impl<K: Serialize + ?Sized> traits::GroupId<CURRENT_VERSION> for Namespaced<'_, K> {}
+impl<K: Serialize + ?Sized> traits::SignaturePublicKey<CURRENT_VERSION> for Namespaced<'_, K> {}
+impl<K: Serialize + ?Sized> traits::EncryptionKey<CURRENT_VERSION> for Namespaced<'_, K> {}
+impl<K: Serialize + ?Sized> traits::HashReference<CURRENT_VERSION> for Namespaced<'_, K> {}
+impl<K: Serialize + ?Sized> traits::PskId<CURRENT_VERSION> for Namespaced<'_, K> {}
+
+/// What precedes a key's encoding in the identity `namespace`: the tag and
+/// the start of the `[handle, key]` pair. Empty for the default identity.
+fn namespace_prefix(namespace: &str) -> Vec<u8> {
+    if namespace.is_empty() {
+        return Vec::new();
+    }
+    let mut prefix = NAMESPACE_HEADER.to_vec();
+    prefix.push(0x82);
+    ciborium::into_writer(namespace, &mut prefix).expect("writing to a Vec cannot fail");
+    prefix
+}
+
+/// Whether a stored key belongs to the identity `namespace`, for scans
+/// over a whole table.
+pub fn in_namespace(namespace: &str, key: &[u8]) -> bool {
+    match namespace.is_empty() {
+        true => !key.starts_with(&NAMESPACE_HEADER),
+        false => key.starts_with(&namespace_prefix(namespace)),
+    }
+}
+
+/// The key the identity `namespace` stores for one of the default
+/// identity, as group exports carry it.
+pub fn add_namespace(namespace: &str, key: &[u8]) -> Vec<u8> {
+    [namespace_prefix(namespace).as_slice(), key].concat()
+}
+
+/// A value that could not be encoded or decoded.
+#[derive(Debug)]
+pub struct CodecError(String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
 
-impl Codec for JsonCodec {
-    type Error = serde_json::Error;
+impl std::error::Error for CodecError {}
+
+impl Codec for CborCodec {
+    type Error = CodecError;
 
     fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, Self::Error> {
-        serde_json::to_vec(value)
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(|e| CodecError(e.to_string()))?;
+        Ok(bytes)
     }
 
     fn from_slice<T: serde::de::DeserializeOwned>(slice: &[u8]) -> Result<T, Self::Error> {
-        serde_json::from_slice(slice)
+        ciborium::from_reader(slice).map_err(|e| CodecError(e.to_string()))
+    }
+}
+
+/// Convert a value stored by the old JSON codec into what [`CborCodec`]
+/// writes for it. Keys are looked up by their encoding, so the result has
+/// to match byte for byte: object fields keep their order, and map keys
+/// that JSON had to stringify (`BTreeMap<LeafNodeIndex, _>`) become
+/// integers again.
+pub fn json_to_cbor(json: &[u8]) -> Result<Vec<u8>, String> {
+    let value: Value = serde_json::from_slice(json).map_err(|e| format!("Invalid JSON row: {e}"))?;
+    // JSON rows predate namespaces: they all belong to the default identity
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&integer_keys(value), &mut bytes).map_err(|e| format!("Failed to encode row: {e}"))?;
+    Ok(bytes)
+}
+
+fn integer_keys(value: Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.into_iter().map(integer_keys).collect()),
+        Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::Text(text) => match text.parse::<u64>() {
+                            Ok(n) => Value::Integer(n.into()),
+                            Err(_) => Value::Text(text),
+                        },
+                        key => key,
+                    };
+                    (key, integer_keys(value))
+                })
+                .collect(),
+        ),
+        value => value,
     }
 }
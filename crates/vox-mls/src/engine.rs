@@ -0,0 +1,1763 @@
+//! The MLS engine as a plain Rust API.
+//!
+//! [`MlsEngine`] is what the Python `vox_mls.MlsEngine` class wraps: one
+//! identity and its groups, persisted in a SQLite database, which can hold
+//! further identities to switch to. Messages, key
+//! packages and backups are passed as serialized bytes, exactly as Python
+//! sees them, so the two can share a database.
+//!
+//! Operations that write more than one thing — a group and its tracking
+//! row, a commit and its merge — run as one transaction, so a crash cannot
+//! leave a group half-written. Databases written before that are checked
+//! and repaired when opened.
+
+use base64::Engine;
+use openmls::prelude::{
+    Ciphersuite, ContentType, CredentialWithKey, GroupId, KeyPackageIn, KeyPackageRef, MlsGroup, ProposalType, Sender,
+    StagedCommit,
+};
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_traits::OpenMlsProvider;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
+use vox_core::key_sink::KeySink;
+
+use crate::backup;
+use crate::group::{self, GroupOptions, JoinOptions, ProcessedResult};
+use crate::identity::{self, KeyPackageOptions};
+use crate::profile;
+use crate::provider::{GroupRows, StorageOptions, StoredKeyPackage, VoxProvider};
+
+/// Error from an [`MlsEngine`] operation. The Python layer raises
+/// `ValueError`, `KeyError`, `RuntimeError` and `WrongEpochError` (a
+/// `RuntimeError`) respectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
+pub enum MlsError {
+    /// Malformed input: a key package, welcome, payload or key.
+    InvalidInput(String),
+    /// No group with this id in the database.
+    UnknownGroup(String),
+    /// Storage, protocol or crypto failure.
+    Failed(String),
+    /// A message for an epoch the group has already left, or too far
+    /// ahead of it to buffer. Nothing was changed; it will not be processed.
+    WrongEpoch { message_epoch: u64, current_epoch: u64 },
+}
+
+impl std::fmt::Display for MlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MlsError::InvalidInput(msg) | MlsError::Failed(msg) => f.write_str(msg),
+            MlsError::UnknownGroup(group_id) => write!(f, "No group with id '{group_id}'"),
+            MlsError::WrongEpoch { message_epoch, current_epoch } => {
+                write!(f, "Message is for epoch {message_epoch}, but the group is at epoch {current_epoch}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MlsError {}
+
+impl From<String> for MlsError {
+    fn from(msg: String) -> Self {
+        MlsError::Failed(msg)
+    }
+}
+
+pub type MlsResult<T> = Result<T, MlsError>;
+
+fn serialize(value: &impl TlsSerialize) -> MlsResult<Vec<u8>> {
+    value.tls_serialize_detached().map_err(|e| MlsError::Failed(format!("{e:?}")))
+}
+
+/// A group ID as the engine names it: UTF-8 as is, anything else
+/// base64url-encoded.
+fn group_id_string(group_id: &GroupId) -> String {
+    String::from_utf8(group_id.as_slice().to_vec())
+        .unwrap_or_else(|e| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(e.into_bytes()))
+}
+
+/// `(leaf_index, identity)` of a group's members.
+fn member_identities(mls_group: &MlsGroup) -> Vec<(u32, Vec<u8>)> {
+    group::members(mls_group).into_iter().map(|(leaf_index, identity, _)| (leaf_index, identity)).collect()
+}
+
+/// Who sent a message, as far as [`MlsEngine::peek_message`] can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageSender {
+    /// A member, by leaf index.
+    Member(u32),
+    /// One of the group's external senders, by index.
+    External(u32),
+    /// A non-member proposing to join.
+    NewMemberProposal,
+    /// A non-member joining by external commit.
+    NewMemberCommit,
+}
+
+impl From<&Sender> for MessageSender {
+    fn from(sender: &Sender) -> Self {
+        match sender {
+            Sender::Member(leaf) => MessageSender::Member(leaf.u32()),
+            Sender::External(index) => MessageSender::External(index.index() as u32),
+            Sender::NewMemberProposal => MessageSender::NewMemberProposal,
+            Sender::NewMemberCommit => MessageSender::NewMemberCommit,
+        }
+    }
+}
+
+impl MessageSender {
+    /// `"member"`, `"external"`, `"new_member_proposal"` or
+    /// `"new_member_commit"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MessageSender::Member(_) => "member",
+            MessageSender::External(_) => "external",
+            MessageSender::NewMemberProposal => "new_member_proposal",
+            MessageSender::NewMemberCommit => "new_member_commit",
+        }
+    }
+
+    /// The leaf or external sender index, if there is one.
+    pub fn index(&self) -> Option<u32> {
+        match self {
+            MessageSender::Member(index) | MessageSender::External(index) => Some(*index),
+            MessageSender::NewMemberProposal | MessageSender::NewMemberCommit => None,
+        }
+    }
+
+    /// The leaf index of a member sender.
+    pub fn leaf_index(&self) -> Option<u32> {
+        match self {
+            MessageSender::Member(index) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+/// What [`MlsEngine::peek_message`] reads from a message's envelope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageInfo {
+    pub group_id: String,
+    pub epoch: u64,
+    /// `"application"`, `"proposal"` or `"commit"`.
+    pub content_type: &'static str,
+    /// None for encrypted messages, whose sender is only known once
+    /// decrypted.
+    pub sender: Option<MessageSender>,
+}
+
+/// An incoming message as [`MlsEngine::process_message`] processed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessedMessage {
+    pub result: ProcessedResult,
+    /// The epoch the message was sent in.
+    pub epoch: u64,
+    /// None while the message is buffered.
+    pub sender: Option<MessageSender>,
+    /// The sender's credential identity, `"<user_id>:<device_id>"`; None
+    /// while the message is buffered.
+    pub sender_identity: Option<Vec<u8>>,
+}
+
+/// A proposal waiting to be committed, from
+/// [`MlsEngine::list_pending_proposals`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingProposal {
+    /// `"add"`, `"update"`, `"remove"`, `"psk"`, `"reinit"`,
+    /// `"external_init"`, `"group_context_extensions"` or `"other"`.
+    pub proposal_type: &'static str,
+    pub sender: MessageSender,
+    /// The proposer's credential identity, `"<user_id>:<device_id>"`; None
+    /// for external senders.
+    pub sender_identity: Option<Vec<u8>>,
+}
+
+/// Something that happened to a group, from
+/// [`MlsEngine::take_group_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupEvent {
+    pub group_id: String,
+    /// The group's epoch once it happened.
+    pub epoch: u64,
+    pub kind: GroupEventKind,
+}
+
+/// What a [`GroupEvent`] reports. A commit reports its membership changes
+/// first, then [`EpochAdvanced`](Self::EpochAdvanced).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupEventKind {
+    /// We created the group, or re-created it with
+    /// [`MlsEngine::reinit_group`]. Its initial members follow as
+    /// [`MemberAdded`](Self::MemberAdded).
+    Created,
+    /// We joined the group, by Welcome or external commit, or imported it.
+    Joined,
+    /// A member joined, with their leaf index and credential identity.
+    MemberAdded { leaf_index: u32, identity: Vec<u8> },
+    /// A member left or was removed, possibly us. A member whose
+    /// credential was rotated is removed and added again.
+    MemberRemoved { leaf_index: u32, identity: Vec<u8> },
+    /// A commit, ours or another member's, moved the group to a new epoch.
+    EpochAdvanced,
+}
+
+/// Storage and group diagnostics from [`MlsEngine::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineStats {
+    /// Bytes the database takes up, for all of its identities, without a
+    /// write-ahead log; on wasm32, bytes of the stored state.
+    pub database_bytes: u64,
+    /// The current identity's groups, in [`list_groups`](MlsEngine::list_groups) order.
+    pub groups: Vec<GroupStats>,
+    /// The current identity's key packages, as tracked for
+    /// [`list_key_packages`](MlsEngine::list_key_packages).
+    pub key_packages: KeyPackageStats,
+}
+
+/// One group's part of [`EngineStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupStats {
+    pub group_id: String,
+    pub epoch: u64,
+    pub members: usize,
+    /// Proposals received and not yet committed.
+    pub pending_proposals: usize,
+}
+
+/// How many key packages we have, in [`EngineStats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPackageStats {
+    pub total: usize,
+    /// Not used by a Welcome yet.
+    pub unused: usize,
+    /// Last resort ones, used or not.
+    pub last_resort: usize,
+}
+
+/// What [`MlsEngine::inspect_key_package`] reads from a key package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPackageInfo {
+    /// Credential identity, `"<user_id>:<device_id>"`.
+    pub identity: Vec<u8>,
+    pub ciphersuite: Ciphersuite,
+    pub signature_key: Vec<u8>,
+    /// Start and end of its validity, in seconds since the Unix epoch.
+    pub not_before: u64,
+    pub not_after: u64,
+    /// Whether it may be used more than once.
+    pub last_resort: bool,
+}
+
+/// What [`MlsEngine::inspect_welcome`] reads from a Welcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WelcomeInfo {
+    pub group_id: String,
+    pub ciphersuite: Ciphersuite,
+    /// Credential identity of the member who added us.
+    pub sender: Vec<u8>,
+}
+
+/// Most messages from later epochs kept per group, so a peer cannot make
+/// the engine hold on to unbounded data. Past it, messages from the
+/// farthest epoch are dropped first, oldest first.
+const MAX_BUFFERED_MESSAGES: usize = 256;
+
+/// Most epochs past the group's that a message may be from and still be
+/// buffered. Reaching later ones would take more commits than a member
+/// could plausibly have missed.
+const MAX_EPOCHS_AHEAD: u64 = 8;
+
+/// Most of our own commits remembered per group, to recognise them when
+/// the server relays them back to us.
+const MAX_SENT_COMMITS: usize = 8;
+
+/// `format` field of [`MlsEngine::export_group`] output.
+const GROUP_EXPORT_FORMAT: &str = "vox-mls-group";
+/// Version 2 switched SQLite rows from JSON to CBOR; version 1 exports
+/// are still accepted.
+const GROUP_EXPORT_VERSION: u32 = 2;
+
+/// One group's state as moved by [`MlsEngine::export_group`].
+#[derive(Serialize, Deserialize)]
+struct GroupExport {
+    format: String,
+    version: u32,
+    group_id: String,
+    rows: GroupRows,
+}
+
+/// MLS encryption engine wrapping OpenMLS.
+///
+/// Each engine manages one identity and multiple groups. It is `Send` and
+/// takes `&mut self`: move it between threads, or share it behind a `Mutex`.
+pub struct MlsEngine {
+    provider: VoxProvider,
+    credential_with_key: Option<CredentialWithKey>,
+    signature_keys: Option<SignatureKeyPair>,
+    /// Media clients receiving each group's per-epoch media key.
+    media_key_sinks: HashMap<String, Vec<KeySink>>,
+    /// Messages that arrived ahead of their group's epoch, by epoch.
+    buffered: HashMap<String, BTreeMap<u64, Vec<Vec<u8>>>>,
+    /// Results of replaying buffered messages, for `take_replayed`.
+    replayed: HashMap<String, Vec<ProcessedMessage>>,
+    /// Commits awaiting `merge_staged_commit`, with the epoch they apply to.
+    staged: HashMap<String, (u64, StagedCommit)>,
+    /// Digests of the last commits we sent, by group, oldest first.
+    sent_commits: HashMap<String, VecDeque<Vec<u8>>>,
+    /// Events for `take_group_events`; None unless they are watched.
+    group_events: Option<Vec<GroupEvent>>,
+    /// `(leaf_index, identity)` of each group's members as of its last
+    /// event, while group events are watched.
+    known_members: HashMap<String, Vec<(u32, Vec<u8>)>>,
+    /// For new key packages and groups, unless a group is given its own.
+    ciphersuite: Ciphersuite,
+    /// Handle of the current identity, empty for the default one.
+    identity_handle: String,
+    /// The in-memory state of the other identities used so far, by handle.
+    parked: HashMap<String, ParkedIdentity>,
+}
+
+/// What the engine holds in memory for an identity while another is in
+/// use; see [`MlsEngine::use_identity`].
+#[derive(Default)]
+struct ParkedIdentity {
+    credential_with_key: Option<CredentialWithKey>,
+    signature_keys: Option<SignatureKeyPair>,
+    media_key_sinks: HashMap<String, Vec<KeySink>>,
+    buffered: HashMap<String, BTreeMap<u64, Vec<Vec<u8>>>>,
+    replayed: HashMap<String, Vec<ProcessedMessage>>,
+    staged: HashMap<String, (u64, StagedCommit)>,
+    sent_commits: HashMap<String, VecDeque<Vec<u8>>>,
+}
+
+impl MlsEngine {
+    /// Open or create the database at `db_path` (`":memory:"` for a
+    /// throwaway engine), restoring the identity stored there, if any.
+    /// With `encryption_key`, private key material is stored AES-256-GCM
+    /// encrypted. On wasm32 the state is held in memory and `db_path` is
+    /// ignored; restore it with [`import_state`](Self::import_state).
+    pub fn open(db_path: &str, encryption_key: Option<[u8; 32]>) -> MlsResult<Self> {
+        Self::open_with_db_key(db_path, encryption_key, None)
+    }
+
+    /// [`open`](Self::open), with the whole database encrypted by SQLCipher
+    /// under `db_key`, not just the private key material. Needs the
+    /// `sqlcipher` feature; fails if the key does not match.
+    pub fn open_with_db_key(
+        db_path: &str,
+        encryption_key: Option<[u8; 32]>,
+        db_key: Option<[u8; 32]>,
+    ) -> MlsResult<Self> {
+        Self::open_with_options(db_path, encryption_key, db_key, &StorageOptions::default())
+    }
+
+    /// [`open_with_db_key`](Self::open_with_db_key), with SQLite's journal
+    /// mode, sync level and busy timeout set from `options`, e.g. WAL
+    /// and a timeout when another process shares the database.
+    pub fn open_with_options(
+        db_path: &str,
+        encryption_key: Option<[u8; 32]>,
+        db_key: Option<[u8; 32]>,
+        options: &StorageOptions,
+    ) -> MlsResult<Self> {
+        let provider = VoxProvider::new(db_path, encryption_key, db_key, options)?;
+        let mut engine = MlsEngine {
+            provider,
+            credential_with_key: None,
+            signature_keys: None,
+            media_key_sinks: HashMap::new(),
+            buffered: HashMap::new(),
+            replayed: HashMap::new(),
+            staged: HashMap::new(),
+            sent_commits: HashMap::new(),
+            group_events: None,
+            known_members: HashMap::new(),
+            ciphersuite: identity::DEFAULT_CIPHERSUITE,
+            identity_handle: String::new(),
+            parked: HashMap::new(),
+        };
+        let (cwk, sig) = engine.load_stored_identity()?.unzip();
+        engine.credential_with_key = cwk;
+        engine.signature_keys = sig;
+        Ok(engine)
+    }
+
+    /// Switch to the identity stored under `handle`, or back to the
+    /// default one with `None`. Identities share the database and nothing
+    /// else: each has its own credential, key packages and groups, and
+    /// two can be members of the same group. A handle not used before
+    /// starts without an identity, for
+    /// [`generate_identity`](Self::generate_identity). What is pending for
+    /// the identity switched away from, such as buffered messages and
+    /// staged commits, waits for it to be used again. Needs SQLite storage.
+    pub fn use_identity(&mut self, handle: Option<&str>) -> MlsResult<()> {
+        let handle = handle.unwrap_or_default();
+        if handle == self.identity_handle {
+            return Ok(());
+        }
+        self.provider.set_namespace(handle).map_err(MlsError::InvalidInput)?;
+        let next = match self.parked.remove(handle) {
+            Some(parked) => parked,
+            None => match self.load_stored_identity() {
+                Ok(identity) => {
+                    let (credential_with_key, signature_keys) = identity.unzip();
+                    ParkedIdentity { credential_with_key, signature_keys, ..Default::default() }
+                }
+                Err(e) => {
+                    self.provider.set_namespace(&self.identity_handle)?;
+                    return Err(e);
+                }
+            },
+        };
+        let current = ParkedIdentity {
+            credential_with_key: std::mem::replace(&mut self.credential_with_key, next.credential_with_key),
+            signature_keys: std::mem::replace(&mut self.signature_keys, next.signature_keys),
+            media_key_sinks: std::mem::replace(&mut self.media_key_sinks, next.media_key_sinks),
+            buffered: std::mem::replace(&mut self.buffered, next.buffered),
+            replayed: std::mem::replace(&mut self.replayed, next.replayed),
+            staged: std::mem::replace(&mut self.staged, next.staged),
+            sent_commits: std::mem::replace(&mut self.sent_commits, next.sent_commits),
+        };
+        let previous = std::mem::replace(&mut self.identity_handle, handle.to_string());
+        self.parked.insert(previous, current);
+        self.remember_members()
+    }
+
+    /// Handle of the identity in use, `None` for the default one.
+    pub fn identity_handle(&self) -> Option<&str> {
+        Some(self.identity_handle.as_str()).filter(|handle| !handle.is_empty())
+    }
+
+    /// Handles of the identities stored in the database, besides the
+    /// default one.
+    pub fn list_identities(&self) -> MlsResult<Vec<String>> {
+        Ok(self.provider.list_namespaces()?)
+    }
+
+    /// Generate a new MLS identity for the given user/device.
+    /// Returns the public identity key bytes.
+    pub fn generate_identity(&mut self, user_id: u64, device_id: &str) -> MlsResult<Vec<u8>> {
+        if self.signature_keys.is_some() {
+            return Err(MlsError::Failed(
+                "Identity already initialized — cannot re-initialize without reset".into(),
+            ));
+        }
+
+        let (cwk, sig_keys) = identity::generate_identity(&self.provider, user_id, device_id)?;
+        self.save_identity(user_id, device_id, &cwk, &sig_keys)?;
+
+        let public_key = sig_keys.to_public_vec();
+        self.credential_with_key = Some(cwk);
+        self.signature_keys = Some(sig_keys);
+        Ok(public_key)
+    }
+
+    /// The ciphersuite of new key packages and groups.
+    pub fn ciphersuite(&self) -> Ciphersuite {
+        self.ciphersuite
+    }
+
+    /// Use `ciphersuite` for key packages and groups created from now on;
+    /// see [`SUPPORTED_CIPHERSUITES`](crate::SUPPORTED_CIPHERSUITES). Existing groups keep
+    /// theirs, and only key packages of a group's ciphersuite can join it.
+    pub fn set_ciphersuite(&mut self, ciphersuite: Ciphersuite) -> MlsResult<()> {
+        if !identity::SUPPORTED_CIPHERSUITES.contains(&ciphersuite) {
+            return Err(MlsError::InvalidInput(format!("Unsupported ciphersuite {ciphersuite:?}")));
+        }
+        self.ciphersuite = ciphersuite;
+        Ok(())
+    }
+
+    /// Generate a serialized KeyPackage for uploading to the server.
+    pub fn generate_key_package(&self) -> MlsResult<Vec<u8>> {
+        self.generate_key_package_with(&KeyPackageOptions::default())
+    }
+
+    /// Generate a KeyPackage with a chosen lifetime, extensions or
+    /// capabilities. It is tracked until it expires or is deleted, see
+    /// [`list_key_packages`](Self::list_key_packages).
+    pub fn generate_key_package_with(&self, options: &KeyPackageOptions) -> MlsResult<Vec<u8>> {
+        let (cwk, sig) = self.require_identity()?;
+        let kp = self.provider.atomically(|| {
+            let kp = identity::generate_key_package(&self.provider, cwk, sig, self.ciphersuite, options)
+                .map_err(MlsError::InvalidInput)?;
+            let hash_ref = kp
+                .hash_ref(self.provider.crypto())
+                .map_err(|e| MlsError::Failed(format!("Failed to hash key package: {e:?}")))?;
+            self.provider.track_key_package(hash_ref.as_slice(), options.last_resort)?;
+            Ok::<_, MlsError>(kp)
+        })?;
+        serialize(&kp)
+    }
+
+    /// Generate multiple KeyPackages.
+    pub fn generate_key_packages(&self, count: usize) -> MlsResult<Vec<Vec<u8>>> {
+        (0..count).map(|_| self.generate_key_package()).collect()
+    }
+
+    /// Generate multiple KeyPackages with the same options.
+    pub fn generate_key_packages_with(&self, count: usize, options: &KeyPackageOptions) -> MlsResult<Vec<Vec<u8>>> {
+        (0..count).map(|_| self.generate_key_package_with(options)).collect()
+    }
+
+    /// The key packages we generated, oldest first, to reconcile with those
+    /// uploaded to the server. Used up ones stay listed as `consumed`
+    /// until deleted.
+    pub fn list_key_packages(&self) -> MlsResult<Vec<StoredKeyPackage>> {
+        Ok(self.provider.list_key_packages()?)
+    }
+
+    /// Delete one of our key packages by hash reference: its private
+    /// bundle, so no Welcome can use it any more, and its tracking entry.
+    pub fn delete_key_package(&self, hash_ref: &[u8]) -> MlsResult<()> {
+        let key_package_ref = KeyPackageRef::from_slice(hash_ref);
+        self.provider.atomically(|| {
+            let had_bundle = identity::delete_key_package(&self.provider, &key_package_ref)?;
+            let was_tracked = self.provider.untrack_key_package(hash_ref)?;
+            if !had_bundle && !was_tracked {
+                return Err(MlsError::InvalidInput("No key package with that hash reference".to_string()));
+            }
+            Ok(())
+        })
+    }
+
+    /// Read who a serialized KeyPackage is for, its ciphersuite and
+    /// validity, after checking its signature. Nothing is stored, so it
+    /// needs no engine.
+    pub fn inspect_key_package(key_package: &[u8]) -> MlsResult<KeyPackageInfo> {
+        let kp = identity::inspect_key_package(key_package).map_err(MlsError::InvalidInput)?;
+        let leaf = kp.leaf_node();
+        Ok(KeyPackageInfo {
+            identity: leaf.credential().serialized_content().to_vec(),
+            ciphersuite: kp.ciphersuite(),
+            signature_key: leaf.signature_key().as_slice().to_vec(),
+            not_before: kp.life_time().not_before(),
+            not_after: kp.life_time().not_after(),
+            last_resort: kp.last_resort(),
+        })
+    }
+
+    /// Create a new MLS group with serialized KeyPackages of its initial
+    /// members. Returns `(welcome, commit)`, both None without members.
+    pub fn create_group(
+        &mut self,
+        group_id: &str,
+        member_key_packages: &[Vec<u8>],
+    ) -> MlsResult<(Option<Vec<u8>>, Option<Vec<u8>>)> {
+        self.create_group_with_ciphersuite(group_id, member_key_packages, self.ciphersuite)
+    }
+
+    /// [`create_group`](Self::create_group) with a ciphersuite other than
+    /// the engine's. The members' key packages must be for it.
+    pub fn create_group_with_ciphersuite(
+        &mut self,
+        group_id: &str,
+        member_key_packages: &[Vec<u8>],
+        ciphersuite: Ciphersuite,
+    ) -> MlsResult<(Option<Vec<u8>>, Option<Vec<u8>>)> {
+        let options = GroupOptions { ciphersuite: Some(ciphersuite), ..GroupOptions::default() };
+        self.create_group_with(group_id, member_key_packages, &options)
+    }
+
+    /// [`create_group`](Self::create_group) with a chosen ciphersuite,
+    /// GroupContext extensions or required capabilities. Members added with
+    /// extensions or required extension types must advertise those types in
+    /// [`KeyPackageOptions::capability_extensions`].
+    pub fn create_group_with(
+        &mut self,
+        group_id: &str,
+        member_key_packages: &[Vec<u8>],
+        options: &GroupOptions,
+    ) -> MlsResult<(Option<Vec<u8>>, Option<Vec<u8>>)> {
+        let (welcome, commit, _) = self.create_group_exporting(group_id, member_key_packages, options, false)?;
+        Ok((welcome, commit))
+    }
+
+    /// [`create_group_with`](Self::create_group_with), also returning the
+    /// new group's signed GroupInfo with its ratchet tree, as
+    /// `(welcome, commit, group_info)`, for publishing along with the
+    /// commit so that others can join by external commit.
+    pub fn create_group_with_group_info(
+        &mut self,
+        group_id: &str,
+        member_key_packages: &[Vec<u8>],
+        options: &GroupOptions,
+    ) -> MlsResult<(Option<Vec<u8>>, Option<Vec<u8>>, Vec<u8>)> {
+        let (welcome, commit, group_info) = self.create_group_exporting(group_id, member_key_packages, options, true)?;
+        Ok((welcome, commit, group_info.expect("group info was asked for")))
+    }
+
+    fn create_group_exporting(
+        &mut self,
+        group_id: &str,
+        member_key_packages: &[Vec<u8>],
+        options: &GroupOptions,
+        with_group_info: bool,
+    ) -> MlsResult<(Option<Vec<u8>>, Option<Vec<u8>>, Option<Vec<u8>>)> {
+        let ciphersuite = options.ciphersuite.unwrap_or(self.ciphersuite);
+        if !identity::SUPPORTED_CIPHERSUITES.contains(&ciphersuite) {
+            return Err(MlsError::InvalidInput(format!("Unsupported ciphersuite {ciphersuite:?}")));
+        }
+        for extension_type in options.extensions.iter().map(|(t, _)| t).chain(&options.required_extensions) {
+            identity::custom_extension_type(*extension_type).map_err(MlsError::InvalidInput)?;
+        }
+        let (cwk, sig) = self.require_identity()?;
+
+        let kp_ins: Vec<KeyPackageIn> = member_key_packages
+            .iter()
+            .map(|bytes| {
+                KeyPackageIn::tls_deserialize_exact(bytes)
+                    .map_err(|e| MlsError::InvalidInput(format!("Invalid key package: {e:?}")))
+            })
+            .collect::<MlsResult<Vec<_>>>()?;
+
+        let (mls_group, welcome, commit, group_info) = self.provider.atomically(|| {
+            let (mls_group, welcome, commit) =
+                group::create_group(&self.provider, sig, cwk, group_id, &kp_ins, ciphersuite, options)?;
+            // Group is automatically persisted by the SQLite storage provider
+            self.provider.save_group_id(group_id)?;
+            let group_info =
+                with_group_info.then(|| group::export_group_info(&self.provider, &mls_group, sig, true)).transpose()?;
+            Ok::<_, MlsError>((mls_group, welcome, commit, group_info))
+        })?;
+        self.record_group_events(group_id, &mls_group, Some(GroupEventKind::Created));
+
+        let welcome = welcome.map(|w| serialize(&w)).transpose()?;
+        let commit = commit.map(|c| self.sent_commit(group_id, &c)).transpose()?;
+        Ok((welcome, commit, group_info.map(|g| serialize(&g)).transpose()?))
+    }
+
+    /// Join a group from a Welcome message. Returns the group ID; binary
+    /// group IDs are base64url-encoded. A Welcome to a group we are in, as
+    /// sent by [`reinit_group`](Self::reinit_group), replaces its state.
+    pub fn join_group(&mut self, welcome: &[u8]) -> MlsResult<String> {
+        self.join_group_with_ratchet_tree(welcome, None)
+    }
+
+    /// [`join_group`](Self::join_group) for groups created with
+    /// [`GroupOptions::omit_ratchet_tree`], whose Welcomes need the
+    /// [`export_ratchet_tree`](Self::export_ratchet_tree) output passed
+    /// along.
+    pub fn join_group_with_ratchet_tree(&mut self, welcome: &[u8], ratchet_tree: Option<&[u8]>) -> MlsResult<String> {
+        self.join_group_with(welcome, ratchet_tree, &JoinOptions::default())
+    }
+
+    /// [`join_group_with_ratchet_tree`](Self::join_group_with_ratchet_tree)
+    /// with [`JoinOptions`], such as message padding.
+    pub fn join_group_with(
+        &mut self,
+        welcome: &[u8],
+        ratchet_tree: Option<&[u8]>,
+        options: &JoinOptions,
+    ) -> MlsResult<String> {
+        let (group_id, mls_group) = self.provider.atomically(|| {
+            let (mls_group, key_package_ref) = group::join_group(&self.provider, welcome, ratchet_tree, options)?;
+            let group_id = group_id_string(mls_group.group_id());
+            if let Some(hash_ref) = key_package_ref {
+                self.provider.mark_key_package_consumed(&hash_ref)?;
+            }
+
+            // Group is automatically persisted by the SQLite storage provider
+            self.provider.forget_group_id(&group_id)?;
+            self.provider.save_group_id(&group_id)?;
+            Ok::<_, MlsError>((group_id, mls_group))
+        })?;
+        self.forget_epochs(&group_id);
+        self.record_group_events(&group_id, &mls_group, Some(GroupEventKind::Joined));
+        Ok(group_id)
+    }
+
+    /// Read which group a Welcome is for, its ciphersuite and who sent it,
+    /// so the user can be asked before [`join_group`](Self::join_group).
+    /// The key package it was for stays available for that.
+    pub fn inspect_welcome(&self, welcome: &[u8], ratchet_tree: Option<&[u8]>) -> MlsResult<WelcomeInfo> {
+        let (group_id, ciphersuite, sender) = self
+            .provider
+            .rolled_back(|| group::inspect_welcome(&self.provider, welcome, ratchet_tree))?
+            .map_err(MlsError::InvalidInput)?;
+        Ok(WelcomeInfo { group_id: group_id_string(&group_id), ciphersuite, sender })
+    }
+
+    /// Move a group to another ciphersuite, in place of an MLS ReInit,
+    /// which OpenMLS cannot create: the group is re-created with the same
+    /// id, members and GroupContext extensions, and the old state deleted.
+    /// `member_key_packages` must be for `ciphersuite` and for exactly the
+    /// other members. Returns the Welcome moving them over, None if we are
+    /// alone.
+    ///
+    /// Epochs start over, so attached media key sinks are dropped; attach
+    /// them again.
+    pub fn reinit_group(
+        &mut self,
+        group_id: &str,
+        ciphersuite: Ciphersuite,
+        member_key_packages: &[Vec<u8>],
+    ) -> MlsResult<Option<Vec<u8>>> {
+        if !identity::SUPPORTED_CIPHERSUITES.contains(&ciphersuite) {
+            return Err(MlsError::InvalidInput(format!("Unsupported ciphersuite {ciphersuite:?}")));
+        }
+        let (cwk, sig) = self.require_identity()?;
+        let kp_ins: Vec<KeyPackageIn> = member_key_packages
+            .iter()
+            .map(|bytes| {
+                KeyPackageIn::tls_deserialize_exact(bytes)
+                    .map_err(|e| MlsError::InvalidInput(format!("Invalid key package: {e:?}")))
+            })
+            .collect::<MlsResult<Vec<_>>>()?;
+
+        let (new_group, welcome) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            group::reinit_group(&self.provider, sig, cwk, &mut mls_group, group_id, &kp_ins, ciphersuite)
+                .map_err(MlsError::InvalidInput)
+        })?;
+        tracing::info!(group_id = %group_id, ciphersuite = ?ciphersuite, "Re-initialized group");
+        self.forget_epochs(group_id);
+        self.record_group_events(group_id, &new_group, Some(GroupEventKind::Created));
+
+        welcome.map(|w| serialize(&w)).transpose()
+    }
+
+    /// Export a group's signed GroupInfo, for publishing so members who
+    /// lost their state can rejoin with
+    /// [`join_by_external_commit`](Self::join_by_external_commit). That
+    /// needs `with_ratchet_tree`; leave it out only if joiners get the tree
+    /// some other way, as it grows with the group.
+    pub fn export_group_info(&self, group_id: &str, with_ratchet_tree: bool) -> MlsResult<Vec<u8>> {
+        let mls_group = self.load_group(group_id)?;
+        let sig = self.group_signer(&mls_group)?;
+        serialize(&group::export_group_info(&self.provider, &mls_group, &sig, with_ratchet_tree)?)
+    }
+
+    /// Serialize a group's ratchet tree, to hand to members joining with a
+    /// Welcome that leaves it out.
+    pub fn export_ratchet_tree(&self, group_id: &str) -> MlsResult<Vec<u8>> {
+        Ok(group::export_ratchet_tree(&self.load_group(group_id)?)?)
+    }
+
+    /// Join a group from its GroupInfo, without a Welcome, for instance to
+    /// rejoin after losing local state. Any state still held for the group
+    /// is replaced. Returns `(group_id, commit)`; the external commit must
+    /// reach the other members for them to move to the new epoch.
+    pub fn join_by_external_commit(&mut self, group_info: &[u8]) -> MlsResult<(String, Vec<u8>)> {
+        self.join_by_external_commit_with(group_info, &JoinOptions::default())
+    }
+
+    /// [`join_by_external_commit`](Self::join_by_external_commit) with
+    /// [`JoinOptions`], such as message padding.
+    pub fn join_by_external_commit_with(
+        &mut self,
+        group_info: &[u8],
+        options: &JoinOptions,
+    ) -> MlsResult<(String, Vec<u8>)> {
+        let (cwk, sig) = self.require_identity()?;
+        let (group_id, mls_group, commit) = self.provider.atomically(|| {
+            let (mls_group, commit) = group::join_by_external_commit(&self.provider, sig, cwk, group_info, options)?;
+            let group_id = group_id_string(mls_group.group_id());
+            self.provider.forget_group_id(&group_id)?;
+            self.provider.save_group_id(&group_id)?;
+            Ok::<_, MlsError>((group_id, mls_group, commit))
+        })?;
+        self.publish_media_key(&group_id, &mls_group);
+        self.record_group_events(&group_id, &mls_group, Some(GroupEventKind::Joined));
+        let commit = self.sent_commit(&group_id, &commit)?;
+
+        Ok((group_id, commit))
+    }
+
+    /// Add a member to an existing group. Returns `(welcome, commit)`.
+    pub fn add_member(&mut self, group_id: &str, key_package: &[u8]) -> MlsResult<(Vec<u8>, Vec<u8>)> {
+        let (welcome, commit, _) = self.add_member_exporting(group_id, key_package, false)?;
+        Ok((welcome, commit))
+    }
+
+    /// [`add_member`](Self::add_member), also returning the group's signed
+    /// GroupInfo for the new epoch, with its ratchet tree, as
+    /// `(welcome, commit, group_info)`.
+    pub fn add_member_with_group_info(
+        &mut self,
+        group_id: &str,
+        key_package: &[u8],
+    ) -> MlsResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let (welcome, commit, group_info) = self.add_member_exporting(group_id, key_package, true)?;
+        Ok((welcome, commit, group_info.expect("group info was asked for")))
+    }
+
+    fn add_member_exporting(
+        &mut self,
+        group_id: &str,
+        key_package: &[u8],
+        with_group_info: bool,
+    ) -> MlsResult<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)> {
+        let (mls_group, welcome, commit, group_info) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let (welcome, commit) = group::add_member(&self.provider, &mut mls_group, &sig, key_package)?;
+            let group_info =
+                with_group_info.then(|| group::export_group_info(&self.provider, &mls_group, &sig, true)).transpose()?;
+            Ok::<_, MlsError>((mls_group, welcome, commit, group_info))
+        })?;
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        let group_info = group_info.map(|g| serialize(&g)).transpose()?;
+        Ok((serialize(&welcome)?, self.sent_commit(group_id, &commit)?, group_info))
+    }
+
+    /// Remove a member from a group by credential identity string.
+    /// Returns the commit.
+    pub fn remove_member(&mut self, group_id: &str, member_identity: &str) -> MlsResult<Vec<u8>> {
+        let (mls_group, commit) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let commit = group::remove_member_by_identity(&self.provider, &mut mls_group, &sig, member_identity)?;
+            Ok::<_, MlsError>((mls_group, commit))
+        })?;
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        self.sent_commit(group_id, &commit)
+    }
+
+    /// Rotate our own leaf key material with an Update commit, so a past
+    /// compromise of our keys stops exposing future epochs. Returns the
+    /// commit for the other members.
+    pub fn update_self(&mut self, group_id: &str) -> MlsResult<Vec<u8>> {
+        let (mls_group, commit) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let commit = group::self_update(&self.provider, &mut mls_group, &sig)?;
+            Ok::<_, MlsError>((mls_group, commit))
+        })?;
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        self.sent_commit(group_id, &commit)
+    }
+
+    /// Replace our credential in a group with a fresh one for
+    /// `new_device_id`, under a new signature key, by an Update commit
+    /// signed with the old key, e.g. after renaming the device or when the
+    /// old key may be compromised. The new identity is stored and used from
+    /// now on. Returns the commit for the other members.
+    ///
+    /// Our other groups keep the old credential until it is rotated there
+    /// too; their old signature keys stay in storage until then.
+    pub fn rotate_credential(&mut self, group_id: &str, new_device_id: &str) -> MlsResult<Vec<u8>> {
+        self.require_identity()?;
+        let (user_id, _) = self
+            .stored_identity()?
+            .ok_or_else(|| MlsError::Failed("Identity is not stored — cannot rotate it".into()))?;
+        let (mls_group, commit, cwk, sig) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let old_sig = self.group_signer(&mls_group)?;
+            let (cwk, sig) = identity::generate_identity(&self.provider, user_id, new_device_id)?;
+            let commit = group::rotate_credential(&self.provider, &mut mls_group, &old_sig, &sig, cwk.clone())?;
+            self.save_identity(user_id, new_device_id, &cwk, &sig)?;
+            Ok::<_, MlsError>((mls_group, commit, cwk, sig))
+        })?;
+        tracing::info!(group_id = %group_id, device_id = %new_device_id, "Rotated credential");
+        self.credential_with_key = Some(cwk);
+        self.signature_keys = Some(sig);
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        self.sent_commit(group_id, &commit)
+    }
+
+    /// Replace a group's application-defined GroupContext extensions, as
+    /// `(type, data)`, with a commit for the other members. Every member
+    /// must support the types, see
+    /// [`KeyPackageOptions::capability_extensions`].
+    pub fn update_group_context_extensions(
+        &mut self,
+        group_id: &str,
+        extensions: &[(u16, Vec<u8>)],
+    ) -> MlsResult<Vec<u8>> {
+        let (mls_group, commit) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let commit = group::update_context_extensions(&self.provider, &mut mls_group, &sig, extensions)
+                .map_err(MlsError::InvalidInput)?;
+            Ok::<_, MlsError>((mls_group, commit))
+        })?;
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        self.sent_commit(group_id, &commit)
+    }
+
+    /// A group's application-defined GroupContext extensions as
+    /// `(type, data)`, as of its current epoch.
+    pub fn group_context_extensions(&self, group_id: &str) -> MlsResult<Vec<(u16, Vec<u8>)>> {
+        Ok(group::context_extensions(&self.load_group(group_id)?))
+    }
+
+    /// Ask to leave a group: returns a proposal removing our own leaf, to
+    /// relay via the server, and marks the group as being left. The
+    /// departure completes when another member commits the proposal; once
+    /// that commit is processed here, the group is deleted.
+    pub fn leave_group(&mut self, group_id: &str) -> MlsResult<Vec<u8>> {
+        let proposal = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let proposal = group::leave_group(&self.provider, &mut mls_group, &sig)?;
+            self.provider.mark_leaving(group_id)?;
+            Ok::<_, MlsError>(proposal)
+        })?;
+
+        serialize(&proposal)
+    }
+
+    /// Whether [`leave_group`](Self::leave_group) was called for a group
+    /// that has not removed us yet.
+    pub fn is_leaving(&self, group_id: &str) -> MlsResult<bool> {
+        Ok(self.provider.is_leaving(group_id)?)
+    }
+
+    /// The proposals received for a group and not yet committed, in the
+    /// order they arrived.
+    pub fn list_pending_proposals(&self, group_id: &str) -> MlsResult<Vec<PendingProposal>> {
+        let mls_group = self.load_group(group_id)?;
+        let proposals = group::pending_proposals(&mls_group)
+            .into_iter()
+            .map(|(proposal_type, sender, sender_identity)| PendingProposal {
+                proposal_type: match proposal_type {
+                    ProposalType::Add => "add",
+                    ProposalType::Update => "update",
+                    ProposalType::Remove => "remove",
+                    ProposalType::PreSharedKey => "psk",
+                    ProposalType::Reinit => "reinit",
+                    ProposalType::ExternalInit => "external_init",
+                    ProposalType::GroupContextExtensions => "group_context_extensions",
+                    _ => "other",
+                },
+                sender: MessageSender::from(&sender),
+                sender_identity,
+            })
+            .collect();
+        Ok(proposals)
+    }
+
+    /// Drop the proposals received for a group and not yet committed, e.g.
+    /// when one can never be committed and keeps the others stuck.
+    pub fn clear_pending_proposals(&mut self, group_id: &str) -> MlsResult<()> {
+        let mut mls_group = self.load_group(group_id)?;
+        Ok(group::clear_pending_proposals(&self.provider, &mut mls_group)?)
+    }
+
+    /// Commit the proposals received for a group, such as another member's
+    /// request to leave. Returns `(commit, welcome)`, or None if there were
+    /// no proposals; `welcome` is set if a proposal added members.
+    pub fn commit_pending_proposals(&mut self, group_id: &str) -> MlsResult<Option<(Vec<u8>, Option<Vec<u8>>)>> {
+        let (mls_group, committed) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let sig = self.group_signer(&mls_group)?;
+            let committed = group::commit_pending_proposals(&self.provider, &mut mls_group, &sig)?;
+            Ok::<_, MlsError>((mls_group, committed))
+        })?;
+        let Some((commit, welcome)) = committed else {
+            return Ok(None);
+        };
+        self.publish_media_key(group_id, &mls_group);
+        self.record_group_events(group_id, &mls_group, None);
+
+        Ok(Some((self.sent_commit(group_id, &commit)?, welcome.map(|w| serialize(&w)).transpose()?)))
+    }
+
+    /// Process an incoming MLS message (commit, proposal, or application message).
+    /// A commit removing us from a group we asked to leave deletes it.
+    ///
+    /// A message from an earlier epoch than the group's fails with
+    /// [`MlsError::WrongEpoch`], unless it is one of our last commits
+    /// relayed back: then [`ProcessedResult::OwnCommit`] is returned. One
+    /// from up to 8 epochs later is kept in memory and
+    /// [`ProcessedResult::Buffered`] returned; one from further ahead fails
+    /// with [`MlsError::WrongEpoch`]. Once commits bring the group
+    /// to its epoch it is processed, and the result can be collected with
+    /// [`take_replayed`](Self::take_replayed).
+    pub fn process_message(&mut self, group_id: &str, message: &[u8]) -> MlsResult<ProcessedMessage> {
+        self.process(group_id, message, false)
+    }
+
+    /// [`process_message`](Self::process_message), except that a commit is
+    /// not merged: [`ProcessedResult::StagedCommit`] lists the membership
+    /// changes it makes, and it waits for
+    /// [`merge_staged_commit`](Self::merge_staged_commit) or
+    /// [`reject_staged_commit`](Self::reject_staged_commit). A group has at
+    /// most one staged commit, kept in memory only. Buffered commits
+    /// replayed in the meantime are staged too.
+    pub fn process_message_staged(&mut self, group_id: &str, message: &[u8]) -> MlsResult<ProcessedMessage> {
+        self.process(group_id, message, true)
+    }
+
+    /// Merge the commit staged for a group, moving it to the next epoch,
+    /// then replay messages buffered for that epoch as
+    /// [`process_message_staged`](Self::process_message_staged) would.
+    pub fn merge_staged_commit(&mut self, group_id: &str) -> MlsResult<()> {
+        let (epoch, staged_commit) = self
+            .staged
+            .remove(group_id)
+            .ok_or_else(|| MlsError::InvalidInput(format!("No staged commit for group '{group_id}'")))?;
+        let (mls_group, left) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            if mls_group.epoch().as_u64() != epoch {
+                return Err(MlsError::InvalidInput(format!(
+                    "Group '{group_id}' has left epoch {epoch} since the commit was staged"
+                )));
+            }
+            group::merge_staged_commit(&self.provider, &mut mls_group, staged_commit)?;
+            let left = self.delete_if_left(group_id, &mut mls_group)?;
+            Ok((mls_group, left))
+        })?;
+        self.after_commit(group_id, &mls_group, left);
+        self.replay_buffered(group_id, true);
+        Ok(())
+    }
+
+    /// Drop the commit staged for a group. The group stays in its epoch,
+    /// so messages from members who merged the commit no longer decrypt.
+    pub fn reject_staged_commit(&mut self, group_id: &str) -> MlsResult<()> {
+        match self.staged.remove(group_id) {
+            Some(_) => Ok(()),
+            None => Err(MlsError::InvalidInput(format!("No staged commit for group '{group_id}'"))),
+        }
+    }
+
+    fn process(&mut self, group_id: &str, message: &[u8], stage_commits: bool) -> MlsResult<ProcessedMessage> {
+        let peeked = group::peek_message(message).ok();
+        let epoch = peeked.as_ref().map(|(_, epoch, _, _)| *epoch);
+        if let Some((_, epoch, ContentType::Commit, _)) = peeked {
+            if let Some(own_commit) = self.own_commit_echo(group_id, message, epoch)? {
+                return Ok(own_commit);
+            }
+        }
+        let processed = self.process_in_epoch(group_id, message, epoch, stage_commits)?;
+        match (&processed.result, epoch) {
+            (ProcessedResult::Buffered, Some(epoch)) => self.buffer_message(group_id, epoch, message),
+            (ProcessedResult::Commit, _) => self.replay_buffered(group_id, stage_commits),
+            _ => {}
+        }
+        Ok(processed)
+    }
+
+    /// Serialize a commit we are about to hand out, remembering it so that
+    /// its echo is recognised by [`process_message`](Self::process_message).
+    fn sent_commit(&mut self, group_id: &str, commit: &impl TlsSerialize) -> MlsResult<Vec<u8>> {
+        let commit = serialize(commit)?;
+        let digest = group::message_digest(&self.provider, &commit)?;
+        let sent = self.sent_commits.entry(group_id.to_string()).or_default();
+        if sent.len() == MAX_SENT_COMMITS {
+            sent.pop_front();
+        }
+        sent.push_back(digest);
+        Ok(commit)
+    }
+
+    /// The result for `commit`, from `epoch`, if it is one we sent.
+    fn own_commit_echo(&self, group_id: &str, commit: &[u8], epoch: u64) -> MlsResult<Option<ProcessedMessage>> {
+        let Some(sent) = self.sent_commits.get(group_id) else {
+            return Ok(None);
+        };
+        if !sent.contains(&group::message_digest(&self.provider, commit)?) {
+            return Ok(None);
+        }
+        tracing::debug!(group_id = %group_id, epoch, "Ignoring our own commit relayed back");
+        let own_leaf = self.load_group(group_id)?.own_leaf_index().u32();
+        Ok(Some(ProcessedMessage {
+            result: ProcessedResult::OwnCommit,
+            epoch,
+            sender: Some(MessageSender::Member(own_leaf)),
+            sender_identity: self.credential_with_key.as_ref().map(|cwk| cwk.credential.serialized_content().to_vec()),
+        }))
+    }
+
+    /// Results of buffered messages replayed since the last call, in the
+    /// order they were processed.
+    pub fn take_replayed(&mut self, group_id: &str) -> Vec<ProcessedMessage> {
+        self.replayed.remove(group_id).unwrap_or_default()
+    }
+
+    /// Start or stop collecting [`GroupEvent`]s for
+    /// [`take_group_events`](Self::take_group_events), so that an app can
+    /// react to groups being created, joined, changing members or moving
+    /// to a new epoch without comparing group lists. Stopping drops the
+    /// events not taken yet.
+    pub fn watch_group_events(&mut self, watch: bool) -> MlsResult<()> {
+        match (watch, self.group_events.is_some()) {
+            (true, false) => {
+                self.group_events = Some(Vec::new());
+                if let Err(e) = self.remember_members() {
+                    self.group_events = None;
+                    return Err(e);
+                }
+            }
+            (false, true) => {
+                self.group_events = None;
+                self.known_members.clear();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The group events since the last call, oldest first; none unless
+    /// [`watch_group_events`](Self::watch_group_events) is on.
+    pub fn take_group_events(&mut self) -> Vec<GroupEvent> {
+        self.group_events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn buffer_message(&mut self, group_id: &str, epoch: u64, message: &[u8]) {
+        let pending = self.buffered.entry(group_id.to_string()).or_default();
+        tracing::debug!(group_id = %group_id, epoch, "Buffering message from a later epoch");
+        pending.entry(epoch).or_default().push(message.to_vec());
+        if pending.values().map(Vec::len).sum::<usize>() > MAX_BUFFERED_MESSAGES {
+            let mut farthest = pending.last_entry().expect("just inserted");
+            tracing::warn!(group_id = %group_id, epoch = *farthest.key(), "Too many buffered messages, dropping one");
+            farthest.get_mut().remove(0);
+            if farthest.get().is_empty() {
+                farthest.remove();
+            }
+        }
+    }
+
+    /// Process the buffered messages for the group's current epoch, and
+    /// again for each epoch a replayed commit moves it to. Within an epoch
+    /// commits go last, as the other messages were sent before them.
+    fn replay_buffered(&mut self, group_id: &str, stage_commits: bool) {
+        while self.buffered.contains_key(group_id) {
+            let Ok(epoch) = self.group_epoch(group_id) else {
+                // Left or deleted by the commit
+                self.buffered.remove(group_id);
+                return;
+            };
+            let pending = self.buffered.get_mut(group_id).expect("checked above");
+            // Anything older can no longer be processed
+            *pending = pending.split_off(&epoch);
+            let Some(mut messages) = pending.remove(&epoch) else {
+                if pending.is_empty() {
+                    self.buffered.remove(group_id);
+                }
+                return;
+            };
+            messages.sort_by_key(|message| {
+                group::peek_message(message).map(|(_, _, content_type, _)| content_type == ContentType::Commit).ok()
+            });
+            for message in messages {
+                match self.process_in_epoch(group_id, &message, None, stage_commits) {
+                    Ok(processed) => self.replayed.entry(group_id.to_string()).or_default().push(processed),
+                    Err(e) => tracing::warn!(group_id = %group_id, epoch, error = %e, "Dropping buffered message"),
+                }
+            }
+        }
+    }
+
+    /// Process a message, unless `message_epoch` is ahead of the group's:
+    /// then nothing is done and [`ProcessedResult::Buffered`] returned. A
+    /// `message_epoch` behind the group's is refused.
+    fn process_in_epoch(
+        &mut self,
+        group_id: &str,
+        message: &[u8],
+        message_epoch: Option<u64>,
+        stage_commits: bool,
+    ) -> MlsResult<ProcessedMessage> {
+        let (mls_group, processed, left, staged_commit) = self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            let current_epoch = mls_group.epoch().as_u64();
+            if let Some(message_epoch) = message_epoch.filter(|epoch| *epoch < current_epoch) {
+                return Err(MlsError::WrongEpoch { message_epoch, current_epoch });
+            }
+            let buffer_limit = current_epoch.saturating_add(MAX_EPOCHS_AHEAD);
+            if let Some(message_epoch) = message_epoch.filter(|epoch| *epoch > buffer_limit) {
+                return Err(MlsError::WrongEpoch { message_epoch, current_epoch });
+            }
+            if let Some(epoch) = message_epoch.filter(|epoch| *epoch > current_epoch) {
+                let buffered = ProcessedMessage {
+                    result: ProcessedResult::Buffered,
+                    epoch,
+                    sender: None,
+                    sender_identity: None,
+                };
+                return Ok((mls_group, buffered, false, None));
+            }
+            let (result, epoch, sender, identity, staged_commit) =
+                group::process_message(&self.provider, &mut mls_group, message, stage_commits)?;
+            if staged_commit.is_some() && self.staged.contains_key(group_id) {
+                return Err(MlsError::InvalidInput(format!(
+                    "Group '{group_id}' already has a staged commit; merge or reject it first"
+                )));
+            }
+            let left = result == ProcessedResult::Commit && self.delete_if_left(group_id, &mut mls_group)?;
+            let processed = ProcessedMessage {
+                result,
+                epoch,
+                sender: Some(MessageSender::from(&sender)),
+                sender_identity: Some(identity),
+            };
+            Ok::<_, MlsError>((mls_group, processed, left, staged_commit))
+        })?;
+        if let Some(staged_commit) = staged_commit {
+            self.staged.insert(group_id.to_string(), (mls_group.epoch().as_u64(), staged_commit));
+        } else if let ProcessedResult::Commit = processed.result {
+            self.after_commit(group_id, &mls_group, left);
+        }
+        Ok(processed)
+    }
+
+    /// After merging someone else's commit: if it removed us from a group
+    /// we asked to leave, delete the group. Returns whether it did.
+    fn delete_if_left(&self, group_id: &str, mls_group: &mut MlsGroup) -> MlsResult<bool> {
+        let left = !mls_group.is_active() && self.provider.is_leaving(group_id)?;
+        if left {
+            group::delete_group(&self.provider, mls_group)?;
+            self.provider.forget_group_id(group_id)?;
+        }
+        Ok(left)
+    }
+
+    /// Once a merged commit is stored: forget the group if we left it,
+    /// otherwise hand out the new epoch's media key.
+    fn after_commit(&mut self, group_id: &str, mls_group: &MlsGroup, left: bool) {
+        self.record_group_events(group_id, mls_group, None);
+        if left {
+            tracing::info!(group_id = %group_id, "Left group");
+            self.media_key_sinks.remove(group_id);
+            self.buffered.remove(group_id);
+            self.staged.remove(group_id);
+            self.sent_commits.remove(group_id);
+            self.known_members.remove(group_id);
+        } else {
+            self.publish_media_key(group_id, mls_group);
+        }
+    }
+
+    /// Delete a group and everything stored for it, e.g. once we have been
+    /// removed. Other members are not told; leave with
+    /// [`leave_group`](Self::leave_group) first for that.
+    pub fn delete_group(&mut self, group_id: &str) -> MlsResult<()> {
+        self.provider.atomically(|| {
+            let mut mls_group = self.load_group(group_id)?;
+            group::delete_group(&self.provider, &mut mls_group)?;
+            self.provider.forget_group_id(group_id)?;
+            Ok::<_, MlsError>(())
+        })?;
+        tracing::info!(group_id = %group_id, "Deleted group");
+        self.media_key_sinks.remove(group_id);
+        self.buffered.remove(group_id);
+        self.staged.remove(group_id);
+        self.sent_commits.remove(group_id);
+        self.known_members.remove(group_id);
+        Ok(())
+    }
+
+    /// Shrink the database of a long-lived install: delete epoch key pairs
+    /// older than each group's `max_past_epochs`, and key packages past
+    /// their lifetime, then VACUUM. Key packages consumed by a Welcome are
+    /// already deleted when we join. Returns how many
+    /// `(epoch_key_pairs, key_packages)` were deleted. Not pruned on wasm32.
+    pub fn compact(&mut self) -> MlsResult<(usize, usize)> {
+        let pruned = self.provider.atomically(|| {
+            let mut epoch_key_pairs = 0;
+            for group_id in self.provider.list_group_ids()? {
+                let mls_group = self.load_group(&group_id)?;
+                let kept = mls_group.configuration().max_past_epochs() as u64;
+                let oldest_epoch = mls_group.epoch().as_u64().saturating_sub(kept);
+                epoch_key_pairs += self.provider.prune_epoch_key_pairs(mls_group.group_id(), oldest_epoch)?;
+            }
+            let key_packages = self.provider.prune_key_packages()?;
+            Ok::<_, MlsError>((epoch_key_pairs, key_packages))
+        })?;
+        self.provider.vacuum()?;
+        tracing::info!(epoch_key_pairs = pruned.0, key_packages = pruned.1, "Compacted storage");
+        Ok(pruned)
+    }
+
+    /// Database size, each group's epoch, member and pending proposal
+    /// counts, and how many key packages are left, for diagnostics such as
+    /// a bug report.
+    pub fn stats(&self) -> MlsResult<EngineStats> {
+        let groups = self
+            .list_groups()?
+            .into_iter()
+            .map(|group_id| {
+                let mls_group = self.load_group(&group_id)?;
+                Ok(GroupStats {
+                    epoch: mls_group.epoch().as_u64(),
+                    members: mls_group.members().count(),
+                    pending_proposals: mls_group.pending_proposals().count(),
+                    group_id,
+                })
+            })
+            .collect::<MlsResult<_>>()?;
+        let mut key_packages = KeyPackageStats::default();
+        for key_package in self.provider.list_key_packages()? {
+            key_packages.total += 1;
+            key_packages.unused += usize::from(!key_package.consumed);
+            key_packages.last_resort += usize::from(key_package.last_resort);
+        }
+        Ok(EngineStats { database_bytes: self.provider.database_size()?, groups, key_packages })
+    }
+
+    /// Export one group's state — tree, epoch secrets, pending proposals
+    /// and our leaf's keys — so another device holding the same identity
+    /// can take the conversation over with
+    /// [`import_group`](Self::import_group). The output contains private
+    /// key material. Like [`export_state`](Self::export_state) output, it
+    /// only imports on the same kind of platform (SQLite or wasm32).
+    pub fn export_group(&self, group_id: &str) -> MlsResult<Vec<u8>> {
+        let mls_group = self.load_group(group_id)?;
+        let own_leaf = mls_group
+            .own_leaf_node()
+            .ok_or_else(|| MlsError::InvalidInput(format!("We are no longer a member of group '{group_id}'")))?;
+        let rows = self.provider.export_group_rows(mls_group.group_id(), own_leaf.encryption_key())?;
+        let export = GroupExport {
+            format: GROUP_EXPORT_FORMAT.to_string(),
+            version: GROUP_EXPORT_VERSION,
+            group_id: group_id.to_string(),
+            rows,
+        };
+        serde_json::to_vec(&export).map_err(|e| MlsError::Failed(format!("Failed to serialize group: {e}")))
+    }
+
+    /// Import a group from [`export_group`](Self::export_group) output,
+    /// replacing any state stored for it, and return its ID. The group's
+    /// leaf must belong to this engine's identity: a device with an
+    /// identity of its own joins instead. The input must come from a
+    /// trusted source.
+    pub fn import_group(&mut self, data: &[u8]) -> MlsResult<String> {
+        let (_, sig) = self.require_identity()?;
+        let export: GroupExport = serde_json::from_slice(data)
+            .map_err(|e| MlsError::InvalidInput(format!("Failed to parse group export: {e}")))?;
+        if export.format != GROUP_EXPORT_FORMAT || !(1..=GROUP_EXPORT_VERSION).contains(&export.version) {
+            return Err(MlsError::InvalidInput(format!(
+                "Unsupported group export {:?} version {}",
+                export.format, export.version
+            )));
+        }
+        let group_id = export.group_id;
+        let rows = export.rows.upgrade(export.version).map_err(MlsError::InvalidInput)?;
+        let gid = GroupId::from_slice(group_id.as_bytes());
+        let mls_group = self.provider.atomically(|| {
+            self.provider.import_group_rows(&group_id, &gid, &rows)?;
+            let mls_group = self.load_group(&group_id)?;
+            let own_key = mls_group.own_leaf_node().map(|leaf| leaf.signature_key().as_slice());
+            if own_key != Some(sig.public()) {
+                return Err(MlsError::InvalidInput(format!("Group '{group_id}' belongs to another identity")));
+            }
+            Ok(mls_group)
+        })?;
+        self.publish_media_key(&group_id, &mls_group);
+        self.record_group_events(&group_id, &mls_group, Some(GroupEventKind::Joined));
+        Ok(group_id)
+    }
+
+    /// Read which group and epoch a message is for, its content type and,
+    /// for unencrypted messages, its sender, without processing it. Use it
+    /// to route and order messages before
+    /// [`process_message`](Self::process_message).
+    pub fn peek_message(&self, message: &[u8]) -> MlsResult<MessageInfo> {
+        let (group_id, epoch, content_type, sender) =
+            group::peek_message(message).map_err(MlsError::InvalidInput)?;
+        let content_type = match content_type {
+            ContentType::Application => "application",
+            ContentType::Proposal => "proposal",
+            ContentType::Commit => "commit",
+        };
+        let sender = sender.as_ref().map(MessageSender::from);
+        Ok(MessageInfo { group_id: group_id_string(&group_id), epoch, content_type, sender })
+    }
+
+    /// Encrypt plaintext into an MLS application message.
+    pub fn encrypt(&mut self, group_id: &str, plaintext: &[u8]) -> MlsResult<Vec<u8>> {
+        let mut mls_group = self.load_group(group_id)?;
+        let sig = self.group_signer(&mls_group)?;
+        Ok(group::encrypt(&self.provider, &mut mls_group, &sig, plaintext)?)
+    }
+
+    /// Decrypt an MLS application message.
+    /// Convenience wrapper around process_message that returns just the plaintext.
+    pub fn decrypt(&mut self, group_id: &str, ciphertext: &[u8]) -> MlsResult<Vec<u8>> {
+        match self.process_message(group_id, ciphertext)?.result {
+            ProcessedResult::Application(plaintext) => Ok(plaintext),
+            ProcessedResult::Buffered => Err(MlsError::InvalidInput(
+                "Message is from a later epoch; it was buffered and will be replayed".into(),
+            )),
+            _ => Err(MlsError::InvalidInput("Message is not an application message".into())),
+        }
+    }
+
+    /// Feed this group's media keys to a media client.
+    ///
+    /// The current epoch's key is delivered now, and each new epoch's once
+    /// the commit starting it is created or processed here. The sink is
+    /// dropped once it reports it no longer wants keys.
+    pub fn attach_media_key_sink(&mut self, group_id: &str, sink: KeySink) -> MlsResult<()> {
+        let mls_group = self.load_group(group_id)?;
+        let (epoch, key) = group::export_media_key(&self.provider, &mls_group)?;
+        if sink.set_key(epoch, &key) {
+            self.media_key_sinks.entry(group_id.to_string()).or_default().push(sink);
+        }
+        Ok(())
+    }
+
+    /// Export the key for a file attachment at the group's current epoch.
+    ///
+    /// If `epoch` is given and the group has moved on, fails with
+    /// [`MlsError::InvalidInput`]: MLS cannot export secrets for past epochs.
+    /// Returns `(epoch, key)`.
+    pub fn export_file_key(&self, group_id: &str, file_id: &[u8], epoch: Option<u64>) -> MlsResult<(u64, Vec<u8>)> {
+        let mls_group = self.load_group(group_id)?;
+        let current = mls_group.epoch().as_u64();
+        if let Some(epoch) = epoch.filter(|e| *e != current) {
+            return Err(MlsError::InvalidInput(format!(
+                "Group '{group_id}' is at epoch {current}; the file key is from epoch {epoch}"
+            )));
+        }
+        Ok(group::export_file_key(&self.provider, &mls_group, file_id)?)
+    }
+
+    /// The members of a group as `(leaf_index, credential_identity,
+    /// signature_public_key)`, ordered by leaf index. Identities are
+    /// `"<user_id>:<device_id>"` as passed to
+    /// [`remove_member`](Self::remove_member).
+    pub fn list_members(&self, group_id: &str) -> MlsResult<Vec<(u32, Vec<u8>, Vec<u8>)>> {
+        let mls_group = self.load_group(group_id)?;
+        Ok(group::members(&mls_group))
+    }
+
+    /// The group's current epoch.
+    pub fn group_epoch(&self, group_id: &str) -> MlsResult<u64> {
+        Ok(self.load_group(group_id)?.epoch().as_u64())
+    }
+
+    /// The current epoch's authenticator. Members in the same epoch with
+    /// the same view of the group have the same one, so comparing it out
+    /// of band verifies the group.
+    pub fn epoch_authenticator(&self, group_id: &str) -> MlsResult<Vec<u8>> {
+        Ok(self.load_group(group_id)?.epoch_authenticator().as_slice().to_vec())
+    }
+
+    /// The safety number of the member with this credential identity
+    /// (`"<user_id>:<device_id>"`), as they see it with
+    /// [`own_fingerprint`](Self::own_fingerprint). If the two match when
+    /// compared out of band, the group has their real signature key.
+    pub fn member_fingerprint(&self, group_id: &str, member_identity: &str) -> MlsResult<String> {
+        let (_, identity, signature_key) = group::members(&self.load_group(group_id)?)
+            .into_iter()
+            .find(|(_, identity, _)| identity == member_identity.as_bytes())
+            .ok_or_else(|| MlsError::InvalidInput(format!("Member '{member_identity}' not found in group")))?;
+        Ok(identity::fingerprint(&self.provider, &identity, &signature_key)?)
+    }
+
+    /// Our own safety number: 60 digits from our credential and signature
+    /// key, the same in every group. See
+    /// [`member_fingerprint`](Self::member_fingerprint).
+    pub fn own_fingerprint(&self) -> MlsResult<String> {
+        let (cwk, sig) = self.require_identity()?;
+        let identity = cwk.credential.serialized_content();
+        Ok(identity::fingerprint(&self.provider, identity, sig.public())?)
+    }
+
+    /// Check if a group exists in storage.
+    pub fn group_exists(&self, group_id: &str) -> bool {
+        let gid = GroupId::from_slice(group_id.as_bytes());
+        MlsGroup::load(self.provider.storage(), &gid)
+            .map(|opt| opt.is_some())
+            .unwrap_or(false)
+    }
+
+    /// List all group IDs managed by this engine.
+    pub fn list_groups(&self) -> MlsResult<Vec<String>> {
+        Ok(self.provider.list_group_ids()?)
+    }
+
+    /// The public identity key bytes, or None if not initialized.
+    pub fn identity_key(&self) -> Option<Vec<u8>> {
+        self.signature_keys.as_ref().map(|sk| sk.to_public_vec())
+    }
+
+    /// The stored identity metadata `(user_id, device_id)`, or None if no
+    /// identity is stored.
+    pub fn stored_identity(&self) -> MlsResult<Option<(u64, String)>> {
+        match self.provider.load_identity() {
+            Ok(identity) => Ok(identity.map(|(user_id, device_id, _, _)| (user_id, device_id))),
+            Err(e) => Err(MlsError::Failed(format!("Failed to load stored identity: {e}"))),
+        }
+    }
+
+    /// Close the database, flushing its write-ahead log, instead of
+    /// leaving it open until the engine is dropped; open it again with
+    /// [`open`](Self::open). Media clients attached to groups stop
+    /// receiving keys.
+    pub fn close(self) -> MlsResult<()> {
+        Ok(self.provider.close()?)
+    }
+
+    /// Export the full MLS state (identity + all groups) as raw SQLite
+    /// database bytes (a key-value snapshot on wasm32). They contain
+    /// private key material; encrypt them before persisting or
+    /// transmitting.
+    pub fn export_state(&self) -> MlsResult<Vec<u8>> {
+        Ok(self.provider.export_db()?)
+    }
+
+    /// Restore full MLS state from raw SQLite database bytes, replacing
+    /// everything in the current database and reloading the identity.
+    pub fn import_state(&mut self, data: &[u8]) -> MlsResult<()> {
+        self.provider.import_db(data)?;
+        // Whatever was pending for other identities is for the old state
+        self.parked.clear();
+
+        match self.provider.load_identity() {
+            Ok(Some((_user_id, _device_id, cwk_json, sig_json))) => {
+                self.restore_identity(&cwk_json, &sig_json, "restored")?;
+                self.recover()?;
+                self.remember_members()
+            }
+            Ok(None) => Err(MlsError::InvalidInput("Backup does not contain identity data".into())),
+            Err(e) => Err(MlsError::Failed(format!("Failed to load identity from backup: {e}"))),
+        }
+    }
+
+    /// [`export_state`](Self::export_state), encrypted with a key derived
+    /// from `passphrase` (Argon2id, AES-256-GCM), so it can be stored
+    /// somewhere untrusted such as a cloud backup.
+    pub fn export_state_encrypted(&self, passphrase: &str) -> MlsResult<Vec<u8>> {
+        if passphrase.is_empty() {
+            return Err(MlsError::InvalidInput("Passphrase must not be empty".into()));
+        }
+        Ok(backup::seal(passphrase, &self.export_state()?)?)
+    }
+
+    /// Restore a backup made by
+    /// [`export_state_encrypted`](Self::export_state_encrypted). A wrong
+    /// passphrase fails with [`MlsError::InvalidInput`] and leaves the
+    /// current state untouched.
+    pub fn import_state_encrypted(&mut self, data: &[u8], passphrase: &str) -> MlsResult<()> {
+        let state = backup::open(passphrase, data).map_err(MlsError::InvalidInput)?;
+        self.import_state(&state)
+    }
+
+    /// Export the identity only (private + public key material), unencrypted.
+    /// Use [`export_state`](Self::export_state) for a full backup.
+    pub fn export_identity(&self) -> MlsResult<Vec<u8>> {
+        let (cwk, sig) = self.require_identity()?;
+        let payload = serde_json::json!({
+            "signature_keys": sig,
+            "credential_with_key": cwk,
+        });
+        serde_json::to_vec(&payload).map_err(|e| MlsError::Failed(format!("{e:?}")))
+    }
+
+    /// Import a previously exported identity and persist it, so it survives
+    /// engine restarts. The input must come from a trusted source.
+    pub fn import_identity(&mut self, data: &[u8], user_id: u64, device_id: &str) -> MlsResult<()> {
+        let invalid = |e: serde_json::Error| MlsError::InvalidInput(format!("{e:?}"));
+        let payload: serde_json::Value = serde_json::from_slice(data).map_err(invalid)?;
+        let field = |name: &str| {
+            payload
+                .get(name)
+                .cloned()
+                .ok_or_else(|| MlsError::InvalidInput(format!("Missing {name}")))
+        };
+        let sig: SignatureKeyPair = serde_json::from_value(field("signature_keys")?).map_err(invalid)?;
+        let cwk: CredentialWithKey = serde_json::from_value(field("credential_with_key")?).map_err(invalid)?;
+
+        sig.store(self.provider.storage()).map_err(|e| MlsError::Failed(format!("{e:?}")))?;
+        self.save_identity(user_id, device_id, &cwk, &sig)?;
+
+        self.signature_keys = Some(sig);
+        self.credential_with_key = Some(cwk);
+        Ok(())
+    }
+
+    fn require_identity(&self) -> MlsResult<(&CredentialWithKey, &SignatureKeyPair)> {
+        match (&self.credential_with_key, &self.signature_keys) {
+            (Some(cwk), Some(sig)) => Ok((cwk, sig)),
+            _ => Err(MlsError::Failed(
+                "Identity not initialized — call generate_identity() first".into(),
+            )),
+        }
+    }
+
+    /// The signature keys of our leaf in a group: the current identity's,
+    /// or older ones from before [`rotate_credential`](Self::rotate_credential)
+    /// in another group.
+    fn group_signer(&self, mls_group: &MlsGroup) -> MlsResult<Cow<'_, SignatureKeyPair>> {
+        let (_, sig) = self.require_identity()?;
+        let Some(leaf_key) = mls_group.own_leaf_node().map(|leaf| leaf.signature_key().as_slice()) else {
+            return Ok(Cow::Borrowed(sig));
+        };
+        if leaf_key == sig.public() {
+            return Ok(Cow::Borrowed(sig));
+        }
+        SignatureKeyPair::read(self.provider.storage(), leaf_key, sig.signature_scheme())
+            .map(Cow::Owned)
+            .ok_or_else(|| MlsError::Failed("Signature keys of our leaf are missing".into()))
+    }
+
+    /// Persist an identity to the vox_identity table.
+    fn save_identity(
+        &self,
+        user_id: u64,
+        device_id: &str,
+        cwk: &CredentialWithKey,
+        sig: &SignatureKeyPair,
+    ) -> MlsResult<()> {
+        let cwk_json = serde_json::to_string(cwk).map_err(|e| MlsError::Failed(format!("{e:?}")))?;
+        let sig_json = serde_json::to_string(sig).map_err(|e| MlsError::Failed(format!("{e:?}")))?;
+        Ok(self.provider.save_identity(user_id, device_id, &cwk_json, &sig_json)?)
+    }
+
+    /// Adopt an identity read back from the database. `origin` ("stored" or
+    /// "restored") goes into error messages.
+    fn restore_identity(&mut self, cwk_json: &str, sig_json: &str, origin: &str) -> MlsResult<()> {
+        let (cwk, sig) = self.read_identity(cwk_json, sig_json, origin)?;
+        self.credential_with_key = Some(cwk);
+        self.signature_keys = Some(sig);
+        Ok(())
+    }
+
+    fn read_identity(
+        &self,
+        cwk_json: &str,
+        sig_json: &str,
+        origin: &str,
+    ) -> MlsResult<(CredentialWithKey, SignatureKeyPair)> {
+        let cwk: CredentialWithKey = serde_json::from_str(cwk_json)
+            .map_err(|e| MlsError::Failed(format!("Failed to deserialize {origin} credential: {e:?}")))?;
+        let sig: SignatureKeyPair = serde_json::from_str(sig_json)
+            .map_err(|e| MlsError::Failed(format!("Failed to deserialize {origin} signature keys: {e:?}")))?;
+
+        // Re-store the signature key pair in the storage provider so OpenMLS can find it
+        sig.store(self.provider.storage())
+            .map_err(|e| MlsError::Failed(format!("Failed to re-store signature keys: {e:?}")))?;
+        Ok((cwk, sig))
+    }
+
+    /// The current identity as stored in the database, if there is one,
+    /// after repairing its groups (see [`recover`](Self::recover)).
+    fn load_stored_identity(&self) -> MlsResult<Option<(CredentialWithKey, SignatureKeyPair)>> {
+        let identity = match self.provider.load_identity() {
+            Ok(Some((_user_id, _device_id, cwk_json, sig_json))) => {
+                Some(self.read_identity(&cwk_json, &sig_json, "stored")?)
+            }
+            Ok(None) => None,
+            Err(e) => return Err(MlsError::Failed(format!("Failed to load identity from database: {e}"))),
+        };
+        self.recover()?;
+        Ok(identity)
+    }
+
+    /// Repair what a crash in the middle of a multi-step operation can have
+    /// left behind: tracked groups whose state is missing are forgotten,
+    /// and commits created but never merged are discarded, as they were
+    /// never handed out to be sent.
+    fn recover(&self) -> MlsResult<()> {
+        for group_id in self.provider.list_group_ids()? {
+            let gid = GroupId::from_slice(group_id.as_bytes());
+            let loaded = MlsGroup::load(self.provider.storage(), &gid)
+                .map_err(|e| MlsError::Failed(format!("Failed to load group '{group_id}': {e:?}")))?;
+            let Some(mut mls_group) = loaded else {
+                tracing::warn!(group_id = %group_id, "Forgetting group with no stored state");
+                self.provider.forget_group_id(&group_id)?;
+                continue;
+            };
+            if mls_group.pending_commit().is_some() {
+                tracing::warn!(group_id = %group_id, "Discarding unmerged commit");
+                mls_group
+                    .clear_pending_commit(self.provider.storage())
+                    .map_err(|e| MlsError::Failed(format!("Failed to clear pending commit: {e:?}")))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop what is kept in memory about a group's past epochs, once it has
+    /// been replaced by a new group of the same id.
+    fn forget_epochs(&mut self, group_id: &str) {
+        self.media_key_sinks.remove(group_id);
+        self.buffered.remove(group_id);
+        self.replayed.remove(group_id);
+        self.staged.remove(group_id);
+        self.sent_commits.remove(group_id);
+    }
+
+    /// While group events are watched, note the members of every group, to
+    /// report changes against.
+    fn remember_members(&mut self) -> MlsResult<()> {
+        if self.group_events.is_none() {
+            return Ok(());
+        }
+        self.known_members = self
+            .list_groups()?
+            .into_iter()
+            .map(|group_id| {
+                let members = member_identities(&self.load_group(&group_id)?);
+                Ok((group_id, members))
+            })
+            .collect::<MlsResult<_>>()?;
+        Ok(())
+    }
+
+    /// While group events are watched, queue `started`, if the group was
+    /// just created or joined, and otherwise the members a commit added
+    /// and removed followed by the new epoch.
+    fn record_group_events(&mut self, group_id: &str, mls_group: &MlsGroup, started: Option<GroupEventKind>) {
+        let Some(events) = self.group_events.as_mut() else {
+            return;
+        };
+        let epoch = mls_group.epoch().as_u64();
+        let event = |kind| GroupEvent { group_id: group_id.to_string(), epoch, kind };
+        let members = member_identities(mls_group);
+        let before = match started {
+            // A new group starts out with just us
+            Some(GroupEventKind::Created) => {
+                let own_leaf = mls_group.own_leaf_index().u32();
+                members.iter().filter(|(leaf_index, _)| *leaf_index == own_leaf).cloned().collect()
+            }
+            Some(_) => members.clone(),
+            None => self.known_members.remove(group_id).unwrap_or_else(|| members.clone()),
+        };
+        let advanced = started.is_none();
+        events.extend(started.map(event));
+        for (leaf_index, identity) in before.iter().filter(|member| !members.contains(member)) {
+            events.push(event(GroupEventKind::MemberRemoved { leaf_index: *leaf_index, identity: identity.clone() }));
+        }
+        for (leaf_index, identity) in members.iter().filter(|member| !before.contains(member)) {
+            events.push(event(GroupEventKind::MemberAdded { leaf_index: *leaf_index, identity: identity.clone() }));
+        }
+        if advanced {
+            events.push(event(GroupEventKind::EpochAdvanced));
+        }
+        self.known_members.insert(group_id.to_string(), members);
+    }
+
+    /// Push the group's current media key to attached media clients,
+    /// forgetting clients that no longer want keys.
+    fn publish_media_key(&mut self, group_id: &str, mls_group: &MlsGroup) {
+        let Some(sinks) = self.media_key_sinks.get_mut(group_id) else {
+            return;
+        };
+        // Best effort: the commit itself has already been applied
+        if let Ok((epoch, key)) = group::export_media_key(&self.provider, mls_group) {
+            sinks.retain(|sink| sink.set_key(epoch, &key));
+        }
+        if sinks.is_empty() {
+            self.media_key_sinks.remove(group_id);
+        }
+    }
+
+    /// Load a group from SQLite storage by group ID.
+    fn load_group(&self, group_id: &str) -> MlsResult<MlsGroup> {
+        let _timer = profile::LOAD_GROUP.start();
+        let gid = GroupId::from_slice(group_id.as_bytes());
+        MlsGroup::load(self.provider.storage(), &gid)
+            .map_err(|e| MlsError::Failed(format!("Failed to load group '{group_id}': {e:?}")))?
+            .ok_or_else(|| MlsError::UnknownGroup(group_id.to_string()))
+    }
+}
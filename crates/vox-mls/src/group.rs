@@ -1,26 +1,116 @@
 use openmls::framing::MlsMessageBodyIn;
+use openmls::group::NewSignerBundle;
 use openmls::messages::Welcome;
 use openmls::prelude::*;
 use openmls_basic_credential::SignatureKeyPair;
+use openmls_traits::crypto::OpenMlsCrypto;
+use openmls_traits::storage::StorageProvider;
+use openmls_traits::types::HashType;
 use tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
 
-use crate::identity::CIPHERSUITE;
+use vox_core::files::{FILE_KEY_LABEL, FILE_KEY_LEN};
+
+use crate::identity;
+use crate::profile;
 use crate::provider::VoxProvider;
 
+/// How to create a group. The default uses the engine's ciphersuite, no
+/// extensions, and puts the ratchet tree in Welcomes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupOptions {
+    /// Overrides the engine's; the members' key packages must match it.
+    pub ciphersuite: Option<Ciphersuite>,
+    /// Application-defined GroupContext extensions as `(type, data)`, e.g.
+    /// the server's room id. Our leaf advertises their types; other
+    /// members need them in their key packages' capabilities.
+    pub extensions: Vec<(u16, Vec<u8>)>,
+    /// Leave the ratchet tree out of Welcome messages, which then only
+    /// join with the tree passed along separately.
+    pub omit_ratchet_tree: bool,
+    /// Pad our application messages to a multiple of this many bytes, so
+    /// the server does not learn plaintext lengths; 0 for no padding.
+    pub padding_size: usize,
+    /// Application-defined extension types every member's key package must
+    /// list in its capabilities, in the group's RequiredCapabilities
+    /// extension. Key packages without them cannot be added. The
+    /// ciphersuite needs no such entry: key packages must already be for
+    /// the group's.
+    pub required_extensions: Vec<u16>,
+    /// Credential types every member must support, e.g. 1 for Basic.
+    pub required_credentials: Vec<u16>,
+}
+
+/// How to join a group, by Welcome or external commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinOptions {
+    /// As [`GroupOptions::padding_size`].
+    pub padding_size: usize,
+}
+
 /// Create a new MLS group with the given group ID, optionally adding initial members.
+/// Their key packages must be for the group's `ciphersuite`, which takes
+/// the place of `options.ciphersuite`.
+#[tracing::instrument(
+    name = "mls.create_group",
+    skip_all,
+    fields(group_id = group_id, members = member_key_packages.len()),
+    err
+)]
 pub fn create_group(
     provider: &VoxProvider,
     signature_keys: &SignatureKeyPair,
     credential_with_key: &CredentialWithKey,
     group_id: &str,
     member_key_packages: &[KeyPackageIn],
+    ciphersuite: Ciphersuite,
+    options: &GroupOptions,
 ) -> Result<(MlsGroup, Option<MlsMessageOut>, Option<MlsMessageOut>), String> {
     let gid = GroupId::from_slice(group_id.as_bytes());
 
-    let config = MlsGroupCreateConfig::builder()
-        .ciphersuite(CIPHERSUITE)
-        .use_ratchet_tree_extension(true)
-        .build();
+    let mut builder = MlsGroupCreateConfig::builder()
+        .ciphersuite(ciphersuite)
+        .use_ratchet_tree_extension(!options.omit_ratchet_tree)
+        .padding_size(options.padding_size);
+    let mut extensions = identity::custom_extensions(&options.extensions)?;
+    let required_types = options
+        .required_extensions
+        .iter()
+        .map(|t| identity::custom_extension_type(*t))
+        .collect::<Result<Vec<_>, String>>()?;
+    let required_credentials: Vec<CredentialType> =
+        options.required_credentials.iter().map(|t| CredentialType::from(*t)).collect();
+    if !required_types.is_empty() || !required_credentials.is_empty() {
+        extensions.push(Extension::RequiredCapabilities(RequiredCapabilitiesExtension::new(
+            &required_types,
+            &[],
+            &required_credentials,
+        )));
+    }
+    if !extensions.is_empty() {
+        // Our own leaf has to support what the group uses and requires
+        let mut types = options
+            .extensions
+            .iter()
+            .map(|(t, _)| identity::custom_extension_type(*t))
+            .collect::<Result<Vec<_>, String>>()?;
+        for t in &required_types {
+            if !types.contains(t) {
+                types.push(*t);
+            }
+        }
+        let mut credentials = required_credentials;
+        if !credentials.is_empty() && !credentials.contains(&CredentialType::Basic) {
+            credentials.push(CredentialType::Basic);
+        }
+        let credentials = (!credentials.is_empty()).then_some(credentials.as_slice());
+        let extensions =
+            Extensions::from_vec(extensions).map_err(|e| format!("Invalid group context extensions: {e:?}"))?;
+        builder = builder
+            .capabilities(Capabilities::new(None, None, Some(&types), None, credentials))
+            .with_group_context_extensions(extensions)
+            .map_err(|e| format!("Invalid group context extensions: {e:?}"))?;
+    }
+    let config = builder.build();
 
     let mut group = MlsGroup::new_with_group_id(
         provider,
@@ -42,12 +132,15 @@ pub fn create_group(
     let validated: Vec<KeyPackage> = member_key_packages
         .iter()
         .map(|kp_in| {
-            kp_in
+            let kp = kp_in
                 .clone()
                 .validate(provider.crypto(), ProtocolVersion::Mls10)
-                .map_err(|e| format!("Invalid key package: {e:?}"))
+                .map_err(|e| format!("Invalid key package: {e:?}"))?;
+            check_ciphersuite(&kp, ciphersuite)?;
+            check_required_capabilities(&kp, &group)?;
+            Ok(kp)
         })
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<Result<Vec<_>, String>>()?;
 
     let (commit, welcome, _group_info) = group
         .add_members(provider, signature_keys, &validated)
@@ -60,10 +153,96 @@ pub fn create_group(
     Ok((group, Some(welcome), Some(commit)))
 }
 
-/// Join a group from a serialized MLS Welcome message.
+/// Refuse a key package for another ciphersuite than the group's, which
+/// OpenMLS would only report as a generic error.
+fn check_ciphersuite(key_package: &KeyPackage, ciphersuite: Ciphersuite) -> Result<(), String> {
+    if key_package.ciphersuite() != ciphersuite {
+        return Err(format!(
+            "Key package is for {:?}, but the group uses {ciphersuite:?}",
+            key_package.ciphersuite()
+        ));
+    }
+    Ok(())
+}
+
+/// Refuse a key package lacking a capability the group requires, before
+/// OpenMLS fails the commit adding it.
+fn check_required_capabilities(key_package: &KeyPackage, group: &MlsGroup) -> Result<(), String> {
+    let Some(required) = group.extensions().required_capabilities() else {
+        return Ok(());
+    };
+    let capabilities = key_package.leaf_node().capabilities();
+    if let Some(missing) = required.extension_types().iter().find(|t| !capabilities.extensions().contains(t)) {
+        return Err(format!(
+            "Key package does not support extension type {}, which the group requires",
+            u16::from(*missing)
+        ));
+    }
+    if let Some(missing) = required.credential_types().iter().find(|t| !capabilities.credentials().contains(t)) {
+        return Err(format!(
+            "Key package does not support credential type {}, which the group requires",
+            u16::from(*missing)
+        ));
+    }
+    Ok(())
+}
+
+/// The group's required extension and credential types, as set by
+/// [`GroupOptions::required_extensions`] and
+/// [`GroupOptions::required_credentials`].
+pub fn required_capabilities(group: &MlsGroup) -> (Vec<u16>, Vec<u16>) {
+    group
+        .extensions()
+        .required_capabilities()
+        .map(|required| {
+            (
+                required.extension_types().iter().map(|t| u16::from(*t)).collect(),
+                required.credential_types().iter().map(|t| u16::from(*t)).collect(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Join a group from a serialized MLS Welcome message, replacing any
+/// state held for a group of the same id. `ratchet_tree` is needed if the
+/// Welcome does not carry the tree.
 ///
-/// Accepts either a raw Welcome or an MlsMessage-wrapped Welcome.
-pub fn join_group(provider: &VoxProvider, welcome_bytes: &[u8]) -> Result<MlsGroup, String> {
+/// Accepts either a raw Welcome or an MlsMessage-wrapped Welcome. Also
+/// returns the hash reference of the key package it was for.
+#[tracing::instrument(name = "mls.join_group", skip_all, err)]
+pub fn join_group(
+    provider: &VoxProvider,
+    welcome_bytes: &[u8],
+    ratchet_tree: Option<&[u8]>,
+    options: &JoinOptions,
+) -> Result<(MlsGroup, Option<Vec<u8>>), String> {
+    let (staged, key_package_ref) = stage_welcome(provider, welcome_bytes, ratchet_tree, options)?;
+
+    // A re-initialized group keeps its id: drop the state of the old one
+    if let Some(mut stale) = MlsGroup::load(provider.storage(), staged.group_context().group_id())
+        .map_err(|e| format!("Failed to load group: {e:?}"))?
+    {
+        stale
+            .delete(provider.storage())
+            .map_err(|e| format!("Failed to delete stale group: {e:?}"))?;
+    }
+
+    let group = staged
+        .into_group(provider)
+        .map_err(|e| format!("Failed to create group from welcome: {e:?}"))?;
+
+    Ok((group, key_package_ref.map(|r| r.as_slice().to_vec())))
+}
+
+/// Decrypt a Welcome with one of our key packages, ready to join, and
+/// say which key package that was. This consumes the key package unless
+/// the caller rolls the storage back.
+fn stage_welcome(
+    provider: &VoxProvider,
+    welcome_bytes: &[u8],
+    ratchet_tree: Option<&[u8]>,
+    options: &JoinOptions,
+) -> Result<(StagedWelcome, Option<KeyPackageRef>), String> {
     // Try deserializing as MlsMessageIn (the MlsMessageOut envelope format)
     let welcome = if let Ok(msg_in) = MlsMessageIn::tls_deserialize_exact(welcome_bytes) {
         match msg_in.extract() {
@@ -76,21 +255,167 @@ pub fn join_group(provider: &VoxProvider, welcome_bytes: &[u8]) -> Result<MlsGro
             .map_err(|e| format!("Failed to deserialize welcome: {e:?}"))?
     };
 
+    // A group sending its tree separately keeps it out of our Welcomes too
     let join_config = MlsGroupJoinConfig::builder()
-        .use_ratchet_tree_extension(true)
+        .use_ratchet_tree_extension(ratchet_tree.is_none())
+        .padding_size(options.padding_size)
         .build();
 
-    let staged = StagedWelcome::new_from_welcome(provider, &join_config, welcome, None)
+    let ratchet_tree = ratchet_tree
+        .map(|bytes| {
+            RatchetTreeIn::tls_deserialize_exact(bytes)
+                .map_err(|e| format!("Failed to deserialize ratchet tree: {e:?}"))
+        })
+        .transpose()?;
+
+    // Looked up before staging, which deletes all but last resort ones
+    let key_package_ref = welcome.secrets().iter().map(|secrets| secrets.new_member()).find(|hash_ref| {
+        matches!(provider.storage().key_package::<_, KeyPackageBundle>(hash_ref), Ok(Some(_)))
+    });
+
+    let staged = StagedWelcome::new_from_welcome(provider, &join_config, welcome, ratchet_tree)
         .map_err(|e| format!("Failed to stage welcome: {e:?}"))?;
+    Ok((staged, key_package_ref))
+}
 
-    let group = staged
-        .into_group(provider)
-        .map_err(|e| format!("Failed to create group from welcome: {e:?}"))?;
+/// Read a Welcome's group id, ciphersuite and the credential identity of
+/// the member who sent it. Run it with the storage rolled back afterwards,
+/// or the key package it was for is used up.
+pub fn inspect_welcome(
+    provider: &VoxProvider,
+    welcome_bytes: &[u8],
+    ratchet_tree: Option<&[u8]>,
+) -> Result<(GroupId, Ciphersuite, Vec<u8>), String> {
+    let (staged, _) = stage_welcome(provider, welcome_bytes, ratchet_tree, &JoinOptions::default())?;
+    let sender = staged
+        .welcome_sender()
+        .map_err(|e| format!("Failed to find the welcome's sender: {e:?}"))?
+        .credential()
+        .serialized_content()
+        .to_vec();
+    let context = staged.group_context();
+    Ok((context.group_id().clone(), context.ciphersuite(), sender))
+}
 
-    Ok(group)
+/// Export the group's signed GroupInfo for others to join by external
+/// commit, optionally carrying the ratchet tree as an extension.
+pub fn export_group_info(
+    provider: &VoxProvider,
+    group: &MlsGroup,
+    signature_keys: &SignatureKeyPair,
+    with_ratchet_tree: bool,
+) -> Result<MlsMessageOut, String> {
+    group
+        .export_group_info(provider.crypto(), signature_keys, with_ratchet_tree)
+        .map_err(|e| format!("Failed to export group info: {e:?}"))
+}
+
+/// Join a group from its serialized GroupInfo without a Welcome, by
+/// committing our own addition. Returns the group and the external commit
+/// for its members.
+#[tracing::instrument(name = "mls.join_by_external_commit", skip_all, err)]
+pub fn join_by_external_commit(
+    provider: &VoxProvider,
+    signature_keys: &SignatureKeyPair,
+    credential_with_key: &CredentialWithKey,
+    group_info_bytes: &[u8],
+    options: &JoinOptions,
+) -> Result<(MlsGroup, MlsMessageOut), String> {
+    let msg_in = MlsMessageIn::tls_deserialize_exact(group_info_bytes)
+        .map_err(|e| format!("Failed to deserialize group info: {e:?}"))?;
+    let MlsMessageBodyIn::GroupInfo(group_info) = msg_in.extract() else {
+        return Err("MLS message is not a GroupInfo".to_string());
+    };
+
+    // Drop stale state left from before, so none of it mixes with the new
+    if let Some(mut stale) = MlsGroup::load(provider.storage(), group_info.group_id())
+        .map_err(|e| format!("Failed to load group: {e:?}"))?
+    {
+        stale
+            .delete(provider.storage())
+            .map_err(|e| format!("Failed to delete stale group: {e:?}"))?;
+    }
+
+    let join_config = MlsGroupJoinConfig::builder()
+        .use_ratchet_tree_extension(true)
+        .padding_size(options.padding_size)
+        .build();
+
+    // The ratchet tree comes from the GroupInfo's extension
+    let (group, bundle) = MlsGroup::external_commit_builder()
+        .with_config(join_config)
+        .build_group(provider, group_info, credential_with_key.clone())
+        .map_err(|e| format!("Failed to stage external commit: {e:?}"))?
+        .load_psks(provider.storage())
+        .map_err(|e| format!("Failed to load PSKs: {e:?}"))?
+        .build(provider.rand(), provider.crypto(), signature_keys, |_| true)
+        .map_err(|e| format!("Failed to build external commit: {e:?}"))?
+        .finalize(provider)
+        .map_err(|e| format!("Failed to join by external commit: {e:?}"))?;
+
+    Ok((group, bundle.into_commit()))
+}
+
+/// Re-create a group under `ciphersuite`, keeping its id, members and
+/// application-defined GroupContext extensions. `member_key_packages` must
+/// be for `ciphersuite` and for exactly the other members. The old group
+/// is deleted; returns the new one and the Welcome moving the others to it.
+#[tracing::instrument(name = "mls.reinit_group", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn reinit_group(
+    provider: &VoxProvider,
+    signature_keys: &SignatureKeyPair,
+    credential_with_key: &CredentialWithKey,
+    group: &mut MlsGroup,
+    group_id: &str,
+    member_key_packages: &[KeyPackageIn],
+    ciphersuite: Ciphersuite,
+) -> Result<(MlsGroup, Option<MlsMessageOut>), String> {
+    let own_index = group.own_leaf_index();
+    let mut members: Vec<Vec<u8>> = group
+        .members()
+        .filter(|m| m.index != own_index)
+        .map(|m| m.credential.serialized_content().to_vec())
+        .collect();
+    let mut joining = member_key_packages
+        .iter()
+        .map(|kp_in| {
+            let kp = kp_in
+                .clone()
+                .validate(provider.crypto(), ProtocolVersion::Mls10)
+                .map_err(|e| format!("Invalid key package: {e:?}"))?;
+            Ok(kp.leaf_node().credential().serialized_content().to_vec())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    members.sort();
+    joining.sort();
+    if members != joining {
+        return Err("Key packages must be for exactly the group's other members".to_string());
+    }
+
+    let (required_extensions, required_credentials) = required_capabilities(group);
+    let options = GroupOptions {
+        ciphersuite: Some(ciphersuite),
+        extensions: context_extensions(group),
+        omit_ratchet_tree: !group.configuration().use_ratchet_tree_extension(),
+        padding_size: group.configuration().padding_size(),
+        required_extensions,
+        required_credentials,
+    };
+    delete_group(provider, group)?;
+    let (new_group, welcome, _commit) = create_group(
+        provider,
+        signature_keys,
+        credential_with_key,
+        group_id,
+        member_key_packages,
+        ciphersuite,
+        &options,
+    )?;
+    Ok((new_group, welcome))
 }
 
 /// Add a member to an existing group.
+#[tracing::instrument(name = "mls.add_member", skip_all, fields(epoch = group.epoch().as_u64()), err)]
 pub fn add_member(
     provider: &VoxProvider,
     group: &mut MlsGroup,
@@ -103,6 +428,8 @@ pub fn add_member(
     let kp = kp_in
         .validate(provider.crypto(), ProtocolVersion::Mls10)
         .map_err(|e| format!("Invalid key package: {e:?}"))?;
+    check_ciphersuite(&kp, group.ciphersuite())?;
+    check_required_capabilities(&kp, group)?;
 
     let (commit, welcome, _group_info) = group
         .add_members(provider, signature_keys, &[kp])
@@ -119,6 +446,7 @@ pub fn add_member(
 ///
 /// Iterates the group's members to find one whose credential identity matches
 /// `member_identity`, then removes them by their leaf index.
+#[tracing::instrument(name = "mls.remove_member", skip_all, fields(epoch = group.epoch().as_u64()), err)]
 pub fn remove_member_by_identity(
     provider: &VoxProvider,
     group: &mut MlsGroup,
@@ -148,21 +476,284 @@ pub fn remove_member_by_identity(
     Ok(commit)
 }
 
+/// Rotate our own leaf's key material with an Update commit, for
+/// post-compromise security.
+#[tracing::instrument(name = "mls.self_update", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn self_update(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    signature_keys: &SignatureKeyPair,
+) -> Result<MlsMessageOut, String> {
+    let bundle = group
+        .self_update(provider, signature_keys, LeafNodeParameters::default())
+        .map_err(|e| format!("Failed to update own leaf: {e:?}"))?;
+
+    group
+        .merge_pending_commit(provider)
+        .map_err(|e| format!("Failed to merge pending commit: {e:?}"))?;
+
+    Ok(bundle.into_commit())
+}
+
+/// Replace our leaf's credential and signature key with an Update commit,
+/// signed with the old key so that members accept it.
+#[tracing::instrument(name = "mls.rotate_credential", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn rotate_credential(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    signature_keys: &SignatureKeyPair,
+    new_signature_keys: &SignatureKeyPair,
+    new_credential_with_key: CredentialWithKey,
+) -> Result<MlsMessageOut, String> {
+    let new_signer = NewSignerBundle { signer: new_signature_keys, credential_with_key: new_credential_with_key };
+    let bundle = group
+        .self_update_with_new_signer(provider, signature_keys, new_signer, LeafNodeParameters::default())
+        .map_err(|e| format!("Failed to rotate credential: {e:?}"))?;
+
+    group
+        .merge_pending_commit(provider)
+        .map_err(|e| format!("Failed to merge pending commit: {e:?}"))?;
+
+    Ok(bundle.into_commit())
+}
+
+/// Propose our own removal. Another member has to commit the proposal.
+#[tracing::instrument(name = "mls.leave_group", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn leave_group(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    signature_keys: &SignatureKeyPair,
+) -> Result<MlsMessageOut, String> {
+    group
+        .leave_group(provider, signature_keys)
+        .map_err(|e| format!("Failed to leave group: {e:?}"))
+}
+
+/// Replace the group's application-defined GroupContext extensions with
+/// a GroupContextExtensions commit. Extensions OpenMLS interprets itself
+/// are kept.
+#[tracing::instrument(name = "mls.update_context_extensions", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn update_context_extensions(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    signature_keys: &SignatureKeyPair,
+    extensions: &[(u16, Vec<u8>)],
+) -> Result<MlsMessageOut, String> {
+    let mut all: Vec<Extension> = group
+        .extensions()
+        .iter()
+        .filter(|e| !matches!(e, Extension::Unknown(..)))
+        .cloned()
+        .collect();
+    all.extend(identity::custom_extensions(extensions)?);
+    let all = Extensions::from_vec(all).map_err(|e| format!("Invalid group context extensions: {e:?}"))?;
+
+    let (commit, _welcome, _group_info) = group
+        .update_group_context_extensions(provider, all, signature_keys)
+        .map_err(|e| format!("Failed to update group context extensions: {e:?}"))?;
+
+    group
+        .merge_pending_commit(provider)
+        .map_err(|e| format!("Failed to merge pending commit: {e:?}"))?;
+
+    Ok(commit)
+}
+
+/// The group's application-defined GroupContext extensions as
+/// `(type, data)`.
+pub fn context_extensions(group: &MlsGroup) -> Vec<(u16, Vec<u8>)> {
+    group
+        .extensions()
+        .iter()
+        .filter_map(|e| match e {
+            Extension::Unknown(t, UnknownExtension(data)) => Some((*t, data.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Delete everything stored for a group: its state, secrets and the
+/// current epoch's encryption keys.
+#[tracing::instrument(name = "mls.delete_group", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn delete_group(provider: &VoxProvider, group: &mut MlsGroup) -> Result<(), String> {
+    provider
+        .storage()
+        .delete_encryption_epoch_key_pairs(group.group_id(), &group.epoch(), group.own_leaf_index().u32())
+        .map_err(|e| format!("Failed to delete epoch keys: {e:?}"))?;
+    group
+        .delete(provider.storage())
+        .map_err(|e| format!("Failed to delete group: {e:?}"))
+}
+
+/// Commit the proposals received so far, such as a member's request to
+/// leave. Returns `(commit, welcome)`, or None if there are none.
+#[tracing::instrument(name = "mls.commit_pending_proposals", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn commit_pending_proposals(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    signature_keys: &SignatureKeyPair,
+) -> Result<Option<(MlsMessageOut, Option<MlsMessageOut>)>, String> {
+    if group.pending_proposals().next().is_none() {
+        return Ok(None);
+    }
+    let (commit, welcome, _group_info) = group
+        .commit_to_pending_proposals(provider, signature_keys)
+        .map_err(|e| format!("Failed to commit pending proposals: {e:?}"))?;
+
+    group
+        .merge_pending_commit(provider)
+        .map_err(|e| format!("Failed to merge pending commit: {e:?}"))?;
+
+    Ok(Some((commit, welcome)))
+}
+
+/// The proposals received and not yet committed, as `(type, sender,
+/// sender's credential identity)`. The identity is known for members and
+/// for non-members proposing their own addition.
+pub fn pending_proposals(group: &MlsGroup) -> Vec<(ProposalType, Sender, Option<Vec<u8>>)> {
+    group
+        .pending_proposals()
+        .map(|queued| {
+            let identity = match (queued.sender(), queued.proposal()) {
+                (Sender::Member(leaf), _) => group.member(*leaf).map(|c| c.serialized_content().to_vec()),
+                (Sender::NewMemberProposal, Proposal::Add(add)) => {
+                    Some(add.key_package().leaf_node().credential().serialized_content().to_vec())
+                }
+                _ => None,
+            };
+            (queued.proposal().proposal_type(), queued.sender().clone(), identity)
+        })
+        .collect()
+}
+
+/// Drop the proposals received and not yet committed.
+pub fn clear_pending_proposals(provider: &VoxProvider, group: &mut MlsGroup) -> Result<(), String> {
+    group
+        .clear_pending_proposals(provider.storage())
+        .map_err(|e| format!("Failed to clear pending proposals: {e:?}"))
+}
+
+/// Serialize the group's ratchet tree, for joiners whose Welcome leaves
+/// it out.
+pub fn export_ratchet_tree(group: &MlsGroup) -> Result<Vec<u8>, String> {
+    group
+        .export_ratchet_tree()
+        .tls_serialize_detached()
+        .map_err(|e| format!("Failed to serialize ratchet tree: {e:?}"))
+}
+
+/// The group's members as `(leaf_index, credential_identity,
+/// signature_public_key)`, by leaf index.
+pub fn members(group: &MlsGroup) -> Vec<(u32, Vec<u8>, Vec<u8>)> {
+    group
+        .members()
+        .map(|m| (m.index.u32(), m.credential.serialized_content().to_vec(), m.signature_key))
+        .collect()
+}
+
+/// MLS exporter label for SFrame media keys.
+const MEDIA_KEY_LABEL: &str = "vox media key";
+/// Length of the exported media base key (AES-128).
+const MEDIA_KEY_LEN: usize = 16;
+
+/// Export the media encryption key for the group's current epoch.
+/// Returns `(epoch, key)`; the epoch doubles as the SFrame key id.
+pub fn export_media_key(
+    provider: &VoxProvider,
+    group: &MlsGroup,
+) -> Result<(u64, Vec<u8>), String> {
+    let key = group
+        .export_secret(provider.crypto(), MEDIA_KEY_LABEL, &[], MEDIA_KEY_LEN)
+        .map_err(|e| format!("Failed to export media key: {e:?}"))?;
+    Ok((group.epoch().as_u64(), key))
+}
+
+/// Export the key for one file attachment, named by its file id, at the
+/// group's current epoch. Returns `(epoch, key)`.
+pub fn export_file_key(
+    provider: &VoxProvider,
+    group: &MlsGroup,
+    file_id: &[u8],
+) -> Result<(u64, Vec<u8>), String> {
+    let key = group
+        .export_secret(provider.crypto(), FILE_KEY_LABEL, file_id, FILE_KEY_LEN)
+        .map_err(|e| format!("Failed to export file key: {e:?}"))?;
+    Ok((group.epoch().as_u64(), key))
+}
+
 /// Simplified result of processing an MLS message.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcessedResult {
+    /// Decrypted plaintext of an application message.
     Application(Vec<u8>),
     Commit,
     Proposal,
     ExternalJoinProposal,
+    /// From a later epoch: kept until the commits before it are processed,
+    /// then replayed.
+    Buffered,
+    /// A commit staged for review rather than merged.
+    StagedCommit(StagedCommitInfo),
+    /// A commit we sent ourselves, relayed back to us; it was merged when
+    /// sent, so nothing was done.
+    OwnCommit,
+}
+
+/// The membership changes a staged commit makes, for review before it is
+/// merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StagedCommitInfo {
+    /// Credential identities of the members it adds.
+    pub adds: Vec<Vec<u8>>,
+    /// `(leaf_index, credential_identity)` of the members it removes.
+    pub removes: Vec<(u32, Vec<u8>)>,
+    /// `(leaf_index, credential_identity)` of the members whose leaf it
+    /// replaces: senders of Update proposals, and the committer if the
+    /// commit has a path.
+    pub updates: Vec<(u32, Vec<u8>)>,
+}
+
+fn staged_commit_info(group: &MlsGroup, staged_commit: &StagedCommit, committer: &Sender) -> StagedCommitInfo {
+    let member = |leaf: LeafNodeIndex| {
+        let identity = group.member(leaf).map(|c| c.serialized_content().to_vec()).unwrap_or_default();
+        (leaf.u32(), identity)
+    };
+    let adds = staged_commit
+        .add_proposals()
+        .map(|p| p.add_proposal().key_package().leaf_node().credential().serialized_content().to_vec())
+        .collect();
+    let removes = staged_commit.remove_proposals().map(|p| member(p.remove_proposal().removed())).collect();
+    let mut updates: Vec<_> = staged_commit
+        .update_proposals()
+        .filter_map(|p| match p.sender() {
+            Sender::Member(leaf) => Some(member(*leaf)),
+            _ => None,
+        })
+        .collect();
+    if let (Some(_), Sender::Member(leaf)) = (staged_commit.update_path_leaf_node(), committer) {
+        updates.push(member(*leaf));
+    }
+    StagedCommitInfo { adds, removes, updates }
 }
 
 /// Process an incoming MLS message (commit, proposal, or application message).
-/// Automatically merges staged commits and stores proposals.
+/// Stores proposals, and merges commits unless `stage_commits` is set: then
+/// a commit is returned for the caller to merge with
+/// [`merge_staged_commit`] or drop. Returns the result with the epoch the
+/// message was sent in, its sender and the sender's credential identity.
+#[tracing::instrument(
+    name = "mls.process_message",
+    skip_all,
+    fields(epoch = group.epoch().as_u64(), bytes = message_bytes.len()),
+    err
+)]
 pub fn process_message(
     provider: &VoxProvider,
     group: &mut MlsGroup,
     message_bytes: &[u8],
-) -> Result<ProcessedResult, String> {
+    stage_commits: bool,
+) -> Result<(ProcessedResult, u64, Sender, Vec<u8>, Option<StagedCommit>), String> {
+    let _timer = profile::PROCESS_MESSAGE.start();
     let mls_in = MlsMessageIn::tls_deserialize_exact(message_bytes)
         .map_err(|e| format!("Failed to deserialize message: {e:?}"))?;
 
@@ -173,26 +764,103 @@ pub fn process_message(
     let processed = group
         .process_message(provider, protocol_msg)
         .map_err(|e| format!("Failed to process message: {e:?}"))?;
+    let epoch = processed.epoch().as_u64();
+    let sender = processed.sender().clone();
+    let identity = processed.credential().serialized_content().to_vec();
 
-    match processed.into_content() {
+    let mut staged = None;
+    let result = match processed.into_content() {
         ProcessedMessageContent::ApplicationMessage(app_msg) => {
-            Ok(ProcessedResult::Application(app_msg.into_bytes()))
+            ProcessedResult::Application(app_msg.into_bytes())
+        }
+        ProcessedMessageContent::StagedCommitMessage(staged_commit) if stage_commits => {
+            let info = staged_commit_info(group, &staged_commit, &sender);
+            staged = Some(*staged_commit);
+            ProcessedResult::StagedCommit(info)
         }
         ProcessedMessageContent::StagedCommitMessage(staged_commit) => {
-            group
-                .merge_staged_commit(provider, *staged_commit)
-                .map_err(|e| format!("Failed to merge staged commit: {e:?}"))?;
-            Ok(ProcessedResult::Commit)
+            merge_staged_commit(provider, group, *staged_commit)?;
+            ProcessedResult::Commit
         }
         ProcessedMessageContent::ProposalMessage(proposal) => {
             group
                 .store_pending_proposal(provider.storage(), *proposal)
                 .map_err(|e| format!("Failed to store pending proposal: {e:?}"))?;
-            Ok(ProcessedResult::Proposal)
-        }
-        ProcessedMessageContent::ExternalJoinProposalMessage(_) => {
-            Ok(ProcessedResult::ExternalJoinProposal)
+            ProcessedResult::Proposal
         }
+        ProcessedMessageContent::ExternalJoinProposalMessage(_) => ProcessedResult::ExternalJoinProposal,
+    };
+    Ok((result, epoch, sender, identity, staged))
+}
+
+/// Merge a commit staged by [`process_message`].
+#[tracing::instrument(name = "mls.merge_staged_commit", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn merge_staged_commit(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    staged_commit: StagedCommit,
+) -> Result<(), String> {
+    group
+        .merge_staged_commit(provider, staged_commit)
+        .map_err(|e| format!("Failed to merge staged commit: {e:?}"))
+}
+
+/// Read a message's group, epoch, content type and, unless it is
+/// encrypted, sender, without touching any group state.
+pub fn peek_message(message_bytes: &[u8]) -> Result<(GroupId, u64, ContentType, Option<Sender>), String> {
+    let mls_in = MlsMessageIn::tls_deserialize_exact(message_bytes)
+        .map_err(|e| format!("Failed to deserialize message: {e:?}"))?;
+    let protocol_msg = mls_in
+        .try_into_protocol_message()
+        .map_err(|e| format!("Not a protocol message: {e:?}"))?;
+    let sender = match &protocol_msg {
+        ProtocolMessage::PublicMessage(public) => Some(public.sender().clone()),
+        ProtocolMessage::PrivateMessage(_) => None,
+    };
+    Ok((
+        protocol_msg.group_id().clone(),
+        protocol_msg.epoch().as_u64(),
+        protocol_msg.content_type(),
+        sender,
+    ))
+}
+
+/// SHA-256 of a serialized message, to recognise it when it comes back.
+pub fn message_digest(provider: &VoxProvider, message_bytes: &[u8]) -> Result<Vec<u8>, String> {
+    provider
+        .crypto()
+        .hash(HashType::Sha2_256, message_bytes)
+        .map_err(|e| format!("Failed to hash message: {e:?}"))
+}
+
+/// Decrypt an MLS application message, returning the plaintext and the
+/// sender's credential identity. Anything else is refused before the
+/// group state is touched.
+#[tracing::instrument(name = "mls.decrypt_application", skip_all, fields(epoch = group.epoch().as_u64()), err)]
+pub fn decrypt_application(
+    provider: &VoxProvider,
+    group: &mut MlsGroup,
+    message_bytes: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let _timer = profile::DECRYPT_APPLICATION.start();
+    let mls_in = MlsMessageIn::tls_deserialize_exact(message_bytes)
+        .map_err(|e| format!("Failed to deserialize message: {e:?}"))?;
+
+    let protocol_msg = mls_in
+        .try_into_protocol_message()
+        .map_err(|e| format!("Not a protocol message: {e:?}"))?;
+    if protocol_msg.content_type() != ContentType::Application {
+        return Err("MLS message is not an application message".to_string());
+    }
+
+    let processed = group
+        .process_message(provider, protocol_msg)
+        .map_err(|e| format!("Failed to process message: {e:?}"))?;
+    let sender = processed.credential().serialized_content().to_vec();
+
+    match processed.into_content() {
+        ProcessedMessageContent::ApplicationMessage(app_msg) => Ok((app_msg.into_bytes(), sender)),
+        _ => Err("MLS message is not an application message".to_string()),
     }
 }
 
@@ -203,6 +871,7 @@ pub fn encrypt(
     signature_keys: &SignatureKeyPair,
     plaintext: &[u8],
 ) -> Result<Vec<u8>, String> {
+    let _timer = profile::ENCRYPT.start();
     let msg = group
         .create_message(provider, signature_keys, plaintext)
         .map_err(|e| format!("Failed to encrypt: {e:?}"))?;
@@ -1,11 +1,74 @@
 use openmls::prelude::*;
 use openmls_basic_credential::SignatureKeyPair;
+use openmls_libcrux_crypto::CryptoProvider;
+use openmls_traits::crypto::OpenMlsCrypto;
+use openmls_traits::storage::StorageProvider;
+use openmls_traits::types::HashType;
+use tls_codec::Deserialize as TlsDeserialize;
 
 use crate::provider::VoxProvider;
 
-pub const CIPHERSUITE: Ciphersuite =
+pub const DEFAULT_CIPHERSUITE: Ciphersuite =
     Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
 
+/// Ciphersuites an engine can use. They all sign with Ed25519, so one
+/// identity serves groups of any of them.
+pub const SUPPORTED_CIPHERSUITES: &[Ciphersuite] = &[
+    Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519,
+    Ciphersuite::MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519,
+];
+
+/// Look up a supported ciphersuite by its RFC 9420 name, e.g.
+/// `"MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519"`.
+pub fn parse_ciphersuite(name: &str) -> Result<Ciphersuite, String> {
+    SUPPORTED_CIPHERSUITES
+        .iter()
+        .copied()
+        .find(|suite| format!("{suite:?}") == name)
+        .ok_or_else(|| {
+            let names: Vec<String> = SUPPORTED_CIPHERSUITES.iter().map(|s| format!("{s:?}")).collect();
+            format!("Unsupported ciphersuite '{name}' (expected one of {})", names.join(", "))
+        })
+}
+
+/// Hashed ahead of a fingerprint's inputs, so that the number cannot be
+/// a hash computed for anything else.
+const FINGERPRINT_LABEL: &[u8] = b"vox safety number v1";
+/// Decimal digits in a fingerprint, shown in groups of five.
+const FINGERPRINT_DIGITS: usize = 60;
+
+/// A safety number for a credential identity and signature key: 60 digits
+/// of their SHA-256 in groups of five, for people to compare out of band.
+/// Numbers that match mean both sides see the same key for that identity.
+pub fn fingerprint(provider: &VoxProvider, identity: &[u8], signature_key: &[u8]) -> Result<String, String> {
+    let mut input = FINGERPRINT_LABEL.to_vec();
+    for part in [identity, signature_key] {
+        // Length-prefixed, so that moving bytes between them changes it
+        input.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        input.extend_from_slice(part);
+    }
+    let mut hash = provider
+        .crypto()
+        .hash(HashType::Sha2_256, &input)
+        .map_err(|e| format!("Failed to hash fingerprint: {e:?}"))?;
+
+    // The hash as a big-endian number, divided down one decimal digit at a
+    // time; 256 bits have room for 77 digits
+    let mut digits = Vec::with_capacity(FINGERPRINT_DIGITS);
+    for _ in 0..FINGERPRINT_DIGITS {
+        let mut remainder = 0u32;
+        for byte in hash.iter_mut() {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(char::from(b'0' + remainder as u8));
+    }
+    digits.reverse();
+    let groups: Vec<String> = digits.chunks(5).map(|group| group.iter().collect()).collect();
+    Ok(groups.join(" "))
+}
+
 /// Generate a new MLS identity (credential + signing keys) for the given user/device.
 pub fn generate_identity(
     provider: &VoxProvider,
@@ -15,7 +78,7 @@ pub fn generate_identity(
     let identity = format!("{user_id}:{device_id}");
     let credential = BasicCredential::new(identity.into_bytes());
 
-    let signature_keys = SignatureKeyPair::new(CIPHERSUITE.signature_algorithm())
+    let signature_keys = SignatureKeyPair::new(DEFAULT_CIPHERSUITE.signature_algorithm())
         .map_err(|e| format!("Failed to generate signature keys: {e:?}"))?;
 
     signature_keys
@@ -30,15 +93,78 @@ pub fn generate_identity(
     Ok((credential_with_key, signature_keys))
 }
 
-/// Generate a KeyPackage for distribution to other members.
+/// How to build key packages. The default is what OpenMLS does: valid for
+/// 12 weeks, no extensions, default capabilities.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPackageOptions {
+    /// Seconds the key package stays valid, from now.
+    pub lifetime_secs: Option<u64>,
+    /// Application-defined key package extensions as `(type, data)`. The
+    /// types are also advertised in the leaf's capabilities.
+    pub extensions: Vec<(u16, Vec<u8>)>,
+    /// Further extension types to advertise support for, e.g. ones groups
+    /// may require of their members.
+    pub capability_extensions: Vec<u16>,
+    /// Mark it as a last resort key package, which joining a group does
+    /// not use up, for when the server has no others left.
+    pub last_resort: bool,
+}
+
+/// Extension types OpenMLS interprets itself cannot be passed as raw data.
+pub(crate) fn custom_extension_type(extension_type: u16) -> Result<ExtensionType, String> {
+    match ExtensionType::from(extension_type) {
+        ExtensionType::Unknown(t) => Ok(ExtensionType::Unknown(t)),
+        known => Err(format!("Extension type {extension_type} is reserved for {known:?}")),
+    }
+}
+
+/// Application-defined extensions from `(type, data)` pairs.
+pub(crate) fn custom_extensions(extensions: &[(u16, Vec<u8>)]) -> Result<Vec<Extension>, String> {
+    extensions
+        .iter()
+        .map(|(t, data)| {
+            custom_extension_type(*t)?;
+            Ok(Extension::Unknown(*t, UnknownExtension(data.clone())))
+        })
+        .collect()
+}
+
+/// Generate a KeyPackage for distribution to other members. It can only
+/// be used to join groups of the same `ciphersuite`.
 pub fn generate_key_package(
     provider: &VoxProvider,
     credential_with_key: &CredentialWithKey,
     signature_keys: &SignatureKeyPair,
+    ciphersuite: Ciphersuite,
+    options: &KeyPackageOptions,
 ) -> Result<KeyPackage, String> {
-    let bundle = KeyPackage::builder()
+    let mut builder = KeyPackage::builder();
+    if let Some(lifetime_secs) = options.lifetime_secs {
+        builder = builder.key_package_lifetime(Lifetime::new(lifetime_secs));
+    }
+    if !options.extensions.is_empty() {
+        let extensions = custom_extensions(&options.extensions)?;
+        builder = builder.key_package_extensions(
+            Extensions::from_vec(extensions).map_err(|e| format!("Invalid key package extensions: {e:?}"))?,
+        );
+    }
+    let mut capability_types = options.capability_extensions.clone();
+    capability_types.extend(options.extensions.iter().map(|(t, _)| *t));
+    if !capability_types.is_empty() {
+        let types = capability_types
+            .into_iter()
+            .map(custom_extension_type)
+            .collect::<Result<Vec<_>, String>>()?;
+        builder = builder.leaf_node_capabilities(Capabilities::new(None, None, Some(&types), None, None));
+    }
+
+    if options.last_resort {
+        builder = builder.mark_as_last_resort();
+    }
+
+    let bundle = builder
         .build(
-            CIPHERSUITE,
+            ciphersuite,
             provider,
             signature_keys,
             credential_with_key.clone(),
@@ -46,3 +172,32 @@ pub fn generate_key_package(
         .map_err(|e| format!("Failed to build key package: {e:?}"))?;
     Ok(bundle.key_package().clone())
 }
+
+/// Delete the private bundle of one of our key packages, and the key of
+/// its leaf. Returns whether there was one.
+pub fn delete_key_package(provider: &VoxProvider, hash_ref: &KeyPackageRef) -> Result<bool, String> {
+    let storage = provider.storage();
+    let Some(bundle) = storage
+        .key_package::<_, KeyPackageBundle>(hash_ref)
+        .map_err(|e| format!("Failed to load key package: {e:?}"))?
+    else {
+        return Ok(false);
+    };
+    storage
+        .delete_encryption_key_pair(bundle.key_package().leaf_node().encryption_key())
+        .map_err(|e| format!("Failed to delete key package leaf key: {e:?}"))?;
+    storage
+        .delete_key_package(hash_ref)
+        .map_err(|e| format!("Failed to delete key package: {e:?}"))?;
+    Ok(true)
+}
+
+/// Parse and verify a serialized KeyPackage without storing anything, for
+/// showing who it is for before adding it to a group.
+pub fn inspect_key_package(key_package_bytes: &[u8]) -> Result<KeyPackage, String> {
+    let crypto = CryptoProvider::new().map_err(|e| format!("Failed to create crypto provider: {e:?}"))?;
+    KeyPackageIn::tls_deserialize_exact(key_package_bytes)
+        .map_err(|e| format!("Failed to deserialize key package: {e:?}"))?
+        .validate(&crypto, ProtocolVersion::Mls10)
+        .map_err(|e| format!("Invalid key package: {e:?}"))
+}
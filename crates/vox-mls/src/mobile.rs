@@ -0,0 +1,431 @@
+//! UniFFI bindings for iOS and Android: [`MobileMlsEngine`] over
+//! [`crate::engine`], push decryption for notification extensions, and,
+//! linked in from vox-core, the sans-IO `MobileMediaSession`. Built with
+//! the `uniffi` feature.
+//!
+//! Databases, identity exports and state backups are byte-for-byte the
+//! ones the Python module reads and writes.
+
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use vox_core::mobile::MobileMediaSession;
+
+use crate::engine::{self, MlsEngine, MlsError, MlsResult};
+use crate::group::ProcessedResult;
+use crate::push::{self, PushNotification};
+
+type Job = Box<dyn FnOnce(&mut MlsEngine) + Send>;
+
+fn check_key(name: &str, key: Option<Vec<u8>>) -> MlsResult<Option<[u8; 32]>> {
+    key.map(|k| {
+        k.try_into()
+            .map_err(|_| MlsError::InvalidInput(format!("{name} must be exactly 32 bytes")))
+    })
+    .transpose()
+}
+
+fn engine_stopped() -> MlsError {
+    MlsError::Failed("MLS engine thread stopped".into())
+}
+
+/// Result of processing an incoming MLS message.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct ProcessedMessage {
+    /// "application", "commit", "proposal", "external_join_proposal",
+    /// "buffered" or "own_commit".
+    pub kind: String,
+    /// Plaintext of an application message.
+    pub data: Option<Vec<u8>>,
+    /// The epoch the message was sent in.
+    pub epoch: u64,
+    /// The sender's credential identity; None while buffered.
+    pub sender_identity: Option<Vec<u8>>,
+    /// The sender's leaf index; None for non-members and while buffered.
+    pub sender_leaf_index: Option<u32>,
+}
+
+impl From<engine::ProcessedMessage> for ProcessedMessage {
+    fn from(processed: engine::ProcessedMessage) -> Self {
+        let (kind, data) = match processed.result {
+            ProcessedResult::Application(plaintext) => ("application", Some(plaintext)),
+            ProcessedResult::Commit => ("commit", None),
+            ProcessedResult::Proposal => ("proposal", None),
+            ProcessedResult::ExternalJoinProposal => ("external_join_proposal", None),
+            ProcessedResult::Buffered => ("buffered", None),
+            ProcessedResult::OwnCommit => ("own_commit", None),
+            // Commits are only staged through the Rust and Python APIs
+            ProcessedResult::StagedCommit(_) => ("staged_commit", None),
+        };
+        ProcessedMessage {
+            kind: kind.to_string(),
+            data,
+            epoch: processed.epoch,
+            sender_identity: processed.sender_identity,
+            sender_leaf_index: processed.sender.and_then(|s| s.leaf_index()),
+        }
+    }
+}
+
+/// Welcome and commit produced by creating a group, adding a member or
+/// committing pending proposals.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct GroupCommit {
+    pub welcome: Option<Vec<u8>>,
+    pub commit: Option<Vec<u8>>,
+}
+
+/// A group joined by external commit, and the commit for its members.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct ExternalJoin {
+    pub group_id: String,
+    pub commit: Vec<u8>,
+}
+
+/// What `peek_message` reads from a message's envelope.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct MessageInfo {
+    pub group_id: String,
+    pub epoch: u64,
+    /// "application", "proposal" or "commit".
+    pub content_type: String,
+    /// "member", "external", "new_member_proposal" or "new_member_commit";
+    /// None for encrypted messages.
+    pub sender_type: Option<String>,
+    pub sender_index: Option<u32>,
+}
+
+/// A member of a group.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct GroupMember {
+    pub leaf_index: u32,
+    /// Credential identity, `"<user_id>:<device_id>"`.
+    pub identity: Vec<u8>,
+    pub signature_key: Vec<u8>,
+}
+
+/// A file attachment key and the epoch it was exported at.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct FileKey {
+    pub epoch: u64,
+    pub key: Vec<u8>,
+}
+
+/// The user and device an engine's identity belongs to.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct StoredIdentity {
+    pub user_id: u64,
+    pub device_id: String,
+}
+
+/// MLS encryption engine, with the same methods as the Python
+/// `vox_mls.MlsEngine`.
+///
+/// [`MlsEngine`] needs `&mut self`, so it lives on a thread of its own and
+/// each call is a round trip to it; UniFFI objects may be called from any
+/// thread. The thread exits when the object is released.
+#[derive(uniffi::Object)]
+pub struct MobileMlsEngine {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl MobileMlsEngine {
+    fn call<R: Send + 'static>(&self, f: impl FnOnce(&mut MlsEngine) -> MlsResult<R> + Send + 'static) -> MlsResult<R> {
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |engine| {
+            let _ = reply.send(f(engine));
+        });
+        self.jobs.send(job).map_err(|_| engine_stopped())?;
+        result.recv().map_err(|_| engine_stopped())?
+    }
+}
+
+#[uniffi::export]
+impl MobileMlsEngine {
+    /// Open or create the database at `db_path` (in memory if None). With
+    /// `db_key` the whole database is encrypted; this needs a build with
+    /// the `sqlcipher` feature.
+    #[uniffi::constructor]
+    pub fn new(
+        db_path: Option<String>,
+        encryption_key: Option<Vec<u8>>,
+        db_key: Option<Vec<u8>>,
+    ) -> MlsResult<Arc<Self>> {
+        let key = check_key("encryption_key", encryption_key)?;
+        let db_key = check_key("db_key", db_key)?;
+        let db_path = db_path.unwrap_or_else(|| ":memory:".to_string());
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, opened) = mpsc::sync_channel(1);
+        thread::Builder::new()
+            .name("vox-mls".into())
+            .spawn(move || {
+                let mut engine = match MlsEngine::open_with_db_key(&db_path, key, db_key) {
+                    Ok(engine) => engine,
+                    Err(e) => {
+                        let _ = ready.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready.send(Ok(()));
+                for job in queue {
+                    job(&mut engine);
+                }
+            })
+            .map_err(|e| MlsError::Failed(format!("Failed to start MLS engine thread: {e}")))?;
+        opened.recv().map_err(|_| engine_stopped())??;
+        Ok(Arc::new(MobileMlsEngine { jobs }))
+    }
+
+    /// Name of the ciphersuite used for new key packages and groups.
+    pub fn ciphersuite(&self) -> MlsResult<String> {
+        self.call(|e| Ok(format!("{:?}", e.ciphersuite())))
+    }
+
+    /// Use the named ciphersuite, e.g.
+    /// "MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519", for new key
+    /// packages and groups.
+    pub fn set_ciphersuite(&self, name: String) -> MlsResult<()> {
+        let ciphersuite = crate::parse_ciphersuite(&name).map_err(MlsError::InvalidInput)?;
+        self.call(move |e| e.set_ciphersuite(ciphersuite))
+    }
+
+    /// Generate a new MLS identity. Returns the public identity key.
+    pub fn generate_identity(&self, user_id: u64, device_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.generate_identity(user_id, &device_id))
+    }
+
+    /// Generate a serialized KeyPackage for uploading to the server.
+    pub fn generate_key_package(&self) -> MlsResult<Vec<u8>> {
+        self.call(|e| e.generate_key_package())
+    }
+
+    /// Generate multiple KeyPackages.
+    pub fn generate_key_packages(&self, count: u32) -> MlsResult<Vec<Vec<u8>>> {
+        self.call(move |e| e.generate_key_packages(count as usize))
+    }
+
+    /// Create a new MLS group, adding the members whose KeyPackages are given.
+    pub fn create_group(&self, group_id: String, member_key_packages: Vec<Vec<u8>>) -> MlsResult<GroupCommit> {
+        self.call(move |e| {
+            let (welcome, commit) = e.create_group(&group_id, &member_key_packages)?;
+            Ok(GroupCommit { welcome, commit })
+        })
+    }
+
+    /// Join a group from a Welcome message. Returns the group ID.
+    pub fn join_group(&self, welcome: Vec<u8>) -> MlsResult<String> {
+        self.call(move |e| e.join_group(&welcome))
+    }
+
+    /// Export a group's signed GroupInfo, for others to join by external
+    /// commit, which needs `with_ratchet_tree`.
+    pub fn export_group_info(&self, group_id: String, with_ratchet_tree: bool) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.export_group_info(&group_id, with_ratchet_tree))
+    }
+
+    /// Join a group from its GroupInfo without a Welcome.
+    pub fn join_by_external_commit(&self, group_info: Vec<u8>) -> MlsResult<ExternalJoin> {
+        self.call(move |e| {
+            let (group_id, commit) = e.join_by_external_commit(&group_info)?;
+            Ok(ExternalJoin { group_id, commit })
+        })
+    }
+
+    /// Add a member to an existing group.
+    pub fn add_member(&self, group_id: String, key_package: Vec<u8>) -> MlsResult<GroupCommit> {
+        self.call(move |e| {
+            let (welcome, commit) = e.add_member(&group_id, &key_package)?;
+            Ok(GroupCommit { welcome: Some(welcome), commit: Some(commit) })
+        })
+    }
+
+    /// Remove a member by credential identity string. Returns the commit.
+    pub fn remove_member(&self, group_id: String, member_identity: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.remove_member(&group_id, &member_identity))
+    }
+
+    /// Rotate our own leaf key material with an Update commit. Returns the
+    /// commit.
+    pub fn update_self(&self, group_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.update_self(&group_id))
+    }
+
+    /// Replace our credential in a group with a fresh one for
+    /// `new_device_id`, under a new signature key. Returns the commit.
+    pub fn rotate_credential(&self, group_id: String, new_device_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.rotate_credential(&group_id, &new_device_id))
+    }
+
+    /// Ask to leave a group. Returns the proposal removing our own leaf.
+    pub fn leave_group(&self, group_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.leave_group(&group_id))
+    }
+
+    /// Whether `leave_group` was called for a group that has not removed
+    /// us yet.
+    pub fn is_leaving(&self, group_id: String) -> MlsResult<bool> {
+        self.call(move |e| e.is_leaving(&group_id))
+    }
+
+    /// Delete a group and all its stored state. Other members are not told.
+    pub fn delete_group(&self, group_id: String) -> MlsResult<()> {
+        self.call(move |e| e.delete_group(&group_id))
+    }
+
+    /// Commit the proposals received for a group, or None if there were
+    /// none.
+    pub fn commit_pending_proposals(&self, group_id: String) -> MlsResult<Option<GroupCommit>> {
+        self.call(move |e| {
+            let committed = e.commit_pending_proposals(&group_id)?;
+            Ok(committed.map(|(commit, welcome)| GroupCommit { welcome, commit: Some(commit) }))
+        })
+    }
+
+    /// Process an incoming MLS message (commit, proposal, or application message).
+    pub fn process_message(&self, group_id: String, message: Vec<u8>) -> MlsResult<ProcessedMessage> {
+        self.call(move |e| Ok(e.process_message(&group_id, &message)?.into()))
+    }
+
+    /// Results of buffered messages replayed since the last call.
+    pub fn take_replayed(&self, group_id: String) -> MlsResult<Vec<ProcessedMessage>> {
+        self.call(move |e| Ok(e.take_replayed(&group_id).into_iter().map(Into::into).collect()))
+    }
+
+    /// Encrypt plaintext into an MLS application message.
+    pub fn encrypt(&self, group_id: String, plaintext: Vec<u8>) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.encrypt(&group_id, &plaintext))
+    }
+
+    /// Decrypt an MLS application message.
+    pub fn decrypt(&self, group_id: String, ciphertext: Vec<u8>) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.decrypt(&group_id, &ciphertext))
+    }
+
+    /// Feed this group's media keys to `session`, now and at every new epoch.
+    pub fn attach_media_session(&self, group_id: String, session: Arc<MobileMediaSession>) -> MlsResult<()> {
+        let sink = session.media_key_sink();
+        self.call(move |e| e.attach_media_key_sink(&group_id, sink))
+    }
+
+    /// Export the key for a file attachment at the group's current epoch.
+    pub fn export_file_key(&self, group_id: String, file_id: Vec<u8>, epoch: Option<u64>) -> MlsResult<FileKey> {
+        self.call(move |e| {
+            let (epoch, key) = e.export_file_key(&group_id, &file_id, epoch)?;
+            Ok(FileKey { epoch, key })
+        })
+    }
+
+    /// Read a message's group, epoch, content type and, unless encrypted,
+    /// sender without processing it.
+    pub fn peek_message(&self, message: Vec<u8>) -> MlsResult<MessageInfo> {
+        self.call(move |e| {
+            let info = e.peek_message(&message)?;
+            Ok(MessageInfo {
+                group_id: info.group_id,
+                epoch: info.epoch,
+                content_type: info.content_type.to_string(),
+                sender_type: info.sender.map(|s| s.kind().to_string()),
+                sender_index: info.sender.and_then(|s| s.index()),
+            })
+        })
+    }
+
+    /// List a group's members, ordered by leaf index.
+    pub fn list_members(&self, group_id: String) -> MlsResult<Vec<GroupMember>> {
+        self.call(move |e| {
+            let members = e.list_members(&group_id)?;
+            Ok(members
+                .into_iter()
+                .map(|(leaf_index, identity, signature_key)| GroupMember { leaf_index, identity, signature_key })
+                .collect())
+        })
+    }
+
+    /// The group's current epoch.
+    pub fn group_epoch(&self, group_id: String) -> MlsResult<u64> {
+        self.call(move |e| e.group_epoch(&group_id))
+    }
+
+    /// The current epoch's authenticator, for out-of-band verification.
+    pub fn epoch_authenticator(&self, group_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.epoch_authenticator(&group_id))
+    }
+
+    /// Check if a group exists in storage.
+    pub fn group_exists(&self, group_id: String) -> MlsResult<bool> {
+        self.call(move |e| Ok(e.group_exists(&group_id)))
+    }
+
+    /// List all group IDs managed by this engine.
+    pub fn list_groups(&self) -> MlsResult<Vec<String>> {
+        self.call(|e| e.list_groups())
+    }
+
+    /// The public identity key, or None if not initialized.
+    pub fn identity_key(&self) -> MlsResult<Option<Vec<u8>>> {
+        self.call(|e| Ok(e.identity_key()))
+    }
+
+    /// The stored identity, or None if there is none.
+    pub fn stored_identity(&self) -> MlsResult<Option<StoredIdentity>> {
+        self.call(|e| Ok(e.stored_identity()?.map(|(user_id, device_id)| StoredIdentity { user_id, device_id })))
+    }
+
+    /// Export the full MLS state (identity + all groups) as raw SQLite
+    /// database bytes. They contain private key material; encrypt them
+    /// before persisting or transmitting.
+    pub fn export_state(&self) -> MlsResult<Vec<u8>> {
+        self.call(|e| e.export_state())
+    }
+
+    /// Restore full MLS state from `export_state` output.
+    pub fn import_state(&self, data: Vec<u8>) -> MlsResult<()> {
+        self.call(move |e| e.import_state(&data))
+    }
+
+    /// Export one group's state for another device with the same identity.
+    pub fn export_group(&self, group_id: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.export_group(&group_id))
+    }
+
+    /// Import a group from `export_group` output. Returns the group ID.
+    pub fn import_group(&self, data: Vec<u8>) -> MlsResult<String> {
+        self.call(move |e| e.import_group(&data))
+    }
+
+    /// `export_state` output encrypted with a passphrase.
+    pub fn export_state_encrypted(&self, passphrase: String) -> MlsResult<Vec<u8>> {
+        self.call(move |e| e.export_state_encrypted(&passphrase))
+    }
+
+    /// Restore state from `export_state_encrypted` output.
+    pub fn import_state_encrypted(&self, data: Vec<u8>, passphrase: String) -> MlsResult<()> {
+        self.call(move |e| e.import_state_encrypted(&data, &passphrase))
+    }
+
+    /// Export the identity only, unencrypted.
+    pub fn export_identity(&self) -> MlsResult<Vec<u8>> {
+        self.call(|e| e.export_identity())
+    }
+
+    /// Import a previously exported identity.
+    pub fn import_identity(&self, data: Vec<u8>, user_id: u64, device_id: String) -> MlsResult<()> {
+        self.call(move |e| e.import_identity(&data, user_id, &device_id))
+    }
+}
+
+/// Decrypt a push payload in a notification extension, sharing the app's
+/// database. See [`push::decrypt_push_payload`].
+#[uniffi::export]
+pub fn decrypt_push(
+    payload: String,
+    db_path: String,
+    encryption_key: Option<Vec<u8>>,
+    preview_len: u32,
+    consume: bool,
+    db_key: Option<Vec<u8>>,
+) -> MlsResult<PushNotification> {
+    let key = check_key("encryption_key", encryption_key)?;
+    let db_key = check_key("db_key", db_key)?;
+    push::decrypt_push_payload(&payload, &db_path, key, db_key, preview_len as usize, consume)
+}
@@ -0,0 +1,64 @@
+//! Timing histograms for MLS operations, reported by `profile_report`.
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+#[cfg(feature = "python")]
+use vox_core::profile;
+use vox_core::profile::Histogram;
+
+pub static ENCRYPT: Histogram = Histogram::new("encrypt");
+pub static PROCESS_MESSAGE: Histogram = Histogram::new("process_message");
+pub static DECRYPT_APPLICATION: Histogram = Histogram::new("decrypt_application");
+pub static LOAD_GROUP: Histogram = Histogram::new("load_group");
+
+#[cfg(feature = "python")]
+static ALL: [&Histogram; 4] = [
+    &ENCRYPT,
+    &PROCESS_MESSAGE,
+    &DECRYPT_APPLICATION,
+    &LOAD_GROUP,
+];
+
+/// Start or stop recording operation timings in vox_mls.
+///
+/// Works like `vox_media.enable_profiling`, but vox_mls keeps its own
+/// switch and counts.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (enabled=true))]
+pub fn enable_profiling(enabled: bool) {
+    profile::set_enabled(enabled);
+}
+
+/// Timing statistics per operation recorded since profiling was enabled.
+///
+/// Keys are operation names: `encrypt`, `process_message` (which covers
+/// `decrypt`), `decrypt_application` (push decryption) and `load_group`,
+/// the engine's read of group state from the database before every group
+/// operation. Operations with no calls are left out. Values have the
+/// same fields as in `vox_media.profile_report`. With `reset=True` the
+/// counts start over.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (reset=false))]
+pub fn profile_report(py: Python<'_>, reset: bool) -> PyResult<Bound<'_, PyDict>> {
+    let report = PyDict::new(py);
+    for histogram in ALL {
+        let snap = histogram.snapshot(reset);
+        if snap.count == 0 {
+            continue;
+        }
+        let d = PyDict::new(py);
+        d.set_item("count", snap.count)?;
+        d.set_item("total_ms", snap.total_ns as f64 / 1e6)?;
+        d.set_item("mean_us", snap.mean_ns() / 1e3)?;
+        d.set_item("p50_us", snap.percentile_ns(0.5) / 1e3)?;
+        d.set_item("p90_us", snap.percentile_ns(0.9) / 1e3)?;
+        d.set_item("p99_us", snap.percentile_ns(0.99) / 1e3)?;
+        d.set_item("max_us", snap.max_ns as f64 / 1e3)?;
+        report.set_item(snap.name, d)?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,401 @@
+//! In-memory provider for wasm32, where SQLite is not available.
+//!
+//! OpenMLS state lives in a `MemoryStorage` key-value map and the
+//! `vox_identity`/`vox_groups` tables become plain fields. The whole state
+//! round-trips through [`VoxProvider::export_db`] and
+//! [`VoxProvider::import_db`] as a JSON snapshot, which is what the `wasm`
+//! bindings keep in IndexedDB.
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use base64::Engine;
+use openmls_libcrux_crypto::CryptoProvider;
+use openmls_memory_storage::MemoryStorage;
+use openmls_traits::{types::CryptoError, OpenMlsProvider};
+use serde::{Deserialize, Serialize};
+
+use super::{open_key_material, seal_key_material, StorageOptions, StoredKeyPackage};
+
+/// `format` field of a snapshot, to tell it apart from SQLite backups.
+const SNAPSHOT_FORMAT: &str = "vox-mls-kv";
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The `vox_identity` row.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredIdentity {
+    user_id: u64,
+    device_id: String,
+    credential_with_key: String,
+    /// Encrypted with the provider's key, if it has one.
+    signature_key_pair: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    format: String,
+    version: u32,
+    identity: Option<StoredIdentity>,
+    groups: BTreeSet<String>,
+    /// Groups we asked to leave; absent from older snapshots.
+    #[serde(default)]
+    leaving: BTreeSet<String>,
+    /// Key packages we generated; absent from older snapshots.
+    #[serde(default)]
+    key_packages: Vec<StoredKeyPackage>,
+    /// OpenMLS storage entries as base64 `(key, value)` pairs.
+    values: Vec<(String, String)>,
+}
+
+/// One group's OpenMLS storage entries as base64 `(key, value)` pairs.
+#[derive(Serialize, Deserialize)]
+pub struct GroupRows {
+    values: Vec<(String, String)>,
+}
+
+impl GroupRows {
+    /// Bring rows from a version `version` group export up to date. The
+    /// in-memory storage has always used JSON, so there is nothing to do.
+    pub fn upgrade(self, _version: u32) -> Result<Self, String> {
+        Ok(self)
+    }
+}
+
+/// Seconds since the Unix epoch. wasm32 has no clock of its own, so
+/// this is 0 outside the JS bindings.
+fn unix_now() -> u64 {
+    #[cfg(feature = "wasm")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+    #[cfg(not(feature = "wasm"))]
+    {
+        0
+    }
+}
+
+/// Whether `needle` occurs in a storage key. Keys are a label followed by
+/// the JSON of what they are for, so a group's entries contain its ID.
+fn key_contains(key: &[u8], needle: &[u8]) -> bool {
+    key.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Composite OpenMLS provider: libcrux crypto + in-memory storage.
+pub struct VoxProvider {
+    crypto: CryptoProvider,
+    storage: MemoryStorage,
+    identity: RefCell<Option<StoredIdentity>>,
+    group_ids: RefCell<BTreeSet<String>>,
+    leaving: RefCell<BTreeSet<String>>,
+    key_packages: RefCell<Vec<StoredKeyPackage>>,
+    /// Optional 256-bit key for encrypting private key material at rest.
+    encryption_key: Option<[u8; 32]>,
+}
+
+impl VoxProvider {
+    /// Create an empty provider. There is no database on wasm32, so
+    /// `_db_path`, `_db_key` and `_options` are ignored; load saved state
+    /// with `import_db`.
+    pub fn new(
+        _db_path: &str,
+        encryption_key: Option<[u8; 32]>,
+        _db_key: Option<[u8; 32]>,
+        _options: &StorageOptions,
+    ) -> Result<Self, String> {
+        let crypto = CryptoProvider::new()
+            .map_err(|e: CryptoError| format!("Failed to create crypto provider: {e:?}"))?;
+        Ok(VoxProvider {
+            crypto,
+            storage: MemoryStorage::default(),
+            identity: RefCell::new(None),
+            group_ids: RefCell::new(BTreeSet::new()),
+            leaving: RefCell::new(BTreeSet::new()),
+            key_packages: RefCell::new(Vec::new()),
+            encryption_key,
+        })
+    }
+
+    /// Save identity metadata, replacing any previous identity.
+    pub fn save_identity(
+        &self,
+        user_id: u64,
+        device_id: &str,
+        credential_with_key_json: &str,
+        signature_key_pair_json: &str,
+    ) -> Result<(), String> {
+        let signature_key_pair = seal_key_material(self.encryption_key.as_ref(), signature_key_pair_json)?;
+        *self.identity.borrow_mut() = Some(StoredIdentity {
+            user_id,
+            device_id: device_id.to_string(),
+            credential_with_key: credential_with_key_json.to_string(),
+            signature_key_pair,
+        });
+        Ok(())
+    }
+
+    /// Load identity metadata; see the SQLite provider.
+    pub fn load_identity(&self) -> Result<Option<(u64, String, String, String)>, String> {
+        let Some(identity) = self.identity.borrow().clone() else {
+            return Ok(None);
+        };
+        let sig_json = open_key_material(self.encryption_key.as_ref(), &identity.signature_key_pair)?;
+        Ok(Some((identity.user_id, identity.device_id, identity.credential_with_key, sig_json)))
+    }
+
+    /// Handles of the identities stored besides the default one: there
+    /// are none, only the SQLite provider holds several.
+    pub fn list_namespaces(&self) -> Result<Vec<String>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Switch identities; only the default one, `""`, exists here.
+    pub fn set_namespace(&mut self, namespace: &str) -> Result<(), String> {
+        match namespace.is_empty() {
+            true => Ok(()),
+            false => Err("Multiple identities need the SQLite storage".to_string()),
+        }
+    }
+
+    /// Record a group ID.
+    pub fn save_group_id(&self, group_id: &str) -> Result<(), String> {
+        self.group_ids.borrow_mut().insert(group_id.to_string());
+        Ok(())
+    }
+
+    /// Stop tracking a group ID whose state is gone.
+    pub fn forget_group_id(&self, group_id: &str) -> Result<(), String> {
+        self.group_ids.borrow_mut().remove(group_id);
+        self.leaving.borrow_mut().remove(group_id);
+        Ok(())
+    }
+
+    /// Record that we asked to leave a group.
+    pub fn mark_leaving(&self, group_id: &str) -> Result<(), String> {
+        self.leaving.borrow_mut().insert(group_id.to_string());
+        Ok(())
+    }
+
+    /// Whether we asked to leave a group that has not removed us yet.
+    pub fn is_leaving(&self, group_id: &str) -> Result<bool, String> {
+        Ok(self.leaving.borrow().contains(group_id))
+    }
+
+    /// List all recorded group IDs.
+    pub fn list_group_ids(&self) -> Result<Vec<String>, String> {
+        Ok(self.group_ids.borrow().iter().cloned().collect())
+    }
+
+    /// Record a key package we generated.
+    pub fn track_key_package(&self, hash_ref: &[u8], last_resort: bool) -> Result<(), String> {
+        self.untrack_key_package(hash_ref)?;
+        self.key_packages.borrow_mut().push(StoredKeyPackage {
+            hash_ref: hash_ref.to_vec(),
+            created_at: unix_now(),
+            last_resort,
+            consumed: false,
+        });
+        Ok(())
+    }
+
+    /// Record that a Welcome used one of our key packages.
+    pub fn mark_key_package_consumed(&self, hash_ref: &[u8]) -> Result<(), String> {
+        for key_package in self.key_packages.borrow_mut().iter_mut() {
+            if key_package.hash_ref == hash_ref {
+                key_package.consumed = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop tracking a key package. Returns whether it was tracked.
+    pub fn untrack_key_package(&self, hash_ref: &[u8]) -> Result<bool, String> {
+        let mut key_packages = self.key_packages.borrow_mut();
+        let before = key_packages.len();
+        key_packages.retain(|key_package| key_package.hash_ref != hash_ref);
+        Ok(key_packages.len() < before)
+    }
+
+    /// The key packages we generated and still track, oldest first.
+    pub fn list_key_packages(&self) -> Result<Vec<StoredKeyPackage>, String> {
+        Ok(self.key_packages.borrow().clone())
+    }
+
+    /// The storage entries of one group, plus the private key of our leaf
+    /// (`encryption_key`, stored apart from the group).
+    pub fn export_group_rows(
+        &self,
+        group_id: &impl Serialize,
+        encryption_key: &impl Serialize,
+    ) -> Result<GroupRows, String> {
+        let group_needle = serde_json::to_vec(group_id).map_err(|e| format!("Failed to serialize group ID: {e}"))?;
+        let key_needle =
+            serde_json::to_vec(encryption_key).map_err(|e| format!("Failed to serialize encryption key: {e}"))?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let values = self
+            .storage
+            .values
+            .read()
+            .map_err(|_| "Storage lock poisoned".to_string())?
+            .iter()
+            .filter(|(k, _)| key_contains(k, &group_needle) || key_contains(k, &key_needle))
+            .map(|(k, v)| (b64.encode(k), b64.encode(v)))
+            .collect();
+        Ok(GroupRows { values })
+    }
+
+    /// Replace whatever is stored for a group with `rows` from
+    /// `export_group_rows`, and track the group.
+    pub fn import_group_rows(
+        &self,
+        group_id_str: &str,
+        group_id: &impl Serialize,
+        rows: &GroupRows,
+    ) -> Result<(), String> {
+        let group_needle = serde_json::to_vec(group_id).map_err(|e| format!("Failed to serialize group ID: {e}"))?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let entries = rows
+            .values
+            .iter()
+            .map(|(k, v)| Ok((b64.decode(k)?, b64.decode(v)?)))
+            .collect::<Result<Vec<_>, base64::DecodeError>>()
+            .map_err(|e| format!("Invalid base64 in group export: {e}"))?;
+
+        let mut values = self.storage.values.write().map_err(|_| "Storage lock poisoned".to_string())?;
+        values.retain(|k, _| !key_contains(k, &group_needle));
+        values.extend(entries);
+        drop(values);
+        self.save_group_id(group_id_str)
+    }
+
+    /// Entries are keyed by serialized IDs, not columns we could filter
+    /// on, so epoch key pairs are left to OpenMLS's own cleanup here.
+    pub fn prune_epoch_key_pairs(&self, _group_id: &impl Serialize, _oldest_epoch: u64) -> Result<usize, String> {
+        Ok(0)
+    }
+
+    /// Expired key packages are not pruned on wasm32; see
+    /// [`prune_epoch_key_pairs`](Self::prune_epoch_key_pairs).
+    pub fn prune_key_packages(&self) -> Result<usize, String> {
+        Ok(0)
+    }
+
+    /// Bytes of the stored keys and values.
+    pub fn database_size(&self) -> Result<u64, String> {
+        let values = self.storage.values.read().map_err(|_| "Storage lock poisoned".to_string())?;
+        Ok(values.iter().map(|(key, value)| (key.len() + value.len()) as u64).sum())
+    }
+
+    /// Nothing to do: snapshots are written out whole.
+    pub fn vacuum(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Run `f`, restoring the state from before it if it fails. Nothing
+    /// reaches IndexedDB until the `wasm` bindings persist the result.
+    pub fn atomically<T, E: From<String>>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let values = self.storage.values.read().map_err(|_| "Storage lock poisoned".to_string())?.clone();
+        let identity = self.identity.borrow().clone();
+        let group_ids = self.group_ids.borrow().clone();
+        let leaving = self.leaving.borrow().clone();
+        let key_packages = self.key_packages.borrow().clone();
+        let result = f();
+        if result.is_err() {
+            *self.storage.values.write().map_err(|_| "Storage lock poisoned".to_string())? = values;
+            *self.identity.borrow_mut() = identity;
+            *self.group_ids.borrow_mut() = group_ids;
+            *self.leaving.borrow_mut() = leaving;
+            *self.key_packages.borrow_mut() = key_packages;
+        }
+        result
+    }
+
+    /// Run `f` and restore the state from before it, so nothing it stores
+    /// is kept.
+    pub fn rolled_back<T>(&self, f: impl FnOnce() -> T) -> Result<T, String> {
+        let values = self.storage.values.read().map_err(|_| "Storage lock poisoned".to_string())?.clone();
+        let identity = self.identity.borrow().clone();
+        let group_ids = self.group_ids.borrow().clone();
+        let leaving = self.leaving.borrow().clone();
+        let key_packages = self.key_packages.borrow().clone();
+        let result = f();
+        *self.storage.values.write().map_err(|_| "Storage lock poisoned".to_string())? = values;
+        *self.identity.borrow_mut() = identity;
+        *self.group_ids.borrow_mut() = group_ids;
+        *self.leaving.borrow_mut() = leaving;
+        *self.key_packages.borrow_mut() = key_packages;
+        Ok(result)
+    }
+
+    /// Export the whole state as a JSON snapshot.
+    pub fn export_db(&self) -> Result<Vec<u8>, String> {
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let values = self
+            .storage
+            .values
+            .read()
+            .map_err(|_| "Storage lock poisoned".to_string())?
+            .iter()
+            .map(|(k, v)| (b64.encode(k), b64.encode(v)))
+            .collect();
+        let snapshot = Snapshot {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_VERSION,
+            identity: self.identity.borrow().clone(),
+            groups: self.group_ids.borrow().clone(),
+            leaving: self.leaving.borrow().clone(),
+            key_packages: self.key_packages.borrow().clone(),
+            values,
+        };
+        serde_json::to_vec(&snapshot).map_err(|e| format!("Failed to serialize state: {e}"))
+    }
+
+    /// Replace the whole state with a snapshot from `export_db`. On failure
+    /// the provider is left unchanged.
+    pub fn import_db(&mut self, data: &[u8]) -> Result<(), String> {
+        let snapshot: Snapshot =
+            serde_json::from_slice(data).map_err(|e| format!("Failed to parse state snapshot: {e}"))?;
+        if snapshot.format != SNAPSHOT_FORMAT || snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Unsupported state snapshot {:?} version {}",
+                snapshot.format, snapshot.version
+            ));
+        }
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let values = snapshot
+            .values
+            .iter()
+            .map(|(k, v)| Ok((b64.decode(k)?, b64.decode(v)?)))
+            .collect::<Result<HashMap<_, _>, base64::DecodeError>>()
+            .map_err(|e| format!("Invalid base64 in state snapshot: {e}"))?;
+
+        *self.storage.values.write().map_err(|_| "Storage lock poisoned".to_string())? = values;
+        *self.identity.borrow_mut() = snapshot.identity;
+        *self.group_ids.borrow_mut() = snapshot.groups;
+        *self.leaving.borrow_mut() = snapshot.leaving;
+        *self.key_packages.borrow_mut() = snapshot.key_packages;
+        Ok(())
+    }
+
+    /// Nothing to release: the state lives as long as the provider.
+    pub fn close(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl OpenMlsProvider for VoxProvider {
+    type CryptoProvider = CryptoProvider;
+    type RandProvider = CryptoProvider;
+    type StorageProvider = MemoryStorage;
+
+    fn storage(&self) -> &Self::StorageProvider {
+        &self.storage
+    }
+
+    fn crypto(&self) -> &Self::CryptoProvider {
+        &self.crypto
+    }
+
+    fn rand(&self) -> &Self::RandProvider {
+        &self.crypto
+    }
+}
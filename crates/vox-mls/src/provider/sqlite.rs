@@ -0,0 +1,1401 @@
+//! SQLite-backed provider used everywhere except wasm32.
+
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use openmls::prelude::KeyPackageBundle;
+use openmls_libcrux_crypto::CryptoProvider;
+use openmls_sqlite_storage::{Connection, SqliteStorageProvider};
+use openmls_traits::storage::{traits, StorageProvider, CURRENT_VERSION};
+use openmls_traits::{types::CryptoError, OpenMlsProvider};
+use rusqlite::backup::Backup;
+use rusqlite::params;
+use rusqlite::serialize::OwnedData;
+use rusqlite::DatabaseName;
+
+use base64::Engine;
+use openmls_sqlite_storage::Codec;
+use serde::{Deserialize, Serialize};
+
+use super::{open_key_material, seal_key_material, StorageOptions, StoredKeyPackage};
+use crate::codec::{self, json_to_cbor, CborCodec, Namespaced};
+
+/// One group's rows from the OpenMLS tables, blobs base64-encoded. Each
+/// row ends with its `provider_version`.
+#[derive(Serialize, Deserialize)]
+pub struct GroupRows {
+    /// `(data_type, group_data)`
+    group_data: Vec<(String, String, i64)>,
+    /// `(proposal_ref, proposal)`
+    proposals: Vec<(String, String, i64)>,
+    /// `(leaf_node)`
+    own_leaf_nodes: Vec<(String, i64)>,
+    /// `(epoch_id, leaf_index, key_pairs)`
+    epoch_key_pairs: Vec<(String, i64, String, i64)>,
+    /// `(public_key, key_pair)` of our leaf
+    encryption_keys: Vec<(String, String, i64)>,
+}
+
+fn b64_encode(bytes: Vec<u8>) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn b64_decode(text: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .map_err(|e| format!("Invalid base64 in group export: {e}"))
+}
+
+impl GroupRows {
+    /// Bring rows from a version `version` group export up to date:
+    /// exports before version 2 carry JSON-codec blobs.
+    pub fn upgrade(self, version: u32) -> Result<Self, String> {
+        if version >= 2 {
+            return Ok(self);
+        }
+        self.map_blobs(|blob| json_to_cbor(&blob))
+    }
+
+    /// Apply `f` to every blob.
+    fn map_blobs(self, f: impl Fn(Vec<u8>) -> Result<Vec<u8>, String>) -> Result<Self, String> {
+        let convert = |text: String| Ok::<_, String>(b64_encode(f(b64_decode(&text)?)?));
+        Ok(GroupRows {
+            group_data: self
+                .group_data
+                .into_iter()
+                .map(|(data_type, data, v)| Ok((data_type, convert(data)?, v)))
+                .collect::<Result<_, String>>()?,
+            proposals: self
+                .proposals
+                .into_iter()
+                .map(|(proposal_ref, proposal, v)| Ok((convert(proposal_ref)?, convert(proposal)?, v)))
+                .collect::<Result<_, String>>()?,
+            own_leaf_nodes: self
+                .own_leaf_nodes
+                .into_iter()
+                .map(|(leaf_node, v)| Ok((convert(leaf_node)?, v)))
+                .collect::<Result<_, String>>()?,
+            epoch_key_pairs: self
+                .epoch_key_pairs
+                .into_iter()
+                .map(|(epoch_id, leaf_index, key_pairs, v)| Ok((convert(epoch_id)?, leaf_index, convert(key_pairs)?, v)))
+                .collect::<Result<_, String>>()?,
+            encryption_keys: self
+                .encryption_keys
+                .into_iter()
+                .map(|(public_key, key_pair, v)| Ok((convert(public_key)?, convert(key_pair)?, v)))
+                .collect::<Result<_, String>>()?,
+        })
+    }
+}
+
+/// Composite OpenMLS provider: libcrux crypto + SQLite storage.
+pub struct VoxProvider {
+    db_path: String,
+    crypto: CryptoProvider,
+    connection: Arc<Mutex<Connection>>,
+    storage: SharedStorage,
+    /// Optional 256-bit key for encrypting private key material at rest.
+    /// When set, `signature_key_pair` is stored as AES-256-GCM ciphertext.
+    encryption_key: Option<[u8; 32]>,
+    /// Optional raw SQLCipher key encrypting the whole database file.
+    db_key: Option<[u8; 32]>,
+    /// Applied again to the connection `import_db` opens.
+    options: StorageOptions,
+}
+
+/// Open the database at `db_path`, unlocking it with `db_key` if given.
+/// Fails if the key is wrong or SQLite was built without SQLCipher (the
+/// `sqlcipher` feature), rather than silently storing plaintext.
+fn open_connection(db_path: &str, db_key: Option<&[u8; 32]>) -> Result<Connection, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open SQLite database: {e}"))?;
+    let Some(key) = db_key else {
+        return Ok(conn);
+    };
+    conn.query_row("PRAGMA cipher_version", [], |_| Ok(()))
+        .map_err(|_| "db_key needs SQLCipher; build vox-mls with the `sqlcipher` feature".to_string())?;
+    // A raw key, so SQLCipher skips its passphrase derivation. It is
+    // hex-encoded, so formatting it into the pragma is safe.
+    let hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+    conn.execute_batch(&format!("PRAGMA key = \"x'{hex}'\""))
+        .map_err(|e| format!("Failed to set database key: {e}"))?;
+    // SQLCipher only checks the key when the first page is read
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|e| format!("Failed to unlock database: wrong db_key or not encrypted ({e})"))?;
+    Ok(conn)
+}
+
+/// Apply journal mode, sync level and busy timeout to a new connection.
+fn configure_connection(conn: &Connection, options: &StorageOptions) -> Result<(), String> {
+    let failed = |e: rusqlite::Error| format!("Failed to configure SQLite: {e}");
+    if let Some(timeout) = options.busy_timeout {
+        conn.busy_timeout(timeout).map_err(failed)?;
+    }
+    if options.wal {
+        // Answers with the mode now in effect: "memory" for `:memory:`
+        conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)).map_err(failed)?;
+    }
+    if let Some(level) = options.synchronous {
+        conn.execute_batch(&format!("PRAGMA synchronous = {}", level.pragma_value())).map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// Lock the connection. A panic while it was held leaves nothing behind
+/// that SQLite itself does not roll back, so a poisoned lock is fine.
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    connection.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Every row of a parameterless query.
+fn query_all<T>(
+    conn: &Connection,
+    sql: &str,
+    map: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+) -> rusqlite::Result<Vec<T>> {
+    conn.prepare(sql)?.query_map([], map)?.collect()
+}
+
+/// Our own tables. Each row belongs to the identity named by its
+/// `namespace`, `''` for the default one.
+const VOX_TABLES: &str = "CREATE TABLE IF NOT EXISTS vox_identity (
+        namespace TEXT NOT NULL PRIMARY KEY,
+        user_id INTEGER NOT NULL,
+        device_id TEXT NOT NULL,
+        credential_with_key TEXT NOT NULL,
+        signature_key_pair TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS vox_groups (
+        namespace TEXT NOT NULL DEFAULT '',
+        group_id TEXT NOT NULL,
+        PRIMARY KEY (namespace, group_id)
+    );
+    CREATE TABLE IF NOT EXISTS vox_leaving (
+        namespace TEXT NOT NULL DEFAULT '',
+        group_id TEXT NOT NULL,
+        PRIMARY KEY (namespace, group_id)
+    );
+    CREATE TABLE IF NOT EXISTS vox_key_packages (
+        hash_ref BLOB PRIMARY KEY,
+        namespace TEXT NOT NULL DEFAULT '',
+        created_at INTEGER NOT NULL,
+        last_resort INTEGER NOT NULL,
+        consumed INTEGER NOT NULL DEFAULT 0
+    )";
+
+/// Create our tables, all or nothing. Those from before multiple
+/// identities get a `namespace`, their rows going to the default identity.
+fn create_vox_tables(conn: &Connection) -> Result<(), String> {
+    let columns = |table: &str| query_all(conn, &format!("PRAGMA table_info({table})"), |row| row.get::<_, String>(1));
+    let outdated = |table: &str| {
+        columns(table).map(|columns| !columns.is_empty() && !columns.iter().any(|column| column == "namespace"))
+    };
+    conn.execute_batch("SAVEPOINT vox_tables").map_err(|e| format!("Failed to create custom tables: {e}"))?;
+    let result = (|| {
+        // Their primary keys change, so these are rebuilt
+        let mut rebuilt = Vec::new();
+        for (table, copied) in [
+            ("vox_identity", "user_id, device_id, credential_with_key, signature_key_pair"),
+            ("vox_groups", "group_id"),
+            ("vox_leaving", "group_id"),
+        ] {
+            if outdated(table)? {
+                conn.execute_batch(&format!("ALTER TABLE {table} RENAME TO {table}_old"))?;
+                rebuilt.push((table, copied));
+            }
+        }
+        if outdated("vox_key_packages")? {
+            conn.execute_batch("ALTER TABLE vox_key_packages ADD COLUMN namespace TEXT NOT NULL DEFAULT ''")?;
+        }
+        conn.execute_batch(VOX_TABLES)?;
+        for (table, copied) in rebuilt {
+            conn.execute_batch(&format!(
+                "INSERT INTO {table} (namespace, {copied}) SELECT '', {copied} FROM {table}_old;
+                 DROP TABLE {table}_old"
+            ))?;
+        }
+        Ok(())
+    })();
+    match result {
+        Ok(()) => conn.execute_batch("RELEASE vox_tables"),
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK TO vox_tables; RELEASE vox_tables");
+            Err(e)
+        }
+    }
+    .map_err(|e| format!("Failed to create custom tables: {e}"))
+}
+
+/// `PRAGMA user_version` once the OpenMLS rows are CBOR. Databases below it
+/// were written with the JSON codec.
+const CBOR_FORMAT_VERSION: i64 = 1;
+
+/// Re-encode every OpenMLS blob of a JSON-codec database as CBOR, all or
+/// nothing. A no-op for databases already converted or just created.
+fn migrate_to_cbor(conn: &Connection) -> Result<(), String> {
+    let failed = |e: rusqlite::Error| format!("Failed to migrate storage to CBOR: {e}");
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(failed)?;
+    if version >= CBOR_FORMAT_VERSION {
+        return Ok(());
+    }
+
+    let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    conn.execute_batch("SAVEPOINT vox_cbor").map_err(failed)?;
+    let result = (|| {
+        let tables: Vec<String> = query_all(
+            conn,
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'openmls\\_%' ESCAPE '\\'",
+            |row| row.get(0),
+        )
+        .map_err(failed)?;
+        for table in tables {
+            let table = quote(&table);
+            let columns: Vec<String> =
+                query_all(conn, &format!("PRAGMA table_info({table})"), |row| row.get(1)).map_err(failed)?;
+            for column in columns {
+                let column = quote(&column);
+                let rows: Vec<(i64, Vec<u8>)> = query_all(
+                    conn,
+                    &format!("SELECT rowid, {column} FROM {table} WHERE typeof({column}) = 'blob'"),
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(failed)?;
+                for (rowid, json) in rows {
+                    conn.execute(
+                        &format!("UPDATE {table} SET {column} = ?1 WHERE rowid = ?2"),
+                        params![json_to_cbor(&json)?, rowid],
+                    )
+                    .map_err(failed)?;
+                }
+            }
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {CBOR_FORMAT_VERSION}")).map_err(failed)
+    })();
+    match result {
+        Ok(()) => conn.execute_batch("RELEASE vox_cbor").map_err(failed),
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK TO vox_cbor; RELEASE vox_cbor");
+            Err(e)
+        }
+    }
+}
+
+impl VoxProvider {
+    /// Create a new provider backed by the given SQLite database path.
+    /// Pass `":memory:"` for an in-memory database (backward compat).
+    ///
+    /// If `encryption_key` is provided (32 bytes), private key material will
+    /// be encrypted with AES-256-GCM before being stored in SQLite. With
+    /// `db_key`, the whole database is encrypted with SQLCipher; it is
+    /// applied before the migrations run, as are `options`.
+    pub fn new(
+        db_path: &str,
+        encryption_key: Option<[u8; 32]>,
+        db_key: Option<[u8; 32]>,
+        options: &StorageOptions,
+    ) -> Result<Self, String> {
+        let mut conn = open_connection(db_path, db_key.as_ref())?;
+        configure_connection(&conn, options)?;
+
+        // Run OpenMLS storage migrations before sharing the connection
+        // (run_migrations needs BorrowMut<Connection>)
+        {
+            let mut temp_storage = SqliteStorageProvider::<CborCodec, &mut Connection>::new(&mut conn);
+            temp_storage
+                .run_migrations()
+                .map_err(|e| format!("Failed to run storage migrations: {e}"))?;
+        }
+        migrate_to_cbor(&conn)?;
+        create_vox_tables(&conn)?;
+
+        let connection = Arc::new(Mutex::new(conn));
+        let storage = SharedStorage { connection: Arc::clone(&connection), namespace: String::new() };
+
+        let crypto = CryptoProvider::new()
+            .map_err(|e: CryptoError| format!("Failed to create crypto provider: {e:?}"))?;
+
+        Ok(VoxProvider {
+            db_path: db_path.to_string(),
+            crypto,
+            connection,
+            storage,
+            encryption_key,
+            db_key,
+            options: options.clone(),
+        })
+    }
+
+    /// The connection, locked until the guard is dropped. Do not hold it
+    /// across calls to the OpenMLS storage or other methods that lock it.
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        lock(&self.connection)
+    }
+
+    /// Save identity metadata to the `vox_identity` table.
+    ///
+    /// # Security
+    ///
+    /// This stores private key material (signature key pair) in SQLite.
+    /// The database file should be protected with appropriate filesystem
+    /// permissions. For transport, use `export_state()` + `encrypt_backup()`.
+    pub fn save_identity(
+        &self,
+        user_id: u64,
+        device_id: &str,
+        credential_with_key_json: &str,
+        signature_key_pair_json: &str,
+    ) -> Result<(), String> {
+        let user_id_i64: i64 = user_id
+            .try_into()
+            .map_err(|_| format!("user_id {user_id} exceeds i64::MAX"))?;
+
+        let stored_sig = seal_key_material(self.encryption_key.as_ref(), signature_key_pair_json)?;
+
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO vox_identity
+                 (namespace, user_id, device_id, credential_with_key, signature_key_pair)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![self.storage.namespace, user_id_i64, device_id, credential_with_key_json, stored_sig],
+            )
+            .map_err(|e| format!("Failed to save identity: {e}"))?;
+        Ok(())
+    }
+
+    /// Load identity metadata from the `vox_identity` table.
+    ///
+    /// # Security
+    ///
+    /// Returns private key material. Callers must not log or serialize the
+    /// returned signature key pair without encryption.
+    pub fn load_identity(&self) -> Result<Option<(u64, String, String, String)>, String> {
+        let connection = self.connection();
+        let mut stmt = connection
+            .prepare(
+                "SELECT user_id, device_id, credential_with_key, signature_key_pair FROM vox_identity
+                 WHERE namespace = ?1",
+            )
+            .map_err(|e| format!("Failed to prepare identity query: {e}"))?;
+
+        let result = stmt
+            .query_row(params![self.storage.namespace], |row| {
+                let user_id: i64 = row.get(0)?;
+                let user_id_u64: u64 = user_id.try_into().map_err(|_| {
+                    rusqlite::Error::IntegralValueOutOfRange(0, user_id.into())
+                })?;
+                let device_id: String = row.get(1)?;
+                let cwk_json: String = row.get(2)?;
+                let sig_stored: String = row.get(3)?;
+                Ok((user_id_u64, device_id, cwk_json, sig_stored))
+            });
+
+        match result {
+            Ok((user_id, device_id, cwk_json, sig_stored)) => {
+                let sig_json = open_key_material(self.encryption_key.as_ref(), &sig_stored)?;
+                Ok(Some((user_id, device_id, cwk_json, sig_json)))
+            }
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(format!("Failed to load identity: {e}")),
+        }
+    }
+
+    /// Handles of the identities stored in this database besides the
+    /// default one.
+    pub fn list_namespaces(&self) -> Result<Vec<String>, String> {
+        query_all(
+            &self.connection(),
+            "SELECT namespace FROM vox_identity WHERE namespace != '' ORDER BY namespace",
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to list identities: {e}"))
+    }
+
+    /// Read and write the state of the identity with this handle from now
+    /// on, `""` being the default identity. Its OpenMLS rows are kept apart
+    /// by their keys (see [`Namespaced`]), its rows in our tables by their
+    /// `namespace`.
+    pub fn set_namespace(&mut self, namespace: &str) -> Result<(), String> {
+        self.storage.namespace = namespace.to_string();
+        Ok(())
+    }
+
+    /// Record a group ID in the `vox_groups` tracking table.
+    pub fn save_group_id(&self, group_id: &str) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "INSERT OR IGNORE INTO vox_groups (namespace, group_id) VALUES (?1, ?2)",
+                params![self.storage.namespace, group_id],
+            )
+            .map_err(|e| format!("Failed to save group ID: {e}"))?;
+        Ok(())
+    }
+
+    /// Stop tracking a group ID whose state is gone.
+    pub fn forget_group_id(&self, group_id: &str) -> Result<(), String> {
+        self.atomically(|| {
+            let connection = self.connection();
+            connection
+                .execute(
+                    "DELETE FROM vox_groups WHERE namespace = ?1 AND group_id = ?2",
+                    params![self.storage.namespace, group_id],
+                )
+                .and_then(|_| {
+                    connection.execute(
+                        "DELETE FROM vox_leaving WHERE namespace = ?1 AND group_id = ?2",
+                        params![self.storage.namespace, group_id],
+                    )
+                })
+                .map_err(|e| format!("Failed to forget group ID: {e}"))?;
+            Ok(())
+        })
+    }
+
+    /// Record that we asked to leave a group, in the `vox_leaving` table.
+    pub fn mark_leaving(&self, group_id: &str) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "INSERT OR IGNORE INTO vox_leaving (namespace, group_id) VALUES (?1, ?2)",
+                params![self.storage.namespace, group_id],
+            )
+            .map_err(|e| format!("Failed to mark group as leaving: {e}"))?;
+        Ok(())
+    }
+
+    /// Whether we asked to leave a group that has not removed us yet.
+    pub fn is_leaving(&self, group_id: &str) -> Result<bool, String> {
+        self.connection()
+            .query_row(
+                "SELECT 1 FROM vox_leaving WHERE namespace = ?1 AND group_id = ?2",
+                params![self.storage.namespace, group_id],
+                |_| Ok(()),
+            )
+            .map(|()| true)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(false),
+                e => Err(format!("Failed to query leaving groups: {e}")),
+            })
+    }
+
+    /// List all group IDs tracked in the `vox_groups` table.
+    pub fn list_group_ids(&self) -> Result<Vec<String>, String> {
+        let connection = self.connection();
+        let mut stmt = connection
+            .prepare("SELECT group_id FROM vox_groups WHERE namespace = ?1")
+            .map_err(|e| format!("Failed to prepare group query: {e}"))?;
+
+        let rows = stmt
+            .query_map(params![self.storage.namespace], |row| row.get(0))
+            .map_err(|e| format!("Failed to query groups: {e}"))?;
+
+        let mut ids = Vec::new();
+        for row in rows {
+            ids.push(row.map_err(|e| format!("Failed to read group row: {e}"))?);
+        }
+        Ok(ids)
+    }
+
+    /// Run a query with one parameter and collect the rows.
+    fn query_rows<T>(
+        &self,
+        sql: &str,
+        param: &[u8],
+        map: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, String> {
+        let connection = self.connection();
+        let mut stmt = connection.prepare(sql).map_err(|e| format!("Failed to prepare group export: {e}"))?;
+        let rows = stmt
+            .query_map(params![param], map)
+            .map_err(|e| format!("Failed to query group rows: {e}"))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| format!("Failed to read group row: {e}"))
+    }
+
+    /// Record a key package we generated, in the `vox_key_packages` table.
+    pub fn track_key_package(&self, hash_ref: &[u8], last_resort: bool) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO vox_key_packages (hash_ref, namespace, created_at, last_resort, consumed)
+                 VALUES (?1, ?2, unixepoch(), ?3, 0)",
+                params![hash_ref, self.storage.namespace, last_resort],
+            )
+            .map_err(|e| format!("Failed to track key package: {e}"))?;
+        Ok(())
+    }
+
+    /// Record that a Welcome used one of our key packages.
+    pub fn mark_key_package_consumed(&self, hash_ref: &[u8]) -> Result<(), String> {
+        self.connection()
+            .execute(
+                "UPDATE vox_key_packages SET consumed = 1 WHERE hash_ref = ?1 AND namespace = ?2",
+                params![hash_ref, self.storage.namespace],
+            )
+            .map_err(|e| format!("Failed to mark key package consumed: {e}"))?;
+        Ok(())
+    }
+
+    /// Stop tracking a key package. Returns whether it was tracked.
+    pub fn untrack_key_package(&self, hash_ref: &[u8]) -> Result<bool, String> {
+        let deleted = self
+            .connection()
+            .execute(
+                "DELETE FROM vox_key_packages WHERE hash_ref = ?1 AND namespace = ?2",
+                params![hash_ref, self.storage.namespace],
+            )
+            .map_err(|e| format!("Failed to untrack key package: {e}"))?;
+        Ok(deleted > 0)
+    }
+
+    /// The key packages we generated and still track, oldest first.
+    pub fn list_key_packages(&self) -> Result<Vec<StoredKeyPackage>, String> {
+        self.connection()
+            .prepare(
+                "SELECT hash_ref, created_at, last_resort, consumed FROM vox_key_packages
+                 WHERE namespace = ?1 ORDER BY created_at, rowid",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![self.storage.namespace], |row| {
+                    Ok(StoredKeyPackage {
+                        hash_ref: row.get(0)?,
+                        created_at: row.get(1)?,
+                        last_resort: row.get(2)?,
+                        consumed: row.get(3)?,
+                    })
+                })?
+                .collect()
+            })
+            .map_err(|e| format!("Failed to list key packages: {e}"))
+    }
+
+    /// The OpenMLS rows of one group, plus the private key of our leaf
+    /// (`encryption_key`, stored apart from the group).
+    pub fn export_group_rows(
+        &self,
+        group_id: &impl Serialize,
+        encryption_key: &impl Serialize,
+    ) -> Result<GroupRows, String> {
+        let group_id =
+            CborCodec::to_vec(&self.storage.key(group_id)).map_err(|e| format!("Failed to serialize group ID: {e}"))?;
+        // Exports are not tied to an identity: the key goes without it
+        let public_key =
+            CborCodec::to_vec(encryption_key).map_err(|e| format!("Failed to serialize encryption key: {e}"))?;
+        Ok(GroupRows {
+            group_data: self.query_rows(
+                "SELECT data_type, group_data, provider_version FROM openmls_group_data WHERE group_id = ?1",
+                &group_id,
+                |row| Ok((row.get(0)?, b64_encode(row.get(1)?), row.get(2)?)),
+            )?,
+            proposals: self.query_rows(
+                "SELECT proposal_ref, proposal, provider_version FROM openmls_proposals WHERE group_id = ?1",
+                &group_id,
+                |row| Ok((b64_encode(row.get(0)?), b64_encode(row.get(1)?), row.get(2)?)),
+            )?,
+            own_leaf_nodes: self.query_rows(
+                "SELECT leaf_node, provider_version FROM openmls_own_leaf_nodes WHERE group_id = ?1",
+                &group_id,
+                |row| Ok((b64_encode(row.get(0)?), row.get(1)?)),
+            )?,
+            epoch_key_pairs: self.query_rows(
+                "SELECT epoch_id, leaf_index, key_pairs, provider_version FROM openmls_epoch_keys_pairs
+                 WHERE group_id = ?1",
+                &group_id,
+                |row| Ok((b64_encode(row.get(0)?), row.get(1)?, b64_encode(row.get(2)?), row.get(3)?)),
+            )?,
+            encryption_keys: self.query_rows(
+                "SELECT key_pair, provider_version FROM openmls_encryption_keys WHERE public_key = ?1",
+                &codec::add_namespace(&self.storage.namespace, &public_key),
+                |row| Ok((b64_encode(public_key.clone()), b64_encode(row.get(0)?), row.get(1)?)),
+            )?,
+        })
+    }
+
+    /// Replace whatever is stored for a group with `rows` from
+    /// `export_group_rows`, and track the group. Run it atomically.
+    /// The rows go to the current identity.
+    pub fn import_group_rows(
+        &self,
+        group_id_str: &str,
+        group_id: &impl Serialize,
+        rows: &GroupRows,
+    ) -> Result<(), String> {
+        let group_id =
+            CborCodec::to_vec(&self.storage.key(group_id)).map_err(|e| format!("Failed to serialize group ID: {e}"))?;
+        let conn = self.connection();
+        let failed = |e: rusqlite::Error| format!("Failed to import group: {e}");
+
+        conn.execute("DELETE FROM openmls_group_data WHERE group_id = ?1", params![group_id]).map_err(failed)?;
+        conn.execute("DELETE FROM openmls_proposals WHERE group_id = ?1", params![group_id]).map_err(failed)?;
+        conn.execute("DELETE FROM openmls_own_leaf_nodes WHERE group_id = ?1", params![group_id]).map_err(failed)?;
+        conn.execute("DELETE FROM openmls_epoch_keys_pairs WHERE group_id = ?1", params![group_id]).map_err(failed)?;
+
+        for (data_type, data, version) in &rows.group_data {
+            conn.execute(
+                "INSERT INTO openmls_group_data (group_id, data_type, group_data, provider_version)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![group_id, data_type, b64_decode(data)?, version],
+            )
+            .map_err(failed)?;
+        }
+        for (proposal_ref, proposal, version) in &rows.proposals {
+            conn.execute(
+                "INSERT INTO openmls_proposals (group_id, proposal_ref, proposal, provider_version)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![group_id, b64_decode(proposal_ref)?, b64_decode(proposal)?, version],
+            )
+            .map_err(failed)?;
+        }
+        for (leaf_node, version) in &rows.own_leaf_nodes {
+            conn.execute(
+                "INSERT INTO openmls_own_leaf_nodes (group_id, leaf_node, provider_version) VALUES (?1, ?2, ?3)",
+                params![group_id, b64_decode(leaf_node)?, version],
+            )
+            .map_err(failed)?;
+        }
+        for (epoch_id, leaf_index, key_pairs, version) in &rows.epoch_key_pairs {
+            conn.execute(
+                "INSERT INTO openmls_epoch_keys_pairs (group_id, epoch_id, leaf_index, key_pairs, provider_version)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![group_id, b64_decode(epoch_id)?, leaf_index, b64_decode(key_pairs)?, version],
+            )
+            .map_err(failed)?;
+        }
+        for (public_key, key_pair, version) in &rows.encryption_keys {
+            conn.execute(
+                "INSERT OR REPLACE INTO openmls_encryption_keys (public_key, key_pair, provider_version)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    codec::add_namespace(&self.storage.namespace, &b64_decode(public_key)?),
+                    b64_decode(key_pair)?,
+                    version
+                ],
+            )
+            .map_err(failed)?;
+        }
+        drop(conn);
+        self.save_group_id(group_id_str)
+    }
+
+    /// Delete a group's epoch key pairs for epochs before `oldest_epoch`.
+    /// Returns how many rows went.
+    pub fn prune_epoch_key_pairs(&self, group_id: &impl Serialize, oldest_epoch: u64) -> Result<usize, String> {
+        let group_id =
+            CborCodec::to_vec(&self.storage.key(group_id)).map_err(|e| format!("Failed to serialize group ID: {e}"))?;
+        let failed = |e: rusqlite::Error| format!("Failed to prune epoch keys: {e}");
+        let rows: Vec<(i64, Vec<u8>)> = self
+            .connection()
+            .prepare("SELECT rowid, epoch_id FROM openmls_epoch_keys_pairs WHERE group_id = ?1")
+            .and_then(|mut stmt| stmt.query_map(params![group_id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect())
+            .map_err(failed)?;
+        let mut pruned = 0;
+        for (rowid, epoch_id) in rows {
+            let epoch: u64 = CborCodec::from_slice(&epoch_id).map_err(|e| format!("Invalid stored epoch: {e}"))?;
+            if epoch < oldest_epoch {
+                pruned += self
+                    .connection()
+                    .execute("DELETE FROM openmls_epoch_keys_pairs WHERE rowid = ?1", params![rowid])
+                    .map_err(failed)?;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Delete key packages whose lifetime has run out, with the private
+    /// key of their leaf, and stop tracking them. Nobody can add us with
+    /// them any more. Returns how many went.
+    pub fn prune_key_packages(&self) -> Result<usize, String> {
+        let failed = |e: rusqlite::Error| format!("Failed to prune key packages: {e}");
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| format!("System clock is before 1970: {e}"))?
+            .as_secs();
+        let rows: Vec<(i64, Vec<u8>, Vec<u8>)> = query_all(
+            &self.connection(),
+            "SELECT rowid, key_package_ref, key_package FROM openmls_key_packages",
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(failed)?;
+        let mut pruned = 0;
+        for (rowid, hash_ref, bundle) in rows {
+            if !codec::in_namespace(&self.storage.namespace, &hash_ref) {
+                continue;
+            }
+            let bundle: KeyPackageBundle =
+                CborCodec::from_slice(&bundle).map_err(|e| format!("Invalid stored key package: {e}"))?;
+            let key_package = bundle.key_package();
+            if key_package.life_time().not_after() >= now {
+                continue;
+            }
+            let public_key = CborCodec::to_vec(&self.storage.key(key_package.leaf_node().encryption_key()))
+                .map_err(|e| format!("Failed to serialize encryption key: {e}"))?;
+            self.connection()
+                .execute("DELETE FROM openmls_encryption_keys WHERE public_key = ?1", params![public_key])
+                .map_err(failed)?;
+            let hash_ref = key_package
+                .hash_ref(self.crypto())
+                .map_err(|e| format!("Failed to hash key package: {e:?}"))?;
+            self.untrack_key_package(hash_ref.as_slice())?;
+            pruned += self
+                .connection()
+                .execute("DELETE FROM openmls_key_packages WHERE rowid = ?1", params![rowid])
+                .map_err(failed)?;
+        }
+        Ok(pruned)
+    }
+
+    /// Bytes the database takes up, free pages included, without its
+    /// write-ahead log.
+    pub fn database_size(&self) -> Result<u64, String> {
+        self.connection()
+            .query_row("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|bytes| bytes as u64)
+            .map_err(|e| format!("Failed to read database size: {e}"))
+    }
+
+    /// Rebuild the database file without its free pages. Cannot run
+    /// inside [`atomically`](Self::atomically).
+    pub fn vacuum(&self) -> Result<(), String> {
+        self.connection().execute_batch("VACUUM").map_err(|e| format!("Failed to vacuum database: {e}"))
+    }
+
+    /// Run `f` as one transaction: what it stores is committed if it
+    /// returns `Ok` and rolled back if it fails, and a crash midway leaves
+    /// none of it behind. Nests inside other savepoints.
+    pub fn atomically<T, E: From<String>>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        self.connection()
+            .execute_batch("SAVEPOINT vox_atomic")
+            .map_err(|e| format!("Failed to open savepoint: {e}"))?;
+        let result = f();
+        let end = match result {
+            Ok(_) => "RELEASE vox_atomic",
+            Err(_) => "ROLLBACK TO vox_atomic; RELEASE vox_atomic",
+        };
+        let ended = self.connection().execute_batch(end);
+        if let Err(e) = ended {
+            // Do not leave the transaction open behind a failed commit
+            let _ = self.connection().execute_batch("ROLLBACK TO vox_atomic; RELEASE vox_atomic");
+            return Err(format!("Failed to end savepoint: {e}").into());
+        }
+        result
+    }
+
+    /// Run `f` inside a savepoint that is rolled back afterwards, so nothing
+    /// it stores reaches the database.
+    pub fn rolled_back<T>(&self, f: impl FnOnce() -> T) -> Result<T, String> {
+        self.connection()
+            .execute_batch("SAVEPOINT vox_rolled_back")
+            .map_err(|e| format!("Failed to open savepoint: {e}"))?;
+        let result = f();
+        self.connection()
+            .execute_batch("ROLLBACK TO vox_rolled_back; RELEASE vox_rolled_back")
+            .map_err(|e| format!("Failed to roll back savepoint: {e}"))?;
+        Ok(result)
+    }
+
+    /// Export the entire SQLite database as raw bytes (for full state backup).
+    ///
+    /// Uses SQLite's serialize API — no temporary files are created.
+    pub fn export_db(&self) -> Result<Vec<u8>, String> {
+        let connection = self.connection();
+        let data = connection
+            .serialize(DatabaseName::Main)
+            .map_err(|e| format!("Failed to serialize database: {e}"))?;
+        Ok(data.to_vec())
+    }
+
+    /// Restore the full SQLite database from raw bytes (for full state restore).
+    ///
+    /// Deserializes the backup into a temporary in-memory connection, then uses
+    /// the Backup API to atomically copy into a fresh connection at the original
+    /// database path. No temporary files or dynamic SQL are used.
+    ///
+    /// All fallible operations complete before `self` is mutated, so on failure
+    /// the provider remains in its previous valid state.
+    pub fn import_db(&mut self, data: &[u8]) -> Result<(), String> {
+        // 1. Allocate sqlite3_malloc memory and copy backup data into it.
+        //    OwnedData requires sqlite3_malloc-allocated memory because it
+        //    calls sqlite3_free on drop.
+        let owned_data = {
+            let ptr = unsafe { rusqlite::ffi::sqlite3_malloc64(data.len() as u64) } as *mut u8;
+            if ptr.is_null() {
+                return Err("Failed to allocate memory for deserialization".to_string());
+            }
+            unsafe {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+                OwnedData::from_raw_nonnull(NonNull::new_unchecked(ptr), data.len())
+            }
+        };
+
+        // 2. Deserialize backup into a temporary in-memory connection
+        let mut mem_conn = Connection::open_in_memory()
+            .map_err(|e| format!("Failed to open in-memory database: {e}"))?;
+        mem_conn
+            .deserialize(DatabaseName::Main, owned_data, false)
+            .map_err(|e| format!("Failed to deserialize backup: {e}"))?;
+
+        // 3. Open a fresh connection at the original path, with the same key
+        let mut new_conn = open_connection(&self.db_path, self.db_key.as_ref())?;
+        configure_connection(&new_conn, &self.options)?;
+
+        // 4. Atomically copy from in-memory → new connection via Backup API
+        {
+            let backup = Backup::new(&mem_conn, &mut new_conn)
+                .map_err(|e| format!("Failed to initialize backup: {e}"))?;
+            backup
+                .run_to_completion(100, std::time::Duration::ZERO, None)
+                .map_err(|e| format!("Failed to restore backup: {e}"))?;
+        }
+
+        // 5. The restored schema already contains OpenMLS tables from the
+        //    source database, so we skip run_migrations() here — re-running
+        //    migrations on an already-migrated schema risks failures if any
+        //    migration is not idempotent. Backups from before the CBOR codec
+        //    still need their rows converted.
+        migrate_to_cbor(&new_conn)?;
+        create_vox_tables(&new_conn)?;
+
+        // 6. Build the new shared connection and storage provider from local variables.
+        //    Only assign to self after all fallible operations above have succeeded,
+        //    so that a failure leaves self unchanged.
+        let connection = Arc::new(Mutex::new(new_conn));
+        let new_storage =
+            SharedStorage { connection: Arc::clone(&connection), namespace: self.storage.namespace.clone() };
+
+        // --- Non-fallible swap: self is only mutated here ---
+        self.connection = connection;
+        self.storage = new_storage;
+
+        Ok(())
+    }
+
+    /// Close the database now rather than when dropped, checkpointing the
+    /// WAL first so that the file holds everything and is no longer open.
+    pub fn close(self) -> Result<(), String> {
+        let VoxProvider { connection, storage, .. } = self;
+        drop(storage);
+        let connection = Arc::try_unwrap(connection)
+            .map_err(|_| "Database connection is still in use".to_string())?
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Answers with a row of counts, which are of no interest
+        connection
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(|e| format!("Failed to checkpoint database: {e}"))?;
+        connection.close().map_err(|(_, e)| format!("Failed to close database: {e}"))
+    }
+}
+
+impl OpenMlsProvider for VoxProvider {
+    type CryptoProvider = CryptoProvider;
+    type RandProvider = CryptoProvider;
+    type StorageProvider = SharedStorage;
+
+    fn storage(&self) -> &Self::StorageProvider {
+        &self.storage
+    }
+
+    fn crypto(&self) -> &Self::CryptoProvider {
+        &self.crypto
+    }
+
+    fn rand(&self) -> &Self::RandProvider {
+        &self.crypto
+    }
+}
+
+/// OpenMLS storage on the provider's connection. Each call locks the
+/// connection and goes to a `SqliteStorageProvider` borrowing it, with
+/// the keys of the current identity.
+pub struct SharedStorage {
+    connection: Arc<Mutex<Connection>>,
+    /// Handle of the identity whose state we read and write; empty for
+    /// the default identity.
+    namespace: String,
+}
+
+impl SharedStorage {
+    fn with<T>(&self, f: impl FnOnce(&SqliteStorageProvider<CborCodec, &Connection>) -> T) -> T {
+        let connection = lock(&self.connection);
+        f(&SqliteStorageProvider::new(&*connection))
+    }
+
+    fn key<'a, K: ?Sized>(&'a self, key: &'a K) -> Namespaced<'a, K> {
+        Namespaced { namespace: &self.namespace, key }
+    }
+}
+
+impl StorageProvider<CURRENT_VERSION> for SharedStorage {
+    type Error = <SqliteStorageProvider<CborCodec, Connection> as StorageProvider<CURRENT_VERSION>>::Error;
+
+    fn write_mls_join_config<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MlsGroupJoinConfig: traits::MlsGroupJoinConfig<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        config: &MlsGroupJoinConfig,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_mls_join_config(&self.key(group_id), config))
+    }
+
+    fn append_own_leaf_node<GroupId: traits::GroupId<CURRENT_VERSION>, LeafNode: traits::LeafNode<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+        leaf_node: &LeafNode,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.append_own_leaf_node(&self.key(group_id), leaf_node))
+    }
+
+    fn queue_proposal<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+        QueuedProposal: traits::QueuedProposal<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        proposal_ref: &ProposalRef,
+        proposal: &QueuedProposal,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.queue_proposal(&self.key(group_id), proposal_ref, proposal))
+    }
+
+    fn write_tree<GroupId: traits::GroupId<CURRENT_VERSION>, TreeSync: traits::TreeSync<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+        tree: &TreeSync,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_tree(&self.key(group_id), tree))
+    }
+
+    fn write_interim_transcript_hash<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        InterimTranscriptHash: traits::InterimTranscriptHash<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        interim_transcript_hash: &InterimTranscriptHash,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_interim_transcript_hash(&self.key(group_id), interim_transcript_hash))
+    }
+
+    fn write_context<GroupId: traits::GroupId<CURRENT_VERSION>, GroupContext: traits::GroupContext<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+        group_context: &GroupContext,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_context(&self.key(group_id), group_context))
+    }
+
+    fn write_confirmation_tag<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ConfirmationTag: traits::ConfirmationTag<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        confirmation_tag: &ConfirmationTag,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_confirmation_tag(&self.key(group_id), confirmation_tag))
+    }
+
+    fn write_group_state<GroupState: traits::GroupState<CURRENT_VERSION>, GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+        group_state: &GroupState,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_group_state(&self.key(group_id), group_state))
+    }
+
+    fn write_message_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MessageSecrets: traits::MessageSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        message_secrets: &MessageSecrets,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_message_secrets(&self.key(group_id), message_secrets))
+    }
+
+    fn write_resumption_psk_store<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ResumptionPskStore: traits::ResumptionPskStore<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        resumption_psk_store: &ResumptionPskStore,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_resumption_psk_store(&self.key(group_id), resumption_psk_store))
+    }
+
+    fn write_own_leaf_index<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        LeafNodeIndex: traits::LeafNodeIndex<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        own_leaf_index: &LeafNodeIndex,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_own_leaf_index(&self.key(group_id), own_leaf_index))
+    }
+
+    fn write_group_epoch_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupEpochSecrets: traits::GroupEpochSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        group_epoch_secrets: &GroupEpochSecrets,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_group_epoch_secrets(&self.key(group_id), group_epoch_secrets))
+    }
+
+    fn write_signature_key_pair<
+        SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>,
+        SignatureKeyPair: traits::SignatureKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &SignaturePublicKey,
+        signature_key_pair: &SignatureKeyPair,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_signature_key_pair(&self.key(public_key), signature_key_pair))
+    }
+
+    fn write_encryption_key_pair<
+        EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &EncryptionKey,
+        key_pair: &HpkeKeyPair,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_encryption_key_pair(&self.key(public_key), key_pair))
+    }
+
+    fn write_encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+        key_pairs: &[HpkeKeyPair],
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_encryption_epoch_key_pairs(&self.key(group_id), epoch, leaf_index, key_pairs))
+    }
+
+    fn write_key_package<
+        HashReference: traits::HashReference<CURRENT_VERSION>,
+        KeyPackage: traits::KeyPackage<CURRENT_VERSION>,
+    >(
+        &self,
+        hash_ref: &HashReference,
+        key_package: &KeyPackage,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_key_package(&self.key(hash_ref), key_package))
+    }
+
+    fn write_psk<PskId: traits::PskId<CURRENT_VERSION>, PskBundle: traits::PskBundle<CURRENT_VERSION>>(
+        &self,
+        psk_id: &PskId,
+        psk: &PskBundle,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.write_psk(&self.key(psk_id), psk))
+    }
+
+    fn mls_group_join_config<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MlsGroupJoinConfig: traits::MlsGroupJoinConfig<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<MlsGroupJoinConfig>, Self::Error> {
+        self.with(|storage| storage.mls_group_join_config(&self.key(group_id)))
+    }
+
+    fn own_leaf_nodes<GroupId: traits::GroupId<CURRENT_VERSION>, LeafNode: traits::LeafNode<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<LeafNode>, Self::Error> {
+        self.with(|storage| storage.own_leaf_nodes(&self.key(group_id)))
+    }
+
+    fn queued_proposal_refs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<ProposalRef>, Self::Error> {
+        self.with(|storage| storage.queued_proposal_refs(&self.key(group_id)))
+    }
+
+    fn queued_proposals<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+        QueuedProposal: traits::QueuedProposal<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<(ProposalRef, QueuedProposal)>, Self::Error> {
+        self.with(|storage| storage.queued_proposals(&self.key(group_id)))
+    }
+
+    fn tree<GroupId: traits::GroupId<CURRENT_VERSION>, TreeSync: traits::TreeSync<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<TreeSync>, Self::Error> {
+        self.with(|storage| storage.tree(&self.key(group_id)))
+    }
+
+    fn group_context<GroupId: traits::GroupId<CURRENT_VERSION>, GroupContext: traits::GroupContext<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupContext>, Self::Error> {
+        self.with(|storage| storage.group_context(&self.key(group_id)))
+    }
+
+    fn interim_transcript_hash<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        InterimTranscriptHash: traits::InterimTranscriptHash<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<InterimTranscriptHash>, Self::Error> {
+        self.with(|storage| storage.interim_transcript_hash(&self.key(group_id)))
+    }
+
+    fn confirmation_tag<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ConfirmationTag: traits::ConfirmationTag<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<ConfirmationTag>, Self::Error> {
+        self.with(|storage| storage.confirmation_tag(&self.key(group_id)))
+    }
+
+    fn group_state<GroupState: traits::GroupState<CURRENT_VERSION>, GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupState>, Self::Error> {
+        self.with(|storage| storage.group_state(&self.key(group_id)))
+    }
+
+    fn message_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MessageSecrets: traits::MessageSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<MessageSecrets>, Self::Error> {
+        self.with(|storage| storage.message_secrets(&self.key(group_id)))
+    }
+
+    fn resumption_psk_store<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ResumptionPskStore: traits::ResumptionPskStore<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<ResumptionPskStore>, Self::Error> {
+        self.with(|storage| storage.resumption_psk_store(&self.key(group_id)))
+    }
+
+    fn own_leaf_index<GroupId: traits::GroupId<CURRENT_VERSION>, LeafNodeIndex: traits::LeafNodeIndex<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<LeafNodeIndex>, Self::Error> {
+        self.with(|storage| storage.own_leaf_index(&self.key(group_id)))
+    }
+
+    fn group_epoch_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupEpochSecrets: traits::GroupEpochSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupEpochSecrets>, Self::Error> {
+        self.with(|storage| storage.group_epoch_secrets(&self.key(group_id)))
+    }
+
+    fn signature_key_pair<
+        SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>,
+        SignatureKeyPair: traits::SignatureKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &SignaturePublicKey,
+    ) -> Result<Option<SignatureKeyPair>, Self::Error> {
+        self.with(|storage| storage.signature_key_pair(&self.key(public_key)))
+    }
+
+    fn encryption_key_pair<
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+        EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &EncryptionKey,
+    ) -> Result<Option<HpkeKeyPair>, Self::Error> {
+        self.with(|storage| storage.encryption_key_pair(&self.key(public_key)))
+    }
+
+    fn encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+    ) -> Result<Vec<HpkeKeyPair>, Self::Error> {
+        self.with(|storage| storage.encryption_epoch_key_pairs(&self.key(group_id), epoch, leaf_index))
+    }
+
+    fn key_package<
+        KeyPackageRef: traits::HashReference<CURRENT_VERSION>,
+        KeyPackage: traits::KeyPackage<CURRENT_VERSION>,
+    >(
+        &self,
+        hash_ref: &KeyPackageRef,
+    ) -> Result<Option<KeyPackage>, Self::Error> {
+        self.with(|storage| storage.key_package(&self.key(hash_ref)))
+    }
+
+    fn psk<PskBundle: traits::PskBundle<CURRENT_VERSION>, PskId: traits::PskId<CURRENT_VERSION>>(
+        &self,
+        psk_id: &PskId,
+    ) -> Result<Option<PskBundle>, Self::Error> {
+        self.with(|storage| storage.psk(&self.key(psk_id)))
+    }
+
+    fn remove_proposal<GroupId: traits::GroupId<CURRENT_VERSION>, ProposalRef: traits::ProposalRef<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+        proposal_ref: &ProposalRef,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.remove_proposal(&self.key(group_id), proposal_ref))
+    }
+
+    fn delete_own_leaf_nodes<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_own_leaf_nodes(&self.key(group_id)))
+    }
+
+    fn delete_group_config<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_group_config(&self.key(group_id)))
+    }
+
+    fn delete_tree<GroupId: traits::GroupId<CURRENT_VERSION>>(&self, group_id: &GroupId) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_tree(&self.key(group_id)))
+    }
+
+    fn delete_confirmation_tag<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_confirmation_tag(&self.key(group_id)))
+    }
+
+    fn delete_group_state<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_group_state(&self.key(group_id)))
+    }
+
+    fn delete_context<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_context(&self.key(group_id)))
+    }
+
+    fn delete_interim_transcript_hash<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_interim_transcript_hash(&self.key(group_id)))
+    }
+
+    fn delete_message_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_message_secrets(&self.key(group_id)))
+    }
+
+    fn delete_all_resumption_psk_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_all_resumption_psk_secrets(&self.key(group_id)))
+    }
+
+    fn delete_own_leaf_index<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_own_leaf_index(&self.key(group_id)))
+    }
+
+    fn delete_group_epoch_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_group_epoch_secrets(&self.key(group_id)))
+    }
+
+    fn clear_proposal_queue<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.clear_proposal_queue::<_, ProposalRef>(&self.key(group_id)))
+    }
+
+    fn delete_signature_key_pair<SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>>(
+        &self,
+        public_key: &SignaturePublicKey,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_signature_key_pair(&self.key(public_key)))
+    }
+
+    fn delete_encryption_key_pair<EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>>(
+        &self,
+        public_key: &EncryptionKey,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_encryption_key_pair(&self.key(public_key)))
+    }
+
+    fn delete_encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_encryption_epoch_key_pairs(&self.key(group_id), epoch, leaf_index))
+    }
+
+    fn delete_key_package<KeyPackageRef: traits::HashReference<CURRENT_VERSION>>(
+        &self,
+        hash_ref: &KeyPackageRef,
+    ) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_key_package(&self.key(hash_ref)))
+    }
+
+    fn delete_psk<PskKey: traits::PskId<CURRENT_VERSION>>(&self, psk_id: &PskKey) -> Result<(), Self::Error> {
+        self.with(|storage| storage.delete_psk(&self.key(psk_id)))
+    }
+}
@@ -0,0 +1,159 @@
+//! Decrypting push notification payloads outside the main app.
+//!
+//! A push payload is a compact JSON envelope, `{"v": 1, "g": group_id,
+//! "m": base64(MLS application message)}`, small enough for APNs and FCM.
+//! Notification extensions are short-lived processes that share the app's
+//! database, so [`decrypt_push`] opens the database itself, decrypts one
+//! message and by default rolls its changes back: MLS deletes a message key
+//! once used, and the app still has to decrypt the same message when it
+//! syncs.
+
+use base64::Engine;
+use openmls::prelude::{GroupId, MlsGroup};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::engine::{MlsError, MlsResult};
+use crate::group;
+use crate::provider::{StorageOptions, VoxProvider};
+
+/// Envelope version written by [`encode_push_payload`].
+const PUSH_VERSION: u64 = 1;
+/// How long to wait for the app to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn invalid(msg: impl Into<String>) -> MlsError {
+    MlsError::InvalidInput(msg.into())
+}
+
+/// A decrypted push notification.
+#[cfg_attr(feature = "python", pyclass)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct PushNotification {
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub group_id: String,
+    /// Sender credential identity, `"<user_id>:<device_id>"`.
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub sender: String,
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub sender_user_id: Option<u64>,
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub sender_device_id: Option<String>,
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub epoch: u64,
+    /// The start of the plaintext for display, or None if it is not UTF-8.
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub preview: Option<String>,
+    #[cfg_attr(feature = "python", pyo3(get))]
+    pub plaintext: Vec<u8>,
+}
+
+/// Truncate to `max_chars` characters, marking the cut with an ellipsis.
+fn preview_text(plaintext: &[u8], max_chars: usize) -> Option<String> {
+    let text = std::str::from_utf8(plaintext).ok()?;
+    match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => Some(format!("{}…", text[..cut].trim_end())),
+        None => Some(text.to_string()),
+    }
+}
+
+/// Build a push payload for an MLS application message.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn encode_push_payload(group_id: &str, message: Vec<u8>) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(message);
+    json!({ "v": PUSH_VERSION, "g": group_id, "m": data }).to_string()
+}
+
+/// Decrypt a push payload with the MLS state in the database at `db_path`.
+///
+/// Needs no `MlsEngine` or media session, so it suits notification
+/// extension processes. `encryption_key` and `db_key` must match the ones
+/// the app opens the database with. Only application messages are accepted; commits and
+/// proposals must wait for the app.
+///
+/// With `consume=False` (the default) the decryption leaves the database
+/// untouched and the app can still decrypt the message later. Pass
+/// `consume=True` only if the app will never see the message itself.
+///
+/// `preview_len` caps the preview in characters. Raises `ValueError` for a
+/// malformed payload and `KeyError` if the group is not in the database.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (payload, db_path, encryption_key=None, preview_len=120, consume=false, db_key=None))]
+pub fn decrypt_push(
+    py: Python<'_>,
+    payload: &str,
+    db_path: &str,
+    encryption_key: Option<Vec<u8>>,
+    preview_len: usize,
+    consume: bool,
+    db_key: Option<Vec<u8>>,
+) -> PyResult<PushNotification> {
+    let encryption_key = crate::python::check_key("encryption_key", encryption_key)?;
+    let db_key = crate::python::check_key("db_key", db_key)?;
+    Ok(py.detach(|| decrypt_push_payload(payload, db_path, encryption_key, db_key, preview_len, consume))?)
+}
+
+/// Decrypt a push payload; see the Python `decrypt_push` for the details.
+/// Unless `consume` is set, the database is left as it was.
+pub fn decrypt_push_payload(
+    payload: &str,
+    db_path: &str,
+    encryption_key: Option<[u8; 32]>,
+    db_key: Option<[u8; 32]>,
+    preview_len: usize,
+    consume: bool,
+) -> MlsResult<PushNotification> {
+    let envelope: Value = serde_json::from_str(payload).map_err(|e| invalid(format!("invalid push payload: {e}")))?;
+    let version = envelope.get("v").and_then(Value::as_u64);
+    if version != Some(PUSH_VERSION) {
+        return Err(invalid(format!("unsupported push payload version {version:?}")));
+    }
+    let group_id = envelope
+        .get("g")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("push payload has no group id"))?
+        .to_string();
+    let message = envelope
+        .get("m")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("push payload has no message"))
+        .and_then(|m| {
+            base64::engine::general_purpose::STANDARD
+                .decode(m)
+                .map_err(|e| invalid(format!("invalid base64 in push payload: {e}")))
+        })?;
+
+    let options = StorageOptions { busy_timeout: Some(BUSY_TIMEOUT), ..StorageOptions::default() };
+    let provider = VoxProvider::new(db_path, encryption_key, db_key, &options)?;
+    let decrypt = || {
+        let gid = GroupId::from_slice(group_id.as_bytes());
+        let Some(mut mls_group) = MlsGroup::load(provider.storage(), &gid)
+            .map_err(|e| format!("Failed to load group '{group_id}': {e:?}"))?
+        else {
+            return Ok(None);
+        };
+        let epoch = mls_group.epoch().as_u64();
+        group::decrypt_application(&provider, &mut mls_group, &message)
+            .map(|(plaintext, sender)| Some((epoch, plaintext, sender)))
+    };
+    let decrypted = if consume { decrypt() } else { provider.rolled_back(decrypt)? };
+    let (epoch, plaintext, sender) = decrypted?.ok_or_else(|| MlsError::UnknownGroup(group_id.clone()))?;
+
+    let sender = String::from_utf8_lossy(&sender).into_owned();
+    let (sender_user_id, sender_device_id) = match sender.split_once(':') {
+        Some((user, device)) => (user.parse().ok(), Some(device.to_string())),
+        None => (None, None),
+    };
+    Ok(PushNotification {
+        group_id,
+        sender,
+        sender_user_id,
+        sender_device_id,
+        epoch,
+        preview: preview_text(&plaintext, preview_len),
+        plaintext,
+    })
+}
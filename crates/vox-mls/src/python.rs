@@ -0,0 +1,1134 @@
+//! The `vox_mls` Python module: thin PyO3 wrappers over [`crate::engine`]
+//! and [`crate::push`]. Built with the `python` feature.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use vox_core::key_sink::{KeySink, RawKeySink, CAPSULE_NAME};
+
+use crate::engine::{self, MlsError};
+use crate::group::{self, ProcessedResult};
+use crate::{profile, push, telemetry};
+
+pyo3::create_exception!(
+    vox_mls,
+    WrongEpochError,
+    pyo3::exceptions::PyRuntimeError,
+    "A message for an epoch the group has already left. Its message_epoch and current_epoch attributes \
+     hold the two epochs; nothing was changed, so the message can be dropped."
+);
+
+impl From<MlsError> for PyErr {
+    fn from(e: MlsError) -> Self {
+        match e {
+            MlsError::InvalidInput(_) => PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()),
+            MlsError::UnknownGroup(_) => PyErr::new::<pyo3::exceptions::PyKeyError, _>(e.to_string()),
+            MlsError::Failed(_) => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()),
+            MlsError::WrongEpoch { message_epoch, current_epoch } => Python::attach(|py| {
+                let err = WrongEpochError::new_err(e.to_string());
+                let value = err.value(py);
+                // Setting attributes on a fresh exception cannot fail
+                let _ = value.setattr("message_epoch", message_epoch);
+                let _ = value.setattr("current_epoch", current_epoch);
+                err
+            }),
+        }
+    }
+}
+
+/// Look up a ciphersuite by name, raising `ValueError` if unsupported.
+fn parse_ciphersuite(name: &str) -> PyResult<crate::Ciphersuite> {
+    crate::parse_ciphersuite(name).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)
+}
+
+/// Key package options from Python arguments.
+fn key_package_options(
+    lifetime_secs: Option<u64>,
+    extensions: Option<Vec<(u16, Vec<u8>)>>,
+    capability_extensions: Option<Vec<u16>>,
+    last_resort: bool,
+) -> PyResult<crate::KeyPackageOptions> {
+    if lifetime_secs == Some(0) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("lifetime_secs must be positive"));
+    }
+    Ok(crate::KeyPackageOptions {
+        lifetime_secs,
+        extensions: extensions.unwrap_or_default(),
+        capability_extensions: capability_extensions.unwrap_or_default(),
+        last_resort,
+    })
+}
+
+/// Check a database key argument passed from Python.
+pub(crate) fn check_key(name: &str, key: Option<Vec<u8>>) -> PyResult<Option<[u8; 32]>> {
+    key.map(|k| {
+        k.try_into().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{name} must be exactly 32 bytes"))
+        })
+    })
+    .transpose()
+}
+
+/// Result of processing an incoming MLS message.
+#[pyclass]
+struct ProcessedMessage {
+    #[pyo3(get)]
+    kind: String, // "application", "commit", "proposal", "external_join_proposal", "buffered", "staged_commit",
+    // "own_commit"
+    #[pyo3(get)]
+    data: Option<Vec<u8>>, // plaintext for application messages
+    #[pyo3(get)]
+    staged_commit: Option<StagedCommitInfo>, // for "staged_commit"
+    #[pyo3(get)]
+    epoch: u64, // the epoch the message was sent in
+    #[pyo3(get)]
+    sender_identity: Option<Vec<u8>>, // "<user_id>:<device_id>"; None while buffered
+    #[pyo3(get)]
+    sender_leaf_index: Option<u32>, // None for non-members and while buffered
+}
+
+impl From<engine::ProcessedMessage> for ProcessedMessage {
+    fn from(processed: engine::ProcessedMessage) -> Self {
+        let mut staged_commit = None;
+        let (kind, data) = match processed.result {
+            ProcessedResult::Application(plaintext) => ("application", Some(plaintext)),
+            ProcessedResult::Commit => ("commit", None),
+            ProcessedResult::Proposal => ("proposal", None),
+            ProcessedResult::ExternalJoinProposal => ("external_join_proposal", None),
+            ProcessedResult::Buffered => ("buffered", None),
+            ProcessedResult::OwnCommit => ("own_commit", None),
+            ProcessedResult::StagedCommit(info) => {
+                staged_commit = Some(info.into());
+                ("staged_commit", None)
+            }
+        };
+        ProcessedMessage {
+            kind: kind.to_string(),
+            data,
+            staged_commit,
+            epoch: processed.epoch,
+            sender_identity: processed.sender_identity,
+            sender_leaf_index: processed.sender.and_then(|s| s.leaf_index()),
+        }
+    }
+}
+
+/// The membership changes of a commit staged by `process_message`.
+#[pyclass]
+#[derive(Clone)]
+struct StagedCommitInfo {
+    #[pyo3(get)]
+    adds: Vec<Vec<u8>>, // credential identities of added members
+    #[pyo3(get)]
+    removes: Vec<(u32, Vec<u8>)>, // (leaf_index, identity) of removed members
+    #[pyo3(get)]
+    updates: Vec<(u32, Vec<u8>)>, // (leaf_index, identity) of members whose leaf is replaced
+}
+
+impl From<group::StagedCommitInfo> for StagedCommitInfo {
+    fn from(info: group::StagedCommitInfo) -> Self {
+        StagedCommitInfo { adds: info.adds, removes: info.removes, updates: info.updates }
+    }
+}
+
+/// What `peek_message` reads from a message's envelope.
+#[pyclass]
+struct MessageInfo {
+    #[pyo3(get)]
+    group_id: String,
+    #[pyo3(get)]
+    epoch: u64,
+    #[pyo3(get)]
+    content_type: String, // "application", "proposal", "commit"
+    #[pyo3(get)]
+    sender_type: Option<String>, // None for encrypted messages
+    #[pyo3(get)]
+    sender_index: Option<u32>, // leaf index of a member sender
+}
+
+impl From<engine::MessageInfo> for MessageInfo {
+    fn from(info: engine::MessageInfo) -> Self {
+        MessageInfo {
+            group_id: info.group_id,
+            epoch: info.epoch,
+            content_type: info.content_type.to_string(),
+            sender_type: info.sender.map(|s| s.kind().to_string()),
+            sender_index: info.sender.and_then(|s| s.index()),
+        }
+    }
+}
+
+/// A proposal waiting to be committed, from `list_pending_proposals`.
+#[pyclass]
+struct PendingProposal {
+    #[pyo3(get)]
+    proposal_type: String, // "add", "update", "remove", "psk", "reinit", "external_init", ...
+    #[pyo3(get)]
+    sender_type: String, // "member", "external", "new_member_proposal"
+    #[pyo3(get)]
+    sender_index: Option<u32>, // leaf index of a member sender
+    #[pyo3(get)]
+    sender_identity: Option<Vec<u8>>, // "<user_id>:<device_id>"; None for external senders
+}
+
+impl From<engine::PendingProposal> for PendingProposal {
+    fn from(proposal: engine::PendingProposal) -> Self {
+        PendingProposal {
+            proposal_type: proposal.proposal_type.to_string(),
+            sender_type: proposal.sender.kind().to_string(),
+            sender_index: proposal.sender.index(),
+            sender_identity: proposal.sender_identity,
+        }
+    }
+}
+
+/// What `inspect_key_package` reads from a key package.
+#[pyclass]
+struct KeyPackageInfo {
+    #[pyo3(get)]
+    identity: Vec<u8>, // "<user_id>:<device_id>"
+    #[pyo3(get)]
+    ciphersuite: String,
+    #[pyo3(get)]
+    signature_key: Vec<u8>,
+    #[pyo3(get)]
+    not_before: u64, // Unix seconds
+    #[pyo3(get)]
+    not_after: u64,
+    #[pyo3(get)]
+    last_resort: bool,
+}
+
+impl From<engine::KeyPackageInfo> for KeyPackageInfo {
+    fn from(info: engine::KeyPackageInfo) -> Self {
+        KeyPackageInfo {
+            identity: info.identity,
+            ciphersuite: format!("{:?}", info.ciphersuite),
+            signature_key: info.signature_key,
+            not_before: info.not_before,
+            not_after: info.not_after,
+            last_resort: info.last_resort,
+        }
+    }
+}
+
+/// A key package we generated, from `list_key_packages`.
+#[pyclass]
+struct StoredKeyPackage {
+    #[pyo3(get)]
+    hash_ref: Vec<u8>,
+    #[pyo3(get)]
+    created_at: u64, // Unix seconds
+    #[pyo3(get)]
+    last_resort: bool,
+    #[pyo3(get)]
+    consumed: bool,
+}
+
+impl From<crate::StoredKeyPackage> for StoredKeyPackage {
+    fn from(key_package: crate::StoredKeyPackage) -> Self {
+        StoredKeyPackage {
+            hash_ref: key_package.hash_ref,
+            created_at: key_package.created_at,
+            last_resort: key_package.last_resort,
+            consumed: key_package.consumed,
+        }
+    }
+}
+
+/// What `inspect_welcome` reads from a Welcome.
+#[pyclass]
+struct WelcomeInfo {
+    #[pyo3(get)]
+    group_id: String,
+    #[pyo3(get)]
+    ciphersuite: String,
+    #[pyo3(get)]
+    sender_identity: Vec<u8>, // "<user_id>:<device_id>" of the member who added us
+}
+
+impl From<engine::WelcomeInfo> for WelcomeInfo {
+    fn from(info: engine::WelcomeInfo) -> Self {
+        WelcomeInfo {
+            group_id: info.group_id,
+            ciphersuite: format!("{:?}", info.ciphersuite),
+            sender_identity: info.sender,
+        }
+    }
+}
+
+/// Something that happened to a group, passed to the callback set with
+/// set_group_event_callback.
+#[pyclass]
+struct GroupEvent {
+    #[pyo3(get)]
+    kind: String, // "created", "joined", "member_added", "member_removed", "epoch_advanced"
+    #[pyo3(get)]
+    group_id: String,
+    #[pyo3(get)]
+    epoch: u64, // the group's epoch once it happened
+    #[pyo3(get)]
+    leaf_index: Option<u32>, // of the member added or removed
+    #[pyo3(get)]
+    identity: Option<Vec<u8>>, // "<user_id>:<device_id>" of the member added or removed
+}
+
+impl From<engine::GroupEvent> for GroupEvent {
+    fn from(event: engine::GroupEvent) -> Self {
+        let (kind, member) = match event.kind {
+            engine::GroupEventKind::Created => ("created", None),
+            engine::GroupEventKind::Joined => ("joined", None),
+            engine::GroupEventKind::MemberAdded { leaf_index, identity } => {
+                ("member_added", Some((leaf_index, identity)))
+            }
+            engine::GroupEventKind::MemberRemoved { leaf_index, identity } => {
+                ("member_removed", Some((leaf_index, identity)))
+            }
+            engine::GroupEventKind::EpochAdvanced => ("epoch_advanced", None),
+        };
+        let (leaf_index, identity) = member.unzip();
+        GroupEvent { kind: kind.to_string(), group_id: event.group_id, epoch: event.epoch, leaf_index, identity }
+    }
+}
+
+/// MLS encryption engine wrapping OpenMLS.
+///
+/// Each engine manages one identity and multiple groups.
+/// State is persisted to SQLite via the storage provider.
+///
+/// # Threading
+///
+/// An engine can be used from any Python thread, e.g. in a thread pool
+/// executor. Calls are serialized by a lock. Those doing crypto or SQLite
+/// I/O release the GIL while they run.
+#[pyclass(name = "MlsEngine")]
+struct PyMlsEngine {
+    /// `None` once closed.
+    inner: Mutex<Option<engine::MlsEngine>>,
+    /// Called with each group event after the call that caused it.
+    on_group_event: Mutex<Option<Py<PyAny>>>,
+}
+
+/// The engine of a [`PyMlsEngine`] that is not closed, locked.
+struct EngineGuard<'a>(MutexGuard<'a, Option<engine::MlsEngine>>);
+
+impl Deref for EngineGuard<'_> {
+    type Target = engine::MlsEngine;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("checked when locked")
+    }
+}
+
+impl DerefMut for EngineGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("checked when locked")
+    }
+}
+
+impl PyMlsEngine {
+    /// The engine with all of its state: `None` once closed. Operations
+    /// are transactional, so a panic in another call leaves nothing
+    /// half-written and the poison can be ignored.
+    fn lock(&self) -> MutexGuard<'_, Option<engine::MlsEngine>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The engine, locked for this call. Raises `RuntimeError` once it
+    /// has been closed. Only called by [`Self::detached`], with the GIL
+    /// released.
+    fn engine(&self) -> PyResult<EngineGuard<'_>> {
+        let guard = self.lock();
+        if guard.is_none() {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("MlsEngine is closed"));
+        }
+        Ok(EngineGuard(guard))
+    }
+
+    /// Run `f` on the engine with the GIL released, so the event loop and
+    /// other threads keep running through OpenMLS tree math and SQLite I/O.
+    /// The lock is released before the GIL is taken back, so a thread
+    /// waiting for it while holding the GIL cannot deadlock with this one.
+    /// Group events the call caused are then passed to the callback.
+    fn detached<T: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&mut engine::MlsEngine) -> engine::MlsResult<T> + Send,
+    ) -> PyResult<T> {
+        let (result, events) = py.detach(|| {
+            let mut engine = self.engine()?;
+            let result = f(&mut engine);
+            Ok::<_, PyErr>((result, engine.take_group_events()))
+        })?;
+        self.dispatch_group_events(py, events);
+        Ok(result?)
+    }
+
+    /// Call the group event callback with each event, outside the engine
+    /// lock so that it can use the engine. The change has been made by
+    /// then, so its exceptions are reported as unraisable, not raised.
+    fn dispatch_group_events(&self, py: Python<'_>, events: Vec<engine::GroupEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        let on_group_event = self.on_group_event.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(callback) = on_group_event.as_ref().map(|callback| callback.clone_ref(py)) else {
+            return;
+        };
+        drop(on_group_event);
+        for event in events {
+            if let Err(e) = callback.call1(py, (GroupEvent::from(event),)) {
+                e.write_unraisable(py, Some(callback.bind(py)));
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl PyMlsEngine {
+    #[new]
+    #[pyo3(signature = (
+        db_path=None, encryption_key=None, ciphersuite=None, db_key=None, wal=false, synchronous=None,
+        busy_timeout=None
+    ))]
+    fn new(
+        db_path: Option<&str>,
+        encryption_key: Option<Vec<u8>>,
+        ciphersuite: Option<&str>,
+        db_key: Option<Vec<u8>>,
+        wal: bool,
+        synchronous: Option<&str>,
+        busy_timeout: Option<f64>,
+    ) -> PyResult<Self> {
+        let key = check_key("encryption_key", encryption_key)?;
+        let db_key = check_key("db_key", db_key)?;
+        let options = crate::StorageOptions {
+            wal,
+            synchronous: synchronous
+                .map(crate::Synchronous::parse)
+                .transpose()
+                .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?,
+            busy_timeout: busy_timeout
+                .map(std::time::Duration::try_from_secs_f64)
+                .transpose()
+                .map_err(|_| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>("busy_timeout must be a non-negative number")
+                })?,
+        };
+        let mut inner =
+            engine::MlsEngine::open_with_options(db_path.unwrap_or(":memory:"), key, db_key, &options)?;
+        if let Some(name) = ciphersuite {
+            inner.set_ciphersuite(parse_ciphersuite(name)?)?;
+        }
+        Ok(PyMlsEngine { inner: Mutex::new(Some(inner)), on_group_event: Mutex::new(None) })
+    }
+
+    /// Close the database, flushing its write-ahead log, so the file can
+    /// be moved or deleted; open it again with a new MlsEngine. Any other
+    /// call then raises RuntimeError. Closing twice does nothing.
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| match self.lock().take() {
+            Some(inner) => Ok(inner.close()?),
+            None => Ok(()),
+        })
+    }
+
+    /// Whether close() has been called.
+    #[getter]
+    fn is_closed(&self, py: Python<'_>) -> bool {
+        py.detach(|| self.lock().is_none())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Close the database on leaving a `with` block. Exceptions propagate.
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<Py<PyAny>>,
+        _exc_value: Option<Py<PyAny>>,
+        _traceback: Option<Py<PyAny>>,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+
+    /// Name of the ciphersuite used for new key packages and groups.
+    #[getter]
+    fn ciphersuite(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("{:?}", self.detached(py, |e| Ok(e.ciphersuite()))?))
+    }
+
+    /// Handle of the identity in use, None for the default one.
+    #[getter]
+    fn identity_handle(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        self.detached(py, |e| Ok(e.identity_handle().map(str::to_string)))
+    }
+
+    /// Switch to the identity stored under `handle` in the same database,
+    /// or back to the default one with None. Each identity has its own
+    /// credential, key packages and groups; a new handle starts without an
+    /// identity, for generate_identity(). Needs SQLite storage.
+    #[pyo3(signature = (handle=None))]
+    fn use_identity(&self, py: Python<'_>, handle: Option<String>) -> PyResult<()> {
+        self.detached(py, |e| e.use_identity(handle.as_deref()))
+    }
+
+    /// Handles of the identities stored in the database, besides the
+    /// default one.
+    fn list_identities(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        self.detached(py, |e| e.list_identities())
+    }
+
+    /// Generate a new MLS identity for the given user/device.
+    /// Returns the public identity key bytes.
+    fn generate_identity<'py>(
+        &self,
+        py: Python<'py>,
+        user_id: u64,
+        device_id: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let public_key = self.detached(py, |e| e.generate_identity(user_id, device_id))?;
+        Ok(PyBytes::new(py, &public_key))
+    }
+
+    /// Generate a serialized KeyPackage for uploading to the server.
+    ///
+    /// lifetime_secs: how long it stays valid from now (OpenMLS defaults to
+    /// 12 weeks). extensions: (type, data) pairs of application-defined key
+    /// package extensions. capability_extensions: further extension types to
+    /// advertise support for. last_resort: joining a group does not use it
+    /// up, for when the server has no others left.
+    #[pyo3(signature = (lifetime_secs=None, extensions=None, capability_extensions=None, last_resort=false))]
+    fn generate_key_package<'py>(
+        &self,
+        py: Python<'py>,
+        lifetime_secs: Option<u64>,
+        extensions: Option<Vec<(u16, Vec<u8>)>>,
+        capability_extensions: Option<Vec<u16>>,
+        last_resort: bool,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let options = key_package_options(lifetime_secs, extensions, capability_extensions, last_resort)?;
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.generate_key_package_with(&options))?))
+    }
+
+    /// Generate multiple KeyPackages, with the same options as
+    /// `generate_key_package`.
+    #[pyo3(signature = (count, lifetime_secs=None, extensions=None, capability_extensions=None, last_resort=false))]
+    fn generate_key_packages<'py>(
+        &self,
+        py: Python<'py>,
+        count: usize,
+        lifetime_secs: Option<u64>,
+        extensions: Option<Vec<(u16, Vec<u8>)>>,
+        capability_extensions: Option<Vec<u16>>,
+        last_resort: bool,
+    ) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let options = key_package_options(lifetime_secs, extensions, capability_extensions, last_resort)?;
+        let packages = self.detached(py, |e| e.generate_key_packages_with(count, &options))?;
+        Ok(packages.iter().map(|kp| PyBytes::new(py, kp)).collect())
+    }
+
+    /// The key packages we generated, oldest first, to reconcile with those
+    /// uploaded to the server. Used up ones stay listed as consumed until
+    /// deleted.
+    fn list_key_packages(&self, py: Python<'_>) -> PyResult<Vec<StoredKeyPackage>> {
+        Ok(self.detached(py, |e| e.list_key_packages())?.into_iter().map(Into::into).collect())
+    }
+
+    /// Delete one of our key packages by hash reference, so no Welcome can
+    /// use it any more.
+    fn delete_key_package(&self, py: Python<'_>, hash_ref: Vec<u8>) -> PyResult<()> {
+        self.detached(py, |e| e.delete_key_package(&hash_ref))
+    }
+
+    /// Read who a serialized KeyPackage is for, its ciphersuite and
+    /// validity, after checking its signature, without storing it.
+    #[staticmethod]
+    fn inspect_key_package(key_package: Vec<u8>) -> PyResult<KeyPackageInfo> {
+        Ok(engine::MlsEngine::inspect_key_package(&key_package)?.into())
+    }
+
+    /// Create a new MLS group.
+    /// member_key_packages: list of serialized KeyPackages for initial members.
+    /// ciphersuite: overrides the engine's; the key packages must match it.
+    /// extensions: application-defined GroupContext extensions as
+    /// (type, data); the members' key packages must list the types in
+    /// capability_extensions.
+    /// with_ratchet_tree: False leaves the tree out of Welcomes; joiners
+    /// then need export_ratchet_tree output.
+    /// padding_size: pad our application messages to a multiple of this
+    /// many bytes, hiding their length from the server; 0 for none.
+    /// required_extensions, required_credentials: extension and credential
+    /// types every member's key package must support; others are refused.
+    /// with_group_info: also return the group's signed GroupInfo, with its
+    /// ratchet tree, to publish for external joins.
+    /// Returns (welcome_bytes | None, commit_bytes | None), plus
+    /// group_info_bytes with with_group_info.
+    #[pyo3(signature = (
+        group_id,
+        member_key_packages,
+        ciphersuite=None,
+        extensions=None,
+        with_ratchet_tree=true,
+        padding_size=0,
+        required_extensions=None,
+        required_credentials=None,
+        with_group_info=false,
+    ))]
+    fn create_group<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        member_key_packages: Vec<Vec<u8>>,
+        ciphersuite: Option<&str>,
+        extensions: Option<Vec<(u16, Vec<u8>)>>,
+        with_ratchet_tree: bool,
+        padding_size: usize,
+        required_extensions: Option<Vec<u16>>,
+        required_credentials: Option<Vec<u16>>,
+        with_group_info: bool,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let options = crate::GroupOptions {
+            ciphersuite: ciphersuite.map(parse_ciphersuite).transpose()?,
+            extensions: extensions.unwrap_or_default(),
+            omit_ratchet_tree: !with_ratchet_tree,
+            padding_size,
+            required_extensions: required_extensions.unwrap_or_default(),
+            required_credentials: required_credentials.unwrap_or_default(),
+        };
+        let (welcome, commit, group_info) = self.detached(py, |e| match with_group_info {
+            true => e
+                .create_group_with_group_info(group_id, &member_key_packages, &options)
+                .map(|(welcome, commit, group_info)| (welcome, commit, Some(group_info))),
+            false => e
+                .create_group_with(group_id, &member_key_packages, &options)
+                .map(|(welcome, commit)| (welcome, commit, None)),
+        })?;
+        let mut returned = vec![welcome, commit];
+        if with_group_info {
+            returned.push(group_info);
+        }
+        PyTuple::new(py, returned.iter().map(|bytes| bytes.as_ref().map(|b| PyBytes::new(py, b))))
+    }
+
+    /// Join a group from a Welcome message, replacing the state of a
+    /// group with the same ID (as after reinit_group). ratchet_tree is
+    /// export_ratchet_tree output, needed if the Welcome leaves it out.
+    /// padding_size is as for create_group.
+    /// Returns the group ID string.
+    #[pyo3(signature = (welcome, ratchet_tree=None, padding_size=0))]
+    fn join_group(
+        &self,
+        py: Python<'_>,
+        welcome: Vec<u8>,
+        ratchet_tree: Option<Vec<u8>>,
+        padding_size: usize,
+    ) -> PyResult<String> {
+        let options = crate::JoinOptions { padding_size };
+        self.detached(py, |e| e.join_group_with(&welcome, ratchet_tree.as_deref(), &options))
+    }
+
+    /// Read which group a Welcome is for, its ciphersuite and who sent it,
+    /// without joining; join_group still works afterwards.
+    #[pyo3(signature = (welcome, ratchet_tree=None))]
+    fn inspect_welcome(
+        &self,
+        py: Python<'_>,
+        welcome: Vec<u8>,
+        ratchet_tree: Option<Vec<u8>>,
+    ) -> PyResult<WelcomeInfo> {
+        Ok(self.detached(py, |e| e.inspect_welcome(&welcome, ratchet_tree.as_deref()))?.into())
+    }
+
+    /// Serialize a group's ratchet tree, for joiners whose Welcome leaves
+    /// it out.
+    fn export_ratchet_tree<'py>(&self, py: Python<'py>, group_id: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let tree = self.detached(py, |e| e.export_ratchet_tree(group_id))?;
+        Ok(PyBytes::new(py, &tree))
+    }
+
+    /// Move a group to another ciphersuite by re-creating it with the same
+    /// ID, members and extensions. member_key_packages must be for the new
+    /// ciphersuite and for exactly the other members. Returns welcome bytes
+    /// for them, or None if we are alone. Attached media key sinks are
+    /// dropped.
+    fn reinit_group<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        ciphersuite: &str,
+        member_key_packages: Vec<Vec<u8>>,
+    ) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let ciphersuite = parse_ciphersuite(ciphersuite)?;
+        let welcome = self.detached(py, |e| e.reinit_group(group_id, ciphersuite, &member_key_packages))?;
+        Ok(welcome.map(|w| PyBytes::new(py, &w)))
+    }
+
+    /// Export a group's signed GroupInfo, for others to join by external
+    /// commit. `join_by_external_commit` needs `with_ratchet_tree`.
+    #[pyo3(signature = (group_id, with_ratchet_tree=true))]
+    fn export_group_info<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        with_ratchet_tree: bool,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let group_info = self.detached(py, |e| e.export_group_info(group_id, with_ratchet_tree))?;
+        Ok(PyBytes::new(py, &group_info))
+    }
+
+    /// Join a group from its GroupInfo without a Welcome, e.g. to rejoin
+    /// after losing local state. Returns (group_id, commit_bytes); relay the
+    /// commit to the group. padding_size is as for create_group.
+    #[pyo3(signature = (group_info, padding_size=0))]
+    fn join_by_external_commit<'py>(
+        &self,
+        py: Python<'py>,
+        group_info: Vec<u8>,
+        padding_size: usize,
+    ) -> PyResult<(String, Bound<'py, PyBytes>)> {
+        let options = crate::JoinOptions { padding_size };
+        let (group_id, commit) = self.detached(py, |e| e.join_by_external_commit_with(&group_info, &options))?;
+        Ok((group_id, PyBytes::new(py, &commit)))
+    }
+
+    /// Add a member to an existing group.
+    /// Returns (welcome_bytes, commit_bytes), plus the group's signed
+    /// GroupInfo for the new epoch with with_group_info, as for
+    /// create_group.
+    #[pyo3(signature = (group_id, key_package, with_group_info=false))]
+    fn add_member<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        key_package: Vec<u8>,
+        with_group_info: bool,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let returned = self.detached(py, |e| match with_group_info {
+            true => e
+                .add_member_with_group_info(group_id, &key_package)
+                .map(|(welcome, commit, group_info)| vec![welcome, commit, group_info]),
+            false => e.add_member(group_id, &key_package).map(|(welcome, commit)| vec![welcome, commit]),
+        })?;
+        PyTuple::new(py, returned.iter().map(|bytes| PyBytes::new(py, bytes)))
+    }
+
+    /// Remove a member from a group by credential identity string.
+    /// Returns commit bytes.
+    fn remove_member<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        member_identity: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let commit = self.detached(py, |e| e.remove_member(group_id, member_identity))?;
+        Ok(PyBytes::new(py, &commit))
+    }
+
+    /// Rotate our own leaf key material with an Update commit, for
+    /// post-compromise security. Returns commit bytes for the other members.
+    fn update_self<'py>(&self, py: Python<'py>, group_id: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let commit = self.detached(py, |e| e.update_self(group_id))?;
+        Ok(PyBytes::new(py, &commit))
+    }
+
+    /// Replace our credential in a group with a fresh one for
+    /// new_device_id, under a new signature key, e.g. after a device rename
+    /// or a key compromise. The new identity is stored and used from now
+    /// on; other groups keep the old one until rotated too. Returns commit
+    /// bytes for the other members.
+    fn rotate_credential<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        new_device_id: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let commit = self.detached(py, |e| e.rotate_credential(group_id, new_device_id))?;
+        Ok(PyBytes::new(py, &commit))
+    }
+
+    /// Replace a group's application-defined GroupContext extensions, as
+    /// (type, data). Returns commit bytes for the other members.
+    fn update_group_context_extensions<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        extensions: Vec<(u16, Vec<u8>)>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let commit = self.detached(py, |e| e.update_group_context_extensions(group_id, &extensions))?;
+        Ok(PyBytes::new(py, &commit))
+    }
+
+    /// A group's application-defined GroupContext extensions as
+    /// (type, data).
+    fn group_context_extensions<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+    ) -> PyResult<Vec<(u16, Bound<'py, PyBytes>)>> {
+        let extensions = self.detached(py, |e| e.group_context_extensions(group_id))?;
+        Ok(extensions.iter().map(|(t, data)| (*t, PyBytes::new(py, data))).collect())
+    }
+
+    /// Ask to leave a group. Returns the proposal removing our own leaf, to
+    /// relay via the server; the group is deleted once the commit of that
+    /// proposal is processed here.
+    fn leave_group<'py>(&self, py: Python<'py>, group_id: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let proposal = self.detached(py, |e| e.leave_group(group_id))?;
+        Ok(PyBytes::new(py, &proposal))
+    }
+
+    /// Whether `leave_group` was called for a group that has not removed
+    /// us yet.
+    fn is_leaving(&self, py: Python<'_>, group_id: &str) -> PyResult<bool> {
+        self.detached(py, |e| e.is_leaving(group_id))
+    }
+
+    /// Delete a group and all its stored state. Other members are not told;
+    /// use leave_group for that.
+    fn delete_group(&self, py: Python<'_>, group_id: &str) -> PyResult<()> {
+        self.detached(py, |e| e.delete_group(group_id))
+    }
+
+    /// Delete epoch keys older than each group's max_past_epochs and
+    /// expired key packages, then VACUUM the database. Returns how many
+    /// `(epoch_key_pairs, key_packages)` were deleted.
+    fn compact(&self, py: Python<'_>) -> PyResult<(usize, usize)> {
+        self.detached(py, |e| e.compact())
+    }
+
+    /// Diagnostics for the client UI and bug reports, as a dict:
+    /// database_bytes (of every identity in the database, without a
+    /// write-ahead log), then for the current identity groups, mapping
+    /// group IDs to their epoch, members and pending_proposals counts, and
+    /// key_packages with total, unused and last_resort counts.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = self.detached(py, |e| e.stats())?;
+        let groups = PyDict::new(py);
+        for group in stats.groups {
+            let d = PyDict::new(py);
+            d.set_item("epoch", group.epoch)?;
+            d.set_item("members", group.members)?;
+            d.set_item("pending_proposals", group.pending_proposals)?;
+            groups.set_item(group.group_id, d)?;
+        }
+        let key_packages = PyDict::new(py);
+        key_packages.set_item("total", stats.key_packages.total)?;
+        key_packages.set_item("unused", stats.key_packages.unused)?;
+        key_packages.set_item("last_resort", stats.key_packages.last_resort)?;
+
+        let report = PyDict::new(py);
+        report.set_item("database_bytes", stats.database_bytes)?;
+        report.set_item("groups", groups)?;
+        report.set_item("key_packages", key_packages)?;
+        Ok(report)
+    }
+
+    /// The proposals received for a group and not yet committed, in the
+    /// order they arrived.
+    fn list_pending_proposals(&self, py: Python<'_>, group_id: &str) -> PyResult<Vec<PendingProposal>> {
+        Ok(self.detached(py, |e| e.list_pending_proposals(group_id))?.into_iter().map(Into::into).collect())
+    }
+
+    /// Drop the proposals received for a group and not yet committed.
+    fn clear_pending_proposals(&self, py: Python<'_>, group_id: &str) -> PyResult<()> {
+        self.detached(py, |e| e.clear_pending_proposals(group_id))
+    }
+
+    /// Commit the proposals received for a group, such as another member's
+    /// request to leave. Returns (commit_bytes, welcome_bytes or None), or
+    /// None if there were no proposals.
+    fn commit_pending_proposals<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+    ) -> PyResult<Option<(Bound<'py, PyBytes>, Option<Bound<'py, PyBytes>>)>> {
+        let committed = self.detached(py, |e| e.commit_pending_proposals(group_id))?;
+        Ok(committed.map(|(commit, welcome)| {
+            (PyBytes::new(py, &commit), welcome.map(|w| PyBytes::new(py, &w)))
+        }))
+    }
+
+    /// Process an incoming MLS message (commit, proposal, or application message).
+    /// A message from up to 8 epochs later is buffered (kind "buffered")
+    /// and replayed once the commits before it arrive; collect the results
+    /// with take_replayed. One from further ahead raises WrongEpochError. One of our own recent commits relayed back by the
+    /// server comes back as kind "own_commit", changing nothing.
+    ///
+    /// With stage_commits=True a commit is not merged: it comes back as kind
+    /// "staged_commit" with the membership changes in staged_commit, and
+    /// waits for merge_staged_commit or reject_staged_commit.
+    #[pyo3(signature = (group_id, message, stage_commits=false))]
+    fn process_message(
+        &self,
+        py: Python<'_>,
+        group_id: &str,
+        message: Vec<u8>,
+        stage_commits: bool,
+    ) -> PyResult<ProcessedMessage> {
+        let processed = self.detached(py, |e| {
+            if stage_commits {
+                e.process_message_staged(group_id, &message)
+            } else {
+                e.process_message(group_id, &message)
+            }
+        })?;
+        Ok(processed.into())
+    }
+
+    /// Merge the commit staged for a group by process_message.
+    fn merge_staged_commit(&self, py: Python<'_>, group_id: &str) -> PyResult<()> {
+        Ok(self.detached(py, |e| e.merge_staged_commit(group_id))?)
+    }
+
+    /// Drop the commit staged for a group. The group stays in its epoch.
+    fn reject_staged_commit(&self, py: Python<'_>, group_id: &str) -> PyResult<()> {
+        self.detached(py, |e| e.reject_staged_commit(group_id))
+    }
+
+    /// Call callback(event) with a GroupEvent, holding the GIL, after each
+    /// call that creates or joins a group, adds or removes members, or
+    /// moves a group to a new epoch; None stops. The callback can use the
+    /// engine; exceptions it raises are reported as unraisable, since the
+    /// change has been made.
+    #[pyo3(signature = (callback=None))]
+    fn set_group_event_callback(&self, py: Python<'_>, callback: Option<Py<PyAny>>) -> PyResult<()> {
+        let watch = callback.is_some();
+        self.detached(py, |e| e.watch_group_events(watch))?;
+        *self.on_group_event.lock().unwrap_or_else(PoisonError::into_inner) = callback;
+        Ok(())
+    }
+
+    /// Results of buffered messages replayed since the last call, in the
+    /// order they were processed.
+    fn take_replayed(&self, py: Python<'_>, group_id: &str) -> PyResult<Vec<ProcessedMessage>> {
+        Ok(self.detached(py, |e| Ok(e.take_replayed(group_id)))?.into_iter().map(Into::into).collect())
+    }
+
+    /// Read a message's group_id, epoch, content type and (if it is not
+    /// encrypted) sender without processing it, to route and order
+    /// messages before process_message.
+    fn peek_message(&self, py: Python<'_>, message: Vec<u8>) -> PyResult<MessageInfo> {
+        Ok(self.detached(py, |e| e.peek_message(&message))?.into())
+    }
+
+    /// Encrypt plaintext into an MLS application message.
+    fn encrypt<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        plaintext: Vec<u8>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let ciphertext = self.detached(py, |e| e.encrypt(group_id, &plaintext))?;
+        Ok(PyBytes::new(py, &ciphertext))
+    }
+
+    /// Decrypt an MLS application message.
+    /// Convenience wrapper around process_message that returns just the plaintext.
+    fn decrypt<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        ciphertext: Vec<u8>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let plaintext = self.detached(py, |e| e.decrypt(group_id, &ciphertext))?;
+        Ok(PyBytes::new(py, &plaintext))
+    }
+
+    /// Feed this group's media keys to a media client.
+    ///
+    /// Called by `VoxMediaClient.set_e2ee_from`, which passes `sink` as a
+    /// capsule. The current epoch's key is delivered now, and each new
+    /// epoch's once the commit starting it is created or processed here.
+    fn attach_media_key_sink(&self, py: Python<'_>, group_id: &str, sink: &Bound<'_, PyAny>) -> PyResult<()> {
+        let raw = unsafe { pyo3::ffi::PyCapsule_GetPointer(sink.as_ptr(), CAPSULE_NAME.as_ptr()) };
+        if raw.is_null() {
+            let _ = PyErr::take(py);
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "sink must be a media key sink created by vox_media",
+            ));
+        }
+        let sink = unsafe { KeySink::from_raw(&*raw.cast::<RawKeySink>()) };
+        self.detached(py, |e| e.attach_media_key_sink(group_id, sink))
+    }
+
+    /// Export the key for a file attachment, for `vox_files`.
+    ///
+    /// Each file id gets its own key from the group's current epoch, so
+    /// the sender and every member still at that epoch derive the same key.
+    /// If `epoch` is given (the manifest's `key_epoch` when decrypting) and
+    /// the group has moved on, a `ValueError` is raised: MLS cannot export
+    /// secrets for past epochs, so keep the key if it is needed later.
+    ///
+    /// Returns `(epoch, key)`.
+    #[pyo3(signature = (group_id, file_id, epoch=None))]
+    fn export_file_key<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+        file_id: Vec<u8>,
+        epoch: Option<u64>,
+    ) -> PyResult<(u64, Bound<'py, PyBytes>)> {
+        let (epoch, key) = self.detached(py, |e| e.export_file_key(group_id, &file_id, epoch))?;
+        Ok((epoch, PyBytes::new(py, &key)))
+    }
+
+    /// List a group's members as (leaf_index, identity_bytes,
+    /// signature_public_key) tuples, ordered by leaf index.
+    fn list_members<'py>(
+        &self,
+        py: Python<'py>,
+        group_id: &str,
+    ) -> PyResult<Vec<(u32, Bound<'py, PyBytes>, Bound<'py, PyBytes>)>> {
+        let members = self.detached(py, |e| e.list_members(group_id))?;
+        Ok(members
+            .iter()
+            .map(|(index, identity, key)| (*index, PyBytes::new(py, identity), PyBytes::new(py, key)))
+            .collect())
+    }
+
+    /// The group's current epoch.
+    fn group_epoch(&self, py: Python<'_>, group_id: &str) -> PyResult<u64> {
+        self.detached(py, |e| e.group_epoch(group_id))
+    }
+
+    /// The current epoch's authenticator, equal for all members in the
+    /// same epoch; compare it out of band to verify the group.
+    fn epoch_authenticator<'py>(&self, py: Python<'py>, group_id: &str) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.epoch_authenticator(group_id))?))
+    }
+
+    /// The safety number of a member ("<user_id>:<device_id>"): 60 digits
+    /// in groups of five from their credential and signature key. Compare
+    /// it out of band with their own_fingerprint() to verify their key.
+    fn member_fingerprint(&self, py: Python<'_>, group_id: &str, member_identity: &str) -> PyResult<String> {
+        self.detached(py, |e| e.member_fingerprint(group_id, member_identity))
+    }
+
+    /// Our own safety number, the same in every group; see
+    /// member_fingerprint().
+    fn own_fingerprint(&self, py: Python<'_>) -> PyResult<String> {
+        self.detached(py, |e| e.own_fingerprint())
+    }
+
+    /// Check if a group exists in storage.
+    fn group_exists(&self, py: Python<'_>, group_id: &str) -> PyResult<bool> {
+        self.detached(py, |e| Ok(e.group_exists(group_id)))
+    }
+
+    /// List all group IDs managed by this engine.
+    fn list_groups(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        self.detached(py, |e| e.list_groups())
+    }
+
+    /// Get the public identity key bytes, or None if not initialized.
+    fn identity_key<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        Ok(self.detached(py, |e| Ok(e.identity_key()))?.map(|key| PyBytes::new(py, &key)))
+    }
+
+    /// Get the stored identity metadata (user_id, device_id) from SQLite,
+    /// or None if no identity is stored.
+    fn get_stored_identity(&self, py: Python<'_>) -> PyResult<Option<(u64, String)>> {
+        self.detached(py, |e| e.stored_identity())
+    }
+
+    /// Export the full MLS state (identity + all groups) as raw SQLite database bytes.
+    ///
+    /// This is the recommended backup method — it preserves group memberships,
+    /// epoch keys, and all other state. Use `import_state()` to restore.
+    ///
+    /// # Security
+    ///
+    /// The returned bytes contain **private key material** (signature keys,
+    /// epoch secrets). Callers must encrypt the output before persisting
+    /// or transmitting it — see [`encrypt_backup`](crate::crypto::backup).
+    fn export_state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.export_state())?))
+    }
+
+    /// Restore full MLS state from raw SQLite database bytes.
+    ///
+    /// Replaces all data in the current database and reloads identity.
+    fn import_state(&self, py: Python<'_>, data: Vec<u8>) -> PyResult<()> {
+        self.detached(py, |e| e.import_state(&data))
+    }
+
+    /// Export one group's state so another device with the same identity
+    /// can take it over with `import_group()`, without moving the whole
+    /// database.
+    ///
+    /// # Security
+    ///
+    /// The returned bytes contain **private key material** for the group.
+    fn export_group<'py>(&self, py: Python<'py>, group_id: &str) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.export_group(group_id))?))
+    }
+
+    /// Import a group from `export_group()` output, replacing any state
+    /// stored for it. Returns the group ID. Raises ValueError if the group
+    /// belongs to another identity.
+    fn import_group(&self, py: Python<'_>, data: Vec<u8>) -> PyResult<String> {
+        self.detached(py, |e| e.import_group(&data))
+    }
+
+    /// Export the full MLS state encrypted with a passphrase (Argon2id key
+    /// derivation, AES-256-GCM), safe to upload to cloud backup.
+    fn export_state_encrypted<'py>(&self, py: Python<'py>, passphrase: &str) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.export_state_encrypted(passphrase))?))
+    }
+
+    /// Restore state from `export_state_encrypted()` output. Raises
+    /// ValueError for a wrong passphrase or corrupted data.
+    fn import_state_encrypted(&self, py: Python<'_>, data: Vec<u8>, passphrase: &str) -> PyResult<()> {
+        self.detached(py, |e| e.import_state_encrypted(&data, passphrase))
+    }
+
+    /// Export the identity only (private + public key material) as serialized bytes.
+    /// Use `export_state()` for a full backup including group memberships.
+    ///
+    /// # Security
+    ///
+    /// The returned bytes contain **unencrypted private key material**.
+    /// Callers must encrypt the output before persisting or transmitting it.
+    fn export_identity<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.detached(py, |e| e.export_identity())?))
+    }
+
+    /// Import a previously exported identity (private + public key material).
+    /// Also persists to the vox_identity SQLite table so it survives engine restarts.
+    ///
+    /// # Security
+    ///
+    /// The input bytes must come from a trusted source. Importing a malicious
+    /// payload could compromise the identity of this device.
+    fn import_identity(&self, py: Python<'_>, data: Vec<u8>, user_id: u64, device_id: &str) -> PyResult<()> {
+        self.detached(py, |e| e.import_identity(&data, user_id, device_id))
+    }
+}
+
+#[pymodule]
+fn vox_mls(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMlsEngine>()?;
+    m.add("WrongEpochError", m.py().get_type::<WrongEpochError>())?;
+    m.add_class::<ProcessedMessage>()?;
+    m.add_class::<StagedCommitInfo>()?;
+    m.add_class::<MessageInfo>()?;
+    m.add_class::<PendingProposal>()?;
+    m.add_class::<KeyPackageInfo>()?;
+    m.add_class::<StoredKeyPackage>()?;
+    m.add_class::<WelcomeInfo>()?;
+    m.add_class::<GroupEvent>()?;
+    m.add_class::<push::PushNotification>()?;
+    m.add_function(wrap_pyfunction!(push::decrypt_push, m)?)?;
+    m.add_function(wrap_pyfunction!(push::encode_push_payload, m)?)?;
+    m.add_function(wrap_pyfunction!(telemetry::configure_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(profile::enable_profiling, m)?)?;
+    m.add_function(wrap_pyfunction!(profile::profile_report, m)?)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! OTLP span export for MLS operations.
+//!
+//! vox-mls has no logging of its own, so its global subscriber exists only
+//! to carry the exporter. It is installed on the first `configure_tracing`
+//! call, with the exporter layer behind a reload handle so the collector can
+//! be changed or removed later.
+
+use pyo3::prelude::*;
+use std::collections::HashMap;
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::sync::{Mutex, OnceLock};
+    use tracing_subscriber::layer::{Identity, SubscriberExt};
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::{reload, Layer, Registry};
+    use vox_core::telemetry::OtlpExporter;
+
+    type TelemetryLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+    static HANDLE: OnceLock<reload::Handle<TelemetryLayer, Registry>> = OnceLock::new();
+    static EXPORTER: Mutex<Option<OtlpExporter>> = Mutex::new(None);
+
+    /// Route spans to `exporter` (or nowhere), returning the exporter it
+    /// replaces so the caller can flush it.
+    pub fn install(exporter: Option<OtlpExporter>) -> Result<Option<OtlpExporter>, String> {
+        let layer = exporter
+            .as_ref()
+            .map_or_else(|| Identity::new().boxed(), |e| e.layer("vox-mls").boxed());
+        match HANDLE.get() {
+            Some(handle) => handle.reload(layer).map_err(|e| e.to_string())?,
+            None => {
+                let (layer, handle) = reload::Layer::new(layer);
+                tracing_subscriber::registry()
+                    .with(layer)
+                    .try_init()
+                    .map_err(|e| format!("Another global subscriber is already installed: {e}"))?;
+                let _ = HANDLE.set(handle);
+            }
+        }
+        Ok(std::mem::replace(&mut *EXPORTER.lock().unwrap(), exporter))
+    }
+}
+
+/// Export MLS operation spans to an OpenTelemetry collector over OTLP/HTTP.
+///
+/// Arguments match `vox_media.configure_tracing`: `endpoint` is the traces
+/// URL (e.g. `"http://localhost:4318/v1/traces"`) or None to flush and stop
+/// exporting. Spans cover group creation, joins, membership changes and
+/// message processing. Raises RuntimeError if the module was built without
+/// the `otel` feature.
+#[pyfunction]
+#[pyo3(signature = (endpoint, service_name="vox-mls", sample_ratio=1.0, headers=None))]
+pub fn configure_tracing(
+    py: Python<'_>,
+    endpoint: Option<String>,
+    service_name: &str,
+    sample_ratio: f64,
+    headers: Option<HashMap<String, String>>,
+) -> PyResult<()> {
+    #[cfg(feature = "otel")]
+    {
+        use vox_core::telemetry::{OtlpConfig, OtlpExporter};
+
+        let exporter = match endpoint {
+            Some(endpoint) => {
+                let config = OtlpConfig {
+                    endpoint,
+                    service_name: service_name.to_string(),
+                    sample_ratio,
+                    headers: headers.unwrap_or_default(),
+                };
+                Some(
+                    OtlpExporter::start(&config)
+                        .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?,
+                )
+            }
+            None => None,
+        };
+        let previous =
+            otel::install(exporter).map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        if let Some(previous) = previous {
+            // Flushing waits on the collector
+            py.detach(move || previous.shutdown())
+                .map_err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>)?;
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = (py, service_name, sample_ratio, headers);
+        match endpoint {
+            Some(_) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "vox_mls was built without the otel feature",
+            )),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! The `vox_mls` WebAssembly module: wasm-bindgen wrappers over
+//! [`crate::engine`] for web clients. Built with the `wasm` feature for
+//! wasm32.
+//!
+//! State is kept in IndexedDB as the provider's snapshot, one record per
+//! database name. Calls that change state only mark it dirty; `flush`
+//! writes the snapshot and rejects if the write fails. Await it after
+//! each such call, before acting on the result (for example sending a
+//! commit), or a reload can lose the change.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use idb::{Database, Factory, ObjectStoreParams, TransactionMode};
+use js_sys::{Array, Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::engine::{self, MlsError};
+use crate::group::ProcessedResult;
+
+/// Object store holding the snapshot.
+const STORE: &str = "vox_mls";
+/// Key of the snapshot within the store.
+const STATE_KEY: &str = "state";
+
+async fn open_database(name: &str) -> Result<Database, idb::Error> {
+    let factory = Factory::new()?;
+    let mut request = factory.open(name, Some(1))?;
+    request.on_upgrade_needed(|event| {
+        if let Ok(database) = event.database() {
+            if !database.store_names().iter().any(|s| s == STORE) {
+                let _ = database.create_object_store(STORE, ObjectStoreParams::new());
+            }
+        }
+    });
+    request.await
+}
+
+async fn load_snapshot(database: &Database) -> Result<Option<Vec<u8>>, idb::Error> {
+    let transaction = database.transaction(&[STORE], TransactionMode::ReadOnly)?;
+    let store = transaction.object_store(STORE)?;
+    let value = store.get(JsValue::from_str(STATE_KEY))?.await?;
+    Ok(value.map(|v| Uint8Array::new(&v).to_vec()))
+}
+
+async fn save_snapshot(database: Database, snapshot: Vec<u8>) -> Result<(), idb::Error> {
+    let transaction = database.transaction(&[STORE], TransactionMode::ReadWrite)?;
+    let store = transaction.object_store(STORE)?;
+    let value: JsValue = Uint8Array::from(snapshot.as_slice()).into();
+    store.put(&value, Some(&JsValue::from_str(STATE_KEY)))?.await?;
+    transaction.commit()?.await?;
+    Ok(())
+}
+
+/// Result of processing an incoming MLS message.
+#[wasm_bindgen]
+pub struct ProcessedMessage {
+    kind: String,
+    data: Option<Vec<u8>>,
+    epoch: u64,
+    sender_identity: Option<Vec<u8>>,
+    sender_leaf_index: Option<u32>,
+}
+
+#[wasm_bindgen]
+impl ProcessedMessage {
+    /// "application", "commit", "proposal", "external_join_proposal" or
+    /// "buffered".
+    #[wasm_bindgen(getter)]
+    pub fn kind(&self) -> String {
+        self.kind.clone()
+    }
+
+    /// Plaintext of an application message.
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Option<Vec<u8>> {
+        self.data.clone()
+    }
+
+    /// The epoch the message was sent in.
+    #[wasm_bindgen(getter)]
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The sender's credential identity; null while buffered.
+    #[wasm_bindgen(getter, js_name = senderIdentity)]
+    pub fn sender_identity(&self) -> Option<Vec<u8>> {
+        self.sender_identity.clone()
+    }
+
+    /// The sender's leaf index; null for non-members and while buffered.
+    #[wasm_bindgen(getter, js_name = senderLeafIndex)]
+    pub fn sender_leaf_index(&self) -> Option<u32> {
+        self.sender_leaf_index
+    }
+}
+
+impl From<engine::ProcessedMessage> for ProcessedMessage {
+    fn from(processed: engine::ProcessedMessage) -> Self {
+        let (kind, data) = match processed.result {
+            ProcessedResult::Application(plaintext) => ("application", Some(plaintext)),
+            ProcessedResult::Commit => ("commit", None),
+            ProcessedResult::Proposal => ("proposal", None),
+            ProcessedResult::ExternalJoinProposal => ("external_join_proposal", None),
+            ProcessedResult::Buffered => ("buffered", None),
+            ProcessedResult::OwnCommit => ("own_commit", None),
+            // Commits are only staged through the Rust and Python APIs
+            ProcessedResult::StagedCommit(_) => ("staged_commit", None),
+        };
+        ProcessedMessage {
+            kind: kind.to_string(),
+            data,
+            epoch: processed.epoch,
+            sender_identity: processed.sender_identity,
+            sender_leaf_index: processed.sender.and_then(|s| s.leaf_index()),
+        }
+    }
+}
+
+fn bytes_or_null(bytes: Option<Vec<u8>>) -> JsValue {
+    bytes.map_or(JsValue::NULL, |b| Uint8Array::from(b.as_slice()).into())
+}
+
+/// MLS encryption engine for the browser, with the same methods as the
+/// Python `vox_mls.MlsEngine` in camelCase. User ids and epochs are
+/// BigInts.
+#[wasm_bindgen(js_name = MlsEngine)]
+pub struct WasmMlsEngine {
+    inner: engine::MlsEngine,
+    database: Database,
+    /// Set when state changed since the last successful `flush`.
+    dirty: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen(js_class = MlsEngine)]
+impl WasmMlsEngine {
+    /// Open the engine stored in the IndexedDB database `name`, creating
+    /// it if needed. `encryptionKey` (32 bytes) must match the one the
+    /// state was saved with.
+    pub async fn open(name: String, encryption_key: Option<Vec<u8>>) -> Result<WasmMlsEngine, JsError> {
+        let key = encryption_key
+            .map(|k| <[u8; 32]>::try_from(k).map_err(|_| JsError::new("encryption_key must be exactly 32 bytes")))
+            .transpose()?;
+        let database = open_database(&name).await?;
+        let mut inner = engine::MlsEngine::open(&name, key)?;
+        if let Some(snapshot) = load_snapshot(&database).await? {
+            inner.import_state(&snapshot)?;
+        }
+        Ok(WasmMlsEngine {
+            inner,
+            database,
+            dirty: Rc::new(Cell::new(false)),
+        })
+    }
+
+    /// Mark the state as changed, for the next `flush` to write.
+    fn persist(&self) {
+        self.dirty.set(true);
+    }
+
+    /// Write the state to IndexedDB if it changed since the last flush.
+    /// The promise rejects if the write fails; the state stays dirty so
+    /// the next flush retries.
+    pub fn flush(&self) -> Result<Promise, JsError> {
+        if !self.dirty.replace(false) {
+            return Ok(Promise::resolve(&JsValue::UNDEFINED));
+        }
+        let snapshot = match self.inner.export_state() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                self.dirty.set(true);
+                return Err(e.into());
+            }
+        };
+        let database = self.database.clone();
+        let dirty = Rc::clone(&self.dirty);
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            save_snapshot(database, snapshot).await.map_err(|e| {
+                dirty.set(true);
+                JsValue::from(JsError::new(&format!("Failed to save state: {e}")))
+            })?;
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    /// Generate a new MLS identity. Returns the public identity key.
+    #[wasm_bindgen(js_name = generateIdentity)]
+    pub fn generate_identity(&mut self, user_id: u64, device_id: &str) -> Result<Vec<u8>, JsError> {
+        let public_key = self.inner.generate_identity(user_id, device_id)?;
+        self.persist();
+        Ok(public_key)
+    }
+
+    /// Name of the ciphersuite used for new key packages and groups.
+    pub fn ciphersuite(&self) -> String {
+        format!("{:?}", self.inner.ciphersuite())
+    }
+
+    /// Use the named ciphersuite for new key packages and groups.
+    #[wasm_bindgen(js_name = setCiphersuite)]
+    pub fn set_ciphersuite(&mut self, name: &str) -> Result<(), JsError> {
+        let ciphersuite = crate::parse_ciphersuite(name).map_err(MlsError::InvalidInput)?;
+        Ok(self.inner.set_ciphersuite(ciphersuite)?)
+    }
+
+    /// Generate a serialized KeyPackage for uploading to the server.
+    #[wasm_bindgen(js_name = generateKeyPackage)]
+    pub fn generate_key_package(&self) -> Result<Vec<u8>, JsError> {
+        let package = self.inner.generate_key_package()?;
+        self.persist();
+        Ok(package)
+    }
+
+    /// Generate multiple KeyPackages.
+    #[wasm_bindgen(js_name = generateKeyPackages)]
+    pub fn generate_key_packages(&self, count: usize) -> Result<Vec<Uint8Array>, JsError> {
+        let packages = self.inner.generate_key_packages(count)?;
+        self.persist();
+        Ok(packages.iter().map(|p| Uint8Array::from(p.as_slice())).collect())
+    }
+
+    /// Create a new MLS group. Returns `[welcome | null, commit | null]`.
+    #[wasm_bindgen(js_name = createGroup)]
+    pub fn create_group(&mut self, group_id: &str, member_key_packages: Vec<Uint8Array>) -> Result<Array, JsError> {
+        let packages: Vec<Vec<u8>> = member_key_packages.iter().map(Uint8Array::to_vec).collect();
+        let (welcome, commit) = self.inner.create_group(group_id, &packages)?;
+        self.persist();
+        Ok(Array::of2(&bytes_or_null(welcome), &bytes_or_null(commit)))
+    }
+
+    /// Join a group from a Welcome message. Returns the group ID.
+    #[wasm_bindgen(js_name = joinGroup)]
+    pub fn join_group(&mut self, welcome: &[u8]) -> Result<String, JsError> {
+        let group_id = self.inner.join_group(welcome)?;
+        self.persist();
+        Ok(group_id)
+    }
+
+    /// Export a group's signed GroupInfo, for others to join by external
+    /// commit, which needs `withRatchetTree`.
+    #[wasm_bindgen(js_name = exportGroupInfo)]
+    pub fn export_group_info(&self, group_id: &str, with_ratchet_tree: bool) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_group_info(group_id, with_ratchet_tree)?)
+    }
+
+    /// Join a group from its GroupInfo without a Welcome. Returns
+    /// `[groupId, commit]`.
+    #[wasm_bindgen(js_name = joinByExternalCommit)]
+    pub fn join_by_external_commit(&mut self, group_info: &[u8]) -> Result<Array, JsError> {
+        let (group_id, commit) = self.inner.join_by_external_commit(group_info)?;
+        self.persist();
+        Ok(Array::of2(&JsValue::from_str(&group_id), &bytes_or_null(Some(commit))))
+    }
+
+    /// Add a member to an existing group. Returns `[welcome, commit]`.
+    #[wasm_bindgen(js_name = addMember)]
+    pub fn add_member(&mut self, group_id: &str, key_package: &[u8]) -> Result<Array, JsError> {
+        let (welcome, commit) = self.inner.add_member(group_id, key_package)?;
+        self.persist();
+        Ok(Array::of2(&bytes_or_null(Some(welcome)), &bytes_or_null(Some(commit))))
+    }
+
+    /// Remove a member by credential identity string. Returns the commit.
+    #[wasm_bindgen(js_name = removeMember)]
+    pub fn remove_member(&mut self, group_id: &str, member_identity: &str) -> Result<Vec<u8>, JsError> {
+        let commit = self.inner.remove_member(group_id, member_identity)?;
+        self.persist();
+        Ok(commit)
+    }
+
+    /// Rotate our own leaf key material with an Update commit. Returns the
+    /// commit.
+    #[wasm_bindgen(js_name = updateSelf)]
+    pub fn update_self(&mut self, group_id: &str) -> Result<Vec<u8>, JsError> {
+        let commit = self.inner.update_self(group_id)?;
+        self.persist();
+        Ok(commit)
+    }
+
+    /// Replace our credential in a group with a fresh one for
+    /// `new_device_id`, under a new signature key. Returns the commit.
+    #[wasm_bindgen(js_name = rotateCredential)]
+    pub fn rotate_credential(&mut self, group_id: &str, new_device_id: &str) -> Result<Vec<u8>, JsError> {
+        let commit = self.inner.rotate_credential(group_id, new_device_id)?;
+        self.persist();
+        Ok(commit)
+    }
+
+    /// Ask to leave a group. Returns the proposal removing our own leaf.
+    #[wasm_bindgen(js_name = leaveGroup)]
+    pub fn leave_group(&mut self, group_id: &str) -> Result<Vec<u8>, JsError> {
+        let proposal = self.inner.leave_group(group_id)?;
+        self.persist();
+        Ok(proposal)
+    }
+
+    /// Whether `leaveGroup` was called for a group that has not removed us
+    /// yet.
+    #[wasm_bindgen(js_name = isLeaving)]
+    pub fn is_leaving(&self, group_id: &str) -> Result<bool, JsError> {
+        Ok(self.inner.is_leaving(group_id)?)
+    }
+
+    /// Delete a group and all its stored state. Other members are not told.
+    #[wasm_bindgen(js_name = deleteGroup)]
+    pub fn delete_group(&mut self, group_id: &str) -> Result<(), JsError> {
+        self.inner.delete_group(group_id)?;
+        self.persist();
+        Ok(())
+    }
+
+    /// Commit the proposals received for a group. Returns
+    /// `[commit, welcome | null]`, or null if there were none.
+    #[wasm_bindgen(js_name = commitPendingProposals)]
+    pub fn commit_pending_proposals(&mut self, group_id: &str) -> Result<JsValue, JsError> {
+        let committed = self.inner.commit_pending_proposals(group_id)?;
+        self.persist();
+        Ok(match committed {
+            Some((commit, welcome)) => Array::of2(&bytes_or_null(Some(commit)), &bytes_or_null(welcome)).into(),
+            None => JsValue::NULL,
+        })
+    }
+
+    /// Process an incoming MLS message (commit, proposal, or application message).
+    #[wasm_bindgen(js_name = processMessage)]
+    pub fn process_message(&mut self, group_id: &str, message: &[u8]) -> Result<ProcessedMessage, JsError> {
+        let processed = self.inner.process_message(group_id, message)?;
+        if !matches!(processed.result, ProcessedResult::Application(_)) {
+            self.persist();
+        }
+        Ok(processed.into())
+    }
+
+    /// Results of buffered messages replayed since the last call.
+    #[wasm_bindgen(js_name = takeReplayed)]
+    pub fn take_replayed(&mut self, group_id: &str) -> Vec<ProcessedMessage> {
+        self.inner.take_replayed(group_id).into_iter().map(Into::into).collect()
+    }
+
+    /// Encrypt plaintext into an MLS application message.
+    pub fn encrypt(&mut self, group_id: &str, plaintext: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.encrypt(group_id, plaintext)?)
+    }
+
+    /// Decrypt an MLS application message.
+    pub fn decrypt(&mut self, group_id: &str, ciphertext: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.decrypt(group_id, ciphertext)?)
+    }
+
+    /// Export the key for a file attachment. Returns `[epoch, key]`.
+    #[wasm_bindgen(js_name = exportFileKey)]
+    pub fn export_file_key(&self, group_id: &str, file_id: &[u8], epoch: Option<u64>) -> Result<Array, JsError> {
+        let (epoch, key) = self.inner.export_file_key(group_id, file_id, epoch)?;
+        Ok(Array::of2(&js_sys::BigInt::from(epoch).into(), &bytes_or_null(Some(key))))
+    }
+
+    /// Read a message's envelope without processing it, as
+    /// `[groupId, epoch, contentType, senderType | null, senderIndex | null]`.
+    /// The sender is null for encrypted messages.
+    #[wasm_bindgen(js_name = peekMessage)]
+    pub fn peek_message(&self, message: &[u8]) -> Result<Array, JsError> {
+        let info = self.inner.peek_message(message)?;
+        let sender_type = info.sender.map_or(JsValue::NULL, |s| JsValue::from(s.kind()));
+        let sender_index = info.sender.and_then(|s| s.index()).map_or(JsValue::NULL, JsValue::from);
+        Ok([
+            JsValue::from(info.group_id),
+            JsValue::from(info.epoch),
+            JsValue::from(info.content_type),
+            sender_type,
+            sender_index,
+        ]
+        .into_iter()
+        .collect())
+    }
+
+    /// List a group's members as `[leafIndex, identity, signatureKey]`
+    /// arrays, ordered by leaf index.
+    #[wasm_bindgen(js_name = listMembers)]
+    pub fn list_members(&self, group_id: &str) -> Result<Array, JsError> {
+        let members = self.inner.list_members(group_id)?;
+        Ok(members
+            .into_iter()
+            .map(|(index, identity, key)| {
+                Array::of3(&JsValue::from(index), &bytes_or_null(Some(identity)), &bytes_or_null(Some(key)))
+            })
+            .collect())
+    }
+
+    /// The group's current epoch.
+    #[wasm_bindgen(js_name = groupEpoch)]
+    pub fn group_epoch(&self, group_id: &str) -> Result<u64, JsError> {
+        Ok(self.inner.group_epoch(group_id)?)
+    }
+
+    /// The current epoch's authenticator, for out-of-band verification.
+    #[wasm_bindgen(js_name = epochAuthenticator)]
+    pub fn epoch_authenticator(&self, group_id: &str) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.epoch_authenticator(group_id)?)
+    }
+
+    /// Check if a group exists in storage.
+    #[wasm_bindgen(js_name = groupExists)]
+    pub fn group_exists(&self, group_id: &str) -> bool {
+        self.inner.group_exists(group_id)
+    }
+
+    /// List all group IDs managed by this engine.
+    #[wasm_bindgen(js_name = listGroups)]
+    pub fn list_groups(&self) -> Result<Vec<String>, JsError> {
+        Ok(self.inner.list_groups()?)
+    }
+
+    /// The public identity key, or undefined if not initialized.
+    #[wasm_bindgen(js_name = identityKey)]
+    pub fn identity_key(&self) -> Option<Vec<u8>> {
+        self.inner.identity_key()
+    }
+
+    /// The stored `[userId, deviceId]`, or null if no identity is stored.
+    #[wasm_bindgen(js_name = getStoredIdentity)]
+    pub fn get_stored_identity(&self) -> Result<JsValue, JsError> {
+        Ok(match self.inner.stored_identity()? {
+            Some((user_id, device_id)) => {
+                Array::of2(&js_sys::BigInt::from(user_id).into(), &JsValue::from_str(&device_id)).into()
+            }
+            None => JsValue::NULL,
+        })
+    }
+
+    /// Export the full MLS state (identity + all groups). The bytes contain
+    /// private key material; encrypt them before storing them elsewhere.
+    #[wasm_bindgen(js_name = exportState)]
+    pub fn export_state(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_state()?)
+    }
+
+    /// Restore full MLS state from `exportState` output, replacing the
+    /// stored state.
+    #[wasm_bindgen(js_name = importState)]
+    pub fn import_state(&mut self, data: &[u8]) -> Result<(), JsError> {
+        self.inner.import_state(data)?;
+        self.persist();
+        Ok(())
+    }
+
+    /// Export one group's state for another device with the same identity.
+    #[wasm_bindgen(js_name = exportGroup)]
+    pub fn export_group(&self, group_id: &str) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_group(group_id)?)
+    }
+
+    /// Import a group from `exportGroup` output. Returns the group ID.
+    #[wasm_bindgen(js_name = importGroup)]
+    pub fn import_group(&mut self, data: &[u8]) -> Result<String, JsError> {
+        let group_id = self.inner.import_group(data)?;
+        self.persist();
+        Ok(group_id)
+    }
+
+    /// `exportState` output encrypted with a passphrase.
+    #[wasm_bindgen(js_name = exportStateEncrypted)]
+    pub fn export_state_encrypted(&self, passphrase: &str) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_state_encrypted(passphrase)?)
+    }
+
+    /// Restore state from `exportStateEncrypted` output.
+    #[wasm_bindgen(js_name = importStateEncrypted)]
+    pub fn import_state_encrypted(&mut self, data: &[u8], passphrase: &str) -> Result<(), JsError> {
+        self.inner.import_state_encrypted(data, passphrase)?;
+        self.persist();
+        Ok(())
+    }
+
+    /// Export the identity only, unencrypted. The format is the same as
+    /// the Python SDK's, so an identity moves between platforms.
+    #[wasm_bindgen(js_name = exportIdentity)]
+    pub fn export_identity(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.inner.export_identity()?)
+    }
+
+    /// Import a previously exported identity.
+    #[wasm_bindgen(js_name = importIdentity)]
+    pub fn import_identity(&mut self, data: &[u8], user_id: u64, device_id: &str) -> Result<(), JsError> {
+        self.inner.import_identity(data, user_id, device_id)?;
+        self.persist();
+        Ok(())
+    }
+}
@@ -0,0 +1,1034 @@
+//! The Rust API that the Python module wraps.
+
+use vox_mls::push::{decrypt_push_payload, encode_push_payload};
+use vox_mls::{
+    GroupEvent, GroupEventKind, GroupOptions, GroupStats, JoinOptions, KeyPackageOptions, KeyPackageStats,
+    MessageSender, MlsEngine, MlsError, PendingProposal, ProcessedResult, StorageOptions, Synchronous,
+};
+
+fn engine(user_id: u64, device_id: &str) -> MlsEngine {
+    let mut engine = MlsEngine::open(":memory:", None).unwrap();
+    engine.generate_identity(user_id, device_id).unwrap();
+    engine
+}
+
+#[test]
+fn two_members_exchange_messages() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _commit) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    assert_eq!(bob.join_group(&welcome).unwrap(), "room");
+    assert_eq!(bob.list_groups().unwrap(), ["room"]);
+
+    let ciphertext = alice.encrypt("room", b"hello bob").unwrap();
+    assert_eq!(bob.decrypt("room", &ciphertext).unwrap(), b"hello bob");
+
+    let reply = bob.encrypt("room", b"hi alice").unwrap();
+    let processed = alice.process_message("room", &reply).unwrap();
+    assert_eq!(processed.result, ProcessedResult::Application(b"hi alice".to_vec()));
+}
+
+#[test]
+fn engines_move_between_threads() {
+    let mut alice = engine(1, "phone");
+    alice.create_group("room", &[]).unwrap();
+    let alice = std::thread::spawn(move || {
+        alice.encrypt("room", b"from another thread").unwrap();
+        alice
+    })
+    .join()
+    .unwrap();
+    assert_eq!(alice.list_groups().unwrap(), ["room"]);
+    alice.close().unwrap();
+}
+
+#[test]
+fn processed_messages_carry_sender_and_epoch() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _commit) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let reply = bob.encrypt("room", b"hi alice").unwrap();
+    let processed = alice.process_message("room", &reply).unwrap();
+    assert_eq!(processed.epoch, alice.group_epoch("room").unwrap());
+    assert_eq!(processed.sender, Some(MessageSender::Member(1)));
+    assert_eq!(processed.sender_identity.as_deref(), Some(&b"2:laptop"[..]));
+}
+
+#[test]
+fn errors_distinguish_unknown_groups_and_missing_identity() {
+    let mut engine = MlsEngine::open(":memory:", None).unwrap();
+    assert!(matches!(engine.create_group("room", &[]), Err(MlsError::Failed(_))));
+
+    engine.generate_identity(1, "phone").unwrap();
+    let err = engine.encrypt("nowhere", b"x").unwrap_err();
+    assert_eq!(err, MlsError::UnknownGroup("nowhere".into()));
+    assert_eq!(err.to_string(), "No group with id 'nowhere'");
+    let junk = vec![b"junk".to_vec()];
+    assert!(matches!(engine.create_group("room", &junk), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn push_payload_decrypts_without_consuming() {
+    let path = std::env::temp_dir().join(format!("vox-mls-push-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let mut alice = engine(1, "phone");
+    let mut bob = MlsEngine::open(db_path, None).unwrap();
+    bob.generate_identity(2, "laptop").unwrap();
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let message = alice.encrypt("room", b"ping").unwrap();
+    let payload = encode_push_payload("room", message.clone());
+    let push = decrypt_push_payload(&payload, db_path, None, None, 120, false).unwrap();
+    assert_eq!((push.sender_user_id, push.sender_device_id.as_deref()), (Some(1), Some("phone")));
+    assert_eq!(push.preview.as_deref(), Some("ping"));
+
+    // The app can still decrypt the same message
+    assert_eq!(bob.decrypt("room", &message).unwrap(), b"ping");
+    drop(bob);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+#[test]
+fn db_key_needs_sqlcipher() {
+    let opened = MlsEngine::open_with_db_key(":memory:", None, Some([7; 32]));
+    assert!(matches!(opened, Err(MlsError::Failed(e)) if e.contains("sqlcipher")));
+}
+
+#[cfg(feature = "sqlcipher")]
+#[test]
+fn db_key_encrypts_the_whole_database() {
+    let path = std::env::temp_dir().join(format!("vox-mls-sqlcipher-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let mut engine = MlsEngine::open_with_db_key(db_path, None, Some([7; 32])).unwrap();
+    engine.generate_identity(1, "phone").unwrap();
+    engine.create_group("room", &[]).unwrap();
+    drop(engine);
+
+    assert!(!std::fs::read(&path).unwrap().windows(7).any(|w| w == b"1:phone"));
+    assert!(MlsEngine::open(db_path, None).is_err());
+    assert!(MlsEngine::open_with_db_key(db_path, None, Some([8; 32])).is_err());
+    let engine = MlsEngine::open_with_db_key(db_path, None, Some([7; 32])).unwrap();
+    assert_eq!(engine.list_groups().unwrap(), ["room"]);
+    drop(engine);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn open_forgets_groups_left_half_written() {
+    let path = std::env::temp_dir().join(format!("vox-mls-recover-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let mut engine = MlsEngine::open(db_path, None).unwrap();
+    engine.generate_identity(1, "phone").unwrap();
+    engine.create_group("room", &[]).unwrap();
+    drop(engine);
+
+    // A tracking row whose group state never made it to disk
+    let conn = rusqlite::Connection::open(db_path).unwrap();
+    conn.execute("INSERT INTO vox_groups (group_id) VALUES ('ghost')", []).unwrap();
+    drop(conn);
+
+    let engine = MlsEngine::open(db_path, None).unwrap();
+    assert_eq!(engine.list_groups().unwrap(), ["room"]);
+    assert!(engine.group_exists("room"));
+    drop(engine);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn storage_options_configure_sqlite() {
+    let path = std::env::temp_dir().join(format!("vox-mls-wal-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let options = StorageOptions {
+        wal: true,
+        synchronous: Some(Synchronous::Normal),
+        busy_timeout: Some(std::time::Duration::from_secs(1)),
+    };
+    let mut engine = MlsEngine::open_with_options(db_path, None, None, &options).unwrap();
+    engine.generate_identity(1, "phone").unwrap();
+    engine.create_group("room", &[]).unwrap();
+
+    // A second connection reads while the engine holds the database open
+    let conn = rusqlite::Connection::open(db_path).unwrap();
+    let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+    assert_eq!(mode, "wal");
+    let groups: i64 = conn.query_row("SELECT count(*) FROM vox_groups", [], |row| row.get(0)).unwrap();
+    assert_eq!(groups, 1);
+    drop(conn);
+    drop(engine);
+    assert!(Synchronous::parse("sometimes").is_err());
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{db_path}{suffix}"));
+    }
+}
+
+#[test]
+fn closing_flushes_the_write_ahead_log() {
+    let path = std::env::temp_dir().join(format!("vox-mls-close-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let options = StorageOptions { wal: true, ..StorageOptions::default() };
+    let mut engine = MlsEngine::open_with_options(db_path, None, None, &options).unwrap();
+    engine.generate_identity(1, "phone").unwrap();
+    engine.create_group("room", &[]).unwrap();
+    engine.close().unwrap();
+    let wal = std::fs::metadata(format!("{db_path}-wal")).map(|m| m.len()).unwrap_or(0);
+    assert_eq!(wal, 0);
+
+    let engine = MlsEngine::open(db_path, None).unwrap();
+    assert_eq!(engine.list_groups().unwrap(), ["room"]);
+    drop(engine);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{db_path}{suffix}"));
+    }
+}
+
+#[test]
+fn json_databases_are_migrated_to_cbor() {
+    let path = std::env::temp_dir().join(format!("vox-mls-json-{}.db", std::process::id()));
+    let db_path = path.to_str().unwrap();
+    let mut engine = MlsEngine::open(db_path, None).unwrap();
+    engine.generate_identity(1, "phone").unwrap();
+    engine.create_group("room", &[]).unwrap();
+    engine.update_self("room").unwrap();
+    drop(engine);
+
+    // Rewrite the OpenMLS rows the way the old JSON codec stored them
+    let conn = rusqlite::Connection::open(db_path).unwrap();
+    let tables: Vec<String> = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'openmls_%'")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    for table in tables {
+        let columns: Vec<String> = conn
+            .prepare(&format!("PRAGMA table_info({table})"))
+            .unwrap()
+            .query_map([], |row| row.get(1))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for column in columns {
+            let rows: Vec<(i64, Vec<u8>)> = conn
+                .prepare(&format!("SELECT rowid, {column} FROM {table} WHERE typeof({column}) = 'blob'"))
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            for (rowid, cbor) in rows {
+                let value: ciborium::Value = ciborium::from_reader(&cbor[..]).unwrap();
+                let json = serde_json::to_vec(&value).unwrap();
+                conn.execute(&format!("UPDATE {table} SET {column} = ?1 WHERE rowid = ?2"), (json, rowid))
+                    .unwrap();
+            }
+        }
+    }
+    conn.execute_batch("PRAGMA user_version = 0").unwrap();
+    drop(conn);
+
+    let mut engine = MlsEngine::open(db_path, None).unwrap();
+    assert_eq!(engine.group_epoch("room").unwrap(), 1);
+    engine.update_self("room").unwrap();
+    drop(engine);
+    let engine = MlsEngine::open(db_path, None).unwrap();
+    assert_eq!(engine.group_epoch("room").unwrap(), 2);
+    drop(engine);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn compact_deletes_expired_key_packages() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let expiring = KeyPackageOptions { lifetime_secs: Some(1), ..KeyPackageOptions::default() };
+    let expired = bob.generate_key_package_with(&expiring).unwrap();
+    let fresh = bob.generate_key_package().unwrap();
+
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert_eq!(bob.compact().unwrap(), (0, 1));
+    assert_eq!(alice.compact().unwrap(), (0, 0));
+
+    // Bob can still join with the key package that had time left
+    let (welcome, _) = alice.add_member("room", &fresh).unwrap();
+    assert_eq!(bob.join_group(&welcome).unwrap(), "room");
+    assert!(alice.add_member("room", &expired).is_err());
+}
+
+#[test]
+fn stats_count_groups_and_key_packages() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let empty = alice.stats().unwrap();
+    assert!(empty.database_bytes > 0);
+    assert!(empty.groups.is_empty());
+    assert_eq!(empty.key_packages, KeyPackageStats::default());
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let last_resort = KeyPackageOptions { last_resort: true, ..KeyPackageOptions::default() };
+    bob.generate_key_package_with(&last_resort).unwrap();
+    let proposal = bob.leave_group("room").unwrap();
+    alice.process_message("room", &proposal).unwrap();
+
+    let room = GroupStats { group_id: "room".into(), epoch: 1, members: 2, pending_proposals: 1 };
+    assert_eq!(alice.stats().unwrap().groups, [room]);
+    assert_eq!(bob.stats().unwrap().key_packages, KeyPackageStats { total: 2, unused: 1, last_resort: 1 });
+}
+
+#[test]
+fn failed_operations_leave_the_group_untouched() {
+    let mut alice = engine(1, "phone");
+    alice.create_group("room", &[]).unwrap();
+    assert!(alice.add_member("room", b"junk").is_err());
+    assert!(alice.process_message("room", b"junk").is_err());
+    assert!(alice.join_group(b"junk").is_err());
+    assert_eq!(alice.list_groups().unwrap(), ["room"]);
+    assert_eq!(alice.export_file_key("room", b"f", Some(0)).unwrap().0, 0);
+}
+
+#[test]
+fn self_update_starts_a_new_epoch_for_everyone() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let (epoch, old_key) = bob.export_file_key("room", b"f", None).unwrap();
+
+    let commit = bob.update_self("room").unwrap();
+    assert_eq!(alice.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+    let (new_epoch, new_key) = alice.export_file_key("room", b"f", None).unwrap();
+    assert_eq!(new_epoch, epoch + 1);
+    assert_ne!(new_key, old_key);
+    assert_eq!(bob.export_file_key("room", b"f", None).unwrap(), (new_epoch, new_key));
+
+    let ciphertext = bob.encrypt("room", b"fresh keys").unwrap();
+    assert_eq!(alice.decrypt("room", &ciphertext).unwrap(), b"fresh keys");
+    assert_eq!(alice.update_self("nowhere"), Err(MlsError::UnknownGroup("nowhere".into())));
+}
+
+#[test]
+fn rotated_credentials_replace_our_identity() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    alice.create_group("lobby", &[]).unwrap();
+    for group_id in ["room", "lobby"] {
+        let (welcome, _) = alice.add_member(group_id, &bob.generate_key_package().unwrap()).unwrap();
+        bob.join_group(&welcome).unwrap();
+    }
+    let old_key = bob.identity_key().unwrap();
+
+    let commit = bob.rotate_credential("room", "tablet").unwrap();
+    assert_eq!(alice.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+    assert_eq!(bob.stored_identity().unwrap(), Some((2, "tablet".to_string())));
+    assert_ne!(bob.identity_key().unwrap(), old_key);
+    assert_eq!(alice.member_fingerprint("room", "2:tablet").unwrap(), bob.own_fingerprint().unwrap());
+    assert!(alice.member_fingerprint("room", "2:laptop").is_err());
+    let ciphertext = bob.encrypt("room", b"new key").unwrap();
+    assert_eq!(alice.decrypt("room", &ciphertext).unwrap(), b"new key");
+
+    // The lobby still knows the old credential, which keeps signing there
+    let ciphertext = bob.encrypt("lobby", b"old key").unwrap();
+    assert_eq!(alice.decrypt("lobby", &ciphertext).unwrap(), b"old key");
+    let commit = bob.rotate_credential("lobby", "tablet").unwrap();
+    alice.process_message("lobby", &commit).unwrap();
+    assert!(alice.member_fingerprint("lobby", "2:tablet").is_ok());
+}
+
+#[test]
+fn leaving_completes_when_another_member_commits() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    assert_eq!(alice.commit_pending_proposals("room").unwrap(), None);
+
+    let proposal = bob.leave_group("room").unwrap();
+    assert!(bob.is_leaving("room").unwrap());
+    assert_eq!(alice.process_message("room", &proposal).unwrap().result, ProcessedResult::Proposal);
+    let (commit, welcome) = alice.commit_pending_proposals("room").unwrap().unwrap();
+    assert_eq!(welcome, None);
+
+    assert_eq!(bob.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+    assert!(!bob.group_exists("room"));
+    assert!(bob.list_groups().unwrap().is_empty());
+    assert!(!bob.is_leaving("room").unwrap());
+    assert!(alice.encrypt("room", b"alone").is_ok());
+}
+
+#[test]
+fn pending_proposals_are_listed_and_cleared() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    assert!(alice.list_pending_proposals("room").unwrap().is_empty());
+
+    let proposal = bob.leave_group("room").unwrap();
+    alice.process_message("room", &proposal).unwrap();
+    assert_eq!(
+        alice.list_pending_proposals("room").unwrap(),
+        [PendingProposal {
+            proposal_type: "remove",
+            sender: MessageSender::Member(1),
+            sender_identity: Some(b"2:laptop".to_vec()),
+        }]
+    );
+
+    alice.clear_pending_proposals("room").unwrap();
+    assert!(alice.list_pending_proposals("room").unwrap().is_empty());
+    assert_eq!(alice.commit_pending_proposals("room").unwrap(), None);
+    assert_eq!(alice.clear_pending_proposals("nowhere"), Err(MlsError::UnknownGroup("nowhere".into())));
+}
+
+#[test]
+fn encrypted_state_backup_round_trips() {
+    let mut alice = engine(1, "phone");
+    alice.create_group("room", &[]).unwrap();
+    let backup = alice.export_state_encrypted("correct horse").unwrap();
+
+    let mut restored = MlsEngine::open(":memory:", None).unwrap();
+    assert!(matches!(restored.import_state_encrypted(&backup, "wrong horse"), Err(MlsError::InvalidInput(_))));
+    assert!(restored.identity_key().is_none());
+    let mut tampered = backup.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(matches!(restored.import_state_encrypted(&tampered, "correct horse"), Err(MlsError::InvalidInput(_))));
+
+    restored.import_state_encrypted(&backup, "correct horse").unwrap();
+    assert_eq!(restored.identity_key(), alice.identity_key());
+    assert!(restored.group_exists("room"));
+}
+
+#[test]
+fn a_group_moves_to_a_device_with_the_same_identity() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    alice.create_group("other", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let export = alice.export_group("room").unwrap();
+
+    let mut stranger = engine(3, "tablet");
+    assert!(matches!(stranger.import_group(&export), Err(MlsError::InvalidInput(_))));
+    assert!(!stranger.group_exists("room"));
+
+    let mut moved = MlsEngine::open(":memory:", None).unwrap();
+    moved.import_identity(&alice.export_identity().unwrap(), 1, "phone").unwrap();
+    assert_eq!(moved.import_group(&export).unwrap(), "room");
+    assert_eq!(moved.list_groups().unwrap(), ["room"]);
+
+    let ciphertext = bob.encrypt("room", b"hello").unwrap();
+    assert_eq!(moved.decrypt("room", &ciphertext).unwrap(), b"hello");
+    let commit = bob.update_self("room").unwrap();
+    moved.process_message("room", &commit).unwrap();
+    let reply = moved.encrypt("room", b"hi").unwrap();
+    assert_eq!(bob.decrypt("room", &reply).unwrap(), b"hi");
+}
+
+#[test]
+fn removed_groups_can_be_deleted() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let commit = alice.remove_member("room", "2:laptop").unwrap();
+    bob.process_message("room", &commit).unwrap();
+    // Removed without asking to leave: the state stays until deleted
+    assert!(bob.group_exists("room"));
+
+    bob.delete_group("room").unwrap();
+    assert!(!bob.group_exists("room"));
+    assert!(bob.list_groups().unwrap().is_empty());
+    assert_eq!(bob.delete_group("room").unwrap_err(), MlsError::UnknownGroup("room".into()));
+    assert!(alice.encrypt("room", b"alone").is_ok());
+}
+
+#[test]
+fn lost_state_is_recovered_by_external_commit() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let group_info = alice.export_group_info("room", true).unwrap();
+    let bare = alice.export_group_info("room", false).unwrap();
+    assert!(bare.len() < group_info.len());
+
+    // Bob's second device has no state for the group at all
+    let mut bob_again = engine(2, "tablet");
+    let (group_id, commit) = bob_again.join_by_external_commit(&group_info).unwrap();
+    assert_eq!(group_id, "room");
+    assert_eq!(bob_again.list_groups().unwrap(), ["room"]);
+    assert_eq!(alice.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+    assert_eq!(bob.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+
+    let ciphertext = bob_again.encrypt("room", b"back again").unwrap();
+    assert_eq!(alice.decrypt("room", &ciphertext).unwrap(), b"back again");
+    assert!(bob_again.join_by_external_commit(b"junk").is_err());
+}
+
+#[test]
+fn group_info_comes_with_the_commit_when_asked_for() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let (_, _, group_info) = alice.create_group_with_group_info("room", &[], &GroupOptions::default()).unwrap();
+    let mut carol = engine(3, "tablet");
+    let (_, join) = carol.join_by_external_commit(&group_info).unwrap();
+    alice.process_message("room", &join).unwrap();
+
+    let (welcome, commit, group_info) =
+        alice.add_member_with_group_info("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    carol.process_message("room", &commit).unwrap();
+    let (_, join) = engine(4, "desktop").join_by_external_commit(&group_info).unwrap();
+    for member in [&mut alice, &mut bob, &mut carol] {
+        assert_eq!(member.process_message("room", &join).unwrap().result, ProcessedResult::Commit);
+    }
+}
+
+#[test]
+fn peeking_reads_the_envelope_only() {
+    let mut alice = engine(1, "phone");
+    alice.create_group("room", &[]).unwrap();
+    let group_info = alice.export_group_info("room", true).unwrap();
+    let mut bob = engine(2, "laptop");
+    let (_, commit) = bob.join_by_external_commit(&group_info).unwrap();
+
+    let info = alice.peek_message(&commit).unwrap();
+    assert_eq!((info.group_id.as_str(), info.epoch, info.content_type), ("room", 0, "commit"));
+    assert_eq!(info.sender, Some(MessageSender::NewMemberCommit));
+    // Peeking changed nothing
+    assert_eq!(alice.group_epoch("room").unwrap(), 0);
+    alice.process_message("room", &commit).unwrap();
+
+    let ciphertext = bob.encrypt("room", b"hello").unwrap();
+    let info = bob.peek_message(&ciphertext).unwrap();
+    assert_eq!((info.epoch, info.content_type, info.sender), (1, "application", None));
+    assert_eq!(alice.decrypt("room", &ciphertext).unwrap(), b"hello");
+    assert!(matches!(alice.peek_message(b"junk"), Err(MlsError::InvalidInput(_))));
+    assert!(matches!(alice.peek_message(&group_info), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn groups_use_the_chosen_ciphersuite() {
+    let chacha = vox_mls::parse_ciphersuite("MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519").unwrap();
+    assert!(vox_mls::parse_ciphersuite("MLS_256_DHKEMP384_AES256GCM_SHA384_P384").is_err());
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    bob.set_ciphersuite(chacha).unwrap();
+    let key_package = bob.generate_key_package().unwrap();
+
+    // Alice's default suite cannot take Bob's key package
+    alice.create_group("default", &[]).unwrap();
+    assert!(alice.add_member("default", &key_package).is_err());
+
+    let (welcome, _) = alice.create_group_with_ciphersuite("room", &[key_package], chacha).unwrap();
+    assert_eq!(bob.join_group(&welcome.unwrap()).unwrap(), "room");
+    let ciphertext = alice.encrypt("room", b"chacha").unwrap();
+    assert_eq!(bob.decrypt("room", &ciphertext).unwrap(), b"chacha");
+    assert_eq!(alice.ciphersuite(), vox_mls::DEFAULT_CIPHERSUITE);
+}
+
+#[test]
+fn members_are_listed_by_leaf_index() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let alice_key = alice.identity_key().unwrap();
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let members = bob.list_members("room").unwrap();
+    let identities: Vec<(u32, &[u8])> = members.iter().map(|(i, id, _)| (*i, id.as_slice())).collect();
+    assert_eq!(identities, [(0, &b"1:phone"[..]), (1, &b"2:laptop"[..])]);
+    assert_eq!(members[0].2, alice_key);
+    assert_eq!(members[1].2, bob.identity_key().unwrap());
+    assert_eq!(bob.list_members("nowhere").unwrap_err(), MlsError::UnknownGroup("nowhere".into()));
+}
+
+#[test]
+fn members_agree_on_epoch_and_authenticator() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    assert_eq!(alice.group_epoch("room").unwrap(), 0);
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    assert_eq!(bob.group_epoch("room").unwrap(), 1);
+    let authenticator = alice.epoch_authenticator("room").unwrap();
+    assert!(!authenticator.is_empty());
+    assert_eq!(bob.epoch_authenticator("room").unwrap(), authenticator);
+
+    let commit = bob.update_self("room").unwrap();
+    assert_ne!(bob.epoch_authenticator("room").unwrap(), authenticator);
+    alice.process_message("room", &commit).unwrap();
+    assert_eq!(alice.epoch_authenticator("room").unwrap(), bob.epoch_authenticator("room").unwrap());
+    assert_eq!(alice.group_epoch("room").unwrap(), 2);
+}
+
+#[test]
+fn key_package_options_are_applied() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let options = KeyPackageOptions {
+        lifetime_secs: Some(3600),
+        extensions: vec![(0xff00, b"vox-marker".to_vec())],
+        capability_extensions: vec![0xff01],
+        last_resort: false,
+    };
+    let packages = bob.generate_key_packages_with(2, &options).unwrap();
+    assert_eq!(packages.len(), 2);
+    assert!(packages[0].windows(10).any(|w| w == b"vox-marker"));
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &packages[0]).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    // Ratchet tree is a type OpenMLS handles itself
+    let reserved = KeyPackageOptions { extensions: vec![(2, vec![])], ..KeyPackageOptions::default() };
+    assert!(matches!(bob.generate_key_package_with(&reserved), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn group_context_extensions_are_shared_and_updated() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let capable = KeyPackageOptions { capability_extensions: vec![0xff10], ..KeyPackageOptions::default() };
+    let key_package = bob.generate_key_package_with(&capable).unwrap();
+    let options = GroupOptions { extensions: vec![(0xff10, b"room 42".to_vec())], ..GroupOptions::default() };
+    let (welcome, _) = alice.create_group_with("room", &[key_package], &options).unwrap();
+    bob.join_group(&welcome.unwrap()).unwrap();
+    assert_eq!(bob.group_context_extensions("room").unwrap(), [(0xff10, b"room 42".to_vec())]);
+
+    let commit = alice.update_group_context_extensions("room", &[(0xff10, b"room 43".to_vec())]).unwrap();
+    assert_eq!(bob.process_message("room", &commit).unwrap().result, ProcessedResult::Commit);
+    assert_eq!(bob.group_context_extensions("room").unwrap(), [(0xff10, b"room 43".to_vec())]);
+    assert_eq!(alice.group_context_extensions("room").unwrap(), bob.group_context_extensions("room").unwrap());
+
+    let reserved = [(2, vec![])];
+    assert!(matches!(alice.update_group_context_extensions("room", &reserved), Err(MlsError::InvalidInput(_))));
+    let options = GroupOptions { extensions: reserved.to_vec(), ..GroupOptions::default() };
+    assert!(matches!(alice.create_group_with("other", &[], &options), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn key_packages_lacking_required_capabilities_are_refused() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let mut carol = engine(3, "tablet");
+    let options = GroupOptions { required_extensions: vec![0xff20], ..GroupOptions::default() };
+    let plain = bob.generate_key_package().unwrap();
+    assert!(alice.create_group_with("room", &[plain.clone()], &options).is_err());
+    assert!(alice.list_groups().unwrap().is_empty());
+
+    let capable = KeyPackageOptions { capability_extensions: vec![0xff20], ..KeyPackageOptions::default() };
+    let key_package = bob.generate_key_package_with(&capable).unwrap();
+    let (welcome, _) = alice.create_group_with("room", &[key_package], &options).unwrap();
+    bob.join_group(&welcome.unwrap()).unwrap();
+    assert!(alice.add_member("room", &carol.generate_key_package().unwrap()).is_err());
+    alice.add_member("room", &carol.generate_key_package_with(&capable).unwrap()).unwrap();
+
+    let reserved = GroupOptions { required_extensions: vec![2], ..GroupOptions::default() };
+    assert!(matches!(alice.create_group_with("other", &[], &reserved), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn messages_from_earlier_epochs_are_refused() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let late = bob.encrypt("room", b"epoch 1").unwrap();
+    let commit = bob.update_self("room").unwrap();
+    alice.process_message("room", &commit).unwrap();
+    assert_eq!(
+        alice.process_message("room", &late),
+        Err(MlsError::WrongEpoch { message_epoch: 1, current_epoch: 2 })
+    );
+    assert_eq!(alice.group_epoch("room").unwrap(), 2);
+}
+
+#[test]
+fn own_commits_relayed_back_are_recognised() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, add) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let update = alice.update_self("room").unwrap();
+
+    for (commit, epoch) in [(&add, 0), (&update, 1)] {
+        let processed = alice.process_message("room", commit).unwrap();
+        assert_eq!((processed.result, processed.epoch), (ProcessedResult::OwnCommit, epoch));
+        assert_eq!(processed.sender, Some(MessageSender::Member(0)));
+    }
+    assert_eq!(alice.group_epoch("room").unwrap(), 2);
+    assert_eq!(bob.process_message("room", &update).unwrap().result, ProcessedResult::Commit);
+
+    // Someone else's commit is refused once it is stale
+    let commit = bob.update_self("room").unwrap();
+    alice.process_message("room", &commit).unwrap();
+    assert!(matches!(alice.process_message("room", &commit), Err(MlsError::WrongEpoch { .. })));
+}
+
+#[test]
+fn messages_from_later_epochs_are_replayed_in_order() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let first_commit = bob.update_self("room").unwrap();
+    let first = bob.encrypt("room", b"epoch 2").unwrap();
+    let second_commit = bob.update_self("room").unwrap();
+    let second = bob.encrypt("room", b"epoch 3").unwrap();
+
+    // Delivered newest first
+    for message in [&second, &second_commit, &first] {
+        assert_eq!(alice.process_message("room", message).unwrap().result, ProcessedResult::Buffered);
+    }
+    assert!(alice.take_replayed("room").is_empty());
+    assert_eq!(alice.process_message("room", &first_commit).unwrap().result, ProcessedResult::Commit);
+    assert_eq!(
+        alice.take_replayed("room").into_iter().map(|processed| processed.result).collect::<Vec<_>>(),
+        [
+            ProcessedResult::Application(b"epoch 2".to_vec()),
+            ProcessedResult::Commit,
+            ProcessedResult::Application(b"epoch 3".to_vec()),
+        ]
+    );
+    assert!(alice.take_replayed("room").is_empty());
+    assert_eq!(alice.group_epoch("room").unwrap(), bob.group_epoch("room").unwrap());
+}
+
+#[test]
+fn messages_too_far_ahead_are_refused() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    for _ in 0..8 {
+        bob.update_self("room").unwrap();
+    }
+    let last_buffered = bob.encrypt("room", b"epoch 9").unwrap();
+    bob.update_self("room").unwrap();
+    let too_far = bob.encrypt("room", b"epoch 10").unwrap();
+
+    assert_eq!(alice.process_message("room", &last_buffered).unwrap().result, ProcessedResult::Buffered);
+    assert_eq!(
+        alice.process_message("room", &too_far),
+        Err(MlsError::WrongEpoch { message_epoch: 10, current_epoch: 1 })
+    );
+}
+
+#[test]
+fn full_buffer_drops_the_farthest_epoch_first() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let first_commit = bob.update_self("room").unwrap();
+    let near = bob.encrypt("room", b"epoch 2").unwrap();
+    let second_commit = bob.update_self("room").unwrap();
+    let far: Vec<Vec<u8>> = (0..256).map(|i| bob.encrypt("room", format!("{i}").as_bytes()).unwrap()).collect();
+
+    // The buffer is full of epoch 3; each epoch 2 message evicts its oldest
+    for message in far.iter().chain([&second_commit, &near]) {
+        assert_eq!(alice.process_message("room", message).unwrap().result, ProcessedResult::Buffered);
+    }
+    alice.process_message("room", &first_commit).unwrap();
+    let replayed: Vec<ProcessedResult> = alice.take_replayed("room").into_iter().map(|p| p.result).collect();
+    assert_eq!(replayed.len(), 256);
+    assert_eq!(replayed[0], ProcessedResult::Application(b"epoch 2".to_vec()));
+    assert_eq!(replayed[1], ProcessedResult::Commit);
+    assert_eq!(replayed[2], ProcessedResult::Application(b"2".to_vec()));
+    assert_eq!(replayed[255], ProcessedResult::Application(b"255".to_vec()));
+}
+
+#[test]
+fn staged_commits_wait_for_merge_or_reject() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let mut carol = engine(3, "tablet");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let (_, commit) = alice.add_member("room", &carol.generate_key_package().unwrap()).unwrap();
+    let processed = bob.process_message_staged("room", &commit).unwrap();
+    let ProcessedResult::StagedCommit(info) = processed.result else {
+        panic!("commit was not staged: {processed:?}");
+    };
+    assert_eq!(info.adds, [b"3:tablet".to_vec()]);
+    assert!(info.removes.is_empty());
+    assert_eq!(bob.group_epoch("room").unwrap(), 1);
+    bob.merge_staged_commit("room").unwrap();
+    assert_eq!(bob.group_epoch("room").unwrap(), 2);
+    assert!(matches!(bob.merge_staged_commit("room"), Err(MlsError::InvalidInput(_))));
+
+    let commit = alice.remove_member("room", "3:tablet").unwrap();
+    let processed = bob.process_message_staged("room", &commit).unwrap();
+    let ProcessedResult::StagedCommit(info) = processed.result else {
+        panic!("commit was not staged: {processed:?}");
+    };
+    assert_eq!(info.removes, [(2, b"3:tablet".to_vec())]);
+    assert_eq!(info.updates, [(0, b"1:phone".to_vec())]);
+    bob.reject_staged_commit("room").unwrap();
+    assert_eq!(bob.group_epoch("room").unwrap(), 2);
+    assert_eq!(bob.list_members("room").unwrap().len(), 3);
+    assert!(matches!(bob.reject_staged_commit("room"), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn reinit_moves_members_to_a_new_ciphersuite() {
+    let chacha = vox_mls::parse_ciphersuite("MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519").unwrap();
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    // Key packages must cover exactly the other members
+    assert!(matches!(alice.reinit_group("room", chacha, &[]), Err(MlsError::InvalidInput(_))));
+
+    bob.set_ciphersuite(chacha).unwrap();
+    let key_package = bob.generate_key_package().unwrap();
+    let welcome = alice.reinit_group("room", chacha, &[key_package]).unwrap().unwrap();
+    assert_eq!(bob.join_group(&welcome).unwrap(), "room");
+    assert_eq!(bob.list_groups().unwrap(), ["room"]);
+    assert_eq!(alice.group_epoch("room").unwrap(), bob.group_epoch("room").unwrap());
+    assert_eq!(alice.epoch_authenticator("room").unwrap(), bob.epoch_authenticator("room").unwrap());
+
+    let ciphertext = alice.encrypt("room", b"after re-init").unwrap();
+    assert_eq!(bob.decrypt("room", &ciphertext).unwrap(), b"after re-init");
+}
+
+#[test]
+fn welcomes_without_the_tree_join_with_it_passed_along() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let options = GroupOptions { omit_ratchet_tree: true, ..GroupOptions::default() };
+    alice.create_group_with("room", &[], &options).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+
+    assert!(bob.join_group(&welcome).is_err());
+    let tree = alice.export_ratchet_tree("room").unwrap();
+    assert_eq!(bob.join_group_with_ratchet_tree(&welcome, Some(&tree)).unwrap(), "room");
+    assert_eq!(bob.export_ratchet_tree("room").unwrap(), tree);
+
+    let ciphertext = alice.encrypt("room", b"out of band").unwrap();
+    assert_eq!(bob.decrypt("room", &ciphertext).unwrap(), b"out of band");
+    assert!(matches!(bob.join_group_with_ratchet_tree(&welcome, Some(b"junk")), Err(MlsError::Failed(_))));
+}
+
+#[test]
+fn padded_messages_hide_plaintext_length() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let options = GroupOptions { padding_size: 256, ..GroupOptions::default() };
+    alice.create_group_with("room", &[], &options).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group_with(&welcome, None, &JoinOptions { padding_size: 256 }).unwrap();
+
+    let short = alice.encrypt("room", b"hi").unwrap();
+    let long = alice.encrypt("room", &[b'x'; 200]).unwrap();
+    assert_eq!(short.len(), long.len());
+    assert_eq!(bob.decrypt("room", &short).unwrap(), b"hi");
+    let reply = bob.encrypt("room", b"hello").unwrap();
+    assert_eq!(reply.len(), bob.encrypt("room", &[b'y'; 100]).unwrap().len());
+    assert_eq!(alice.decrypt("room", &reply).unwrap(), b"hello");
+}
+
+#[test]
+fn key_packages_are_tracked_until_deleted() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let used = bob.generate_key_package().unwrap();
+    let spare = KeyPackageOptions { last_resort: true, ..KeyPackageOptions::default() };
+    let spare = bob.generate_key_package_with(&spare).unwrap();
+    let listed = bob.list_key_packages().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert!(!listed[0].last_resort && listed[1].last_resort);
+    assert!(listed.iter().all(|kp| !kp.consumed && kp.created_at > 0));
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &used).unwrap();
+    bob.join_group(&welcome).unwrap();
+    let listed = bob.list_key_packages().unwrap();
+    assert!(listed[0].consumed && !listed[1].consumed);
+
+    // A deleted key package can no longer be joined with
+    bob.delete_key_package(&listed[1].hash_ref).unwrap();
+    alice.create_group("other", &[]).unwrap();
+    let (welcome, _) = alice.add_member("other", &spare).unwrap();
+    assert!(bob.join_group(&welcome).is_err());
+
+    bob.delete_key_package(&listed[0].hash_ref).unwrap();
+    assert!(bob.list_key_packages().unwrap().is_empty());
+    assert!(matches!(bob.delete_key_package(&listed[0].hash_ref), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn key_packages_are_inspected_without_an_engine() {
+    let bob = engine(2, "laptop");
+    let options = KeyPackageOptions { lifetime_secs: Some(3600), ..KeyPackageOptions::default() };
+    let key_package = bob.generate_key_package_with(&options).unwrap();
+
+    let info = MlsEngine::inspect_key_package(&key_package).unwrap();
+    assert_eq!(info.identity, b"2:laptop");
+    assert_eq!(info.ciphersuite, vox_mls::DEFAULT_CIPHERSUITE);
+    assert_eq!(Some(info.signature_key), bob.identity_key());
+    assert!(info.not_after > info.not_before && info.not_after - info.not_before <= 3600 * 2);
+    assert!(!info.last_resort);
+    assert!(matches!(MlsEngine::inspect_key_package(b"junk"), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn welcomes_are_inspected_without_using_up_the_key_package() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+
+    let info = bob.inspect_welcome(&welcome, None).unwrap();
+    assert_eq!((info.group_id.as_str(), info.sender.as_slice()), ("room", b"1:phone".as_slice()));
+    assert_eq!(info.ciphersuite, vox_mls::DEFAULT_CIPHERSUITE);
+    assert!(!bob.group_exists("room"));
+
+    assert_eq!(bob.join_group(&welcome).unwrap(), "room");
+    let stranger = engine(3, "tablet");
+    assert!(matches!(stranger.inspect_welcome(&welcome, None), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn identities_share_a_database_and_a_group() {
+    let mut engine = engine(1, "phone");
+    engine.use_identity(Some("bot")).unwrap();
+    assert_eq!(engine.identity_handle(), Some("bot"));
+    assert_eq!(engine.identity_key(), None);
+    engine.generate_identity(2, "bot").unwrap();
+    let key_package = engine.generate_key_package().unwrap();
+
+    engine.use_identity(None).unwrap();
+    engine.create_group("room", &[]).unwrap();
+    let (welcome, _) = engine.add_member("room", &key_package).unwrap();
+    let ciphertext = engine.encrypt("room", b"hello bot").unwrap();
+
+    engine.use_identity(Some("bot")).unwrap();
+    assert_eq!(engine.join_group(&welcome).unwrap(), "room");
+    assert_eq!(engine.decrypt("room", &ciphertext).unwrap(), b"hello bot");
+    let reply = engine.encrypt("room", b"hi").unwrap();
+
+    engine.use_identity(None).unwrap();
+    assert_eq!(engine.decrypt("room", &reply).unwrap(), b"hi");
+    assert_eq!(engine.list_identities().unwrap(), ["bot"]);
+    assert!(engine.list_key_packages().unwrap().is_empty());
+
+    // Identities have their own groups
+    engine.create_group("lobby", &[]).unwrap();
+    engine.use_identity(Some("bot")).unwrap();
+    assert_eq!(engine.list_groups().unwrap(), ["room"]);
+}
+
+#[test]
+fn engines_on_one_thread_keep_their_identities() {
+    let mut alice = engine(1, "phone");
+    let mut bot = MlsEngine::open(":memory:", None).unwrap();
+    bot.use_identity(Some("bot")).unwrap();
+    bot.generate_identity(2, "bot").unwrap();
+
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bot.generate_key_package().unwrap()).unwrap();
+    assert_eq!(bot.join_group(&welcome).unwrap(), "room");
+    let ciphertext = alice.encrypt("room", b"hello bot").unwrap();
+    let reply = bot.encrypt("room", b"hi").unwrap();
+    assert_eq!(alice.decrypt("room", &reply).unwrap(), b"hi");
+    assert_eq!(bot.decrypt("room", &ciphertext).unwrap(), b"hello bot");
+    assert!(alice.list_identities().unwrap().is_empty());
+    assert_eq!(bot.list_identities().unwrap(), ["bot"]);
+}
+
+#[test]
+fn fingerprints_match_across_members() {
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    alice.create_group("room", &[]).unwrap();
+    let (welcome, _) = alice.add_member("room", &bob.generate_key_package().unwrap()).unwrap();
+    bob.join_group(&welcome).unwrap();
+
+    let bobs = bob.own_fingerprint().unwrap();
+    assert_eq!(alice.member_fingerprint("room", "2:laptop").unwrap(), bobs);
+    assert_eq!(bob.member_fingerprint("room", "1:phone").unwrap(), alice.own_fingerprint().unwrap());
+    assert_ne!(alice.own_fingerprint().unwrap(), bobs);
+    let groups: Vec<&str> = bobs.split(' ').collect();
+    assert_eq!(groups.len(), 12);
+    assert!(groups.iter().all(|group| group.len() == 5 && group.bytes().all(|b| b.is_ascii_digit())));
+    assert!(matches!(alice.member_fingerprint("room", "3:tablet"), Err(MlsError::InvalidInput(_))));
+}
+
+#[test]
+fn group_events_report_lifecycle_changes() {
+    let event = |group_id: &str, epoch, kind| GroupEvent { group_id: group_id.into(), epoch, kind };
+    let added = |leaf_index, identity: &str| GroupEventKind::MemberAdded { leaf_index, identity: identity.into() };
+    let removed = |leaf_index, identity: &str| GroupEventKind::MemberRemoved { leaf_index, identity: identity.into() };
+
+    let mut alice = engine(1, "phone");
+    let mut bob = engine(2, "laptop");
+    let mut carol = engine(3, "tablet");
+    alice.create_group("lobby", &[]).unwrap();
+    alice.watch_group_events(true).unwrap();
+    bob.watch_group_events(true).unwrap();
+    assert!(alice.take_group_events().is_empty());
+
+    let (welcome, _) = alice.create_group("room", &[bob.generate_key_package().unwrap()]).unwrap();
+    assert_eq!(
+        alice.take_group_events(),
+        [event("room", 1, GroupEventKind::Created), event("room", 1, added(1, "2:laptop"))]
+    );
+    bob.join_group(&welcome.unwrap()).unwrap();
+    assert_eq!(bob.take_group_events(), [event("room", 1, GroupEventKind::Joined)]);
+
+    // Groups from before watching started are compared with what they were then
+    alice.add_member("lobby", &carol.generate_key_package().unwrap()).unwrap();
+    assert_eq!(
+        alice.take_group_events(),
+        [event("lobby", 1, added(1, "3:tablet")), event("lobby", 1, GroupEventKind::EpochAdvanced)]
+    );
+
+    let commit = alice.remove_member("room", "2:laptop").unwrap();
+    let expected = [event("room", 2, removed(1, "2:laptop")), event("room", 2, GroupEventKind::EpochAdvanced)];
+    assert_eq!(alice.take_group_events(), expected);
+    bob.process_message("room", &commit).unwrap();
+    assert_eq!(bob.take_group_events(), expected);
+
+    alice.watch_group_events(false).unwrap();
+    alice.update_self("lobby").unwrap();
+    assert!(alice.take_group_events().is_empty());
+}
@@ -0,0 +1,65 @@
+//! The UniFFI layer, called from Rust as a mobile app would call it.
+
+use std::thread;
+
+use vox_core::mobile::{MediaSessionEvent, MobileMediaSession};
+use vox_mls::mobile::MobileMlsEngine;
+use vox_mls::MlsError;
+
+#[test]
+fn engine_works_from_any_thread_and_keys_media_sessions() {
+    let alice = MobileMlsEngine::new(None, None, None).unwrap();
+    let bob = MobileMlsEngine::new(None, None, None).unwrap();
+    alice.generate_identity(1, "phone".into()).unwrap();
+    bob.generate_identity(2, "tablet".into()).unwrap();
+
+    alice.create_group("room".into(), vec![]).unwrap();
+    let package = thread::spawn({
+        let bob = bob.clone();
+        move || bob.generate_key_package().unwrap()
+    })
+    .join()
+    .unwrap();
+    let added = alice.add_member("room".into(), package).unwrap();
+    assert_eq!(bob.join_group(added.welcome.unwrap()).unwrap(), "room");
+
+    let ciphertext = alice.encrypt("room".into(), b"hello".to_vec()).unwrap();
+    let processed = bob.process_message("room".into(), ciphertext).unwrap();
+    assert_eq!((processed.kind.as_str(), processed.data), ("application", Some(b"hello".to_vec())));
+
+    let tx = MobileMediaSession::new(7, 1);
+    let rx = MobileMediaSession::new(7, 2);
+    alice.attach_media_session("room".into(), tx.clone()).unwrap();
+    bob.attach_media_session("room".into(), rx.clone()).unwrap();
+    assert!(tx.active_key_id().is_some());
+
+    rx.handle_datagram(tx.audio_datagram(b"opus".to_vec(), false).unwrap(), true);
+    let audio = std::iter::from_fn(|| rx.poll_event())
+        .find_map(|event| match event {
+            MediaSessionEvent::Audio { payload, .. } => Some(payload),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(audio, b"opus");
+}
+
+#[test]
+fn errors_and_identity_exports_match_the_engine() {
+    assert!(matches!(
+        MobileMlsEngine::new(None, Some(vec![0; 16]), None),
+        Err(MlsError::InvalidInput(_))
+    ));
+
+    let engine = MobileMlsEngine::new(None, None, None).unwrap();
+    engine.generate_identity(1, "phone".into()).unwrap();
+    assert_eq!(
+        engine.encrypt("nowhere".into(), b"x".to_vec()).unwrap_err(),
+        MlsError::UnknownGroup("nowhere".into())
+    );
+
+    let restored = MobileMlsEngine::new(None, None, None).unwrap();
+    restored.import_identity(engine.export_identity().unwrap(), 1, "phone".into()).unwrap();
+    assert_eq!(restored.identity_key().unwrap(), engine.identity_key().unwrap());
+    let stored = restored.stored_identity().unwrap().unwrap();
+    assert_eq!((stored.user_id, stored.device_id.as_str()), (1, "phone"));
+}
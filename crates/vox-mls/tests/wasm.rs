@@ -0,0 +1,59 @@
+//! The wasm-bindgen module's IndexedDB persistence. Run in a browser with
+//! `make mls-wasm-test`.
+
+#![cfg(target_arch = "wasm32")]
+
+use vox_mls::wasm::WasmMlsEngine;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// A database name no earlier run has used.
+fn fresh_name(test: &str) -> String {
+    format!("vox_mls_test_{test}_{}", js_sys::Date::now())
+}
+
+async fn open(name: &str) -> WasmMlsEngine {
+    WasmMlsEngine::open(name.to_string(), None).await.ok().expect("open engine")
+}
+
+async fn flush(engine: &WasmMlsEngine) {
+    let promise = engine.flush().ok().expect("export state");
+    JsFuture::from(promise).await.expect("flush");
+}
+
+#[wasm_bindgen_test]
+async fn flushed_state_survives_reopening() {
+    let name = fresh_name("reopen");
+    let mut engine = open(&name).await;
+    let identity_key = engine.generate_identity(1, "browser").ok().expect("generate identity");
+    engine.create_group("room", Vec::new()).ok().expect("create group");
+    flush(&engine).await;
+    drop(engine);
+
+    let reopened = open(&name).await;
+    assert_eq!(reopened.identity_key(), Some(identity_key));
+    assert!(reopened.group_exists("room"));
+}
+
+#[wasm_bindgen_test]
+async fn unflushed_changes_are_not_saved() {
+    let name = fresh_name("unflushed");
+    let mut engine = open(&name).await;
+    engine.generate_identity(1, "browser").ok().expect("generate identity");
+    flush(&engine).await;
+    engine.create_group("room", Vec::new()).ok().expect("create group");
+    drop(engine);
+
+    let reopened = open(&name).await;
+    assert!(reopened.identity_key().is_some());
+    assert!(!reopened.group_exists("room"));
+}
+
+#[wasm_bindgen_test]
+async fn flush_without_changes_resolves() {
+    let engine = open(&fresh_name("clean")).await;
+    flush(&engine).await;
+    flush(&engine).await;
+}
@@ -0,0 +1,286 @@
+# Type stubs for the vox_mls extension module.
+#
+# Packaged by maturin alongside the compiled module. Keep in sync with
+# src/lib.rs; tests/test_stubs.py checks them against the built module.
+
+from collections.abc import Callable
+from typing import Literal, TypedDict, final
+
+__version__: str
+
+class _ProfileStats(TypedDict):
+    count: int
+    total_ms: float
+    mean_us: float
+    p50_us: float
+    p90_us: float
+    p99_us: float
+    max_us: float
+
+class _GroupStats(TypedDict):
+    epoch: int
+    members: int
+    pending_proposals: int
+
+class _KeyPackageStats(TypedDict):
+    total: int
+    unused: int
+    last_resort: int
+
+class _EngineStats(TypedDict):
+    database_bytes: int
+    groups: dict[str, _GroupStats]
+    key_packages: _KeyPackageStats
+
+class WrongEpochError(RuntimeError):
+    message_epoch: int
+    current_epoch: int
+
+@final
+class StagedCommitInfo:
+    @property
+    def adds(self) -> list[bytes]: ...
+    @property
+    def removes(self) -> list[tuple[int, bytes]]: ...
+    @property
+    def updates(self) -> list[tuple[int, bytes]]: ...
+
+@final
+class ProcessedMessage:
+    @property
+    def kind(
+        self,
+    ) -> Literal[
+        "application", "commit", "proposal", "external_join_proposal", "buffered", "staged_commit", "own_commit"
+    ]: ...
+    @property
+    def data(self) -> bytes | None: ...
+    @property
+    def staged_commit(self) -> StagedCommitInfo | None: ...
+    @property
+    def epoch(self) -> int: ...
+    @property
+    def sender_identity(self) -> bytes | None: ...
+    @property
+    def sender_leaf_index(self) -> int | None: ...
+
+@final
+class MessageInfo:
+    @property
+    def group_id(self) -> str: ...
+    @property
+    def epoch(self) -> int: ...
+    @property
+    def content_type(self) -> Literal["application", "proposal", "commit"]: ...
+    @property
+    def sender_type(
+        self,
+    ) -> Literal["member", "external", "new_member_proposal", "new_member_commit"] | None: ...
+    @property
+    def sender_index(self) -> int | None: ...
+
+@final
+class PendingProposal:
+    @property
+    def proposal_type(
+        self,
+    ) -> Literal[
+        "add", "update", "remove", "psk", "reinit", "external_init", "group_context_extensions", "other"
+    ]: ...
+    @property
+    def sender_type(self) -> Literal["member", "external", "new_member_proposal"]: ...
+    @property
+    def sender_index(self) -> int | None: ...
+    @property
+    def sender_identity(self) -> bytes | None: ...
+
+@final
+class KeyPackageInfo:
+    @property
+    def identity(self) -> bytes: ...
+    @property
+    def ciphersuite(self) -> str: ...
+    @property
+    def signature_key(self) -> bytes: ...
+    @property
+    def not_before(self) -> int: ...
+    @property
+    def not_after(self) -> int: ...
+    @property
+    def last_resort(self) -> bool: ...
+
+@final
+class StoredKeyPackage:
+    @property
+    def hash_ref(self) -> bytes: ...
+    @property
+    def created_at(self) -> int: ...
+    @property
+    def last_resort(self) -> bool: ...
+    @property
+    def consumed(self) -> bool: ...
+
+@final
+class WelcomeInfo:
+    @property
+    def group_id(self) -> str: ...
+    @property
+    def ciphersuite(self) -> str: ...
+    @property
+    def sender_identity(self) -> bytes: ...
+
+@final
+class GroupEvent:
+    @property
+    def kind(
+        self,
+    ) -> Literal["created", "joined", "member_added", "member_removed", "epoch_advanced"]: ...
+    @property
+    def group_id(self) -> str: ...
+    @property
+    def epoch(self) -> int: ...
+    @property
+    def leaf_index(self) -> int | None: ...
+    @property
+    def identity(self) -> bytes | None: ...
+
+@final
+class MlsEngine:
+    def __new__(
+        cls,
+        db_path: str | None = None,
+        encryption_key: bytes | None = None,
+        ciphersuite: str | None = None,
+        db_key: bytes | None = None,
+        wal: bool = False,
+        synchronous: str | None = None,
+        busy_timeout: float | None = None,
+    ) -> MlsEngine: ...
+    def close(self) -> None: ...
+    @property
+    def is_closed(self) -> bool: ...
+    def __enter__(self) -> MlsEngine: ...
+    def __exit__(self, _exc_type: object, _exc_value: object, _traceback: object) -> bool: ...
+    @property
+    def ciphersuite(self) -> str: ...
+    @property
+    def identity_handle(self) -> str | None: ...
+    def use_identity(self, handle: str | None = None) -> None: ...
+    def list_identities(self) -> list[str]: ...
+    def generate_identity(self, user_id: int, device_id: str) -> bytes: ...
+    def generate_key_package(
+        self,
+        lifetime_secs: int | None = None,
+        extensions: list[tuple[int, bytes]] | None = None,
+        capability_extensions: list[int] | None = None,
+        last_resort: bool = False,
+    ) -> bytes: ...
+    def generate_key_packages(
+        self,
+        count: int,
+        lifetime_secs: int | None = None,
+        extensions: list[tuple[int, bytes]] | None = None,
+        capability_extensions: list[int] | None = None,
+        last_resort: bool = False,
+    ) -> list[bytes]: ...
+    def list_key_packages(self) -> list[StoredKeyPackage]: ...
+    def delete_key_package(self, hash_ref: bytes) -> None: ...
+    @staticmethod
+    def inspect_key_package(key_package: bytes) -> KeyPackageInfo: ...
+    def create_group(
+        self,
+        group_id: str,
+        member_key_packages: list[bytes],
+        ciphersuite: str | None = None,
+        extensions: list[tuple[int, bytes]] | None = None,
+        with_ratchet_tree: bool = True,
+        padding_size: int = 0,
+        required_extensions: list[int] | None = None,
+        required_credentials: list[int] | None = None,
+        with_group_info: bool = False,
+    ) -> tuple[bytes | None, bytes | None] | tuple[bytes | None, bytes | None, bytes]: ...
+    def join_group(self, welcome: bytes, ratchet_tree: bytes | None = None, padding_size: int = 0) -> str: ...
+    def inspect_welcome(self, welcome: bytes, ratchet_tree: bytes | None = None) -> WelcomeInfo: ...
+    def export_ratchet_tree(self, group_id: str) -> bytes: ...
+    def reinit_group(self, group_id: str, ciphersuite: str, member_key_packages: list[bytes]) -> bytes | None: ...
+    def export_group_info(self, group_id: str, with_ratchet_tree: bool = True) -> bytes: ...
+    def join_by_external_commit(self, group_info: bytes, padding_size: int = 0) -> tuple[str, bytes]: ...
+    def add_member(
+        self, group_id: str, key_package: bytes, with_group_info: bool = False
+    ) -> tuple[bytes, bytes] | tuple[bytes, bytes, bytes]: ...
+    def remove_member(self, group_id: str, member_identity: str) -> bytes: ...
+    def update_self(self, group_id: str) -> bytes: ...
+    def rotate_credential(self, group_id: str, new_device_id: str) -> bytes: ...
+    def update_group_context_extensions(self, group_id: str, extensions: list[tuple[int, bytes]]) -> bytes: ...
+    def group_context_extensions(self, group_id: str) -> list[tuple[int, bytes]]: ...
+    def leave_group(self, group_id: str) -> bytes: ...
+    def is_leaving(self, group_id: str) -> bool: ...
+    def delete_group(self, group_id: str) -> None: ...
+    def compact(self) -> tuple[int, int]: ...
+    def stats(self) -> _EngineStats: ...
+    def list_pending_proposals(self, group_id: str) -> list[PendingProposal]: ...
+    def clear_pending_proposals(self, group_id: str) -> None: ...
+    def commit_pending_proposals(self, group_id: str) -> tuple[bytes, bytes | None] | None: ...
+    def peek_message(self, message: bytes) -> MessageInfo: ...
+    def process_message(self, group_id: str, message: bytes, stage_commits: bool = False) -> ProcessedMessage: ...
+    def merge_staged_commit(self, group_id: str) -> None: ...
+    def reject_staged_commit(self, group_id: str) -> None: ...
+    def set_group_event_callback(self, callback: Callable[[GroupEvent], object] | None = None) -> None: ...
+    def take_replayed(self, group_id: str) -> list[ProcessedMessage]: ...
+    def encrypt(self, group_id: str, plaintext: bytes) -> bytes: ...
+    def decrypt(self, group_id: str, ciphertext: bytes) -> bytes: ...
+    def attach_media_key_sink(self, group_id: str, sink: object) -> None: ...
+    def export_file_key(self, group_id: str, file_id: bytes, epoch: int | None = None) -> tuple[int, bytes]: ...
+    def list_members(self, group_id: str) -> list[tuple[int, bytes, bytes]]: ...
+    def group_epoch(self, group_id: str) -> int: ...
+    def epoch_authenticator(self, group_id: str) -> bytes: ...
+    def member_fingerprint(self, group_id: str, member_identity: str) -> str: ...
+    def own_fingerprint(self) -> str: ...
+    def group_exists(self, group_id: str) -> bool: ...
+    def list_groups(self) -> list[str]: ...
+    def identity_key(self) -> bytes | None: ...
+    def get_stored_identity(self) -> tuple[int, str] | None: ...
+    def export_state(self) -> bytes: ...
+    def import_state(self, data: bytes) -> None: ...
+    def export_group(self, group_id: str) -> bytes: ...
+    def import_group(self, data: bytes) -> str: ...
+    def export_state_encrypted(self, passphrase: str) -> bytes: ...
+    def import_state_encrypted(self, data: bytes, passphrase: str) -> None: ...
+    def export_identity(self) -> bytes: ...
+    def import_identity(self, data: bytes, user_id: int, device_id: str) -> None: ...
+
+@final
+class PushNotification:
+    @property
+    def group_id(self) -> str: ...
+    @property
+    def sender(self) -> str: ...
+    @property
+    def sender_user_id(self) -> int | None: ...
+    @property
+    def sender_device_id(self) -> str | None: ...
+    @property
+    def epoch(self) -> int: ...
+    @property
+    def preview(self) -> str | None: ...
+    @property
+    def plaintext(self) -> bytes: ...
+
+def encode_push_payload(group_id: str, message: bytes) -> str: ...
+def decrypt_push(
+    payload: str,
+    db_path: str,
+    encryption_key: bytes | None = None,
+    preview_len: int = 120,
+    consume: bool = False,
+    db_key: bytes | None = None,
+) -> PushNotification: ...
+
+def configure_tracing(
+    endpoint: str | None,
+    service_name: str = "vox-mls",
+    sample_ratio: float = 1.0,
+    headers: dict[str, str] | None = None,
+) -> None: ...
+def enable_profiling(enabled: bool = True) -> None: ...
+def profile_report(reset: bool = False) -> dict[str, _ProfileStats]: ...
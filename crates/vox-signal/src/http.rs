@@ -0,0 +1,91 @@
+//! REST calls that go with signaling: voice room join/leave and MLS key
+//! package upload/fetch.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+
+#[derive(Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base: String,
+    token: String,
+}
+
+impl ApiClient {
+    /// `api_url` overrides the base URL; otherwise it is the gateway URL's
+    /// origin with `ws`/`wss` replaced by `http`/`https`. A `quic://`
+    /// gateway gives no usable origin, so then `None` is returned.
+    pub fn new(gateway_url: &str, api_url: Option<&str>, token: &str) -> Option<Self> {
+        let base = match api_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                let (scheme, rest) = gateway_url.split_once("://")?;
+                let scheme = match scheme {
+                    "ws" => "http",
+                    "wss" => "https",
+                    _ => return None,
+                };
+                let host = rest.split(['/', '?', '#']).next()?;
+                format!("{scheme}://{host}")
+            }
+        };
+        Some(ApiClient { http: reqwest::Client::new(), base, token: token.to_string() })
+    }
+
+    async fn post(&self, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let mut request = self.http.post(format!("{}{path}", self.base)).bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        Self::read(request.send().await).await
+    }
+
+    async fn get(&self, path: &str) -> Result<Value, String> {
+        Self::read(self.http.get(format!("{}{path}", self.base)).bearer_auth(&self.token).send().await).await
+    }
+
+    async fn read(response: reqwest::Result<reqwest::Response>) -> Result<Value, String> {
+        let response = response.map_err(|e| format!("request failed: {e}"))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| format!("failed to read response: {e}"))?;
+        if !status.is_success() {
+            return Err(format!("HTTP {}: {body}", status.as_u16()));
+        }
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&body).map_err(|e| format!("invalid JSON response: {e}"))
+    }
+
+    /// Returns the server's join response (media URL and token).
+    pub async fn join_room(&self, room_id: u64, self_mute: bool, self_deaf: bool) -> Result<Value, String> {
+        self.post(
+            &format!("/api/v1/rooms/{room_id}/voice/join"),
+            Some(json!({ "self_mute": self_mute, "self_deaf": self_deaf })),
+        )
+        .await
+    }
+
+    pub async fn leave_room(&self, room_id: u64) -> Result<(), String> {
+        self.post(&format!("/api/v1/rooms/{room_id}/voice/leave"), None).await.map(drop)
+    }
+
+    pub async fn upload_key_packages(&self, device_id: &str, key_packages: &[Vec<u8>]) -> Result<(), String> {
+        let encoded: Vec<String> = key_packages.iter().map(|kp| STANDARD.encode(kp)).collect();
+        self.post(&format!("/api/v1/keys/mls/{device_id}/key-packages"), Some(json!({ "key_packages": encoded })))
+            .await
+            .map(drop)
+    }
+
+    /// Base64 key packages for all of a user's devices, as the server sends them.
+    pub async fn fetch_key_packages(&self, user_id: u64) -> Result<Vec<String>, String> {
+        let body = self.get(&format!("/api/v1/keys/mls/{user_id}/key-packages")).await?;
+        body.get("key_packages")
+            .and_then(Value::as_array)
+            .ok_or("response has no 'key_packages' list")?
+            .iter()
+            .map(|kp| kp.as_str().map(str::to_string).ok_or_else(|| "key package is not a string".to_string()))
+            .collect()
+    }
+}
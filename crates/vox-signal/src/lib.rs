@@ -0,0 +1,385 @@
+mod http;
+mod state;
+mod transport;
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use vox_core::signal::{ClientMessage, MlsMessageType, GATEWAY_PROTOCOL_VERSION};
+
+/// Commands from Python to the signaling runtime.
+enum SignalCommand {
+    Connect {
+        url: String,
+        token: String,
+        api: Option<http::ApiClient>,
+        protocol_version: u32,
+        max_reconnect_attempts: Option<u32>,
+        /// Echoed in the `command_result` event.
+        command_id: u64,
+    },
+    Disconnect,
+    Send { message: ClientMessage, command_id: u64 },
+    Api { call: ApiCall, command_id: u64 },
+}
+
+/// REST requests, run alongside the gateway connection.
+enum ApiCall {
+    JoinRoom { room_id: u64, self_mute: bool, self_deaf: bool },
+    LeaveRoom { room_id: u64 },
+    UploadKeyPackages { device_id: String, key_packages: Vec<Vec<u8>> },
+    FetchKeyPackages { user_id: u64 },
+}
+
+/// Events emitted by the signaling runtime for Python consumption.
+enum SignalEvent {
+    Connected { session_id: String, resumed: bool, transport: &'static str },
+    Disconnected(String),
+    ConnectFailed(String),
+    Reconnecting { attempt: u32, delay_secs: f64 },
+    FatalError(String),
+    /// A gateway message that could not be parsed.
+    ProtocolError(String),
+    /// Outcome of an acknowledged command; `error` is `None` on success.
+    CommandResult { id: u64, error: Option<String> },
+    /// A gateway dispatch event with its `d` payload as JSON.
+    Dispatch { kind: String, data: String },
+    RoomJoined { room_id: u64, response: String },
+    RoomLeft(u64),
+    KeyPackages { user_id: u64, key_packages: Vec<String> },
+}
+
+impl SignalEvent {
+    fn to_tuple(&self) -> (String, String) {
+        match self {
+            SignalEvent::Connected { session_id, resumed, transport } => {
+                ("connected".into(), format!("transport={transport},resumed={resumed},session_id={session_id}"))
+            }
+            SignalEvent::Disconnected(reason) => ("disconnected".into(), reason.clone()),
+            SignalEvent::ConnectFailed(reason) => ("connect_failed".into(), reason.clone()),
+            SignalEvent::Reconnecting { attempt, delay_secs } => {
+                ("reconnecting".into(), format!("attempt={attempt},delay={delay_secs:.1}"))
+            }
+            SignalEvent::FatalError(msg) => ("fatal_error".into(), msg.clone()),
+            SignalEvent::ProtocolError(msg) => ("protocol_error".into(), msg.clone()),
+            SignalEvent::CommandResult { id, error: None } => ("command_result".into(), format!("id={id},ok=true")),
+            // Error text last: it may itself contain commas
+            SignalEvent::CommandResult { id, error: Some(e) } => {
+                ("command_result".into(), format!("id={id},ok=false,error={e}"))
+            }
+            SignalEvent::Dispatch { kind, data } => (kind.clone(), data.clone()),
+            // JSON last: it contains commas
+            SignalEvent::RoomJoined { room_id, response } => {
+                ("room_joined".into(), format!("room_id={room_id},response={response}"))
+            }
+            SignalEvent::RoomLeft(room_id) => ("room_left".into(), room_id.to_string()),
+            SignalEvent::KeyPackages { user_id, key_packages } => (
+                "key_packages".into(),
+                format!("user_id={user_id},key_packages={}", serde_json::Value::from(key_packages.clone())),
+            ),
+        }
+    }
+}
+
+/// Thread-safe event queue for pushing events from the signaling runtime to Python.
+pub(crate) type EventQueue = Arc<Mutex<VecDeque<(String, String)>>>;
+
+/// Push an event onto the queue.
+pub(crate) fn push_event(queue: &EventQueue, event: SignalEvent) {
+    if let Ok(mut q) = queue.lock() {
+        q.push_back(event.to_tuple());
+    }
+}
+
+/// An MLS handshake message fanned out to this client by the gateway.
+pub(crate) struct MlsMessage {
+    pub mls_type: MlsMessageType,
+    pub group_id: Option<String>,
+    pub data: Vec<u8>,
+}
+
+pub(crate) type MlsQueue = Arc<Mutex<VecDeque<MlsMessage>>>;
+
+/// Commands queued for the runtime before `send_cmd` fails.
+const COMMAND_QUEUE_CAPACITY: usize = 256;
+
+/// Name of the signaling runtime thread.
+const RUNTIME_THREAD_NAME: &str = "vox-signal-runtime";
+
+/// Client for the Vox signaling gateway.
+///
+/// Runs a background tokio runtime that holds the gateway connection
+/// (WebSocket, or a QUIC stream), heartbeats, resumes or re-identifies
+/// after drops, and makes the REST calls that go with signaling.
+#[pyclass]
+struct VoxSignalClient {
+    cmd_tx: Option<mpsc::Sender<SignalCommand>>,
+    cancel: Option<CancellationToken>,
+    rt_handle: Option<std::thread::JoinHandle<()>>,
+    events: EventQueue,
+    mls_messages: MlsQueue,
+    /// Source of correlation ids for acknowledged commands.
+    command_ids: std::sync::atomic::AtomicU64,
+}
+
+#[pymethods]
+impl VoxSignalClient {
+    #[new]
+    fn new() -> Self {
+        VoxSignalClient {
+            cmd_tx: None,
+            cancel: None,
+            rt_handle: None,
+            events: Arc::new(Mutex::new(VecDeque::new())),
+            mls_messages: Arc::new(Mutex::new(VecDeque::new())),
+            command_ids: std::sync::atomic::AtomicU64::new(1),
+        }
+    }
+
+    /// Start the background signaling runtime.
+    ///
+    /// May be called again after the runtime died (see `fatal_error`).
+    fn start(&mut self) -> PyResult<()> {
+        if self.cancel.is_some() {
+            if !self.rt_handle.as_ref().is_some_and(|h| h.is_finished()) {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Signal client is already running",
+                ));
+            }
+            self.cancel = None;
+            self.cmd_tx = None;
+            if let Some(handle) = self.rt_handle.take() {
+                let _ = handle.join();
+            }
+        }
+
+        let cancel = CancellationToken::new();
+        let cancel_loop = cancel.clone();
+        let (cmd_tx, cmd_rx) = mpsc::channel(COMMAND_QUEUE_CAPACITY);
+        let events = self.events.clone();
+        let mls_messages = self.mls_messages.clone();
+        let spawned = std::thread::Builder::new().name(RUNTIME_THREAD_NAME.into()).spawn(move || {
+            let rt = match tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    push_event(&events, SignalEvent::FatalError(format!("Failed to create runtime: {e}")));
+                    return;
+                }
+            };
+            let events_loop = events.clone();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                rt.block_on(state::run_signal_loop(cmd_rx, cancel_loop.clone(), events_loop, mls_messages));
+            }));
+            match result {
+                Err(payload) => {
+                    let msg = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".into());
+                    tracing::error!("Signal runtime panicked: {msg}");
+                    push_event(&events, SignalEvent::FatalError(msg));
+                }
+                Ok(()) if !cancel_loop.is_cancelled() => {
+                    push_event(&events, SignalEvent::FatalError("signal loop exited unexpectedly".into()));
+                }
+                Ok(()) => {}
+            }
+        });
+        let handle = spawned.map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Failed to spawn signal runtime thread: {e}"))
+        })?;
+
+        self.cancel = Some(cancel);
+        self.cmd_tx = Some(cmd_tx);
+        self.rt_handle = Some(handle);
+        Ok(())
+    }
+
+    /// Connect to the gateway and identify with `token`.
+    ///
+    /// `url` selects the transport: `ws://` or `wss://` for WebSocket,
+    /// `quic://host:port` for a QUIC stream. Once the gateway answers with
+    /// `ready`, a `connected` event is emitted with
+    /// `"transport=<kind>,resumed=false,session_id=<id>"`.
+    ///
+    /// Dropped connections are resumed when the close code allows it
+    /// (`resumed=true` on the `connected` event) and otherwise re-identified,
+    /// with exponential backoff up to 60 s between attempts, for at most
+    /// `max_reconnect_attempts` attempts (unlimited if None). Close codes
+    /// that rule out reconnecting end the session with `disconnected`.
+    ///
+    /// REST calls go to `api_url`, by default the gateway URL's origin
+    /// with `ws`/`wss` replaced by `http`/`https`. With a `quic://` gateway
+    /// `api_url` must be given for them to work.
+    ///
+    /// Returns a command id; its `command_result` follows `connected` or
+    /// `connect_failed`.
+    #[pyo3(signature = (url, token, api_url=None, protocol_version=GATEWAY_PROTOCOL_VERSION, max_reconnect_attempts=None))]
+    fn connect(
+        &self,
+        url: &str,
+        token: &str,
+        api_url: Option<&str>,
+        protocol_version: u32,
+        max_reconnect_attempts: Option<u32>,
+    ) -> PyResult<u64> {
+        let command_id = self.next_command_id();
+        self.send_cmd(SignalCommand::Connect {
+            url: url.to_string(),
+            token: token.to_string(),
+            api: http::ApiClient::new(url, api_url, token),
+            protocol_version,
+            max_reconnect_attempts,
+            command_id,
+        })?;
+        Ok(command_id)
+    }
+
+    /// Close the gateway connection; `disconnected` is emitted once it is.
+    fn disconnect(&self) -> PyResult<()> {
+        self.send_cmd(SignalCommand::Disconnect)
+    }
+
+    /// Join a voice room. On success `room_joined` carries
+    /// `"room_id=<id>,response=<JSON>"` with the server's response, which
+    /// holds the media URL and token for `VoxMediaClient.connect`.
+    /// Returns a command id.
+    #[pyo3(signature = (room_id, self_mute=false, self_deaf=false))]
+    fn join_room(&self, room_id: u64, self_mute: bool, self_deaf: bool) -> PyResult<u64> {
+        self.send_api(ApiCall::JoinRoom { room_id, self_mute, self_deaf })
+    }
+
+    /// Leave a voice room; `room_left` follows on success. Returns a command id.
+    fn leave_room(&self, room_id: u64) -> PyResult<u64> {
+        self.send_api(ApiCall::LeaveRoom { room_id })
+    }
+
+    /// Upload serialized MLS key packages for this device. Returns a command id.
+    fn upload_key_packages(&self, device_id: &str, key_packages: Vec<Vec<u8>>) -> PyResult<u64> {
+        self.send_api(ApiCall::UploadKeyPackages { device_id: device_id.to_string(), key_packages })
+    }
+
+    /// Fetch the key packages of all of a user's devices. On success
+    /// `key_packages` carries `"user_id=<id>,key_packages=<JSON list>"` with
+    /// base64 key packages. Returns a command id.
+    fn fetch_key_packages(&self, user_id: u64) -> PyResult<u64> {
+        self.send_api(ApiCall::FetchKeyPackages { user_id })
+    }
+
+    /// Relay an MLS handshake message ("welcome", "commit" or "proposal") to
+    /// the other members through the gateway. Returns a command id.
+    fn send_mls(&self, mls_type: &str, data: Vec<u8>) -> PyResult<u64> {
+        let mls_type = MlsMessageType::parse(mls_type).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        let command_id = self.next_command_id();
+        self.send_cmd(SignalCommand::Send { message: ClientMessage::MlsRelay { mls_type, data }, command_id })?;
+        Ok(command_id)
+    }
+
+    /// Send any other gateway message. `data` is the JSON object sent as its
+    /// `d` payload. Returns a command id.
+    #[pyo3(signature = (message_type, data=None))]
+    fn send(&self, message_type: &str, data: Option<&str>) -> PyResult<u64> {
+        let data = match data {
+            Some(json) => serde_json::from_str(json)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("invalid JSON: {e}")))?,
+            None => serde_json::Value::Object(Default::default()),
+        };
+        if !data.is_object() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("data must be a JSON object"));
+        }
+        let command_id = self.next_command_id();
+        let message = ClientMessage::Raw { kind: message_type.to_string(), data };
+        self.send_cmd(SignalCommand::Send { message, command_id })?;
+        Ok(command_id)
+    }
+
+    /// Poll for the next event from the signaling runtime.
+    /// Returns a (event_type, detail) tuple, or None if no events are pending.
+    ///
+    /// Besides the runtime's own events, every gateway dispatch event
+    /// (`ready`, `voice_state_update`, ...) is passed on under its own type
+    /// with its payload as JSON.
+    fn poll_event(&self) -> Option<(String, String)> {
+        self.events.lock().ok()?.pop_front()
+    }
+
+    /// Poll for the next MLS handshake message relayed by the gateway.
+    /// Returns (mls_type, group_id, data) or None; `group_id` is None for
+    /// welcomes.
+    fn poll_mls<'py>(&self, py: Python<'py>) -> Option<(&'static str, Option<String>, Bound<'py, PyBytes>)> {
+        let msg = self.mls_messages.lock().ok()?.pop_front()?;
+        Some((msg.mls_type.as_str(), msg.group_id, PyBytes::new(py, &msg.data)))
+    }
+
+    /// Stop the signaling runtime, closing the gateway connection.
+    ///
+    /// The client can be started again afterwards. Unread events are kept.
+    fn stop(&mut self, py: Python<'_>) -> PyResult<()> {
+        if let Some(cancel) = self.cancel.take() {
+            cancel.cancel();
+        }
+        self.cmd_tx = None;
+        if let Some(handle) = self.rt_handle.take() {
+            let _ = py.detach(move || handle.join());
+        }
+        Ok(())
+    }
+
+    /// Start the runtime (if not already running) on entering a `with` block.
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyResult<PyRefMut<'_, Self>> {
+        if slf.cancel.is_none() {
+            slf.start()?;
+        }
+        Ok(slf)
+    }
+
+    /// Stop the runtime on leaving a `with` block. Exceptions propagate.
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: Option<Py<PyAny>>,
+        _exc_value: Option<Py<PyAny>>,
+        _traceback: Option<Py<PyAny>>,
+    ) -> PyResult<bool> {
+        self.stop(py)?;
+        Ok(false)
+    }
+}
+
+impl VoxSignalClient {
+    fn next_command_id(&self) -> u64 {
+        self.command_ids.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn send_api(&self, call: ApiCall) -> PyResult<u64> {
+        let command_id = self.next_command_id();
+        self.send_cmd(SignalCommand::Api { call, command_id })?;
+        Ok(command_id)
+    }
+
+    fn send_cmd(&self, cmd: SignalCommand) -> PyResult<()> {
+        match &self.cmd_tx {
+            Some(tx) => tx.try_send(cmd).map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Signal command queue is full; the runtime is not keeping up",
+                ),
+                mpsc::error::TrySendError::Closed(_) => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                    "Signal runtime is dead (see the fatal_error event); call start() again",
+                ),
+            }),
+            None => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("Signal client not started")),
+        }
+    }
+}
+
+/// Python module definition.
+#[pymodule]
+fn vox_signal(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<VoxSignalClient>()?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
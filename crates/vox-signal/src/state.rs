@@ -0,0 +1,365 @@
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use vox_core::signal::{can_reconnect, can_resume, ClientMessage, MlsMessageType, ServerMessage};
+
+use crate::http::ApiClient;
+use crate::transport::{Closed, Transport};
+use crate::{push_event, ApiCall, EventQueue, MlsMessage, MlsQueue, SignalCommand, SignalEvent};
+
+/// First reconnect delay; doubles per attempt.
+const BASE_BACKOFF_SECS: f64 = 1.0;
+/// Maximum reconnect delay, before jitter.
+const MAX_BACKOFF_SECS: f64 = 60.0;
+/// How long the gateway has to send `hello` after the connection opens.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Close code sent when the client disconnects.
+const NORMAL_CLOSE: u16 = 1000;
+
+/// Where and as whom to connect, plus what a resume needs.
+struct Gateway {
+    url: String,
+    token: String,
+    protocol_version: u32,
+    max_reconnect_attempts: Option<u32>,
+    session_id: Option<String>,
+    seq: u64,
+}
+
+struct Session {
+    transport: Transport,
+    heartbeat_interval: Duration,
+    next_heartbeat: Instant,
+    last_ack: Instant,
+    /// Connect command to acknowledge once `ready` or `resumed` arrives.
+    pending_connect: Option<u64>,
+}
+
+/// What to do after the connection closed.
+enum Recovery {
+    Resume,
+    Reidentify,
+    Fatal,
+}
+
+fn recovery_for(closed: &Closed) -> Recovery {
+    match closed.code {
+        // Network errors: the session may well still be resumable
+        None => Recovery::Resume,
+        Some(code) if can_resume(code) => Recovery::Resume,
+        Some(code) if can_reconnect(code) => Recovery::Reidentify,
+        Some(_) => Recovery::Fatal,
+    }
+}
+
+/// Open a connection, wait for `hello` and identify or resume.
+async fn establish_session(gateway: &Gateway) -> Result<Session, Closed> {
+    let mut transport = Transport::connect(&gateway.url).await?;
+    let hello = tokio::time::timeout(HELLO_TIMEOUT, transport.recv())
+        .await
+        .map_err(|_| Closed::error("no hello from gateway"))??;
+    let hello = ServerMessage::parse(&hello).map_err(Closed::error)?;
+    if hello.kind != "hello" {
+        return Err(Closed::error(format!("expected hello, got {}", hello.kind)));
+    }
+    let interval_ms = hello
+        .heartbeat_interval()
+        .filter(|ms| *ms > 0)
+        .ok_or_else(|| Closed::error("hello has no heartbeat_interval"))?;
+    let heartbeat_interval = Duration::from_millis(interval_ms);
+
+    let message = match &gateway.session_id {
+        Some(session_id) => ClientMessage::Resume {
+            token: gateway.token.clone(),
+            session_id: session_id.clone(),
+            last_seq: gateway.seq,
+        },
+        None => ClientMessage::Identify { token: gateway.token.clone(), protocol_version: gateway.protocol_version },
+    };
+    transport.send(message.to_json()).await?;
+    tracing::info!("Gateway {} connection open, {} sent", transport.kind(), message.kind());
+
+    let now = Instant::now();
+    Ok(Session { transport, heartbeat_interval, next_heartbeat: now + heartbeat_interval, last_ack: now, pending_connect: None })
+}
+
+/// What woke the loop up.
+enum Step {
+    Cancelled,
+    Command(Option<SignalCommand>),
+    Message(Result<String, Closed>),
+    Heartbeat,
+}
+
+struct SignalLoop {
+    cancel: CancellationToken,
+    events: EventQueue,
+    mls_messages: MlsQueue,
+    gateway: Option<Gateway>,
+    api: Option<ApiClient>,
+    session: Option<Session>,
+}
+
+/// Main signaling loop. Receives commands from the Python layer and owns
+/// the gateway connection.
+pub async fn run_signal_loop(
+    mut cmd_rx: mpsc::Receiver<SignalCommand>,
+    cancel: CancellationToken,
+    events: EventQueue,
+    mls_messages: MlsQueue,
+) {
+    let mut state = SignalLoop { cancel: cancel.clone(), events, mls_messages, gateway: None, api: None, session: None };
+
+    loop {
+        let step = match &mut state.session {
+            None => tokio::select! {
+                _ = cancel.cancelled() => Step::Cancelled,
+                cmd = cmd_rx.recv() => Step::Command(cmd),
+            },
+            Some(s) => tokio::select! {
+                _ = cancel.cancelled() => Step::Cancelled,
+                cmd = cmd_rx.recv() => Step::Command(cmd),
+                msg = s.transport.recv() => Step::Message(msg),
+                _ = tokio::time::sleep_until(s.next_heartbeat) => Step::Heartbeat,
+            },
+        };
+
+        match step {
+            Step::Cancelled | Step::Command(None) => {
+                tracing::info!("Signal loop cancelled");
+                if let Some(s) = state.session.take() {
+                    s.transport.close(NORMAL_CLOSE, "client stopped").await;
+                }
+                break;
+            }
+            Step::Command(Some(cmd)) => state.handle_command(cmd).await,
+            Step::Message(Ok(text)) => state.handle_message(&text),
+            Step::Message(Err(closed)) => state.recover(closed).await,
+            Step::Heartbeat => state.heartbeat().await,
+        }
+    }
+}
+
+impl SignalLoop {
+    async fn handle_command(&mut self, cmd: SignalCommand) {
+        match cmd {
+            SignalCommand::Connect { url, token, api, protocol_version, max_reconnect_attempts, command_id } => {
+                if let Some(s) = self.session.take() {
+                    s.transport.close(NORMAL_CLOSE, "reconnecting").await;
+                }
+                tracing::info!("Connecting to gateway at {}", url);
+                let gateway =
+                    Gateway { url, token, protocol_version, max_reconnect_attempts, session_id: None, seq: 0 };
+                self.api = api;
+                match establish_session(&gateway).await {
+                    Ok(mut s) => {
+                        s.pending_connect = Some(command_id);
+                        self.session = Some(s);
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to connect to gateway: {}", e);
+                        push_event(&self.events, SignalEvent::ConnectFailed(e.to_string()));
+                        push_event(&self.events, SignalEvent::CommandResult { id: command_id, error: Some(e.to_string()) });
+                    }
+                }
+                self.gateway = Some(gateway);
+            }
+            SignalCommand::Disconnect => {
+                if let Some(s) = self.session.take() {
+                    if let Some(id) = s.pending_connect {
+                        push_event(&self.events, SignalEvent::CommandResult { id, error: Some("disconnected".into()) });
+                    }
+                    s.transport.close(NORMAL_CLOSE, "client disconnect").await;
+                    push_event(&self.events, SignalEvent::Disconnected("client disconnect".into()));
+                }
+                if let Some(gateway) = &mut self.gateway {
+                    gateway.session_id = None;
+                    gateway.seq = 0;
+                }
+            }
+            SignalCommand::Send { message, command_id } => {
+                let error = match &mut self.session {
+                    Some(s) if s.pending_connect.is_none() => {
+                        s.transport.send(message.to_json()).await.err().map(|e| e.reason)
+                    }
+                    _ => Some("not connected".into()),
+                };
+                push_event(&self.events, SignalEvent::CommandResult { id: command_id, error });
+            }
+            SignalCommand::Api { call, command_id } => {
+                let Some(api) = self.api.clone() else {
+                    let error = match self.gateway {
+                        Some(_) => "no API URL for this gateway; pass api_url to connect()",
+                        None => "not connected",
+                    };
+                    push_event(&self.events, SignalEvent::CommandResult { id: command_id, error: Some(error.into()) });
+                    return;
+                };
+                let events = self.events.clone();
+                tokio::spawn(async move {
+                    let error = match run_api_call(&api, call).await {
+                        Ok(event) => {
+                            if let Some(event) = event {
+                                push_event(&events, event);
+                            }
+                            None
+                        }
+                        Err(e) => Some(e),
+                    };
+                    push_event(&events, SignalEvent::CommandResult { id: command_id, error });
+                });
+            }
+        }
+    }
+
+    fn handle_message(&mut self, text: &str) {
+        let (Some(s), Some(gateway)) = (&mut self.session, &mut self.gateway) else {
+            return;
+        };
+        let msg = match ServerMessage::parse(text) {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::warn!("Dropping gateway message: {}", e);
+                push_event(&self.events, SignalEvent::ProtocolError(e));
+                return;
+            }
+        };
+        if let Some(seq) = msg.seq {
+            gateway.seq = seq;
+        }
+
+        match msg.kind.as_str() {
+            "heartbeat_ack" => {
+                s.last_ack = Instant::now();
+                return;
+            }
+            "ready" | "resumed" => {
+                let resumed = msg.kind == "resumed";
+                if !resumed {
+                    gateway.session_id = msg.session_id().map(str::to_string);
+                }
+                let session_id = gateway.session_id.clone().unwrap_or_default();
+                push_event(&self.events, SignalEvent::Connected { session_id, resumed, transport: s.transport.kind() });
+                if let Some(id) = s.pending_connect.take() {
+                    push_event(&self.events, SignalEvent::CommandResult { id, error: None });
+                }
+            }
+            "mls_welcome" | "mls_commit" | "mls_proposal" => {
+                let mls_type = match MlsMessageType::parse(&msg.kind["mls_".len()..]) {
+                    Ok(t) => t,
+                    Err(e) => return push_event(&self.events, SignalEvent::ProtocolError(e)),
+                };
+                match msg.mls_payload() {
+                    Ok(data) => {
+                        let group_id = msg.data.get("group_id").and_then(|g| g.as_str()).map(str::to_string);
+                        if let Ok(mut q) = self.mls_messages.lock() {
+                            q.push_back(MlsMessage { mls_type, group_id, data });
+                        }
+                    }
+                    Err(e) => push_event(&self.events, SignalEvent::ProtocolError(e)),
+                }
+                return;
+            }
+            _ => {}
+        }
+        push_event(&self.events, SignalEvent::Dispatch { kind: msg.kind, data: msg.data.to_string() });
+    }
+
+    async fn heartbeat(&mut self) {
+        let Some(s) = &mut self.session else { return };
+        if s.last_ack.elapsed() > 2 * s.heartbeat_interval {
+            tracing::warn!("Heartbeat ACK timeout, reconnecting");
+            let Some(s) = self.session.take() else { return };
+            let pending = s.pending_connect;
+            s.transport.close(NORMAL_CLOSE, "heartbeat timeout").await;
+            return self.reconnect(Closed::error("heartbeat ACK timeout"), pending).await;
+        }
+        s.next_heartbeat += s.heartbeat_interval;
+        if let Err(e) = s.transport.send(ClientMessage::Heartbeat.to_json()).await {
+            tracing::warn!("Heartbeat send failed: {}", e);
+        }
+    }
+
+    /// Handle the connection closing under us.
+    async fn recover(&mut self, closed: Closed) {
+        let pending = self.session.take().and_then(|s| s.pending_connect);
+        tracing::warn!("Gateway connection closed: {}", closed);
+        self.reconnect(closed, pending).await;
+    }
+
+    async fn reconnect(&mut self, mut closed: Closed, pending: Option<u64>) {
+        let Some(gateway) = &mut self.gateway else { return };
+        let mut attempt = 0;
+        loop {
+            match recovery_for(&closed) {
+                Recovery::Fatal => break,
+                Recovery::Reidentify => {
+                    gateway.session_id = None;
+                    gateway.seq = 0;
+                }
+                Recovery::Resume => {}
+            }
+            attempt += 1;
+            if gateway.max_reconnect_attempts.is_some_and(|max| attempt > max) {
+                closed.reason = format!("reconnection failed after {} attempts: {}", attempt - 1, closed.reason);
+                break;
+            }
+
+            let delay_secs = (BASE_BACKOFF_SECS * 2f64.powi(attempt as i32 - 1)).min(MAX_BACKOFF_SECS)
+                * (0.5 + rand::random::<f64>());
+            push_event(&self.events, SignalEvent::Reconnecting { attempt, delay_secs });
+            tokio::select! {
+                _ = self.cancel.cancelled() => return,
+                _ = tokio::time::sleep(Duration::from_secs_f64(delay_secs)) => {}
+            }
+
+            tracing::info!("Gateway reconnect attempt {}", attempt);
+            match establish_session(gateway).await {
+                Ok(mut s) => {
+                    s.pending_connect = pending;
+                    self.session = Some(s);
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!("Gateway reconnect attempt {} failed: {}", attempt, e);
+                    closed = e;
+                }
+            }
+        }
+
+        let reason = closed.to_string();
+        match pending {
+            // Never got a session: the connect itself failed
+            Some(id) => {
+                push_event(&self.events, SignalEvent::ConnectFailed(reason.clone()));
+                push_event(&self.events, SignalEvent::CommandResult { id, error: Some(reason) });
+            }
+            None => push_event(&self.events, SignalEvent::Disconnected(reason)),
+        }
+        gateway.session_id = None;
+        gateway.seq = 0;
+    }
+}
+
+/// Make a REST call; the returned event, if any, carries its result.
+async fn run_api_call(api: &ApiClient, call: ApiCall) -> Result<Option<SignalEvent>, String> {
+    match call {
+        ApiCall::JoinRoom { room_id, self_mute, self_deaf } => {
+            let response = api.join_room(room_id, self_mute, self_deaf).await?;
+            Ok(Some(SignalEvent::RoomJoined { room_id, response: response.to_string() }))
+        }
+        ApiCall::LeaveRoom { room_id } => {
+            api.leave_room(room_id).await?;
+            Ok(Some(SignalEvent::RoomLeft(room_id)))
+        }
+        ApiCall::UploadKeyPackages { device_id, key_packages } => {
+            api.upload_key_packages(&device_id, &key_packages).await?;
+            Ok(None)
+        }
+        ApiCall::FetchKeyPackages { user_id } => {
+            let key_packages = api.fetch_key_packages(user_id).await?;
+            Ok(Some(SignalEvent::KeyPackages { user_id, key_packages }))
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Gateway connections: WebSocket text frames, or length-framed messages on
+//! a bidirectional QUIC stream.
+
+use futures_util::{SinkExt, StreamExt};
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use vox_core::signal::{encode_frame, FrameDecoder, SIGNAL_ALPN};
+
+/// Why a gateway connection ended. `code` is the WebSocket close code or
+/// QUIC application error code; `None` for network errors and connections
+/// that never came up.
+#[derive(Debug)]
+pub struct Closed {
+    pub code: Option<u16>,
+    pub reason: String,
+}
+
+impl Closed {
+    pub fn error(reason: impl Into<String>) -> Self {
+        Closed { code: None, reason: reason.into() }
+    }
+}
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "code={code},reason={}", self.reason),
+            None => write!(f, "reason={}", self.reason),
+        }
+    }
+}
+
+pub enum Transport {
+    WebSocket(Box<WebSocketStream<MaybeTlsStream<TcpStream>>>),
+    Quic {
+        // Dropping the endpoint would tear down the connection
+        _endpoint: quinn::Endpoint,
+        connection: quinn::Connection,
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+        decoder: FrameDecoder,
+    },
+}
+
+impl Transport {
+    /// Open a connection. `ws://` and `wss://` URLs use WebSocket,
+    /// `quic://host:port` a QUIC stream.
+    pub async fn connect(url: &str) -> Result<Self, Closed> {
+        if let Some(authority) = url.strip_prefix("quic://") {
+            return connect_quic(authority.trim_end_matches('/')).await;
+        }
+        if !(url.starts_with("ws://") || url.starts_with("wss://")) {
+            return Err(Closed::error(format!("unsupported gateway URL '{url}' (expected ws://, wss:// or quic://)")));
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| Closed::error(format!("WebSocket connect failed: {e}")))?;
+        Ok(Transport::WebSocket(Box::new(ws)))
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Transport::WebSocket(_) => "websocket",
+            Transport::Quic { .. } => "quic",
+        }
+    }
+
+    pub async fn send(&mut self, text: String) -> Result<(), Closed> {
+        match self {
+            Transport::WebSocket(ws) => {
+                ws.send(Message::text(text)).await.map_err(|e| Closed::error(format!("send failed: {e}")))
+            }
+            Transport::Quic { send, .. } => {
+                send.write_all(&encode_frame(&text)).await.map_err(|e| Closed::error(format!("send failed: {e}")))
+            }
+        }
+    }
+
+    /// Next message from the gateway. Cancel-safe.
+    pub async fn recv(&mut self) -> Result<String, Closed> {
+        match self {
+            Transport::WebSocket(ws) => loop {
+                match ws.next().await {
+                    Some(Ok(Message::Text(text))) => return Ok(text.to_string()),
+                    Some(Ok(Message::Binary(bytes))) => {
+                        return String::from_utf8(bytes.to_vec())
+                            .map_err(|_| Closed::error("binary gateway message is not valid UTF-8"));
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        return Err(match frame {
+                            Some(f) => Closed { code: Some(f.code.into()), reason: f.reason.to_string() },
+                            None => Closed::error("closed without a code"),
+                        });
+                    }
+                    // Pings are answered by tungstenite itself
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(Closed::error(e.to_string())),
+                    None => return Err(Closed::error("connection lost")),
+                }
+            },
+            Transport::Quic { connection, recv, decoder, .. } => loop {
+                if let Some(text) = decoder.next_message().map_err(Closed::error)? {
+                    return Ok(text);
+                }
+                let mut buf = [0u8; 8192];
+                match recv.read(&mut buf).await {
+                    Ok(Some(n)) => decoder.push(&buf[..n]),
+                    Ok(None) => return Err(quic_close_reason(connection)),
+                    Err(quinn::ReadError::ConnectionLost(e)) => return Err(quic_closed(e)),
+                    Err(e) => return Err(Closed::error(e.to_string())),
+                }
+            },
+        }
+    }
+
+    /// Close the connection with `code`, best effort.
+    pub async fn close(self, code: u16, reason: &str) {
+        match self {
+            Transport::WebSocket(mut ws) => {
+                let frame = CloseFrame { code: CloseCode::from(code), reason: reason.to_owned().into() };
+                let _ = ws.send(Message::Close(Some(frame))).await;
+            }
+            Transport::Quic { _endpoint, connection, mut send, .. } => {
+                let _ = send.finish();
+                connection.close(quinn::VarInt::from(code), reason.as_bytes());
+                _endpoint.wait_idle().await;
+            }
+        }
+    }
+}
+
+async fn connect_quic(authority: &str) -> Result<Transport, Closed> {
+    let host = authority
+        .rsplit_once(':')
+        .map(|(host, _)| host.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| Closed::error(format!("quic:// URL needs a port: '{authority}'")))?;
+    let addr = authority
+        .to_socket_addrs()
+        .map_err(|e| Closed::error(format!("failed to resolve {authority}: {e}")))?
+        .next()
+        .ok_or_else(|| Closed::error(format!("no addresses for {authority}")))?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let mut crypto = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| Closed::error(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![SIGNAL_ALPN.to_vec()];
+    let quic_config =
+        quinn::crypto::rustls::QuicClientConfig::try_from(crypto).map_err(|e| Closed::error(e.to_string()))?;
+
+    let bind: std::net::SocketAddr = if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }.parse().unwrap();
+    let mut endpoint = quinn::Endpoint::client(bind).map_err(|e| Closed::error(format!("QUIC bind failed: {e}")))?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(quic_config)));
+
+    let connection = endpoint
+        .connect(addr, host)
+        .map_err(|e| Closed::error(format!("QUIC connect failed: {e}")))?
+        .await
+        .map_err(quic_closed)?;
+    let (send, recv) = connection.open_bi().await.map_err(quic_closed)?;
+    Ok(Transport::Quic { _endpoint: endpoint, connection, send, recv, decoder: FrameDecoder::new() })
+}
+
+fn quic_closed(e: quinn::ConnectionError) -> Closed {
+    match e {
+        quinn::ConnectionError::ApplicationClosed(close) => Closed {
+            code: u16::try_from(close.error_code.into_inner()).ok(),
+            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+        },
+        e => Closed::error(e.to_string()),
+    }
+}
+
+/// The peer finished its stream; report the connection's close if it has one.
+fn quic_close_reason(connection: &quinn::Connection) -> Closed {
+    match connection.close_reason() {
+        Some(e) => quic_closed(e),
+        None => Closed::error("gateway closed the stream"),
+    }
+}
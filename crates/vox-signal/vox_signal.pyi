@@ -0,0 +1,36 @@
+# Type stubs for the vox_signal extension module.
+#
+# Packaged by maturin alongside the compiled module. Keep in sync with
+# src/lib.rs; tests/test_stubs.py checks them against the built module.
+
+from typing import Literal, final
+
+__version__: str
+
+_MlsType = Literal["welcome", "commit", "proposal"]
+
+@final
+class VoxSignalClient:
+    def __new__(cls) -> VoxSignalClient: ...
+    def start(self) -> None: ...
+    def connect(
+        self,
+        url: str,
+        token: str,
+        api_url: str | None = None,
+        protocol_version: int = 1,
+        max_reconnect_attempts: int | None = None,
+    ) -> int: ...
+    def disconnect(self) -> None: ...
+    def join_room(self, room_id: int, self_mute: bool = False, self_deaf: bool = False) -> int: ...
+    def leave_room(self, room_id: int) -> int: ...
+    def upload_key_packages(self, device_id: str, key_packages: list[bytes]) -> int: ...
+    def fetch_key_packages(self, user_id: int) -> int: ...
+    def send_mls(self, mls_type: _MlsType, data: bytes) -> int: ...
+    def send(self, message_type: str, data: str | None = None) -> int: ...
+    # Gateway dispatch events arrive under their own type, so any string
+    def poll_event(self) -> tuple[str, str] | None: ...
+    def poll_mls(self) -> tuple[_MlsType, str | None, bytes] | None: ...
+    def stop(self) -> None: ...
+    def __enter__(self) -> VoxSignalClient: ...
+    def __exit__(self, _exc_type: object, _exc_value: object, _traceback: object) -> bool: ...
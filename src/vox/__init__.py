@@ -0,0 +1,155 @@
+"""Vox native extensions under one package.
+
+``vox.media`` wraps ``vox_media``, ``vox.mls`` wraps ``vox_mls``,
+``vox.signal`` wraps ``vox_signal`` and ``vox.files`` wraps ``vox_files``;
+all raise subclasses of ``vox.VoxError``. Each submodule needs its
+extension installed (``pip install vox-sdk[media]`` / ``vox-sdk[mls]`` /
+``vox-sdk[signal]`` / ``vox-sdk[files]``) and is only imported on first use.
+"""
+
+from __future__ import annotations
+
+import importlib
+from importlib import metadata
+from typing import Any
+
+from vox.errors import (
+    FilesError,
+    InvalidArgumentError,
+    MediaConnectionError,
+    MediaError,
+    MlsError,
+    NotFoundError,
+    SignalError,
+    VoxError,
+    VoxTimeoutError,
+    WrongEpochError,
+)
+
+try:
+    __version__ = metadata.version("vox-sdk")
+except metadata.PackageNotFoundError:
+    __version__ = "unknown"
+
+_SUBMODULES = ("media", "mls", "signal", "files")
+
+
+def __getattr__(name: str) -> Any:
+    if name in _SUBMODULES:
+        return importlib.import_module(f"vox.{name}")
+    raise AttributeError(f"module 'vox' has no attribute {name!r}")
+
+
+def build_info() -> dict[str, Any]:
+    """Versions of the SDK and of each installed native extension.
+
+    Missing extensions are reported as ``None``; ``media_features`` lists
+    optional cargo features compiled into vox_media.
+    """
+    info: dict[str, Any] = {
+        "vox": __version__,
+        "media": None,
+        "media_features": [],
+        "mls": None,
+        "signal": None,
+        "files": None,
+    }
+    try:
+        import vox_media
+
+        info["media"] = getattr(vox_media, "__version__", "unknown")
+        info["media_features"] = list(getattr(vox_media, "__features__", []))
+    except ImportError:
+        pass
+    try:
+        import vox_mls
+
+        info["mls"] = getattr(vox_mls, "__version__", "unknown")
+    except ImportError:
+        pass
+    try:
+        import vox_signal
+
+        info["signal"] = getattr(vox_signal, "__version__", "unknown")
+    except ImportError:
+        pass
+    try:
+        import vox_files
+
+        info["files"] = getattr(vox_files, "__version__", "unknown")
+    except ImportError:
+        pass
+    return info
+
+
+def configure_tracing(
+    endpoint: str | None,
+    service_name: str = "vox",
+    sample_ratio: float = 1.0,
+    headers: dict[str, str] | None = None,
+) -> None:
+    """Export spans from every installed native extension to one collector.
+
+    Both extensions report under ``service_name`` so their spans share a
+    service in the tracing backend; the instrumentation scope (``vox-media``
+    or ``vox-mls``) tells them apart. ``endpoint`` is an OTLP/HTTP traces
+    URL such as ``"http://localhost:4318/v1/traces"``, or ``None`` to flush
+    and stop exporting. Extensions that are not installed, or that have no
+    spans to export (vox_signal, vox_files), are skipped.
+    """
+    for name in _SUBMODULES:
+        try:
+            module = importlib.import_module(f"vox.{name}")
+        except ImportError:
+            continue
+        if not hasattr(module, "configure_tracing"):
+            continue
+        module.configure_tracing(endpoint, service_name, sample_ratio, headers)
+
+
+def _profiled_modules():
+    for name in _SUBMODULES:
+        try:
+            module = importlib.import_module(f"vox.{name}")
+        except ImportError:
+            continue
+        if hasattr(module, "profile_report"):
+            yield name, module
+
+
+def enable_profiling(enabled: bool = True) -> None:
+    """Turn timing histograms on or off in every installed extension that has them.
+
+    Profiling is off by default and cheap while off; see
+    ``vox.media.profile_report`` and ``vox.mls.profile_report`` for what is
+    timed.
+    """
+    for _, module in _profiled_modules():
+        module.enable_profiling(enabled)
+
+
+def profile_report(reset: bool = False) -> dict[str, dict[str, dict[str, float]]]:
+    """Timing statistics from every profiled extension, keyed by submodule.
+
+    For example ``profile_report()["media"]["opus_encode"]["p99_us"]``.
+    Extensions that are not installed are left out.
+    """
+    return {name: module.profile_report(reset) for name, module in _profiled_modules()}
+
+
+__all__ = [
+    "FilesError",
+    "InvalidArgumentError",
+    "MediaConnectionError",
+    "MediaError",
+    "MlsError",
+    "NotFoundError",
+    "SignalError",
+    "VoxError",
+    "VoxTimeoutError",
+    "WrongEpochError",
+    "build_info",
+    "configure_tracing",
+    "enable_profiling",
+    "profile_report",
+]
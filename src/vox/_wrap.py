@@ -0,0 +1,99 @@
+"""Wrap native extension classes so their errors become ``VoxError``s."""
+
+from __future__ import annotations
+
+import functools
+import types
+from typing import Any, Callable
+
+from vox.errors import VoxError, translate
+
+
+def _call(domain: type[VoxError], fn: Callable[..., Any], *args: Any, **kwargs: Any) -> Any:
+    try:
+        return fn(*args, **kwargs)
+    except Exception as exc:
+        mapped = translate(exc, domain)
+        if mapped is exc:
+            raise
+        raise mapped from exc
+
+
+async def _await(domain: type[VoxError], awaitable: Any) -> Any:
+    try:
+        return await awaitable
+    except Exception as exc:
+        mapped = translate(exc, domain)
+        if mapped is exc:
+            raise
+        raise mapped from exc
+
+
+def _unwrap(value: Any) -> Any:
+    # Native methods expect native objects, e.g. connect(transport_config=...)
+    return getattr(value, "_native", value)
+
+
+def _method(domain: type[VoxError], name: str, doc: str | None) -> Callable[..., Any]:
+    def method(self: Any, *args: Any, **kwargs: Any) -> Any:
+        args = tuple(_unwrap(a) for a in args)
+        kwargs = {k: _unwrap(v) for k, v in kwargs.items()}
+        return _call(domain, getattr(self._native, name), *args, **kwargs)
+
+    method.__name__ = name
+    method.__doc__ = doc
+    return method
+
+
+def _static(domain: type[VoxError], fn: Callable[..., Any]) -> staticmethod:
+    @functools.wraps(fn)
+    def static(*args: Any, **kwargs: Any) -> Any:
+        return _call(domain, fn, *args, **kwargs)
+
+    return staticmethod(static)
+
+
+def _property(name: str, doc: str | None) -> property:
+    def get(self: Any) -> Any:
+        return getattr(self._native, name)
+
+    def set_(self: Any, value: Any) -> None:
+        setattr(self._native, name, value)
+
+    return property(get, set_, doc=doc)
+
+
+def wrap_class(native: type, domain: type[VoxError], name: str | None = None) -> type:
+    """Build a class mirroring ``native`` whose calls raise ``VoxError``s.
+
+    Methods, static methods and properties are forwarded; ``with`` blocks
+    work if the native class supports them. The native object is available
+    as ``_native``.
+    """
+    namespace: dict[str, Any] = {"__doc__": native.__doc__, "__slots__": ("_native",)}
+
+    def __init__(self: Any, *args: Any, **kwargs: Any) -> None:
+        self._native = _call(domain, native, *args, **kwargs)
+
+    namespace["__init__"] = __init__
+
+    for attr_name, attr in vars(native).items():
+        if attr_name.startswith("__") and attr_name not in ("__enter__", "__exit__"):
+            continue
+        if isinstance(attr, staticmethod):
+            namespace[attr_name] = _static(domain, attr.__func__)
+        elif isinstance(attr, (types.GetSetDescriptorType, types.MemberDescriptorType, property)):
+            namespace[attr_name] = _property(attr_name, attr.__doc__)
+        elif callable(attr):
+            namespace[attr_name] = _method(domain, attr_name, attr.__doc__)
+
+    if "__enter__" in namespace:
+        native_enter = namespace["__enter__"]
+
+        def __enter__(self: Any) -> Any:
+            native_enter(self)
+            return self
+
+        namespace["__enter__"] = __enter__
+
+    return type(name or native.__name__, (), namespace)
@@ -0,0 +1,89 @@
+"""Common exception hierarchy for the Vox native extensions.
+
+vox_media, vox_mls, vox_signal and vox_files raise plain ``ValueError``/``RuntimeError``/``KeyError``.
+Objects obtained through the ``vox`` package re-raise those as subclasses of
+``VoxError`` that still derive from the original built-in type, so existing
+``except ValueError`` handlers keep working. vox_mls's ``WrongEpochError``
+becomes ``vox.WrongEpochError``, keeping its two epochs.
+"""
+
+from __future__ import annotations
+
+
+class VoxError(Exception):
+    """Base class for every error raised through the ``vox`` package."""
+
+
+class InvalidArgumentError(VoxError, ValueError):
+    """An argument was rejected before any work was done."""
+
+
+class NotFoundError(VoxError, KeyError):
+    """A referenced object (MLS group, device, ...) does not exist."""
+
+    def __str__(self) -> str:
+        # KeyError quotes its message; keep the plain text
+        return str(self.args[0]) if self.args else ""
+
+
+class VoxTimeoutError(VoxError, TimeoutError):
+    """An operation did not finish in time."""
+
+
+class MediaError(VoxError, RuntimeError):
+    """The media runtime failed or is not in a state to do what was asked."""
+
+
+class MediaConnectionError(MediaError, ConnectionError):
+    """Connecting to the SFU failed."""
+
+
+class MlsError(VoxError, RuntimeError):
+    """An MLS operation failed."""
+
+
+class WrongEpochError(MlsError):
+    """An MLS message is for an epoch the group has already left.
+
+    ``message_epoch`` and ``current_epoch`` hold the two epochs. Nothing was
+    changed, so the message can be dropped.
+    """
+
+    def __init__(self, *args: object, message_epoch: int = 0, current_epoch: int = 0) -> None:
+        super().__init__(*args)
+        self.message_epoch = message_epoch
+        self.current_epoch = current_epoch
+
+
+class SignalError(VoxError, RuntimeError):
+    """The signaling runtime failed or is not in a state to do what was asked."""
+
+
+class FilesError(VoxError, RuntimeError):
+    """An encrypted file failed verification."""
+
+
+def translate(exc: BaseException, domain: type[VoxError]) -> BaseException:
+    """Map a built-in exception from a native call to its ``VoxError`` type.
+
+    ``domain`` (``MediaError``, ``MlsError``, ``SignalError`` or
+    ``FilesError``) is used for runtime errors. Exceptions without a mapping
+    are returned unchanged.
+    """
+    if isinstance(exc, VoxError):
+        return exc
+    if isinstance(exc, ValueError):
+        return InvalidArgumentError(*exc.args)
+    if isinstance(exc, KeyError):
+        return NotFoundError(*exc.args)
+    if isinstance(exc, TimeoutError):
+        return VoxTimeoutError(*exc.args)
+    if isinstance(exc, ConnectionError) and domain is MediaError:
+        return MediaConnectionError(*exc.args)
+    if isinstance(exc, RuntimeError) and domain is MlsError and hasattr(exc, "current_epoch"):
+        return WrongEpochError(
+            *exc.args, message_epoch=exc.message_epoch, current_epoch=exc.current_epoch
+        )
+    if isinstance(exc, RuntimeError):
+        return domain(*exc.args)
+    return exc
@@ -0,0 +1,84 @@
+"""Encrypted file attachments (``vox_files``) with ``VoxError`` exceptions."""
+
+from __future__ import annotations
+
+from os import PathLike
+from typing import Any
+
+import vox_files as _native
+
+from vox._wrap import _call, wrap_class
+from vox.errors import FilesError
+
+FileDecryptor = wrap_class(_native.FileDecryptor, FilesError)
+DEFAULT_CHUNK_SIZE: int = _native.DEFAULT_CHUNK_SIZE
+
+
+def new_file_id() -> bytes:
+    return _call(FilesError, _native.new_file_id)
+
+
+def encrypt_file(*args, **kwargs) -> str:
+    return _call(FilesError, _native.encrypt_file, *args, **kwargs)
+
+
+def decrypt_file(*args, **kwargs) -> int:
+    return _call(FilesError, _native.decrypt_file, *args, **kwargs)
+
+
+def read_manifest(manifest: str) -> dict[str, Any]:
+    return _call(FilesError, _native.read_manifest, manifest)
+
+
+new_file_id.__doc__ = _native.new_file_id.__doc__
+encrypt_file.__doc__ = _native.encrypt_file.__doc__
+decrypt_file.__doc__ = _native.decrypt_file.__doc__
+read_manifest.__doc__ = _native.read_manifest.__doc__
+
+
+def encrypt_for_group(
+    engine: Any,
+    group_id: str,
+    src: str | PathLike[str],
+    dst: str | PathLike[str],
+    chunk_size: int = DEFAULT_CHUNK_SIZE,
+) -> str:
+    """Encrypt ``src`` to ``dst`` under a fresh key from an MLS group.
+
+    ``engine`` is a ``vox.mls.MlsEngine`` (or the native one). Returns the
+    manifest JSON; members at the same epoch can decrypt with
+    ``decrypt_for_group``.
+    """
+    file_id = new_file_id()
+    epoch, key = engine.export_file_key(group_id, file_id)
+    return encrypt_file(src, dst, key, file_id, epoch, chunk_size)
+
+
+def decrypt_for_group(
+    engine: Any,
+    group_id: str,
+    src: str | PathLike[str],
+    dst: str | PathLike[str],
+    manifest: str,
+) -> int:
+    """Decrypt a blob made by ``encrypt_for_group``; returns the plaintext size.
+
+    The group must still be at the manifest's ``key_epoch``.
+    """
+    info = read_manifest(manifest)
+    _, key = engine.export_file_key(group_id, info["file_id"], info["key_epoch"])
+    return decrypt_file(src, dst, key, manifest)
+
+
+__version__: str = getattr(_native, "__version__", "unknown")
+
+__all__ = [
+    "DEFAULT_CHUNK_SIZE",
+    "FileDecryptor",
+    "decrypt_file",
+    "decrypt_for_group",
+    "encrypt_file",
+    "encrypt_for_group",
+    "new_file_id",
+    "read_manifest",
+]
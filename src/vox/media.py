@@ -0,0 +1,55 @@
+"""Media transport (``vox_media``) with ``VoxError`` exceptions."""
+
+from __future__ import annotations
+
+import vox_media as _native
+
+from vox._wrap import _await, _call, _unwrap, wrap_class
+from vox.errors import MediaError
+
+MediaClient = wrap_class(_native.VoxMediaClient, MediaError, "MediaClient")
+TransportConfig = wrap_class(_native.TransportConfig, MediaError)
+DatagramValidation = wrap_class(_native.DatagramValidation, MediaError)
+RateLimits = wrap_class(_native.RateLimits, MediaError)
+
+
+async def _connect_async(self, *args, **kwargs) -> None:
+    # The native coroutine fails after the call returns, so translate on await
+    args = tuple(_unwrap(a) for a in args)
+    kwargs = {k: _unwrap(v) for k, v in kwargs.items()}
+    return await _await(MediaError, _call(MediaError, self._native.connect_async, *args, **kwargs))
+
+
+_connect_async.__doc__ = _native.VoxMediaClient.connect_async.__doc__
+MediaClient.connect_async = _connect_async
+
+
+def configure_logging(*args, **kwargs) -> None:
+    return _call(MediaError, _native.configure_logging, *args, **kwargs)
+
+
+configure_logging.__doc__ = _native.configure_logging.__doc__
+
+
+def configure_tracing(*args, **kwargs) -> None:
+    return _call(MediaError, _native.configure_tracing, *args, **kwargs)
+
+
+configure_tracing.__doc__ = _native.configure_tracing.__doc__
+
+enable_profiling = _native.enable_profiling
+profile_report = _native.profile_report
+
+__version__: str = getattr(_native, "__version__", "unknown")
+__features__: list[str] = list(getattr(_native, "__features__", []))
+
+__all__ = [
+    "DatagramValidation",
+    "MediaClient",
+    "RateLimits",
+    "TransportConfig",
+    "configure_logging",
+    "configure_tracing",
+    "enable_profiling",
+    "profile_report",
+]
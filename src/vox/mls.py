@@ -0,0 +1,44 @@
+"""MLS end-to-end encryption (``vox_mls``) with ``VoxError`` exceptions."""
+
+from __future__ import annotations
+
+import vox_mls as _native
+
+from vox._wrap import _call, wrap_class
+from vox.errors import MlsError, WrongEpochError
+
+MlsEngine = wrap_class(_native.MlsEngine, MlsError)
+ProcessedMessage = _native.ProcessedMessage
+PushNotification = _native.PushNotification
+encode_push_payload = _native.encode_push_payload
+
+
+def decrypt_push(*args, **kwargs) -> PushNotification:
+    return _call(MlsError, _native.decrypt_push, *args, **kwargs)
+
+
+decrypt_push.__doc__ = _native.decrypt_push.__doc__
+
+
+def configure_tracing(*args, **kwargs) -> None:
+    return _call(MlsError, _native.configure_tracing, *args, **kwargs)
+
+
+configure_tracing.__doc__ = _native.configure_tracing.__doc__
+
+enable_profiling = _native.enable_profiling
+profile_report = _native.profile_report
+
+__version__: str = getattr(_native, "__version__", "unknown")
+
+__all__ = [
+    "MlsEngine",
+    "ProcessedMessage",
+    "PushNotification",
+    "WrongEpochError",
+    "configure_tracing",
+    "decrypt_push",
+    "enable_profiling",
+    "encode_push_payload",
+    "profile_report",
+]
@@ -0,0 +1,14 @@
+"""Gateway signaling (``vox_signal``) with ``VoxError`` exceptions."""
+
+from __future__ import annotations
+
+import vox_signal as _native
+
+from vox._wrap import wrap_class
+from vox.errors import SignalError
+
+SignalClient = wrap_class(_native.VoxSignalClient, SignalError, "SignalClient")
+
+__version__: str = getattr(_native, "__version__", "unknown")
+
+__all__ = ["SignalClient"]
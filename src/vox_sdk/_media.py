@@ -1,6 +1,24 @@
 """Re-export the native vox_media extension as vox_sdk._media."""
 
 from vox_media import *  # noqa: F401,F403
-from vox_media import VoxMediaClient
+from vox_media import (
+    DatagramValidation,
+    RateLimits,
+    TransportConfig,
+    VoxMediaClient,
+    configure_logging,
+    configure_tracing,
+    enable_profiling,
+    profile_report,
+)
 
-__all__ = ["VoxMediaClient"]
+__all__ = [
+    "DatagramValidation",
+    "RateLimits",
+    "TransportConfig",
+    "VoxMediaClient",
+    "configure_logging",
+    "configure_tracing",
+    "enable_profiling",
+    "profile_report",
+]
@@ -59,11 +59,12 @@ class CryptoManager:
         client: Client,
         db_path: str | None = None,
         encryption_key: bytes | None = None,
+        db_key: bytes | None = None,
     ) -> None:
         _require_mls()
         self._client = client
         self._engine: MlsEngine = MlsEngine(
-            db_path=db_path, encryption_key=encryption_key
+            db_path=db_path, encryption_key=encryption_key, db_key=db_key
         )
         self._device_id: str | None = None
         self._user_id: int | None = None
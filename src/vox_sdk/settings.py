@@ -0,0 +1,120 @@
+"""Persistent client settings shared with the native extensions.
+
+Settings live in a small SQLite database of text key/value pairs. vox_media
+reads the media keys from it when its runtime starts
+(``VoxMediaClient.start(settings_path=...)``), so device choices and volumes
+survive restarts without every application writing its own config file.
+Keys outside the ones below are free for the application to use.
+"""
+
+from __future__ import annotations
+
+import os
+import sqlite3
+import sys
+from collections.abc import Iterator
+from pathlib import Path
+
+# Keys understood by vox_media (kept in sync with vox-core settings.rs)
+INPUT_DEVICE = "audio.input_device"
+OUTPUT_DEVICE = "audio.output_device"
+INPUT_VOLUME = "audio.input_volume"
+OUTPUT_VOLUME = "audio.output_volume"
+CAMERA = "video.camera"
+RECONNECT_MAX_ATTEMPTS = "reconnect.max_attempts"
+RECONNECT_MAX_BACKOFF_SECS = "reconnect.max_backoff_secs"
+
+_SCHEMA = "CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)"
+
+
+def default_path(app_name: str = "vox") -> Path:
+    """Per-user location for the settings database.
+
+    ``%APPDATA%`` on Windows, ``~/Library/Application Support`` on macOS and
+    ``$XDG_CONFIG_HOME`` (default ``~/.config``) elsewhere. The directory is
+    not created.
+    """
+    if sys.platform == "win32":
+        base = Path(os.environ.get("APPDATA") or Path.home() / "AppData" / "Roaming")
+    elif sys.platform == "darwin":
+        base = Path.home() / "Library" / "Application Support"
+    else:
+        base = Path(os.environ.get("XDG_CONFIG_HOME") or Path.home() / ".config")
+    return base / app_name / "settings.db"
+
+
+class _Setting:
+    """Typed property over one settings key; assigning ``None`` removes it."""
+
+    def __init__(self, key: str, kind: type) -> None:
+        self.key = key
+        self.kind = kind
+
+    def __get__(self, obj: Settings | None, owner: type) -> object:
+        if obj is None:
+            return self
+        value = obj.get(self.key)
+        if value is None:
+            return None
+        try:
+            return self.kind(value)
+        except ValueError:
+            return None
+
+    def __set__(self, obj: Settings, value: object) -> None:
+        if value is None:
+            obj.delete(self.key)
+        else:
+            obj.set(self.key, str(self.kind(value)))
+
+
+class Settings:
+    """A settings database, created on first use.
+
+    Values are stored as text. The typed properties cover the keys the SDK
+    itself reads; ``get``/``set`` reach any key::
+
+        settings = Settings(default_path())
+        settings.input_device = "USB Microphone"
+        settings.output_volume = 0.8
+        client.start(settings_path=settings.path)
+    """
+
+    input_device = _Setting(INPUT_DEVICE, str)
+    output_device = _Setting(OUTPUT_DEVICE, str)
+    input_volume = _Setting(INPUT_VOLUME, float)
+    output_volume = _Setting(OUTPUT_VOLUME, float)
+    camera = _Setting(CAMERA, int)
+    reconnect_max_attempts = _Setting(RECONNECT_MAX_ATTEMPTS, int)
+    reconnect_max_backoff_secs = _Setting(RECONNECT_MAX_BACKOFF_SECS, int)
+
+    def __init__(self, path: str | os.PathLike[str]) -> None:
+        self.path = Path(path)
+        self.path.parent.mkdir(parents=True, exist_ok=True)
+        self._conn = sqlite3.connect(self.path, timeout=2.0, isolation_level=None)
+        self._conn.execute(_SCHEMA)
+
+    def get(self, key: str, default: str | None = None) -> str | None:
+        row = self._conn.execute("SELECT value FROM settings WHERE key = ?", (key,)).fetchone()
+        return row[0] if row else default
+
+    def set(self, key: str, value: str) -> None:
+        self._conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)", (key, str(value))
+        )
+
+    def delete(self, key: str) -> None:
+        self._conn.execute("DELETE FROM settings WHERE key = ?", (key,))
+
+    def items(self) -> Iterator[tuple[str, str]]:
+        """Every stored pair, sorted by key."""
+        yield from self._conn.execute("SELECT key, value FROM settings ORDER BY key").fetchall()
+
+    def close(self) -> None:
+        self._conn.close()
+
+    def __enter__(self) -> Settings:
+        return self
+
+    def __exit__(self, *exc: object) -> None:
+        self.close()
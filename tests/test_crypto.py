@@ -156,6 +156,48 @@ class TestMlsEngine:
         assert engine2.identity_key() == original_ik
         assert engine2.group_exists("export-test")
 
+    def test_encrypted_state_export_import(self):
+        """An encrypted state backup only opens with its passphrase."""
+        engine = self.MlsEngine(db_path=None)
+        engine.generate_identity(1, "device-a")
+        engine.create_group("export-test", [])
+
+        backup = bytes(engine.export_state_encrypted("correct horse"))
+        assert bytes(engine.export_state()) not in backup
+
+        engine2 = self.MlsEngine(db_path=None)
+        with pytest.raises(ValueError):
+            engine2.import_state_encrypted(backup, "wrong horse")
+        with pytest.raises(ValueError):
+            engine.export_state_encrypted("")
+
+        engine2.import_state_encrypted(backup, "correct horse")
+        assert engine2.identity_key() == engine.identity_key()
+        assert engine2.group_exists("export-test")
+
+    def test_group_export_import(self):
+        """A single group moves to another engine with the same identity."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("move-test", [bytes(bob.generate_key_package())])
+        bob.join_group(bytes(welcome))
+        alice.create_group("stays-behind", [])
+        export = bytes(alice.export_group("move-test"))
+
+        with pytest.raises(ValueError):
+            bob.import_group(export)
+
+        alice2 = self.MlsEngine(db_path=None)
+        alice2.import_identity(bytes(alice.export_identity()), 1, "alice-device")
+        assert alice2.import_group(export) == "move-test"
+        assert alice2.list_groups() == ["move-test"]
+        ct = bob.encrypt("move-test", b"hello")
+        assert bytes(alice2.decrypt("move-test", bytes(ct))) == b"hello"
+
     def test_identity_export_import(self):
         """export_identity(), new engine, import_identity(), verify match.
 
@@ -297,6 +339,594 @@ class TestMlsEngine:
         assert bytes(alice.decrypt("commit-test", bytes(ct))) == msg
         assert bytes(charlie.decrypt("commit-test", bytes(ct))) == msg
 
+    def test_update_self(self):
+        """Bob rotates his leaf key; Alice follows the commit and both keep talking."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("update-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+        epoch, _key = alice.export_file_key("update-test", b"f")
+
+        commit = bob.update_self("update-test")
+        result = alice.process_message("update-test", bytes(commit))
+        assert result.kind == "commit"
+        assert alice.export_file_key("update-test", b"f")[0] == epoch + 1
+
+        ct = bob.encrypt("update-test", b"fresh keys")
+        assert bytes(alice.decrypt("update-test", bytes(ct))) == b"fresh keys"
+
+    def test_rotate_credential(self):
+        """Bob moves to a new credential and key; Alice follows the commit."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        old_key = bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("rotate-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        commit = bob.rotate_credential("rotate-test", "bob-tablet")
+        assert alice.process_message("rotate-test", bytes(commit)).kind == "commit"
+        assert bob.get_stored_identity() == (2, "bob-tablet")
+        assert bytes(bob.identity_key()) != bytes(old_key)
+        assert alice.member_fingerprint("rotate-test", "2:bob-tablet") == bob.own_fingerprint()
+
+        ct = bob.encrypt("rotate-test", b"new key")
+        assert bytes(alice.decrypt("rotate-test", bytes(ct))) == b"new key"
+
+    def test_group_event_callback(self):
+        """The callback hears of group changes and can use the engine."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        seen = []
+
+        def on_event(event):
+            seen.append((event.kind, event.group_id, event.epoch, event.leaf_index, event.identity))
+            assert alice.group_epoch(event.group_id) == event.epoch
+
+        alice.set_group_event_callback(on_event)
+        alice.create_group("events-test", [])
+        alice.add_member("events-test", bytes(bob.generate_key_packages(1)[0]))
+        assert seen == [
+            ("created", "events-test", 0, None, None),
+            ("member_added", "events-test", 1, 1, b"2:bob-device"),
+            ("epoch_advanced", "events-test", 1, None, None),
+        ]
+
+        alice.set_group_event_callback(None)
+        alice.update_self("events-test")
+        assert len(seen) == 3
+
+    def test_leave_group(self):
+        """Bob asks to leave; Alice commits the proposal and Bob's group is gone."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("leave-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+        assert alice.commit_pending_proposals("leave-test") is None
+
+        proposal = bob.leave_group("leave-test")
+        assert bob.is_leaving("leave-test")
+        assert alice.process_message("leave-test", bytes(proposal)).kind == "proposal"
+        commit, welcome = alice.commit_pending_proposals("leave-test")
+        assert welcome is None
+
+        bob.process_message("leave-test", bytes(commit))
+        assert not bob.group_exists("leave-test")
+        assert not bob.is_leaving("leave-test")
+
+    def test_pending_proposals(self):
+        """Pending proposals are listed with their proposer, and can be cleared."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("pending-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+        assert alice.list_pending_proposals("pending-test") == []
+
+        alice.process_message("pending-test", bytes(bob.leave_group("pending-test")))
+        [proposal] = alice.list_pending_proposals("pending-test")
+        assert (proposal.proposal_type, proposal.sender_type) == ("remove", "member")
+        assert proposal.sender_index == 1
+        assert bytes(proposal.sender_identity) == b"2:bob-device"
+
+        alice.clear_pending_proposals("pending-test")
+        assert alice.list_pending_proposals("pending-test") == []
+        assert alice.commit_pending_proposals("pending-test") is None
+
+    def test_key_package_inventory(self):
+        """Generated key packages are listed, marked consumed on join, and deletable."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+        kp = bytes(bob.generate_key_package())
+        bob.generate_key_package(last_resort=True)
+        used, spare = bob.list_key_packages()
+        assert (used.last_resort, spare.last_resort) == (False, True)
+        assert not used.consumed
+
+        welcome, _commit = alice.create_group("inventory-test", [kp])
+        bob.join_group(bytes(welcome))
+        assert [p.consumed for p in bob.list_key_packages()] == [True, False]
+
+        bob.delete_key_package(bytes(used.hash_ref))
+        assert [bytes(p.hash_ref) for p in bob.list_key_packages()] == [bytes(spare.hash_ref)]
+        with pytest.raises(ValueError):
+            bob.delete_key_package(bytes(used.hash_ref))
+
+    def test_multiple_identities(self, tmp_path):
+        """Identities in one database each keep their own groups, even a shared one."""
+        db_file = str(tmp_path / "identities.db")
+        engine = self.MlsEngine(db_path=db_file)
+        engine.generate_identity(1, "alice-device")
+        engine.use_identity("bot")
+        assert engine.identity_handle == "bot"
+        engine.generate_identity(2, "bot-device")
+        kp = bytes(engine.generate_key_package())
+
+        engine.use_identity()
+        welcome, _commit = engine.create_group("identities-test", [kp])
+        engine.use_identity("bot")
+        engine.join_group(bytes(welcome))
+        ct = bytes(engine.encrypt("identities-test", b"from bot"))
+        engine.use_identity()
+        assert bytes(engine.decrypt("identities-test", ct)) == b"from bot"
+        del engine
+
+        reopened = self.MlsEngine(db_path=db_file)
+        assert reopened.identity_handle is None
+        assert reopened.list_identities() == ["bot"]
+        assert reopened.get_stored_identity() == (1, "alice-device")
+        reopened.use_identity("bot")
+        assert reopened.get_stored_identity() == (2, "bot-device")
+        assert reopened.list_groups() == ["identities-test"]
+
+    def test_fingerprints(self):
+        """A member's fingerprint as others see it matches their own."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+        welcome, _commit = alice.create_group("fingerprint-test", [bytes(bob.generate_key_package())])
+        bob.join_group(bytes(welcome))
+
+        assert alice.member_fingerprint("fingerprint-test", "2:bob-device") == bob.own_fingerprint()
+        assert bob.member_fingerprint("fingerprint-test", "1:alice-device") == alice.own_fingerprint()
+        assert len(alice.own_fingerprint().replace(" ", "")) == 60
+        with pytest.raises(ValueError):
+            alice.member_fingerprint("fingerprint-test", "3:nobody")
+
+    def test_compact(self):
+        """Compacting deletes expired key packages and keeps groups working."""
+        import time
+
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        alice.create_group("compact-test", [])
+        alice.generate_key_package(lifetime_secs=1)
+        alice.generate_key_package()
+
+        time.sleep(2)
+        assert alice.compact() == (0, 1)
+        assert alice.compact() == (0, 0)
+        alice.encrypt("compact-test", b"still here")
+
+    def test_stats(self):
+        """stats() reports the database size, groups and key packages."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("stats-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+        bob.generate_key_package()
+        alice.process_message("stats-test", bytes(bob.leave_group("stats-test")))
+
+        stats = alice.stats()
+        assert stats["database_bytes"] > 0
+        assert stats["groups"] == {"stats-test": {"epoch": 1, "members": 2, "pending_proposals": 1}}
+        assert stats["key_packages"] == {"total": 0, "unused": 0, "last_resort": 0}
+        assert bob.stats()["key_packages"] == {"total": 2, "unused": 1, "last_resort": 0}
+
+    def test_delete_group(self):
+        """Deleting a group removes all of its state."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        alice.create_group("delete-test", [])
+        alice.delete_group("delete-test")
+        assert not alice.group_exists("delete-test")
+        assert alice.list_groups() == []
+
+        with pytest.raises(KeyError):
+            alice.delete_group("delete-test")
+
+    def test_join_by_external_commit(self):
+        """A device with no state joins from the GroupInfo alone."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        alice.create_group("external-test", [])
+        group_info = alice.export_group_info("external-test")
+        assert len(alice.export_group_info("external-test", with_ratchet_tree=False)) < len(group_info)
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+        group_id, commit = bob.join_by_external_commit(bytes(group_info))
+        assert group_id == "external-test"
+        assert alice.process_message("external-test", bytes(commit)).kind == "commit"
+
+        ct = bob.encrypt("external-test", b"joined")
+        assert bytes(alice.decrypt("external-test", bytes(ct))) == b"joined"
+
+    def test_group_info_with_commit(self):
+        """create_group and add_member return the GroupInfo on request."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        assert len(alice.create_group("info-test", [])) == 2
+        welcome, commit, group_info = alice.add_member(
+            "info-test", bytes(bob.generate_key_packages(1)[0]), with_group_info=True
+        )
+        bob.join_group(bytes(welcome))
+
+        carol = self.MlsEngine(db_path=None)
+        carol.generate_identity(3, "carol-device")
+        _group_id, join = carol.join_by_external_commit(bytes(group_info))
+        assert alice.process_message("info-test", bytes(join)).kind == "commit"
+        assert bob.process_message("info-test", bytes(join)).kind == "commit"
+
+        _welcome, _commit, group_info = alice.create_group("other-test", [], with_group_info=True)
+        assert carol.join_by_external_commit(bytes(group_info))[0] == "other-test"
+
+    def test_peek_message(self):
+        """peek_message routes a message without processing it."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        alice.create_group("peek-test", [])
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+        _group_id, commit = bob.join_by_external_commit(bytes(alice.export_group_info("peek-test")))
+
+        info = alice.peek_message(bytes(commit))
+        assert (info.group_id, info.epoch, info.content_type) == ("peek-test", 0, "commit")
+        assert (info.sender_type, info.sender_index) == ("new_member_commit", None)
+        assert alice.group_epoch("peek-test") == 0
+        alice.process_message("peek-test", bytes(commit))
+
+        info = alice.peek_message(bytes(bob.encrypt("peek-test", b"hi")))
+        assert (info.epoch, info.content_type, info.sender_type) == (1, "application", None)
+        with pytest.raises(ValueError):
+            alice.peek_message(b"junk")
+
+    def test_processed_message_sender(self):
+        """Processed messages carry the sender's identity, leaf and epoch."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("sender-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        processed = alice.process_message("sender-test", bytes(bob.encrypt("sender-test", b"hi")))
+        assert bytes(processed.sender_identity) == b"2:bob-device"
+        assert (processed.sender_leaf_index, processed.epoch) == (1, 1)
+
+        commit = bob.update_self("sender-test")
+        ct = bob.encrypt("sender-test", b"later")
+        buffered = alice.process_message("sender-test", bytes(ct))
+        assert (buffered.kind, buffered.epoch, buffered.sender_identity) == ("buffered", 2, None)
+        alice.process_message("sender-test", bytes(commit))
+        [replayed] = alice.take_replayed("sender-test")
+        assert (replayed.epoch, replayed.sender_leaf_index) == (2, 1)
+
+    def test_padding_size(self):
+        """Padded groups hide the length of application messages."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group(
+            "padding-test", [bytes(bob.generate_key_packages(1)[0])], padding_size=128
+        )
+        bob.join_group(bytes(welcome), padding_size=128)
+
+        short = bytes(alice.encrypt("padding-test", b"hi"))
+        assert len(short) == len(alice.encrypt("padding-test", b"x" * 100))
+        assert bytes(bob.decrypt("padding-test", short)) == b"hi"
+        assert len(bob.encrypt("padding-test", b"a")) == len(bob.encrypt("padding-test", b"b" * 100))
+
+    def test_staged_commit(self):
+        """A staged commit waits for merge_staged_commit or reject_staged_commit."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        carol = self.MlsEngine(db_path=None)
+        carol.generate_identity(3, "carol-device")
+
+        welcome, _commit = alice.create_group("staged-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        _welcome, commit = alice.add_member("staged-test", bytes(carol.generate_key_packages(1)[0]))
+        processed = bob.process_message("staged-test", bytes(commit), stage_commits=True)
+        assert processed.kind == "staged_commit"
+        assert [bytes(a) for a in processed.staged_commit.adds] == [b"3:carol-device"]
+        assert bob.group_epoch("staged-test") == 1
+        bob.merge_staged_commit("staged-test")
+        assert bob.group_epoch("staged-test") == 2
+
+        commit = alice.remove_member("staged-test", "3:carol-device")
+        processed = bob.process_message("staged-test", bytes(commit), stage_commits=True)
+        [(leaf_index, identity)] = processed.staged_commit.removes
+        assert (leaf_index, bytes(identity)) == (2, b"3:carol-device")
+        bob.reject_staged_commit("staged-test")
+        assert bob.group_epoch("staged-test") == 2
+        with pytest.raises(ValueError):
+            bob.reject_staged_commit("staged-test")
+
+    def test_wrong_epoch_error(self):
+        """A message from an epoch the group has left raises WrongEpochError."""
+        import vox_mls
+
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("epoch-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        late = bob.encrypt("epoch-test", b"epoch 1")
+        alice.process_message("epoch-test", bytes(bob.update_self("epoch-test")))
+        with pytest.raises(vox_mls.WrongEpochError) as excinfo:
+            alice.process_message("epoch-test", bytes(late))
+        assert (excinfo.value.message_epoch, excinfo.value.current_epoch) == (1, 2)
+        assert isinstance(excinfo.value, RuntimeError)
+
+    def test_own_commit_relayed_back(self):
+        """Our own commit echoed by the server is recognised, not an error."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("echo-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        commit = bytes(alice.update_self("echo-test"))
+        processed = alice.process_message("echo-test", commit)
+        assert (processed.kind, processed.epoch, processed.sender_leaf_index) == ("own_commit", 1, 0)
+        assert alice.group_epoch("echo-test") == 2
+        assert bob.process_message("echo-test", commit).kind == "commit"
+
+    def test_out_of_order_messages_replayed(self):
+        """Messages from later epochs wait for the commit before them."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("replay-test", [bytes(bob.generate_key_packages(1)[0])])
+        bob.join_group(bytes(welcome))
+
+        commit = bob.update_self("replay-test")
+        ct = bob.encrypt("replay-test", b"after the commit")
+        assert alice.process_message("replay-test", bytes(ct)).kind == "buffered"
+        assert alice.take_replayed("replay-test") == []
+
+        assert alice.process_message("replay-test", bytes(commit)).kind == "commit"
+        [replayed] = alice.take_replayed("replay-test")
+        assert (replayed.kind, bytes(replayed.data)) == ("application", b"after the commit")
+
+    def test_ciphersuite(self):
+        """Engines and groups can use ChaCha20-Poly1305 instead of AES-GCM."""
+        chacha = "MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519"
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+        assert alice.ciphersuite == "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519"
+
+        bob = self.MlsEngine(db_path=None, ciphersuite=chacha)
+        bob.generate_identity(2, "bob-device")
+        assert bob.ciphersuite == chacha
+
+        welcome, _commit = alice.create_group(
+            "suite-test", [bytes(bob.generate_key_package())], ciphersuite=chacha
+        )
+        bob.join_group(bytes(welcome))
+        ct = alice.encrypt("suite-test", b"chacha")
+        assert bytes(bob.decrypt("suite-test", bytes(ct))) == b"chacha"
+
+        with pytest.raises(ValueError, match="Unsupported ciphersuite"):
+            self.MlsEngine(db_path=None, ciphersuite="MLS_NOPE")
+
+    def test_reinit_group(self):
+        """A re-initialized group keeps its ID and members."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("reinit-test", [bytes(bob.generate_key_package())])
+        bob.join_group(bytes(welcome))
+
+        with pytest.raises(ValueError):
+            alice.reinit_group("reinit-test", alice.ciphersuite, [])
+
+        welcome = alice.reinit_group("reinit-test", alice.ciphersuite, [bytes(bob.generate_key_package())])
+        assert bob.join_group(bytes(welcome)) == "reinit-test"
+        ct = alice.encrypt("reinit-test", b"migrated")
+        assert bytes(bob.decrypt("reinit-test", bytes(ct))) == b"migrated"
+
+    def test_ratchet_tree_out_of_band(self):
+        """Welcomes without the ratchet tree join with the exported tree."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group(
+            "tree-test", [bytes(bob.generate_key_package())], with_ratchet_tree=False
+        )
+        with pytest.raises(RuntimeError):
+            bob.join_group(bytes(welcome))
+        tree = alice.export_ratchet_tree("tree-test")
+        assert bob.join_group(bytes(welcome), ratchet_tree=bytes(tree)) == "tree-test"
+
+        ct = alice.encrypt("tree-test", b"out of band")
+        assert bytes(bob.decrypt("tree-test", bytes(ct))) == b"out of band"
+
+    def test_list_members(self):
+        """Members are listed with their leaf index, identity and signature key."""
+        alice = self.MlsEngine(db_path=None)
+        alice_key = alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        alice.create_group("members-test", [bytes(bob.generate_key_package())])
+        members = alice.list_members("members-test")
+        assert [(index, bytes(identity)) for index, identity, _ in members] == [
+            (0, b"1:alice-device"),
+            (1, b"2:bob-device"),
+        ]
+        assert bytes(members[0][2]) == bytes(alice_key)
+
+    def test_epoch_and_authenticator(self):
+        """Members in the same epoch share its authenticator."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("epoch-test", [bytes(bob.generate_key_package())])
+        bob.join_group(bytes(welcome))
+        assert alice.group_epoch("epoch-test") == bob.group_epoch("epoch-test") == 1
+        assert bytes(alice.epoch_authenticator("epoch-test")) == bytes(bob.epoch_authenticator("epoch-test"))
+
+        with pytest.raises(KeyError):
+            alice.group_epoch("nowhere")
+
+    def test_key_package_options(self):
+        """Key packages carry the requested extensions and still join groups."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        kps = bob.generate_key_packages(
+            1, lifetime_secs=3600, extensions=[(0xFF00, b"vox-marker")], capability_extensions=[0xFF01]
+        )
+        assert b"vox-marker" in bytes(kps[0])
+        welcome, _commit = alice.create_group("kp-test", [bytes(kps[0])])
+        bob.join_group(bytes(welcome))
+
+        with pytest.raises(ValueError):
+            bob.generate_key_package(lifetime_secs=0)
+        with pytest.raises(ValueError):
+            bob.generate_key_package(extensions=[(2, b"")])
+
+    def test_group_context_extensions(self):
+        """Room metadata in the GroupContext reaches members and can be changed."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        kp = bob.generate_key_package(capability_extensions=[0xFF10])
+        welcome, _commit = alice.create_group("gce-test", [bytes(kp)], extensions=[(0xFF10, b"room 42")])
+        bob.join_group(bytes(welcome))
+        assert [(t, bytes(d)) for t, d in bob.group_context_extensions("gce-test")] == [(0xFF10, b"room 42")]
+
+        commit = alice.update_group_context_extensions("gce-test", [(0xFF10, b"room 43")])
+        assert bob.process_message("gce-test", bytes(commit)).kind == "commit"
+        assert [(t, bytes(d)) for t, d in bob.group_context_extensions("gce-test")] == [(0xFF10, b"room 43")]
+
+        with pytest.raises(ValueError):
+            alice.update_group_context_extensions("gce-test", [(2, b"")])
+
+    def test_required_capabilities(self):
+        """Key packages lacking a required extension type are refused."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        alice.create_group("required-test", [], required_extensions=[0xFF20])
+        with pytest.raises(RuntimeError):
+            alice.add_member("required-test", bytes(bob.generate_key_package()))
+        kp = bob.generate_key_package(capability_extensions=[0xFF20])
+        welcome, _commit = alice.add_member("required-test", bytes(kp))
+        assert bob.join_group(bytes(welcome)) == "required-test"
+
+        with pytest.raises(ValueError):
+            alice.create_group("reserved-test", [], required_extensions=[2])
+
+    def test_inspect_key_package(self):
+        """A key package shows who it is for without an engine or a group."""
+        bob = self.MlsEngine(db_path=None)
+        bob_key = bob.generate_identity(2, "bob-device")
+
+        info = self.MlsEngine.inspect_key_package(bytes(bob.generate_key_package(lifetime_secs=3600)))
+        assert bytes(info.identity) == b"2:bob-device"
+        assert info.ciphersuite == bob.ciphersuite
+        assert bytes(info.signature_key) == bytes(bob_key)
+        assert info.not_before < info.not_after
+        assert not info.last_resort
+        with pytest.raises(ValueError):
+            self.MlsEngine.inspect_key_package(b"junk")
+
+    def test_inspect_welcome(self):
+        """A Welcome names its group and sender, and can still be joined."""
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("welcome-test", [bytes(bob.generate_key_package())])
+        info = bob.inspect_welcome(bytes(welcome))
+        assert (info.group_id, bytes(info.sender_identity)) == ("welcome-test", b"1:alice-device")
+        assert info.ciphersuite == alice.ciphersuite
+        assert not bob.group_exists("welcome-test")
+        assert bob.join_group(bytes(welcome)) == "welcome-test"
+
     def test_decrypt_wrong_group(self):
         """Attempt decrypt with wrong group ID, expect PyKeyError."""
         engine = self.MlsEngine(db_path=None)
@@ -353,6 +983,58 @@ class TestMlsEngine:
         engine2 = self.MlsEngine(db_path=db_file)
         assert engine2.identity_key() == original_ik
 
+    def test_storage_options(self, tmp_path):
+        """WAL mode and a busy timeout let another process share the database."""
+        import sqlite3
+
+        db_file = str(tmp_path / "wal_test.db")
+        engine = self.MlsEngine(db_path=db_file, wal=True, synchronous="normal", busy_timeout=2.5)
+        engine.generate_identity(1, "device-a")
+
+        conn = sqlite3.connect(db_file)
+        assert conn.execute("PRAGMA journal_mode").fetchone()[0] == "wal"
+        conn.close()
+
+        with pytest.raises(ValueError):
+            self.MlsEngine(db_path=None, synchronous="sometimes")
+        with pytest.raises(ValueError):
+            self.MlsEngine(db_path=None, busy_timeout=-1)
+
+    def test_close_and_reopen(self, tmp_path):
+        """A closed engine releases its database file and refuses further calls."""
+        import os
+
+        db_file = str(tmp_path / "close_test.db")
+        engine = self.MlsEngine(db_path=db_file, wal=True)
+        engine.generate_identity(1, "device-a")
+        engine.create_group("close-test", [])
+        assert not engine.is_closed
+
+        engine.close()
+        engine.close()
+        assert engine.is_closed
+        assert not os.path.exists(db_file + "-wal") or os.path.getsize(db_file + "-wal") == 0
+        with pytest.raises(RuntimeError, match="closed"):
+            engine.list_groups()
+
+        moved = str(tmp_path / "moved.db")
+        os.replace(db_file, moved)
+        reopened = self.MlsEngine(db_path=moved)
+        assert reopened.get_stored_identity() == (1, "device-a")
+        assert reopened.list_groups() == ["close-test"]
+
+    def test_context_manager(self, tmp_path):
+        """Leaving a with block closes the engine, also on an exception."""
+        db_file = str(tmp_path / "with_test.db")
+        with self.MlsEngine(db_path=db_file) as engine:
+            engine.generate_identity(1, "device-a")
+        assert engine.is_closed
+
+        with pytest.raises(KeyError):
+            with self.MlsEngine(db_path=db_file) as engine:
+                engine.encrypt("nowhere", b"x")
+        assert engine.is_closed
+
     def test_remove_member_invalid_identity(self):
         """Removing a member with unknown identity raises error."""
         alice = self.MlsEngine(db_path=None)
@@ -371,3 +1053,112 @@ class TestMlsEngine:
         """Encryption key that is not 32 bytes raises ValueError."""
         with pytest.raises(ValueError, match="32 bytes"):
             self.MlsEngine(db_path=None, encryption_key=b"too-short")
+
+    def test_engine_used_from_worker_threads(self):
+        """An engine can be called from threads other than its creator's."""
+        from concurrent.futures import ThreadPoolExecutor
+
+        alice = self.MlsEngine(db_path=None)
+        alice.generate_identity(1, "alice-device")
+
+        bob = self.MlsEngine(db_path=None)
+        bob.generate_identity(2, "bob-device")
+
+        welcome, _commit = alice.create_group("thread-test", [bytes(bob.generate_key_package())])
+        bob.join_group(bytes(welcome))
+        with ThreadPoolExecutor(max_workers=4) as pool:
+            cts = list(pool.map(lambda i: bytes(alice.encrypt("thread-test", b"msg %d" % i)), range(4)))
+        with ThreadPoolExecutor(max_workers=1) as pool:
+            plaintexts = list(pool.map(lambda ct: bytes(bob.decrypt("thread-test", ct)), cts))
+        assert sorted(plaintexts) == sorted(b"msg %d" % i for i in range(4))
+
+    def test_db_key_wrong_length(self):
+        """A db_key that is not 32 bytes raises ValueError."""
+        with pytest.raises(ValueError, match="db_key must be exactly 32 bytes"):
+            self.MlsEngine(db_path=None, db_key=b"too-short")
+
+    def test_profile_report(self):
+        """Profiling records encrypt and load_group timings only while enabled."""
+        import vox_mls
+
+        engine = self.MlsEngine(db_path=None)
+        engine.generate_identity(1, "alice-device")
+        engine.create_group("profiled", [])
+        vox_mls.profile_report(reset=True)
+
+        engine.encrypt("profiled", b"not timed")
+        assert vox_mls.profile_report() == {}
+
+        vox_mls.enable_profiling()
+        try:
+            for _ in range(3):
+                engine.encrypt("profiled", b"timed")
+        finally:
+            vox_mls.enable_profiling(False)
+        report = vox_mls.profile_report(reset=True)
+        assert report["encrypt"]["count"] == 3
+        assert report["load_group"]["count"] == 3
+        stats = report["encrypt"]
+        assert 0 < stats["p50_us"] <= stats["max_us"]
+        assert stats["total_ms"] * 1000 >= stats["max_us"]
+        assert vox_mls.profile_report() == {}
+
+
+class TestPushDecryption:
+    @pytest.fixture(autouse=True)
+    def _import_mls(self, tmp_path):
+        vox_mls = pytest.importorskip("vox_mls")
+        self.vox_mls = vox_mls
+        self.db = str(tmp_path / "bob.db")
+        self.alice = vox_mls.MlsEngine(db_path=None)
+        self.alice.generate_identity(1, "alice-device")
+        self.bob = vox_mls.MlsEngine(db_path=self.db)
+        self.bob.generate_identity(2, "bob-device")
+        welcome, _ = self.alice.create_group("push-group", [bytes(self.bob.generate_key_packages(1)[0])])
+        self.bob.join_group(bytes(welcome))
+
+    def _payload(self, plaintext: bytes) -> str:
+        ciphertext = self.alice.encrypt("push-group", plaintext)
+        return self.vox_mls.encode_push_payload("push-group", bytes(ciphertext))
+
+    def test_decrypt_leaves_message_for_app(self):
+        payload = self._payload("hello from alice".encode())
+        note = self.vox_mls.decrypt_push(payload, self.db)
+        assert note.group_id == "push-group"
+        assert note.sender == "1:alice-device"
+        assert (note.sender_user_id, note.sender_device_id) == (1, "alice-device")
+        assert note.preview == "hello from alice"
+        # Not consumed: the app can still decrypt the same message
+        ciphertext = base64.b64decode(json.loads(payload)["m"])
+        assert bytes(self.bob.decrypt("push-group", ciphertext)) == b"hello from alice"
+
+    def test_consume(self):
+        payload = self._payload(b"once")
+        self.vox_mls.decrypt_push(payload, self.db, consume=True)
+        with pytest.raises(RuntimeError):
+            self.vox_mls.decrypt_push(payload, self.db)
+
+    def test_preview_truncated(self):
+        note = self.vox_mls.decrypt_push(self._payload(("word " * 50).encode()), self.db, preview_len=12)
+        assert note.preview == "word word wo…"
+        assert note.plaintext == ("word " * 50).encode()
+
+    def test_binary_plaintext_has_no_preview(self):
+        note = self.vox_mls.decrypt_push(self._payload(b"\xff\xfe"), self.db)
+        assert note.preview is None
+
+    def test_unknown_group(self):
+        payload = self.vox_mls.encode_push_payload("nope", b"\x00")
+        with pytest.raises(KeyError):
+            self.vox_mls.decrypt_push(payload, self.db)
+
+    def test_malformed_payload(self):
+        for payload in ("not json", '{"v": 2, "g": "g", "m": ""}', '{"v": 1, "g": "g", "m": "%%"}'):
+            with pytest.raises(ValueError):
+                self.vox_mls.decrypt_push(payload, self.db)
+
+    def test_rejects_commits(self):
+        commit = self.alice.remove_member("push-group", "2:bob-device")
+        payload = self.vox_mls.encode_push_payload("push-group", bytes(commit))
+        with pytest.raises(RuntimeError, match="not an application message"):
+            self.vox_mls.decrypt_push(payload, self.db)
@@ -0,0 +1,93 @@
+"""Tests for the vox_files encrypted attachment extension."""
+
+import json
+
+import pytest
+
+vox_files = pytest.importorskip("vox_files")
+
+KEY = b"\x07" * 32
+
+
+@pytest.fixture
+def encrypted(tmp_path):
+    src = tmp_path / "plain.bin"
+    src.write_bytes(bytes(range(256)) * 40)
+    blob = tmp_path / "blob.bin"
+    file_id = vox_files.new_file_id()
+    manifest = vox_files.encrypt_file(src, blob, KEY, file_id, 4, chunk_size=1000)
+    return src, blob, file_id, manifest
+
+
+class TestEncryptDecrypt:
+    def test_round_trip(self, tmp_path, encrypted):
+        src, blob, _, manifest = encrypted
+        out = tmp_path / "out.bin"
+        assert vox_files.decrypt_file(blob, out, KEY, manifest) == src.stat().st_size
+        assert out.read_bytes() == src.read_bytes()
+
+    def test_manifest_fields(self, encrypted):
+        src, blob, file_id, manifest = encrypted
+        info = vox_files.read_manifest(manifest)
+        assert info["file_id"] == file_id
+        assert info["key_epoch"] == 4
+        assert info["size"] == src.stat().st_size
+        assert info["encrypted_size"] == blob.stat().st_size
+        assert info["chunks"] == 11
+        assert json.loads(manifest)["algorithm"] == "aes-256-gcm"
+
+    def test_tampered_blob_removes_output(self, tmp_path, encrypted):
+        _, blob, _, manifest = encrypted
+        data = bytearray(blob.read_bytes())
+        data[5000] ^= 1
+        blob.write_bytes(bytes(data))
+        out = tmp_path / "out.bin"
+        with pytest.raises(RuntimeError, match="integrity check failed"):
+            vox_files.decrypt_file(blob, out, KEY, manifest)
+        assert not out.exists()
+
+    def test_bad_arguments(self, tmp_path, encrypted):
+        src, blob, file_id, _ = encrypted
+        with pytest.raises(ValueError, match="file_id"):
+            vox_files.encrypt_file(src, blob, KEY, b"short", 0)
+        with pytest.raises(ValueError, match="file key"):
+            vox_files.encrypt_file(src, blob, b"\x00" * 16, file_id, 0)
+        with pytest.raises(ValueError):
+            vox_files.read_manifest("{}")
+        with pytest.raises(OSError):
+            vox_files.encrypt_file(tmp_path / "missing", blob, KEY, file_id, 0)
+
+
+class TestFileDecryptor:
+    def test_streaming(self, encrypted):
+        src, blob, _, manifest = encrypted
+        decryptor = vox_files.FileDecryptor(KEY, manifest)
+        assert decryptor.size == src.stat().st_size
+        data = blob.read_bytes()
+        plain = b"".join(decryptor.update(data[i : i + 333]) for i in range(0, len(data), 333))
+        decryptor.finish()
+        assert plain == src.read_bytes()
+
+    def test_truncated(self, encrypted):
+        _, blob, _, manifest = encrypted
+        decryptor = vox_files.FileDecryptor(KEY, manifest)
+        decryptor.update(blob.read_bytes()[:-1])
+        with pytest.raises(RuntimeError, match="truncated"):
+            decryptor.finish()
+        with pytest.raises(RuntimeError, match="finished"):
+            decryptor.update(b"")
+
+
+class TestGroupKeys:
+    def test_encrypt_for_group(self, tmp_path):
+        vox_mls = pytest.importorskip("vox_mls")
+        from vox import files
+
+        engine = vox_mls.MlsEngine()
+        engine.generate_identity(1, "dev")
+        engine.create_group("g", [])
+        src = tmp_path / "plain.txt"
+        src.write_text("attachment")
+        manifest = files.encrypt_for_group(engine, "g", src, tmp_path / "blob")
+        assert files.decrypt_for_group(engine, "g", tmp_path / "blob", tmp_path / "out", manifest) == 10
+        assert (tmp_path / "out").read_text() == "attachment"
@@ -264,3 +264,129 @@ class TestConnectWithoutServer:
             time.sleep(0.1)
         finally:
             client.stop()
+
+
+class TestVideoTransforms:
+    """Rotation, preview mirroring and scaling via set_video_config."""
+
+    def test_rotation_and_mirror_accepted(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            for degrees in (0, 90, 180, 270):
+                client.set_video_config(rotation=degrees)
+            client.set_video_config(640, 480, 30, 500, rotation=90, mirror_preview=True)
+        finally:
+            client.stop()
+
+    def test_invalid_rotation_rejected(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            with pytest.raises(ValueError, match="rotation"):
+                client.set_video_config(rotation=45)
+        finally:
+            client.stop()
+
+    def test_scale_mode_accepted(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            client.set_video_config(1280, 720, scale_mode="crop")
+            client.set_video_config(1280, 720, scale_mode="stretch")
+        finally:
+            client.stop()
+
+    def test_invalid_scale_mode_rejected(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            with pytest.raises(ValueError, match="scale_mode"):
+                client.set_video_config(scale_mode="zoom")
+        finally:
+            client.stop()
+
+    def test_zero_size_rejected(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            with pytest.raises(ValueError):
+                client.set_video_config(0, 480)
+        finally:
+            client.stop()
+
+
+class TestBackgroundEffect:
+    """Background blur / replacement configuration."""
+
+    def test_disable_without_model(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            client.set_background_effect(None)
+        finally:
+            client.stop()
+
+    def test_unknown_effect_rejected(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError, match="background effect"):
+            client.set_background_effect("sepia", model_path="model.onnx")
+
+    def test_model_path_required(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError, match="model_path"):
+            client.set_background_effect("blur")
+
+    def test_image_size_checked(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError):
+            client.set_background_effect("image", model_path="model.onnx", image=(2, 2, b"\x00" * 3))
+
+    def test_missing_model_raises(self, tmp_path):
+        client = VoxMediaClient()
+        with pytest.raises(RuntimeError):
+            client.set_background_effect("blur", model_path=str(tmp_path / "missing.onnx"))
+
+
+class TestVideoOverlay:
+    """Overlay compositing configuration."""
+
+    LOGO = (2, 2, b"\xff\x00\x00\x80" * 4)
+
+    def test_set_and_clear_overlay(self):
+        client = VoxMediaClient()
+        client.start()
+        try:
+            client.set_video_overlay(self.LOGO, anchor="bottom-right", offset=(16, 16), opacity=0.5)
+            client.set_video_overlay(None)
+        finally:
+            client.stop()
+
+    def test_image_size_checked(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError):
+            client.set_video_overlay((2, 2, b"\x00" * 15))
+
+    def test_unknown_anchor_rejected(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError, match="anchor"):
+            client.set_video_overlay(self.LOGO, anchor="middle")
+
+    def test_opacity_bounds(self):
+        client = VoxMediaClient()
+        with pytest.raises(ValueError, match="opacity"):
+            client.set_video_overlay(self.LOGO, opacity=1.5)
+
+
+class TestGpuAcceleration:
+    """GPU conversion toggle; falls back to the CPU when unavailable."""
+
+    def test_availability_is_bool(self):
+        assert isinstance(VoxMediaClient.gpu_acceleration_available(), bool)
+
+    def test_disable_turns_off_gpu(self):
+        VoxMediaClient.set_gpu_acceleration(False)
+        try:
+            assert VoxMediaClient.gpu_acceleration_available() is False
+        finally:
+            VoxMediaClient.set_gpu_acceleration(True)
@@ -0,0 +1,67 @@
+"""Tests for the persistent settings store."""
+
+import sqlite3
+import sys
+
+import pytest
+
+from vox_sdk.settings import INPUT_DEVICE, OUTPUT_VOLUME, Settings, default_path
+
+
+@pytest.fixture
+def settings(tmp_path):
+    with Settings(tmp_path / "nested" / "settings.db") as s:
+        yield s
+
+
+class TestSettings:
+    def test_get_set_delete(self, settings):
+        assert settings.get("app.theme") is None
+        assert settings.get("app.theme", "dark") == "dark"
+        settings.set("app.theme", "light")
+        settings.set("app.theme", "solarized")
+        assert settings.get("app.theme") == "solarized"
+        settings.delete("app.theme")
+        settings.delete("app.theme")
+        assert settings.get("app.theme") is None
+
+    def test_typed_properties(self, settings):
+        assert settings.input_device is None
+        settings.input_device = "USB Mic"
+        settings.output_volume = 0.75
+        settings.camera = 1
+        assert settings.input_device == "USB Mic"
+        assert settings.output_volume == 0.75
+        assert settings.camera == 1
+        assert dict(settings.items()) == {
+            INPUT_DEVICE: "USB Mic",
+            OUTPUT_VOLUME: "0.75",
+            "video.camera": "1",
+        }
+        settings.camera = None
+        assert settings.camera is None
+
+    def test_unparsable_value_reads_as_none(self, settings):
+        settings.set("reconnect.max_attempts", "lots")
+        assert settings.reconnect_max_attempts is None
+
+    def test_rejects_wrong_type(self, settings):
+        with pytest.raises(ValueError):
+            settings.input_volume = "loud"
+
+    def test_persists_across_connections(self, tmp_path):
+        path = tmp_path / "settings.db"
+        with Settings(path) as s:
+            s.reconnect_max_attempts = 8
+        with Settings(path) as s:
+            assert s.reconnect_max_attempts == 8
+        # Plain sqlite3 sees the same table the native reader uses
+        rows = sqlite3.connect(path).execute("SELECT key, value FROM settings").fetchall()
+        assert rows == [("reconnect.max_attempts", "8")]
+
+    def test_default_path(self, monkeypatch, tmp_path):
+        if sys.platform in ("win32", "darwin"):
+            pytest.skip("XDG layout only")
+        monkeypatch.setenv("XDG_CONFIG_HOME", str(tmp_path))
+        assert default_path() == tmp_path / "vox" / "settings.db"
+        assert default_path("myapp").parent.name == "myapp"
@@ -0,0 +1,73 @@
+"""Tests for the vox_signal gateway client (VoxSignalClient)."""
+
+import time
+
+import pytest
+
+vox_signal = pytest.importorskip("vox_signal")
+VoxSignalClient = vox_signal.VoxSignalClient
+
+
+def _wait_for(client, event_type, timeout=5.0):
+    deadline = time.monotonic() + timeout
+    while time.monotonic() < deadline:
+        event = client.poll_event()
+        if event is None:
+            time.sleep(0.01)
+        elif event[0] == event_type:
+            return event[1]
+    pytest.fail(f"no {event_type} event")
+
+
+class TestLifecycle:
+    def test_commands_before_start_raise(self):
+        client = VoxSignalClient()
+        with pytest.raises(RuntimeError, match="not started"):
+            client.connect("wss://gateway.invalid/gateway", "token")
+
+    def test_start_twice_raises(self):
+        with VoxSignalClient() as client:
+            with pytest.raises(RuntimeError, match="already running"):
+                client.start()
+
+    def test_no_events_initially(self):
+        with VoxSignalClient() as client:
+            assert client.poll_event() is None
+            assert client.poll_mls() is None
+
+
+class TestConnect:
+    def test_unsupported_scheme_fails(self):
+        with VoxSignalClient() as client:
+            command_id = client.connect("http://gateway.invalid", "token")
+            assert "unsupported gateway URL" in _wait_for(client, "connect_failed")
+            assert _wait_for(client, "command_result").startswith(f"id={command_id},ok=false")
+
+    def test_quic_url_needs_port(self):
+        with VoxSignalClient() as client:
+            client.connect("quic://gateway.invalid", "token")
+            assert "needs a port" in _wait_for(client, "connect_failed")
+
+
+class TestCommands:
+    def test_send_mls_rejects_unknown_type(self):
+        with VoxSignalClient() as client:
+            with pytest.raises(ValueError, match="unknown MLS message type"):
+                client.send_mls("application", b"\x00")
+
+    def test_send_rejects_non_object(self):
+        with VoxSignalClient() as client:
+            with pytest.raises(ValueError, match="JSON object"):
+                client.send("typing_start", "[1, 2]")
+            with pytest.raises(ValueError, match="invalid JSON"):
+                client.send("typing_start", "{")
+
+    def test_send_without_connection(self):
+        with VoxSignalClient() as client:
+            command_id = client.send_mls("commit", b"\x00\x01")
+            assert _wait_for(client, "command_result") == f"id={command_id},ok=false,error=not connected"
+
+    def test_rest_without_connect(self):
+        with VoxSignalClient() as client:
+            command_id = client.join_room(1)
+            assert _wait_for(client, "command_result") == f"id={command_id},ok=false,error=not connected"
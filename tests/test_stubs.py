@@ -0,0 +1,119 @@
+"""Check the shipped .pyi stubs against the native extension modules."""
+
+from __future__ import annotations
+
+import ast
+import inspect
+from pathlib import Path
+
+import pytest
+
+CRATES = Path(__file__).resolve().parent.parent / "crates"
+STUBS = {
+    "vox_media": CRATES / "vox-media" / "vox_media.pyi",
+    "vox_mls": CRATES / "vox-mls" / "vox_mls.pyi",
+    "vox_signal": CRATES / "vox-signal" / "vox_signal.pyi",
+    "vox_files": CRATES / "vox-files" / "vox_files.pyi",
+}
+
+
+def _stub_tree(module: str) -> ast.Module:
+    return ast.parse(STUBS[module].read_text(), filename=str(STUBS[module]))
+
+
+def _public(names):
+    return {n for n in names if not n.startswith("_") or n in ("__enter__", "__exit__")}
+
+
+def _stub_classes(tree: ast.Module) -> dict[str, dict[str, ast.FunctionDef]]:
+    classes = {}
+    for node in tree.body:
+        if isinstance(node, ast.ClassDef) and not node.name.startswith("_"):
+            classes[node.name] = {
+                item.name: item for item in node.body if isinstance(item, ast.FunctionDef)
+            }
+    return classes
+
+
+def _stub_params(fn: ast.FunctionDef) -> list[tuple[str, object]]:
+    """(name, default) pairs, without self/cls; defaults as Python values."""
+    args = fn.args
+    positional = args.posonlyargs + args.args
+    defaults = [inspect.Parameter.empty] * (len(positional) - len(args.defaults)) + [
+        ast.literal_eval(d) for d in args.defaults
+    ]
+    params = [(a.arg, d) for a, d in zip(positional, defaults)]
+    if args.vararg:
+        params.append(("*" + args.vararg.arg, inspect.Parameter.empty))
+    if args.kwarg:
+        params.append(("**" + args.kwarg.arg, inspect.Parameter.empty))
+    if params and params[0][0] in ("self", "cls"):
+        params = params[1:]
+    return params
+
+
+def _runtime_params(obj) -> list[tuple[str, object]] | None:
+    try:
+        sig = inspect.signature(obj)
+    except (TypeError, ValueError):
+        return None
+    params = []
+    for p in sig.parameters.values():
+        if p.kind is p.VAR_POSITIONAL:
+            params.append(("*" + p.name, inspect.Parameter.empty))
+        elif p.kind is p.VAR_KEYWORD:
+            params.append(("**" + p.name, inspect.Parameter.empty))
+        elif p.name not in ("self", "cls", "$self", "$cls", "type"):
+            default = p.default
+            params.append((p.name, tuple(default) if isinstance(default, list) else default))
+    return params
+
+
+def _matches(runtime, stub) -> bool:
+    # pyo3 omits signatures it cannot express, and renders defaults it
+    # cannot spell in Python as "..."
+    if runtime is None:
+        return True
+    return len(runtime) == len(stub) and all(
+        rn == sn and (rd is Ellipsis or rd == sd) for (rn, rd), (sn, sd) in zip(runtime, stub)
+    )
+
+
+@pytest.mark.parametrize("module", sorted(STUBS))
+def test_stub_parses(module):
+    tree = _stub_tree(module)
+    assert any(
+        isinstance(n, ast.AnnAssign) and getattr(n.target, "id", None) == "__version__"
+        for n in tree.body
+    )
+
+
+@pytest.mark.parametrize("module", sorted(STUBS))
+def test_stub_matches_runtime(module):
+    native = pytest.importorskip(module)
+    tree = _stub_tree(module)
+    classes = _stub_classes(tree)
+    functions = {n.name: n for n in tree.body if isinstance(n, ast.FunctionDef)}
+
+    runtime_classes = {n for n, v in vars(native).items() if isinstance(v, type) and not n.startswith("_")}
+    assert runtime_classes == set(classes)
+    runtime_functions = {
+        n for n, v in vars(native).items() if callable(v) and not isinstance(v, type) and not n.startswith("_")
+    }
+    assert runtime_functions == set(functions)
+
+    for name, fn in functions.items():
+        assert _matches(_runtime_params(getattr(native, name)), _stub_params(fn)), name
+
+    for cls_name, stub_members in classes.items():
+        cls = getattr(native, cls_name)
+        runtime_members = _public(vars(cls))
+        assert runtime_members == _public(stub_members) - {"__new__"}, cls_name
+        for member, fn in stub_members.items():
+            if member == "__new__":
+                runtime = _runtime_params(cls)
+            elif any(getattr(d, "id", None) == "property" for d in fn.decorator_list):
+                continue
+            else:
+                runtime = _runtime_params(getattr(cls, member))
+            assert _matches(runtime, _stub_params(fn)), f"{cls_name}.{member}"
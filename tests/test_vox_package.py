@@ -0,0 +1,154 @@
+"""Tests for the unified vox package and its error model."""
+
+from __future__ import annotations
+
+import pytest
+
+import vox
+from vox._wrap import wrap_class
+from vox.errors import (
+    FilesError,
+    InvalidArgumentError,
+    MediaConnectionError,
+    MediaError,
+    MlsError,
+    NotFoundError,
+    SignalError,
+    VoxError,
+    VoxTimeoutError,
+    WrongEpochError,
+    translate,
+)
+
+
+class _FakeNative:
+    """Stands in for a pyo3 class."""
+
+    def __init__(self, value: int = 1) -> None:
+        if value < 0:
+            raise ValueError("negative")
+        self.value = value
+
+    def fail(self, kind: str) -> None:
+        raise {"runtime": RuntimeError, "key": KeyError, "timeout": TimeoutError,
+               "connection": ConnectionError, "os": OSError}[kind]("boom")
+
+    def echo(self, arg):
+        return arg
+
+    @staticmethod
+    def version() -> str:
+        return "1.0"
+
+    @property
+    def doubled(self) -> int:
+        return self.value * 2
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc) -> bool:
+        return False
+
+
+class TestTranslate:
+    def test_builtin_types_preserved(self):
+        assert isinstance(translate(ValueError("x"), MediaError), ValueError)
+        assert isinstance(translate(KeyError("x"), MlsError), KeyError)
+        assert isinstance(translate(TimeoutError("x"), MediaError), TimeoutError)
+        assert isinstance(translate(RuntimeError("x"), MlsError), RuntimeError)
+
+    def test_domain_used_for_runtime_errors(self):
+        assert type(translate(RuntimeError("x"), MediaError)) is MediaError
+        assert type(translate(RuntimeError("x"), MlsError)) is MlsError
+        assert type(translate(RuntimeError("x"), SignalError)) is SignalError
+        assert type(translate(RuntimeError("x"), FilesError)) is FilesError
+
+    def test_connection_errors_are_media_only(self):
+        assert type(translate(ConnectionError("x"), MediaError)) is MediaConnectionError
+        err = ConnectionError("x")
+        assert translate(err, MlsError) is err
+
+    def test_wrong_epoch_keeps_epochs(self):
+        class NativeWrongEpochError(RuntimeError):
+            """Stands in for vox_mls.WrongEpochError."""
+
+        native = NativeWrongEpochError("stale message")
+        native.message_epoch, native.current_epoch = 1, 2
+        err = translate(native, MlsError)
+        assert type(err) is WrongEpochError
+        assert isinstance(err, MlsError) and isinstance(err, RuntimeError)
+        assert (err.message_epoch, err.current_epoch) == (1, 2)
+        assert str(err) == "stale message"
+        assert vox.WrongEpochError is WrongEpochError
+
+    def test_unmapped_passthrough(self):
+        err = OSError("disk full")
+        assert translate(err, MediaError) is err
+
+    def test_not_found_message_unquoted(self):
+        assert str(NotFoundError("No group with id 'g'")) == "No group with id 'g'"
+
+
+class TestWrapClass:
+    Wrapped = wrap_class(_FakeNative, MediaError, "Wrapped")
+
+    def test_forwards_methods_and_properties(self):
+        obj = self.Wrapped(3)
+        assert obj.echo("hi") == "hi"
+        assert obj.doubled == 6
+        assert self.Wrapped.version() == "1.0"
+        assert self.Wrapped.__name__ == "Wrapped"
+
+    def test_constructor_errors_translated(self):
+        with pytest.raises(InvalidArgumentError):
+            self.Wrapped(-1)
+
+    @pytest.mark.parametrize(
+        ("kind", "expected"),
+        [
+            ("runtime", MediaError),
+            ("key", NotFoundError),
+            ("timeout", VoxTimeoutError),
+            ("connection", MediaConnectionError),
+        ],
+    )
+    def test_method_errors_translated(self, kind, expected):
+        with pytest.raises(expected) as info:
+            self.Wrapped().fail(kind)
+        assert isinstance(info.value, VoxError)
+        assert info.value.__cause__ is not None
+
+    def test_unmapped_errors_untouched(self):
+        with pytest.raises(OSError) as info:
+            self.Wrapped().fail("os")
+        assert not isinstance(info.value, VoxError)
+
+    def test_wrapped_arguments_unwrapped(self):
+        inner = self.Wrapped(5)
+        assert self.Wrapped().echo(inner) is inner._native
+
+    def test_context_manager_returns_wrapper(self):
+        with self.Wrapped() as obj:
+            assert isinstance(obj, self.Wrapped)
+
+
+class TestPackage:
+    def test_build_info_keys(self):
+        info = vox.build_info()
+        assert set(info) == {"vox", "media", "media_features", "mls", "signal", "files"}
+        assert isinstance(info["media_features"], list)
+
+    def test_configure_tracing_disable(self):
+        # Skips extensions that are not installed
+        vox.configure_tracing(None)
+
+    def test_profile_report_by_extension(self):
+        vox.enable_profiling(False)
+        report = vox.profile_report(reset=True)
+        assert set(report) <= {"media", "mls"}
+        assert all(isinstance(stats, dict) for stats in report.values())
+
+    def test_unknown_attribute(self):
+        with pytest.raises(AttributeError):
+            vox.does_not_exist